@@ -197,6 +197,10 @@ function asString(value: unknown): string {
   return typeof value === "string" ? value : value ? String(value) : "";
 }
 
+function asNumber(value: unknown): number | undefined {
+  return typeof value === "number" && Number.isFinite(value) ? value : undefined;
+}
+
 function asBoolean(value: unknown): boolean | null {
   if (typeof value === "boolean") return value;
   if (typeof value === "number") return value !== 0;
@@ -610,6 +614,11 @@ export function normalizeModelOptions(raw: unknown): ModelOption[] {
       defaultReasoningEffort:
         asString(item.defaultReasoningEffort ?? item.default_reasoning_effort) || null,
       isDefault: Boolean(item.isDefault ?? item.is_default),
+      contextWindow: asNumber(item.contextWindow ?? item.context_window),
+      inputCostPerMtok: asNumber(item.inputCostPerMtok ?? item.input_cost_per_mtok),
+      outputCostPerMtok: asNumber(item.outputCostPerMtok ?? item.output_cost_per_mtok),
+      supportsReasoning:
+        asBoolean(item.supportsReasoning ?? item.supports_reasoning) ?? undefined,
     };
   });
 }
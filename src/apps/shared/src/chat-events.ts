@@ -50,6 +50,7 @@ export interface ChatOutputDeltaEvent extends ChatMappedEventBase {
   type: "command.output" | "file.output";
   itemId?: string;
   delta: string;
+  stream?: "stdout" | "stderr";
 }
 
 export interface ChatDiffUpdatedEvent extends ChatMappedEventBase {
@@ -248,7 +249,15 @@ export function mapChatEvent(
       ...base,
       type: event.topic === "chat.command.output" ? "command.output" : "file.output",
       itemId: getItemId(params),
-      delta: typeof params.delta === "string" ? params.delta : "",
+      delta:
+        typeof params.delta === "string"
+          ? params.delta
+          : typeof params.chunk === "string"
+            ? params.chunk
+            : "",
+      ...(params.stream === "stdout" || params.stream === "stderr"
+        ? { stream: params.stream }
+        : {}),
     };
   }
 
@@ -83,6 +83,10 @@ export interface ModelOption {
   supportedReasoningEfforts: ReasoningEffortOption[];
   defaultReasoningEffort: string | null;
   isDefault: boolean;
+  contextWindow?: number;
+  inputCostPerMtok?: number;
+  outputCostPerMtok?: number;
+  supportsReasoning?: boolean;
 }
 
 export interface SkillOption {
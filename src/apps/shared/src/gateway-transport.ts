@@ -54,6 +54,8 @@ export interface GatewayTransportOptions {
   authToken?: string;
   protocolVersion?: number;
   clientId?: string;
+  /** Stable per-device id, shown to this identity's other clients in presence events. */
+  deviceId?: string;
   capabilities?: string[];
   handshakeTimeoutMs?: number;
   maxBinaryBacklogBytes?: number;
@@ -181,10 +183,11 @@ export class GatewayTransport {
   private readonly options: Required<
     Omit<
       GatewayTransportOptions,
-      "authToken" | "createWebSocket" | "logger" | "createRequestId" | "reconnectBackoff"
+      "authToken" | "deviceId" | "createWebSocket" | "logger" | "createRequestId" | "reconnectBackoff"
     >
   > & {
     authToken?: string;
+    deviceId?: string;
     createWebSocket: (url: string) => GatewaySocketLike;
     logger?: (...args: unknown[]) => void;
     createRequestId: () => string;
@@ -218,6 +221,7 @@ export class GatewayTransport {
       authToken: options.authToken,
       protocolVersion: options.protocolVersion ?? PROTOCOL_VERSION,
       clientId: options.clientId ?? "homie-client/0.0.1",
+      deviceId: options.deviceId,
       capabilities: options.capabilities ?? ["terminal", "chat"],
       handshakeTimeoutMs: options.handshakeTimeoutMs ?? DEFAULT_HANDSHAKE_TIMEOUT_MS,
       maxBinaryBacklogBytes: options.maxBinaryBacklogBytes ?? DEFAULT_MAX_BINARY_BACKLOG_BYTES,
@@ -462,6 +466,7 @@ export class GatewayTransport {
         client_id: this.options.clientId,
         auth_token: this.options.authToken,
         capabilities: this.options.capabilities,
+        device_id: this.options.deviceId,
       };
 
       this.log("send hello", {
@@ -8,6 +8,7 @@ export interface ClientHello {
   client_id: string;
   auth_token?: string;
   capabilities: string[];
+  device_id?: string;
 }
 
 export interface ServiceCapability {
@@ -35,10 +36,21 @@ export type HandshakeResponse =
 
 export const PROTOCOL_VERSION = 1;
 
+export type RpcErrorCategory =
+  | "auth"
+  | "not_found"
+  | "invalid_params"
+  | "rate_limited"
+  | "quota"
+  | "backend"
+  | "internal";
+
 export interface RpcError {
   code: number;
   message: string;
   data?: unknown;
+  category?: RpcErrorCategory;
+  retryable?: boolean;
 }
 
 export interface RpcRequest<TParams = unknown> {
@@ -0,0 +1,203 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, mpsc, watch};
+
+/// How long `cancel_turn` waits for a connection to confirm the abort.
+const TURN_CANCEL_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Process-wide kill-switch for agent runs.
+///
+/// While frozen, new runs are refused. In-flight runs keep going unless the
+/// freeze asked for them to be cancelled, which bumps a generation counter
+/// that running turns watch. Single turns can also be aborted by id from any
+/// connection, which is how `jobs.cancel` reaches a running agent turn.
+#[derive(Debug, Clone)]
+pub struct RunFreeze {
+    inner: Arc<FreezeInner>,
+}
+
+#[derive(Debug)]
+struct FreezeInner {
+    frozen: AtomicBool,
+    reason: Mutex<Option<String>>,
+    cancel_tx: watch::Sender<u64>,
+    turn_cancel_tx: broadcast::Sender<TurnCancel>,
+}
+
+/// Request to abort one agent turn, wherever its connection lives.
+#[derive(Debug, Clone)]
+pub struct TurnCancel {
+    pub chat_id: String,
+    pub turn_id: String,
+    ack: mpsc::Sender<()>,
+}
+
+impl TurnCancel {
+    /// Tell the requester this listener aborted the turn.
+    pub fn acknowledge(&self) {
+        let _ = self.ack.try_send(());
+    }
+}
+
+impl Default for RunFreeze {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunFreeze {
+    pub fn new() -> Self {
+        let (cancel_tx, _cancel_rx) = watch::channel(0);
+        let (turn_cancel_tx, _turn_cancel_rx) = broadcast::channel(16);
+        Self {
+            inner: Arc::new(FreezeInner {
+                frozen: AtomicBool::new(false),
+                reason: Mutex::new(None),
+                cancel_tx,
+                turn_cancel_tx,
+            }),
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.inner.frozen.load(Ordering::SeqCst)
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        self.inner.reason.lock().ok().and_then(|r| r.clone())
+    }
+
+    /// Refuse new runs; optionally cancel every run currently in flight.
+    pub fn freeze(&self, reason: Option<String>, cancel_in_flight: bool) {
+        if let Ok(mut slot) = self.inner.reason.lock() {
+            *slot = reason;
+        }
+        self.inner.frozen.store(true, Ordering::SeqCst);
+        if cancel_in_flight {
+            self.inner
+                .cancel_tx
+                .send_modify(|generation| *generation = generation.wrapping_add(1));
+        }
+    }
+
+    pub fn unfreeze(&self) {
+        self.inner.frozen.store(false, Ordering::SeqCst);
+        if let Ok(mut slot) = self.inner.reason.lock() {
+            *slot = None;
+        }
+    }
+
+    /// Receiver that changes whenever a freeze cancels in-flight runs.
+    pub fn cancel_signal(&self) -> watch::Receiver<u64> {
+        self.inner.cancel_tx.subscribe()
+    }
+
+    /// Ask every connection to abort `turn_id` if it is driving it. Returns
+    /// true once one of them confirms the abort; false when none is listening,
+    /// every listener passed, or none answered in time.
+    pub async fn cancel_turn(&self, chat_id: &str, turn_id: &str) -> bool {
+        let (ack, mut acked) = mpsc::channel(1);
+        let sent = self.inner.turn_cancel_tx.send(TurnCancel {
+            chat_id: chat_id.to_string(),
+            turn_id: turn_id.to_string(),
+            ack,
+        });
+        if sent.is_err() {
+            return false;
+        }
+        // Each listener drops its copy once handled, so the channel closes
+        // with no ack when nobody owned the turn.
+        matches!(
+            tokio::time::timeout(TURN_CANCEL_ACK_TIMEOUT, acked.recv()).await,
+            Ok(Some(()))
+        )
+    }
+
+    /// Receiver for targeted turn cancellations.
+    pub fn turn_cancel_signal(&self) -> broadcast::Receiver<TurnCancel> {
+        self.inner.turn_cancel_tx.subscribe()
+    }
+
+    /// Message used when refusing a run.
+    pub fn refusal_message(&self) -> String {
+        match self.reason() {
+            Some(reason) => format!("agent runs are frozen: {reason}"),
+            None => "agent runs are frozen".to_string(),
+        }
+    }
+
+    pub fn status(&self) -> Value {
+        json!({
+            "frozen": self.is_frozen(),
+            "reason": self.reason(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freeze_and_unfreeze_toggle_state() {
+        let freeze = RunFreeze::new();
+        assert!(!freeze.is_frozen());
+
+        freeze.freeze(Some("incident".into()), false);
+        assert!(freeze.clone().is_frozen());
+        assert_eq!(freeze.refusal_message(), "agent runs are frozen: incident");
+
+        freeze.unfreeze();
+        assert!(!freeze.is_frozen());
+        assert_eq!(freeze.status()["reason"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn cancel_signal_fires_only_when_cancelling() {
+        let freeze = RunFreeze::new();
+        let mut rx = freeze.cancel_signal();
+
+        freeze.freeze(None, false);
+        assert!(!rx.has_changed().expect("sender alive"));
+
+        freeze.freeze(None, true);
+        rx.changed().await.expect("cancel signal");
+        assert_eq!(*rx.borrow(), 1);
+    }
+
+    #[tokio::test]
+    async fn turn_cancel_reaches_every_listener() {
+        let freeze = RunFreeze::new();
+        assert!(!freeze.cancel_turn("chat-1", "turn-1").await);
+
+        let mut first = freeze.turn_cancel_signal();
+        let mut second = freeze.clone().turn_cancel_signal();
+        let listeners = tokio::spawn(async move {
+            let passed = first.recv().await.expect("first");
+            let owner = second.recv().await.expect("second");
+            assert_eq!(
+                (owner.chat_id.as_str(), owner.turn_id.as_str()),
+                ("chat-1", "turn-1")
+            );
+            drop(passed);
+            owner.acknowledge();
+        });
+        assert!(freeze.cancel_turn("chat-1", "turn-1").await);
+        listeners.await.unwrap();
+        assert!(!freeze.is_frozen());
+    }
+
+    #[tokio::test]
+    async fn turn_cancel_fails_when_no_listener_owns_the_turn() {
+        let freeze = RunFreeze::new();
+        let mut rx = freeze.turn_cancel_signal();
+        let listener = tokio::spawn(async move {
+            drop(rx.recv().await.expect("cancel"));
+        });
+        assert!(!freeze.cancel_turn("chat-1", "turn-1").await);
+        listener.await.unwrap();
+    }
+}
@@ -0,0 +1,6 @@
+mod freeze;
+mod selftest;
+mod service;
+
+pub use freeze::RunFreeze;
+pub use service::AdminService;
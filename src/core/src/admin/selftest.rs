@@ -0,0 +1,128 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde_json::{json, Value};
+
+use crate::agent::{check_providers, check_run, check_tools};
+use crate::storage::Store;
+use crate::HomieConfig;
+
+/// Run every `admin.selftest` check in order and build the report.
+pub(super) async fn run_selftest(store: Arc<dyn Store>, homie_config: Arc<HomieConfig>) -> Value {
+    let checks = vec![
+        timed("store", async { check_store(store.as_ref()) }).await,
+        timed("run", check_run(homie_config.clone())).await,
+        timed("tools", async { check_tools(homie_config.clone()) }).await,
+        timed("providers", async { check_providers(&homie_config) }).await,
+    ];
+    let ok = checks.iter().all(|check| check["ok"] == true);
+    json!({ "ok": ok, "checks": checks })
+}
+
+async fn timed(name: &str, check: impl Future<Output = Result<Value, String>>) -> Value {
+    let started = Instant::now();
+    let result = check.await;
+    let duration_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(detail) => json!({
+            "name": name,
+            "ok": true,
+            "duration_ms": duration_ms,
+            "detail": detail,
+        }),
+        Err(error) => {
+            tracing::warn!(check = name, %error, "selftest check failed");
+            json!({
+                "name": name,
+                "ok": false,
+                "duration_ms": duration_ms,
+                "error": error,
+            })
+        }
+    }
+}
+
+/// Round-trip a throwaway thread-state row through the store.
+fn check_store(store: &dyn Store) -> Result<Value, String> {
+    let key = format!("homie-selftest-{}", uuid::Uuid::new_v4());
+    let probe = json!({ "selftest": true });
+    store.upsert_chat_thread_state(&key, &probe)?;
+    let read = store.get_chat_thread_state(&key);
+    let deleted = store.delete_chat_thread_state(&key);
+    match read? {
+        Some(value) if value == probe => {}
+        _ => return Err("store read back a different value".into()),
+    }
+    deleted?;
+    Ok(json!({ "read_write": true }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+
+    fn test_config() -> Arc<HomieConfig> {
+        let mut config = HomieConfig::default();
+        let creds = std::env::temp_dir().join(format!("homie-selftest-{}", uuid::Uuid::new_v4()));
+        config.paths.credentials_dir = Some(creds.to_string_lossy().to_string());
+        config.providers.openai_codex.enabled = false;
+        config.providers.github_copilot.enabled = false;
+        config.providers.claude_code.enabled = false;
+        Arc::new(config)
+    }
+
+    fn check<'a>(report: &'a Value, name: &str) -> &'a Value {
+        report["checks"]
+            .as_array()
+            .expect("checks")
+            .iter()
+            .find(|check| check["name"] == name)
+            .expect("check present")
+    }
+
+    #[tokio::test]
+    async fn reports_every_subsystem() {
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let report = run_selftest(store, test_config()).await;
+        for name in ["store", "run", "tools", "providers"] {
+            assert_eq!(check(&report, name)["ok"], true, "{name}: {report}");
+        }
+        assert_eq!(report["ok"], true);
+        assert!(check(&report, "tools")["detail"]["channels"]["web"].is_number());
+    }
+
+    #[tokio::test]
+    async fn missing_credentials_fail_providers_check() {
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let mut config = (*test_config()).clone();
+        config.providers.github_copilot.enabled = true;
+        let report = run_selftest(store, Arc::new(config)).await;
+        let providers = check(&report, "providers");
+        assert_eq!(providers["ok"], false);
+        assert!(providers["error"]
+            .as_str()
+            .unwrap()
+            .contains("github-copilot/default"));
+        assert_eq!(report["ok"], false);
+    }
+
+    #[tokio::test]
+    async fn store_errors_fail_store_check() {
+        let path = std::env::temp_dir().join(format!("homie-selftest-{}.db", uuid::Uuid::new_v4()));
+        let store = Arc::new(SqliteStore::open(&path).expect("store"));
+        rusqlite::Connection::open(&path)
+            .expect("second connection")
+            .execute_batch("DROP TABLE chat_thread_states;")
+            .expect("drop table");
+
+        let report = run_selftest(store, test_config()).await;
+        let store_check = check(&report, "store");
+        assert_eq!(store_check["ok"], false);
+        assert!(store_check["error"].as_str().is_some());
+        assert_eq!(report["ok"], false);
+        assert_eq!(check(&report, "tools")["ok"], true);
+        let _ = std::fs::remove_file(&path);
+    }
+}
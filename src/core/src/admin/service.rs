@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use homie_protocol::{error_codes, BinaryFrame, Response};
+
+use crate::authz::Scope;
+use crate::router::{ReapEvent, ServiceHandler};
+use crate::storage::{LoginSessionRecord, Store};
+use crate::HomieConfig;
+
+use super::freeze::RunFreeze;
+use super::selftest::run_selftest;
+
+#[derive(Debug, Default, Deserialize)]
+struct FreezeParams {
+    reason: Option<String>,
+    #[serde(default)]
+    cancel_in_flight: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ClearLoginSessionsParams {
+    provider: Option<String>,
+    profile: Option<String>,
+    /// Only drop sessions whose device code has already expired.
+    #[serde(default)]
+    expired_only: bool,
+}
+
+/// Owner-only emergency controls and debugging views.
+pub struct AdminService {
+    run_freeze: RunFreeze,
+    store: Arc<dyn Store>,
+    event_tx: broadcast::Sender<ReapEvent>,
+    homie_config: Arc<HomieConfig>,
+}
+
+impl AdminService {
+    pub fn new(
+        run_freeze: RunFreeze,
+        store: Arc<dyn Store>,
+        event_tx: broadcast::Sender<ReapEvent>,
+        homie_config: Arc<HomieConfig>,
+    ) -> Self {
+        Self {
+            run_freeze,
+            store,
+            event_tx,
+            homie_config,
+        }
+    }
+
+    fn freeze(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let params: FreezeParams = match params {
+            Some(v) => match serde_json::from_value(v) {
+                Ok(p) => p,
+                Err(e) => {
+                    return Response::error(
+                        req_id,
+                        error_codes::INVALID_PARAMS,
+                        format!("invalid params: {e}"),
+                    )
+                }
+            },
+            None => FreezeParams::default(),
+        };
+        let reason = params
+            .reason
+            .map(|r| r.trim().to_string())
+            .filter(|r| !r.is_empty());
+        tracing::warn!(
+            reason = ?reason,
+            cancel_in_flight = params.cancel_in_flight,
+            "agent runs frozen"
+        );
+        self.run_freeze.freeze(reason, params.cancel_in_flight);
+        let mut status = self.run_freeze.status();
+        status["cancel_in_flight"] = Value::Bool(params.cancel_in_flight);
+        let _ = self
+            .event_tx
+            .send(ReapEvent::new("admin.runs.frozen", Some(status.clone())));
+        Response::success(req_id, status)
+    }
+
+    fn unfreeze(&mut self, req_id: Uuid) -> Response {
+        tracing::warn!("agent runs unfrozen");
+        self.run_freeze.unfreeze();
+        let status = self.run_freeze.status();
+        let _ = self
+            .event_tx
+            .send(ReapEvent::new("admin.runs.unfrozen", Some(status.clone())));
+        Response::success(req_id, status)
+    }
+
+    fn login_sessions_list(&mut self, req_id: Uuid) -> Response {
+        match self.store.list_login_sessions() {
+            Ok(sessions) => {
+                let now = chrono::Utc::now().timestamp().max(0) as u64;
+                let sessions: Vec<Value> = sessions
+                    .iter()
+                    .map(|rec| login_session_json(rec, now))
+                    .collect();
+                Response::success(req_id, json!({ "sessions": sessions }))
+            }
+            Err(e) => Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+        }
+    }
+
+    fn login_sessions_clear(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let params: ClearLoginSessionsParams = match params {
+            Some(v) => match serde_json::from_value(v) {
+                Ok(p) => p,
+                Err(e) => {
+                    return Response::error(
+                        req_id,
+                        error_codes::INVALID_PARAMS,
+                        format!("invalid params: {e}"),
+                    )
+                }
+            },
+            None => ClearLoginSessionsParams::default(),
+        };
+        let cleared = if params.expired_only {
+            self.store.prune_login_sessions()
+        } else {
+            self.store
+                .delete_login_sessions(params.provider.as_deref(), params.profile.as_deref())
+        };
+        match cleared {
+            Ok(cleared) => {
+                tracing::info!(
+                    cleared,
+                    provider = ?params.provider,
+                    profile = ?params.profile,
+                    expired_only = params.expired_only,
+                    "login sessions cleared"
+                );
+                Response::success(req_id, json!({ "cleared": cleared }))
+            }
+            Err(e) => Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+        }
+    }
+}
+
+/// Admin view of a pending login. The device code itself is omitted: it is
+/// the secret the server polls with and has no debugging value.
+fn login_session_json(rec: &LoginSessionRecord, now: u64) -> Value {
+    json!({
+        "provider": rec.provider,
+        "profile": rec.profile,
+        "user_code": rec.user_code,
+        "verification_url": rec.verification_url,
+        "interval_secs": rec.interval_secs,
+        "created_at": rec.created_at,
+        "expires_at": rec.expires_at,
+        "expired": rec.expires_at < now,
+    })
+}
+
+impl AdminService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("admin.runs.freeze", Scope::Admin),
+        ("admin.runs.unfreeze", Scope::Admin),
+        ("admin.runs.status", Scope::Admin),
+        ("admin.login.sessions.list", Scope::Admin),
+        ("admin.login.sessions.clear", Scope::Admin),
+        ("admin.selftest", Scope::Admin),
+    ];
+}
+
+impl ServiceHandler for AdminService {
+    fn namespace(&self) -> &str {
+        "admin"
+    }
+
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
+    fn handle_request(
+        &mut self,
+        id: Uuid,
+        method: &str,
+        params: Option<Value>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + '_>> {
+        if method == "admin.selftest" {
+            let store = self.store.clone();
+            let homie_config = self.homie_config.clone();
+            return Box::pin(async move {
+                Response::success(id, run_selftest(store, homie_config).await)
+            });
+        }
+        let resp = match method {
+            "admin.runs.freeze" => self.freeze(id, params),
+            "admin.runs.unfreeze" => self.unfreeze(id),
+            "admin.runs.status" => Response::success(id, self.run_freeze.status()),
+            "admin.login.sessions.list" => self.login_sessions_list(id),
+            "admin.login.sessions.clear" => self.login_sessions_clear(id, params),
+            _ => Response::error(
+                id,
+                error_codes::METHOD_NOT_FOUND,
+                format!("unknown method: {method}"),
+            ),
+        };
+        Box::pin(async move { resp })
+    }
+
+    fn handle_binary(&mut self, _frame: &BinaryFrame) {}
+
+    fn reap(&mut self) -> Vec<ReapEvent> {
+        Vec::new()
+    }
+
+    fn shutdown(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+
+    fn make_service(freeze: RunFreeze) -> (AdminService, Arc<SqliteStore>) {
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let (event_tx, _) = broadcast::channel(4);
+        let homie_config = Arc::new(HomieConfig::default());
+        (
+            AdminService::new(freeze, store.clone(), event_tx, homie_config),
+            store,
+        )
+    }
+
+    #[tokio::test]
+    async fn freeze_is_shared_and_broadcast() {
+        let freeze = RunFreeze::new();
+        let (event_tx, mut event_rx) = broadcast::channel(4);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let mut svc = AdminService::new(
+            freeze.clone(),
+            store,
+            event_tx,
+            Arc::new(HomieConfig::default()),
+        );
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "admin.runs.freeze",
+                Some(json!({ "reason": "incident", "cancel_in_flight": true })),
+            )
+            .await;
+        let result = resp.result.expect("result");
+        assert_eq!(result["frozen"], true);
+        assert_eq!(result["cancel_in_flight"], true);
+        assert!(freeze.is_frozen());
+        assert_eq!(
+            event_rx.try_recv().expect("event").topic,
+            "admin.runs.frozen"
+        );
+
+        let resp = svc
+            .handle_request(Uuid::new_v4(), "admin.runs.unfreeze", None)
+            .await;
+        assert_eq!(resp.result.expect("result")["frozen"], false);
+        assert!(!freeze.is_frozen());
+        assert_eq!(
+            event_rx.try_recv().expect("event").topic,
+            "admin.runs.unfrozen"
+        );
+    }
+
+    #[tokio::test]
+    async fn login_sessions_list_and_clear() {
+        let (mut svc, store) = make_service(RunFreeze::new());
+        let now = chrono::Utc::now().timestamp() as u64;
+        for (provider, profile, expires_at) in [
+            ("github-copilot", "default", now + 600),
+            ("openai-codex", "work", now.saturating_sub(60)),
+        ] {
+            store
+                .upsert_login_session(&LoginSessionRecord {
+                    provider: provider.into(),
+                    profile: profile.into(),
+                    device_code: "secret-device-code".into(),
+                    user_code: "ABCD-1234".into(),
+                    verification_url: "https://example.com/device".into(),
+                    interval_secs: 5,
+                    created_at: now,
+                    expires_at,
+                })
+                .unwrap();
+        }
+
+        let resp = svc
+            .handle_request(Uuid::new_v4(), "admin.login.sessions.list", None)
+            .await;
+        let result = resp.result.expect("result");
+        let sessions = result["sessions"].as_array().expect("sessions");
+        assert_eq!(sessions.len(), 2);
+        let expired = sessions
+            .iter()
+            .find(|s| s["provider"] == "openai-codex")
+            .expect("codex session");
+        assert_eq!(expired["profile"], "work");
+        assert_eq!(expired["expired"], true);
+        assert!(expired.get("device_code").is_none());
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "admin.login.sessions.clear",
+                Some(json!({ "expired_only": true })),
+            )
+            .await;
+        assert_eq!(resp.result.expect("result")["cleared"], 1);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "admin.login.sessions.clear",
+                Some(json!({ "provider": "github-copilot" })),
+            )
+            .await;
+        assert_eq!(resp.result.expect("result")["cleared"], 1);
+        assert!(store.list_login_sessions().unwrap().is_empty());
+    }
+}
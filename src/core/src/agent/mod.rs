@@ -1,6 +1,12 @@
 mod process;
+mod prompt;
 mod roci_backend;
+mod selftest;
 mod service;
 mod tools;
+mod usage;
 
+pub use roci_backend::{check_default_model, RunSlots};
+pub(crate) use selftest::{check_providers, check_run, check_tools};
 pub use service::{AgentService, ChatService};
+pub(crate) use tools::{ip_is_private, supervise_tool_providers};
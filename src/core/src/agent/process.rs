@@ -6,6 +6,7 @@ use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
 use tokio::sync::{mpsc, oneshot};
 
+use crate::homie_config::ChildEnvConfig;
 use crate::paths::{homie_home_dir, homie_skills_dir};
 
 /// Request IDs can be numbers or strings in JSON-RPC.
@@ -47,7 +48,7 @@ pub struct CodexEvent {
 /// # Example
 ///
 /// ```ignore
-/// let (process, event_rx) = CodexProcess::spawn().await?;
+/// let (process, event_rx) = CodexProcess::spawn(&ChildEnvConfig::default()).await?;
 /// process.initialize().await?;
 /// let result = process.send_request("thread/start", None).await?;
 /// ```
@@ -90,13 +91,27 @@ struct PendingEntry {
 
 impl CodexProcess {
     /// Spawn `codex app-server` and return the process handle plus an event
-    /// receiver for notifications/requests from the Codex server.
-    pub async fn spawn() -> Result<(Self, mpsc::Receiver<CodexEvent>), String> {
+    /// receiver for notifications/requests from the Codex server. Variables
+    /// `child_env` denies are not inherited.
+    pub async fn spawn(
+        child_env: &ChildEnvConfig,
+    ) -> Result<(Self, mpsc::Receiver<CodexEvent>), String> {
         let homie_dir = homie_home_dir()?;
         let _ = homie_skills_dir()?;
-        let mut child = Command::new("codex")
-            .arg("app-server")
-            .current_dir(&homie_dir)
+        let mut command = Command::new("codex");
+        for name in child_env.scrubbed_vars() {
+            command.env_remove(name);
+        }
+        command.arg("app-server").current_dir(&homie_dir);
+        Self::spawn_command(command)
+    }
+
+    /// Start `command` as the app-server. Split out of [`Self::spawn`] so
+    /// tests can stand in a scripted server.
+    pub(crate) fn spawn_command(
+        mut command: Command,
+    ) -> Result<(Self, mpsc::Receiver<CodexEvent>), String> {
+        let mut child = command
             .stdin(std::process::Stdio::piped())
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::null())
@@ -444,7 +459,9 @@ mod tests {
             return;
         }
 
-        let (process, mut event_rx) = CodexProcess::spawn().await.expect("spawn codex app-server");
+        let (process, mut event_rx) = CodexProcess::spawn(&ChildEnvConfig::default())
+            .await
+            .expect("spawn codex app-server");
 
         with_timeout(Duration::from_secs(15), process.initialize())
             .await
@@ -0,0 +1,306 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+
+use crate::homie_config::{ChatConfig, UnknownPlaceholders};
+
+const DEFAULT_LOCALE: &str = "en-US";
+
+/// Longest per-chat `system_prompt` override accepted, in characters.
+pub(crate) const MAX_SYSTEM_PROMPT_CHARS: usize = 32_000;
+
+/// Timezone and locale a chat's prompts and timestamps are rendered in.
+///
+/// Per-chat values come from the chat's `timezone` / `locale` settings and
+/// fall back to `[chat]` in config.toml, then UTC / `en-US`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ChatLocale {
+    pub(crate) timezone: Tz,
+    pub(crate) locale: String,
+}
+
+impl Default for ChatLocale {
+    fn default() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            locale: DEFAULT_LOCALE.to_string(),
+        }
+    }
+}
+
+impl ChatLocale {
+    pub(crate) fn resolve(settings: Option<&Value>, defaults: &ChatConfig) -> Self {
+        let setting = |key: &str| {
+            settings
+                .and_then(|s| s.get(key))
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let timezone = setting("timezone")
+            .and_then(|tz| parse_timezone(tz).ok())
+            .or_else(|| {
+                defaults
+                    .timezone
+                    .as_deref()
+                    .and_then(|tz| parse_timezone(tz).ok())
+            })
+            .unwrap_or(Tz::UTC);
+        let locale = setting("locale")
+            .map(str::to_string)
+            .or_else(|| defaults.locale.clone().filter(|l| is_valid_locale(l)))
+            .unwrap_or_else(|| DEFAULT_LOCALE.to_string());
+        Self { timezone, locale }
+    }
+
+    /// Calendar date in the chat's timezone, e.g. `2025-03-14`.
+    pub(crate) fn format_date(&self, at: DateTime<Utc>) -> String {
+        at.with_timezone(&self.timezone)
+            .format("%Y-%m-%d")
+            .to_string()
+    }
+
+    /// Wall-clock time in the chat's timezone, e.g. `09:30 PDT`.
+    pub(crate) fn format_time(&self, at: DateTime<Utc>) -> String {
+        at.with_timezone(&self.timezone)
+            .format("%H:%M %Z")
+            .to_string()
+    }
+}
+
+pub(crate) fn parse_timezone(raw: &str) -> Result<Tz, String> {
+    raw.trim()
+        .parse::<Tz>()
+        .map_err(|_| format!("unknown timezone: {raw}"))
+}
+
+/// Loose BCP 47 check: alphanumeric subtags separated by `-` or `_`.
+pub(crate) fn is_valid_locale(raw: &str) -> bool {
+    let raw = raw.trim();
+    !raw.is_empty()
+        && raw.len() <= 35
+        && raw
+            .split(['-', '_'])
+            .all(|tag| !tag.is_empty() && tag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Validate `timezone` / `locale` keys in a `chat.settings.update` payload.
+/// `null` clears a value and is always accepted.
+pub(crate) fn validate_locale_settings(settings: &Value) -> Result<(), String> {
+    if let Some(tz) = settings.get("timezone").filter(|v| !v.is_null()) {
+        let tz = tz.as_str().ok_or("timezone must be a string")?;
+        parse_timezone(tz)?;
+    }
+    if let Some(locale) = settings.get("locale").filter(|v| !v.is_null()) {
+        let locale = locale.as_str().ok_or("locale must be a string")?;
+        if !is_valid_locale(locale) {
+            return Err(format!("invalid locale: {locale}"));
+        }
+    }
+    Ok(())
+}
+
+/// System prompt template for a chat: its `system_prompt` setting when set
+/// and non-blank, otherwise the server default from `[chat]`.
+pub(crate) fn chat_system_prompt<'a>(settings: Option<&'a Value>, default: &'a str) -> &'a str {
+    settings
+        .and_then(|s| s.get("system_prompt"))
+        .and_then(Value::as_str)
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or(default)
+}
+
+/// Validate the `system_prompt` key in a `chat.settings.update` payload.
+/// `null` clears the override and is always accepted.
+pub(crate) fn validate_system_prompt_settings(settings: &Value) -> Result<(), String> {
+    let Some(prompt) = settings.get("system_prompt").filter(|v| !v.is_null()) else {
+        return Ok(());
+    };
+    let prompt = prompt.as_str().ok_or("system_prompt must be a string")?;
+    if prompt.chars().count() > MAX_SYSTEM_PROMPT_CHARS {
+        return Err(format!(
+            "system_prompt exceeds {MAX_SYSTEM_PROMPT_CHARS} characters"
+        ));
+    }
+    Ok(())
+}
+
+/// Values `{{name}}` placeholders in a system prompt expand to.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PromptVars {
+    values: Vec<(&'static str, String)>,
+}
+
+impl PromptVars {
+    /// `date`, `time`, `timezone` and `locale` for the chat's locale at `now`.
+    pub(crate) fn new(locale: &ChatLocale, now: DateTime<Utc>) -> Self {
+        Self::default()
+            .with("date", Some(&locale.format_date(now)))
+            .with("time", Some(&locale.format_time(now)))
+            .with("timezone", Some(locale.timezone.name()))
+            .with("locale", Some(&locale.locale))
+    }
+
+    /// Add `name`; a missing value still counts as known and renders empty.
+    pub(crate) fn with(mut self, name: &'static str, value: Option<&str>) -> Self {
+        self.values
+            .push((name, value.unwrap_or_default().to_string()));
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Expand `{{name}}` placeholders in a system prompt from `vars`. Names
+/// `vars` doesn't know are kept as written or removed, per `unknown`.
+pub(crate) fn render_system_prompt(
+    template: &str,
+    vars: &PromptVars,
+    unknown: UnknownPlaceholders,
+) -> String {
+    if !template.contains("{{") {
+        return template.to_string();
+    }
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let placeholder = &rest[start..start + 2 + end + 2];
+        let name = after[..end].trim();
+        match vars.get(name) {
+            Some(value) => out.push_str(value),
+            None if unknown == UnknownPlaceholders::Blank && is_placeholder_name(name) => {}
+            None => out.push_str(placeholder),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Only `{{word}}` placeholders are blanked, so literal braces in a prompt
+/// (code samples, templates) survive.
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn late_utc_evening() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 14, 23, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn date_renders_in_chat_timezone() {
+        let settings = json!({ "timezone": "Asia/Tokyo", "locale": "ja-JP" });
+        let locale = ChatLocale::resolve(Some(&settings), &ChatConfig::default());
+        let rendered = render_system_prompt(
+            "Today is {{date}} ({{timezone}}, {{locale}}).",
+            &PromptVars::new(&locale, late_utc_evening()),
+            UnknownPlaceholders::Keep,
+        );
+        assert_eq!(rendered, "Today is 2025-03-15 (Asia/Tokyo, ja-JP).");
+    }
+
+    #[test]
+    fn falls_back_to_server_config_then_utc() {
+        let defaults = ChatConfig {
+            timezone: Some("America/Los_Angeles".into()),
+            locale: Some("en-GB".into()),
+            ..ChatConfig::default()
+        };
+        let locale = ChatLocale::resolve(None, &defaults);
+        assert_eq!(locale.format_date(late_utc_evening()), "2025-03-14");
+        assert_eq!(locale.format_time(late_utc_evening()), "16:30 PDT");
+        assert_eq!(locale.locale, "en-GB");
+
+        let locale = ChatLocale::resolve(None, &ChatConfig::default());
+        assert_eq!(locale, ChatLocale::default());
+        assert_eq!(locale.format_time(late_utc_evening()), "23:30 UTC");
+    }
+
+    #[test]
+    fn invalid_chat_timezone_uses_default() {
+        let settings = json!({ "timezone": "Mars/Olympus" });
+        let locale = ChatLocale::resolve(Some(&settings), &ChatConfig::default());
+        assert_eq!(locale.timezone, Tz::UTC);
+    }
+
+    #[test]
+    fn validates_settings_updates() {
+        assert!(validate_locale_settings(&json!({ "timezone": "Europe/Berlin" })).is_ok());
+        assert!(validate_locale_settings(&json!({ "timezone": null, "locale": null })).is_ok());
+        assert!(validate_locale_settings(&json!({ "timezone": "Nowhere/Land" })).is_err());
+        assert!(validate_locale_settings(&json!({ "locale": "en US" })).is_err());
+        assert!(validate_locale_settings(&json!({ "locale": 5 })).is_err());
+    }
+
+    #[test]
+    fn chat_system_prompt_prefers_override() {
+        let settings = json!({ "system_prompt": "You are a terse shell assistant." });
+        assert_eq!(
+            chat_system_prompt(Some(&settings), "You are Homie."),
+            "You are a terse shell assistant."
+        );
+        let blank = json!({ "system_prompt": "  " });
+        assert_eq!(
+            chat_system_prompt(Some(&blank), "You are Homie."),
+            "You are Homie."
+        );
+        assert_eq!(chat_system_prompt(None, "You are Homie."), "You are Homie.");
+    }
+
+    #[test]
+    fn validates_system_prompt_updates() {
+        assert!(validate_system_prompt_settings(&json!({ "system_prompt": "Be brief." })).is_ok());
+        assert!(validate_system_prompt_settings(&json!({ "system_prompt": null })).is_ok());
+        assert!(validate_system_prompt_settings(&json!({ "system_prompt": 3 })).is_err());
+        let long = "x".repeat(MAX_SYSTEM_PROMPT_CHARS + 1);
+        assert!(validate_system_prompt_settings(&json!({ "system_prompt": long })).is_err());
+    }
+
+    #[test]
+    fn prompts_without_placeholders_are_untouched() {
+        let prompt = "You are Homie.";
+        let vars = PromptVars::new(&ChatLocale::default(), late_utc_evening());
+        assert_eq!(
+            render_system_prompt(prompt, &vars, UnknownPlaceholders::Keep),
+            prompt
+        );
+    }
+
+    #[test]
+    fn context_placeholders_render_and_unknown_ones_follow_the_policy() {
+        let vars = PromptVars::new(&ChatLocale::default(), late_utc_evening())
+            .with("identity", Some("alice@example.com"))
+            .with("cwd", Some("/srv/app"))
+            .with("channel", None);
+        let template =
+            "You help {{ identity }} in {{cwd}} via [{{channel}}]. {{team}} {{ not a var }} {{";
+        assert_eq!(
+            render_system_prompt(template, &vars, UnknownPlaceholders::Keep),
+            "You help alice@example.com in /srv/app via []. {{team}} {{ not a var }} {{"
+        );
+        assert_eq!(
+            render_system_prompt(template, &vars, UnknownPlaceholders::Blank),
+            "You help alice@example.com in /srv/app via [].  {{ not a var }} {{"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::storage::{AttachmentRecord, Store};
+
+/// Attachments up to this many bytes stay inline in the tool result.
+pub(super) const INLINE_ATTACHMENT_BYTES: usize = 32 * 1024;
+/// Largest attachment stored; bigger ones are replaced by an error entry.
+pub(super) const MAX_ATTACHMENT_BYTES: usize = 16 * 1024 * 1024;
+/// Attachments kept per tool result; the rest are dropped.
+const MAX_ATTACHMENTS: usize = 16;
+
+/// Rewrite the `attachments` a tool put in its result so no large blob
+/// travels in events or thread history.
+///
+/// Tools report artifacts as `"attachments": [{"mime_type", "data"}]` with
+/// base64 `data`. Each becomes `{"mime_type", "size", "data_ref"}`, where
+/// `data_ref` is `{"inline": <base64>}` for small blobs, or
+/// `{"attachment_id"}` for blobs stored in `store` under `thread_id` and
+/// fetched with `chat.attachment.read`. Results without attachments are
+/// returned unchanged.
+fn store_tool_attachments(store: &Arc<dyn Store>, thread_id: &str, result: &Value) -> Value {
+    let Some(entries) = result.get("attachments").and_then(Value::as_array) else {
+        return result.clone();
+    };
+    let attachments: Vec<Value> = entries
+        .iter()
+        .take(MAX_ATTACHMENTS)
+        .map(|entry| store_attachment(store, thread_id, entry))
+        .collect();
+    let mut result = result.clone();
+    result["attachments"] = Value::Array(attachments);
+    result
+}
+
+/// [`store_tool_attachments`] on the blocking pool: decoding and writing
+/// blobs must not hold up the run's event loop. A failed task leaves the
+/// result as the tool returned it.
+pub(super) async fn store_tool_attachments_blocking(
+    store: &Arc<dyn Store>,
+    thread_id: &str,
+    result: &Value,
+) -> Value {
+    if result.get("attachments").is_none() {
+        return result.clone();
+    }
+    let (store, thread_id, raw) = (store.clone(), thread_id.to_string(), result.clone());
+    tokio::task::spawn_blocking(move || store_tool_attachments(&store, &thread_id, &raw))
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "storing tool attachments failed");
+            result.clone()
+        })
+}
+
+fn store_attachment(store: &Arc<dyn Store>, thread_id: &str, entry: &Value) -> Value {
+    if entry.get("data_ref").is_some() {
+        return entry.clone();
+    }
+    let mime_type = entry
+        .get("mime_type")
+        .or_else(|| entry.get("mimeType"))
+        .and_then(Value::as_str)
+        .unwrap_or("application/octet-stream");
+    let Some(encoded) = entry.get("data").and_then(Value::as_str) else {
+        return json!({ "mime_type": mime_type, "error": "attachment has no data" });
+    };
+    let data = match BASE64.decode(encoded.trim()) {
+        Ok(data) => data,
+        Err(e) => {
+            return json!({ "mime_type": mime_type, "error": format!("invalid base64: {e}") });
+        }
+    };
+    let size = data.len();
+    if size <= INLINE_ATTACHMENT_BYTES {
+        return json!({
+            "mime_type": mime_type,
+            "size": size,
+            "data_ref": { "inline": BASE64.encode(&data) },
+        });
+    }
+    if size > MAX_ATTACHMENT_BYTES {
+        return json!({
+            "mime_type": mime_type,
+            "size": size,
+            "error": format!("attachment exceeds {MAX_ATTACHMENT_BYTES} bytes"),
+        });
+    }
+    let record = AttachmentRecord {
+        attachment_id: Uuid::new_v4().to_string(),
+        thread_id: thread_id.to_string(),
+        mime_type: mime_type.to_string(),
+        data,
+        created_at: super::now_unix(),
+    };
+    if let Err(e) = store.insert_attachment(&record) {
+        tracing::warn!(%thread_id, error = %e, "failed to store tool attachment");
+        return json!({ "mime_type": mime_type, "size": size, "error": "attachment not stored" });
+    }
+    json!({
+        "mime_type": mime_type,
+        "size": size,
+        "data_ref": { "attachment_id": record.attachment_id },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+
+    #[test]
+    fn small_attachments_stay_inline_and_large_ones_are_stored() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        let small = BASE64.encode(b"tiny");
+        let large = vec![7u8; INLINE_ATTACHMENT_BYTES + 1];
+        let result = json!({
+            "ok": true,
+            "attachments": [
+                { "mime_type": "text/plain", "data": small },
+                { "mimeType": "image/png", "data": BASE64.encode(&large) },
+                { "mime_type": "image/png", "data": "not base64!" },
+            ],
+        });
+
+        let rewritten = store_tool_attachments(&store, "thread-a", &result);
+        assert_eq!(rewritten["ok"], true);
+        let attachments = rewritten["attachments"].as_array().unwrap();
+        assert_eq!(attachments[0]["data_ref"]["inline"], small);
+        assert_eq!(attachments[0]["size"], 4);
+
+        assert_eq!(attachments[1]["mime_type"], "image/png");
+        let id = attachments[1]["data_ref"]["attachment_id"]
+            .as_str()
+            .unwrap();
+        let stored = store.get_attachment(id).unwrap().unwrap();
+        assert_eq!(stored.data, large);
+        assert_eq!(stored.thread_id, "thread-a");
+
+        assert!(attachments[2]["error"].as_str().is_some());
+
+        let plain = json!({ "stdout": "hi" });
+        assert_eq!(store_tool_attachments(&store, "thread-a", &plain), plain);
+    }
+}
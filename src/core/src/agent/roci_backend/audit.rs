@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use roci::agent_loop::ApprovalRequest;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::storage::{Store, ToolInvocationRecord};
+
+use super::state::{RociState, ToolCallInfo};
+
+const REDACTED: &str = "[redacted]";
+
+/// Argument keys whose values never reach the audit trail.
+const SENSITIVE_KEY_PARTS: [&str; 10] = [
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "authorization",
+    "cookie",
+    "credential",
+    "private_key",
+];
+
+/// Approver label for calls allowed by the exec policy.
+pub(super) const APPROVER_EXECPOLICY: &str = "execpolicy";
+/// Approver label for calls allowed by an earlier "accept for session".
+pub(super) const APPROVER_SESSION: &str = "session";
+/// Approver label for client decisions on unauthenticated connections.
+pub(super) const APPROVER_CLIENT: &str = "client";
+/// Approver label for requests declined because nobody answered in time.
+pub(super) const APPROVER_TIMEOUT: &str = "timeout";
+
+/// How a tool call's approval request was resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct ToolApproval {
+    pub(super) approver: String,
+    pub(super) accepted: bool,
+}
+
+/// Remember who resolved an approval so the tool result can be audited
+/// with it. Requests without a `tool_call_id` are not tied to a call.
+pub(super) async fn note_tool_approval(
+    state: &Arc<Mutex<RociState>>,
+    request: &ApprovalRequest,
+    approver: &str,
+    accepted: bool,
+) {
+    let Some(tool_call_id) = request.payload.get("tool_call_id").and_then(Value::as_str) else {
+        return;
+    };
+    let mut guard = state.lock().await;
+    guard.tool_approvals.insert(
+        tool_call_id.to_string(),
+        ToolApproval {
+            approver: approver.to_string(),
+            accepted,
+        },
+    );
+}
+
+impl super::RociBackend {
+    /// Audit a finished tool call when `[tools] audit` is enabled, pairing
+    /// it with the approval recorded for the same call.
+    pub(super) async fn audit_tool_result(
+        &self,
+        chat_id: &str,
+        thread_id: &str,
+        turn_id: &str,
+        tool_call_id: &str,
+        info: &ToolCallInfo,
+        is_error: bool,
+    ) {
+        let approval = self.state.lock().await.tool_approvals.remove(tool_call_id);
+        if !self.tool_audit {
+            return;
+        }
+        record_tool_invocation(
+            &self.store,
+            ToolInvocation {
+                chat_id,
+                thread_id,
+                turn_id,
+                tool_call_id,
+                info,
+                is_error,
+                approval,
+            },
+        );
+    }
+}
+
+struct ToolInvocation<'a> {
+    chat_id: &'a str,
+    thread_id: &'a str,
+    turn_id: &'a str,
+    tool_call_id: &'a str,
+    info: &'a ToolCallInfo,
+    is_error: bool,
+    approval: Option<ToolApproval>,
+}
+
+/// Write one finished tool call to the thread's audit trail.
+fn record_tool_invocation(store: &Arc<dyn Store>, invocation: ToolInvocation<'_>) {
+    let outcome = match &invocation.approval {
+        Some(approval) if !approval.accepted => "declined",
+        _ if invocation.is_error => "error",
+        _ => "ok",
+    };
+    // Hashed after redaction: a digest of the raw arguments would let
+    // anyone reading the audit trail confirm a guessed secret.
+    let args = redact_tool_args(&invocation.info.input);
+    let record = ToolInvocationRecord {
+        invocation_id: Uuid::new_v4().to_string(),
+        chat_id: invocation.chat_id.to_string(),
+        thread_id: invocation.thread_id.to_string(),
+        turn_id: invocation.turn_id.to_string(),
+        tool_call_id: invocation.tool_call_id.to_string(),
+        tool: invocation.info.name.clone(),
+        args_hash: args_hash(&args),
+        args,
+        duration_ms: invocation.info.started_at.elapsed().as_millis() as u64,
+        outcome: outcome.to_string(),
+        approver: invocation.approval.map(|approval| approval.approver),
+        created_at: super::now_unix(),
+    };
+    if let Err(error) = store.insert_tool_invocation(&record) {
+        tracing::warn!(
+            %error,
+            thread_id = %record.thread_id,
+            tool = %record.tool,
+            "failed to record tool invocation"
+        );
+    }
+}
+
+/// Copy of tool arguments with values under sensitive keys replaced.
+fn redact_tool_args(args: &Value) -> Value {
+    match args {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let value = if is_sensitive_key(key) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact_tool_args(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_tool_args).collect()),
+        Value::String(text)
+            if text
+                .trim_start()
+                .to_ascii_lowercase()
+                .starts_with("bearer ") =>
+        {
+            Value::String(REDACTED.to_string())
+        }
+        other => other.clone(),
+    }
+}
+
+fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase().replace('-', "_");
+    SENSITIVE_KEY_PARTS.iter().any(|part| key.contains(part))
+}
+
+fn args_hash(args: &Value) -> String {
+    let canonical = serde_json::to_string(args).unwrap_or_default();
+    Sha256::digest(canonical.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redacts_sensitive_keys_recursively() {
+        let args = json!({
+            "command": "curl example.com",
+            "env": { "API_KEY": "sk-123", "PATH": "/usr/bin" },
+            "headers": [{ "Authorization": "Bearer abc" }, "Bearer xyz"],
+            "password": "hunter2",
+        });
+        assert_eq!(
+            redact_tool_args(&args),
+            json!({
+                "command": "curl example.com",
+                "env": { "API_KEY": REDACTED, "PATH": "/usr/bin" },
+                "headers": [{ "Authorization": REDACTED }, REDACTED],
+                "password": REDACTED,
+            })
+        );
+    }
+
+    #[test]
+    fn hash_covers_only_redacted_args() {
+        let a = json!({ "command": "ls", "token": "one" });
+        let b = json!({ "command": "ls", "token": "two" });
+        let c = json!({ "command": "pwd", "token": "one" });
+        assert_eq!(
+            args_hash(&redact_tool_args(&a)),
+            args_hash(&redact_tool_args(&b))
+        );
+        assert_ne!(
+            args_hash(&redact_tool_args(&a)),
+            args_hash(&redact_tool_args(&c))
+        );
+        assert_eq!(args_hash(&a).len(), 64);
+    }
+}
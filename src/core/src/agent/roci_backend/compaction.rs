@@ -0,0 +1,400 @@
+use roci::agent_loop::{LoopRunner, RunEvent, RunEventPayload, RunLifecycle, RunRequest, Runner};
+use roci::config::RociConfig;
+use roci::models::LanguageModel;
+use roci::types::{ContentPart, ModelMessage, Role};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use super::state::{model_messages_from_turns, RociThreadState, RociTurn};
+use crate::homie_config::{ChatConfig, CompactionStrategy};
+
+/// Messages kept when a run's history is compacted.
+pub(super) const COMPACTION_KEEP_MESSAGES: usize = 80;
+/// Rough chars-per-token ratio used for estimates; not model accurate.
+const CHARS_PER_TOKEN: usize = 4;
+/// Share of the token budget the unsummarized recent history may use.
+const SUMMARY_RECENT_SHARE: usize = 2;
+/// Characters of one message copied into the summarization transcript.
+const SUMMARY_MESSAGE_CHARS: usize = 2_000;
+/// Marks a summary message in a thread's history.
+pub(super) const SUMMARY_PREFIX: &str = "Summary of earlier conversation:\n";
+const SUMMARY_PROMPT: &str = "Summarize the conversation below so it can replace the original \
+messages as context for continuing it. Keep decisions, facts, file paths, open tasks and user \
+preferences; drop pleasantries. Reply with the summary only.";
+
+/// How a backend's runs keep their history within budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct CompactionPolicy {
+    /// Estimated token budget; `0` only applies the message cap.
+    pub(super) max_tokens: usize,
+    pub(super) strategy: CompactionStrategy,
+}
+
+impl CompactionPolicy {
+    pub(super) fn from_config(chat: &ChatConfig) -> Self {
+        Self {
+            max_tokens: chat.max_context_tokens.unwrap_or(0),
+            strategy: chat.compaction_strategy,
+        }
+    }
+
+    /// Whether history over budget is summarized before a run starts.
+    pub(super) fn summarizes(&self) -> bool {
+        self.max_tokens > 0 && self.strategy == CompactionStrategy::Summarize
+    }
+}
+
+/// Compaction hook handed to the roci run loop: keeps the most recent
+/// messages, then drops the oldest non-system messages until the history
+/// fits `max_tokens`.
+pub(super) fn compact_messages(
+    messages: &[ModelMessage],
+    max_tokens: usize,
+) -> Option<Vec<ModelMessage>> {
+    let mut compacted = None;
+    if messages.len() > COMPACTION_KEEP_MESSAGES {
+        compacted =
+            Some(messages[messages.len().saturating_sub(COMPACTION_KEEP_MESSAGES)..].to_vec());
+    }
+    if max_tokens > 0 {
+        let current = compacted.as_deref().unwrap_or(messages);
+        if estimate_tokens(current) > max_tokens {
+            compacted = Some(truncate_to_tokens(current, max_tokens));
+        }
+    }
+    compacted
+}
+
+/// Drop the oldest non-system messages until the estimate fits
+/// `max_tokens`, always keeping the latest message. Tool results are never
+/// left without the call that produced them.
+pub(super) fn truncate_to_tokens(
+    messages: &[ModelMessage],
+    max_tokens: usize,
+) -> Vec<ModelMessage> {
+    let (system, mut rest): (Vec<ModelMessage>, Vec<ModelMessage>) = messages
+        .iter()
+        .cloned()
+        .partition(|msg| msg.role == Role::System);
+    let budget = max_tokens.saturating_sub(estimate_tokens(&system));
+    let mut start = 0;
+    while start + 1 < rest.len() && estimate_tokens(&rest[start..]) > budget {
+        start += 1;
+    }
+    while start + 1 < rest.len() && rest[start].role == Role::Tool {
+        start += 1;
+    }
+    let mut kept = system;
+    kept.extend(rest.drain(start..));
+    kept
+}
+
+pub(super) fn estimate_tokens(messages: &[ModelMessage]) -> usize {
+    messages
+        .iter()
+        .map(|message| {
+            serde_json::to_string(message)
+                .map(|raw| raw.len())
+                .unwrap_or(0)
+        })
+        .sum::<usize>()
+        .div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Number of leading turns an over-budget thread should have summarized so
+/// the turns after them fit half of `max_tokens`. The first `summarized`
+/// turns already are; the last turn, the one about to run, is always kept.
+/// `None` when `messages` fit or no further turn would be summarized.
+pub(super) fn summary_turn_split(
+    messages: &[ModelMessage],
+    turns: &[RociTurn],
+    summarized: usize,
+    max_tokens: usize,
+) -> Option<usize> {
+    if max_tokens == 0 || estimate_tokens(messages) <= max_tokens {
+        return None;
+    }
+    let last = turns.len().checked_sub(1)?;
+    if summarized >= last {
+        return None;
+    }
+    let recent_budget = max_tokens / SUMMARY_RECENT_SHARE;
+    let sizes: Vec<usize> = turns[summarized..last]
+        .iter()
+        .map(|turn| estimate_tokens(&model_messages_from_turns(std::slice::from_ref(turn))))
+        .collect();
+    let mut recent: usize = sizes.iter().sum();
+    let mut split = summarized;
+    for size in sizes {
+        if recent <= recent_budget {
+            break;
+        }
+        recent -= size;
+        split += 1;
+    }
+    (split > summarized).then_some(split)
+}
+
+/// The history message carrying a thread summary.
+pub(super) fn summary_message(summary: &str) -> ModelMessage {
+    ModelMessage::assistant(format!("{SUMMARY_PREFIX}{}", summary.trim()))
+}
+
+/// Ask `model` for a summary of `messages` in a single tool-less run.
+pub(super) async fn summarize_messages(
+    config: RociConfig,
+    model: LanguageModel,
+    messages: &[ModelMessage],
+) -> Result<String, String> {
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<RunEvent>();
+    let mut request = RunRequest::new(
+        model,
+        vec![
+            ModelMessage::system(SUMMARY_PROMPT.to_string()),
+            ModelMessage::user(transcript(messages)),
+        ],
+    );
+    request.event_sink = Some(std::sync::Arc::new(move |event: RunEvent| {
+        let _ = event_tx.send(event);
+    }));
+    let _handle = LoopRunner::new(config)
+        .start(request)
+        .await
+        .map_err(|e| format!("summary run start failed: {e}"))?;
+
+    let mut summary = String::new();
+    while let Some(event) = event_rx.recv().await {
+        match event.payload {
+            RunEventPayload::AssistantDelta { text } => summary.push_str(&text),
+            RunEventPayload::Lifecycle {
+                state: RunLifecycle::Completed,
+            } => break,
+            RunEventPayload::Lifecycle {
+                state: RunLifecycle::Failed { error },
+            } => return Err(format!("summary run failed: {error}")),
+            RunEventPayload::Lifecycle {
+                state: RunLifecycle::Canceled,
+            } => return Err("summary run canceled".into()),
+            _ => {}
+        }
+    }
+    if summary.trim().is_empty() {
+        return Err("summary run returned no text".into());
+    }
+    Ok(summary)
+}
+
+/// Plain-text rendering of `messages` for the summarization prompt.
+fn transcript(messages: &[ModelMessage]) -> String {
+    let mut out = String::new();
+    for message in messages.iter().filter(|msg| msg.role != Role::System) {
+        let role = match message.role {
+            Role::User => "user",
+            Role::Tool => "tool",
+            _ => "assistant",
+        };
+        for part in &message.content {
+            let text = match part {
+                ContentPart::Text { text } => text.clone(),
+                ContentPart::ToolCall(call) => format!("called tool `{}`", call.name),
+                ContentPart::ToolResult(result) => match result.result.as_str() {
+                    Some(text) => text.to_string(),
+                    None => result.result.to_string(),
+                },
+                _ => continue,
+            };
+            let text: String = text.chars().take(SUMMARY_MESSAGE_CHARS).collect();
+            out.push_str(&format!("{role}: {text}\n\n"));
+        }
+    }
+    out
+}
+
+/// Payload of `chat.context.compacted`.
+pub(super) fn compaction_event(
+    thread_id: &str,
+    turn_id: &str,
+    strategy: CompactionStrategy,
+    before: &[ModelMessage],
+    after: &[ModelMessage],
+) -> Value {
+    json!({
+        "threadId": thread_id,
+        "turnId": turn_id,
+        "strategy": strategy.label(),
+        "before": { "messages": before.len(), "tokens": estimate_tokens(before) },
+        "after": { "messages": after.len(), "tokens": estimate_tokens(after) },
+    })
+}
+
+/// Preview what compaction would do to `thread` without touching it.
+/// Under `summarize`, the turns a summary would replace are reported as
+/// `summarize` and `after` counts the messages kept verbatim; the summary
+/// itself is left out, since its size is only known once a model writes it.
+/// When nothing would be summarized the run truncates, so that is previewed
+/// instead, and `strategy` names the one the preview shows.
+pub(super) fn compaction_preview(
+    thread_id: &str,
+    thread: &RociThreadState,
+    policy: CompactionPolicy,
+) -> Value {
+    let messages = &thread.messages;
+    let turns = &thread.thread.turns;
+    let summarized = thread.summary.as_ref().map_or(0, |summary| summary.turns);
+    let split = if policy.summarizes() {
+        summary_turn_split(messages, turns, summarized, policy.max_tokens)
+    } else {
+        None
+    };
+    let before = json!({
+        "messages": messages.len(),
+        "tokens": estimate_tokens(messages),
+    });
+    let Some(split) = split else {
+        let compacted = compact_messages(messages, policy.max_tokens);
+        let after = compacted.as_deref().unwrap_or(messages);
+        return json!({
+            "threadId": thread_id,
+            "strategy": CompactionStrategy::Truncate.label(),
+            "threshold": COMPACTION_KEEP_MESSAGES,
+            "maxTokens": policy.max_tokens,
+            "compacted": compacted.is_some(),
+            "before": before,
+            "after": {
+                "messages": after.len(),
+                "tokens": estimate_tokens(after),
+            },
+        });
+    };
+    let mut older: Vec<ModelMessage> = thread
+        .summary
+        .iter()
+        .map(|summary| summary_message(&summary.text))
+        .collect();
+    older.extend(model_messages_from_turns(&turns[summarized..split]));
+    let mut kept: Vec<ModelMessage> = messages
+        .iter()
+        .filter(|msg| msg.role == Role::System)
+        .cloned()
+        .collect();
+    kept.extend(model_messages_from_turns(&turns[split..]));
+    json!({
+        "threadId": thread_id,
+        "strategy": CompactionStrategy::Summarize.label(),
+        "threshold": COMPACTION_KEEP_MESSAGES,
+        "maxTokens": policy.max_tokens,
+        "compacted": true,
+        "before": before,
+        "summarize": {
+            "turns": split - summarized,
+            "messages": older.len(),
+            "tokens": estimate_tokens(&older),
+        },
+        "after": {
+            "messages": kept.len(),
+            "tokens": estimate_tokens(&kept),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::state::{RociItem, ThreadSummary};
+    use super::*;
+
+    fn history(turns: usize) -> Vec<ModelMessage> {
+        let mut messages = vec![ModelMessage::system("You are Homie.".to_string())];
+        for i in 0..turns {
+            messages.push(ModelMessage::user(format!(
+                "question {i} {}",
+                "x".repeat(200)
+            )));
+            messages.push(ModelMessage::assistant(format!(
+                "answer {i} {}",
+                "y".repeat(200)
+            )));
+        }
+        messages
+    }
+
+    #[test]
+    fn truncates_to_the_token_budget_keeping_system_messages() {
+        let messages = history(20);
+        assert!(compact_messages(&messages, 0).is_none());
+        assert!(compact_messages(&messages, 1_000_000).is_none());
+
+        let compacted = compact_messages(&messages, 500).expect("compacted");
+        assert!(estimate_tokens(&compacted) <= 500);
+        assert_eq!(compacted[0].role, Role::System);
+        assert_eq!(compacted.last(), messages.last());
+    }
+
+    fn turns(count: usize) -> Vec<RociTurn> {
+        (0..count)
+            .map(|i| {
+                RociTurn::new(
+                    format!("turn-{i}"),
+                    vec![
+                        RociItem::user(
+                            format!("u{i}"),
+                            format!("question {i} {}", "x".repeat(200)),
+                        ),
+                        RociItem::assistant(
+                            format!("a{i}"),
+                            format!("answer {i} {}", "y".repeat(200)),
+                        ),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn summary_covers_whole_turns_and_keeps_the_latest() {
+        let turns = turns(20);
+        let messages = model_messages_from_turns(&turns);
+        assert!(summary_turn_split(&messages, &turns, 0, 0).is_none());
+        assert!(summary_turn_split(&messages, &turns, 0, 1_000_000).is_none());
+
+        let split = summary_turn_split(&messages, &turns, 0, 1_000).expect("split");
+        assert!(split < turns.len() - 1);
+        assert!(estimate_tokens(&model_messages_from_turns(&turns[split..turns.len() - 1])) <= 500);
+        // Turns already summarized are not summarized again.
+        assert!(summary_turn_split(&messages, &turns, split, 1_000).is_none());
+        assert!(summary_turn_split(&messages, &turns, 19, 1_000).is_none());
+        assert_eq!(
+            summary_message(" short version "),
+            ModelMessage::assistant(format!("{SUMMARY_PREFIX}short version"))
+        );
+    }
+
+    #[test]
+    fn summarize_preview_reports_the_span_to_summarize() {
+        let mut thread = RociThreadState::new("thread-1".to_string());
+        thread.thread.turns = turns(20);
+        thread.messages = model_messages_from_turns(&thread.thread.turns);
+        let summarize = CompactionPolicy {
+            max_tokens: 1_000,
+            strategy: CompactionStrategy::Summarize,
+        };
+        let split =
+            summary_turn_split(&thread.messages, &thread.thread.turns, 0, 1_000).expect("split");
+
+        let preview = compaction_preview("thread-1", &thread, summarize);
+        assert_eq!(preview["strategy"], "summarize");
+        assert_eq!(preview["compacted"], true);
+        assert_eq!(preview["summarize"]["turns"], split);
+        let kept = model_messages_from_turns(&thread.thread.turns[split..]);
+        assert_eq!(preview["after"]["messages"], kept.len());
+        assert_eq!(preview["after"]["tokens"], estimate_tokens(&kept));
+
+        // Nothing left to summarize: the run truncates, and so does the preview.
+        thread.summary = Some(ThreadSummary {
+            text: "earlier".to_string(),
+            turns: 19,
+        });
+        let preview = compaction_preview("thread-1", &thread, summarize);
+        assert_eq!(preview["strategy"], "truncate");
+        assert!(preview.get("summarize").is_none());
+    }
+}
@@ -4,6 +4,7 @@ use roci::agent_loop::ApprovalRequest;
 use serde_json::Value;
 use tokio::sync::mpsc;
 
+use crate::agent::usage::TokenUsage;
 use crate::outbound::OutboundMessage;
 use crate::storage::Store;
 
@@ -55,6 +56,7 @@ pub(super) struct ToolItemCompletedData<'a> {
     input: &'a Value,
     result: &'a Value,
     is_error: bool,
+    status: &'a str,
 }
 
 impl<'a> ToolItemCompletedData<'a> {
@@ -71,6 +73,20 @@ impl<'a> ToolItemCompletedData<'a> {
             input,
             result,
             is_error,
+            status: if is_error { "failed" } else { "completed" },
+        }
+    }
+
+    /// A tool call cut short by run cancellation.
+    pub(super) fn canceled(
+        item_id: &'a str,
+        tool_name: &'a str,
+        input: &'a Value,
+        result: &'a Value,
+    ) -> Self {
+        Self {
+            status: "canceled",
+            ..Self::new(item_id, tool_name, input, result, true)
         }
     }
 }
@@ -115,6 +131,33 @@ pub(super) fn emit_turn_started(
     );
 }
 
+/// Why a turn ended `failed` or `canceled`, sent as `reason` on
+/// `chat.turn.completed` next to the unchanged `status`.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum TurnEndReason {
+    /// `chat.cancel` from a client.
+    UserCancel,
+    /// An operator froze runs with cancellation.
+    Freeze,
+    /// The provider or agent loop failed the run.
+    ModelError(String),
+    /// The server shut down while the run was streaming.
+    Shutdown,
+}
+
+impl TurnEndReason {
+    pub(super) fn to_value(&self) -> Value {
+        match self {
+            Self::UserCancel => serde_json::json!({ "kind": "user_cancel" }),
+            Self::Freeze => serde_json::json!({ "kind": "freeze" }),
+            Self::Shutdown => serde_json::json!({ "kind": "shutdown" }),
+            Self::ModelError(message) => {
+                serde_json::json!({ "kind": "model_error", "message": message })
+            }
+        }
+    }
+}
+
 pub(super) fn emit_turn_completed(
     outbound: &mpsc::Sender<OutboundMessage>,
     store: &Arc<dyn Store>,
@@ -122,13 +165,44 @@ pub(super) fn emit_turn_completed(
     thread_id: &str,
     turn_id: &str,
     status: &str,
+    reason: Option<&TurnEndReason>,
 ) {
+    let mut params =
+        serde_json::json!({ "threadId": thread_id, "turnId": turn_id, "status": status });
+    if let Some(reason) = reason {
+        params["reason"] = reason.to_value();
+    }
     emit_event(
         outbound,
         store,
         chat_id,
         "chat.turn.completed",
-        Some(serde_json::json!({ "threadId": thread_id, "turnId": turn_id, "status": status })),
+        Some(params),
+    );
+}
+
+/// Report a settled turn's token usage. `estimated` is set when the counts
+/// were estimated by Homie rather than reported by the provider.
+pub(super) fn emit_token_usage(
+    outbound: &mpsc::Sender<OutboundMessage>,
+    store: &Arc<dyn Store>,
+    chat_id: &str,
+    thread_id: &str,
+    turn_id: &str,
+    usage: TokenUsage,
+    estimated: bool,
+) {
+    emit_event(
+        outbound,
+        store,
+        chat_id,
+        "chat.token.usage.updated",
+        Some(serde_json::json!({
+            "threadId": thread_id,
+            "turnId": turn_id,
+            "usage": usage,
+            "estimated": estimated,
+        })),
     );
 }
 
@@ -264,12 +338,11 @@ pub(super) fn emit_tool_item_started(ctx: ToolEventContext<'_>, data: ToolItemSt
 }
 
 pub(super) fn emit_tool_item_completed(ctx: ToolEventContext<'_>, data: ToolItemCompletedData<'_>) {
-    let status = if data.is_error { "failed" } else { "completed" };
     let item = serde_json::json!({
         "id": data.item_id,
         "type": "mcpToolCall",
         "tool": data.tool_name,
-        "status": status,
+        "status": data.status,
         "input": data.input,
         "result": data.result,
         "error": data.is_error,
@@ -283,6 +356,37 @@ pub(super) fn emit_tool_item_completed(ctx: ToolEventContext<'_>, data: ToolItem
     );
 }
 
+/// Live output of a running command, shaped like Codex's
+/// `item/commandExecution/outputDelta`.
+/// One chunk of live output for a tool item.
+pub(super) struct CommandOutput {
+    pub(super) item_id: String,
+    pub(super) stream: &'static str,
+    pub(super) chunk: String,
+}
+
+/// Emit `chat.command.output` for every chunk in `outputs`, advancing the
+/// chat's event pointer once for the whole batch.
+pub(super) fn emit_command_outputs(ctx: ToolEventContext<'_>, outputs: &[CommandOutput]) {
+    if outputs.is_empty() {
+        return;
+    }
+    advance_event_pointer(ctx.store, ctx.chat_id, outputs.len() as u64);
+    for output in outputs {
+        send_event(
+            ctx.outbound,
+            "chat.command.output",
+            Some(serde_json::json!({
+                "threadId": ctx.thread_id,
+                "turnId": ctx.turn_id,
+                "itemId": output.item_id,
+                "chunk": output.chunk,
+                "stream": output.stream,
+            })),
+        );
+    }
+}
+
 pub(super) fn emit_approval_required(
     outbound: &mpsc::Sender<OutboundMessage>,
     store: &Arc<dyn Store>,
@@ -291,21 +395,71 @@ pub(super) fn emit_approval_required(
     turn_id: &str,
     request: &ApprovalRequest,
 ) {
+    emit_event(
+        outbound,
+        store,
+        chat_id,
+        "chat.approval.required",
+        Some(approval_required_params(thread_id, turn_id, request)),
+    );
+}
+
+/// `chat.approval.required` params for `request`.
+pub(super) fn approval_required_params(
+    thread_id: &str,
+    turn_id: &str,
+    request: &ApprovalRequest,
+) -> Value {
     let (command, cwd) = approval_command_from_payload(&request.payload);
+    serde_json::json!({
+        "threadId": thread_id,
+        "turnId": turn_id,
+        "itemId": request.id,
+        "request_id": request.id,
+        "codex_request_id": request.id,
+        "reason": request.reason,
+        "command": command,
+        "cwd": cwd,
+    })
+}
+
+/// Announce a still-pending approval again, marked `redelivered`.
+pub(super) fn emit_approval_redelivered(
+    outbound: &mpsc::Sender<OutboundMessage>,
+    store: &Arc<dyn Store>,
+    chat_id: &str,
+    params: &Value,
+) {
+    let mut params = params.clone();
+    params["redelivered"] = Value::Bool(true);
     emit_event(
         outbound,
         store,
         chat_id,
         "chat.approval.required",
+        Some(params),
+    );
+}
+
+/// An approval request nobody answered within `timeout_secs`; the call was
+/// declined.
+pub(super) fn emit_approval_timeout(
+    ctx: ToolEventContext<'_>,
+    request_id: &str,
+    timeout_secs: u64,
+) {
+    emit_event(
+        ctx.outbound,
+        ctx.store,
+        ctx.chat_id,
+        "chat.approval.timeout",
         Some(serde_json::json!({
-            "threadId": thread_id,
-            "turnId": turn_id,
-            "itemId": request.id,
-            "request_id": request.id,
-            "codex_request_id": request.id,
-            "reason": request.reason,
-            "command": command,
-            "cwd": cwd,
+            "threadId": ctx.thread_id,
+            "turnId": ctx.turn_id,
+            "itemId": request_id,
+            "request_id": request_id,
+            "timeout_secs": timeout_secs,
+            "decision": "decline",
         })),
     );
 }
@@ -347,6 +501,22 @@ pub(super) fn emit_plan_updated(
     );
 }
 
+/// `chat.context.compacted`: a run's history was summarized or truncated.
+pub(super) fn emit_context_compacted(
+    outbound: &mpsc::Sender<OutboundMessage>,
+    store: &Arc<dyn Store>,
+    chat_id: &str,
+    params: Value,
+) {
+    emit_event(
+        outbound,
+        store,
+        chat_id,
+        "chat.context.compacted",
+        Some(params),
+    );
+}
+
 pub(super) fn emit_diff_updated(
     outbound: &mpsc::Sender<OutboundMessage>,
     store: &Arc<dyn Store>,
@@ -495,10 +665,18 @@ fn emit_event(
     topic: &str,
     params: Option<Value>,
 ) {
+    advance_event_pointer(store, chat_id, 1);
+    send_event(outbound, topic, params);
+}
+
+fn advance_event_pointer(store: &Arc<dyn Store>, chat_id: &str, by: u64) {
     if let Ok(Some(chat)) = store.get_chat(chat_id) {
-        let next = chat.event_pointer.saturating_add(1);
+        let next = chat.event_pointer.saturating_add(by);
         let _ = store.update_event_pointer(chat_id, next);
     }
+}
+
+fn send_event(outbound: &mpsc::Sender<OutboundMessage>, topic: &str, params: Option<Value>) {
     match outbound.try_send(OutboundMessage::event(topic, params)) {
         Ok(()) => {}
         Err(mpsc::error::TrySendError::Full(_)) => {
@@ -1,38 +1,56 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use serde_json::Value;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, oneshot, Mutex};
 use uuid::Uuid;
 
 use roci::agent_loop::{ApprovalDecision, ApprovalPolicy};
 use roci::config::RociConfig;
 use roci::models::LanguageModel;
 use roci::tools::Tool;
-use roci::types::{GenerationSettings, ModelMessage, ReasoningEffort, Role};
+use roci::types::{GenerationSettings, ModelMessage, ReasoningEffort};
 
-use crate::agent::tools::{build_tools, ToolContext};
+use crate::admin::RunFreeze;
+use crate::agent::tools::{build_tools, SessionTools, ToolContext, ToolOutputSender};
+use crate::homie_config::{CompactionStrategy, ProvidersConfig};
 use crate::outbound::OutboundMessage;
-use crate::storage::Store;
+use crate::shutdown::ShutdownSignal;
+use crate::storage::{PendingRunRecord, Store};
 use crate::ExecPolicy;
 
+mod attachments;
+mod audit;
+mod compaction;
 mod events;
 mod persistence;
 mod run;
+mod slots;
 mod state;
 
-use self::events::{emit_assistant_item, emit_turn_started, emit_user_item};
+use self::compaction::CompactionPolicy;
+use self::events::{
+    emit_approval_redelivered, emit_assistant_item, emit_context_compacted, emit_turn_completed,
+    emit_turn_started, emit_user_item, TurnEndReason,
+};
 use self::persistence::{
     backfill_thread_state_from_raw_events, decode_persisted_thread_state, persist_roci_raw_event,
     persist_thread_snapshot, PersistedThreadSnapshot,
 };
+pub use self::slots::RunSlots;
+#[cfg(test)]
+use self::state::RociRunState;
 use self::state::{
-    PendingRun, RociItem, RociState, RociThreadState, RociTurn, ToolOutputRetention,
+    model_messages_from_turns, recent_context, set_system_prompt, PendingApproval, PendingRun,
+    RociItem, RociState, RociThread, RociThreadState, RociTurn, SlotWait, ToolOutputRetention,
 };
-#[cfg(test)]
-use self::state::{RociRunState, RociThread};
 
 const DEFAULT_ROCI_MODEL: &str = "openai-codex:gpt-5.1-codex";
 const TOOL_OUTPUT_RETENTION_TURNS: usize = 2;
+/// How long shutdown waits for in-flight runs to persist partial replies.
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChatBackend {
@@ -57,9 +75,25 @@ pub struct RociBackend {
     outbound_tx: mpsc::Sender<OutboundMessage>,
     store: Arc<dyn Store>,
     tools: Vec<Arc<dyn Tool>>,
+    /// Context the tool set was built from; carries the connection's
+    /// session tools so runs can rebuild once a client registers one.
+    tool_ctx: ToolContext,
+    homie_config: Arc<crate::HomieConfig>,
     processes: Arc<crate::agent::tools::ProcessRegistry>,
     exec_policy: Arc<ExecPolicy>,
     raw_events_enabled: bool,
+    run_freeze: RunFreeze,
+    run_slots: RunSlots,
+    shutdown: ShutdownSignal,
+    max_context_messages: usize,
+    compaction: CompactionPolicy,
+    /// Model writing compaction summaries; the run's own when unset.
+    summary_model: Option<LanguageModel>,
+    summary_timeout: Duration,
+    /// Authenticated identity of the owning connection, recorded as the
+    /// approver of tool calls its client approves.
+    identity: Option<String>,
+    tool_audit: bool,
 }
 
 pub struct StartRunRequest<'a> {
@@ -72,6 +106,17 @@ pub struct StartRunRequest<'a> {
     pub config: RociConfig,
     pub collaboration_mode: Option<String>,
     pub system_prompt: Option<String>,
+    /// Plan-only run: every tool call with side effects needs an explicit
+    /// client approval, whatever `approval_policy` and the exec policy say.
+    pub read_only: bool,
+    /// Working directory for the run's tool calls; the server's own when
+    /// unset.
+    pub cwd: Option<PathBuf>,
+    /// Regenerate: drop this turn and every later one as the new turn is
+    /// added, so nothing is lost unless the run starts. Background processes
+    /// of the dropped turns are removed and their messages leave the search
+    /// index. Refused while the thread has a run in flight.
+    pub replace_turn: Option<&'a str>,
 }
 
 impl RociBackend {
@@ -89,7 +134,7 @@ impl RociBackend {
             tool_channel.as_deref(),
         )
         .with_store(store.clone());
-        let tools = match build_tools(tool_ctx, &homie_config) {
+        let tools = match build_tools(tool_ctx.clone(), &homie_config) {
             Ok(tools) => tools,
             Err(error) => {
                 tracing::error!(%error, "failed to build tool registry; using empty tool set");
@@ -101,12 +146,82 @@ impl RociBackend {
             outbound_tx,
             store,
             tools,
+            tool_ctx,
+            homie_config: homie_config.clone(),
             processes,
             exec_policy,
             raw_events_enabled: homie_config.raw_events_enabled(),
+            run_freeze: RunFreeze::new(),
+            run_slots: RunSlots::new(homie_config.chat.max_concurrent_runs),
+            shutdown: ShutdownSignal::new(),
+            max_context_messages: homie_config.chat.max_context_messages.unwrap_or(0),
+            compaction: CompactionPolicy::from_config(&homie_config.chat),
+            summary_model: homie_config.chat.summary_model.as_ref().and_then(|model| {
+                Self::parse_model(Some(model))
+                    .inspect_err(|error| tracing::error!(%error, "ignoring chat.summary_model"))
+                    .ok()
+            }),
+            summary_timeout: Duration::from_secs(homie_config.chat.summary_timeout_secs),
+            identity: None,
+            tool_audit: homie_config.tools.audit,
+        }
+    }
+
+    /// Share the process-wide run kill-switch with this backend.
+    pub fn with_run_freeze(mut self, run_freeze: RunFreeze) -> Self {
+        self.run_freeze = run_freeze;
+        self
+    }
+
+    /// Share the process-wide concurrent run cap with this backend.
+    pub fn with_run_slots(mut self, run_slots: RunSlots) -> Self {
+        self.run_slots = run_slots;
+        self
+    }
+
+    /// Flush in-flight runs' partial replies when the server shuts down.
+    pub fn with_shutdown(mut self, shutdown: ShutdownSignal) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Attribute client approvals on this backend to `identity`.
+    pub fn with_identity(mut self, identity: Option<String>) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Process registry shared by this backend's tools, so directly invoked
+    /// tools and agent turns see the same background processes.
+    pub fn processes(&self) -> Arc<crate::agent::tools::ProcessRegistry> {
+        self.processes.clone()
+    }
+
+    /// Client-registered tools offered to this backend's runs.
+    pub fn session_tools(&self) -> SessionTools {
+        self.tool_ctx.session_tools.clone()
+    }
+
+    /// Tools for a new run: the startup set rebuilt around the run's working
+    /// directory and output stream, plus any session tools registered since.
+    fn run_tools(&self, cwd: Option<&Path>, output: ToolOutputSender) -> Vec<Arc<dyn Tool>> {
+        let mut ctx = self.tool_ctx.clone().with_output(output);
+        if let Some(cwd) = cwd {
+            ctx = ctx.with_cwd(cwd.to_path_buf());
+        }
+        match build_tools(ctx, &self.homie_config) {
+            Ok(tools) => tools,
+            Err(error) => {
+                tracing::warn!(%error, "failed to build run tools; using startup tool set");
+                self.tools.clone()
+            }
         }
     }
 
+    pub fn run_freeze(&self) -> &RunFreeze {
+        &self.run_freeze
+    }
+
     pub async fn ensure_thread(&self, thread_id: &str) {
         {
             let state = self.state.lock().await;
@@ -141,6 +256,59 @@ impl RociBackend {
         serde_json::to_value(&thread.thread).ok()
     }
 
+    /// Summary of the files `apply_patch` changed in a thread; see
+    /// `RociThreadState::diff_summary`.
+    pub async fn thread_diffs(&self, thread_id: &str) -> Option<Value> {
+        let state = self.state.lock().await;
+        Some(state.threads.get(thread_id)?.diff_summary())
+    }
+
+    /// Up to `limit` turns just before `before_turn_id`, or the newest turns
+    /// when it is `None`, as `{thread, total_turns, next_cursor}`.
+    /// `next_cursor` is the oldest returned turn while older turns remain.
+    pub async fn thread_read_page(
+        &self,
+        thread_id: &str,
+        before_turn_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Option<Value>, String> {
+        let state = self.state.lock().await;
+        let Some(thread) = state.threads.get(thread_id) else {
+            return Ok(None);
+        };
+        let turns = &thread.thread.turns;
+        let end = match before_turn_id {
+            Some(turn_id) => turns
+                .iter()
+                .position(|turn| turn.id == turn_id)
+                .ok_or_else(|| format!("unknown turn: {turn_id}"))?,
+            None => turns.len(),
+        };
+        let start = end.saturating_sub(limit);
+        let page = RociThread {
+            id: thread.thread.id.clone(),
+            created_at: thread.thread.created_at,
+            updated_at: thread.thread.updated_at,
+            turns: turns[start..end].to_vec(),
+        };
+        Ok(Some(serde_json::json!({
+            "thread": page,
+            "total_turns": turns.len(),
+            "next_cursor": (start > 0).then(|| turns[start].id.clone()),
+        })))
+    }
+
+    /// Preview what compaction would do to a thread's model messages.
+    pub async fn compaction_preview(&self, thread_id: &str) -> Option<Value> {
+        let state = self.state.lock().await;
+        let thread = state.threads.get(thread_id)?;
+        Some(compaction::compaction_preview(
+            thread_id,
+            thread,
+            self.compaction,
+        ))
+    }
+
     pub async fn thread_list(&self) -> Vec<Value> {
         let state = self.state.lock().await;
         state
@@ -170,6 +338,106 @@ impl RociBackend {
         if let Err(error) = self.store.delete_chat_thread_state(thread_id) {
             tracing::warn!(%thread_id, "failed to delete persisted roci thread state: {error}");
         }
+        if let Err(error) = self.store.delete_pending_runs_for_thread(thread_id) {
+            tracing::warn!(%thread_id, "failed to delete queued roci runs: {error}");
+        }
+    }
+
+    /// Branch `source_thread_id` into a new thread holding a copy of its turns
+    /// up to and including `up_to_turn_id`. The fork is persisted and runs
+    /// independently of the source. Returns how many turns it kept.
+    pub async fn thread_fork(
+        &self,
+        source_thread_id: &str,
+        up_to_turn_id: &str,
+        thread_id: &str,
+    ) -> Result<usize, String> {
+        self.ensure_thread(source_thread_id).await;
+        let snapshot = {
+            let mut state = self.state.lock().await;
+            if state
+                .active_threads
+                .get(source_thread_id)
+                .map(String::as_str)
+                == Some(up_to_turn_id)
+            {
+                return Err(format!("turn {up_to_turn_id} is still running"));
+            }
+            let source = state
+                .threads
+                .get(source_thread_id)
+                .ok_or_else(|| format!("unknown thread: {source_thread_id}"))?;
+            let fork = source
+                .fork(thread_id.to_string(), up_to_turn_id)
+                .ok_or_else(|| format!("unknown turn: {up_to_turn_id}"))?;
+            let snapshot = PersistedThreadSnapshot::from_thread_state(&fork);
+            state.threads.insert(thread_id.to_string(), fork);
+            snapshot
+        };
+        let turns = snapshot.thread.turns.len();
+        persist_thread_snapshot(&self.store, thread_id, Some(snapshot));
+        Ok(turns)
+    }
+
+    /// Create `thread_id` from an exported thread (the `thread` returned by
+    /// `chat.thread.read`). Turn and item ids are regenerated and the model
+    /// history is rebuilt from the turns. Returns the number of turns.
+    pub async fn thread_import(&self, thread_id: &str, exported: Value) -> Result<usize, String> {
+        let exported: RociThread =
+            serde_json::from_value(exported).map_err(|e| format!("invalid thread: {e}"))?;
+        if exported.turns.is_empty() {
+            return Err("invalid thread: no turns".to_string());
+        }
+        if let Some(turn) = exported.turns.iter().find(|turn| turn.items.is_empty()) {
+            return Err(format!("invalid thread: turn {} has no items", turn.id));
+        }
+        let imported = RociThreadState::imported(thread_id.to_string(), &exported.turns);
+        let turns = imported.thread.turns.len();
+        let snapshot = PersistedThreadSnapshot::from_thread_state(&imported);
+        {
+            let mut state = self.state.lock().await;
+            if state.threads.contains_key(thread_id) {
+                return Err(format!("thread {thread_id} already exists"));
+            }
+            state.threads.insert(thread_id.to_string(), imported);
+        }
+        persist_thread_snapshot(&self.store, thread_id, Some(snapshot));
+        Ok(turns)
+    }
+
+    /// Opening message of `turn_id`, the one a regenerate of it sends again.
+    /// Fails when the turn is unknown or the thread has a run in flight.
+    pub async fn turn_message(&self, thread_id: &str, turn_id: &str) -> Result<String, String> {
+        self.ensure_thread(thread_id).await;
+        let state = self.state.lock().await;
+        check_thread_idle(&state, thread_id)?;
+        state
+            .threads
+            .get(thread_id)
+            .ok_or_else(|| format!("unknown thread: {thread_id}"))?
+            .thread
+            .turns
+            .iter()
+            .find(|turn| turn.id == turn_id)
+            .ok_or_else(|| format!("unknown turn: {turn_id}"))?
+            .user_message()
+            .ok_or_else(|| format!("turn {turn_id} has no user message"))
+    }
+
+    /// Drop what truncated turns leave behind: the tool processes whose
+    /// output they kept, and their messages in the search index.
+    fn forget_truncated_turns(&self, thread_id: &str, removed: &[RociTurn], evicted: Vec<String>) {
+        for process_id in evicted {
+            self.processes.remove(&process_id);
+        }
+        let item_ids: Vec<String> = removed
+            .iter()
+            .flat_map(|turn| &turn.items)
+            .map(|item| item.id().to_string())
+            .collect();
+        if let Err(error) = self.store.unindex_chat_items(thread_id, &item_ids) {
+            tracing::warn!(%thread_id, "failed to unindex truncated turns: {error}");
+        }
     }
 
     async fn register_tool_turn(&self, thread_id: &str, turn_id: &str) {
@@ -198,6 +466,23 @@ impl RociBackend {
         }
     }
 
+    /// Thread and turn that started each tracked background process.
+    pub async fn process_owners(&self) -> HashMap<String, (String, String)> {
+        let state = self.state.lock().await;
+        let mut owners = HashMap::new();
+        for (thread_id, turns) in &state.tool_output_cache {
+            for turn in turns {
+                for process_id in &turn.process_ids {
+                    owners.insert(
+                        process_id.clone(),
+                        (thread_id.clone(), turn.turn_id.clone()),
+                    );
+                }
+            }
+        }
+        owners
+    }
+
     async fn record_tool_process(&self, thread_id: &str, turn_id: &str, process_id: String) {
         let evicted = {
             let mut state = self.state.lock().await;
@@ -252,24 +537,33 @@ impl RociBackend {
             config,
             collaboration_mode,
             system_prompt,
+            read_only,
+            cwd,
+            replace_turn,
         } = request;
+        if self.run_freeze.is_frozen() {
+            return Err(self.run_freeze.refusal_message());
+        }
         self.ensure_thread(thread_id).await;
         let system_prompt = system_prompt
             .map(|p| p.trim().to_string())
             .filter(|p| !p.is_empty());
-        let (turn_id, user_item_id, assistant_item_id) = {
+        let (turn_id, user_item_id, assistant_item_id, truncated) = {
             let mut state = self.state.lock().await;
+            let truncated = match replace_turn {
+                Some(replace_turn) => {
+                    let (_, removed, evicted) =
+                        truncate_thread_locked(&mut state, thread_id, replace_turn)?;
+                    Some((removed, evicted))
+                }
+                None => None,
+            };
             let thread = state
                 .threads
                 .get_mut(thread_id)
                 .ok_or_else(|| "thread missing".to_string())?;
             if let Some(prompt) = system_prompt.as_ref() {
-                let has_system = thread.messages.iter().any(|msg| msg.role == Role::System);
-                if !has_system {
-                    thread
-                        .messages
-                        .insert(0, ModelMessage::system(prompt.clone()));
-                }
+                set_system_prompt(&mut thread.messages, prompt);
             }
             let turn_id = Uuid::new_v4().to_string();
             let user_item_id = Uuid::new_v4().to_string();
@@ -283,8 +577,11 @@ impl RociBackend {
                 .messages
                 .push(ModelMessage::user(message.to_string()));
             thread.last_assistant_item_id = Some(assistant_item_id.clone());
-            (turn_id, user_item_id, assistant_item_id)
+            (turn_id, user_item_id, assistant_item_id, truncated)
         };
+        if let Some((removed, evicted)) = truncated {
+            self.forget_truncated_turns(thread_id, &removed, evicted);
+        }
         self.persist_thread_state(thread_id).await;
 
         if self.raw_events_enabled {
@@ -333,14 +630,19 @@ impl RociBackend {
             assistant_item_id.clone(),
         );
 
+        if self.compaction.summarizes() {
+            self.summarize_thread(chat_id, thread_id, &turn_id, &model, &config)
+                .await;
+        }
         let messages = {
             let state = self.state.lock().await;
             state
                 .threads
                 .get(thread_id)
-                .map(|thread| thread.messages.clone())
+                .map(|thread| recent_context(&thread.messages, self.max_context_messages))
                 .unwrap_or_default()
         };
+        let messages = self.truncate_run_context(chat_id, thread_id, &turn_id, messages);
         let pending = PendingRun {
             chat_id: chat_id.to_string(),
             thread_id: thread_id.to_string(),
@@ -352,22 +654,129 @@ impl RociBackend {
             approval_policy,
             config,
             collaboration_mode,
+            read_only,
+            cwd,
+            span: run_span(thread_id, &turn_id),
+        };
+
+        self.enqueue_or_launch(pending, message).await?;
+        Ok(turn_id)
+    }
+
+    /// Put a chat's persisted queued run back in line. The run's turn must
+    /// already be in the thread; it is not added again. Returns `false` when
+    /// the turn is gone and the run was dropped.
+    pub async fn requeue_run(
+        &self,
+        turn_id: &str,
+        request: StartRunRequest<'_>,
+    ) -> Result<bool, String> {
+        let StartRunRequest {
+            chat_id,
+            thread_id,
+            message,
+            model,
+            settings,
+            approval_policy,
+            config,
+            collaboration_mode,
+            system_prompt,
+            read_only,
+            cwd,
+            replace_turn: _,
+        } = request;
+        self.ensure_thread(thread_id).await;
+        let (assistant_item_id, messages) = {
+            let mut state = self.state.lock().await;
+            let Some(thread) = state.threads.get_mut(thread_id) else {
+                return Ok(false);
+            };
+            if let Some(prompt) = system_prompt
+                .as_deref()
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+            {
+                set_system_prompt(&mut thread.messages, prompt);
+            }
+            let Some(position) = thread
+                .thread
+                .turns
+                .iter()
+                .position(|turn| turn.id == turn_id)
+            else {
+                return Ok(false);
+            };
+            let assistant_item_id = thread.thread.turns[position]
+                .items
+                .iter()
+                .find_map(|item| match item {
+                    RociItem::AgentMessage { id, .. } => Some(id.clone()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+            let (mut messages, _) = thread.history_for(&thread.thread.turns[..position]);
+            messages.push(ModelMessage::user(message.to_string()));
+            (
+                assistant_item_id,
+                recent_context(&messages, self.max_context_messages),
+            )
+        };
+        let messages = self.truncate_run_context(chat_id, thread_id, turn_id, messages);
+        let pending = PendingRun {
+            chat_id: chat_id.to_string(),
+            thread_id: thread_id.to_string(),
+            turn_id: turn_id.to_string(),
+            assistant_item_id,
+            messages,
+            model,
+            settings,
+            approval_policy,
+            config,
+            collaboration_mode,
+            read_only,
+            cwd,
+            span: run_span(thread_id, turn_id),
+        };
+        self.enqueue_or_launch(pending, message).await?;
+        let held = {
+            let state = self.state.lock().await;
+            state.slot_waiting.contains_key(turn_id)
+                || state
+                    .run_queue
+                    .get(thread_id)
+                    .is_some_and(|queue| queue.iter().any(|run| run.turn_id == turn_id))
         };
+        if !held {
+            // Started right away: its persisted record is no longer needed.
+            self.forget_pending_run(turn_id);
+        }
+        Ok(true)
+    }
 
+    /// Start `pending` now, or queue it behind its thread's active run. A
+    /// run that is queued or waits for a run slot is persisted until it
+    /// starts so a restart does not lose it.
+    async fn enqueue_or_launch(&self, pending: PendingRun, message: &str) -> Result<(), String> {
+        let chat_id = pending.chat_id.clone();
+        let thread_id = pending.thread_id.clone();
+        let turn_id = pending.turn_id.clone();
         let mut pending = Some(pending);
         let should_start = {
             let mut state = self.state.lock().await;
-            if state.active_threads.contains_key(thread_id) {
+            if state.active_threads.contains_key(&thread_id) {
+                // Persisted under the state lock so the dequeue that deletes
+                // the record cannot run first.
+                self.persist_pending_run(&chat_id, &thread_id, &turn_id, message);
                 state
                     .run_queue
-                    .entry(thread_id.to_string())
+                    .entry(thread_id.clone())
                     .or_default()
                     .push_back(pending.take().unwrap());
                 false
             } else {
                 state
                     .active_threads
-                    .insert(thread_id.to_string(), turn_id.clone());
+                    .insert(thread_id.clone(), turn_id.clone());
                 true
             }
         };
@@ -381,32 +790,188 @@ impl RociBackend {
                     "roci run queued"
                 );
             }
-            return Ok(turn_id);
+            return Ok(());
         }
 
-        if let Err(err) = self.clone().start_run_inner(pending.take().unwrap()).await {
-            {
-                let mut state = self.state.lock().await;
-                if state.active_threads.get(thread_id) == Some(&turn_id) {
-                    state.active_threads.remove(thread_id);
-                }
-            }
-            if let Some(next) = self.dequeue_next_run(thread_id).await {
-                if let Err(next_err) = self.clone().start_run_inner(next).await {
-                    if debug_enabled() {
-                        tracing::debug!(
-                            %chat_id,
-                            %thread_id,
-                            error = %next_err,
-                            "roci queued run start failed"
-                        );
-                    }
-                }
+        let pending = pending.take().unwrap();
+        let Some(slot) = self.run_slots.try_acquire() else {
+            // Every run slot is busy: the thread stays active with this turn
+            // and the run starts once a slot frees up.
+            let (wake, woken) = oneshot::channel();
+            let mut state = self.state.lock().await;
+            self.persist_pending_run(&chat_id, &thread_id, &turn_id, message);
+            state.slot_waiting.insert(
+                turn_id.clone(),
+                SlotWait {
+                    chat_id: chat_id.clone(),
+                    thread_id: thread_id.clone(),
+                    _wake: wake,
+                },
+            );
+            drop(state);
+            if debug_enabled() {
+                tracing::debug!(
+                    %chat_id,
+                    %thread_id,
+                    %turn_id,
+                    "roci run waiting for a run slot"
+                );
             }
+            run::spawn_slot_waiter(self.clone(), pending, woken);
+            return Ok(());
+        };
+
+        if let Err(err) = self.clone().start_run_inner(pending, Some(slot)).await {
+            run::move_to_next_run(self.clone(), &chat_id, &thread_id, &turn_id).await;
             return Err(err);
         }
 
-        Ok(turn_id)
+        Ok(())
+    }
+
+    fn persist_pending_run(&self, chat_id: &str, thread_id: &str, turn_id: &str, message: &str) {
+        let settings = self
+            .store
+            .get_chat(chat_id)
+            .ok()
+            .flatten()
+            .and_then(|rec| rec.settings);
+        let record = PendingRunRecord {
+            turn_id: turn_id.to_string(),
+            chat_id: chat_id.to_string(),
+            thread_id: thread_id.to_string(),
+            message: message.to_string(),
+            settings,
+            queued_at: now_unix(),
+        };
+        if let Err(error) = self.store.insert_pending_run(&record) {
+            tracing::warn!(%thread_id, %turn_id, "failed to persist queued roci run: {error}");
+        }
+    }
+
+    fn forget_pending_run(&self, turn_id: &str) {
+        if let Err(error) = self.store.delete_pending_run(turn_id) {
+            tracing::warn!(%turn_id, "failed to delete queued roci run: {error}");
+        }
+    }
+
+    /// Replace the oldest turns of an over-budget thread with a model-written
+    /// summary, keeping the recent turns verbatim. The summary is skipped
+    /// while another run is writing to the thread; a failed or slow summary
+    /// leaves the thread alone and the run falls back to truncation.
+    async fn summarize_thread(
+        &self,
+        chat_id: &str,
+        thread_id: &str,
+        turn_id: &str,
+        model: &LanguageModel,
+        config: &RociConfig,
+    ) {
+        let (older, covered, prior) = {
+            let state = self.state.lock().await;
+            if state.active_threads.contains_key(thread_id) {
+                return;
+            }
+            let Some(thread) = state.threads.get(thread_id) else {
+                return;
+            };
+            let prior = thread.summary.clone();
+            let summarized = prior.as_ref().map_or(0, |summary| summary.turns);
+            let Some(split) = compaction::summary_turn_split(
+                &thread.messages,
+                &thread.thread.turns,
+                summarized,
+                self.compaction.max_tokens,
+            ) else {
+                return;
+            };
+            let mut older: Vec<ModelMessage> = prior
+                .iter()
+                .map(|summary| compaction::summary_message(&summary.text))
+                .collect();
+            older.extend(model_messages_from_turns(
+                &thread.thread.turns[summarized..split],
+            ));
+            let covered: Vec<String> = thread.thread.turns[..split]
+                .iter()
+                .map(|turn| turn.id.clone())
+                .collect();
+            (older, covered, prior)
+        };
+        let model = self.summary_model.clone().unwrap_or_else(|| model.clone());
+        let summary = compaction::summarize_messages(config.clone(), model, &older);
+        let summary = match tokio::time::timeout(self.summary_timeout, summary).await {
+            Ok(Ok(summary)) => summary,
+            Ok(Err(error)) => {
+                tracing::warn!(%chat_id, %thread_id, %error, "context summary failed");
+                return;
+            }
+            Err(_) => {
+                tracing::warn!(%chat_id, %thread_id, "context summary timed out");
+                return;
+            }
+        };
+        let event = {
+            let mut state = self.state.lock().await;
+            if state.active_threads.contains_key(thread_id) {
+                return;
+            }
+            let Some(thread) = state.threads.get_mut(thread_id) else {
+                return;
+            };
+            // Bail out if the summarized turns were rewritten meanwhile.
+            let unchanged = thread.summary == prior
+                && thread.thread.turns.len() > covered.len()
+                && thread
+                    .thread
+                    .turns
+                    .iter()
+                    .zip(&covered)
+                    .all(|(turn, id)| &turn.id == id);
+            if !unchanged {
+                return;
+            }
+            let before = thread.messages.clone();
+            thread.apply_summary(summary, covered.len());
+            compaction::compaction_event(
+                thread_id,
+                turn_id,
+                CompactionStrategy::Summarize,
+                &before,
+                &thread.messages,
+            )
+        };
+        self.persist_thread_state(thread_id).await;
+        emit_context_compacted(&self.outbound_tx, &self.store, chat_id, event);
+    }
+
+    /// Trim a run's starting messages to the token budget. The stored
+    /// thread keeps its full history.
+    fn truncate_run_context(
+        &self,
+        chat_id: &str,
+        thread_id: &str,
+        turn_id: &str,
+        messages: Vec<ModelMessage>,
+    ) -> Vec<ModelMessage> {
+        let max_tokens = self.compaction.max_tokens;
+        if max_tokens == 0 || compaction::estimate_tokens(&messages) <= max_tokens {
+            return messages;
+        }
+        let truncated = compaction::truncate_to_tokens(&messages, max_tokens);
+        emit_context_compacted(
+            &self.outbound_tx,
+            &self.store,
+            chat_id,
+            compaction::compaction_event(
+                thread_id,
+                turn_id,
+                CompactionStrategy::Truncate,
+                &messages,
+                &truncated,
+            ),
+        );
+        truncated
     }
 
     pub async fn queue_message(
@@ -469,10 +1034,15 @@ impl RociBackend {
         None
     }
 
-    async fn start_run_inner(self, pending: PendingRun) -> Result<(), String> {
-        run::start_run_inner(self, pending).await
+    async fn start_run_inner(
+        self,
+        pending: PendingRun,
+        slot: Option<slots::RunSlot>,
+    ) -> Result<(), String> {
+        run::start_run_inner(self, pending, slot).await
     }
 
+    #[cfg(test)]
     async fn dequeue_next_run(&self, thread_id: &str) -> Option<PendingRun> {
         run::dequeue_next_run(self, thread_id).await
     }
@@ -481,9 +1051,16 @@ impl RociBackend {
         let mut state = self.state.lock().await;
         if let Some(run) = state.runs.get_mut(turn_id) {
             if let Some(mut handle) = run.handle.take() {
+                run.cancel_reason = Some(TurnEndReason::UserCancel);
                 return handle.abort();
             }
         }
+        if let Some(wait) = state.slot_waiting.remove(turn_id) {
+            drop(state);
+            self.end_slot_wait(wait, turn_id, TurnEndReason::UserCancel)
+                .await;
+            return true;
+        }
         let mut removed = false;
         for queue in state.run_queue.values_mut() {
             if let Some(idx) = queue.iter().position(|run| run.turn_id == turn_id) {
@@ -492,10 +1069,92 @@ impl RociBackend {
                 break;
             }
         }
+        if removed {
+            self.forget_pending_run(turn_id);
+        }
         removed
     }
 
+    /// Report a turn that never got a run slot as canceled and hand its
+    /// thread to the next queued run.
+    async fn end_slot_wait(&self, wait: SlotWait, turn_id: &str, reason: TurnEndReason) {
+        tracing::info!(thread_id = %wait.thread_id, %turn_id, "roci run canceled while waiting for a slot");
+        self.forget_pending_run(turn_id);
+        emit_turn_completed(
+            &self.outbound_tx,
+            &self.store,
+            &wait.chat_id,
+            &wait.thread_id,
+            turn_id,
+            "canceled",
+            Some(&reason),
+        );
+        run::move_to_next_run(self.clone(), &wait.chat_id, &wait.thread_id, turn_id).await;
+    }
+
+    /// Abort a turn, running or waiting for a slot, and drop its thread's
+    /// queued runs after a freeze requested cancellation of in-flight work.
+    async fn cancel_frozen_run(&self, thread_id: &str, turn_id: &str) {
+        let mut state = self.state.lock().await;
+        state.run_queue.remove(thread_id);
+        if let Err(error) = self.store.delete_pending_runs_for_thread(thread_id) {
+            tracing::warn!(%thread_id, "failed to delete queued roci runs: {error}");
+        }
+        if let Some(wait) = state.slot_waiting.remove(turn_id) {
+            drop(state);
+            self.end_slot_wait(wait, turn_id, TurnEndReason::Freeze)
+                .await;
+            return;
+        }
+        if let Some(run) = state.runs.get_mut(turn_id) {
+            if let Some(mut handle) = run.handle.take() {
+                tracing::info!(%thread_id, %turn_id, "cancelling run for freeze");
+                run.cancel_reason = Some(TurnEndReason::Freeze);
+                handle.abort();
+            }
+        }
+    }
+
+    /// Abort this backend's runs. Queued runs and runs waiting for a slot
+    /// stay persisted and are released so the next connection allowed to
+    /// run them restores them.
     pub async fn shutdown(&self) {
+        let flushes: Vec<_> = {
+            let mut state = self.state.lock().await;
+            let held = state
+                .run_queue
+                .values()
+                .flatten()
+                .map(|run| &run.turn_id)
+                .chain(state.slot_waiting.keys());
+            for turn_id in held {
+                if let Err(error) = self.store.release_pending_run(turn_id) {
+                    tracing::warn!(%turn_id, "failed to release queued roci run: {error}");
+                }
+            }
+            state
+                .runs
+                .values_mut()
+                .filter_map(|run| run.flush_tx.take())
+                .collect()
+        };
+        // Let each run persist what it has streamed so far before it is
+        // aborted; a run that is stuck only costs the deadline.
+        let mut acks = Vec::new();
+        for flush_tx in flushes {
+            let (ack_tx, ack_rx) = oneshot::channel();
+            if flush_tx.send(ack_tx).is_ok() {
+                acks.push(ack_rx);
+            }
+        }
+        let deadline = tokio::time::Instant::now() + SHUTDOWN_FLUSH_TIMEOUT;
+        for ack in acks {
+            if tokio::time::timeout_at(deadline, ack).await.is_err() {
+                tracing::warn!("roci run did not flush before shutdown");
+                break;
+            }
+        }
+
         let mut state = self.state.lock().await;
         for run in state.runs.values_mut() {
             if let Some(mut handle) = run.handle.take() {
@@ -504,6 +1163,7 @@ impl RociBackend {
         }
         state.runs.clear();
         state.run_queue.clear();
+        state.slot_waiting.clear();
         state.active_threads.clear();
     }
 
@@ -522,15 +1182,25 @@ impl RociBackend {
             .map_err(|e| format!("invalid model: {e}"))
     }
 
+    /// Reasoning effort named by a client `effort` string. An unknown value
+    /// is an error rather than silently dropped, so typos surface.
+    pub fn parse_effort(effort: Option<&String>) -> Result<Option<ReasoningEffort>, String> {
+        let Some(effort) = effort.map(|e| e.trim()).filter(|e| !e.is_empty()) else {
+            return Ok(None);
+        };
+        effort
+            .parse::<ReasoningEffort>()
+            .map(Some)
+            .map_err(|_| format!("invalid effort: {effort}"))
+    }
+
     pub fn parse_settings(
-        effort: Option<&String>,
+        effort: Option<ReasoningEffort>,
         stream_idle_timeout_ms: Option<u64>,
     ) -> GenerationSettings {
         let mut settings = GenerationSettings::default();
         if let Some(effort) = effort {
-            if let Ok(parsed) = effort.parse::<ReasoningEffort>() {
-                settings.reasoning_effort = Some(parsed);
-            }
+            settings.reasoning_effort = Some(effort);
         }
         settings.stream_idle_timeout_ms = stream_idle_timeout_ms;
         settings
@@ -567,43 +1237,295 @@ impl RociBackend {
         }
     }
 
+    /// Announce every approval still waiting on `thread_id` again, oldest
+    /// first, with its original request id so `chat.approval.respond`
+    /// resolves it. Returns the params that were sent.
+    pub async fn redeliver_approvals(&self, thread_id: &str) -> Vec<Value> {
+        let pending: Vec<(String, Value)> = {
+            let state = self.state.lock().await;
+            let mut pending: Vec<&PendingApproval> = state
+                .approvals
+                .values()
+                .filter(|approval| approval.thread_id == thread_id)
+                .collect();
+            pending.sort_by_key(|approval| approval.requested_at);
+            pending
+                .into_iter()
+                .map(|approval| (approval.chat_id.clone(), approval.params.clone()))
+                .collect()
+        };
+        for (chat_id, params) in &pending {
+            emit_approval_redelivered(&self.outbound_tx, &self.store, chat_id, params);
+        }
+        pending.into_iter().map(|(_, params)| params).collect()
+    }
+
     pub async fn respond_approval(&self, request_id: &str, decision: ApprovalDecision) -> bool {
         let mut state = self.state.lock().await;
-        if let Some(tx) = state.approvals.remove(request_id) {
-            return tx.send(decision).is_ok();
+        if let Some(pending) = state.approvals.remove(request_id) {
+            return pending.tx.send(decision).is_ok();
         }
         false
     }
 }
 
-fn default_roci_model() -> String {
+pub(crate) fn default_roci_model() -> String {
     std::env::var("HOMIE_ROCI_MODEL").unwrap_or_else(|_| DEFAULT_ROCI_MODEL.to_string())
 }
 
-pub(super) fn now_unix() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs()
+/// Validate the default model (`HOMIE_ROCI_MODEL`, else the built-in) so a
+/// bad value fails at startup instead of on the first run. `Ok(Some(_))` is a
+/// warning: the model parses but its provider is disabled.
+pub fn check_default_model(providers: &ProvidersConfig) -> Result<Option<String>, String> {
+    check_model(&default_roci_model(), providers)
 }
 
-pub(super) fn debug_enabled() -> bool {
-    matches!(std::env::var("HOMIE_DEBUG").as_deref(), Ok("1"))
-        || matches!(std::env::var("HOME_DEBUG").as_deref(), Ok("1"))
+fn check_model(raw: &str, providers: &ProvidersConfig) -> Result<Option<String>, String> {
+    let model = RociBackend::parse_model(Some(&raw.to_string()))
+        .map_err(|e| format!("default model `{raw}` (HOMIE_ROCI_MODEL): {e}"))?;
+    let provider = model.provider_name();
+    Ok(match providers.model_provider_section(provider) {
+        Some((section, false)) => Some(format!(
+            "default model `{raw}` uses provider `{provider}`, but `{section}` is disabled; \
+             runs with this model will fail auth"
+        )),
+        _ => None,
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::storage::SqliteStore;
-    use roci::auth::{providers::openai_codex::OpenAiCodexAuth, FileTokenStore, TokenStoreConfig};
-    use roci::config::RociConfig;
-    use roci::types::ContentPart;
-    use std::sync::Arc;
+/// Span a run's logs are emitted in, a child of the current (request) span.
+fn run_span(thread_id: &str, turn_id: &str) -> tracing::Span {
+    tracing::info_span!("run", %thread_id, %turn_id)
+}
+
+/// Refuse to rewrite a thread while a run is active or queued on it.
+fn check_thread_idle(state: &RociState, thread_id: &str) -> Result<(), String> {
+    if state.active_threads.contains_key(thread_id)
+        || state
+            .run_queue
+            .get(thread_id)
+            .is_some_and(|queue| !queue.is_empty())
+    {
+        return Err(format!("thread {thread_id} has a run in progress"));
+    }
+    Ok(())
+}
+
+/// Cut `thread_id` back to just before `turn_id`. Returns the turn's user
+/// message, the removed turns and the processes whose retained output went
+/// with them.
+fn truncate_thread_locked(
+    state: &mut RociState,
+    thread_id: &str,
+    turn_id: &str,
+) -> Result<(String, Vec<RociTurn>, Vec<String>), String> {
+    check_thread_idle(state, thread_id)?;
+    let thread = state
+        .threads
+        .get_mut(thread_id)
+        .ok_or_else(|| format!("unknown thread: {thread_id}"))?;
+    let message = thread
+        .thread
+        .turns
+        .iter()
+        .find(|turn| turn.id == turn_id)
+        .ok_or_else(|| format!("unknown turn: {turn_id}"))?
+        .user_message()
+        .ok_or_else(|| format!("turn {turn_id} has no user message"))?;
+    let removed = thread.truncate_before(turn_id).unwrap_or_default();
+    let mut evicted = Vec::new();
+    if let Some(deque) = state.tool_output_cache.get_mut(thread_id) {
+        deque.retain(|entry| {
+            if removed.iter().any(|turn| turn.id == entry.turn_id) {
+                evicted.extend(entry.process_ids.iter().cloned());
+                false
+            } else {
+                true
+            }
+        });
+    }
+    Ok((message, removed, evicted))
+}
+
+pub(super) fn now_unix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub(super) fn debug_enabled() -> bool {
+    matches!(std::env::var("HOMIE_DEBUG").as_deref(), Ok("1"))
+        || matches!(std::env::var("HOME_DEBUG").as_deref(), Ok("1"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+    use roci::auth::{providers::openai_codex::OpenAiCodexAuth, FileTokenStore, TokenStoreConfig};
+    use roci::config::RociConfig;
+    use roci::types::{ContentPart, Role};
+    use serde_json::json;
+    use std::sync::Arc;
     use std::time::Duration;
     use tokio::sync::mpsc;
     use tokio::time::timeout;
 
+    #[test]
+    fn tool_output_goes_to_the_earliest_running_matching_call() {
+        let started = std::time::Instant::now();
+        let call = |name: &str, input: serde_json::Value, offset: u64| state::ToolCallInfo {
+            name: name.to_string(),
+            input,
+            started_at: started + Duration::from_millis(offset),
+        };
+        let mut tool_calls = std::collections::HashMap::new();
+        tool_calls.insert("late".to_string(), call("exec", json!({ "cmd": "ls" }), 20));
+        tool_calls.insert(
+            "early".to_string(),
+            call("exec", json!({ "cmd": "ls" }), 10),
+        );
+        tool_calls.insert(
+            "other".to_string(),
+            call("exec", json!({ "cmd": "pwd" }), 0),
+        );
+        let ls = json!({ "cmd": "ls" });
+        assert_eq!(run::output_call_id(&tool_calls, "exec", &ls), Some("early"));
+
+        tool_calls.remove("early");
+        assert_eq!(run::output_call_id(&tool_calls, "exec", &ls), Some("late"));
+        tool_calls.remove("late");
+        assert_eq!(run::output_call_id(&tool_calls, "exec", &ls), None);
+    }
+
+    #[tokio::test]
+    async fn unanswered_approvals_time_out_and_are_forgotten() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(4);
+        let backend = RociBackend::new(
+            outbound_tx,
+            Arc::new(SqliteStore::open_memory().expect("store")),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+
+        let decision = run::await_approval(
+            &backend.state,
+            "req-1",
+            "chat-1",
+            "thread-1",
+            json!({ "request_id": "req-1" }),
+            Some(Duration::from_millis(20)),
+        )
+        .await;
+        assert!(decision.is_none());
+        assert!(backend.state.lock().await.approvals.is_empty());
+        assert!(
+            !backend
+                .respond_approval("req-1", ApprovalDecision::Accept)
+                .await
+        );
+
+        let waiting = {
+            let state = backend.state.clone();
+            tokio::spawn(async move {
+                run::await_approval(
+                    &state,
+                    "req-2",
+                    "chat-1",
+                    "thread-1",
+                    json!({ "request_id": "req-2" }),
+                    Some(Duration::from_secs(5)),
+                )
+                .await
+            })
+        };
+        while !backend.state.lock().await.approvals.contains_key("req-2") {
+            tokio::task::yield_now().await;
+        }
+        assert!(
+            backend
+                .respond_approval("req-2", ApprovalDecision::Accept)
+                .await
+        );
+        assert!(matches!(
+            waiting.await.expect("join"),
+            Some(ApprovalDecision::Accept)
+        ));
+    }
+
+    #[test]
+    fn unknown_efforts_are_rejected_instead_of_dropped() {
+        assert!(matches!(
+            RociBackend::parse_effort(Some(&"high".to_string())),
+            Ok(Some(_))
+        ));
+        assert!(matches!(RociBackend::parse_effort(None), Ok(None)));
+        assert!(matches!(
+            RociBackend::parse_effort(Some(&"  ".to_string())),
+            Ok(None)
+        ));
+        assert!(matches!(
+            RociBackend::parse_effort(Some(&"hihg".to_string())),
+            Err(err) if err == "invalid effort: hihg"
+        ));
+    }
+
+    #[tokio::test]
+    async fn pending_approvals_are_redelivered_with_their_request_ids() {
+        let (outbound_tx, mut outbound_rx) = mpsc::channel(8);
+        let backend = RociBackend::new(
+            outbound_tx,
+            Arc::new(SqliteStore::open_memory().expect("store")),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        for (request_id, thread_id) in [("req-1", "thread-1"), ("req-2", "thread-2")] {
+            let state = backend.state.clone();
+            tokio::spawn(async move {
+                run::await_approval(
+                    &state,
+                    request_id,
+                    "chat-1",
+                    thread_id,
+                    json!({ "threadId": thread_id, "request_id": request_id }),
+                    None,
+                )
+                .await
+            });
+            while !backend
+                .state
+                .lock()
+                .await
+                .approvals
+                .contains_key(request_id)
+            {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        let redelivered = backend.redeliver_approvals("thread-1").await;
+        assert_eq!(redelivered.len(), 1);
+        assert_eq!(redelivered[0]["request_id"], "req-1");
+        let Ok(OutboundMessage::Event { topic, params }) = outbound_rx.try_recv() else {
+            panic!("expected a redelivered approval");
+        };
+        assert_eq!(topic, "chat.approval.required");
+        let params = params.expect("params");
+        assert_eq!(params["request_id"], "req-1");
+        assert_eq!(params["redelivered"], true);
+        assert!(outbound_rx.try_recv().is_err());
+
+        assert!(
+            backend
+                .respond_approval("req-1", ApprovalDecision::Accept)
+                .await
+        );
+        assert!(backend.redeliver_approvals("thread-1").await.is_empty());
+    }
+
     #[tokio::test]
     async fn queue_message_appends_to_active_turn() {
         let (outbound_tx, _outbound_rx) = mpsc::channel(4);
@@ -638,6 +1560,9 @@ mod tests {
                 RociRunState {
                     thread_id: thread_id.to_string(),
                     handle: Some(handle),
+                    _slot: None,
+                    cancel_reason: None,
+                    flush_tx: None,
                 },
             );
         }
@@ -702,6 +1627,7 @@ mod tests {
                 ModelMessage::assistant("world"),
             ],
             last_assistant_item_id: Some(assistant_item_id.clone()),
+            summary: None,
         };
         store
             .upsert_chat_thread_state(
@@ -765,6 +1691,7 @@ mod tests {
             },
             messages: Vec::new(),
             last_assistant_item_id: Some(assistant_item_id),
+            summary: None,
         };
         store
             .upsert_chat_thread_state(
@@ -899,6 +1826,7 @@ mod tests {
             },
             messages: Vec::new(),
             last_assistant_item_id: None,
+            summary: None,
         };
         store
             .upsert_chat_thread_state(
@@ -925,6 +1853,1313 @@ mod tests {
         assert!(persisted.is_none());
     }
 
+    #[tokio::test]
+    async fn compaction_preview_reports_reduction_without_mutating_state() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(4);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store,
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        let thread_id = "compaction-thread";
+        backend.ensure_thread(thread_id).await;
+        let oversized = compaction::COMPACTION_KEEP_MESSAGES + 20;
+        {
+            let mut state = backend.state.lock().await;
+            let thread = state.threads.get_mut(thread_id).expect("thread");
+            thread.messages = (0..oversized)
+                .map(|i| ModelMessage::user(format!("message {i}")))
+                .collect();
+        }
+
+        let preview = backend
+            .compaction_preview(thread_id)
+            .await
+            .expect("preview");
+        assert_eq!(preview["compacted"], true);
+        assert_eq!(preview["before"]["messages"], oversized);
+        assert_eq!(
+            preview["after"]["messages"],
+            compaction::COMPACTION_KEEP_MESSAGES
+        );
+        let before_tokens = preview["before"]["tokens"].as_u64().expect("tokens");
+        let after_tokens = preview["after"]["tokens"].as_u64().expect("tokens");
+        assert!(after_tokens < before_tokens);
+
+        let state = backend.state.lock().await;
+        let thread = state.threads.get(thread_id).expect("thread");
+        assert_eq!(thread.messages.len(), oversized);
+        assert_eq!(thread.messages[0], ModelMessage::user("message 0"));
+    }
+
+    #[tokio::test]
+    async fn compaction_preview_leaves_small_threads_alone() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(4);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store,
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        let thread_id = "small-thread";
+        backend.ensure_thread(thread_id).await;
+
+        let preview = backend
+            .compaction_preview(thread_id)
+            .await
+            .expect("preview");
+        assert_eq!(preview["compacted"], false);
+        assert_eq!(preview["before"]["messages"], preview["after"]["messages"]);
+        assert!(backend.compaction_preview("missing-thread").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn run_context_over_token_budget_is_truncated_and_reported() {
+        let (outbound_tx, mut outbound_rx) = mpsc::channel(4);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let mut config = crate::HomieConfig::default();
+        config.chat.max_context_tokens = Some(200);
+        let backend = RociBackend::new(
+            outbound_tx,
+            store,
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(config),
+            None,
+        );
+        let mut messages = vec![ModelMessage::system("You are Homie.".to_string())];
+        messages.extend(
+            (0..20).map(|i| ModelMessage::user(format!("message {i} {}", "x".repeat(100)))),
+        );
+
+        let truncated =
+            backend.truncate_run_context("chat-1", "thread-1", "turn-1", messages.clone());
+        assert!(truncated.len() < messages.len());
+        assert_eq!(truncated[0], messages[0]);
+        assert_eq!(truncated.last(), messages.last());
+        let Ok(OutboundMessage::Event { topic, params }) = outbound_rx.try_recv() else {
+            panic!("expected compaction event");
+        };
+        assert_eq!(topic, "chat.context.compacted");
+        let params = params.expect("params");
+        assert_eq!(params["strategy"], "truncate");
+        assert_eq!(params["before"]["messages"], messages.len());
+        assert_eq!(params["after"]["messages"], truncated.len());
+
+        let small = vec![ModelMessage::user("hi".to_string())];
+        assert_eq!(
+            backend.truncate_run_context("chat-1", "thread-1", "turn-2", small.clone()),
+            small
+        );
+        assert!(outbound_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn queued_run_is_persisted_and_requeued_after_restart() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().expect("store"));
+        let new_backend = || {
+            let (outbound_tx, _outbound_rx) = mpsc::channel(16);
+            RociBackend::new(
+                outbound_tx,
+                store.clone(),
+                Arc::new(ExecPolicy::empty()),
+                Arc::new(crate::HomieConfig::default()),
+                None,
+            )
+        };
+        let request = || StartRunRequest {
+            chat_id: "chat-1",
+            thread_id: "thread-1",
+            message: "second",
+            model: RociBackend::parse_model(None).expect("model"),
+            settings: GenerationSettings::default(),
+            approval_policy: ApprovalPolicy::Never,
+            config: RociConfig::from_env(),
+            collaboration_mode: None,
+            system_prompt: None,
+            read_only: false,
+            cwd: None,
+            replace_turn: None,
+        };
+        let occupy = |backend: &RociBackend| {
+            let backend = backend.clone();
+            async move {
+                backend.ensure_thread("thread-1").await;
+                backend
+                    .state
+                    .lock()
+                    .await
+                    .active_threads
+                    .insert("thread-1".to_string(), "turn-running".to_string());
+            }
+        };
+
+        let backend = new_backend();
+        occupy(&backend).await;
+        let turn_id = backend.start_run(request()).await.expect("queued");
+        // A live backend still owns its queue.
+        assert!(store.take_pending_runs("chat-1").unwrap().is_empty());
+        backend.shutdown().await;
+
+        let runs = store.take_pending_runs("chat-1").unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].turn_id, turn_id);
+        assert_eq!(runs[0].message, "second");
+
+        let restored = new_backend();
+        occupy(&restored).await;
+        assert!(restored
+            .requeue_run(&turn_id, request())
+            .await
+            .expect("requeued"));
+        {
+            let state = restored.state.lock().await;
+            let queue = state.run_queue.get("thread-1").expect("queue");
+            assert_eq!(queue.len(), 1);
+            assert_eq!(queue[0].turn_id, turn_id);
+            assert_eq!(
+                queue[0].messages.last(),
+                Some(&ModelMessage::user("second".to_string()))
+            );
+            assert_eq!(state.threads["thread-1"].thread.turns.len(), 1);
+        }
+        assert!(!restored
+            .requeue_run("turn-missing", request())
+            .await
+            .expect("dropped"));
+
+        // Once started, a run is never restored again.
+        let started = restored.dequeue_next_run("thread-1").await.expect("next");
+        assert_eq!(started.turn_id, turn_id);
+        store.release_all_pending_runs().unwrap();
+        assert!(store.take_pending_runs("chat-1").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn start_run_refused_while_frozen_and_resumes_after_unfreeze() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(16);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let freeze = RunFreeze::new();
+        let backend = RociBackend::new(
+            outbound_tx,
+            store,
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        )
+        .with_run_freeze(freeze.clone());
+        let request = || StartRunRequest {
+            chat_id: "chat-1",
+            thread_id: "thread-1",
+            message: "hello",
+            model: RociBackend::parse_model(None).expect("model"),
+            settings: GenerationSettings::default(),
+            approval_policy: ApprovalPolicy::Never,
+            config: RociConfig::from_env(),
+            collaboration_mode: None,
+            system_prompt: None,
+            read_only: false,
+            cwd: None,
+            replace_turn: None,
+        };
+
+        freeze.freeze(Some("incident".into()), false);
+        let err = backend.start_run(request()).await.expect_err("frozen");
+        assert_eq!(err, "agent runs are frozen: incident");
+        assert!(backend.thread_read("thread-1").await.is_none());
+
+        freeze.unfreeze();
+        let _ = backend.start_run(request()).await;
+        let thread = backend.thread_read("thread-1").await.expect("thread");
+        assert_eq!(thread["turns"].as_array().map(Vec::len), Some(1));
+        backend.shutdown().await;
+    }
+
+    fn slot_waiting_backend(
+        slots: &RunSlots,
+        freeze: &RunFreeze,
+    ) -> (RociBackend, mpsc::Receiver<OutboundMessage>) {
+        let (outbound_tx, outbound_rx) = mpsc::channel(16);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store,
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        )
+        .with_run_slots(slots.clone())
+        .with_run_freeze(freeze.clone());
+        (backend, outbound_rx)
+    }
+
+    fn waiting_run_request() -> StartRunRequest<'static> {
+        StartRunRequest {
+            chat_id: "chat-1",
+            thread_id: "thread-1",
+            message: "hello",
+            model: RociBackend::parse_model(None).expect("model"),
+            settings: GenerationSettings::default(),
+            approval_policy: ApprovalPolicy::Never,
+            config: RociConfig::from_env(),
+            collaboration_mode: None,
+            system_prompt: None,
+            read_only: false,
+            cwd: None,
+            replace_turn: None,
+        }
+    }
+
+    /// `reason` of the first `chat.turn.completed` queued on `outbound_rx`.
+    fn turn_completed_reason(outbound_rx: &mut mpsc::Receiver<OutboundMessage>) -> Value {
+        while let Ok(OutboundMessage::Event { topic, params }) = outbound_rx.try_recv() {
+            if topic == "chat.turn.completed" {
+                let params = params.expect("params");
+                assert_eq!(params["status"], "canceled");
+                return params["reason"].clone();
+            }
+        }
+        panic!("expected turn completion");
+    }
+
+    #[tokio::test]
+    async fn start_run_waits_for_a_run_slot_and_cancel_frees_the_thread() {
+        let slots = RunSlots::new(1);
+        let (backend, mut outbound_rx) = slot_waiting_backend(&slots, &RunFreeze::new());
+        let held = slots.try_acquire().expect("slot");
+
+        let turn_id = backend
+            .start_run(waiting_run_request())
+            .await
+            .expect("run waits for a slot");
+        {
+            let state = backend.state.lock().await;
+            assert_eq!(state.active_threads.get("thread-1"), Some(&turn_id));
+            assert!(state.slot_waiting.contains_key(&turn_id));
+            assert!(!state.runs.contains_key(&turn_id));
+        }
+        // A waiting run is persisted like a queued one.
+        backend.store.release_all_pending_runs().unwrap();
+        let runs = backend.store.take_pending_runs("chat-1").unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].turn_id, turn_id);
+
+        assert!(backend.cancel_run(&turn_id).await);
+        {
+            let state = backend.state.lock().await;
+            assert!(!state.active_threads.contains_key("thread-1"));
+            assert!(state.slot_waiting.is_empty());
+        }
+        backend.store.release_all_pending_runs().unwrap();
+        assert!(backend
+            .store
+            .take_pending_runs("chat-1")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            turn_completed_reason(&mut outbound_rx),
+            json!({ "kind": "user_cancel" })
+        );
+        drop(held);
+        assert_eq!(slots.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelling_freeze_ends_runs_waiting_for_a_slot() {
+        let slots = RunSlots::new(1);
+        let freeze = RunFreeze::new();
+        let (backend, mut outbound_rx) = slot_waiting_backend(&slots, &freeze);
+        let held = slots.try_acquire().expect("slot");
+
+        let turn_id = backend
+            .start_run(waiting_run_request())
+            .await
+            .expect("run waits for a slot");
+        freeze.freeze(None, true);
+        timeout(Duration::from_secs(2), async {
+            while backend
+                .state
+                .lock()
+                .await
+                .active_threads
+                .contains_key("thread-1")
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("thread released after freeze");
+        assert!(!backend
+            .state
+            .lock()
+            .await
+            .slot_waiting
+            .contains_key(&turn_id));
+        assert_eq!(
+            turn_completed_reason(&mut outbound_rx),
+            json!({ "kind": "freeze" })
+        );
+        drop(held);
+        assert_eq!(slots.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn run_frozen_while_waiting_for_a_slot_is_refused_once_it_gets_one() {
+        let slots = RunSlots::new(1);
+        let freeze = RunFreeze::new();
+        let (backend, mut outbound_rx) = slot_waiting_backend(&slots, &freeze);
+        let held = slots.try_acquire().expect("slot");
+
+        backend
+            .start_run(waiting_run_request())
+            .await
+            .expect("run waits for a slot");
+        freeze.freeze(None, false);
+        drop(held);
+        timeout(Duration::from_secs(2), async {
+            while backend
+                .state
+                .lock()
+                .await
+                .active_threads
+                .contains_key("thread-1")
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("thread released once the slot frees");
+        assert!(backend.state.lock().await.runs.is_empty());
+        assert_eq!(
+            turn_completed_reason(&mut outbound_rx),
+            json!({ "kind": "freeze" })
+        );
+        assert_eq!(slots.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn thread_read_page_walks_back_from_the_newest_turns() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(4);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store,
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        backend.ensure_thread("thread-1").await;
+        {
+            let mut state = backend.state.lock().await;
+            let thread = state.threads.get_mut("thread-1").expect("thread");
+            for n in 1..=5 {
+                thread
+                    .thread
+                    .turns
+                    .push(RociTurn::new(format!("turn-{n}"), Vec::new()));
+            }
+        }
+        let turn_ids = |page: &Value| -> Vec<String> {
+            page["thread"]["turns"]
+                .as_array()
+                .expect("turns")
+                .iter()
+                .map(|turn| turn["id"].as_str().unwrap_or_default().to_string())
+                .collect()
+        };
+
+        let latest = backend
+            .thread_read_page("thread-1", None, 2)
+            .await
+            .expect("page")
+            .expect("thread");
+        assert_eq!(turn_ids(&latest), ["turn-4", "turn-5"]);
+        assert_eq!(latest["total_turns"], 5);
+        assert_eq!(latest["next_cursor"], "turn-4");
+
+        let older = backend
+            .thread_read_page("thread-1", Some("turn-4"), 2)
+            .await
+            .expect("page")
+            .expect("thread");
+        assert_eq!(turn_ids(&older), ["turn-2", "turn-3"]);
+        let oldest = backend
+            .thread_read_page("thread-1", Some("turn-2"), 2)
+            .await
+            .expect("page")
+            .expect("thread");
+        assert_eq!(turn_ids(&oldest), ["turn-1"]);
+        assert_eq!(oldest["next_cursor"], Value::Null);
+
+        assert!(backend
+            .thread_read_page("thread-1", Some("turn-missing"), 2)
+            .await
+            .is_err());
+        assert!(backend
+            .thread_read_page("thread-2", None, 2)
+            .await
+            .expect("page")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn thread_diffs_sum_apply_patch_lines_per_file() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(4);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store,
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        backend.ensure_thread("thread-1").await;
+        {
+            let mut state = backend.state.lock().await;
+            let thread = state.threads.get_mut("thread-1").expect("thread");
+            let mut turn = RociTurn::new("turn-1".to_string(), Vec::new());
+            let patch =
+                |body: &str| json!({ "diff": format!("*** Begin Patch\n{body}*** End Patch") });
+            state::upsert_tool_item_completed(
+                &mut turn,
+                "call-1",
+                "apply_patch",
+                json!({}),
+                patch("*** Update File: a.rs\n@@\n-x\n+y\n+z\n"),
+                false,
+            );
+            state::upsert_tool_item_completed(
+                &mut turn,
+                "call-2",
+                "apply_patch",
+                json!({}),
+                patch("*** Add File: b.rs\n+one\n+two\n*** Update File: a.rs\n@@\n-z\n"),
+                false,
+            );
+            state::upsert_tool_item_completed(
+                &mut turn,
+                "call-3",
+                "apply_patch",
+                json!({}),
+                patch("*** Update File: c.rs\n@@\n-x\n"),
+                true,
+            );
+            thread.thread.turns.push(turn);
+        }
+
+        let diffs = backend.thread_diffs("thread-1").await.expect("thread");
+        assert_eq!(
+            diffs,
+            json!({ "files": [
+                { "path": "a.rs", "additions": 2, "deletions": 2 },
+                { "path": "b.rs", "additions": 2, "deletions": 0 },
+            ] })
+        );
+        assert!(backend.thread_diffs("thread-2").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn thread_fork_copies_turns_with_fresh_ids() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(4);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store.clone(),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        backend.ensure_thread("source").await;
+        {
+            let mut state = backend.state.lock().await;
+            let thread = state.threads.get_mut("source").expect("thread");
+            thread.messages = vec![ModelMessage::system("You are Homie.".to_string())];
+            for n in 1..=3 {
+                let mut turn = RociTurn::new(format!("turn-{n}"), Vec::new());
+                state::upsert_user_item(&mut turn, &format!("user-{n}"), format!("q{n}"));
+                state::upsert_tool_item_completed(
+                    &mut turn,
+                    &format!("call-{n}"),
+                    "ls",
+                    json!({}),
+                    json!("ok"),
+                    false,
+                );
+                state::upsert_assistant_item(
+                    &mut turn,
+                    &format!("assistant-{n}"),
+                    format!("a{n}"),
+                    false,
+                );
+                thread.thread.turns.push(turn);
+            }
+            thread
+                .messages
+                .extend(state::model_messages_from_turns(&thread.thread.turns));
+        }
+
+        assert!(backend
+            .thread_fork("source", "missing-turn", "fork")
+            .await
+            .is_err());
+        let turns = backend
+            .thread_fork("source", "turn-2", "fork")
+            .await
+            .expect("fork");
+        assert_eq!(turns, 2);
+
+        let state = backend.state.lock().await;
+        let fork = state.threads.get("fork").expect("fork thread");
+        assert_eq!(fork.thread.id, "fork");
+        assert_eq!(fork.thread.turns.len(), 2);
+        let source = state.threads.get("source").expect("source thread");
+        assert_eq!(source.thread.turns.len(), 3);
+        for (forked, original) in fork.thread.turns.iter().zip(&source.thread.turns) {
+            assert_ne!(forked.id, original.id);
+            assert_eq!(forked.items.len(), original.items.len());
+        }
+        let RociItem::AgentMessage { id, text, .. } = &fork.thread.turns[1].items[2] else {
+            panic!("expected assistant item");
+        };
+        assert_eq!(text, "a2");
+        assert_eq!(fork.last_assistant_item_id.as_deref(), Some(id.as_str()));
+        // System prompt, then user/tool call/tool result/assistant per turn.
+        assert_eq!(fork.messages.len(), 1 + 2 * 4);
+        assert_eq!(fork.messages[0].role, Role::System);
+        assert_eq!(
+            fork.messages.last(),
+            Some(&ModelMessage::assistant("a2".to_string()))
+        );
+        drop(state);
+
+        assert!(store
+            .get_chat_thread_state("fork")
+            .expect("persisted read")
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn regenerate_replaces_turns_once_the_run_starts() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(4);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let slots = RunSlots::new(1);
+        let backend = RociBackend::new(
+            outbound_tx,
+            store.clone(),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        )
+        .with_run_slots(slots.clone());
+        // The regenerated run waits for the slot instead of launching.
+        let _held = slots.try_acquire().expect("slot");
+        store
+            .upsert_chat(&crate::storage::ChatRecord {
+                chat_id: "chat-1".to_string(),
+                thread_id: "thread".to_string(),
+                created_at: String::new(),
+                status: crate::storage::SessionStatus::Active,
+                event_pointer: 0,
+                settings: None,
+                owner: None,
+            })
+            .expect("chat");
+        backend.ensure_thread("thread").await;
+        {
+            let mut state = backend.state.lock().await;
+            let thread = state.threads.get_mut("thread").expect("thread");
+            thread.messages = vec![ModelMessage::system("You are Homie.".to_string())];
+            for n in 1..=3 {
+                let mut turn = RociTurn::new(format!("turn-{n}"), Vec::new());
+                state::upsert_user_item(&mut turn, &format!("user-{n}"), format!("q{n}"));
+                state::upsert_assistant_item(
+                    &mut turn,
+                    &format!("assistant-{n}"),
+                    format!("a{n}"),
+                    false,
+                );
+                thread.thread.turns.push(turn);
+            }
+            thread
+                .messages
+                .extend(state::model_messages_from_turns(&thread.thread.turns));
+            state
+                .tool_output_cache
+                .entry("thread".to_string())
+                .or_default()
+                .push_back(ToolOutputRetention {
+                    turn_id: "turn-3".to_string(),
+                    process_ids: vec!["proc-3".to_string()],
+                });
+        }
+
+        backend.persist_thread_state("thread").await;
+        assert_eq!(store.search_chats("q3", None, 10).expect("search").len(), 1);
+        let request = |replace_turn| StartRunRequest {
+            chat_id: "chat-1",
+            thread_id: "thread",
+            message: "q2 again",
+            model: RociBackend::parse_model(None).expect("model"),
+            settings: GenerationSettings::default(),
+            approval_policy: ApprovalPolicy::Never,
+            config: RociConfig::from_env(),
+            collaboration_mode: None,
+            system_prompt: None,
+            read_only: false,
+            cwd: None,
+            replace_turn: Some(replace_turn),
+        };
+
+        assert!(backend.start_run(request("missing-turn")).await.is_err());
+        assert_eq!(
+            backend.state.lock().await.threads["thread"]
+                .thread
+                .turns
+                .len(),
+            3
+        );
+        assert_eq!(
+            backend
+                .turn_message("thread", "turn-2")
+                .await
+                .expect("message"),
+            "q2"
+        );
+        let turn_id = backend.start_run(request("turn-2")).await.expect("start");
+
+        let state = backend.state.lock().await;
+        let thread = state.threads.get("thread").expect("thread");
+        let turns: Vec<&str> = thread.thread.turns.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(turns, ["turn-1", turn_id.as_str()]);
+        // System prompt, turn 1, then the new message.
+        assert_eq!(thread.messages.len(), 4);
+        assert_eq!(thread.messages[0].role, Role::System);
+        assert_eq!(
+            thread.messages.last(),
+            Some(&ModelMessage::user("q2 again".to_string()))
+        );
+        assert!(state.tool_output_cache["thread"]
+            .iter()
+            .all(|entry| entry.turn_id != "turn-3"));
+        drop(state);
+        assert!(store
+            .search_chats("q3", None, 10)
+            .expect("search")
+            .is_empty());
+
+        let persisted = store
+            .get_chat_thread_state("thread")
+            .expect("persisted read")
+            .expect("persisted state");
+        let snapshot: PersistedThreadSnapshot =
+            serde_json::from_value(persisted).expect("snapshot decode");
+        assert_eq!(snapshot.thread.turns.len(), 2);
+    }
+
+    #[test]
+    fn settled_runs_record_estimated_usage() {
+        let store = SqliteStore::open_memory().expect("store");
+        let usage = crate::storage::TurnUsageRecord {
+            chat_id: "chat-1".into(),
+            thread_id: "thread-1".into(),
+            turn_id: "turn-1".into(),
+            identity: Some("alice".into()),
+            model: Some("openai:gpt-5".into()),
+            input: 50,
+            output: 0,
+            total: 0,
+            created_at: now_unix(),
+        };
+        let reported = run::record_run_usage(&store, usage, &"x".repeat(400));
+        let buckets = store
+            .usage_summary(&crate::storage::UsageQuery {
+                chat_id: None,
+                identity: Some("alice".into()),
+                from: 0,
+                to: u64::MAX,
+                group_by: crate::storage::UsageGroupBy::Model,
+            })
+            .expect("summary");
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].key, "openai:gpt-5");
+        assert_eq!(buckets[0].input, 50);
+        assert!(buckets[0].output >= 100);
+        assert_eq!(buckets[0].total, buckets[0].input + buckets[0].output);
+        assert_eq!(
+            reported,
+            crate::agent::usage::TokenUsage::estimated(50, buckets[0].output)
+        );
+    }
+
+    #[test]
+    fn summary_is_kept_through_fork_truncate_and_persistence() {
+        let mut thread = RociThreadState::new("thread".to_string());
+        thread.messages = vec![ModelMessage::system("You are Homie.".to_string())];
+        for n in 1..=4 {
+            let mut turn = RociTurn::new(format!("turn-{n}"), Vec::new());
+            state::upsert_user_item(&mut turn, &format!("user-{n}"), format!("q{n}"));
+            state::upsert_assistant_item(
+                &mut turn,
+                &format!("assistant-{n}"),
+                format!("a{n}"),
+                false,
+            );
+            thread.thread.turns.push(turn);
+        }
+        thread
+            .messages
+            .extend(state::model_messages_from_turns(&thread.thread.turns));
+        // The turn about to run: its reply is still empty.
+        thread.thread.turns.push(RociTurn::new(
+            "turn-5".to_string(),
+            vec![
+                RociItem::user("user-5".to_string(), "q5".to_string()),
+                RociItem::assistant("assistant-5".to_string(), String::new()),
+            ],
+        ));
+        thread.messages.push(ModelMessage::user("q5".to_string()));
+
+        thread.apply_summary("earlier talk".to_string(), 2);
+        let summary = compaction::summary_message("earlier talk");
+        assert_eq!(thread.messages[0].role, Role::System);
+        assert_eq!(thread.messages[1], summary);
+        assert_eq!(
+            &thread.messages[2..],
+            &[
+                ModelMessage::user("q3".to_string()),
+                ModelMessage::assistant("a3".to_string()),
+                ModelMessage::user("q4".to_string()),
+                ModelMessage::assistant("a4".to_string()),
+                ModelMessage::user("q5".to_string()),
+            ]
+        );
+
+        let fork = thread.fork("fork".to_string(), "turn-3").expect("fork");
+        assert_eq!(fork.summary, thread.summary);
+        assert_eq!(fork.messages[1], summary);
+        assert_eq!(fork.messages.len(), 4);
+        let early = thread.fork("early".to_string(), "turn-1").expect("fork");
+        assert!(early.summary.is_none());
+        assert!(!early.messages.contains(&summary));
+        assert_eq!(early.messages.len(), 3);
+
+        let decoded = decode_persisted_thread_state(
+            "thread",
+            serde_json::to_value(PersistedThreadSnapshot::from_thread_state(&thread))
+                .expect("snapshot encode"),
+        )
+        .expect("decode");
+        assert_eq!(decoded.summary, thread.summary);
+        assert_eq!(decoded.messages, thread.messages);
+
+        let mut kept = thread.clone();
+        kept.truncate_before("turn-4").expect("truncate");
+        assert_eq!(kept.summary, thread.summary);
+        assert_eq!(kept.messages.len(), 4);
+        assert_eq!(kept.messages[1], summary);
+        thread.truncate_before("turn-2").expect("truncate");
+        assert!(thread.summary.is_none());
+        assert_eq!(thread.messages.len(), 3);
+        assert!(!thread.messages.contains(&summary));
+    }
+
+    #[tokio::test]
+    async fn regenerate_refuses_active_threads() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(4);
+        let backend = RociBackend::new(
+            outbound_tx,
+            Arc::new(SqliteStore::open_memory().expect("store")),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        backend.ensure_thread("thread").await;
+        {
+            let mut state = backend.state.lock().await;
+            let thread = state.threads.get_mut("thread").expect("thread");
+            let mut turn = RociTurn::new("turn-1".to_string(), Vec::new());
+            state::upsert_user_item(&mut turn, "user-1", "q1".to_string());
+            thread.thread.turns.push(turn);
+            state
+                .active_threads
+                .insert("thread".to_string(), "turn-1".to_string());
+        }
+        let err = backend.turn_message("thread", "turn-1").await.unwrap_err();
+        assert!(err.contains("in progress"));
+        let err = backend
+            .start_run(StartRunRequest {
+                chat_id: "chat-1",
+                thread_id: "thread",
+                message: "q1",
+                model: RociBackend::parse_model(None).expect("model"),
+                settings: GenerationSettings::default(),
+                approval_policy: ApprovalPolicy::Never,
+                config: RociConfig::from_env(),
+                collaboration_mode: None,
+                system_prompt: None,
+                read_only: false,
+                cwd: None,
+                replace_turn: Some("turn-1"),
+            })
+            .await
+            .unwrap_err();
+        assert!(err.contains("in progress"));
+        assert_eq!(
+            backend.state.lock().await.threads["thread"]
+                .thread
+                .turns
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn default_model_check_warns_when_provider_disabled() {
+        let mut providers = ProvidersConfig::default();
+        assert_eq!(
+            check_model("openai-codex:gpt-5.1-codex", &providers),
+            Ok(None)
+        );
+
+        providers.openai_codex.enabled = false;
+        let warning = check_model("openai-codex:gpt-5.1-codex", &providers)
+            .expect("model parses")
+            .expect("disabled provider warning");
+        assert!(warning.contains("providers.openai_codex"));
+        // Bare names are `openai` models, keyed by env rather than config.
+        assert_eq!(check_model("gpt-4o", &providers), Ok(None));
+    }
+
+    #[test]
+    fn set_system_prompt_replaces_changed_override() {
+        let mut messages = vec![ModelMessage::user("hi".to_string())];
+        set_system_prompt(&mut messages, "You are Homie.");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, Role::System);
+
+        set_system_prompt(&mut messages, "You are Homie.");
+        assert_eq!(messages.len(), 2);
+
+        set_system_prompt(&mut messages, "You are a terse shell assistant.");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].content,
+            vec![ContentPart::Text {
+                text: "You are a terse shell assistant.".to_string()
+            }]
+        );
+        assert_eq!(messages[1].role, Role::User);
+    }
+
+    #[test]
+    fn long_thread_context_is_capped_to_recent_window() {
+        use super::state::model_messages_from_turns;
+
+        let turns: Vec<RociTurn> = (0..50)
+            .map(|n| {
+                RociTurn::new(
+                    format!("turn-{n}"),
+                    vec![
+                        RociItem::user(format!("user-{n}"), format!("question {n}")),
+                        RociItem::tool_call(
+                            format!("call-{n}"),
+                            "read".into(),
+                            "completed".into(),
+                            json!({ "path": "README.md" }),
+                            Some(json!({ "content": "..." })),
+                            false,
+                        ),
+                        RociItem::assistant(format!("assistant-{n}"), format!("answer {n}")),
+                    ],
+                )
+            })
+            .collect();
+        let mut messages = model_messages_from_turns(&turns);
+        set_system_prompt(&mut messages, "You are Homie.");
+        assert_eq!(messages.len(), 201);
+
+        let context = recent_context(&messages, 10);
+        assert_eq!(context[0].role, Role::System);
+        // The 10-message window opens on a tool result, which is dropped
+        // along with its cut-off call.
+        assert_eq!(context.len(), 1 + 9);
+        assert_ne!(context[1].role, Role::Tool);
+        assert_eq!(context.last(), messages.last());
+        assert_eq!(
+            context[context.len() - 4],
+            ModelMessage::user("question 49")
+        );
+
+        assert_eq!(recent_context(&messages, 0).len(), messages.len());
+        assert_eq!(recent_context(&messages, 500).len(), messages.len());
+    }
+
+    #[tokio::test]
+    async fn cancel_marks_in_flight_tool_calls_canceled() {
+        let (outbound_tx, mut outbound_rx) = mpsc::channel(16);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store.clone(),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        let thread_id = "thread-cancel";
+        let turn_id = Uuid::new_v4().to_string();
+        backend.ensure_thread(thread_id).await;
+
+        // A turn with one finished tool call and one long-running `exec`
+        // that is still waiting on its command when the user cancels.
+        let (handle, _abort_rx, _result_tx, _input_rx) =
+            roci::agent_loop::RunHandle::new(Uuid::new_v4());
+        {
+            let mut state = backend.state.lock().await;
+            let thread = state.threads.get_mut(thread_id).expect("thread");
+            let mut turn = RociTurn::new(turn_id.clone(), Vec::new());
+            state::upsert_tool_item_started(&mut turn, "call-done", "read", json!({}));
+            state::upsert_tool_item_completed(
+                &mut turn,
+                "call-done",
+                "read",
+                json!({}),
+                json!("ok"),
+                false,
+            );
+            state::upsert_tool_item_started(
+                &mut turn,
+                "call-slow",
+                "exec",
+                json!({ "command": "sleep 600" }),
+            );
+            thread.thread.turns.push(turn);
+            state
+                .active_threads
+                .insert(thread_id.to_string(), turn_id.clone());
+            state.runs.insert(
+                turn_id.clone(),
+                RociRunState {
+                    thread_id: thread_id.to_string(),
+                    handle: Some(handle),
+                    _slot: None,
+                    cancel_reason: None,
+                    flush_tx: None,
+                },
+            );
+        }
+
+        assert!(backend.cancel_run(&turn_id).await);
+        run::finish_canceled_turn(&backend, "chat-cancel", thread_id, &turn_id).await;
+
+        let thread = backend.thread_read(thread_id).await.expect("thread");
+        let items = thread["turns"][0]["items"].as_array().expect("items");
+        assert_eq!(items[0]["status"], "completed");
+        assert_eq!(items[1]["status"], "canceled");
+        assert_eq!(items[1]["error"], true);
+        assert_eq!(items[1]["result"]["note"], state::TOOL_CANCELED_NOTE);
+
+        let persisted = store
+            .get_chat_thread_state(thread_id)
+            .expect("persisted state read")
+            .expect("persisted state");
+        let snapshot: PersistedThreadSnapshot =
+            serde_json::from_value(persisted).expect("snapshot decode");
+        match &snapshot.thread.turns[0].items[1] {
+            RociItem::ToolCall { status, .. } => assert_eq!(status, "canceled"),
+            other => panic!("unexpected item: {other:?}"),
+        }
+        let last = snapshot.messages.last().expect("tool result message");
+        assert!(matches!(
+            last.content.first(),
+            Some(ContentPart::ToolResult(result)) if result.tool_call_id == "call-slow"
+        ));
+
+        let mut topics = Vec::new();
+        while let Ok(OutboundMessage::Event { topic, params }) = outbound_rx.try_recv() {
+            topics.push(topic.clone());
+            let params = params.expect("params");
+            if topic == "chat.item.completed" {
+                assert_eq!(params["item"]["id"], "call-slow");
+                assert_eq!(params["item"]["status"], "canceled");
+            } else if topic == "chat.turn.completed" {
+                assert_eq!(params["status"], "canceled");
+                assert_eq!(params["reason"], json!({ "kind": "user_cancel" }));
+            }
+        }
+        assert_eq!(topics, vec!["chat.item.completed", "chat.turn.completed"]);
+
+        let state = backend.state.lock().await;
+        assert!(state.runs.is_empty());
+        assert!(!state.active_threads.contains_key(thread_id));
+    }
+
+    #[tokio::test]
+    async fn shutdown_persists_partial_replies_as_incomplete() {
+        let (outbound_tx, mut outbound_rx) = mpsc::channel(16);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store.clone(),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        let thread_id = "thread-shutdown";
+        let turn_id = Uuid::new_v4().to_string();
+        backend.ensure_thread(thread_id).await;
+
+        let (handle, _abort_rx, _result_tx, _input_rx) =
+            roci::agent_loop::RunHandle::new(Uuid::new_v4());
+        let (flush_tx, flush_rx) = oneshot::channel::<oneshot::Sender<()>>();
+        {
+            let mut state = backend.state.lock().await;
+            let thread = state.threads.get_mut(thread_id).expect("thread");
+            thread.thread.turns.push(RociTurn::new(
+                turn_id.clone(),
+                vec![RociItem::assistant("assistant-1".into(), String::new())],
+            ));
+            state
+                .active_threads
+                .insert(thread_id.to_string(), turn_id.clone());
+            state.runs.insert(
+                turn_id.clone(),
+                RociRunState {
+                    thread_id: thread_id.to_string(),
+                    handle: Some(handle),
+                    _slot: None,
+                    cancel_reason: None,
+                    flush_tx: Some(flush_tx),
+                },
+            );
+        }
+
+        // Stand-in for the run's event task, halfway through its reply.
+        let task_backend = backend.clone();
+        let task_turn_id = turn_id.clone();
+        let task = tokio::spawn(async move {
+            let ack = flush_rx.await.expect("flush request");
+            run::finish_interrupted_turn(
+                &task_backend,
+                "chat-shutdown",
+                thread_id,
+                &task_turn_id,
+                "assistant-1",
+                "Half an ans",
+            )
+            .await;
+            let _ = ack.send(());
+        });
+        backend.shutdown().await;
+        task.await.expect("task");
+
+        let persisted = store
+            .get_chat_thread_state(thread_id)
+            .expect("persisted state read")
+            .expect("persisted state");
+        let snapshot: PersistedThreadSnapshot =
+            serde_json::from_value(persisted).expect("snapshot decode");
+        match &snapshot.thread.turns[0].items[0] {
+            RociItem::AgentMessage {
+                text, incomplete, ..
+            } => {
+                assert_eq!(text, "Half an ans");
+                assert!(*incomplete);
+            }
+            other => panic!("unexpected item: {other:?}"),
+        }
+        assert_eq!(
+            snapshot.messages.last(),
+            Some(&ModelMessage::assistant("Half an ans"))
+        );
+
+        let Ok(OutboundMessage::Event { topic, params }) = outbound_rx.try_recv() else {
+            panic!("expected turn completion");
+        };
+        assert_eq!(topic, "chat.turn.completed");
+        assert_eq!(
+            params.expect("params")["reason"],
+            json!({ "kind": "shutdown" })
+        );
+
+        let state = backend.state.lock().await;
+        assert!(state.runs.is_empty());
+        assert!(state.active_threads.is_empty());
+    }
+
+    #[tokio::test]
+    async fn runs_pick_up_session_tools_registered_later() {
+        let app = axum::Router::new().route(
+            "/tool",
+            axum::routing::post(|axum::Json(body): axum::Json<Value>| async move {
+                axum::Json(json!({ "received": body }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let (outbound_tx, _outbound_rx) = mpsc::channel(16);
+        let backend = RociBackend::new(
+            outbound_tx,
+            Arc::new(SqliteStore::open_memory().expect("store")),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            Some("web".into()),
+        );
+        let (output_tx, _output_rx) = mpsc::unbounded_channel();
+        assert!(!backend
+            .run_tools(None, output_tx.clone())
+            .iter()
+            .any(|t| t.name() == "ping"));
+
+        backend
+            .session_tools()
+            .register(crate::agent::tools::SessionToolSpec {
+                name: "ping".into(),
+                description: "Ping the test endpoint".into(),
+                input_schema: json!({ "type": "object" }),
+                url: format!("http://{addr}/tool"),
+            });
+        let tools = backend.run_tools(None, output_tx);
+        assert!(tools.iter().any(|t| t.name() == "read"));
+        let ping = tools
+            .iter()
+            .find(|t| t.name() == "ping")
+            .expect("session tool offered to runs");
+        let result = ping
+            .execute(
+                &roci::tools::ToolArguments::new(json!({ "n": 1 })),
+                &roci::tools::tool::ToolExecutionContext::default(),
+            )
+            .await
+            .expect("tool result");
+        assert_eq!(result, json!({ "received": { "n": 1 } }));
+    }
+
+    #[tokio::test]
+    async fn tool_results_are_audited_with_outcome_and_approver() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(16);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let mut homie_config = crate::HomieConfig::default();
+        homie_config.tools.audit = true;
+        let backend = RociBackend::new(
+            outbound_tx,
+            store.clone(),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(homie_config),
+            Some("web".into()),
+        )
+        .with_identity(Some("alice@example.com".into()));
+
+        // Run a real tool call the way the run loop does, then feed its
+        // result through the audit path with the client's approval noted.
+        let ls = backend
+            .tools
+            .iter()
+            .find(|tool| tool.name() == "ls")
+            .expect("ls tool")
+            .clone();
+        let input = json!({ "path": ".", "token": "do-not-log" });
+        let info = state::ToolCallInfo {
+            name: "ls".into(),
+            input: input.clone(),
+            started_at: std::time::Instant::now(),
+        };
+        let result = ls
+            .execute(
+                &roci::tools::ToolArguments::new(input),
+                &roci::tools::tool::ToolExecutionContext::default(),
+            )
+            .await;
+        backend.state.lock().await.tool_approvals.insert(
+            "call-ls".into(),
+            audit::ToolApproval {
+                approver: "alice@example.com".into(),
+                accepted: true,
+            },
+        );
+        backend
+            .audit_tool_result(
+                "chat-a",
+                "thread-a",
+                "turn-1",
+                "call-ls",
+                &info,
+                result.is_err(),
+            )
+            .await;
+
+        // A declined call, and one that needed no approval at all.
+        backend.state.lock().await.tool_approvals.insert(
+            "call-rm".into(),
+            audit::ToolApproval {
+                approver: "alice@example.com".into(),
+                accepted: false,
+            },
+        );
+        let rm = state::ToolCallInfo {
+            name: "exec".into(),
+            input: json!({ "command": "rm -rf /tmp/x" }),
+            started_at: std::time::Instant::now(),
+        };
+        backend
+            .audit_tool_result("chat-a", "thread-a", "turn-1", "call-rm", &rm, true)
+            .await;
+        backend
+            .audit_tool_result("chat-a", "thread-a", "turn-1", "call-read", &info, true)
+            .await;
+
+        let records = store.list_tool_invocations("thread-a", 10).unwrap();
+        assert_eq!(records.len(), 3);
+        let by_call = |id: &str| {
+            records
+                .iter()
+                .find(|r| r.tool_call_id == id)
+                .expect("audited call")
+        };
+        let ls_record = by_call("call-ls");
+        assert_eq!(ls_record.tool, "ls");
+        assert_eq!(ls_record.outcome, "ok");
+        assert_eq!(ls_record.approver.as_deref(), Some("alice@example.com"));
+        assert_eq!(ls_record.args["token"], "[redacted]");
+        assert_eq!(ls_record.args["path"], ".");
+        assert_eq!(by_call("call-rm").outcome, "declined");
+        assert_eq!(by_call("call-read").outcome, "error");
+        assert_eq!(by_call("call-read").approver, None);
+        assert!(backend.state.lock().await.tool_approvals.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tool_audit_is_off_by_default() {
+        let (outbound_tx, _outbound_rx) = mpsc::channel(16);
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let backend = RociBackend::new(
+            outbound_tx,
+            store.clone(),
+            Arc::new(ExecPolicy::empty()),
+            Arc::new(crate::HomieConfig::default()),
+            None,
+        );
+        let info = state::ToolCallInfo {
+            name: "ls".into(),
+            input: json!({}),
+            started_at: std::time::Instant::now(),
+        };
+        backend
+            .audit_tool_result("chat-a", "thread-a", "turn-1", "call-ls", &info, false)
+            .await;
+        assert!(store
+            .list_tool_invocations("thread-a", 10)
+            .unwrap()
+            .is_empty());
+    }
+
     fn live_enabled() -> bool {
         matches!(std::env::var("HOMIE_LIVE_TESTS").as_deref(), Ok("1"))
     }
@@ -979,6 +3214,9 @@ mod tests {
                 config,
                 collaboration_mode: None,
                 system_prompt: Some(homie_config.chat.system_prompt.clone()),
+                read_only: false,
+                cwd: None,
+                replace_turn: None,
             })
             .await
             .expect("start run");
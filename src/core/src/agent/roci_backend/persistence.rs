@@ -9,7 +9,7 @@ use crate::storage::{ChatRawEventRecord, Store};
 
 use super::state::{
     last_assistant_item_id_from_turns, model_messages_from_turns, upsert_assistant_item,
-    upsert_tool_item, upsert_user_item, RociThread, RociThreadState, RociTurn,
+    upsert_tool_item, upsert_user_item, RociThread, RociThreadState, RociTurn, ThreadSummary,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +19,8 @@ pub(super) struct PersistedThreadSnapshot {
     pub(super) messages: Vec<ModelMessage>,
     #[serde(default)]
     pub(super) last_assistant_item_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) summary: Option<ThreadSummary>,
 }
 
 impl PersistedThreadSnapshot {
@@ -27,6 +29,7 @@ impl PersistedThreadSnapshot {
             thread: state.thread.clone(),
             messages: state.messages.clone(),
             last_assistant_item_id: state.last_assistant_item_id.clone(),
+            summary: state.summary.clone(),
         }
     }
 
@@ -35,10 +38,10 @@ impl PersistedThreadSnapshot {
         if thread.id != thread_id {
             thread.id = thread_id.to_string();
         }
-        let messages = if self.messages.is_empty() && !thread.turns.is_empty() {
-            model_messages_from_turns(&thread.turns)
+        let (messages, summary) = if self.messages.is_empty() && !thread.turns.is_empty() {
+            (model_messages_from_turns(&thread.turns), None)
         } else {
-            self.messages
+            (self.messages, self.summary)
         };
         let last_assistant_item_id = self
             .last_assistant_item_id
@@ -47,6 +50,7 @@ impl PersistedThreadSnapshot {
             thread,
             messages,
             last_assistant_item_id,
+            summary,
         }
     }
 }
@@ -74,6 +78,7 @@ pub(super) fn decode_persisted_thread_state(
         PersistedThreadSnapshotPayload::LegacyThread(thread) => PersistedThreadSnapshot {
             messages: model_messages_from_turns(&thread.turns),
             last_assistant_item_id: last_assistant_item_id_from_turns(&thread.turns),
+            summary: None,
             thread,
         }
         .into_thread_state(thread_id),
@@ -144,6 +149,7 @@ pub(super) fn backfill_thread_state_from_raw_events(
         thread,
         messages,
         last_assistant_item_id,
+        summary: None,
     };
     persist_thread_snapshot(
         store,
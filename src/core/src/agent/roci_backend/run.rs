@@ -1,32 +1,131 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use roci::agent_loop::{
-    ApprovalDecision, LoopRunner, RunEvent, RunEventPayload, RunHooks, RunLifecycle, RunRequest,
-    Runner,
+    ApprovalDecision, ApprovalKind, ApprovalPolicy, ApprovalRequest, LoopRunner, RunEvent,
+    RunEventPayload, RunHooks, RunLifecycle, RunRequest, Runner,
 };
 use roci::types::ModelMessage;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tracing::Instrument;
 use uuid::Uuid;
 
+use crate::agent::tools::{tool_side_effect, ToolSideEffect};
+use crate::agent::usage::TokenUsage;
+use crate::notifications::{notify_turn_finished, TurnNotification};
+use crate::storage::{Store, TurnUsageRecord};
+
+use super::attachments::store_tool_attachments_blocking;
+use super::audit::{
+    note_tool_approval, APPROVER_CLIENT, APPROVER_EXECPOLICY, APPROVER_SESSION, APPROVER_TIMEOUT,
+};
+use super::compaction::{compact_messages, estimate_tokens};
 use super::events::{
-    approval_cache_key, approval_command_argv, emit_approval_required, emit_diff_updated,
-    emit_error, emit_item_completed, emit_message_delta, emit_plan_updated, emit_reasoning_delta,
-    emit_tool_item_completed, emit_tool_item_started, emit_turn_completed, ToolEventContext,
-    ToolItemCompletedData, ToolItemStartedData,
+    approval_cache_key, approval_command_argv, approval_required_params, emit_approval_required,
+    emit_approval_timeout, emit_command_outputs, emit_diff_updated, emit_error,
+    emit_item_completed, emit_message_delta, emit_plan_updated, emit_reasoning_delta,
+    emit_token_usage, emit_tool_item_completed, emit_tool_item_started, emit_turn_completed,
+    CommandOutput, ToolEventContext, ToolItemCompletedData, ToolItemStartedData, TurnEndReason,
 };
 use super::persistence::{
     persist_roci_raw_event, persist_thread_snapshot, PersistedThreadSnapshot,
 };
+use super::slots::RunSlot;
 use super::state::{
-    model_tool_call_message, upsert_tool_item_completed, upsert_tool_item_started, PendingRun,
-    RociRunState, ToolCallInfo,
+    model_tool_call_message, upsert_tool_item_completed, upsert_tool_item_started, PendingApproval,
+    PendingRun, RociRunState, RociState, ToolCallInfo,
 };
 
+/// Side-effect class of the tool behind an approval request, falling back
+/// to the request kind when the payload does not name the tool.
+fn approval_side_effect(request: &ApprovalRequest) -> ToolSideEffect {
+    if let Some(tool) = request.payload.get("tool_name").and_then(|v| v.as_str()) {
+        return tool_side_effect(tool);
+    }
+    match request.kind {
+        ApprovalKind::CommandExecution => ToolSideEffect::Exec,
+        ApprovalKind::FileChange | ApprovalKind::Other => ToolSideEffect::Write,
+    }
+}
+
+/// The running call that output from `tool` run with `input` belongs to.
+/// Tools only know their own arguments, so identical calls in flight get
+/// their output attributed to the one that started first; finished calls
+/// are no longer candidates.
+pub(super) fn output_call_id<'a>(
+    tool_calls: &'a HashMap<String, ToolCallInfo>,
+    tool: &str,
+    input: &serde_json::Value,
+) -> Option<&'a str> {
+    tool_calls
+        .iter()
+        .filter(|(_, call)| call.name == tool && call.input == *input)
+        .min_by_key(|(_, call)| call.started_at)
+        .map(|(id, _)| id.as_str())
+}
+
+/// Wait for the client's answer to approval `request_id`, announced to
+/// `chat_id` with `params`. `None` when no answer came within `timeout`;
+/// the request is then dropped from `state.approvals` so a late answer
+/// finds nothing.
+pub(super) async fn await_approval(
+    state: &Arc<Mutex<RociState>>,
+    request_id: &str,
+    chat_id: &str,
+    thread_id: &str,
+    params: serde_json::Value,
+    timeout: Option<Duration>,
+) -> Option<ApprovalDecision> {
+    let (tx, rx) = oneshot::channel();
+    {
+        let mut guard = state.lock().await;
+        guard.approvals.insert(
+            request_id.to_string(),
+            PendingApproval {
+                tx,
+                chat_id: chat_id.to_string(),
+                thread_id: thread_id.to_string(),
+                params,
+                requested_at: Instant::now(),
+            },
+        );
+    }
+    let Some(timeout) = timeout else {
+        return Some(rx.await.unwrap_or(ApprovalDecision::Decline));
+    };
+    match tokio::time::timeout(timeout, rx).await {
+        Ok(decision) => Some(decision.unwrap_or(ApprovalDecision::Decline)),
+        Err(_) => {
+            state.lock().await.approvals.remove(request_id);
+            None
+        }
+    }
+}
+
 pub(super) async fn start_run_inner(
     backend: super::RociBackend,
     pending: PendingRun,
+    slot: Option<RunSlot>,
 ) -> Result<(), String> {
+    let slot = match slot {
+        Some(slot) => slot,
+        None => backend.run_slots.acquire().await,
+    };
+    // A freeze may have landed while the run waited for its slot.
+    if backend.run_freeze.is_frozen() {
+        drop(slot);
+        emit_turn_completed(
+            &backend.outbound_tx,
+            &backend.store,
+            &pending.chat_id,
+            &pending.thread_id,
+            &pending.turn_id,
+            "canceled",
+            Some(&TurnEndReason::Freeze),
+        );
+        return Err(backend.run_freeze.refusal_message());
+    }
     let run_id = Uuid::parse_str(&pending.turn_id).unwrap_or_else(|_| Uuid::new_v4());
     if super::debug_enabled() {
         tracing::debug!(
@@ -46,24 +145,47 @@ pub(super) async fn start_run_inner(
 
     let state = backend.state.clone();
     let exec_policy = backend.exec_policy.clone();
+    let approver_identity = backend
+        .identity
+        .clone()
+        .unwrap_or_else(|| APPROVER_CLIENT.to_string());
     let thread_id_for_cache = pending.thread_id.clone();
+    let read_only = pending.read_only;
+    let approval_timeout = match backend.homie_config.chat.approval_timeout_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let approval_outbound = backend.outbound_tx.clone();
+    let approval_store = backend.store.clone();
+    let approval_chat_id = pending.chat_id.clone();
+    let approval_turn_id = pending.turn_id.clone();
     let approval_handler: roci::agent_loop::ApprovalHandler = Arc::new(move |request| {
         let state = state.clone();
         let exec_policy = exec_policy.clone();
+        let approver_identity = approver_identity.clone();
         let thread_id = thread_id_for_cache.clone();
+        let approval_outbound = approval_outbound.clone();
+        let approval_store = approval_store.clone();
+        let approval_chat_id = approval_chat_id.clone();
+        let approval_turn_id = approval_turn_id.clone();
         Box::pin(async move {
-            if request.kind == roci::agent_loop::ApprovalKind::CommandExecution {
+            let gated = read_only && approval_side_effect(&request) != ToolSideEffect::ReadOnly;
+            if gated {
+                tracing::debug!(request_id = %request.id, "read-only run; asking client");
+            }
+            if !gated && request.kind == ApprovalKind::CommandExecution {
                 if let Some(argv) = approval_command_argv(&request.payload) {
                     if exec_policy.is_allowed(&argv) {
                         if super::debug_enabled() {
                             tracing::debug!(argv = ?argv, "roci execpolicy auto-approve");
                         }
+                        note_tool_approval(&state, &request, APPROVER_EXECPOLICY, true).await;
                         return ApprovalDecision::Accept;
                     }
                 }
             }
             let cache_key = approval_cache_key(&request);
-            if let Some(key) = cache_key.as_ref() {
+            if let Some(key) = cache_key.as_ref().filter(|_| !gated) {
                 let cached = {
                     let guard = state.lock().await;
                     guard
@@ -73,15 +195,45 @@ pub(super) async fn start_run_inner(
                         .unwrap_or(false)
                 };
                 if cached {
+                    note_tool_approval(&state, &request, APPROVER_SESSION, true).await;
                     return ApprovalDecision::Accept;
                 }
             }
-            let (tx, rx) = oneshot::channel();
-            {
-                let mut guard = state.lock().await;
-                guard.approvals.insert(request.id.clone(), tx);
-            }
-            let decision = rx.await.unwrap_or(ApprovalDecision::Decline);
+            let params = approval_required_params(&thread_id, &approval_turn_id, &request);
+            let Some(decision) = await_approval(
+                &state,
+                &request.id,
+                &approval_chat_id,
+                &thread_id,
+                params,
+                approval_timeout,
+            )
+            .await
+            else {
+                tracing::info!(
+                    request_id = %request.id,
+                    %thread_id,
+                    "approval timed out; declining"
+                );
+                note_tool_approval(&state, &request, APPROVER_TIMEOUT, false).await;
+                emit_approval_timeout(
+                    ToolEventContext::new(
+                        &approval_outbound,
+                        &approval_store,
+                        &approval_chat_id,
+                        &thread_id,
+                        &approval_turn_id,
+                    ),
+                    &request.id,
+                    approval_timeout.map_or(0, |timeout| timeout.as_secs()),
+                );
+                return ApprovalDecision::Decline;
+            };
+            let accepted = matches!(
+                decision,
+                ApprovalDecision::Accept | ApprovalDecision::AcceptForSession
+            );
+            note_tool_approval(&state, &request, &approver_identity, accepted).await;
             let mut guard = state.lock().await;
             guard.approvals.remove(&request.id);
             if matches!(decision, ApprovalDecision::AcceptForSession) {
@@ -97,15 +249,27 @@ pub(super) async fn start_run_inner(
         })
     });
 
+    let usage_input = estimate_tokens(&pending.messages) as u64;
+    let usage_model = pending.model.to_string();
     let mut run_request = RunRequest::new(pending.model, pending.messages);
     run_request.run_id = run_id;
     run_request.settings = pending.settings;
-    run_request.tools = backend.tools.clone();
-    run_request.approval_policy = pending.approval_policy;
+    let (output_tx, mut output_rx) = mpsc::unbounded_channel();
+    run_request.tools = backend.run_tools(pending.cwd.as_deref(), output_tx);
+    // Read-only runs must reach the approval handler, so auto-accepting
+    // policies are downgraded to asking.
+    run_request.approval_policy = if pending.read_only {
+        ApprovalPolicy::Ask
+    } else {
+        pending.approval_policy
+    };
     run_request.event_sink = Some(event_sink);
     run_request.approval_handler = Some(approval_handler);
+    let max_context_tokens = backend.compaction.max_tokens;
     run_request.hooks = RunHooks {
-        compaction: Some(Arc::new(compact_messages)),
+        compaction: Some(Arc::new(move |messages: &[ModelMessage]| {
+            compact_messages(messages, max_context_tokens)
+        })),
         tool_result_persist: Some(Arc::new(trim_tool_result)),
     };
 
@@ -115,6 +279,7 @@ pub(super) async fn start_run_inner(
         .await
         .map_err(|e| format!("run start failed: {e}"))?;
 
+    let (flush_tx, mut flush_rx) = oneshot::channel::<oneshot::Sender<()>>();
     {
         let mut state = backend.state.lock().await;
         state.runs.insert(
@@ -122,6 +287,9 @@ pub(super) async fn start_run_inner(
             RociRunState {
                 thread_id: pending.thread_id.clone(),
                 handle: Some(handle),
+                _slot: Some(slot),
+                cancel_reason: None,
+                flush_tx: Some(flush_tx),
             },
         );
     }
@@ -135,12 +303,115 @@ pub(super) async fn start_run_inner(
     let assistant_item_id_clone = pending.assistant_item_id.clone();
     let collaboration_mode = pending.collaboration_mode.clone();
     let raw_events_enabled = backend.raw_events_enabled;
+    let raw_event_runs = backend.homie_config.chat.raw_event_runs();
     let backend_for_task = backend.clone();
+    let shutdown = backend.shutdown.clone();
+    let span = pending.span.clone();
+
+    // Abort this turn if an operator freezes runs with cancellation; the
+    // watcher exits once the event task below finishes.
+    let (run_done_tx, run_done_rx) = oneshot::channel::<()>();
+    let mut freeze_rx = backend.run_freeze.cancel_signal();
+    let backend_for_freeze = backend.clone();
+    let freeze_thread_id = pending.thread_id.clone();
+    let freeze_turn_id = pending.turn_id.clone();
+    tokio::spawn(
+        async move {
+            tokio::select! {
+                changed = freeze_rx.changed() => {
+                    if changed.is_ok() {
+                        backend_for_freeze
+                            .cancel_frozen_run(&freeze_thread_id, &freeze_turn_id)
+                            .await;
+                    }
+                }
+                _ = run_done_rx => {}
+            }
+        }
+        .instrument(span.clone()),
+    );
 
     tokio::spawn(async move {
+        let _run_done = run_done_tx;
         let mut assistant_text = String::new();
         let mut tool_calls: HashMap<String, ToolCallInfo> = HashMap::new();
-        while let Some(event) = event_rx.recv().await {
+        let mut flush_closed = false;
+        let server_shutdown = shutdown.wait();
+        tokio::pin!(server_shutdown);
+        loop {
+            // Shutdown first, then run events, so a call's start is seen
+            // before its output.
+            let event = tokio::select! {
+                biased;
+                flush = &mut flush_rx, if !flush_closed => {
+                    match flush {
+                        Ok(ack) => {
+                            finish_interrupted_turn(
+                                &backend_for_task,
+                                &chat_id,
+                                &thread_id,
+                                &turn_id_clone,
+                                &assistant_item_id_clone,
+                                &assistant_text,
+                            )
+                            .await;
+                            let _ = ack.send(());
+                            break;
+                        }
+                        // The run settled normally and dropped its state.
+                        Err(_) => flush_closed = true,
+                    }
+                    continue;
+                }
+                _ = &mut server_shutdown => {
+                    finish_interrupted_turn(
+                        &backend_for_task,
+                        &chat_id,
+                        &thread_id,
+                        &turn_id_clone,
+                        &assistant_item_id_clone,
+                        &assistant_text,
+                    )
+                    .await;
+                    break;
+                }
+                event = event_rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                Some(output) = output_rx.recv() => {
+                    // Send everything already queued as one batch.
+                    let mut outputs: Vec<CommandOutput> = Vec::new();
+                    let mut next = Some(output);
+                    while let Some(output) = next {
+                        if let Some(item_id) = output_call_id(&tool_calls, output.tool, &output.input) {
+                            let stream = output.stream.as_str();
+                            match outputs.last_mut() {
+                                Some(last) if last.item_id == item_id && last.stream == stream => {
+                                    last.chunk.push_str(&output.chunk);
+                                }
+                                _ => outputs.push(CommandOutput {
+                                    item_id: item_id.to_string(),
+                                    stream,
+                                    chunk: output.chunk,
+                                }),
+                            }
+                        }
+                        next = output_rx.try_recv().ok();
+                    }
+                    emit_command_outputs(
+                        ToolEventContext::new(
+                            &outbound,
+                            &store,
+                            &chat_id,
+                            &thread_id,
+                            &turn_id_clone,
+                        ),
+                        &outputs,
+                    );
+                    continue;
+                }
+            };
             match event.payload {
                 RunEventPayload::AssistantDelta { text } => {
                     if !text.is_empty() {
@@ -257,6 +528,7 @@ pub(super) async fn start_run_inner(
                         ToolCallInfo {
                             name: call.name.clone(),
                             input: call.arguments.clone(),
+                            started_at: Instant::now(),
                         },
                     );
                     emit_tool_item_started(
@@ -288,7 +560,12 @@ pub(super) async fn start_run_inner(
                             .unwrap_or_else(|| ToolCallInfo {
                                 name: "tool".to_string(),
                                 input: serde_json::Value::Null,
+                                started_at: Instant::now(),
                             });
+                    // Large artifacts go to the store; events and history
+                    // carry references instead.
+                    let tool_result =
+                        store_tool_attachments_blocking(&store, &thread_id, &result.result).await;
                     {
                         let mut guard = state.lock().await;
                         if let Some(thread) = guard.threads.get_mut(&thread_id) {
@@ -303,19 +580,29 @@ pub(super) async fn start_run_inner(
                                     &result.tool_call_id,
                                     &info.name,
                                     info.input.clone(),
-                                    result.result.clone(),
+                                    tool_result.clone(),
                                     result.is_error,
                                 );
                             }
                             thread.messages.push(ModelMessage::tool_result(
                                 result.tool_call_id.clone(),
-                                result.result.clone(),
+                                tool_result.clone(),
                                 result.is_error,
                             ));
                             thread.thread.updated_at = super::now_unix();
                         }
                     }
                     backend_for_task.persist_thread_state(&thread_id).await;
+                    backend_for_task
+                        .audit_tool_result(
+                            &chat_id,
+                            &thread_id,
+                            &turn_id_clone,
+                            &result.tool_call_id,
+                            &info,
+                            result.is_error,
+                        )
+                        .await;
                     if raw_events_enabled {
                         let status = if result.is_error {
                             "failed"
@@ -336,7 +623,7 @@ pub(super) async fn start_run_inner(
                                     "tool": info.name.clone(),
                                     "status": status,
                                     "input": info.input.clone(),
-                                    "result": result.result.clone(),
+                                    "result": tool_result.clone(),
                                     "error": result.is_error,
                                 },
                             }),
@@ -361,7 +648,7 @@ pub(super) async fn start_run_inner(
                             &result.tool_call_id,
                             &info.name,
                             &info.input,
-                            &result.result,
+                            &tool_result,
                             result.is_error,
                         ),
                     );
@@ -487,7 +774,7 @@ pub(super) async fn start_run_inner(
                                         },
                                     }),
                                 );
-                                let _ = store.prune_chat_raw_events(10);
+                                let _ = store.prune_chat_raw_events(raw_event_runs);
                             }
 
                             emit_item_completed(
@@ -518,6 +805,17 @@ pub(super) async fn start_run_inner(
                                 &thread_id,
                                 &turn_id_clone,
                                 "completed",
+                                None,
+                            );
+                            notify_turn_finished(
+                                &store,
+                                TurnNotification::new(
+                                    &chat_id,
+                                    &thread_id,
+                                    &turn_id_clone,
+                                    "completed",
+                                    &assistant_text,
+                                ),
                             );
                             if let Some(next) =
                                 dequeue_next_run(&backend_for_task, &thread_id).await
@@ -584,7 +882,7 @@ pub(super) async fn start_run_inner(
                                         },
                                     }),
                                 );
-                                let _ = store.prune_chat_raw_events(10);
+                                let _ = store.prune_chat_raw_events(raw_event_runs);
                             }
                             emit_item_completed(
                                 &outbound,
@@ -602,6 +900,17 @@ pub(super) async fn start_run_inner(
                                 &thread_id,
                                 &turn_id_clone,
                                 "failed",
+                                Some(&TurnEndReason::ModelError(error.clone())),
+                            );
+                            notify_turn_finished(
+                                &store,
+                                TurnNotification::new(
+                                    &chat_id,
+                                    &thread_id,
+                                    &turn_id_clone,
+                                    "failed",
+                                    &error,
+                                ),
                             );
                             if let Some(next) =
                                 dequeue_next_run(&backend_for_task, &thread_id).await
@@ -616,27 +925,13 @@ pub(super) async fn start_run_inner(
                             break;
                         }
                         RunLifecycle::Canceled => {
-                            emit_turn_completed(
-                                &outbound,
-                                &store,
+                            finish_canceled_turn(
+                                &backend_for_task,
                                 &chat_id,
                                 &thread_id,
                                 &turn_id_clone,
-                                "canceled",
-                            );
-                            let snapshot = {
-                                let mut guard = state.lock().await;
-                                let snapshot = guard
-                                    .threads
-                                    .get(&thread_id)
-                                    .map(PersistedThreadSnapshot::from_thread_state);
-                                guard.runs.remove(&turn_id_clone);
-                                if guard.active_threads.get(&thread_id) == Some(&turn_id_clone) {
-                                    guard.active_threads.remove(&thread_id);
-                                }
-                                snapshot
-                            };
-                            persist_thread_snapshot(&store, &thread_id, snapshot);
+                            )
+                            .await;
                             if let Some(next) =
                                 dequeue_next_run(&backend_for_task, &thread_id).await
                             {
@@ -659,11 +954,188 @@ pub(super) async fn start_run_inner(
                 }
             }
         }
-    });
+        let usage = record_run_usage(
+            store.as_ref(),
+            TurnUsageRecord {
+                chat_id: chat_id.clone(),
+                thread_id: thread_id.clone(),
+                turn_id: turn_id_clone.clone(),
+                identity: backend_for_task.identity.clone(),
+                model: Some(usage_model),
+                input: usage_input,
+                output: 0,
+                total: 0,
+                created_at: super::now_unix(),
+            },
+            &assistant_text,
+        );
+        emit_token_usage(
+            &outbound,
+            &store,
+            &chat_id,
+            &thread_id,
+            &turn_id_clone,
+            usage,
+            true,
+        );
+    }.instrument(span));
 
     Ok(())
 }
 
+/// Record a settled run's token usage against its chat and the
+/// connection's identity, so `chat.token_budget` counts roci runs too. The
+/// run loop reports no provider counts: `usage.input` is the estimated
+/// starting context and the output is estimated from `reply`. Returns the
+/// counts in the shape clients get for every provider.
+pub(super) fn record_run_usage(
+    store: &dyn Store,
+    mut usage: TurnUsageRecord,
+    reply: &str,
+) -> TokenUsage {
+    usage.output = estimate_tokens(&[ModelMessage::assistant(reply.to_string())]) as u64;
+    usage.total = usage.input + usage.output;
+    if let Err(error) = store.add_turn_usage(&usage) {
+        tracing::warn!(thread_id = %usage.thread_id, "failed to record token usage: {error}");
+    }
+    TokenUsage::estimated(usage.input, usage.output)
+}
+
+/// Settle a cancelled turn: tool calls still in flight are marked
+/// `canceled` (their foreground processes die with the aborted run), the
+/// thread is persisted and clients see the items and the turn complete.
+pub(super) async fn finish_canceled_turn(
+    backend: &super::RociBackend,
+    chat_id: &str,
+    thread_id: &str,
+    turn_id: &str,
+) {
+    let (canceled, snapshot, reason) = {
+        let mut guard = backend.state.lock().await;
+        let canceled = guard
+            .threads
+            .get_mut(thread_id)
+            .map(|thread| thread.cancel_running_tools(turn_id))
+            .unwrap_or_default();
+        let snapshot = guard
+            .threads
+            .get(thread_id)
+            .map(PersistedThreadSnapshot::from_thread_state);
+        let reason = guard.runs.remove(turn_id).and_then(|run| run.cancel_reason);
+        if guard.active_threads.get(thread_id).map(String::as_str) == Some(turn_id) {
+            guard.active_threads.remove(thread_id);
+        }
+        (canceled, snapshot, reason)
+    };
+    persist_thread_snapshot(&backend.store, thread_id, snapshot);
+    for item in &canceled {
+        if super::debug_enabled() {
+            tracing::debug!(
+                %chat_id,
+                %thread_id,
+                %turn_id,
+                tool_call_id = %item.id,
+                tool = %item.tool,
+                "roci tool call canceled"
+            );
+        }
+        if backend.raw_events_enabled {
+            persist_roci_raw_event(
+                &backend.store,
+                turn_id,
+                thread_id,
+                "item/completed",
+                serde_json::json!({
+                    "threadId": thread_id,
+                    "turnId": turn_id,
+                    "item": {
+                        "id": item.id,
+                        "type": "mcpToolCall",
+                        "tool": item.tool,
+                        "status": "canceled",
+                        "input": item.input,
+                        "result": item.result,
+                        "error": true,
+                    },
+                }),
+            );
+        }
+        emit_tool_item_completed(
+            ToolEventContext::new(
+                &backend.outbound_tx,
+                &backend.store,
+                chat_id,
+                thread_id,
+                turn_id,
+            ),
+            ToolItemCompletedData::canceled(&item.id, &item.tool, &item.input, &item.result),
+        );
+    }
+    emit_turn_completed(
+        &backend.outbound_tx,
+        &backend.store,
+        chat_id,
+        thread_id,
+        turn_id,
+        "canceled",
+        reason.as_ref(),
+    );
+}
+
+/// Settle a turn cut off by a server shutdown: keep the reply streamed so
+/// far, flagged incomplete, and persist it so the thread rehydrates with it.
+pub(super) async fn finish_interrupted_turn(
+    backend: &super::RociBackend,
+    chat_id: &str,
+    thread_id: &str,
+    turn_id: &str,
+    assistant_item_id: &str,
+    assistant_text: &str,
+) {
+    let snapshot = {
+        let mut guard = backend.state.lock().await;
+        if let Some(thread) = guard.threads.get_mut(thread_id) {
+            thread.interrupt_assistant_text(assistant_item_id, assistant_text);
+            thread.cancel_running_tools(turn_id);
+            if !assistant_text.is_empty() {
+                thread
+                    .messages
+                    .push(ModelMessage::assistant(assistant_text.to_string()));
+            }
+            thread.thread.updated_at = super::now_unix();
+        }
+        if let Some(mut run) = guard.runs.remove(turn_id) {
+            if let Some(mut handle) = run.handle.take() {
+                handle.abort();
+            }
+        }
+        if guard.active_threads.get(thread_id).map(String::as_str) == Some(turn_id) {
+            guard.active_threads.remove(thread_id);
+        }
+        guard
+            .threads
+            .get(thread_id)
+            .map(PersistedThreadSnapshot::from_thread_state)
+    };
+    persist_thread_snapshot(&backend.store, thread_id, snapshot);
+    tracing::info!(
+        %chat_id,
+        %thread_id,
+        %turn_id,
+        text_len = assistant_text.len(),
+        "persisted partial roci reply for shutdown"
+    );
+    emit_turn_completed(
+        &backend.outbound_tx,
+        &backend.store,
+        chat_id,
+        thread_id,
+        turn_id,
+        "canceled",
+        Some(&TurnEndReason::Shutdown),
+    );
+}
+
 pub(super) async fn dequeue_next_run(
     backend: &super::RociBackend,
     thread_id: &str,
@@ -674,6 +1146,7 @@ pub(super) async fn dequeue_next_run(
         .get_mut(thread_id)
         .and_then(|queue| queue.pop_front());
     if let Some(run) = next.as_ref() {
+        backend.forget_pending_run(&run.turn_id);
         state
             .active_threads
             .insert(thread_id.to_string(), run.turn_id.clone());
@@ -689,24 +1162,98 @@ fn spawn_next_run(
 ) {
     tokio::task::spawn_blocking(move || {
         let handle = tokio::runtime::Handle::current();
-        if let Err(err) = handle.block_on(start_run_inner(backend, next)) {
-            if super::debug_enabled() {
-                tracing::debug!(
-                    %chat_id,
-                    %thread_id,
-                    error = %err,
-                    "roci queued run start failed"
-                );
+        let span = next.span.clone();
+        let turn_id = next.turn_id.clone();
+        handle.block_on(
+            async move {
+                if let Err(err) = start_run_inner(backend.clone(), next, None).await {
+                    if super::debug_enabled() {
+                        tracing::debug!(
+                            %chat_id,
+                            %thread_id,
+                            error = %err,
+                            "roci queued run start failed"
+                        );
+                    }
+                    move_to_next_run(backend, &chat_id, &thread_id, &turn_id).await;
+                }
             }
-        }
+            .instrument(span),
+        );
     });
 }
 
-fn compact_messages(messages: &[ModelMessage]) -> Option<Vec<ModelMessage>> {
-    if messages.len() <= 80 {
-        return None;
+/// Free `thread_id` from `turn_id`, which will not run, and start the
+/// thread's next queued run.
+pub(super) async fn move_to_next_run(
+    backend: super::RociBackend,
+    chat_id: &str,
+    thread_id: &str,
+    turn_id: &str,
+) {
+    {
+        let mut state = backend.state.lock().await;
+        if state.active_threads.get(thread_id).map(String::as_str) == Some(turn_id) {
+            state.active_threads.remove(thread_id);
+        }
+    }
+    if let Some(next) = dequeue_next_run(&backend, thread_id).await {
+        spawn_next_run(backend, next, chat_id.to_string(), thread_id.to_string());
     }
-    Some(messages[messages.len().saturating_sub(80)..].to_vec())
+}
+
+/// Start `pending` once a run slot frees up. A cancel or a cancelling
+/// freeze while it waits ends the turn instead; a failed start moves the
+/// thread on to its next queued run.
+pub(super) fn spawn_slot_waiter(
+    backend: super::RociBackend,
+    pending: PendingRun,
+    mut woken: oneshot::Receiver<()>,
+) {
+    let mut freeze_rx = backend.run_freeze.cancel_signal();
+    tokio::task::spawn_blocking(move || {
+        let handle = tokio::runtime::Handle::current();
+        handle.block_on(async move {
+            let chat_id = pending.chat_id.clone();
+            let thread_id = pending.thread_id.clone();
+            let turn_id = pending.turn_id.clone();
+            let slot = tokio::select! {
+                slot = backend.run_slots.acquire() => slot,
+                // Whoever removed the wait already ended the turn.
+                _ = &mut woken => return,
+                Ok(()) = freeze_rx.changed() => {
+                    backend.cancel_frozen_run(&thread_id, &turn_id).await;
+                    return;
+                }
+            };
+            if backend
+                .state
+                .lock()
+                .await
+                .slot_waiting
+                .remove(&turn_id)
+                .is_none()
+            {
+                return;
+            }
+            backend.forget_pending_run(&turn_id);
+            match start_run_inner(backend.clone(), pending, Some(slot)).await {
+                Ok(()) => return,
+                Err(err) => {
+                    if super::debug_enabled() {
+                        tracing::debug!(
+                            %chat_id,
+                            %thread_id,
+                            %turn_id,
+                            error = %err,
+                            "roci run start failed after waiting for a slot"
+                        );
+                    }
+                }
+            }
+            move_to_next_run(backend, &chat_id, &thread_id, &turn_id).await;
+        });
+    });
 }
 
 fn trim_tool_result(mut result: roci::types::AgentToolResult) -> roci::types::AgentToolResult {
@@ -741,6 +1288,10 @@ fn exec_process_id_from_result(tool_name: &str, result: &serde_json::Value) -> O
         || result
             .get("stderr_truncated")
             .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+        || result
+            .get("output_truncated")
+            .and_then(|v| v.as_bool())
             .unwrap_or(false);
     if !truncated {
         return None;
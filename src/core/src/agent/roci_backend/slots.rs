@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Held by a run from start until its lifecycle ends; dropping it frees the
+/// slot for the next waiting run.
+pub(super) type RunSlot = OwnedSemaphorePermit;
+
+/// Process-wide cap on how many agent runs execute at once, shared by every
+/// connection's backend. Runs past the cap wait for a slot; the per-thread
+/// queue still orders runs within a thread.
+#[derive(Clone)]
+pub struct RunSlots {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl RunSlots {
+    /// Allow `limit` concurrent runs; `0` is treated as `1`.
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Slots not currently held by a run.
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    pub(super) fn try_acquire(&self) -> Option<RunSlot> {
+        self.semaphore.clone().try_acquire_owned().ok()
+    }
+
+    pub(super) async fn acquire(&self) -> RunSlot {
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("run slot semaphore is never closed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn slots_cap_concurrent_holders() {
+        let slots = RunSlots::new(2);
+        let first = slots.try_acquire().expect("first slot");
+        let _second = slots.try_acquire().expect("second slot");
+        assert!(slots.try_acquire().is_none());
+        assert_eq!(slots.available(), 0);
+
+        let waiter = tokio::spawn({
+            let slots = slots.clone();
+            async move { slots.acquire().await }
+        });
+        drop(first);
+        let _third = waiter.await.expect("waiter");
+        assert_eq!(slots.available(), 0);
+        assert_eq!(RunSlots::new(0).limit(), 1);
+    }
+}
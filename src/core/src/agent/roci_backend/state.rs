@@ -1,4 +1,6 @@
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::time::Instant;
 
 use roci::agent_loop::{ApprovalDecision, ApprovalPolicy};
 use roci::config::RociConfig;
@@ -7,6 +9,17 @@ use roci::types::{AgentToolCall, ContentPart, GenerationSettings, ModelMessage,
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::audit::ToolApproval;
+use super::compaction::summary_message;
+use super::events::TurnEndReason;
+use super::slots::RunSlot;
+use crate::agent::tools::{patch_file_stats, PatchFileStats};
+
+/// Note recorded on tool calls that were still running when their turn was
+/// cancelled.
+pub(super) const TOOL_CANCELED_NOTE: &str = "interrupted: run canceled";
 
 #[derive(Debug, Clone)]
 pub(super) struct PendingRun {
@@ -20,6 +33,11 @@ pub(super) struct PendingRun {
     pub(super) approval_policy: ApprovalPolicy,
     pub(super) config: RociConfig,
     pub(super) collaboration_mode: Option<String>,
+    pub(super) read_only: bool,
+    pub(super) cwd: Option<PathBuf>,
+    /// `run` span (`turn_id`, `thread_id`) opened under the request that
+    /// started the run; the run's tasks are instrumented with it.
+    pub(super) span: tracing::Span,
 }
 
 #[derive(Default)]
@@ -28,19 +46,52 @@ pub(super) struct RociState {
     pub(super) runs: HashMap<String, RociRunState>,
     pub(super) run_queue: HashMap<String, VecDeque<PendingRun>>,
     pub(super) active_threads: HashMap<String, String>,
-    pub(super) approvals: HashMap<String, oneshot::Sender<ApprovalDecision>>,
+    /// Turns whose run is waiting for a free run slot, by turn id.
+    pub(super) slot_waiting: HashMap<String, SlotWait>,
+    /// Approval requests waiting for the client, by request id.
+    pub(super) approvals: HashMap<String, PendingApproval>,
     pub(super) approval_cache: HashMap<String, HashSet<String>>,
     pub(super) tool_output_cache: HashMap<String, VecDeque<ToolOutputRetention>>,
+    /// Approval outcome per tool call id, consumed when the call's result
+    /// is audited.
+    pub(super) tool_approvals: HashMap<String, ToolApproval>,
+}
+
+/// A tool approval waiting for the client's decision.
+pub(super) struct PendingApproval {
+    pub(super) tx: oneshot::Sender<ApprovalDecision>,
+    pub(super) chat_id: String,
+    pub(super) thread_id: String,
+    /// The `chat.approval.required` params it was announced with, sent
+    /// again when a client asks for the thread's pending approvals.
+    pub(super) params: Value,
+    pub(super) requested_at: Instant,
+}
+
+/// A turn waiting for a run slot. Removing it from `slot_waiting` drops
+/// `_wake`, which stops the waiter; whoever removed it ends the turn.
+pub(super) struct SlotWait {
+    pub(super) chat_id: String,
+    pub(super) thread_id: String,
+    pub(super) _wake: oneshot::Sender<()>,
 }
 
 pub(super) struct RociRunState {
     pub(super) thread_id: String,
     pub(super) handle: Option<roci::agent_loop::RunHandle>,
+    /// Concurrent run slot, released when the run is removed.
+    pub(super) _slot: Option<RunSlot>,
+    /// Set by whoever aborts the run, reported when the turn settles.
+    pub(super) cancel_reason: Option<TurnEndReason>,
+    /// Asks the run's event task to persist its partial reply before the
+    /// backend shuts down; the task acks on the enclosed sender.
+    pub(super) flush_tx: Option<oneshot::Sender<oneshot::Sender<()>>>,
 }
 
 pub(super) struct ToolCallInfo {
     pub(super) name: String,
     pub(super) input: serde_json::Value,
+    pub(super) started_at: Instant,
 }
 
 pub(super) struct ToolOutputRetention {
@@ -53,6 +104,17 @@ pub(super) struct RociThreadState {
     pub(super) thread: RociThread,
     pub(super) messages: Vec<ModelMessage>,
     pub(super) last_assistant_item_id: Option<String>,
+    /// Summary standing in for the thread's oldest turns in `messages`.
+    pub(super) summary: Option<ThreadSummary>,
+}
+
+/// Model-written summary that replaced a thread's leading turns in its
+/// model history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct ThreadSummary {
+    pub(super) text: String,
+    /// Leading turns the summary covers.
+    pub(super) turns: usize,
 }
 
 impl RociThreadState {
@@ -67,21 +129,232 @@ impl RociThreadState {
             },
             messages: Vec::new(),
             last_assistant_item_id: None,
+            summary: None,
         }
     }
 
+    /// Model history for `turns`, a prefix of this thread's turns: the
+    /// system prompt, the summary when every turn it covers is kept, then
+    /// messages rebuilt from the turns after it. Returns the summary kept.
+    pub(super) fn history_for(
+        &self,
+        turns: &[RociTurn],
+    ) -> (Vec<ModelMessage>, Option<ThreadSummary>) {
+        let mut messages: Vec<ModelMessage> = self
+            .messages
+            .iter()
+            .filter(|msg| msg.role == Role::System)
+            .cloned()
+            .collect();
+        let summary = self
+            .summary
+            .clone()
+            .filter(|summary| summary.turns <= turns.len());
+        let start = match &summary {
+            Some(summary) => {
+                messages.push(summary_message(&summary.text));
+                summary.turns
+            }
+            None => 0,
+        };
+        messages.extend(model_messages_from_turns(&turns[start..]));
+        (messages, summary)
+    }
+
+    /// Replace the history of the first `turns` turns with `text`. The
+    /// latest turn is the one about to run: its opening message is kept as
+    /// is rather than rebuilt from the turn.
+    pub(super) fn apply_summary(&mut self, text: String, turns: usize) {
+        let current = self.messages.last().cloned();
+        self.summary = Some(ThreadSummary { text, turns });
+        let last = self.thread.turns.len().saturating_sub(1);
+        let (mut messages, _) = self.history_for(&self.thread.turns[..last]);
+        messages.extend(current.filter(|msg| msg.role == Role::User));
+        self.messages = messages;
+    }
+
+    /// Files the thread's successful `apply_patch` calls changed, as
+    /// `{"files":[{path, additions, deletions}]}`, in the order first
+    /// patched, with counts summed across patches.
+    pub(super) fn diff_summary(&self) -> Value {
+        let mut files: Vec<PatchFileStats> = Vec::new();
+        for item in self.thread.turns.iter().flat_map(|turn| &turn.items) {
+            let RociItem::ToolCall {
+                tool,
+                result: Some(result),
+                error: false,
+                ..
+            } = item
+            else {
+                continue;
+            };
+            let Some(patch) = result.get("diff").and_then(Value::as_str) else {
+                continue;
+            };
+            if tool != "apply_patch" {
+                continue;
+            }
+            for stats in patch_file_stats(patch) {
+                match files.iter_mut().find(|file| file.path == stats.path) {
+                    Some(file) => {
+                        file.additions += stats.additions;
+                        file.deletions += stats.deletions;
+                    }
+                    None => files.push(stats),
+                }
+            }
+        }
+        let files: Vec<Value> = files
+            .into_iter()
+            .map(|file| {
+                serde_json::json!({
+                    "path": file.path,
+                    "additions": file.additions,
+                    "deletions": file.deletions,
+                })
+            })
+            .collect();
+        serde_json::json!({ "files": files })
+    }
+
     pub(super) fn update_assistant_text(&mut self, item_id: &str, text: &str) {
         for turn in &mut self.thread.turns {
             for item in &mut turn.items {
-                if let RociItem::AgentMessage { id, text: body } = item {
+                if let RociItem::AgentMessage { id, text: body, .. } = item {
+                    if id == item_id {
+                        *body = text.to_string();
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keep `text` as the reply of a run that was cut off before it
+    /// finished, flagged incomplete.
+    pub(super) fn interrupt_assistant_text(&mut self, item_id: &str, text: &str) {
+        for turn in &mut self.thread.turns {
+            for item in &mut turn.items {
+                if let RociItem::AgentMessage {
+                    id,
+                    text: body,
+                    incomplete,
+                } = item
+                {
                     if id == item_id {
                         *body = text.to_string();
+                        *incomplete = true;
                         return;
                     }
                 }
             }
         }
     }
+
+    /// A copy of this thread cut after `up_to_turn_id`, under `thread_id`.
+    /// Turn and item ids are replaced with fresh UUIDs and the model history
+    /// is rebuilt from the kept turns, keeping the system prompt and any
+    /// summary of them. `None` when the turn is not in this thread.
+    pub(super) fn fork(&self, thread_id: String, up_to_turn_id: &str) -> Option<Self> {
+        let end = self
+            .thread
+            .turns
+            .iter()
+            .position(|turn| turn.id == up_to_turn_id)?;
+        let turns = fresh_turns(&self.thread.turns[..=end]);
+        let (messages, summary) = self.history_for(&turns);
+        let now = super::now_unix();
+        Some(Self {
+            last_assistant_item_id: last_assistant_item_id_from_turns(&turns),
+            summary,
+            thread: RociThread {
+                id: thread_id,
+                created_at: now,
+                updated_at: now,
+                turns,
+            },
+            messages,
+        })
+    }
+
+    /// A new thread `thread_id` holding copies of `turns` with fresh turn
+    /// and item ids, its model history rebuilt from them.
+    pub(super) fn imported(thread_id: String, turns: &[RociTurn]) -> Self {
+        let turns = fresh_turns(turns);
+        let now = super::now_unix();
+        Self {
+            messages: model_messages_from_turns(&turns),
+            last_assistant_item_id: last_assistant_item_id_from_turns(&turns),
+            summary: None,
+            thread: RociThread {
+                id: thread_id,
+                created_at: now,
+                updated_at: now,
+                turns,
+            },
+        }
+    }
+
+    /// Cut this thread back to just before `turn_id`, rebuilding the model
+    /// history from the turns that remain and keeping the system prompt and
+    /// any summary of them.
+    /// Returns the removed turns, oldest first; `None` when the turn is not
+    /// in this thread.
+    pub(super) fn truncate_before(&mut self, turn_id: &str) -> Option<Vec<RociTurn>> {
+        let start = self
+            .thread
+            .turns
+            .iter()
+            .position(|turn| turn.id == turn_id)?;
+        let removed = self.thread.turns.split_off(start);
+        let (messages, summary) = self.history_for(&self.thread.turns);
+        self.messages = messages;
+        self.summary = summary;
+        self.last_assistant_item_id = last_assistant_item_id_from_turns(&self.thread.turns);
+        self.thread.updated_at = super::now_unix();
+        Some(removed)
+    }
+
+    /// Close out tool calls in `turn_id` that never produced a result. Each
+    /// one is marked `canceled` and gets an error tool result so the model
+    /// history stays well-formed for the next turn.
+    pub(super) fn cancel_running_tools(&mut self, turn_id: &str) -> Vec<CanceledToolItem> {
+        let Some(turn) = self.thread.turns.iter_mut().find(|turn| turn.id == turn_id) else {
+            return Vec::new();
+        };
+        let canceled = cancel_running_tool_items(turn);
+        for item in &canceled {
+            self.messages.push(ModelMessage::tool_result(
+                item.id.clone(),
+                item.result.clone(),
+                true,
+            ));
+        }
+        if !canceled.is_empty() {
+            self.thread.updated_at = super::now_unix();
+        }
+        canceled
+    }
+}
+
+/// Copies of `turns` with every turn and item id replaced.
+fn fresh_turns(turns: &[RociTurn]) -> Vec<RociTurn> {
+    turns
+        .iter()
+        .map(|turn| RociTurn {
+            id: Uuid::new_v4().to_string(),
+            items: turn.items.iter().map(RociItem::with_fresh_id).collect(),
+        })
+        .collect()
+}
+
+/// A tool call interrupted by cancellation, as recorded in the thread.
+#[derive(Debug, Clone)]
+pub(super) struct CanceledToolItem {
+    pub(super) id: String,
+    pub(super) tool: String,
+    pub(super) input: Value,
+    pub(super) result: Value,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -102,6 +375,21 @@ impl RociTurn {
     pub(super) fn new(id: String, items: Vec<RociItem>) -> Self {
         Self { id, items }
     }
+
+    /// Text of the message that opened this turn.
+    pub(super) fn user_message(&self) -> Option<String> {
+        self.items.iter().find_map(|item| match item {
+            RociItem::UserMessage { content, .. } => Some(
+                content
+                    .iter()
+                    .map(|part| match part {
+                        RociContent::Text { text } => text.as_str(),
+                    })
+                    .collect::<String>(),
+            ),
+            _ => None,
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -113,7 +401,14 @@ pub(super) enum RociItem {
         content: Vec<RociContent>,
     },
     #[serde(rename = "agentMessage")]
-    AgentMessage { id: String, text: String },
+    AgentMessage {
+        id: String,
+        text: String,
+        /// The run was interrupted by a server shutdown before the reply
+        /// finished.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        incomplete: bool,
+    },
     #[serde(rename = "mcpToolCall")]
     ToolCall {
         id: String,
@@ -137,7 +432,27 @@ impl RociItem {
     }
 
     pub(super) fn assistant(id: String, text: String) -> Self {
-        Self::AgentMessage { id, text }
+        Self::AgentMessage {
+            id,
+            text,
+            incomplete: false,
+        }
+    }
+
+    pub(super) fn id(&self) -> &str {
+        let (Self::UserMessage { id, .. }
+        | Self::AgentMessage { id, .. }
+        | Self::ToolCall { id, .. }) = self;
+        id
+    }
+
+    fn with_fresh_id(&self) -> Self {
+        let mut item = self.clone();
+        let (Self::UserMessage { id, .. }
+        | Self::AgentMessage { id, .. }
+        | Self::ToolCall { id, .. }) = &mut item;
+        *id = Uuid::new_v4().to_string();
+        item
     }
 
     pub(super) fn tool_call(
@@ -212,6 +527,45 @@ pub(super) fn model_messages_from_turns(turns: &[RociTurn]) -> Vec<ModelMessage>
     messages
 }
 
+/// Starting context for a run: every system message plus the `max_recent`
+/// most recent others. The window never opens on a tool result whose call
+/// was cut off.
+pub(super) fn recent_context(messages: &[ModelMessage], max_recent: usize) -> Vec<ModelMessage> {
+    let non_system: Vec<usize> = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, msg)| msg.role != Role::System)
+        .map(|(idx, _)| idx)
+        .collect();
+    if max_recent == 0 || non_system.len() <= max_recent {
+        return messages.to_vec();
+    }
+    let mut start = non_system[non_system.len() - max_recent];
+    while messages
+        .get(start)
+        .is_some_and(|msg| msg.role == Role::Tool)
+    {
+        start += 1;
+    }
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(idx, msg)| msg.role == Role::System || *idx >= start)
+        .map(|(_, msg)| msg.clone())
+        .collect()
+}
+
+/// Make `prompt` the thread's system message. It is inserted when the thread
+/// has none and replaced in place when a per-chat override changed it.
+pub(super) fn set_system_prompt(messages: &mut Vec<ModelMessage>, prompt: &str) {
+    let message = ModelMessage::system(prompt.to_string());
+    match messages.iter_mut().find(|msg| msg.role == Role::System) {
+        Some(existing) if existing.content == message.content => {}
+        Some(existing) => *existing = message,
+        None => messages.insert(0, message),
+    }
+}
+
 pub(super) fn model_tool_call_message(call: &AgentToolCall) -> ModelMessage {
     ModelMessage {
         role: Role::Assistant,
@@ -249,7 +603,7 @@ pub(super) fn upsert_assistant_item(
     append: bool,
 ) {
     if let Some(existing) = turn.items.iter_mut().find_map(|item| match item {
-        RociItem::AgentMessage { id, text } if id == item_id => Some(text),
+        RociItem::AgentMessage { id, text, .. } if id == item_id => Some(text),
         _ => None,
     }) {
         if append {
@@ -339,3 +693,34 @@ pub(super) fn upsert_tool_item(
         is_error,
     ));
 }
+
+pub(super) fn cancel_running_tool_items(turn: &mut RociTurn) -> Vec<CanceledToolItem> {
+    let mut canceled = Vec::new();
+    for item in &mut turn.items {
+        let RociItem::ToolCall {
+            id,
+            tool,
+            status,
+            input,
+            result,
+            error,
+        } = item
+        else {
+            continue;
+        };
+        if status != "running" {
+            continue;
+        }
+        let note = serde_json::json!({ "status": "canceled", "note": TOOL_CANCELED_NOTE });
+        *status = "canceled".to_string();
+        *error = true;
+        let result = result.get_or_insert(note).clone();
+        canceled.push(CanceledToolItem {
+            id: id.clone(),
+            tool: tool.clone(),
+            input: input.clone(),
+            result,
+        });
+    }
+    canceled
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use roci::auth::{FileTokenStore, TokenStore, TokenStoreConfig};
+use roci::tools::tool::ToolExecutionContext;
+use roci::tools::ToolArguments;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::agent::roci_backend::RociBackend;
+use crate::agent::tools::{build_tools, canonical_tool_channels, ToolContext};
+use crate::storage::{SqliteStore, Store};
+use crate::{ExecPolicy, HomieConfig};
+
+/// Providers whose credentials live in the token store.
+const CREDENTIAL_PROVIDERS: [&str; 3] = ["openai-codex", "github-copilot", "claude-code"];
+
+/// Scripted run against a scratch in-memory store: create a thread, read it
+/// back, and execute one `ls` tool call. No model is contacted.
+pub(crate) async fn check_run(homie_config: Arc<HomieConfig>) -> Result<Value, String> {
+    let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory()?);
+    let (outbound_tx, _outbound_rx) = mpsc::channel(16);
+    let backend = RociBackend::new(
+        outbound_tx,
+        store,
+        Arc::new(ExecPolicy::empty()),
+        homie_config.clone(),
+        None,
+    );
+    let thread_id = "homie-selftest";
+    backend.ensure_thread(thread_id).await;
+    let thread = backend.thread_read(thread_id).await;
+    backend.shutdown().await;
+    if thread.is_none() {
+        return Err("scripted thread missing after create".into());
+    }
+
+    let ctx = ToolContext::new_with_channel(homie_config.clone(), Some("web"));
+    let tools = build_tools(ctx, &homie_config)?;
+    let Some(ls) = tools.iter().find(|tool| tool.name() == "ls") else {
+        return Ok(json!({ "thread": true, "tool_call": "skipped: ls disabled" }));
+    };
+    ls.execute(
+        &ToolArguments::new(json!({ "path": "." })),
+        &ToolExecutionContext::default(),
+    )
+    .await
+    .map_err(|e| format!("scripted ls call failed: {e}"))?;
+    Ok(json!({ "thread": true, "tool_call": "ls" }))
+}
+
+/// Build the tool set for every canonical channel.
+pub(crate) fn check_tools(homie_config: Arc<HomieConfig>) -> Result<Value, String> {
+    let mut channels = serde_json::Map::new();
+    for channel in canonical_tool_channels(&homie_config.tools) {
+        let ctx = ToolContext::new_with_channel(homie_config.clone(), Some(&channel));
+        let tools = build_tools(ctx, &homie_config).map_err(|e| format!("{channel}: {e}"))?;
+        channels.insert(channel, json!(tools.len()));
+    }
+    Ok(json!({ "channels": channels }))
+}
+
+/// Every enabled provider has a stored credential for its configured profile.
+pub(crate) fn check_providers(homie_config: &HomieConfig) -> Result<Value, String> {
+    let providers = &homie_config.providers;
+    let store = FileTokenStore::new(TokenStoreConfig::new(homie_config.credentials_dir()?));
+    let mut report = Vec::new();
+    let mut missing = Vec::new();
+    for provider_id in CREDENTIAL_PROVIDERS {
+        let enabled = match provider_id {
+            "openai-codex" => providers.openai_codex.enabled,
+            "github-copilot" => providers.github_copilot.enabled,
+            _ => providers.claude_code.enabled,
+        };
+        if !enabled {
+            continue;
+        }
+        let profile = providers
+            .profiles
+            .get(provider_id)
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .unwrap_or("default");
+        let token = store
+            .load(provider_id, profile)
+            .map_err(|e| format!("load {provider_id} token: {e}"))?;
+        if token.is_none() {
+            missing.push(format!("{provider_id}/{profile}"));
+        }
+        report.push(json!({
+            "id": provider_id,
+            "profile": profile,
+            "logged_in": token.is_some(),
+        }));
+    }
+    if !missing.is_empty() {
+        return Err(format!("missing credentials: {}", missing.join(", ")));
+    }
+    Ok(json!({ "providers": report }))
+}
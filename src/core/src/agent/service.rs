@@ -6,8 +6,10 @@ mod core;
 mod dispatch;
 mod events;
 mod files;
+mod idle;
 mod models;
 mod params;
+mod uploads;
 
 #[cfg(test)]
 mod tests;
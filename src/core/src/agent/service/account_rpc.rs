@@ -1,20 +1,24 @@
 use homie_protocol::{error_codes, Response};
 use roci::auth::{
     providers::claude_code::ClaudeCodeAuth, providers::github_copilot::GitHubCopilotAuth,
-    providers::openai_codex::OpenAiCodexAuth, FileTokenStore, TokenStore, TokenStoreConfig,
+    providers::openai_codex::OpenAiCodexAuth, DeviceCodePoll, DeviceCodeSession, FileTokenStore,
+    TokenStore, TokenStoreConfig,
 };
 use roci::config::RociConfig;
 use roci::models::LanguageModel;
 use serde_json::{json, Value};
+use std::path::PathBuf;
 use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::agent::service::core::CodexChatCore;
 use crate::homie_config::ProvidersConfig;
+use crate::storage::LoginSessionRecord;
 
 use super::params::{
-    device_code_poll_json, device_code_session_json, parse_account_provider_params,
-    parse_device_code_session,
+    device_code_poll_json, device_code_session_json, login_session_from_record,
+    parse_account_provider_params, parse_device_code_session, pinned_profile, preferred_profile,
+    refresh_if_expiring, require_profile, select_profile, DEFAULT_PROFILE,
 };
 
 impl CodexChatCore {
@@ -67,10 +71,13 @@ impl CodexChatCore {
             "openai-codex" => {
                 let auth = self.openai_codex_auth(store, &profile);
                 match auth.start_device_code().await {
-                    Ok(session) => Response::success(
-                        req_id,
-                        json!({ "session": device_code_session_json(&session) }),
-                    ),
+                    Ok(session) => {
+                        self.persist_login_session(&provider_id, &profile, &session);
+                        Response::success(
+                            req_id,
+                            json!({ "session": device_code_session_json(&session) }),
+                        )
+                    }
                     Err(e) => Response::error(
                         req_id,
                         error_codes::INTERNAL_ERROR,
@@ -81,10 +88,13 @@ impl CodexChatCore {
             "github-copilot" => {
                 let auth = self.github_copilot_auth(store, &profile);
                 match auth.start_device_code().await {
-                    Ok(session) => Response::success(
-                        req_id,
-                        json!({ "session": device_code_session_json(&session) }),
-                    ),
+                    Ok(session) => {
+                        self.persist_login_session(&provider_id, &profile, &session);
+                        Response::success(
+                            req_id,
+                            json!({ "session": device_code_session_json(&session) }),
+                        )
+                    }
                     Err(e) => Response::error(
                         req_id,
                         error_codes::INTERNAL_ERROR,
@@ -115,7 +125,14 @@ impl CodexChatCore {
         if !self.provider_enabled(&provider_id) {
             return Response::error(req_id, error_codes::INVALID_PARAMS, "provider disabled");
         }
-        let session = match parse_device_code_session(&param_map, &provider_id) {
+        let persisted = || {
+            self.store
+                .get_login_session(&provider_id, &profile)
+                .ok()
+                .flatten()
+                .map(|rec| login_session_from_record(&rec))
+        };
+        let session = match parse_device_code_session(&param_map, &provider_id).or_else(persisted) {
             Some(session) => session,
             None => return Response::error(req_id, error_codes::INVALID_PARAMS, "missing session"),
         };
@@ -153,7 +170,24 @@ impl CodexChatCore {
         };
 
         match poll {
-            Ok(result) => Response::success(req_id, device_code_poll_json(result)),
+            Ok(result) => {
+                if matches!(result, DeviceCodePoll::Authorized { .. }) && profile == DEFAULT_PROFILE
+                {
+                    self.set_cli_import_suppressed(&provider_id, false);
+                }
+                if !matches!(
+                    result,
+                    DeviceCodePoll::Pending { .. } | DeviceCodePoll::SlowDown { .. }
+                ) {
+                    if let Err(e) = self
+                        .store
+                        .delete_login_sessions(Some(&provider_id), Some(&profile))
+                    {
+                        tracing::warn!(error = %e, "failed to clear finished login session");
+                    }
+                }
+                Response::success(req_id, device_code_poll_json(result))
+            }
             Err(e) => Response::error(
                 req_id,
                 error_codes::INTERNAL_ERROR,
@@ -162,6 +196,49 @@ impl CodexChatCore {
         }
     }
 
+    /// Delete the stored token for a provider profile. Logging out of the
+    /// default profile also stops CLI credentials from being imported back
+    /// until the next Homie login.
+    pub(super) fn chat_account_logout(&self, req_id: Uuid, params: Option<Value>) -> Response {
+        let (provider_id, profile, _param_map) = match parse_account_provider_params(&params) {
+            Some(value) => value,
+            None => {
+                return Response::error(req_id, error_codes::INVALID_PARAMS, "missing provider")
+            }
+        };
+        if !self.provider_enabled(&provider_id) {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "provider disabled");
+        }
+        let store = match self.roci_token_store() {
+            Ok(store) => store,
+            Err(e) => {
+                return Response::error(
+                    req_id,
+                    error_codes::INTERNAL_ERROR,
+                    format!("account logout failed: {e}"),
+                )
+            }
+        };
+        if let Err(e) = store.clear(&provider_id, &profile) {
+            return Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("clear {provider_id} token: {e}"),
+            );
+        }
+        if let Err(e) = self
+            .store
+            .delete_login_sessions(Some(&provider_id), Some(&profile))
+        {
+            tracing::warn!(error = %e, "failed to clear login sessions on logout");
+        }
+        if profile == DEFAULT_PROFILE {
+            self.set_cli_import_suppressed(&provider_id, true);
+        }
+        tracing::info!(provider = %provider_id, %profile, "provider logged out");
+        Response::success(req_id, json!({ "ok": true }))
+    }
+
     pub(super) async fn chat_account_read(&mut self, req_id: Uuid) -> Response {
         if self.use_roci() {
             let store = match self.roci_token_store() {
@@ -224,6 +301,24 @@ impl CodexChatCore {
         }
     }
 
+    /// Remember a pending device-code login so polling survives a restart
+    /// and `admin.login.sessions.list` can show it.
+    fn persist_login_session(&self, provider_id: &str, profile: &str, session: &DeviceCodeSession) {
+        let record = LoginSessionRecord {
+            provider: provider_id.to_string(),
+            profile: profile.to_string(),
+            device_code: session.device_code.clone(),
+            user_code: session.user_code.clone(),
+            verification_url: session.verification_url.clone(),
+            interval_secs: session.interval_secs,
+            created_at: chrono::Utc::now().timestamp().max(0) as u64,
+            expires_at: session.expires_at.timestamp().max(0) as u64,
+        };
+        if let Err(e) = self.store.upsert_login_session(&record) {
+            tracing::warn!(error = %e, "failed to persist login session");
+        }
+    }
+
     pub(super) fn roci_token_store(&self) -> Result<FileTokenStore, String> {
         let base = self.homie_config.credentials_dir()?;
         Ok(FileTokenStore::new(TokenStoreConfig::new(base)))
@@ -278,9 +373,26 @@ impl CodexChatCore {
         ClaudeCodeAuth::new(Arc::new(store)).with_profile(profile)
     }
 
+    /// Profile whose credentials a chat should use for `provider_id`. Fails
+    /// when the chat pins a profile that has no stored credentials.
+    fn credential_profile(
+        &self,
+        store: &FileTokenStore,
+        provider_id: &str,
+        chat_settings: Option<&Value>,
+    ) -> Result<String, String> {
+        let has_token = |profile: &str| matches!(store.load(provider_id, profile), Ok(Some(_)));
+        let preferred = preferred_profile(provider_id, chat_settings, &self.homie_config.providers);
+        if pinned_profile(chat_settings) == Some(preferred.as_str()) {
+            return require_profile(provider_id, preferred, has_token);
+        }
+        Ok(select_profile(preferred, has_token))
+    }
+
     pub(super) async fn roci_config_for_model(
         &self,
         model: &LanguageModel,
+        chat_settings: Option<&Value>,
     ) -> Result<RociConfig, String> {
         let config = RociConfig::from_env();
         let store = self.roci_token_store()?;
@@ -311,15 +423,19 @@ impl CodexChatCore {
         }
 
         match model.provider_name() {
-            "openai" => {
-                if config.get_api_key("openai").is_none() {
-                    return Err("Missing OPENAI_API_KEY. Codex OAuth is available; use openai-codex/* models or set OPENAI_API_KEY.".to_string());
-                }
+            "openai" if config.get_api_key("openai").is_none() => {
+                return Err("Missing OPENAI_API_KEY. Codex OAuth is available; use openai-codex/* models or set OPENAI_API_KEY.".to_string());
             }
-            "openai-codex" => {
-                if cfg.openai_codex.enabled {
-                    let auth = self.openai_codex_auth(store.clone(), "default");
-                    if let Ok(token) = auth.get_token().await {
+            "openai-codex" if cfg.openai_codex.enabled => {
+                let profile = self.credential_profile(&store, "openai-codex", chat_settings)?;
+                let auth = self.openai_codex_auth(store.clone(), &profile);
+                match auth.get_token().await {
+                    Ok(token) => {
+                        let expires_at = token.expires_at;
+                        let token = refresh_if_expiring("openai-codex", token, expires_at, || {
+                            auth.refresh_token()
+                        })
+                        .await?;
                         if config.get_api_key("openai-codex").is_none() {
                             config.set_api_key("openai-codex", token.access_token);
                         }
@@ -335,12 +451,18 @@ impl CodexChatCore {
                             }
                         }
                     }
+                    Err(e) => {
+                        tracing::warn!(provider = "openai-codex", %profile, error = %e, "token load failed");
+                    }
                 }
             }
-            "github-copilot" => {
-                if cfg.github_copilot.enabled && config.get_api_key("github-copilot").is_none() {
-                    let auth = self.github_copilot_auth(store.clone(), "default");
-                    if let Ok(token) = auth.exchange_copilot_token().await {
+            "github-copilot"
+                if cfg.github_copilot.enabled && config.get_api_key("github-copilot").is_none() =>
+            {
+                let profile = self.credential_profile(&store, "github-copilot", chat_settings)?;
+                let auth = self.github_copilot_auth(store.clone(), &profile);
+                match auth.exchange_copilot_token().await {
+                    Ok(token) => {
                         config.set_api_key("github-copilot", token.token.clone());
                         if config.get_base_url("github-copilot").is_none() {
                             config.set_base_url("github-copilot", token.base_url.clone());
@@ -352,25 +474,44 @@ impl CodexChatCore {
                             config.set_base_url("openai-compatible", token.base_url);
                         }
                     }
+                    Err(e) => {
+                        tracing::warn!(provider = "github-copilot", %profile, error = %e, "copilot token exchange failed");
+                    }
                 }
             }
-            "openai-compatible" => {
-                if cfg.github_copilot.enabled && config.get_api_key("openai-compatible").is_none() {
-                    let auth = self.github_copilot_auth(store.clone(), "default");
-                    if let Ok(token) = auth.exchange_copilot_token().await {
+            "openai-compatible"
+                if cfg.github_copilot.enabled
+                    && config.get_api_key("openai-compatible").is_none() =>
+            {
+                let profile = self.credential_profile(&store, "github-copilot", chat_settings)?;
+                let auth = self.github_copilot_auth(store.clone(), &profile);
+                match auth.exchange_copilot_token().await {
+                    Ok(token) => {
                         config.set_api_key("openai-compatible", token.token.clone());
                         if config.get_base_url("openai-compatible").is_none() {
                             config.set_base_url("openai-compatible", token.base_url);
                         }
                     }
+                    Err(e) => {
+                        tracing::warn!(provider = "github-copilot", %profile, error = %e, "copilot token exchange failed");
+                    }
                 }
             }
-            "anthropic" => {
-                if cfg.claude_code.enabled && config.get_api_key("anthropic").is_none() {
-                    let auth = self.claude_code_auth(store.clone(), "default");
-                    if let Ok(token) = auth.get_token().await {
+            "anthropic" if cfg.claude_code.enabled && config.get_api_key("anthropic").is_none() => {
+                let profile = self.credential_profile(&store, "claude-code", chat_settings)?;
+                let auth = self.claude_code_auth(store.clone(), &profile);
+                match auth.get_token().await {
+                    Ok(token) => {
+                        let expires_at = token.expires_at;
+                        let token = refresh_if_expiring("claude-code", token, expires_at, || {
+                            auth.refresh_token()
+                        })
+                        .await?;
                         config.set_api_key("anthropic", token.access_token);
                     }
+                    Err(e) => {
+                        tracing::warn!(provider = "claude-code", %profile, error = %e, "token load failed");
+                    }
                 }
             }
             _ => {}
@@ -392,7 +533,38 @@ impl CodexChatCore {
         }
     }
 
+    /// Marker left by `chat.account.logout` so CLI credentials for
+    /// `provider_id` are not imported straight back.
+    fn logout_marker(&self, provider_id: &str) -> Option<PathBuf> {
+        let dir = self.homie_config.credentials_dir().ok()?;
+        Some(dir.join(format!(".{provider_id}.logged-out")))
+    }
+
+    fn cli_import_suppressed(&self, provider_id: &str) -> bool {
+        self.logout_marker(provider_id)
+            .is_some_and(|marker| marker.exists())
+    }
+
+    fn set_cli_import_suppressed(&self, provider_id: &str, suppressed: bool) {
+        let Some(marker) = self.logout_marker(provider_id) else {
+            return;
+        };
+        let result = if suppressed {
+            std::fs::write(&marker, b"")
+        } else if marker.exists() {
+            std::fs::remove_file(&marker)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            tracing::warn!(provider = %provider_id, error = %e, "failed to update logout marker");
+        }
+    }
+
     pub(super) fn import_codex_cli_credentials(&self, store: &FileTokenStore) {
+        if self.cli_import_suppressed("openai-codex") {
+            return;
+        }
         let existing = match store.load("openai-codex", "default") {
             Ok(token) => token,
             Err(err) => {
@@ -416,6 +588,9 @@ impl CodexChatCore {
     }
 
     pub(super) fn import_claude_cli_credentials(&self, store: &FileTokenStore) {
+        if self.cli_import_suppressed("claude-code") {
+            return;
+        }
         let existing = match store.load("claude-code", "default") {
             Ok(token) => token,
             Err(err) => {
@@ -438,6 +613,59 @@ impl CodexChatCore {
         }
     }
 
+    /// Check that `provider` (a model catalog provider) can be called right
+    /// now, going through the same auth path a run would use.
+    pub(super) async fn model_provider_availability(
+        &self,
+        store: &FileTokenStore,
+        provider: &str,
+    ) -> Result<(), String> {
+        let cfg = &self.homie_config.providers;
+        match provider {
+            "openai" => {
+                if RociConfig::from_env().get_api_key("openai").is_none() {
+                    return Err("OPENAI_API_KEY not set".to_string());
+                }
+                Ok(())
+            }
+            "openai-codex" => {
+                stored_token_state(store, "openai-codex")?;
+                let auth = self.openai_codex_auth(store.clone(), "default");
+                auth.get_token()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| auth_failure_reason(store, "openai-codex", e))
+            }
+            "github-copilot" => {
+                stored_token_state(store, "github-copilot")?;
+                let auth = self.github_copilot_auth(store.clone(), "default");
+                auth.exchange_copilot_token()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| auth_failure_reason(store, "github-copilot", e))
+            }
+            "anthropic" => {
+                stored_token_state(store, "claude-code")?;
+                let auth = self.claude_code_auth(store.clone(), "default");
+                auth.get_token()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| auth_failure_reason(store, "claude-code", e))
+            }
+            "openai-compatible" => {
+                if cfg.openai_compatible.base_url.trim().is_empty()
+                    && RociConfig::from_env()
+                        .get_base_url("openai-compatible")
+                        .is_none()
+                {
+                    return Err("base_url not configured".to_string());
+                }
+                Ok(())
+            }
+            other => Err(format!("unknown provider {other}")),
+        }
+    }
+
     pub(super) fn build_provider_status(
         &self,
         store: &FileTokenStore,
@@ -502,3 +730,28 @@ impl CodexChatCore {
         Ok(providers)
     }
 }
+
+/// Fail with "not logged in" when no credentials are stored for `provider_id`.
+fn stored_token_state(store: &FileTokenStore, provider_id: &str) -> Result<(), String> {
+    match store.load(provider_id, "default") {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err("not logged in".to_string()),
+        Err(e) => Err(format!("load {provider_id} token: {e}")),
+    }
+}
+
+fn auth_failure_reason(
+    store: &FileTokenStore,
+    provider_id: &str,
+    error: impl std::fmt::Display,
+) -> String {
+    let expired = matches!(
+        store.load(provider_id, "default"),
+        Ok(Some(token)) if token.expires_at.is_some_and(|at| at <= chrono::Utc::now())
+    );
+    if expired {
+        "token expired".to_string()
+    } else {
+        format!("auth failed: {error}")
+    }
+}
@@ -6,7 +6,7 @@ use uuid::Uuid;
 use crate::agent::process::CodexRequestId;
 use crate::agent::service::core::CodexChatCore;
 
-use super::params::parse_approval_params;
+use super::params::{parse_approval_params, parse_resume_params};
 
 pub(super) fn approval_command_argv(params: &Value) -> Option<Vec<String>> {
     let command = params.get("command")?.as_str()?;
@@ -91,4 +91,31 @@ impl CodexChatCore {
             ),
         }
     }
+
+    /// Re-announce the approvals a chat's thread is still waiting on, as
+    /// `chat.approval.required` events, and list them in the response.
+    pub(super) async fn chat_approvals_pending(
+        &mut self,
+        req_id: Uuid,
+        params: Option<Value>,
+    ) -> Response {
+        if !self.use_roci() {
+            return Response::error(
+                req_id,
+                error_codes::METHOD_NOT_FOUND,
+                "chat.approvals.pending requires the roci backend",
+            );
+        }
+        let Some((chat_id, thread_id)) = parse_resume_params(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing chat_id");
+        };
+        let Some(thread_id) = self.resolve_thread_id(&chat_id, thread_id.as_deref()) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing thread_id");
+        };
+        let approvals = self.roci.redeliver_approvals(&thread_id).await;
+        Response::success(
+            req_id,
+            json!({ "chat_id": chat_id, "thread_id": thread_id, "approvals": approvals }),
+        )
+    }
 }
@@ -1,16 +1,39 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
 use homie_protocol::{error_codes, Response};
 use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::agent::service::core::CodexChatCore;
-use crate::agent::tools::{list_tools, ToolContext, TOOL_CHANNEL_DENIED_CODE};
+use roci::tools::tool::ToolExecutionContext;
+use roci::tools::ToolArguments;
+
+use crate::agent::tools::{
+    build_tools, canonical_tool_channels, describe_tool, exec_command_argv, list_tools,
+    tool_examples, tool_output_schema, tool_side_effect, ProcessStatus, SessionTools, ToolContext,
+    ToolSideEffect, TOOL_CHANNEL_DENIED_CODE,
+};
+use crate::HomieConfig;
 
 use super::files::list_homie_skills;
 use super::models::{
-    append_openai_compatible_models, discover_github_copilot_models,
-    discover_openai_compatible_models, roci_model_catalog,
+    annotate_model_metadata, append_openai_compatible_models, discover_github_copilot_models,
+    discover_openai_compatible_models, mark_model_availability, roci_model_catalog,
+};
+use super::params::{
+    normalize_model_selector, parse_model_list_verify, parse_process_kill_params,
+    parse_tool_channel, parse_tool_describe_params, parse_tool_invoke_params,
+    parse_tool_register_params,
 };
-use super::params::parse_tool_channel;
+
+/// How long one provider may take to answer a `chat.model.list` verify
+/// probe before it is reported unavailable.
+const PROVIDER_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Output kept per process in `chat.process.list`.
+const PROCESS_LIST_TAIL_BYTES: usize = 2_000;
 
 impl CodexChatCore {
     pub(super) async fn chat_skills_list(
@@ -85,6 +108,17 @@ impl CodexChatCore {
                     tracing::debug!("openai-compatible model discovery skipped: {err}");
                 }
             }
+            models.retain(|entry| self.model_entry_allowed(entry));
+            annotate_model_metadata(&mut models);
+            if parse_model_list_verify(&params) {
+                if let Err(err) = self.verify_model_availability(&mut models).await {
+                    return Response::error(
+                        req_id,
+                        error_codes::INTERNAL_ERROR,
+                        format!("model verify failed: {err}"),
+                    );
+                }
+            }
             return Response::success(req_id, json!({ "data": models }));
         }
 
@@ -95,7 +129,12 @@ impl CodexChatCore {
         let process = self.process.as_ref().unwrap();
         let params = params.or_else(|| Some(json!({})));
         match process.send_request("model/list", params).await {
-            Ok(result) => Response::success(req_id, result),
+            Ok(mut result) => {
+                if let Some(models) = result.get_mut("data").and_then(Value::as_array_mut) {
+                    models.retain(|entry| self.model_entry_allowed(entry));
+                }
+                Response::success(req_id, result)
+            }
             Err(e) => Response::error(
                 req_id,
                 error_codes::INTERNAL_ERROR,
@@ -104,54 +143,66 @@ impl CodexChatCore {
         }
     }
 
+    /// Whether a `chat.model.list` entry passes the caller's allowlist.
+    fn model_entry_allowed(&self, entry: &Value) -> bool {
+        let Some(model) = entry
+            .get("model")
+            .or_else(|| entry.get("id"))
+            .and_then(Value::as_str)
+        else {
+            return true;
+        };
+        self.model_allowed(&normalize_model_selector(
+            model,
+            &self.homie_config.providers,
+        ))
+    }
+
+    /// Probe each provider in `models` once and mark its entries with
+    /// `available` and, when unreachable, a `reason`.
+    async fn verify_model_availability(&self, models: &mut [Value]) -> Result<(), String> {
+        let store = self.roci_token_store()?;
+        self.import_enabled_provider_credentials(&self.homie_config.providers, &store);
+        let mut providers: Vec<&str> = Vec::new();
+        for entry in models.iter() {
+            if let Some(provider) = entry.get("provider").and_then(|v| v.as_str()) {
+                if !providers.contains(&provider) {
+                    providers.push(provider);
+                }
+            }
+        }
+        let probes = providers.iter().map(|provider| async {
+            let probe = self.model_provider_availability(&store, provider);
+            let result = tokio::time::timeout(PROVIDER_PROBE_TIMEOUT, probe)
+                .await
+                .unwrap_or_else(|_| Err("timed out".to_string()));
+            (provider.to_string(), result)
+        });
+        let availability: HashMap<String, Result<(), String>> = futures::future::join_all(probes)
+            .await
+            .into_iter()
+            .collect();
+        mark_model_availability(models, &availability);
+        Ok(())
+    }
+
     pub(super) async fn chat_tools_list(
         &mut self,
         req_id: Uuid,
         params: Option<Value>,
     ) -> Response {
         if self.use_roci() {
-            let requested_channel = parse_tool_channel(&params);
-            if let (Some(bound_channel), Some(requested)) =
-                (self.tool_channel.as_deref(), requested_channel.as_deref())
-            {
-                if !bound_channel.eq_ignore_ascii_case(requested) {
-                    tracing::info!(
-                        provider = "*",
-                        tool = "*",
-                        channel = requested,
-                        bound_channel = bound_channel,
-                        decision = "deny",
-                        "tool channel policy"
-                    );
-                    return Response::error(
-                        req_id,
-                        error_codes::INVALID_PARAMS,
-                        TOOL_CHANNEL_DENIED_CODE,
-                    );
-                }
-            }
-            let effective_channel = self
-                .tool_channel
-                .as_deref()
-                .or(requested_channel.as_deref());
-            let ctx = ToolContext::new_with_channel(self.homie_config.clone(), effective_channel);
-            let resolved_channel = match ctx.channel.clone() {
-                Some(channel) => channel,
+            let ctx = match self.tool_context_for_request(&params) {
+                Some(ctx) => ctx,
                 None => {
-                    tracing::info!(
-                        provider = "*",
-                        tool = "*",
-                        channel = effective_channel.unwrap_or("undefined"),
-                        decision = "deny",
-                        "tool channel policy"
-                    );
                     return Response::error(
                         req_id,
                         error_codes::INVALID_PARAMS,
                         TOOL_CHANNEL_DENIED_CODE,
-                    );
+                    )
                 }
             };
+            let resolved_channel = ctx.channel.clone().unwrap_or_default();
             let tools = match list_tools(ctx, &self.homie_config) {
                 Ok(tools) => tools,
                 Err(err) => {
@@ -200,6 +251,270 @@ impl CodexChatCore {
         }
     }
 
+    /// Everything known about one tool: its `chat.tools.list` entry plus
+    /// hand-written examples, the channels allowed to use it and, for
+    /// dynamic providers, whether the provider is reachable right now.
+    pub(super) fn chat_tools_describe(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let Some(name) = parse_tool_describe_params(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing name");
+        };
+        let channels = tool_channels(&self.homie_config, &self.roci.session_tools(), &name);
+        if channels.is_empty() {
+            return Response::error(
+                req_id,
+                error_codes::METHOD_NOT_FOUND,
+                format!("unknown tool: {name}"),
+            );
+        }
+        let Some(ctx) = self.tool_context_for_request(&params) else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                TOOL_CHANNEL_DENIED_CODE,
+            );
+        };
+        let tool = match describe_tool(ctx, &self.homie_config, &name) {
+            Ok(Some(tool)) => tool,
+            Ok(None) => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    TOOL_CHANNEL_DENIED_CODE,
+                )
+            }
+            Err(err) => {
+                return Response::error(
+                    req_id,
+                    error_codes::INTERNAL_ERROR,
+                    format!("tools describe failed: {err}"),
+                )
+            }
+        };
+        let mut data = json!({
+            "name": tool.name,
+            "description": tool.description,
+            "provider": tool.provider_id,
+            "provider_dynamic": tool.provider_dynamic,
+            "input_schema": tool.input_schema,
+            "output_schema": tool_output_schema(&name),
+            "examples": tool_examples(&name),
+            "channels": channels,
+        });
+        if tool.provider_dynamic {
+            data["provider_reachable"] = json!(tool.provider_available);
+        }
+        Response::success(req_id, data)
+    }
+
+    /// Run one Homie tool directly, outside of any agent turn. Channel gating
+    /// matches `chat.tools.list`. With no approval prompt to fall back on,
+    /// only read-only tools run, plus `exec` commands the exec policy would
+    /// auto-approve in a turn.
+    pub(super) async fn chat_tools_invoke(
+        &mut self,
+        req_id: Uuid,
+        params: Option<Value>,
+    ) -> Response {
+        let (name, input) = match parse_tool_invoke_params(&params) {
+            Some(v) => v,
+            None => return Response::error(req_id, error_codes::INVALID_PARAMS, "missing name"),
+        };
+        if !tool_exists(&self.homie_config, &self.roci.session_tools(), &name) {
+            return Response::error(
+                req_id,
+                error_codes::METHOD_NOT_FOUND,
+                format!("unknown tool: {name}"),
+            );
+        }
+        let ctx = match self.tool_context_for_request(&params) {
+            Some(ctx) => ctx,
+            None => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    TOOL_CHANNEL_DENIED_CODE,
+                )
+            }
+        };
+        let channel = ctx.channel.clone().unwrap_or_default();
+        let tools = match build_tools(ctx, &self.homie_config) {
+            Ok(tools) => tools,
+            Err(err) => {
+                return Response::error(
+                    req_id,
+                    error_codes::INTERNAL_ERROR,
+                    format!("tools build failed: {err}"),
+                )
+            }
+        };
+        let Some(tool) = tools.into_iter().find(|tool| tool.name() == name) else {
+            tracing::info!(
+                provider = "*",
+                tool = %name,
+                channel = %channel,
+                decision = "deny",
+                "tool channel policy"
+            );
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                TOOL_CHANNEL_DENIED_CODE,
+            );
+        };
+        if name == "exec" {
+            let argv = match exec_command_argv(&input) {
+                Ok(argv) => argv,
+                Err(err) => return Response::error(req_id, error_codes::INVALID_PARAMS, err),
+            };
+            if !self.exec_policy.is_allowed(&argv) {
+                tracing::info!(argv = ?argv, "tools.invoke exec denied by execpolicy");
+                return Response::error(
+                    req_id,
+                    error_codes::UNAUTHORIZED,
+                    "command not allowed by exec policy",
+                );
+            }
+        } else if tool_side_effect(&name) != ToolSideEffect::ReadOnly {
+            tracing::info!(tool = %name, "tools.invoke denied: tool has side effects");
+            return Response::error(
+                req_id,
+                error_codes::UNAUTHORIZED,
+                format!("{name} has side effects and needs approval; run it in a chat turn"),
+            );
+        }
+
+        tracing::info!(tool = %name, %channel, "tools.invoke");
+        let args = ToolArguments::new(input);
+        match tool.execute(&args, &ToolExecutionContext::default()).await {
+            Ok(result) => Response::success(req_id, json!({ "result": result, "is_error": false })),
+            Err(err) => Response::success(
+                req_id,
+                json!({ "result": err.to_string(), "is_error": true }),
+            ),
+        }
+    }
+
+    /// Register an HTTP-backed tool for the rest of this connection. The
+    /// tool is offered to later runs and listed by `chat.tools.list`; names
+    /// of built-in tools cannot be taken over.
+    pub(super) fn chat_tools_register(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let Some(spec) = parse_tool_register_params(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing name or url");
+        };
+        if let Err(err) = spec.validate() {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, err);
+        }
+        let session_tools = self.roci.session_tools();
+        if !session_tools.contains(&spec.name)
+            && tool_exists(&self.homie_config, &session_tools, &spec.name)
+        {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("tool name already in use: {}", spec.name),
+            );
+        }
+        tracing::info!(tool = %spec.name, url = %spec.url, "tools.register");
+        let name = spec.name.clone();
+        session_tools.register(spec);
+        Response::success(req_id, json!({ "name": name, "registered": true }))
+    }
+
+    /// Background processes started by tools that are still running,
+    /// oldest first, with the thread and turn that started them when known.
+    pub(super) async fn chat_process_list(&mut self, req_id: Uuid) -> Response {
+        let owners = self.roci.process_owners().await;
+        let mut processes = self.roci.processes().list(PROCESS_LIST_TAIL_BYTES);
+        processes.retain(|info| info.status == ProcessStatus::Running);
+        processes.sort_by_key(|info| info.started_at);
+        let data: Vec<Value> = processes
+            .into_iter()
+            .map(|info| {
+                let owner = owners.get(&info.id);
+                json!({
+                    "process_id": info.id,
+                    "pid": info.pid,
+                    "command": info.command,
+                    "cwd": info.cwd,
+                    "started_at": info.started_at.to_rfc3339(),
+                    "thread_id": owner.map(|(thread_id, _)| thread_id),
+                    "turn_id": owner.map(|(_, turn_id)| turn_id),
+                    "output_tail": info.output_tail,
+                })
+            })
+            .collect();
+        Response::success(req_id, json!({ "processes": data }))
+    }
+
+    /// Terminate a tool-started background process and forget it. A process
+    /// that already exited is removed with `killed: false`.
+    pub(super) async fn chat_process_kill(
+        &mut self,
+        req_id: Uuid,
+        params: Option<Value>,
+    ) -> Response {
+        let Some(process_id) = parse_process_kill_params(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing process_id");
+        };
+        match self.roci.processes().kill_and_remove(&process_id).await {
+            Ok(killed) => {
+                tracing::info!(%process_id, killed, "process.kill");
+                Response::success(
+                    req_id,
+                    json!({ "process_id": process_id, "killed": killed }),
+                )
+            }
+            Err(err) => Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("{err}: {process_id}"),
+            ),
+        }
+    }
+
+    /// Tool context for a tools request, honoring the connection's bound
+    /// channel. `None` means the requested channel is denied.
+    fn tool_context_for_request(&self, params: &Option<Value>) -> Option<ToolContext> {
+        let requested_channel = parse_tool_channel(params);
+        if let (Some(bound_channel), Some(requested)) =
+            (self.tool_channel.as_deref(), requested_channel.as_deref())
+        {
+            if !bound_channel.eq_ignore_ascii_case(requested) {
+                tracing::info!(
+                    provider = "*",
+                    tool = "*",
+                    channel = requested,
+                    bound_channel = bound_channel,
+                    decision = "deny",
+                    "tool channel policy"
+                );
+                return None;
+            }
+        }
+        let effective_channel = self
+            .tool_channel
+            .as_deref()
+            .or(requested_channel.as_deref());
+        let ctx = ToolContext::with_processes_and_channel(
+            self.roci.processes(),
+            self.homie_config.clone(),
+            effective_channel,
+        )
+        .with_store(self.store.clone())
+        .with_session_tools(self.roci.session_tools());
+        if ctx.channel.is_none() {
+            tracing::info!(
+                provider = "*",
+                tool = "*",
+                channel = effective_channel.unwrap_or("undefined"),
+                decision = "deny",
+                "tool channel policy"
+            );
+            return None;
+        }
+        Some(ctx)
+    }
+
     pub(super) async fn chat_collaboration_mode_list(
         &mut self,
         req_id: Uuid,
@@ -261,3 +576,33 @@ impl CodexChatCore {
         }
     }
 }
+
+/// Channels whose tool catalog contains `name`, reachable or not.
+fn tool_channels(
+    homie_config: &Arc<HomieConfig>,
+    session_tools: &SessionTools,
+    name: &str,
+) -> Vec<String> {
+    canonical_tool_channels(&homie_config.tools)
+        .into_iter()
+        .filter(|channel| {
+            let ctx = ToolContext::new_with_channel(homie_config.clone(), Some(channel))
+                .with_session_tools(session_tools.clone());
+            matches!(describe_tool(ctx, homie_config, name), Ok(Some(_)))
+        })
+        .collect()
+}
+
+/// Whether any channel exposes a tool called `name`. Separates unknown tools
+/// from ones the caller's channel is not allowed to use.
+fn tool_exists(homie_config: &Arc<HomieConfig>, session_tools: &SessionTools, name: &str) -> bool {
+    canonical_tool_channels(&homie_config.tools)
+        .iter()
+        .any(|channel| {
+            let ctx = ToolContext::new_with_channel(homie_config.clone(), Some(channel))
+                .with_session_tools(session_tools.clone());
+            list_tools(ctx, homie_config)
+                .map(|tools| tools.iter().any(|tool| tool.name == name))
+                .unwrap_or(false)
+        })
+}
@@ -1,23 +1,61 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
 use homie_protocol::{error_codes, Response};
 use serde_json::{json, Value};
 use uuid::Uuid;
 
+use crate::agent::prompt::{
+    chat_system_prompt, render_system_prompt, validate_locale_settings,
+    validate_system_prompt_settings, ChatLocale, PromptVars,
+};
 use crate::agent::roci_backend::{RociBackend, StartRunRequest};
-use crate::storage::SessionStatus;
+use crate::agent::tools::confine_path;
+use crate::homie_config::{TokenBudgetMode, TokenBudgetScope};
+use crate::storage::{SessionStatus, UsageGroupBy, UsageQuery};
 
-use super::files::{extract_attached_folder, search_files_in_folder};
-use super::models::{chrono_now, extract_id_from_result};
+use super::events::codex_method_to_topics;
+use super::files::{
+    chat_working_dir, extract_attached_folders, normalize_search_root, search_files_in_folders,
+    FileSearchBounds,
+};
+use super::models::{
+    chrono_now, debug_enabled, extract_id_from_result, run_ignores_effort, unix_now,
+};
 use super::params::{
-    build_chat_settings, merge_settings, normalize_model_selector, normalize_settings_models,
-    parse_cancel_params, parse_files_search_params, parse_message_params, parse_resume_params,
-    parse_settings_update_params, parse_thread_archive_params, parse_thread_read_params,
-    parse_thread_rename_params, MessageParams,
+    auto_chat_title, build_chat_settings, chat_model, chat_pinned, chat_read_only,
+    chat_stream_idle_timeout, chat_title, codex_fork_cut, merge_settings, normalize_model_selector,
+    normalize_settings_models, page_thread_turns, parse_attachment_id, parse_cancel_params,
+    parse_chat_import_params, parse_chat_search_params, parse_create_model,
+    parse_events_since_params, parse_files_search_params, parse_include_diffs,
+    parse_message_params, parse_regenerate_params, parse_resume_params,
+    parse_settings_update_params, parse_thread_archive_params, parse_thread_fork_params,
+    parse_thread_pin_params, parse_thread_read_params, parse_thread_rename_params,
+    parse_tools_audit_params, parse_turn_page_params, parse_upload_begin_params, parse_upload_id,
+    parse_usage_summary_params, validate_known_settings, validate_profile_settings,
+    validate_read_only_settings, validate_stream_idle_timeout_settings, ChatImportParams,
+    MessageParams, RegenerateParams, SettingsUpdateParams,
 };
+use super::uploads::MAX_UPLOAD_BYTES;
 use crate::agent::service::core::CodexChatCore;
-use crate::storage::ChatRecord;
+use crate::outbound::OutboundMessage;
+use crate::router::ReapEvent;
+use crate::storage::{ChatRecord, PendingRunRecord};
 
 impl CodexChatCore {
-    pub(super) async fn chat_create(&mut self, req_id: Uuid) -> Response {
+    pub(super) async fn chat_create(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let model = parse_create_model(&params)
+            .map(|m| normalize_model_selector(&m, &self.homie_config.providers));
+        if let Some(model) = model.as_deref() {
+            if !self.model_allowed(model) {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    format!("model not allowed: {model}"),
+                );
+            }
+        }
+        let settings = build_chat_settings(model.as_ref(), None, None, None);
+
         if self.use_roci() {
             let chat_id = Uuid::new_v4().to_string();
             let thread_id = chat_id.clone();
@@ -29,11 +67,13 @@ impl CodexChatCore {
                 created_at: chrono_now(),
                 status: SessionStatus::Active,
                 event_pointer: 0,
-                settings: None,
+                settings,
+                owner: self.principal.clone(),
             };
             if let Err(e) = self.store.upsert_chat(&rec) {
                 tracing::warn!(%chat_id, "failed to persist chat create: {e}");
             }
+            self.emit_chat_list_upsert(&rec);
             return Response::success(
                 req_id,
                 json!({ "chat_id": chat_id, "thread_id": thread_id }),
@@ -45,8 +85,11 @@ impl CodexChatCore {
         }
 
         let process = self.process.as_ref().unwrap();
-        let params = json!({ "model": crate::agent::service::models::codex_model() });
-        match process.send_request("thread/start", Some(params)).await {
+        let model = model.unwrap_or_else(crate::agent::service::models::codex_model);
+        match process
+            .send_request("thread/start", Some(json!({ "model": model })))
+            .await
+        {
             Ok(result) => {
                 let thread_id = extract_id_from_result(
                     &result,
@@ -68,11 +111,13 @@ impl CodexChatCore {
                     created_at: chrono_now(),
                     status: SessionStatus::Active,
                     event_pointer: 0,
-                    settings: None,
+                    settings,
+                    owner: self.principal.clone(),
                 };
                 if let Err(e) = self.store.upsert_chat(&rec) {
                     tracing::warn!(%chat_id, "failed to persist chat create: {e}");
                 }
+                self.emit_chat_list_upsert(&rec);
 
                 Response::success(
                     req_id,
@@ -102,6 +147,7 @@ impl CodexChatCore {
 
         if self.use_roci() {
             self.roci.ensure_thread(&thread_id).await;
+            self.restore_pending_runs(&chat_id).await;
             let rec = match self.store.get_chat(&chat_id).ok().flatten() {
                 Some(mut rec) => {
                     rec.thread_id = thread_id.clone();
@@ -115,14 +161,21 @@ impl CodexChatCore {
                     status: SessionStatus::Active,
                     event_pointer: 0,
                     settings: None,
+                    owner: self.principal.clone(),
                 },
             };
             if let Err(e) = self.store.upsert_chat(&rec) {
                 tracing::warn!(%chat_id, "failed to persist chat resume: {e}");
             }
+            self.emit_chat_list_upsert(&rec);
+            let pending = self.roci.redeliver_approvals(&thread_id).await;
             return Response::success(
                 req_id,
-                json!({ "chat_id": chat_id, "thread_id": thread_id }),
+                json!({
+                    "chat_id": chat_id,
+                    "thread_id": thread_id,
+                    "pending_approvals": pending.len(),
+                }),
             );
         }
 
@@ -130,8 +183,17 @@ impl CodexChatCore {
             return Response::error(req_id, error_codes::INTERNAL_ERROR, e);
         }
 
+        let mut params = json!({ "threadId": thread_id });
+        let settings = self
+            .store
+            .get_chat(&chat_id)
+            .ok()
+            .flatten()
+            .and_then(|rec| rec.settings);
+        if let Some(model) = chat_model(settings.as_ref()) {
+            params["model"] = json!(model);
+        }
         let process = self.process.as_ref().unwrap();
-        let params = json!({ "threadId": thread_id });
         match process.send_request("thread/resume", Some(params)).await {
             Ok(result) => {
                 let resolved = extract_id_from_result(
@@ -155,11 +217,13 @@ impl CodexChatCore {
                         status: SessionStatus::Active,
                         event_pointer: 0,
                         settings: None,
+                        owner: self.principal.clone(),
                     },
                 };
                 if let Err(e) = self.store.upsert_chat(&rec) {
                     tracing::warn!(%chat_id, "failed to persist chat resume: {e}");
                 }
+                self.emit_chat_list_upsert(&rec);
 
                 Response::success(req_id, json!({ "chat_id": chat_id, "thread_id": resolved }))
             }
@@ -171,10 +235,153 @@ impl CodexChatCore {
         }
     }
 
+    /// Put runs that were queued for `chat_id` when their connection or the
+    /// previous process went away back in line. Model, credentials and
+    /// prompt are resolved again from the settings they were queued with; a
+    /// run that cannot be resolved, or whose token budget is spent, stays
+    /// persisted for the next resume.
+    async fn restore_pending_runs(&self, chat_id: &str) {
+        let runs = match self.store.take_pending_runs(chat_id) {
+            Ok(runs) => runs,
+            Err(e) => {
+                tracing::warn!(%chat_id, "failed to load queued runs: {e}");
+                return;
+            }
+        };
+        for run in runs {
+            match self.restore_pending_run(&run).await {
+                Ok(true) => tracing::info!(%chat_id, turn_id = %run.turn_id, "restored queued run"),
+                Ok(false) => {
+                    tracing::warn!(%chat_id, turn_id = %run.turn_id, "dropped queued run for missing turn");
+                    if let Err(e) = self.store.delete_pending_run(&run.turn_id) {
+                        tracing::warn!(%chat_id, "failed to delete queued run: {e}");
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(%chat_id, turn_id = %run.turn_id, "failed to restore queued run: {e}");
+                    // Released again in place, so it keeps its position.
+                    if let Err(e) = self.store.release_pending_run(&run.turn_id) {
+                        tracing::warn!(%chat_id, "failed to keep queued run: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Restore the released queued runs of every chat this connection may
+    /// access. Called when a connection that may send messages attaches, so
+    /// runs queued before a restart resume without waiting for the chat to
+    /// be opened again.
+    pub(super) async fn restore_released_runs(&self) {
+        if !self.use_roci() {
+            return;
+        }
+        let chats = match self.store.list_released_pending_run_chats() {
+            Ok(chats) => chats,
+            Err(e) => {
+                tracing::warn!("failed to list queued runs: {e}");
+                return;
+            }
+        };
+        for chat_id in chats.iter().filter(|chat_id| self.can_access_chat(chat_id)) {
+            self.restore_pending_runs(chat_id).await;
+        }
+    }
+
+    async fn restore_pending_run(&self, run: &PendingRunRecord) -> Result<bool, String> {
+        let settings = run.settings.as_ref();
+        let setting = |key: &str| {
+            settings
+                .and_then(|s| s.get(key))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        let model = setting("model")
+            .map(|model| normalize_model_selector(&model, &self.homie_config.providers));
+        self.check_run_model(model.as_deref())?;
+        if self.homie_config.chat.token_budget.mode == TokenBudgetMode::Block
+            && self.spent_token_budget(&run.chat_id).is_some()
+        {
+            return Err("token budget exceeded".into());
+        }
+        let roci_model = RociBackend::parse_model(model.as_ref())?;
+        let effort = RociBackend::parse_effort(setting("effort").as_ref())
+            .unwrap_or_else(|err| {
+                tracing::warn!(turn_id = %run.turn_id, "ignoring stored effort: {err}");
+                None
+            })
+            .filter(|_| !run_ignores_effort(model.as_deref()));
+        let system_prompt = self.chat_prompt(settings);
+        let config = self.roci_config_for_model(&roci_model, settings).await?;
+        self.roci
+            .requeue_run(
+                &run.turn_id,
+                StartRunRequest {
+                    chat_id: &run.chat_id,
+                    thread_id: &run.thread_id,
+                    message: &run.message,
+                    model: roci_model,
+                    settings: RociBackend::parse_settings(
+                        effort,
+                        chat_stream_idle_timeout(
+                            settings,
+                            self.homie_config.chat.stream_idle_timeout_ms,
+                        ),
+                    ),
+                    approval_policy: RociBackend::parse_approval_policy(
+                        setting("approval_policy").as_ref(),
+                    ),
+                    config,
+                    collaboration_mode: RociBackend::parse_collaboration_mode(
+                        settings.and_then(|s| s.get("collaboration_mode")),
+                    ),
+                    system_prompt: Some(system_prompt),
+                    read_only: chat_read_only(settings),
+                    cwd: chat_working_dir(settings),
+                    replace_turn: None,
+                },
+            )
+            .await
+    }
+
+    /// The chat's system prompt with its placeholders filled in for a run
+    /// started from this connection.
+    fn chat_prompt(&self, settings: Option<&Value>) -> String {
+        let chat_config = &self.homie_config.chat;
+        let locale = ChatLocale::resolve(settings, chat_config);
+        let cwd = extract_attached_folders(settings)
+            .first()
+            .map(|folder| normalize_search_root(folder).display().to_string());
+        let collaboration_mode = RociBackend::parse_collaboration_mode(
+            settings.and_then(|s| s.get("collaboration_mode")),
+        );
+        let vars = PromptVars::new(&locale, chrono::Utc::now())
+            .with("identity", self.principal.as_deref())
+            .with("channel", self.tool_channel.as_deref())
+            .with("cwd", cwd.as_deref())
+            .with("collaboration_mode", collaboration_mode.as_deref());
+        render_system_prompt(
+            chat_system_prompt(settings, &chat_config.system_prompt),
+            &vars,
+            chat_config.unknown_placeholders,
+        )
+    }
+
     pub(super) async fn chat_message_send(
         &mut self,
         req_id: Uuid,
         params: Option<Value>,
+    ) -> Response {
+        self.send_message(req_id, params, None).await
+    }
+
+    /// `chat.message.send`, optionally starting the run in place of
+    /// `replace_turn` and the turns after it (roci backend).
+    async fn send_message(
+        &mut self,
+        req_id: Uuid,
+        params: Option<Value>,
+        replace_turn: Option<&str>,
     ) -> Response {
         let MessageParams {
             chat_id,
@@ -194,9 +401,28 @@ impl CodexChatCore {
                 )
             }
         };
+        if self.roci.run_freeze().is_frozen() {
+            return Response::error(
+                req_id,
+                error_codes::FROZEN,
+                self.roci.run_freeze().refusal_message(),
+            );
+        }
         let normalized_model = model
             .as_ref()
             .map(|m| normalize_model_selector(m, &self.homie_config.providers));
+        if let Some(model) = normalized_model.as_deref() {
+            if !self.model_allowed(model) {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    format!("model not allowed: {model}"),
+                );
+            }
+        }
+        if let Some(refusal) = self.token_budget_refusal(req_id, &chat_id, true) {
+            return refusal;
+        }
 
         if self.use_roci() {
             let thread_id = match self.resolve_thread_id(&chat_id, None) {
@@ -210,6 +436,10 @@ impl CodexChatCore {
                 }
             };
 
+            let roci_effort = match RociBackend::parse_effort(effort.as_ref()) {
+                Ok(effort) => effort,
+                Err(err) => return Response::error(req_id, error_codes::INVALID_PARAMS, err),
+            };
             let settings = build_chat_settings(
                 normalized_model.as_ref(),
                 effort.as_ref(),
@@ -222,12 +452,19 @@ impl CodexChatCore {
                 .ok()
                 .flatten()
                 .and_then(|rec| rec.settings);
-            if let Some(settings) = settings {
-                let merged = merge_settings(existing_settings, settings);
-                if let Err(e) = self.store.update_chat_settings(&chat_id, Some(&merged)) {
-                    tracing::warn!(%chat_id, "failed to persist chat settings: {e}");
+            let chat_settings = match settings {
+                Some(settings) => {
+                    let merged = merge_settings(existing_settings, settings);
+                    if let Err(e) = self.store.update_chat_settings(&chat_id, Some(&merged)) {
+                        tracing::warn!(%chat_id, "failed to persist chat settings: {e}");
+                    }
+                    Some(merged)
                 }
-            }
+                None => existing_settings,
+            };
+            // Runs queued before a restart go ahead of this message.
+            self.restore_pending_runs(&chat_id).await;
+            let system_prompt = self.chat_prompt(chat_settings.as_ref());
 
             if inject {
                 if let Some(turn_id) = self
@@ -242,18 +479,32 @@ impl CodexChatCore {
                 }
             }
 
-            let roci_model = match RociBackend::parse_model(normalized_model.as_ref()) {
+            let bound_model = normalized_model
+                .clone()
+                .or_else(|| chat_model(chat_settings.as_ref()).map(str::to_string));
+            if let Err(err) = self.check_run_model(bound_model.as_deref()) {
+                return Response::error(req_id, error_codes::INVALID_PARAMS, err);
+            }
+            let effort_ignored =
+                roci_effort.is_some() && run_ignores_effort(bound_model.as_deref());
+            let roci_model = match RociBackend::parse_model(bound_model.as_ref()) {
                 Ok(model) => model,
                 Err(err) => return Response::error(req_id, error_codes::INVALID_PARAMS, err),
             };
             let roci_settings = RociBackend::parse_settings(
-                effort.as_ref(),
-                self.homie_config.chat.stream_idle_timeout_ms,
+                roci_effort.filter(|_| !effort_ignored),
+                chat_stream_idle_timeout(
+                    chat_settings.as_ref(),
+                    self.homie_config.chat.stream_idle_timeout_ms,
+                ),
             );
             let roci_policy = RociBackend::parse_approval_policy(approval_policy.as_ref());
             let roci_collab_mode =
                 RociBackend::parse_collaboration_mode(collaboration_mode.as_ref());
-            let roci_config = match self.roci_config_for_model(&roci_model).await {
+            let roci_config = match self
+                .roci_config_for_model(&roci_model, chat_settings.as_ref())
+                .await
+            {
                 Ok(config) => config,
                 Err(err) => return Response::error(req_id, error_codes::INTERNAL_ERROR, err),
             };
@@ -268,15 +519,26 @@ impl CodexChatCore {
                     approval_policy: roci_policy,
                     config: roci_config,
                     collaboration_mode: roci_collab_mode,
-                    system_prompt: Some(self.homie_config.chat.system_prompt.clone()),
+                    system_prompt: Some(system_prompt),
+                    read_only: chat_read_only(chat_settings.as_ref()),
+                    cwd: chat_working_dir(chat_settings.as_ref()),
+                    replace_turn,
                 })
                 .await
             {
                 Ok(turn_id) => {
-                    return Response::success(
-                        req_id,
-                        json!({ "chat_id": chat_id, "turn_id": turn_id }),
-                    )
+                    if chat_title(chat_settings.as_ref()).is_none() {
+                        if let Some(title) = auto_chat_title(&message) {
+                            if let Err(e) = self.persist_chat_title(&chat_id, &thread_id, &title) {
+                                tracing::warn!(%chat_id, "failed to persist chat title: {e}");
+                            }
+                        }
+                    }
+                    let mut result = json!({ "chat_id": chat_id, "turn_id": turn_id });
+                    if effort_ignored {
+                        result["effort_ignored"] = json!(true);
+                    }
+                    return Response::success(req_id, result);
                 }
                 Err(e) => {
                     return Response::error(
@@ -299,11 +561,28 @@ impl CodexChatCore {
             }
         };
 
+        let existing_settings = self
+            .store
+            .get_chat(&chat_id)
+            .ok()
+            .flatten()
+            .and_then(|rec| rec.settings);
+        if let Err(err) = self.check_run_model(
+            normalized_model
+                .as_deref()
+                .or_else(|| chat_model(existing_settings.as_ref())),
+        ) {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, err);
+        }
         let mut codex_params = json!({
             "threadId": thread_id,
             "input": [{"type": "text", "text": message}],
         });
-        if let Some(model) = normalized_model.as_ref() {
+        // The chat's bound model applies when the message names none.
+        if let Some(model) = normalized_model
+            .as_deref()
+            .or_else(|| chat_model(existing_settings.as_ref()))
+        {
             codex_params["model"] = json!(model);
         }
         if let Some(effort) = effort.as_ref() {
@@ -312,6 +591,12 @@ impl CodexChatCore {
         if let Some(approval_policy) = approval_policy.as_ref() {
             codex_params["approvalPolicy"] = json!(approval_policy);
         }
+        // Read-only chats run sandboxed and ask before every command,
+        // whatever policy the message asks for.
+        if chat_read_only(existing_settings.as_ref()) {
+            codex_params["approvalPolicy"] = json!("untrusted");
+            codex_params["sandboxPolicy"] = json!({ "type": "readOnly" });
+        }
         if let Some(collaboration_mode) = collaboration_mode.as_ref() {
             if collaboration_mode.is_object() {
                 codex_params["collaborationMode"] = collaboration_mode.clone();
@@ -323,12 +608,6 @@ impl CodexChatCore {
             approval_policy.as_ref(),
             collaboration_mode.as_ref(),
         );
-        let existing_settings = self
-            .store
-            .get_chat(&chat_id)
-            .ok()
-            .flatten()
-            .and_then(|rec| rec.settings);
 
         let process = self.process.as_ref().unwrap();
         match process.send_request("turn/start", Some(codex_params)).await {
@@ -408,6 +687,33 @@ impl CodexChatCore {
         }
     }
 
+    /// Abort a turn on behalf of `jobs.cancel`. Only turns this connection
+    /// is driving are touched; returns whether one was interrupted.
+    pub(super) async fn cancel_turn_for_job(&mut self, chat_id: &str, turn_id: &str) -> bool {
+        if self.use_roci() {
+            return self.roci.cancel_run(turn_id).await;
+        }
+        let (Some(process), Some(thread_id)) =
+            (self.process.as_ref(), self.thread_ids.get(chat_id))
+        else {
+            return false;
+        };
+        let codex_params = json!({
+            "threadId": thread_id,
+            "turnId": turn_id,
+        });
+        match process
+            .send_request("turn/interrupt", Some(codex_params))
+            .await
+        {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::warn!(%chat_id, %turn_id, "turn/interrupt for job failed: {e}");
+                false
+            }
+        }
+    }
+
     pub(super) async fn chat_thread_read(
         &mut self,
         req_id: Uuid,
@@ -423,6 +729,9 @@ impl CodexChatCore {
                 )
             }
         };
+        let page = parse_turn_page_params(&params);
+        let include_turns = include_turns || page.is_some();
+        let include_diffs = parse_include_diffs(&params);
 
         let thread_id = match thread_id {
             Some(id) => id,
@@ -453,12 +762,21 @@ impl CodexChatCore {
             });
 
         if self.use_roci() {
+            let diffs = if include_diffs {
+                self.roci.ensure_thread(&thread_id).await;
+                self.roci.thread_diffs(&thread_id).await
+            } else {
+                None
+            };
             let with_settings = |thread: Value| {
                 let mut result = json!({ "thread": thread });
-                if let Some(settings) = settings.clone() {
-                    if let Some(obj) = result.as_object_mut() {
+                if let Some(obj) = result.as_object_mut() {
+                    if let Some(settings) = settings.clone() {
                         obj.insert("settings".into(), settings);
                     }
+                    if let Some(diffs) = diffs.clone() {
+                        obj.insert("diffs".into(), diffs);
+                    }
                 }
                 result
             };
@@ -470,6 +788,31 @@ impl CodexChatCore {
 
             self.roci.ensure_thread(&thread_id).await;
 
+            if let Some(page) = page {
+                let mut result = match self
+                    .roci
+                    .thread_read_page(&thread_id, page.before_turn_id.as_deref(), page.limit)
+                    .await
+                {
+                    Ok(Some(result)) => result,
+                    Ok(None) => json!({
+                        "thread": { "id": thread_id, "turns": [] },
+                        "total_turns": 0,
+                        "next_cursor": null,
+                    }),
+                    Err(e) => return Response::error(req_id, error_codes::INVALID_PARAMS, e),
+                };
+                if let Some(obj) = result.as_object_mut() {
+                    if let Some(settings) = settings.clone() {
+                        obj.insert("settings".into(), settings);
+                    }
+                    if let Some(diffs) = diffs.clone() {
+                        obj.insert("diffs".into(), diffs);
+                    }
+                }
+                return Response::success(req_id, result);
+            }
+
             if let Some(thread) = self.roci.thread_read(&thread_id).await {
                 return Response::success(req_id, with_settings(thread));
             }
@@ -483,9 +826,15 @@ impl CodexChatCore {
         }
 
         let process = self.process.as_ref().unwrap();
+        // The app-server has no paged read, so pages are cut from a full one.
         let params = json!({ "threadId": thread_id, "includeTurns": include_turns });
         match process.send_request("thread/read", Some(params)).await {
             Ok(mut result) => {
+                if let Some(page) = page.as_ref() {
+                    if let Err(e) = page_thread_turns(&mut result, page) {
+                        return Response::error(req_id, error_codes::INVALID_PARAMS, e);
+                    }
+                }
                 if let Some(settings) = settings {
                     if let Some(obj) = result.as_object_mut() {
                         obj.insert("settings".into(), settings);
@@ -503,6 +852,53 @@ impl CodexChatCore {
         }
     }
 
+    pub(super) async fn chat_compaction_preview(
+        &mut self,
+        req_id: Uuid,
+        params: Option<Value>,
+    ) -> Response {
+        if !debug_enabled() {
+            return Response::error(
+                req_id,
+                error_codes::METHOD_NOT_FOUND,
+                "chat.compaction.preview requires HOMIE_DEBUG=1",
+            );
+        }
+        if !self.use_roci() {
+            return Response::error(
+                req_id,
+                error_codes::METHOD_NOT_FOUND,
+                "chat.compaction.preview is only available for the roci backend",
+            );
+        }
+        let (chat_id, thread_id, _) = match parse_thread_read_params(&params) {
+            Some(v) => v,
+            None => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    "missing chat_id or thread_id",
+                )
+            }
+        };
+        let thread_id = match thread_id.or_else(|| {
+            chat_id
+                .as_deref()
+                .and_then(|id| self.resolve_thread_id(id, None))
+        }) {
+            Some(id) => id,
+            None => {
+                return Response::error(req_id, error_codes::INVALID_PARAMS, "missing thread_id")
+            }
+        };
+
+        self.roci.ensure_thread(&thread_id).await;
+        match self.roci.compaction_preview(&thread_id).await {
+            Some(preview) => Response::success(req_id, preview),
+            None => Response::error(req_id, error_codes::INVALID_PARAMS, "unknown thread"),
+        }
+    }
+
     pub(super) async fn chat_thread_list(
         &mut self,
         req_id: Uuid,
@@ -528,18 +924,44 @@ impl CodexChatCore {
         }
     }
 
-    pub(super) fn chat_settings_update(&self, req_id: Uuid, params: Option<Value>) -> Response {
-        let (chat_id, updates) = match parse_settings_update_params(&params) {
-            Some(v) => v,
-            None => {
-                return Response::error(
-                    req_id,
-                    error_codes::INVALID_PARAMS,
-                    "missing chat_id or settings",
-                )
+    /// Validate chat settings from a client and normalize their models.
+    /// With `force`, keys this backend does not know are kept.
+    fn checked_settings(&self, settings: Value, force: bool) -> Result<Value, String> {
+        if !force {
+            validate_known_settings(&settings, self.use_roci())?;
+        }
+        validate_locale_settings(&settings)?;
+        validate_profile_settings(&settings)?;
+        validate_read_only_settings(&settings)?;
+        validate_system_prompt_settings(&settings)?;
+        validate_stream_idle_timeout_settings(&settings)?;
+        let settings = normalize_settings_models(settings, &self.homie_config.providers);
+        if let Some(model) = chat_model(Some(&settings)) {
+            if !self.model_allowed(model) {
+                return Err(format!("model not allowed: {model}"));
             }
+        }
+        Ok(settings)
+    }
+
+    pub(super) fn chat_settings_update(&self, req_id: Uuid, params: Option<Value>) -> Response {
+        let Some(SettingsUpdateParams {
+            chat_id,
+            settings: updates,
+            force,
+            validate_only,
+        }) = parse_settings_update_params(&params)
+        else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                "missing chat_id or settings",
+            );
+        };
+        let updates = match self.checked_settings(updates, force) {
+            Ok(updates) => updates,
+            Err(e) => return Response::error(req_id, error_codes::INVALID_PARAMS, e),
         };
-        let updates = normalize_settings_models(updates, &self.homie_config.providers);
 
         let existing = self
             .store
@@ -548,6 +970,12 @@ impl CodexChatCore {
             .flatten()
             .and_then(|rec| rec.settings);
         let merged = merge_settings(existing, updates);
+        if validate_only {
+            return Response::success(
+                req_id,
+                json!({ "ok": true, "settings": merged, "validate_only": true }),
+            );
+        }
         if let Err(e) = self.store.update_chat_settings(&chat_id, Some(&merged)) {
             return Response::error(
                 req_id,
@@ -555,44 +983,125 @@ impl CodexChatCore {
                 format!("settings update failed: {e}"),
             );
         }
+        if let Ok(Some(rec)) = self.store.get_chat(&chat_id) {
+            self.emit_chat_list_upsert(&rec);
+        }
         Response::success(req_id, json!({ "ok": true, "settings": merged }))
     }
 
     pub(super) fn chat_files_search(&self, req_id: Uuid, params: Option<Value>) -> Response {
-        let (chat_id, query, limit, base_override) = match parse_files_search_params(&params) {
-            Some(v) => v,
-            None => {
-                return Response::error(
-                    req_id,
-                    error_codes::INVALID_PARAMS,
-                    "missing chat_id or query",
-                )
-            }
+        let Some(search) = parse_files_search_params(&params) else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                "missing chat_id or query",
+            );
         };
+        let chat_id = search.chat_id;
+        let query = search.query;
+        let bounds = FileSearchBounds::from_config(
+            &self.homie_config.tools.file_search,
+            search.limit,
+            search.max_depth,
+        );
 
         let settings = match self.store.get_chat(&chat_id) {
             Ok(Some(rec)) => rec.settings,
             _ => None,
         };
-        let base = extract_attached_folder(settings.as_ref()).or_else(|| base_override.clone());
-        let base = match base {
-            Some(path) => path,
-            None => {
-                tracing::debug!(%chat_id, "chat files search skipped: no attached folder");
-                return Response::success(req_id, json!({ "files": [] }));
+        let mut bases = extract_attached_folders(settings.as_ref());
+        let mut allowed = self.homie_config.tools.fs.roots();
+        if allowed.is_empty() {
+            // Without `tools.fs.allowed_roots`, the attached folders are the
+            // roots, or the server's cwd when there are none.
+            allowed = if bases.is_empty() {
+                std::env::current_dir().into_iter().collect()
+            } else {
+                bases
+                    .iter()
+                    .map(|base| normalize_search_root(base))
+                    .collect()
+            };
+        }
+        if bases.is_empty() {
+            bases.extend(search.base_path);
+        }
+        bases.retain(|base| {
+            let inside = confine_path(&normalize_search_root(base), &allowed).is_ok();
+            if !inside {
+                tracing::debug!(%chat_id, %base, "search root outside allowed roots");
             }
-        };
+            inside
+        });
+        if bases.is_empty() {
+            tracing::debug!(%chat_id, "chat files search skipped: no attached folder");
+            return Response::success(req_id, json!({ "files": [], "truncated": false }));
+        }
 
-        tracing::debug!(%chat_id, %base, %query, %limit, "chat files search");
-        match search_files_in_folder(&base, &query, limit) {
-            Ok(files) => {
-                tracing::debug!(%chat_id, count = files.len(), "chat files search complete");
-                Response::success(req_id, json!({ "files": files, "base_path": base }))
+        let respect_gitignore = search.respect_gitignore;
+        tracing::debug!(%chat_id, ?bases, %query, ?bounds, respect_gitignore, "chat files search");
+        match search_files_in_folders(&bases, &query, bounds, respect_gitignore) {
+            Ok(found) => {
+                tracing::debug!(
+                    %chat_id,
+                    count = found.files.len(),
+                    truncated = found.truncated,
+                    "chat files search complete"
+                );
+                Response::success(
+                    req_id,
+                    json!({ "files": found.files, "truncated": found.truncated }),
+                )
             }
             Err(e) => Response::error(req_id, error_codes::INTERNAL_ERROR, e),
         }
     }
 
+    /// Start a chunked upload. Chunks arrive as `StreamType::Upload` binary
+    /// frames tagged with the returned `upload_id`.
+    pub(super) async fn chat_file_upload_begin(
+        &self,
+        req_id: Uuid,
+        params: Option<Value>,
+    ) -> Response {
+        let Some((name, size, sha256)) = parse_upload_begin_params(&params) else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                "missing name, size or sha256",
+            );
+        };
+        match self.uploads.begin(&name, size, &sha256).await {
+            Ok(upload_id) => Response::success(
+                req_id,
+                json!({ "upload_id": upload_id, "max_bytes": MAX_UPLOAD_BYTES }),
+            ),
+            Err(e) => Response::error(req_id, error_codes::INVALID_PARAMS, e),
+        }
+    }
+
+    pub(super) async fn chat_file_upload_commit(
+        &self,
+        req_id: Uuid,
+        params: Option<Value>,
+    ) -> Response {
+        let Some(upload_id) = parse_upload_id(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing upload_id");
+        };
+        match self.uploads.commit(upload_id).await {
+            Ok(upload) => Response::success(
+                req_id,
+                json!({
+                    "upload_id": upload_id,
+                    "path": upload.path.to_string_lossy(),
+                    "size": upload.size,
+                    "sha256": upload.sha256,
+                }),
+            ),
+            Err(e) => Response::error(req_id, error_codes::INVALID_PARAMS, e),
+        }
+    }
+
     pub(super) async fn chat_thread_archive(
         &mut self,
         req_id: Uuid,
@@ -622,6 +1131,7 @@ impl CodexChatCore {
                 tracing::warn!(%chat_id, "failed to delete archived chat: {e}");
             }
             self.thread_ids.remove(&chat_id);
+            self.emit_chat_list_remove(&chat_id);
             return Response::success(req_id, json!({ "ok": true }));
         }
 
@@ -637,6 +1147,7 @@ impl CodexChatCore {
                     tracing::warn!(%chat_id, "failed to delete archived chat: {e}");
                 }
                 self.thread_ids.remove(&chat_id);
+                self.emit_chat_list_remove(&chat_id);
                 Response::success(req_id, json!({ "ok": true }))
             }
             Err(e) => Response::error(
@@ -671,8 +1182,14 @@ impl CodexChatCore {
         };
 
         if self.use_roci() {
-            tracing::debug!(%chat_id, %thread_id, "roci rename ignored");
-            return Response::success(req_id, json!({ "ok": true }));
+            return match self.persist_chat_title(&chat_id, &thread_id, &title) {
+                Ok(()) => Response::success(req_id, json!({ "ok": true })),
+                Err(e) => Response::error(
+                    req_id,
+                    error_codes::INTERNAL_ERROR,
+                    format!("rename failed: {e}"),
+                ),
+            };
         }
 
         if let Err(e) = self.ensure_process().await {
@@ -682,7 +1199,12 @@ impl CodexChatCore {
         let process = self.process.as_ref().unwrap();
         let params = json!({ "threadId": thread_id, "name": title });
         match process.send_request("thread/name/set", Some(params)).await {
-            Ok(_) => Response::success(req_id, json!({ "ok": true })),
+            Ok(_) => {
+                if let Err(e) = self.persist_chat_title(&chat_id, &thread_id, &title) {
+                    tracing::warn!(%chat_id, "failed to persist chat title: {e}");
+                }
+                Response::success(req_id, json!({ "ok": true }))
+            }
             Err(e) => Response::error(
                 req_id,
                 error_codes::INTERNAL_ERROR,
@@ -691,29 +1213,747 @@ impl CodexChatCore {
         }
     }
 
-    pub(super) fn chat_list(&self, req_id: Uuid) -> Response {
-        match self.store.list_chats() {
-            Ok(records) => {
-                let chats: Vec<Value> = records
-                    .into_iter()
-                    .map(|r| {
-                        json!({
-                            "chat_id": r.chat_id,
-                            "thread_id": r.thread_id,
-                            "created_at": r.created_at,
-                            "status": r.status,
-                            "event_pointer": r.event_pointer,
-                            "settings": r.settings,
-                        })
-                    })
-                    .collect();
-                Response::success(req_id, json!({ "chats": chats }))
+    /// Pin a chat to the top of `chat.list`, or unpin it. Stored in the
+    /// chat settings, so it works the same on either backend.
+    pub(super) fn chat_thread_pin(
+        &mut self,
+        req_id: Uuid,
+        params: Option<Value>,
+        pinned: bool,
+    ) -> Response {
+        let Some((chat_id, thread_id_param)) = parse_thread_pin_params(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing chat_id");
+        };
+        let thread_id = match self.resolve_thread_id(&chat_id, thread_id_param.as_deref()) {
+            Some(id) => id,
+            None => {
+                return Response::error(req_id, error_codes::INVALID_PARAMS, "missing thread_id")
             }
-            Err(e) => Response::error(
-                req_id,
-                error_codes::INTERNAL_ERROR,
-                format!("list failed: {e}"),
-            ),
-        }
+        };
+        let existing = match self.store.get_chat(&chat_id) {
+            Ok(Some(rec)) => rec.settings,
+            Ok(None) => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    format!("unknown chat: {chat_id}"),
+                )
+            }
+            Err(e) => {
+                return Response::error(
+                    req_id,
+                    error_codes::INTERNAL_ERROR,
+                    format!("pin failed: {e}"),
+                )
+            }
+        };
+        let merged = merge_settings(existing, json!({ "pinned": pinned }));
+        if let Err(e) = self.store.update_chat_settings(&chat_id, Some(&merged)) {
+            return Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("pin failed: {e}"),
+            );
+        }
+        if let Ok(Some(rec)) = self.store.get_chat(&chat_id) {
+            self.emit_chat_list_upsert(&rec);
+        }
+        self.emit_event(
+            CHAT_THREAD_PINNED_TOPIC,
+            json!({ "chat_id": chat_id, "thread_id": thread_id, "pinned": pinned }),
+        );
+        Response::success(req_id, json!({ "ok": true, "pinned": pinned }))
+    }
+
+    /// Branch a chat: a new chat whose thread copies the source turns up to
+    /// and including `up_to_turn_id`, with the source chat's settings.
+    pub(super) async fn chat_thread_fork(
+        &mut self,
+        req_id: Uuid,
+        params: Option<Value>,
+    ) -> Response {
+        let (source_chat_id, up_to_turn_id) = match parse_thread_fork_params(&params) {
+            Some(v) => v,
+            None => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    "missing chat_id or up_to_turn_id",
+                )
+            }
+        };
+        let Some(source_thread_id) = self.resolve_thread_id(&source_chat_id, None) else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("unknown chat: {source_chat_id}"),
+            );
+        };
+        let settings = self
+            .store
+            .get_chat(&source_chat_id)
+            .ok()
+            .flatten()
+            .and_then(|rec| rec.settings);
+
+        let (chat_id, thread_id, turns) = if self.use_roci() {
+            let chat_id = Uuid::new_v4().to_string();
+            let thread_id = chat_id.clone();
+            match self
+                .roci
+                .thread_fork(&source_thread_id, &up_to_turn_id, &thread_id)
+                .await
+            {
+                Ok(turns) => (chat_id, thread_id, turns),
+                Err(e) => return Response::error(req_id, error_codes::INVALID_PARAMS, e),
+            }
+        } else {
+            match self
+                .codex_thread_fork(req_id, &source_thread_id, &up_to_turn_id, settings.as_ref())
+                .await
+            {
+                Ok((thread_id, turns)) => (thread_id.clone(), thread_id, turns),
+                Err(response) => return response,
+            }
+        };
+        self.thread_ids.insert(chat_id.clone(), thread_id.clone());
+        let rec = ChatRecord {
+            chat_id: chat_id.clone(),
+            thread_id: thread_id.clone(),
+            created_at: chrono_now(),
+            status: SessionStatus::Active,
+            event_pointer: 0,
+            settings,
+            owner: self.principal.clone(),
+        };
+        if let Err(e) = self.store.upsert_chat(&rec) {
+            self.thread_ids.remove(&chat_id);
+            self.discard_thread(&thread_id).await;
+            return Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("failed to persist forked chat: {e}"),
+            );
+        }
+        self.emit_chat_list_upsert(&rec);
+        Response::success(
+            req_id,
+            json!({ "chat_id": chat_id, "thread_id": thread_id, "turns": turns }),
+        )
+    }
+
+    /// Archive a thread created for a chat that could not be saved, so no
+    /// thread is left without a chat.
+    async fn discard_thread(&mut self, thread_id: &str) {
+        if self.use_roci() {
+            self.roci.thread_archive(thread_id).await;
+            return;
+        }
+        let Some(process) = self.process.as_ref() else {
+            return;
+        };
+        let params = json!({ "threadId": thread_id });
+        if let Err(e) = process.send_request("thread/archive", Some(params)).await {
+            tracing::warn!(%thread_id, "failed to archive unsaved thread: {e}");
+        }
+    }
+
+    /// Codex keeps thread state in the app-server: `thread/fork` copies the
+    /// source thread and `thread/rollback` drops the turns after the cut.
+    /// Returns the new thread and how many turns it kept.
+    async fn codex_thread_fork(
+        &mut self,
+        req_id: Uuid,
+        source_thread_id: &str,
+        up_to_turn_id: &str,
+        settings: Option<&Value>,
+    ) -> Result<(String, usize), Response> {
+        if let Err(e) = self.ensure_process().await {
+            return Err(Response::error(req_id, error_codes::INTERNAL_ERROR, e));
+        }
+        let process = self.process.as_ref().unwrap();
+        let failed = |what: &str, e: String| {
+            Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("{what} failed: {e}"),
+            )
+        };
+        let params = json!({ "threadId": source_thread_id, "includeTurns": true });
+        let source = process
+            .send_request("thread/read", Some(params))
+            .await
+            .and_then(codex_result)
+            .map_err(|e| failed("thread/read", e))?;
+        let (kept, dropped) = codex_fork_cut(&source, up_to_turn_id)
+            .map_err(|e| Response::error(req_id, error_codes::INVALID_PARAMS, e))?;
+
+        let mut params = json!({ "threadId": source_thread_id });
+        if let Some(model) = chat_model(settings) {
+            params["model"] = json!(model);
+        }
+        let forked = process
+            .send_request("thread/fork", Some(params))
+            .await
+            .and_then(codex_result)
+            .map_err(|e| failed("thread/fork", e))?;
+        let thread_id =
+            extract_id_from_result(&forked, &["threadId", "thread_id"], &[("thread", "id")])
+                .filter(|id| !id.is_empty())
+                .ok_or_else(|| failed("thread/fork", "no thread id".to_string()))?;
+        if dropped > 0 {
+            let params = json!({ "threadId": thread_id, "numTurns": dropped });
+            let rolled_back = process
+                .send_request("thread/rollback", Some(params))
+                .await
+                .and_then(codex_result);
+            if let Err(e) = rolled_back {
+                // The copy still holds the later turns; don't leave it around.
+                let params = json!({ "threadId": thread_id });
+                if let Err(archive) = process.send_request("thread/archive", Some(params)).await {
+                    tracing::warn!(%thread_id, "failed to archive partial fork: {archive}");
+                }
+                return Err(failed("thread/rollback", e));
+            }
+        }
+        Ok((thread_id, kept))
+    }
+
+    /// Restore an exported roci thread as a new chat. Ids are regenerated,
+    /// so the same export can be imported more than once.
+    pub(super) async fn chat_import(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        if !self.use_roci() {
+            return Response::error(
+                req_id,
+                error_codes::METHOD_NOT_FOUND,
+                "chat.import is only available for the roci backend",
+            );
+        }
+        let Some(ChatImportParams { thread, settings }) = parse_chat_import_params(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing thread");
+        };
+        let max_bytes = self.homie_config.chat.max_import_bytes;
+        let size = serde_json::to_vec(&thread)
+            .map(|b| b.len())
+            .unwrap_or(usize::MAX);
+        if size > max_bytes {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("import exceeds {max_bytes} bytes"),
+            );
+        }
+        let settings = match settings
+            .map(|settings| self.checked_settings(settings, false))
+            .transpose()
+        {
+            Ok(settings) => settings,
+            Err(e) => return Response::error(req_id, error_codes::INVALID_PARAMS, e),
+        };
+
+        let chat_id = Uuid::new_v4().to_string();
+        let thread_id = chat_id.clone();
+        let turns = match self.roci.thread_import(&thread_id, thread).await {
+            Ok(turns) => turns,
+            Err(e) => return Response::error(req_id, error_codes::INVALID_PARAMS, e),
+        };
+        self.thread_ids.insert(chat_id.clone(), thread_id.clone());
+        let rec = ChatRecord {
+            chat_id: chat_id.clone(),
+            thread_id: thread_id.clone(),
+            created_at: chrono_now(),
+            status: SessionStatus::Active,
+            event_pointer: 0,
+            settings,
+            owner: self.principal.clone(),
+        };
+        if let Err(e) = self.store.upsert_chat(&rec) {
+            self.thread_ids.remove(&chat_id);
+            self.discard_thread(&thread_id).await;
+            return Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("failed to persist imported chat: {e}"),
+            );
+        }
+        self.emit_chat_list_upsert(&rec);
+        tracing::info!(%chat_id, turns, "chat imported");
+        Response::success(
+            req_id,
+            json!({ "chat_id": chat_id, "thread_id": thread_id, "turns": turns }),
+        )
+    }
+
+    /// Re-run a roci turn: the turn and every later one are dropped from the
+    /// thread, then its user message (or `new_message`) is sent again with
+    /// the chat's stored settings, starting a fresh turn.
+    pub(super) async fn chat_message_regenerate(
+        &mut self,
+        req_id: Uuid,
+        params: Option<Value>,
+    ) -> Response {
+        if !self.use_roci() {
+            return Response::error(
+                req_id,
+                error_codes::METHOD_NOT_FOUND,
+                "chat.message.regenerate is only available for the roci backend",
+            );
+        }
+        let Some(RegenerateParams {
+            chat_id,
+            turn_id,
+            new_message,
+        }) = parse_regenerate_params(&params)
+        else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                "missing chat_id or turn_id",
+            );
+        };
+        if self.roci.run_freeze().is_frozen() {
+            return Response::error(
+                req_id,
+                error_codes::FROZEN,
+                self.roci.run_freeze().refusal_message(),
+            );
+        }
+        let Some(thread_id) = self.resolve_thread_id(&chat_id, None) else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("unknown chat: {chat_id}"),
+            );
+        };
+
+        let chat_settings = self
+            .store
+            .get_chat(&chat_id)
+            .ok()
+            .flatten()
+            .and_then(|rec| rec.settings);
+        if let Err(err) = self.check_run_model(chat_model(chat_settings.as_ref())) {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, err);
+        }
+        // Refuse before the turn is dropped; the send below warns.
+        if let Some(refusal) = self.token_budget_refusal(req_id, &chat_id, false) {
+            return refusal;
+        }
+
+        let original = match self.roci.turn_message(&thread_id, &turn_id).await {
+            Ok(message) => message,
+            Err(e) => return Response::error(req_id, error_codes::INVALID_PARAMS, e),
+        };
+        let message = new_message
+            .filter(|m| !m.trim().is_empty())
+            .unwrap_or(original);
+        let mut send = json!({ "chat_id": chat_id, "message": message });
+        if let Some(Value::Object(settings)) = chat_settings {
+            for key in ["model", "effort", "approval_policy", "collaboration_mode"] {
+                if let Some(value) = settings.get(key) {
+                    send[key] = value.clone();
+                }
+            }
+        }
+        // The turn is dropped only once the new run starts in its place.
+        self.send_message(req_id, Some(send), Some(&turn_id)).await
+    }
+
+    /// Store `title` in the chat settings and tell clients: a
+    /// `chat.list.updated` upsert plus `chat.thread.renamed`.
+    fn persist_chat_title(
+        &self,
+        chat_id: &str,
+        thread_id: &str,
+        title: &str,
+    ) -> Result<(), String> {
+        let existing = self
+            .store
+            .get_chat(chat_id)?
+            .ok_or_else(|| format!("unknown chat: {chat_id}"))?
+            .settings;
+        let merged = merge_settings(existing, json!({ "title": title }));
+        self.store.update_chat_settings(chat_id, Some(&merged))?;
+        if let Ok(Some(rec)) = self.store.get_chat(chat_id) {
+            self.emit_chat_list_upsert(&rec);
+        }
+        self.emit_event(
+            CHAT_THREAD_RENAMED_TOPIC,
+            json!({ "chat_id": chat_id, "thread_id": thread_id, "title": title }),
+        );
+        Ok(())
+    }
+
+    /// Replay persisted provider events for a chat so a reconnecting client
+    /// can catch up. `pointer` is the chat event pointer the client last saw
+    /// and `cursor` the last `seq` it received; the response carries both for
+    /// the next call. Delivery is at-least-once, so clients dedupe on `seq`.
+    /// `reset_required` means events after `pointer` were pruned and the
+    /// client must reload the thread instead of replaying.
+    pub(super) fn chat_events_since(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        if !self.homie_config.raw_events_enabled() {
+            return Response::error(
+                req_id,
+                error_codes::METHOD_NOT_FOUND,
+                "chat.events.since requires debug.persist_raw_provider_events",
+            );
+        }
+        let params = match parse_events_since_params(&params) {
+            Some(p) => p,
+            None => return Response::error(req_id, error_codes::INVALID_PARAMS, "missing chat_id"),
+        };
+        let thread_id = match self.resolve_thread_id(&params.chat_id, None) {
+            Some(id) => id,
+            None => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    format!("unknown chat: {}", params.chat_id),
+                )
+            }
+        };
+        let lookup = self.store.get_chat(&params.chat_id).and_then(|chat| {
+            let oldest = self.store.oldest_chat_raw_event_pointer(&thread_id)?;
+            let records = self.store.list_chat_raw_events_since(
+                &thread_id,
+                params.pointer,
+                params.cursor,
+                params.limit + 1,
+            )?;
+            Ok((chat.map_or(0, |c| c.event_pointer), oldest, records))
+        });
+        let (current, oldest, records) = match lookup {
+            Ok(found) => found,
+            Err(e) => {
+                return Response::error(
+                    req_id,
+                    error_codes::INTERNAL_ERROR,
+                    format!("events since failed: {e}"),
+                )
+            }
+        };
+        // Each stored event carries the pointer it was recorded at, so an
+        // oldest retained pointer past the client's means the rows it still
+        // needs were pruned.
+        let reset_required =
+            params.pointer < current && oldest.is_none_or(|oldest| oldest > params.pointer);
+        let has_more = records.len() > params.limit;
+        let mut cursor = params.cursor;
+        let mut events = Vec::new();
+        for record in records.into_iter().take(params.limit) {
+            cursor = record.seq;
+            // Same topic the live forwarder uses; methods it never forwards
+            // still advance the cursor.
+            let Some((topic, _)) = codex_method_to_topics(&record.method) else {
+                continue;
+            };
+            events.push(json!({
+                "seq": record.seq,
+                "topic": topic,
+                "params": record.params,
+            }));
+        }
+        let pointer = if has_more {
+            params.pointer
+        } else {
+            params.pointer.max(current)
+        };
+        Response::success(
+            req_id,
+            json!({
+                "chat_id": params.chat_id,
+                "thread_id": thread_id,
+                "events": events,
+                "pointer": pointer,
+                "cursor": cursor,
+                "has_more": has_more,
+                "reset_required": reset_required,
+            }),
+        )
+    }
+
+    pub(super) fn chat_tools_audit(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let Some((chat_id, limit)) = parse_tools_audit_params(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing chat_id");
+        };
+        let Some(thread_id) = self.resolve_thread_id(&chat_id, None) else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("unknown chat: {chat_id}"),
+            );
+        };
+        match self.store.list_tool_invocations(&thread_id, limit) {
+            Ok(invocations) => Response::success(
+                req_id,
+                json!({
+                    "chat_id": chat_id,
+                    "thread_id": thread_id,
+                    "invocations": invocations,
+                }),
+            ),
+            Err(e) => Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("tool audit failed: {e}"),
+            ),
+        }
+    }
+
+    /// `BUDGET_EXCEEDED` for a run on `chat_id` when this connection's token
+    /// budget is spent and blocks. A spent budget in warn mode instead emits
+    /// `chat.budget.warning` when `warn` is set.
+    fn token_budget_refusal(&self, req_id: Uuid, chat_id: &str, warn: bool) -> Option<Response> {
+        let budget = self.spent_token_budget(chat_id)?;
+        match self.homie_config.chat.token_budget.mode {
+            TokenBudgetMode::Block => {
+                let message = format!(
+                    "token budget exceeded: {} of {} tokens used in the last {}h",
+                    budget["used"], budget["limit"], budget["window_hours"]
+                );
+                Some(Response::error_with_data(
+                    req_id,
+                    error_codes::BUDGET_EXCEEDED,
+                    message,
+                    budget,
+                ))
+            }
+            TokenBudgetMode::Warn => {
+                if warn {
+                    let _ = self.outbound_tx.try_send(OutboundMessage::event(
+                        CHAT_BUDGET_WARNING_TOPIC,
+                        Some(budget),
+                    ));
+                }
+                None
+            }
+        }
+    }
+
+    /// Usage against `chat.token_budget` for a message to `chat_id`, when a
+    /// budget applies to this connection's role and is spent.
+    fn spent_token_budget(&self, chat_id: &str) -> Option<Value> {
+        let budget = &self.homie_config.chat.token_budget;
+        let limit = budget.limit_for(self.role);
+        if limit == 0 {
+            return None;
+        }
+        let query = UsageQuery {
+            chat_id: (budget.scope == TokenBudgetScope::Chat).then(|| chat_id.to_string()),
+            identity: self.principal.clone(),
+            from: unix_now().saturating_sub(budget.window_secs()),
+            to: u64::MAX,
+            group_by: UsageGroupBy::Day,
+        };
+        let used: u64 = match self.store.usage_summary(&query) {
+            Ok(buckets) => buckets.iter().map(|bucket| bucket.total).sum(),
+            Err(e) => {
+                tracing::warn!(%chat_id, "token budget check failed: {e}");
+                return None;
+            }
+        };
+        (used >= limit).then(|| {
+            json!({
+                "chat_id": chat_id,
+                "scope": budget.scope.label(),
+                "limit": limit,
+                "used": used,
+                "remaining": limit.saturating_sub(used),
+                "window_hours": budget.window_secs() / 3600,
+            })
+        })
+    }
+
+    /// Whether this connection may read `chat_id`'s data: chats without a
+    /// recorded owner are shared, the rest belong to their owner's identity.
+    /// Unknown chats are refused.
+    pub(super) fn can_access_chat(&self, chat_id: &str) -> bool {
+        match self.store.get_chat(chat_id) {
+            Ok(Some(chat)) => chat.owner.is_none() || chat.owner == self.principal,
+            _ => false,
+        }
+    }
+
+    /// Whether the chat `thread_id` belongs to is open to this connection.
+    fn can_access_thread(&self, thread_id: &str) -> bool {
+        match self.store.get_chat_by_thread(thread_id) {
+            Ok(Some(chat)) => self.can_access_chat(&chat.chat_id),
+            _ => false,
+        }
+    }
+
+    /// Token usage summed per day or model, for one of the caller's chats or
+    /// every chat of the caller's identity.
+    pub(super) fn chat_usage_summary(&self, req_id: Uuid, params: Option<Value>) -> Response {
+        let Some(mut query) = parse_usage_summary_params(&params) else {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                "group_by must be day or model, and from must not be after to",
+            );
+        };
+        if let Some(chat_id) = query.chat_id.as_deref() {
+            if !self.can_access_chat(chat_id) {
+                return Response::error(
+                    req_id,
+                    error_codes::UNAUTHORIZED,
+                    format!("chat {chat_id} belongs to another identity"),
+                );
+            }
+        }
+        query.identity = self.principal.clone();
+        match self.store.usage_summary(&query) {
+            Ok(buckets) => {
+                let input: u64 = buckets.iter().map(|bucket| bucket.input).sum();
+                let output: u64 = buckets.iter().map(|bucket| bucket.output).sum();
+                let total: u64 = buckets.iter().map(|bucket| bucket.total).sum();
+                Response::success(
+                    req_id,
+                    json!({
+                        "buckets": buckets,
+                        "grand_total": { "input": input, "output": output, "total": total },
+                    }),
+                )
+            }
+            Err(e) => Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("usage summary failed: {e}"),
+            ),
+        }
+    }
+
+    pub(super) fn chat_attachment_read(&self, req_id: Uuid, params: Option<Value>) -> Response {
+        let Some(attachment_id) = parse_attachment_id(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing attachment_id");
+        };
+        match self.store.get_attachment(&attachment_id) {
+            Ok(Some(attachment)) if !self.can_access_thread(&attachment.thread_id) => {
+                Response::error(
+                    req_id,
+                    error_codes::UNAUTHORIZED,
+                    format!("attachment {attachment_id} belongs to another identity"),
+                )
+            }
+            Ok(Some(attachment)) => Response::success(
+                req_id,
+                json!({
+                    "attachment_id": attachment.attachment_id,
+                    "thread_id": attachment.thread_id,
+                    "mime_type": attachment.mime_type,
+                    "size": attachment.data.len(),
+                    "data": BASE64.encode(&attachment.data),
+                    "created_at": attachment.created_at,
+                }),
+            ),
+            Ok(None) => Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("unknown attachment: {attachment_id}"),
+            ),
+            Err(e) => Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("attachment read failed: {e}"),
+            ),
+        }
+    }
+
+    pub(super) fn chat_search(&self, req_id: Uuid, params: Option<Value>) -> Response {
+        let Some((query, limit)) = parse_chat_search_params(&params) else {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, "missing query");
+        };
+        match self
+            .store
+            .search_chats(&query, self.principal.as_deref(), limit)
+        {
+            Ok(results) => Response::success(req_id, json!({ "results": results })),
+            Err(e) => Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("chat search failed: {e}"),
+            ),
+        }
+    }
+
+    pub(super) fn chat_list(&self, req_id: Uuid) -> Response {
+        match self.store.list_chats() {
+            Ok(records) => {
+                let chats: Vec<Value> = records.iter().map(chat_list_entry).collect();
+                Response::success(req_id, json!({ "chats": chats }))
+            }
+            Err(e) => Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("list failed: {e}"),
+            ),
+        }
+    }
+
+    pub(super) fn emit_chat_list_upsert(&self, rec: &ChatRecord) {
+        self.emit_chat_list_update(json!({ "op": "upsert", "chat": chat_list_entry(rec) }));
+    }
+
+    fn emit_chat_list_remove(&self, chat_id: &str) {
+        self.emit_chat_list_update(json!({ "op": "remove", "chat_id": chat_id }));
+    }
+
+    /// Publish one `chat.list.updated` delta.
+    fn emit_chat_list_update(&self, delta: Value) {
+        self.emit_event(CHAT_LIST_UPDATED_TOPIC, delta);
+    }
+
+    /// Broadcast an event to every connection when the server bus is wired,
+    /// else only to this connection.
+    fn emit_event(&self, topic: &str, params: Value) {
+        match self.list_events.as_ref() {
+            Some(tx) => {
+                let _ = tx.send(ReapEvent::new(topic, Some(params)));
+            }
+            None => {
+                let _ = self
+                    .outbound_tx
+                    .try_send(OutboundMessage::event(topic, Some(params)));
+            }
+        }
+    }
+}
+
+/// Topic carrying incremental sidebar deltas (`op`: `upsert` | `remove`).
+pub(crate) const CHAT_LIST_UPDATED_TOPIC: &str = "chat.list.updated";
+
+/// Topic telling the sender a message went over a warn-mode token budget.
+pub(crate) const CHAT_BUDGET_WARNING_TOPIC: &str = "chat.budget.warning";
+
+/// Topic announcing a chat's new title.
+pub(crate) const CHAT_THREAD_RENAMED_TOPIC: &str = "chat.thread.renamed";
+
+/// Topic announcing that a chat was pinned or unpinned.
+pub(crate) const CHAT_THREAD_PINNED_TOPIC: &str = "chat.thread.pinned";
+
+/// One `chat.list` entry; `chat.list.updated` upserts use the same shape.
+fn chat_list_entry(r: &ChatRecord) -> Value {
+    json!({
+        "chat_id": r.chat_id,
+        "thread_id": r.thread_id,
+        "created_at": r.created_at,
+        "status": r.status,
+        "event_pointer": r.event_pointer,
+        "title": chat_title(r.settings.as_ref()),
+        "pinned": chat_pinned(r.settings.as_ref()),
+        "settings": r.settings,
+    })
+}
+
+/// A Codex response's result, or its JSON-RPC error message. The process
+/// hands back the whole response object when it carries no `result`.
+fn codex_result(response: Value) -> Result<Value, String> {
+    match response.get("error") {
+        Some(error) if error.get("code").is_some() => Err(error
+            .get("message")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string())),
+        _ => Ok(response),
     }
 }
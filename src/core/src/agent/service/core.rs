@@ -1,21 +1,29 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 use crate::agent::process::{CodexEvent, CodexProcess};
-use crate::agent::roci_backend::{ChatBackend, RociBackend};
-use tokio::sync::mpsc;
+use crate::agent::roci_backend::{default_roci_model, ChatBackend, RociBackend};
+use serde_json::json;
+use tokio::sync::{broadcast, mpsc, Mutex};
 
+use crate::admin::RunFreeze;
+use crate::authz::Role;
 use crate::outbound::OutboundMessage;
-use crate::router::ReapEvent;
+use crate::router::{ConnectionContext, ReapEvent};
 use crate::storage::{SessionStatus, Store};
 use crate::{ExecPolicy, HomieConfig};
 
-use super::events::event_forwarder_loop;
+use super::events::{event_forwarder_loop, EventForwarder};
+use super::idle::{idle_check_interval, CodexActivity};
+use super::models::codex_model;
+use super::params::chat_model;
+use super::uploads::FileUploads;
 
 /// Chat core: bridges the Codex app-server to the Homie WS protocol.
 ///
 /// Each WS connection gets its own core. A `CodexProcess` is started lazily
-/// on the first chat request and killed on shutdown.
+/// on the first chat request and killed on shutdown, or once it has sat
+/// idle for `chat.codex_idle_secs`.
 ///
 /// # Example interaction
 ///
@@ -38,6 +46,26 @@ pub(super) struct CodexChatCore {
     pub(super) exec_policy: Arc<ExecPolicy>,
     pub(super) tool_channel: Option<String>,
     pub(super) roci: RociBackend,
+    /// Server-wide event bus for `chat.list.updated` and
+    /// `chat.thread.renamed`/`chat.thread.pinned`, so every connection's
+    /// sidebar sees changes. Without it updates stay on this connection.
+    pub(super) list_events: Option<broadcast::Sender<ReapEvent>>,
+    pub(super) turn_cancel_listener: Option<tokio::task::JoinHandle<()>>,
+    /// `chat.file.upload.*` state, shared with the service's binary frame
+    /// handler.
+    pub(super) uploads: FileUploads,
+    /// Role of the connection this core serves, for the model allowlist.
+    /// Starts least-privileged until the connection context is applied.
+    pub(super) role: Role,
+    /// Who the connection's actions are attributed to; `{{identity}}` in
+    /// system prompts.
+    pub(super) principal: Option<String>,
+    /// Codex use and running turns, for the idle unload.
+    pub(super) codex_activity: CodexActivity,
+    pub(super) codex_idle_watcher: Option<tokio::task::JoinHandle<()>>,
+    /// `(chat_id, thread_id)` pairs loaded in a Codex process that was
+    /// unloaded while idle; the next process resumes them.
+    pub(super) codex_resume: Vec<(String, String)>,
 }
 
 impl CodexChatCore {
@@ -45,12 +73,53 @@ impl CodexChatCore {
         matches!(self.backend, ChatBackend::Roci)
     }
 
+    /// Whether this connection's role may use the normalized `model`. Bare
+    /// ids are matched as the provider the backend would run them on:
+    /// `openai:` for roci, `openai-codex:` for the Codex app-server.
+    pub(super) fn model_allowed(&self, model: &str) -> bool {
+        let models = &self.homie_config.models;
+        if model.contains(':') {
+            return models.allows(self.role, model);
+        }
+        let provider = if self.use_roci() {
+            "openai"
+        } else {
+            "openai-codex"
+        };
+        models.allows(self.role, &format!("{provider}:{model}"))
+    }
+
+    /// Take the role and principal of the connection this core serves.
+    /// Without a context the core stays least-privileged.
+    pub(super) fn apply_context(&mut self, ctx: Option<&ConnectionContext>) {
+        if let Some(ctx) = ctx {
+            self.role = ctx.role;
+            self.principal = ctx.principal.clone();
+        }
+    }
+
+    /// Allowlist check for the model a run will use: `model` when the
+    /// message or chat names one, else the backend's default.
+    pub(super) fn check_run_model(&self, model: Option<&str>) -> Result<(), String> {
+        let model = match model.map(str::trim).filter(|model| !model.is_empty()) {
+            Some(model) => model.to_string(),
+            None if self.use_roci() => default_roci_model(),
+            None => codex_model(),
+        };
+        if self.model_allowed(&model) {
+            Ok(())
+        } else {
+            Err(format!("model not allowed: {model}"))
+        }
+    }
+
     pub(super) fn new(
         outbound_tx: mpsc::Sender<OutboundMessage>,
         store: Arc<dyn Store>,
         homie_config: Arc<HomieConfig>,
         exec_policy: Arc<ExecPolicy>,
         tool_channel: Option<String>,
+        run_freeze: RunFreeze,
     ) -> Self {
         let backend = ChatBackend::from_env();
         let roci = RociBackend::new(
@@ -59,7 +128,8 @@ impl CodexChatCore {
             exec_policy.clone(),
             homie_config.clone(),
             tool_channel.clone(),
-        );
+        )
+        .with_run_freeze(run_freeze);
         Self {
             backend,
             outbound_tx,
@@ -72,17 +142,116 @@ impl CodexChatCore {
             exec_policy,
             tool_channel,
             roci,
+            list_events: None,
+            turn_cancel_listener: None,
+            uploads: FileUploads::default(),
+            role: Role::Viewer,
+            principal: None,
+            codex_activity: CodexActivity::default(),
+            codex_idle_watcher: None,
+            codex_resume: Vec::new(),
         }
     }
 
+    /// Route `jobs.cancel` turn aborts from any connection into this core's
+    /// cancel path. `core` is the `Arc` holding `self`, locked by the caller,
+    /// so the listener cannot reach the core before its handle is stored.
+    /// The listener is aborted on shutdown.
+    pub(super) fn spawn_turn_cancel_listener(
+        &mut self,
+        core: Weak<Mutex<Self>>,
+        run_freeze: &RunFreeze,
+    ) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let mut cancel_rx = run_freeze.turn_cancel_signal();
+        let listener = handle.spawn(async move {
+            loop {
+                let cancel = match cancel_rx.recv().await {
+                    Ok(cancel) => cancel,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Some(core) = core.upgrade() else {
+                    break;
+                };
+                let mut core = core.lock().await;
+                if core
+                    .cancel_turn_for_job(&cancel.chat_id, &cancel.turn_id)
+                    .await
+                {
+                    cancel.acknowledge();
+                    tracing::info!(
+                        chat_id = %cancel.chat_id,
+                        turn_id = %cancel.turn_id,
+                        "turn cancelled by job"
+                    );
+                }
+            }
+        });
+        self.turn_cancel_listener = Some(listener);
+    }
+
+    /// Unload Codex once it has been idle for `chat.codex_idle_secs`. Like
+    /// the cancel listener, `core` is the locked `Arc` holding `self`. The
+    /// watcher is aborted on shutdown.
+    pub(super) fn spawn_codex_idle_watcher(&mut self, core: Weak<Mutex<Self>>) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let Some(timeout) = self.homie_config.chat.codex_idle_timeout() else {
+            return;
+        };
+        let watcher = handle.spawn(async move {
+            let mut ticks = tokio::time::interval(idle_check_interval(timeout));
+            loop {
+                ticks.tick().await;
+                let Some(core) = core.upgrade() else {
+                    break;
+                };
+                core.lock().await.unload_idle_codex(timeout);
+            }
+        });
+        self.codex_idle_watcher = Some(watcher);
+    }
+
+    /// Stop the Codex process if it has gone unused for `timeout` with no
+    /// turn running. Unlike `shutdown`, chats stay active and the threads
+    /// it had loaded are resumed when the next request spawns it again.
+    pub(super) fn unload_idle_codex(&mut self, timeout: std::time::Duration) -> bool {
+        if self.process.is_none() || !self.codex_activity.is_idle(timeout) {
+            return false;
+        }
+        if let Some(h) = self.event_forwarder.take() {
+            h.abort();
+        }
+        if let Some(mut p) = self.process.take() {
+            p.shutdown();
+        }
+        for (chat_id, thread_id) in &self.thread_ids {
+            let loaded = (chat_id.clone(), thread_id.clone());
+            if !self.codex_resume.contains(&loaded) {
+                self.codex_resume.push(loaded);
+            }
+        }
+        tracing::info!(
+            threads = self.codex_resume.len(),
+            idle_secs = timeout.as_secs(),
+            "unloaded idle codex process"
+        );
+        true
+    }
+
     /// Ensure the Codex process is running; spawn + initialize if needed.
     pub(super) async fn ensure_process(&mut self) -> Result<(), String> {
+        self.codex_activity.touch();
         if self.process.is_some() {
             return Ok(());
         }
 
         let (process, event_rx): (CodexProcess, mpsc::Receiver<CodexEvent>) =
-            CodexProcess::spawn().await?;
+            CodexProcess::spawn(&self.homie_config.env).await?;
         process.initialize().await?;
 
         let outbound = self.outbound_tx.clone();
@@ -90,19 +259,48 @@ impl CodexChatCore {
         let exec_policy = self.exec_policy.clone();
         let homie_config = self.homie_config.clone();
         let response_sender = process.response_sender();
+        self.codex_activity.reset();
         let forwarder = tokio::spawn(event_forwarder_loop(
             event_rx,
-            outbound,
-            store,
-            response_sender,
-            exec_policy,
-            homie_config,
+            EventForwarder {
+                outbound_tx: outbound,
+                store,
+                response_sender,
+                exec_policy,
+                homie_config,
+                identity: self.principal.clone(),
+                activity: self.codex_activity.clone(),
+            },
         ));
         self.event_forwarder = Some(forwarder);
         self.process = Some(process);
+        self.resume_unloaded_threads().await;
         Ok(())
     }
 
+    /// Load the threads an idle unload dropped into the new process, so
+    /// their chats carry on where they left off.
+    async fn resume_unloaded_threads(&mut self) {
+        let Some(process) = self.process.as_ref() else {
+            return;
+        };
+        for (chat_id, thread_id) in std::mem::take(&mut self.codex_resume) {
+            let mut params = json!({ "threadId": thread_id });
+            let settings = self
+                .store
+                .get_chat(&chat_id)
+                .ok()
+                .flatten()
+                .and_then(|rec| rec.settings);
+            if let Some(model) = chat_model(settings.as_ref()) {
+                params["model"] = json!(model);
+            }
+            if let Err(e) = process.send_request("thread/resume", Some(params)).await {
+                tracing::warn!(%chat_id, %thread_id, "failed to resume thread after idle unload: {e}");
+            }
+        }
+    }
+
     pub(super) fn reap(&mut self) -> Vec<ReapEvent> {
         std::mem::take(&mut self.reap_events)
     }
@@ -117,16 +315,25 @@ impl CodexChatCore {
                 rec.status = SessionStatus::Inactive;
                 if let Err(e) = self.store.upsert_chat(&rec) {
                     tracing::warn!(%chat_id, "failed to persist chat disconnect: {e}");
+                    continue;
                 }
+                self.emit_chat_list_upsert(&rec);
             }
         }
 
         if let Some(h) = self.event_forwarder.take() {
             h.abort();
         }
+        if let Some(h) = self.turn_cancel_listener.take() {
+            h.abort();
+        }
+        if let Some(h) = self.codex_idle_watcher.take() {
+            h.abort();
+        }
         if let Some(mut p) = self.process.take() {
             p.shutdown();
         }
+        self.uploads.clear();
         if self.use_roci() {
             let roci = self.roci.clone();
             if let Ok(handle) = tokio::runtime::Handle::try_current() {
@@ -1,24 +1,34 @@
 use std::pin::Pin;
 use std::sync::Arc;
 
-use homie_protocol::{error_codes, Response};
+use homie_protocol::{error_codes, Response, StreamType};
 use serde_json::Value;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{broadcast, mpsc, Mutex};
 use uuid::Uuid;
 
+use crate::admin::RunFreeze;
+use crate::agent::RunSlots;
+use crate::authz::{AuthContext, Scope};
 use crate::outbound::OutboundMessage;
-use crate::router::{ReapEvent, ServiceHandler};
+use crate::router::{ConnectionContext, ReapEvent, ServiceHandler};
+use crate::shutdown::ShutdownSignal;
 use crate::storage::Store;
 use crate::{ExecPolicy, HomieConfig};
 
 use super::core::CodexChatCore;
+use super::uploads::FileUploads;
 
 pub struct ChatService {
-    core: Arc<Mutex<CodexChatCore>>,
+    pub(super) core: Arc<Mutex<CodexChatCore>>,
+    uploads: FileUploads,
+    /// Connection the service is attached to; its role and principal are
+    /// applied to the core under the lock each request takes.
+    context: Option<Arc<ConnectionContext>>,
 }
 
 pub struct AgentService {
     core: Arc<Mutex<CodexChatCore>>,
+    context: Option<Arc<ConnectionContext>>,
 }
 
 impl ChatService {
@@ -39,14 +49,18 @@ impl ChatService {
         exec_policy: Arc<ExecPolicy>,
         tool_channel: Option<String>,
     ) -> Self {
+        let core = CodexChatCore::new(
+            outbound_tx,
+            store,
+            homie_config,
+            exec_policy,
+            tool_channel,
+            RunFreeze::new(),
+        );
         Self {
-            core: Arc::new(Mutex::new(CodexChatCore::new(
-                outbound_tx,
-                store,
-                homie_config,
-                exec_policy,
-                tool_channel,
-            ))),
+            uploads: core.uploads.clone(),
+            core: Arc::new(Mutex::new(core)),
+            context: None,
         }
     }
 
@@ -56,7 +70,14 @@ impl ChatService {
         homie_config: Arc<HomieConfig>,
         exec_policy: Arc<ExecPolicy>,
     ) -> (Self, AgentService) {
-        Self::new_shared_with_channel(outbound_tx, store, homie_config, exec_policy, None)
+        Self::new_shared_with_channel(
+            outbound_tx,
+            store,
+            homie_config,
+            exec_policy,
+            None,
+            RunFreeze::new(),
+        )
     }
 
     pub fn new_shared_with_channel(
@@ -65,15 +86,72 @@ impl ChatService {
         homie_config: Arc<HomieConfig>,
         exec_policy: Arc<ExecPolicy>,
         tool_channel: Option<String>,
+        run_freeze: RunFreeze,
     ) -> (Self, AgentService) {
-        let core = Arc::new(Mutex::new(CodexChatCore::new(
+        let core = CodexChatCore::new(
             outbound_tx,
             store,
             homie_config,
             exec_policy,
             tool_channel,
-        )));
-        (Self { core: core.clone() }, AgentService { core })
+            run_freeze.clone(),
+        );
+        let uploads = core.uploads.clone();
+        let core = Arc::new(Mutex::new(core));
+        {
+            // Nothing else holds the core yet, so the lock is free. Holding
+            // it keeps the new tasks off the core until their handles are in.
+            let mut locked = core.try_lock().expect("new chat core is unshared");
+            locked.spawn_turn_cancel_listener(Arc::downgrade(&core), &run_freeze);
+            locked.spawn_codex_idle_watcher(Arc::downgrade(&core));
+        }
+        (
+            Self {
+                core: core.clone(),
+                uploads,
+                context: None,
+            },
+            AgentService {
+                core,
+                context: None,
+            },
+        )
+    }
+
+    /// Publish `chat.list.updated` deltas, `chat.thread.renamed` and
+    /// `chat.thread.pinned` on the server-wide event bus instead of only this
+    /// connection's outbound queue.
+    pub fn with_list_events(self, event_tx: broadcast::Sender<ReapEvent>) -> Self {
+        if let Ok(mut core) = self.core.try_lock() {
+            core.list_events = Some(event_tx);
+        }
+        self
+    }
+
+    /// Share the process-wide concurrent run cap with this connection's
+    /// runs.
+    pub fn with_run_slots(self, run_slots: RunSlots) -> Self {
+        if let Ok(mut core) = self.core.try_lock() {
+            core.roci = core.roci.clone().with_run_slots(run_slots);
+        }
+        self
+    }
+
+    /// Persist in-flight runs' partial replies when `shutdown` fires.
+    pub fn with_shutdown(self, shutdown: ShutdownSignal) -> Self {
+        if let Ok(mut core) = self.core.try_lock() {
+            core.roci = core.roci.clone().with_shutdown(shutdown);
+        }
+        self
+    }
+
+    /// Record `identity` as the approver of tool calls this connection's
+    /// client approves.
+    pub fn with_identity(self, identity: Option<String>) -> Self {
+        if let Ok(mut core) = self.core.try_lock() {
+            core.roci = core.roci.clone().with_identity(identity);
+        }
+        self
     }
 
     fn shutdown_core(&mut self) {
@@ -121,7 +199,9 @@ impl AgentService {
                 homie_config,
                 exec_policy,
                 tool_channel,
+                RunFreeze::new(),
             ))),
+            context: None,
         }
     }
 
@@ -145,11 +225,76 @@ impl AgentService {
     }
 }
 
+impl ChatService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("chat.list", Scope::AgentRead),
+        ("chat.search", Scope::AgentRead),
+        ("chat.thread.read", Scope::AgentRead),
+        ("chat.events.since", Scope::AgentRead),
+        ("chat.tools.audit", Scope::AgentRead),
+        ("chat.usage.summary", Scope::AgentRead),
+        ("chat.attachment.read", Scope::AgentRead),
+        ("chat.tools.list", Scope::AgentRead),
+        ("chat.tools.describe", Scope::AgentRead),
+        ("chat.thread.list", Scope::AgentRead),
+        ("chat.account.read", Scope::AgentRead),
+        ("chat.account.list", Scope::AgentRead),
+        ("chat.skills.list", Scope::AgentRead),
+        ("chat.model.list", Scope::AgentRead),
+        ("chat.collaboration.mode.list", Scope::AgentRead),
+        ("chat.compaction.preview", Scope::AgentRead),
+        ("chat.files.search", Scope::AgentRead),
+        ("chat.process.list", Scope::AgentRead),
+        ("chat.approvals.pending", Scope::AgentRead),
+        ("chat.create", Scope::AgentWrite),
+        ("chat.resume", Scope::AgentWrite),
+        ("chat.message.send", Scope::AgentWrite),
+        ("chat.message.regenerate", Scope::AgentWrite),
+        ("chat.cancel", Scope::AgentWrite),
+        ("chat.approval.respond", Scope::AgentWrite),
+        ("chat.thread.archive", Scope::AgentWrite),
+        ("chat.thread.rename", Scope::AgentWrite),
+        ("chat.thread.pin", Scope::AgentWrite),
+        ("chat.thread.unpin", Scope::AgentWrite),
+        ("chat.thread.fork", Scope::AgentWrite),
+        ("chat.import", Scope::AgentWrite),
+        ("chat.file.upload.begin", Scope::AgentWrite),
+        ("chat.file.upload.commit", Scope::AgentWrite),
+        ("chat.settings.update", Scope::AgentWrite),
+        ("chat.skills.config.write", Scope::AgentWrite),
+        ("chat.tools.invoke", Scope::AgentWrite),
+        ("chat.process.kill", Scope::AgentWrite),
+        ("chat.account.login.start", Scope::AgentWrite),
+        ("chat.account.login.poll", Scope::AgentWrite),
+        ("chat.account.logout", Scope::AgentWrite),
+        ("chat.tools.register", Scope::Admin),
+    ];
+}
+
 impl ServiceHandler for ChatService {
     fn namespace(&self) -> &str {
         "chat"
     }
 
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
+    fn attach(&mut self, ctx: Arc<ConnectionContext>) {
+        // Runs queued before a restart go back in line once a connection
+        // that may send to their chats shows up.
+        if AuthContext::new(ctx.role).allows(Scope::AgentWrite) {
+            let core = self.core.clone();
+            let restore_ctx = ctx.clone();
+            tokio::spawn(async move {
+                let mut core = core.lock().await;
+                core.apply_context(Some(&restore_ctx));
+                core.restore_released_runs().await;
+            });
+        }
+        self.context = Some(ctx);
+    }
+
     fn handle_request(
         &mut self,
         id: Uuid,
@@ -159,26 +304,47 @@ impl ServiceHandler for ChatService {
         let method = method.to_string();
         Box::pin(async move {
             let mut core = self.core.lock().await;
+            core.apply_context(self.context.as_deref());
             match method.as_str() {
-                "chat.create" => core.chat_create(id).await,
+                "chat.create" => core.chat_create(id, params).await,
                 "chat.resume" => core.chat_resume(id, params).await,
                 "chat.message.send" => core.chat_message_send(id, params).await,
+                "chat.message.regenerate" => core.chat_message_regenerate(id, params).await,
                 "chat.cancel" => core.chat_cancel(id, params).await,
                 "chat.approval.respond" => core.approval_respond(id, params).await,
+                "chat.approvals.pending" => core.chat_approvals_pending(id, params).await,
                 "chat.list" => core.chat_list(id),
+                "chat.search" => core.chat_search(id, params),
                 "chat.thread.read" => core.chat_thread_read(id, params).await,
+                "chat.events.since" => core.chat_events_since(id, params),
                 "chat.thread.list" => core.chat_thread_list(id, params).await,
                 "chat.thread.archive" => core.chat_thread_archive(id, params).await,
                 "chat.thread.rename" => core.chat_thread_rename(id, params).await,
+                "chat.thread.pin" => core.chat_thread_pin(id, params, true),
+                "chat.thread.unpin" => core.chat_thread_pin(id, params, false),
+                "chat.thread.fork" => core.chat_thread_fork(id, params).await,
+                "chat.import" => core.chat_import(id, params).await,
+                "chat.compaction.preview" => core.chat_compaction_preview(id, params).await,
                 "chat.settings.update" => core.chat_settings_update(id, params),
                 "chat.files.search" => core.chat_files_search(id, params),
+                "chat.file.upload.begin" => core.chat_file_upload_begin(id, params).await,
+                "chat.file.upload.commit" => core.chat_file_upload_commit(id, params).await,
                 "chat.account.read" => core.chat_account_read(id).await,
                 "chat.account.list" => core.chat_account_list(id).await,
                 "chat.account.login.start" => core.chat_account_login_start(id, params).await,
                 "chat.account.login.poll" => core.chat_account_login_poll(id, params).await,
+                "chat.account.logout" => core.chat_account_logout(id, params),
                 "chat.skills.list" => core.chat_skills_list(id, params).await,
                 "chat.model.list" => core.chat_model_list(id, params).await,
                 "chat.tools.list" => core.chat_tools_list(id, params).await,
+                "chat.tools.describe" => core.chat_tools_describe(id, params),
+                "chat.tools.invoke" => core.chat_tools_invoke(id, params).await,
+                "chat.tools.register" => core.chat_tools_register(id, params),
+                "chat.tools.audit" => core.chat_tools_audit(id, params),
+                "chat.usage.summary" => core.chat_usage_summary(id, params),
+                "chat.attachment.read" => core.chat_attachment_read(id, params),
+                "chat.process.list" => core.chat_process_list(id).await,
+                "chat.process.kill" => core.chat_process_kill(id, params).await,
                 "chat.collaboration.mode.list" => {
                     core.chat_collaboration_mode_list(id, params).await
                 }
@@ -192,8 +358,12 @@ impl ServiceHandler for ChatService {
         })
     }
 
-    fn handle_binary(&mut self, _frame: &homie_protocol::BinaryFrame) {
-        tracing::debug!("chat service does not handle binary frames");
+    fn handle_binary(&mut self, frame: &homie_protocol::BinaryFrame) {
+        if frame.stream != StreamType::Upload {
+            tracing::debug!(stream = ?frame.stream, "chat service ignores non-upload frame");
+            return;
+        }
+        self.uploads.write_chunk(frame.session_id, &frame.payload);
     }
 
     fn reap(&mut self) -> Vec<ReapEvent> {
@@ -205,11 +375,34 @@ impl ServiceHandler for ChatService {
     }
 }
 
+impl AgentService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("agent.chat.list", Scope::AgentRead),
+        ("agent.codex.list", Scope::AgentRead),
+        ("agent.chat.create", Scope::AgentWrite),
+        ("agent.chat.message.send", Scope::AgentWrite),
+        ("agent.chat.cancel", Scope::AgentWrite),
+        ("agent.chat.approval.respond", Scope::AgentWrite),
+        ("agent.codex.create", Scope::AgentWrite),
+        ("agent.codex.message.send", Scope::AgentWrite),
+        ("agent.codex.cancel", Scope::AgentWrite),
+        ("agent.codex.approval.respond", Scope::AgentWrite),
+    ];
+}
+
 impl ServiceHandler for AgentService {
     fn namespace(&self) -> &str {
         "agent"
     }
 
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
+    fn attach(&mut self, ctx: Arc<ConnectionContext>) {
+        self.context = Some(ctx);
+    }
+
     fn handle_request(
         &mut self,
         id: Uuid,
@@ -228,8 +421,9 @@ impl ServiceHandler for AgentService {
         let canonical = canonical.to_string();
         Box::pin(async move {
             let mut core = self.core.lock().await;
+            core.apply_context(self.context.as_deref());
             match canonical.as_str() {
-                "agent.chat.create" => core.chat_create(id).await,
+                "agent.chat.create" => core.chat_create(id, params).await,
                 "agent.chat.message.send" => core.chat_message_send(id, params).await,
                 "agent.chat.cancel" => core.chat_cancel(id, params).await,
                 "agent.chat.approval.respond" => core.approval_respond(id, params).await,
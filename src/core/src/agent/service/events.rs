@@ -1,12 +1,15 @@
 use crate::outbound::OutboundMessage;
-use crate::storage::Store;
+use crate::storage::{Store, TurnUsageRecord};
 use crate::ExecPolicy;
 use crate::HomieConfig;
-use serde_json::json;
+use serde_json::{json, Value};
 use tokio::sync::mpsc;
 
-use super::params::{extract_thread_id, extract_turn_id};
+use super::idle::CodexActivity;
+use super::models::unix_now;
+use super::params::{chat_model, chat_read_only, extract_thread_id, extract_turn_id};
 use crate::agent::process::{CodexEvent, CodexResponseSender};
+use crate::agent::usage::TokenUsage;
 
 pub(super) fn codex_method_to_topics(method: &str) -> Option<(&'static str, &'static str)> {
     match method {
@@ -37,18 +40,82 @@ pub(super) fn codex_method_to_topics(method: &str) -> Option<(&'static str, &'st
     }
 }
 
+/// A Codex `thread/tokenUsage/updated` report's `tokenUsage.last`, in the
+/// provider-independent shape.
+fn codex_turn_usage(params: &Value) -> Option<TokenUsage> {
+    let last = params.get("tokenUsage")?.get("last")?;
+    Some(TokenUsage::from_codex(last))
+}
+
+/// Add a Codex `thread/tokenUsage/updated` report to its turn's usage,
+/// attributed to `identity`. Each report's `tokenUsage.last` covers one
+/// model response, so a turn's usage is the sum of its reports.
+pub(super) fn record_codex_usage(store: &dyn Store, identity: Option<&str>, params: &Value) {
+    let (Some(thread_id), Some(turn_id)) = (extract_thread_id(params), extract_turn_id(params))
+    else {
+        return;
+    };
+    let Some(last) = codex_turn_usage(params) else {
+        return;
+    };
+    let chat = store.get_chat(&thread_id).ok().flatten();
+    let usage = TurnUsageRecord {
+        chat_id: chat
+            .as_ref()
+            .map_or_else(|| thread_id.clone(), |chat| chat.chat_id.clone()),
+        model: chat
+            .as_ref()
+            .and_then(|chat| chat_model(chat.settings.as_ref()))
+            .map(str::to_string),
+        thread_id,
+        turn_id,
+        identity: identity.map(str::to_string),
+        input: last.input,
+        output: last.output,
+        total: last.total,
+        created_at: unix_now(),
+    };
+    if let Err(e) = store.add_turn_usage(&usage) {
+        tracing::warn!(thread_id = %usage.thread_id, "failed to record token usage: {e}");
+    }
+}
+
+/// What the event forwarder needs besides the Codex event stream.
+pub(super) struct EventForwarder {
+    pub(super) outbound_tx: mpsc::Sender<OutboundMessage>,
+    pub(super) store: std::sync::Arc<dyn Store>,
+    pub(super) response_sender: CodexResponseSender,
+    pub(super) exec_policy: std::sync::Arc<ExecPolicy>,
+    pub(super) homie_config: std::sync::Arc<HomieConfig>,
+    /// Token usage reports are recorded against this identity.
+    pub(super) identity: Option<String>,
+    /// Marked on every event; running turns hold off the idle unload.
+    pub(super) activity: CodexActivity,
+}
+
 /// Background task: reads Codex events and forwards them as Homie Event
 /// messages via the outbound WS channel.
 pub(super) async fn event_forwarder_loop(
     mut event_rx: mpsc::Receiver<CodexEvent>,
-    outbound_tx: mpsc::Sender<OutboundMessage>,
-    store: std::sync::Arc<dyn Store>,
-    response_sender: CodexResponseSender,
-    exec_policy: std::sync::Arc<ExecPolicy>,
-    homie_config: std::sync::Arc<HomieConfig>,
+    forwarder: EventForwarder,
 ) {
+    let EventForwarder {
+        outbound_tx,
+        store,
+        response_sender,
+        exec_policy,
+        homie_config,
+        identity,
+        activity,
+    } = forwarder;
+    let raw_event_runs = homie_config.chat.raw_event_runs();
     while let Some(event) = event_rx.recv().await {
         let raw_params = event.params.unwrap_or(json!({}));
+        match (event.method.as_str(), extract_turn_id(&raw_params)) {
+            ("turn/started", Some(turn_id)) => activity.turn_started(&turn_id),
+            ("turn/completed", Some(turn_id)) => activity.turn_completed(&turn_id),
+            _ => activity.touch(),
+        }
         if homie_config.raw_events_enabled() {
             if let (Some(thread_id), Some(run_id)) =
                 (extract_thread_id(&raw_params), extract_turn_id(&raw_params))
@@ -57,20 +124,28 @@ pub(super) async fn event_forwarder_loop(
                     .insert_chat_raw_event(&run_id, &thread_id, &event.method, &raw_params)
                     .is_ok()
                 {
-                    let _ = store.prune_chat_raw_events(10);
+                    let _ = store.prune_chat_raw_events(raw_event_runs);
                 }
             }
         }
 
+        if event.method == "thread/tokenUsage/updated" {
+            record_codex_usage(store.as_ref(), identity.as_deref(), &raw_params);
+        }
+
         if event.method == "item/commandExecution/requestApproval" {
             if let Some(id) = event.id.clone() {
+                let chat = extract_thread_id(&raw_params)
+                    .and_then(|thread_id| store.get_chat_by_thread(&thread_id).ok().flatten());
+                // Read-only chats always ask the client.
+                let read_only = chat
+                    .as_ref()
+                    .is_some_and(|chat| chat_read_only(chat.settings.as_ref()));
                 if let Some(argv) = super::approvals::approval_command_argv(&raw_params) {
-                    if exec_policy.is_allowed(&argv) {
-                        if let Some(thread_id) = extract_thread_id(&raw_params) {
-                            if let Ok(Some(chat)) = store.get_chat(&thread_id) {
-                                let next = chat.event_pointer.saturating_add(1);
-                                let _ = store.update_event_pointer(&chat.chat_id, next);
-                            }
+                    if !read_only && exec_policy.is_allowed(&argv) {
+                        if let Some(chat) = chat.as_ref() {
+                            let next = chat.event_pointer.saturating_add(1);
+                            let _ = store.update_event_pointer(&chat.chat_id, next);
                         }
                         let result = json!({ "decision": "accept" });
                         if response_sender.send_response(id, result).await.is_ok() {
@@ -91,6 +166,12 @@ pub(super) async fn event_forwarder_loop(
         };
 
         let mut event_params = raw_params;
+        // Same `usage` fields as roci turns report, whatever the provider.
+        if let Some(usage) = codex_turn_usage(&event_params) {
+            if let Some(obj) = event_params.as_object_mut() {
+                obj.insert("usage".into(), json!(usage));
+            }
+        }
 
         if let Some(codex_id) = event.id {
             if let Some(obj) = event_params.as_object_mut() {
@@ -1,25 +1,90 @@
-use std::collections::VecDeque;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use ignore::WalkBuilder;
 use serde_json::{json, Value};
 
+use crate::homie_config::FileSearchConfig;
 use crate::paths::homie_skills_dir;
 
-pub(super) fn extract_attached_folder(settings: Option<&Value>) -> Option<String> {
-    let settings = settings?;
-    let attachments = settings.get("attachments")?;
-    if let Some(folder) = attachments.get("folder").and_then(|v| v.as_str()) {
-        if !folder.trim().is_empty() {
-            return Some(folder.to_string());
+/// How far one file search may go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct FileSearchBounds {
+    /// Results returned at most.
+    pub(super) limit: usize,
+    /// Directory entries read at most, across all roots.
+    pub(super) max_visited: usize,
+    /// Directory levels below each root to descend into; `0` searches only
+    /// the roots' own entries.
+    pub(super) max_depth: Option<usize>,
+}
+
+impl FileSearchBounds {
+    /// Bounds from `config`, with a request's own `limit` and `max_depth`
+    /// taking over where given. A request cannot search deeper than the
+    /// configured `max_depth`.
+    pub(super) fn from_config(
+        config: &FileSearchConfig,
+        limit: Option<usize>,
+        max_depth: Option<usize>,
+    ) -> Self {
+        let max_depth = match (config.max_depth, max_depth) {
+            (Some(configured), Some(requested)) => Some(configured.min(requested)),
+            (configured, requested) => configured.or(requested),
+        };
+        Self {
+            limit: limit.unwrap_or(config.default_limit),
+            max_visited: config.max_visited,
+            max_depth,
+        }
+    }
+}
+
+/// Hits of one file search. `truncated` is set when the search stopped at
+/// its result limit or visit cap, so more matches may exist.
+#[derive(Debug, Default)]
+pub(super) struct FileSearchResult {
+    pub(super) files: Vec<Value>,
+    pub(super) truncated: bool,
+}
+
+/// Folders attached to a chat: `attachments.folder` first, then each entry
+/// of `attachments.folders`, skipping blanks and repeats.
+pub(super) fn extract_attached_folders(settings: Option<&Value>) -> Vec<String> {
+    let Some(attachments) = settings.and_then(|s| s.get("attachments")) else {
+        return Vec::new();
+    };
+    let single = attachments.get("folder").and_then(|v| v.as_str());
+    let many = attachments
+        .get("folders")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str());
+    let mut folders: Vec<String> = Vec::new();
+    for folder in single.into_iter().chain(many) {
+        if !folder.trim().is_empty() && !folders.iter().any(|f| f == folder) {
+            folders.push(folder.to_string());
         }
     }
-    let folders = attachments.get("folders").and_then(|v| v.as_array())?;
     folders
-        .iter()
-        .filter_map(|v| v.as_str())
-        .find(|v| !v.trim().is_empty())
-        .map(|v| v.to_string())
+}
+
+/// Working directory for a chat's tool calls: its first attached folder.
+/// `None` (the server's own cwd) when nothing is attached or the folder is
+/// not a directory.
+pub(super) fn chat_working_dir(settings: Option<&Value>) -> Option<PathBuf> {
+    let folder = extract_attached_folders(settings).into_iter().next()?;
+    let path = normalize_search_root(&folder);
+    if path.is_dir() {
+        Some(path)
+    } else {
+        tracing::warn!(
+            folder = %path.display(),
+            "attached folder is not a directory; tool calls use the server cwd"
+        );
+        None
+    }
 }
 
 pub(super) fn should_skip_dir(name: &str) -> bool {
@@ -51,81 +116,128 @@ pub(super) fn normalize_search_root(base: &str) -> PathBuf {
     path
 }
 
-pub(super) fn search_files_in_folder(
-    base: &str,
+/// Existing search roots for `bases`, in order. A root inside another root
+/// is dropped so its entries are not walked (and reported) twice.
+fn dedupe_search_roots(bases: &[String]) -> Vec<(String, PathBuf)> {
+    let mut roots: Vec<(String, PathBuf)> = Vec::new();
+    for base in bases {
+        let path = normalize_search_root(base);
+        if path.is_dir() && !roots.iter().any(|(_, root)| *root == path) {
+            roots.push((base.clone(), path));
+        }
+    }
+    let all: Vec<PathBuf> = roots.iter().map(|(_, path)| path.clone()).collect();
+    roots.retain(|(_, path)| {
+        !all.iter()
+            .any(|other| other != path && path.starts_with(other))
+    });
+    roots
+}
+
+/// Whether a `.gitignore` sits in any directory from `path`'s parent up to
+/// the search root, `depth` levels above `path`.
+fn under_gitignore(path: &Path, depth: usize) -> bool {
+    path.ancestors()
+        .skip(1)
+        .take(depth)
+        .any(|dir| dir.join(".gitignore").is_file())
+}
+
+/// Name search across every root. `bounds.max_visited` caps the walk as a
+/// whole. Each hit carries the `base_path` it was found under.
+///
+/// With `respect_gitignore`, `.gitignore` files in and above the roots hide
+/// what they ignore; directories outside any `.gitignore` fall back to
+/// `should_skip_dir`.
+pub(super) fn search_files_in_folders(
+    bases: &[String],
     query: &str,
-    limit: usize,
-) -> Result<Vec<Value>, String> {
+    bounds: FileSearchBounds,
+    respect_gitignore: bool,
+) -> Result<FileSearchResult, String> {
     if query.trim().is_empty() {
-        return Ok(Vec::new());
+        return Ok(FileSearchResult::default());
     }
-    let base_path = normalize_search_root(base);
-    if !base_path.is_dir() {
-        return Ok(Vec::new());
+    let roots = dedupe_search_roots(bases);
+    let Some(((_, first), rest)) = roots.split_first() else {
+        return Ok(FileSearchResult::default());
+    };
+    let mut walker = WalkBuilder::new(first);
+    for (_, root) in rest {
+        walker.add(root);
     }
+    walker
+        .standard_filters(false)
+        .git_ignore(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .parents(respect_gitignore)
+        .require_git(false)
+        // Depth 0 is the root itself.
+        .max_depth(bounds.max_depth.map(|depth| depth + 1))
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+            let name = entry.file_name().to_string_lossy();
+            if !is_dir || entry.depth() == 0 || !should_skip_dir(&name) {
+                return true;
+            }
+            name != ".git" && respect_gitignore && under_gitignore(entry.path(), entry.depth())
+        });
 
-    let mut queue = VecDeque::new();
-    queue.push_back(base_path.clone());
+    let limit = bounds.limit;
     let mut results = Vec::new();
     let mut visited = 0usize;
     let query_lower = query.to_lowercase();
 
-    while let Some(dir) = queue.pop_front() {
-        if visited > 25_000 || results.len() >= limit {
+    for entry in walker.build().flatten() {
+        if entry.depth() == 0 {
+            continue;
+        }
+        if results.len() >= limit {
+            break;
+        }
+        visited = visited.saturating_add(1);
+        if visited > bounds.max_visited {
             break;
         }
-        let entries = match std::fs::read_dir(&dir) {
-            Ok(entries) => entries,
-            Err(_) => continue,
+        let Some(file_type) = entry.file_type() else {
+            continue;
         };
-        for entry in entries.flatten() {
-            if results.len() >= limit {
-                break;
-            }
-            visited = visited.saturating_add(1);
-            if visited > 25_000 {
-                break;
-            }
-            let path = entry.path();
-            let file_type = match entry.file_type() {
-                Ok(ft) => ft,
-                Err(_) => continue,
-            };
-            let name = entry.file_name().to_string_lossy().to_string();
-            if file_type.is_dir() {
-                if should_skip_dir(&name) {
-                    continue;
-                }
-                queue.push_back(path.clone());
-            }
-            if !file_type.is_file() && !file_type.is_dir() {
-                continue;
-            }
-            let rel = match path.strip_prefix(&base_path) {
-                Ok(p) => p,
-                Err(_) => Path::new(&name),
-            };
-            let rel_str = rel.to_string_lossy().to_string();
-            let haystack = format!("{name} {rel_str}").to_lowercase();
-            if !haystack.contains(&query_lower) {
-                continue;
-            }
-            visited += 1;
-            let kind = if file_type.is_dir() {
-                "directory"
-            } else {
-                "file"
-            };
-            results.push(json!({
-                "name": name,
-                "path": path.to_string_lossy(),
-                "relative_path": rel_str,
-                "type": kind,
-            }));
+        if !file_type.is_file() && !file_type.is_dir() {
+            continue;
         }
+        let path = entry.path();
+        let Some((base, base_path)) = roots.iter().find(|(_, root)| path.starts_with(root)) else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        let rel = match path.strip_prefix(base_path) {
+            Ok(p) => p,
+            Err(_) => Path::new(&name),
+        };
+        let rel_str = rel.to_string_lossy().to_string();
+        let haystack = format!("{name} {rel_str}").to_lowercase();
+        if !haystack.contains(&query_lower) {
+            continue;
+        }
+        let kind = if file_type.is_dir() {
+            "directory"
+        } else {
+            "file"
+        };
+        results.push(json!({
+            "name": name,
+            "path": path.to_string_lossy(),
+            "relative_path": rel_str,
+            "type": kind,
+            "base_path": base,
+        }));
     }
 
-    Ok(results)
+    let truncated = visited > bounds.max_visited || results.len() >= limit;
+    Ok(FileSearchResult {
+        files: results,
+        truncated,
+    })
 }
 
 pub(super) fn list_homie_skills() -> Result<Vec<Value>, String> {
@@ -152,3 +264,171 @@ pub(super) fn list_homie_skills() -> Result<Vec<Value>, String> {
     });
     Ok(skills)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(limit: usize) -> FileSearchBounds {
+        FileSearchBounds::from_config(&FileSearchConfig::default(), Some(limit), None)
+    }
+
+    #[test]
+    fn extracts_every_attached_folder() {
+        let settings = json!({
+            "attachments": { "folder": "/a", "folders": ["/b", " ", "/a", "/c"] }
+        });
+        assert_eq!(
+            extract_attached_folders(Some(&settings)),
+            vec!["/a", "/b", "/c"]
+        );
+        assert!(extract_attached_folders(Some(&json!({}))).is_empty());
+    }
+
+    #[test]
+    fn working_dir_is_the_first_existing_attached_folder() {
+        let dir = std::env::temp_dir().join(format!("homie-cwd-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let settings = json!({
+            "attachments": { "folder": dir.to_string_lossy(), "folders": ["/elsewhere"] }
+        });
+        assert_eq!(chat_working_dir(Some(&settings)), Some(dir.clone()));
+
+        let missing = json!({ "attachments": { "folder": dir.join("gone").to_string_lossy() } });
+        assert_eq!(chat_working_dir(Some(&missing)), None);
+        assert_eq!(chat_working_dir(None), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn searches_across_roots_without_duplicates() {
+        let dir = std::env::temp_dir().join(format!("homie-files-{}", uuid::Uuid::new_v4()));
+        let web = dir.join("web");
+        let api = dir.join("api");
+        fs::create_dir_all(web.join("src")).unwrap();
+        fs::create_dir_all(&api).unwrap();
+        fs::write(web.join("src").join("notes.md"), "").unwrap();
+        fs::write(api.join("notes.txt"), "").unwrap();
+
+        let bases = [
+            web.to_string_lossy().to_string(),
+            api.to_string_lossy().to_string(),
+            web.join("src").to_string_lossy().to_string(),
+        ];
+        let found = search_files_in_folders(&bases, "notes", bounds(10), true).unwrap();
+        assert!(!found.truncated);
+        let mut found: Vec<(String, String)> = found
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    file["relative_path"].as_str().unwrap().to_string(),
+                    file["base_path"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                ("notes.txt".to_string(), bases[1].clone()),
+                (
+                    Path::new("src")
+                        .join("notes.md")
+                        .to_string_lossy()
+                        .to_string(),
+                    bases[0].clone()
+                ),
+            ]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn depth_and_visit_bounds_stop_the_walk() {
+        let dir = std::env::temp_dir().join(format!("homie-files-{}", uuid::Uuid::new_v4()));
+        let deep = dir.join("a").join("b");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(dir.join("note-0.md"), "").unwrap();
+        fs::write(dir.join("a").join("note-1.md"), "").unwrap();
+        fs::write(deep.join("note-2.md"), "").unwrap();
+
+        let bases = [dir.to_string_lossy().to_string()];
+        let search = |bounds: FileSearchBounds| {
+            let found = search_files_in_folders(&bases, "note-", bounds, true).unwrap();
+            let mut names: Vec<String> = found
+                .files
+                .iter()
+                .map(|file| file["name"].as_str().unwrap().to_string())
+                .collect();
+            names.sort();
+            (names, found.truncated)
+        };
+
+        let all = search(bounds(10));
+        assert_eq!(
+            all,
+            (
+                vec!["note-0.md".into(), "note-1.md".into(), "note-2.md".into()],
+                false
+            )
+        );
+
+        let config = FileSearchConfig {
+            max_depth: Some(1),
+            ..FileSearchConfig::default()
+        };
+        let shallow = FileSearchBounds::from_config(&config, Some(10), Some(5));
+        assert_eq!(shallow.max_depth, Some(1));
+        assert_eq!(
+            search(shallow),
+            (vec!["note-0.md".into(), "note-1.md".into()], false)
+        );
+        let root_only = FileSearchBounds::from_config(&config, None, Some(0));
+        assert_eq!(root_only.limit, 40);
+        assert_eq!(search(root_only), (vec!["note-0.md".into()], false));
+
+        assert!(search(bounds(1)).1);
+        let capped = FileSearchBounds {
+            max_visited: 1,
+            ..bounds(10)
+        };
+        assert!(search(capped).1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn gitignore_hides_ignored_entries_unless_disabled() {
+        let dir = std::env::temp_dir().join(format!("homie-files-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("out")).unwrap();
+        fs::create_dir_all(dir.join("app").join("target")).unwrap();
+        fs::write(dir.join(".gitignore"), "out/\n*.log\n").unwrap();
+        fs::write(dir.join("app").join(".gitignore"), "!keep.log\n").unwrap();
+        fs::write(dir.join("out").join("report.txt"), "").unwrap();
+        fs::write(dir.join("debug.log"), "").unwrap();
+        fs::write(dir.join("app").join("keep.log"), "").unwrap();
+        fs::write(dir.join("app").join("target").join("report.bin"), "").unwrap();
+        fs::write(dir.join("report.md"), "").unwrap();
+
+        let bases = [dir.to_string_lossy().to_string()];
+        let names = |query: &str, respect: bool| -> Vec<String> {
+            let mut names: Vec<String> =
+                search_files_in_folders(&bases, query, bounds(20), respect)
+                    .unwrap()
+                    .files
+                    .iter()
+                    .map(|file| file["name"].as_str().unwrap().to_string())
+                    .collect();
+            names.sort();
+            names
+        };
+
+        // `target` is not gitignored here, so only the gitignore applies.
+        assert_eq!(names("report", true), vec!["report.bin", "report.md"]);
+        assert_eq!(names(".log", true), vec!["keep.log"]);
+        assert_eq!(names("report", false), vec!["report.md", "report.txt"]);
+        assert_eq!(names(".log", false), vec!["debug.log", "keep.log"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
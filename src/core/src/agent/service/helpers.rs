@@ -8,27 +8,27 @@ use serde_json::{json, Map, Value};
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
-use homie_protocol::{error_codes, BinaryFrame, Response};
-use roci::auth::TokenStoreConfig;
-use roci::config::RociConfig;
-use roci::models::LanguageModel;
-use roci::auth::providers::claude_code::ClaudeCodeAuth;
-use roci::auth::providers::github_copilot::GitHubCopilotAuth;
-use roci::auth::providers::openai_codex::OpenAiCodexAuth;
 use crate::homie_config::{OpenAiCompatibleProviderConfig, ProvidersConfig};
 use crate::outbound::OutboundMessage;
 use crate::paths::homie_skills_dir;
 use crate::router::ReapEvent;
-use crate::storage::{ChatRecord, SessionStatus, Store};
+use crate::storage::{ChatRecord, SessionStatus, Store, CHAT_RAW_EVENT_MAX_RUNS};
 use crate::{ExecPolicy, HomieConfig};
+use homie_protocol::{error_codes, BinaryFrame, Response};
+use roci::auth::providers::claude_code::ClaudeCodeAuth;
+use roci::auth::providers::github_copilot::GitHubCopilotAuth;
+use roci::auth::providers::openai_codex::OpenAiCodexAuth;
+use roci::auth::TokenStoreConfig;
+use roci::config::RociConfig;
+use roci::models::LanguageModel;
 
 use super::process::{CodexEvent, CodexProcess, CodexRequestId, CodexResponseSender};
 use super::roci_backend::{ChatBackend, RociBackend};
 use super::tools::{list_tools, ToolContext, DEFAULT_TOOL_CHANNEL};
+use crate::agent::service::core::CodexChatCore;
+use reqwest;
 use roci::agent_loop::ApprovalDecision;
 use shell_words;
-use reqwest;
-use crate::agent::service::core::CodexChatCore;
 use std::sync::Arc;
 
 fn codex_method_to_topics(method: &str) -> Option<(&'static str, &'static str)> {
@@ -88,7 +88,7 @@ async fn event_forwarder_loop(
                     .insert_chat_raw_event(&run_id, &thread_id, &event.method, &raw_params)
                     .is_ok()
                 {
-                    let _ = store.prune_chat_raw_events(10);
+                    let _ = store.prune_chat_raw_events(CHAT_RAW_EVENT_MAX_RUNS);
                 }
             }
         }
@@ -1107,4 +1107,3 @@ fn roci_model_catalog(providers: &ProvidersConfig) -> Vec<Value> {
 
     models
 }
-
@@ -0,0 +1,111 @@
+//! Idle unload of the Codex app-server.
+//!
+//! Requests that use Codex and the events it sends mark it active; turns
+//! are tracked from `turn/started` to `turn/completed`. Once no turn is
+//! running and nothing has touched Codex for `chat.codex_idle_secs`, the
+//! process is unloaded and spawned again by the next request.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Longest wait between idle checks, so short idle timeouts stay accurate
+/// without polling long ones.
+const MAX_IDLE_CHECK: Duration = Duration::from_secs(30);
+
+/// How recently Codex was used and which of its turns are running.
+///
+/// Cloning is cheap: all clones share the same state.
+#[derive(Clone)]
+pub(super) struct CodexActivity {
+    inner: Arc<Mutex<ActivityState>>,
+}
+
+struct ActivityState {
+    last_used: Instant,
+    running_turns: HashSet<String>,
+}
+
+impl Default for CodexActivity {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ActivityState {
+                last_used: Instant::now(),
+                running_turns: HashSet::new(),
+            })),
+        }
+    }
+}
+
+impl CodexActivity {
+    /// Restart the idle clock.
+    pub(super) fn touch(&self) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.last_used = Instant::now();
+        }
+    }
+
+    pub(super) fn turn_started(&self, turn_id: &str) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.last_used = Instant::now();
+            state.running_turns.insert(turn_id.to_string());
+        }
+    }
+
+    pub(super) fn turn_completed(&self, turn_id: &str) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.last_used = Instant::now();
+            state.running_turns.remove(turn_id);
+        }
+    }
+
+    /// Forget running turns; their process is gone.
+    pub(super) fn reset(&self) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.last_used = Instant::now();
+            state.running_turns.clear();
+        }
+    }
+
+    /// Whether Codex has gone unused for `timeout` with no turn running.
+    pub(super) fn is_idle(&self, timeout: Duration) -> bool {
+        self.inner.lock().is_ok_and(|state| {
+            state.running_turns.is_empty() && state.last_used.elapsed() >= timeout
+        })
+    }
+}
+
+/// How often to check for an idle timeout of `timeout`.
+pub(super) fn idle_check_interval(timeout: Duration) -> Duration {
+    timeout.min(MAX_IDLE_CHECK).max(Duration::from_secs(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_turns_keep_codex_from_going_idle() {
+        let activity = CodexActivity::default();
+        assert!(activity.is_idle(Duration::ZERO));
+        assert!(!activity.is_idle(Duration::from_secs(60)));
+
+        activity.turn_started("turn-1");
+        assert!(!activity.is_idle(Duration::ZERO));
+        activity.turn_completed("turn-1");
+        assert!(activity.is_idle(Duration::ZERO));
+
+        activity.turn_started("turn-2");
+        activity.reset();
+        assert!(activity.is_idle(Duration::ZERO));
+
+        assert_eq!(
+            idle_check_interval(Duration::from_secs(600)),
+            MAX_IDLE_CHECK
+        );
+        assert_eq!(
+            idle_check_interval(Duration::from_millis(10)),
+            Duration::from_secs(1)
+        );
+    }
+}
@@ -1,10 +1,11 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
 use roci::auth::providers::github_copilot::GitHubCopilotAuth;
 use roci::config::RociConfig;
 use serde_json::{json, Value};
 
+use crate::agent::roci_backend::default_roci_model;
 use crate::homie_config::{OpenAiCompatibleProviderConfig, ProvidersConfig};
 
 pub(super) const COPILOT_FALLBACK_MODELS: &[&str] = &[
@@ -33,6 +34,73 @@ pub(super) const COPILOT_FALLBACK_MODELS: &[&str] = &[
     "raptor-mini",
 ];
 
+/// Display metadata for a known model id (without provider prefix):
+/// context window in tokens, list price as USD per million input/output
+/// tokens when published, and whether it reasons before answering.
+type ModelMetadata = (&'static str, u64, Option<(f64, f64)>, bool);
+
+const MODEL_METADATA: &[ModelMetadata] = &[
+    ("gpt-4o", 128_000, Some((2.5, 10.0)), false),
+    ("gpt-4o-mini", 128_000, Some((0.15, 0.6)), false),
+    ("gpt-4.1", 1_047_576, Some((2.0, 8.0)), false),
+    ("gpt-4.1-mini", 1_047_576, Some((0.4, 1.6)), false),
+    ("gpt-4.1-nano", 1_047_576, Some((0.1, 0.4)), false),
+    ("gpt-5", 400_000, Some((1.25, 10.0)), true),
+    ("gpt-5-mini", 400_000, Some((0.25, 2.0)), true),
+    ("gpt-5-nano", 400_000, Some((0.05, 0.4)), true),
+    ("gpt-5.1", 400_000, Some((1.25, 10.0)), true),
+    ("gpt-5.2", 400_000, Some((1.75, 14.0)), true),
+    ("gpt-5-codex", 400_000, Some((1.25, 10.0)), true),
+    ("gpt-5.1-codex", 400_000, Some((1.25, 10.0)), true),
+    ("gpt-5.1-codex-mini", 400_000, Some((0.25, 2.0)), true),
+    ("gpt-5.1-codex-max", 400_000, Some((1.25, 10.0)), true),
+    ("gpt-5.2-codex", 400_000, Some((1.75, 14.0)), true),
+    ("gpt-5.3-codex", 400_000, None, true),
+    ("o1", 200_000, Some((15.0, 60.0)), true),
+    ("o1-mini", 128_000, Some((1.1, 4.4)), true),
+    ("o1-pro", 200_000, Some((150.0, 600.0)), true),
+    ("o3", 200_000, Some((2.0, 8.0)), true),
+    ("o3-mini", 200_000, Some((1.1, 4.4)), true),
+    ("o4-mini", 200_000, Some((1.1, 4.4)), true),
+    ("claude-opus-4-5-20251101", 200_000, Some((5.0, 25.0)), true),
+    (
+        "claude-sonnet-4-5-20250514",
+        200_000,
+        Some((3.0, 15.0)),
+        true,
+    ),
+    ("claude-sonnet-4-20250514", 200_000, Some((3.0, 15.0)), true),
+    (
+        "claude-haiku-3-5-20241022",
+        200_000,
+        Some((0.8, 4.0)),
+        false,
+    ),
+    ("claude-3-opus-20240229", 200_000, Some((15.0, 75.0)), false),
+    (
+        "claude-3-sonnet-20240229",
+        200_000,
+        Some((3.0, 15.0)),
+        false,
+    ),
+    (
+        "claude-3-haiku-20240307",
+        200_000,
+        Some((0.25, 1.25)),
+        false,
+    ),
+    ("claude-haiku-4.5", 200_000, Some((1.0, 5.0)), true),
+    ("claude-opus-4.1", 200_000, Some((15.0, 75.0)), true),
+    ("claude-opus-4.5", 200_000, Some((5.0, 25.0)), true),
+    ("claude-sonnet-4", 200_000, Some((3.0, 15.0)), true),
+    ("claude-sonnet-4.5", 200_000, Some((3.0, 15.0)), true),
+    ("gemini-2.5-pro", 1_048_576, Some((1.25, 10.0)), true),
+];
+
+/// Providers billed per token. Subscription providers (Codex, Copilot) get
+/// no costs, since list prices do not apply to them.
+const METERED_PROVIDERS: &[&str] = &["openai", "anthropic"];
+
 pub(super) fn debug_enabled() -> bool {
     matches!(
         std::env::var("HOMIE_DEBUG").as_deref(),
@@ -44,10 +112,14 @@ pub(super) fn debug_enabled() -> bool {
 }
 
 pub(super) fn chrono_now() -> String {
-    let dur = std::time::SystemTime::now()
+    format!("{}s", unix_now())
+}
+
+pub(super) fn unix_now() -> u64 {
+    std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default();
-    format!("{}s", dur.as_secs())
+        .unwrap_or_default()
+        .as_secs()
 }
 
 pub(super) fn codex_model() -> String {
@@ -174,6 +246,83 @@ pub(super) fn replace_github_copilot_models(models: &mut Vec<Value>, copilot_mod
     }
 }
 
+/// Add `context_window`, `supports_reasoning` and, for metered providers,
+/// `input_cost_per_mtok`/`output_cost_per_mtok` to catalog entries whose
+/// model is in `MODEL_METADATA`. Unknown models are left alone.
+pub(super) fn annotate_model_metadata(models: &mut [Value]) {
+    for entry in models.iter_mut() {
+        let Some(model) = entry.get("model").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let (provider, model_id) = model.split_once(':').unwrap_or(("", model));
+        let Some(&(_, context_window, costs, supports_reasoning)) =
+            MODEL_METADATA.iter().find(|meta| meta.0 == model_id)
+        else {
+            continue;
+        };
+        let metered = METERED_PROVIDERS.contains(&provider);
+        let Some(map) = entry.as_object_mut() else {
+            continue;
+        };
+        map.insert("context_window".into(), json!(context_window));
+        map.insert("supports_reasoning".into(), json!(supports_reasoning));
+        if let Some((input, output)) = costs.filter(|_| metered) {
+            map.insert("input_cost_per_mtok".into(), json!(input));
+            map.insert("output_cost_per_mtok".into(), json!(output));
+        }
+    }
+}
+
+/// Whether `model` (`provider:model` or a bare id) accepts a reasoning
+/// effort. Models missing from `MODEL_METADATA` are assumed to.
+pub(super) fn model_supports_reasoning(model: &str) -> bool {
+    let model_id = model.split_once(':').map_or(model, |(_, id)| id);
+    MODEL_METADATA
+        .iter()
+        .find(|meta| meta.0 == model_id)
+        .is_none_or(|meta| meta.3)
+}
+
+/// Whether a run on `model` drops its reasoning effort. `None` is the
+/// model a roci run falls back to when the chat has none bound.
+pub(super) fn run_ignores_effort(model: Option<&str>) -> bool {
+    match model {
+        Some(model) => !model_supports_reasoning(model),
+        None => !model_supports_reasoning(&default_roci_model()),
+    }
+}
+
+/// Set `available` on every catalog entry, plus a `reason` for entries
+/// whose provider is missing from `availability` or failed its check.
+pub(super) fn mark_model_availability(
+    models: &mut [Value],
+    availability: &HashMap<String, Result<(), String>>,
+) {
+    for entry in models.iter_mut() {
+        let provider = entry
+            .get("provider")
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let Some(map) = entry.as_object_mut() else {
+            continue;
+        };
+        match availability.get(&provider) {
+            Some(Ok(())) => {
+                map.insert("available".into(), json!(true));
+            }
+            Some(Err(reason)) => {
+                map.insert("available".into(), json!(false));
+                map.insert("reason".into(), json!(reason));
+            }
+            None => {
+                map.insert("available".into(), json!(false));
+                map.insert("reason".into(), json!("provider not checked"));
+            }
+        }
+    }
+}
+
 pub(super) async fn discover_github_copilot_models(
     auth: &GitHubCopilotAuth,
 ) -> Result<Vec<String>, String> {
@@ -1,8 +1,12 @@
 use serde_json::{json, Map, Value};
+use uuid::Uuid;
 
 use crate::agent::process::CodexRequestId;
 use crate::agent::process::CodexRequestId::Text;
+use crate::agent::roci_backend::RociBackend;
+use crate::agent::tools::SessionToolSpec;
 use crate::homie_config::ProvidersConfig;
+use crate::storage::{LoginSessionRecord, UsageGroupBy, UsageQuery};
 use roci::auth::DeviceCodePoll;
 use roci::auth::DeviceCodeSession;
 
@@ -98,11 +102,99 @@ pub(super) fn parse_cancel_params(params: &Option<Value>) -> Option<(String, Str
     Some((chat_id, turn_id))
 }
 
-pub(super) fn parse_settings_update_params(params: &Option<Value>) -> Option<(String, Value)> {
+/// `chat.settings.update` params.
+pub(super) struct SettingsUpdateParams {
+    pub(super) chat_id: String,
+    pub(super) settings: Value,
+    /// Store keys and values the server does not recognize.
+    pub(super) force: bool,
+    /// Validate and merge without persisting.
+    pub(super) validate_only: bool,
+}
+
+pub(super) fn parse_settings_update_params(params: &Option<Value>) -> Option<SettingsUpdateParams> {
     let p = params.as_ref()?;
     let chat_id = p.get("chat_id")?.as_str()?.to_string();
     let settings = p.get("settings")?.clone();
-    Some((chat_id, settings))
+    let flag = |key: &str| p.get(key).and_then(Value::as_bool).unwrap_or(false);
+    Some(SettingsUpdateParams {
+        chat_id,
+        settings,
+        force: flag("force"),
+        validate_only: flag("validate_only"),
+    })
+}
+
+/// Chat settings keys the server reads.
+const KNOWN_SETTINGS: &[&str] = &[
+    "model",
+    "effort",
+    "approval_policy",
+    "collaboration_mode",
+    "title",
+    "pinned",
+    "attachments",
+    "timezone",
+    "locale",
+    "system_prompt",
+    "read_only",
+    "stream_idle_timeout_ms",
+    "provider_profile",
+    "profiles",
+];
+
+/// Approval policies the backends accept.
+const APPROVAL_POLICIES: &[&str] = &["never", "always", "on-request", "on-failure", "untrusted"];
+
+/// Collaboration modes the roci backend offers; Codex lists its own.
+const ROCI_COLLABORATION_MODES: &[&str] = &["plan", "code"];
+
+/// Validate the keys in a `chat.settings.update` payload that a run
+/// interprets, and reject keys the server does not know, so a typo fails
+/// here instead of being ignored later. `null` clears a key and always
+/// passes.
+pub(super) fn validate_known_settings(settings: &Value, roci: bool) -> Result<(), String> {
+    let map = settings.as_object().ok_or("settings must be an object")?;
+    if let Some(key) = map
+        .keys()
+        .find(|key| !KNOWN_SETTINGS.contains(&key.as_str()))
+    {
+        return Err(format!("unknown setting: {key}"));
+    }
+    let set = |key: &str| map.get(key).filter(|v| !v.is_null());
+    if let Some(model) = set("model") {
+        let model = model.as_str().ok_or("model must be a string")?.to_string();
+        RociBackend::parse_model(Some(&model))?;
+    }
+    if let Some(effort) = set("effort") {
+        let effort = effort
+            .as_str()
+            .ok_or("effort must be a string")?
+            .to_string();
+        if effort != "auto" {
+            RociBackend::parse_effort(Some(&effort))?;
+        }
+    }
+    if let Some(policy) = set("approval_policy") {
+        let policy = policy.as_str().ok_or("approval_policy must be a string")?;
+        if !APPROVAL_POLICIES.contains(&policy.trim().to_lowercase().as_str()) {
+            return Err(format!(
+                "invalid approval_policy: {policy} (expected one of {})",
+                APPROVAL_POLICIES.join(", ")
+            ));
+        }
+    }
+    if let Some(mode) = set("collaboration_mode") {
+        let name = RociBackend::parse_collaboration_mode(Some(mode))
+            .ok_or("collaboration_mode must name a mode")?;
+        if roci && !ROCI_COLLABORATION_MODES.contains(&name.as_str()) {
+            return Err(format!(
+                "unknown collaboration_mode: {name} (expected one of {})",
+                ROCI_COLLABORATION_MODES.join(", ")
+            ));
+        }
+    }
+    Ok(())
 }
 
 pub(super) fn normalize_settings_models(settings: Value, providers: &ProvidersConfig) -> Value {
@@ -138,23 +230,83 @@ pub(super) fn normalize_model_selector(raw: &str, providers: &ProvidersConfig) -
     trimmed.to_string()
 }
 
-pub(super) fn parse_files_search_params(
-    params: &Option<Value>,
-) -> Option<(String, String, usize, Option<String>)> {
+pub(super) fn parse_upload_begin_params(params: &Option<Value>) -> Option<(String, u64, String)> {
+    let p = params.as_ref()?;
+    let name = p.get("name")?.as_str()?.to_string();
+    let size = p.get("size")?.as_u64()?;
+    let sha256 = p.get("sha256")?.as_str()?.to_string();
+    Some((name, size, sha256))
+}
+
+pub(super) fn parse_attachment_id(params: &Option<Value>) -> Option<String> {
+    let p = params.as_ref()?;
+    let id = p
+        .get("attachment_id")
+        .or_else(|| p.get("attachmentId"))?
+        .as_str()?
+        .trim();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+pub(super) fn parse_upload_id(params: &Option<Value>) -> Option<Uuid> {
+    let p = params.as_ref()?;
+    p.get("upload_id")
+        .or_else(|| p.get("uploadId"))?
+        .as_str()?
+        .parse()
+        .ok()
+}
+
+/// `chat.files.search` params. `limit` and `max_depth` are `None` when the
+/// request leaves them to `tools.file_search`.
+pub(super) struct FilesSearchParams {
+    pub(super) chat_id: String,
+    pub(super) query: String,
+    pub(super) limit: Option<usize>,
+    pub(super) max_depth: Option<usize>,
+    pub(super) base_path: Option<String>,
+    pub(super) respect_gitignore: bool,
+}
+
+pub(super) fn parse_files_search_params(params: &Option<Value>) -> Option<FilesSearchParams> {
     let p = params.as_ref()?;
     let chat_id = p.get("chat_id")?.as_str()?.to_string();
     let query = p.get("query")?.as_str()?.to_string();
     let limit = p
         .get("limit")
         .and_then(|v| v.as_u64())
-        .map(|v| v.min(200) as usize)
-        .unwrap_or(40);
+        .map(|v| v.min(200) as usize);
+    let max_depth = p
+        .get("max_depth")
+        .or_else(|| p.get("maxDepth"))
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
     let base_path = p
         .get("base_path")
         .or_else(|| p.get("basePath"))
         .and_then(|v| v.as_str())
         .map(|v| v.to_string());
-    Some((chat_id, query, limit, base_path))
+    let respect_gitignore = p
+        .get("respect_gitignore")
+        .or_else(|| p.get("respectGitignore"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    Some(FilesSearchParams {
+        chat_id,
+        query,
+        limit,
+        max_depth,
+        base_path,
+        respect_gitignore,
+    })
+}
+
+pub(super) fn parse_model_list_verify(params: &Option<Value>) -> bool {
+    params
+        .as_ref()
+        .and_then(|p| p.get("verify"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
 }
 
 pub(super) fn parse_tool_channel(params: &Option<Value>) -> Option<String> {
@@ -166,6 +318,57 @@ pub(super) fn parse_tool_channel(params: &Option<Value>) -> Option<String> {
         .filter(|value| !value.is_empty())
 }
 
+/// `chat.tools.describe` params: the tool name.
+pub(super) fn parse_tool_describe_params(params: &Option<Value>) -> Option<String> {
+    params
+        .as_ref()?
+        .get("name")?
+        .as_str()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// `chat.process.kill` params: the background process id.
+pub(super) fn parse_process_kill_params(params: &Option<Value>) -> Option<String> {
+    params
+        .as_ref()?
+        .get("process_id")?
+        .as_str()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+/// `chat.tools.invoke` params: the tool name and its input (an empty object
+/// when omitted).
+pub(super) fn parse_tool_invoke_params(params: &Option<Value>) -> Option<(String, Value)> {
+    let p = params.as_ref()?;
+    let name = p
+        .get("name")?
+        .as_str()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())?
+        .to_string();
+    let input = p
+        .get("input")
+        .cloned()
+        .filter(|input| !input.is_null())
+        .unwrap_or_else(|| Value::Object(Map::new()));
+    Some((name, input))
+}
+
+/// Model requested by `chat.create`, if any.
+pub(super) fn parse_create_model(params: &Option<Value>) -> Option<String> {
+    params
+        .as_ref()?
+        .get("model")?
+        .as_str()
+        .map(str::trim)
+        .filter(|model| !model.is_empty())
+        .map(str::to_string)
+}
+
 pub(super) fn parse_resume_params(params: &Option<Value>) -> Option<(String, Option<String>)> {
     let p = params.as_ref()?;
     let chat_id = p.get("chat_id")?.as_str()?.to_string();
@@ -202,6 +405,191 @@ pub(super) fn parse_thread_read_params(
     }
 }
 
+/// `chat.thread.read` `include_diffs`: add the thread's `apply_patch`
+/// file summary.
+pub(super) fn parse_include_diffs(params: &Option<Value>) -> bool {
+    params
+        .as_ref()
+        .and_then(|p| p.get("include_diffs").or_else(|| p.get("includeDiffs")))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+pub(super) const THREAD_PAGE_DEFAULT_LIMIT: usize = 20;
+pub(super) const THREAD_PAGE_MAX_LIMIT: usize = 200;
+
+/// `chat.thread.read` paging: the `limit` turns just before
+/// `before_turn_id`, or the newest turns when it is absent.
+pub(super) struct TurnPage {
+    pub(super) before_turn_id: Option<String>,
+    pub(super) limit: usize,
+}
+
+/// Paging params of `chat.thread.read`; `None` when neither `limit` nor
+/// `before_turn_id` is given and the whole thread is wanted.
+pub(super) fn parse_turn_page_params(params: &Option<Value>) -> Option<TurnPage> {
+    let p = params.as_ref()?.as_object()?;
+    let before_turn_id = p
+        .get("before_turn_id")
+        .or_else(|| p.get("beforeTurnId"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let limit = get_u64(p, &["limit"]);
+    if before_turn_id.is_none() && limit.is_none() {
+        return None;
+    }
+    Some(TurnPage {
+        before_turn_id,
+        limit: limit
+            .map(|v| v as usize)
+            .unwrap_or(THREAD_PAGE_DEFAULT_LIMIT)
+            .clamp(1, THREAD_PAGE_MAX_LIMIT),
+    })
+}
+
+/// Cut a full `thread/read` result down to `page`, adding `total_turns` and
+/// `next_cursor` the way the roci backend's paged read does. Results
+/// without a turn list are left alone.
+pub(super) fn page_thread_turns(result: &mut Value, page: &TurnPage) -> Result<(), String> {
+    let Some(turns) = result
+        .pointer_mut("/thread/turns")
+        .and_then(|v| v.as_array_mut())
+    else {
+        return Ok(());
+    };
+    let total = turns.len();
+    let turn_id_at = |turn: &Value| turn.get("id").and_then(|v| v.as_str()).map(str::to_string);
+    let end = match page.before_turn_id.as_deref() {
+        Some(turn_id) => turns
+            .iter()
+            .position(|turn| turn_id_at(turn).as_deref() == Some(turn_id))
+            .ok_or_else(|| format!("unknown turn: {turn_id}"))?,
+        None => total,
+    };
+    let start = end.saturating_sub(page.limit);
+    let next_cursor = if start > 0 {
+        turn_id_at(&turns[start])
+    } else {
+        None
+    };
+    turns.truncate(end);
+    turns.drain(..start);
+    if let Some(obj) = result.as_object_mut() {
+        obj.insert("total_turns".into(), json!(total));
+        obj.insert("next_cursor".into(), json!(next_cursor));
+    }
+    Ok(())
+}
+
+pub(super) const EVENTS_SINCE_DEFAULT_LIMIT: usize = 500;
+pub(super) const EVENTS_SINCE_MAX_LIMIT: usize = 5_000;
+
+pub(super) struct EventsSinceParams {
+    pub(super) chat_id: String,
+    pub(super) pointer: u64,
+    /// Last `seq` already received; resumes a page within `pointer`.
+    pub(super) cursor: u64,
+    pub(super) limit: usize,
+}
+
+pub(super) fn parse_events_since_params(params: &Option<Value>) -> Option<EventsSinceParams> {
+    let p = params.as_ref()?.as_object()?;
+    let chat_id = p.get("chat_id")?.as_str()?.to_string();
+    let pointer = get_u64(p, &["pointer"]).unwrap_or(0);
+    let cursor = get_u64(p, &["cursor"]).unwrap_or(0);
+    let limit = get_u64(p, &["limit"])
+        .map(|v| v as usize)
+        .unwrap_or(EVENTS_SINCE_DEFAULT_LIMIT)
+        .clamp(1, EVENTS_SINCE_MAX_LIMIT);
+    Some(EventsSinceParams {
+        chat_id,
+        pointer,
+        cursor,
+        limit,
+    })
+}
+
+pub(super) const TOOLS_AUDIT_DEFAULT_LIMIT: usize = 100;
+pub(super) const TOOLS_AUDIT_MAX_LIMIT: usize = 1_000;
+
+pub(super) fn parse_tools_audit_params(params: &Option<Value>) -> Option<(String, usize)> {
+    let p = params.as_ref()?.as_object()?;
+    let chat_id = p.get("chat_id")?.as_str()?.to_string();
+    let limit = get_u64(p, &["limit"])
+        .map(|v| v as usize)
+        .unwrap_or(TOOLS_AUDIT_DEFAULT_LIMIT)
+        .clamp(1, TOOLS_AUDIT_MAX_LIMIT);
+    Some((chat_id, limit))
+}
+
+/// `chat.usage.summary` params as a query; the caller's identity is filled
+/// in by the handler. `None` when `group_by` is unknown or the window is
+/// inverted.
+pub(super) fn parse_usage_summary_params(params: &Option<Value>) -> Option<UsageQuery> {
+    let empty = Map::new();
+    let p = match params {
+        Some(value) => value.as_object()?,
+        None => &empty,
+    };
+    let chat_id = p
+        .get("chat_id")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string);
+    let group_by = match p.get("group_by").and_then(Value::as_str) {
+        Some(label) => UsageGroupBy::from_label(label)?,
+        None => UsageGroupBy::default(),
+    };
+    let from = get_u64(p, &["from"]).unwrap_or(0);
+    let to = get_u64(p, &["to"]).unwrap_or(u64::MAX);
+    if from > to {
+        return None;
+    }
+    Some(UsageQuery {
+        chat_id,
+        identity: None,
+        from,
+        to,
+        group_by,
+    })
+}
+
+pub(super) const CHAT_SEARCH_DEFAULT_LIMIT: usize = 20;
+pub(super) const CHAT_SEARCH_MAX_LIMIT: usize = 100;
+
+pub(super) fn parse_chat_search_params(params: &Option<Value>) -> Option<(String, usize)> {
+    let p = params.as_ref()?.as_object()?;
+    let query = p.get("query")?.as_str()?.trim();
+    if query.is_empty() {
+        return None;
+    }
+    let limit = get_u64(p, &["limit"])
+        .map(|v| v as usize)
+        .unwrap_or(CHAT_SEARCH_DEFAULT_LIMIT)
+        .clamp(1, CHAT_SEARCH_MAX_LIMIT);
+    Some((query.to_string(), limit))
+}
+
+pub(super) fn parse_tool_register_params(params: &Option<Value>) -> Option<SessionToolSpec> {
+    let p = params.as_ref()?.as_object()?;
+    let text = |key: &str| {
+        p.get(key)
+            .and_then(Value::as_str)
+            .map(|value| value.trim().to_string())
+    };
+    Some(SessionToolSpec {
+        name: text("name")?,
+        description: text("description").unwrap_or_default(),
+        input_schema: p
+            .get("input_schema")
+            .or_else(|| p.get("inputSchema"))
+            .cloned()
+            .unwrap_or(Value::Null),
+        url: text("url")?,
+    })
+}
+
 pub(super) fn parse_thread_archive_params(
     params: &Option<Value>,
 ) -> Option<(String, Option<String>)> {
@@ -215,6 +603,12 @@ pub(super) fn parse_thread_archive_params(
     Some((chat_id, thread_id))
 }
 
+/// `chat.thread.pin` / `chat.thread.unpin` take the same `chat_id` and
+/// optional `thread_id` as `chat.thread.archive`.
+pub(super) fn parse_thread_pin_params(params: &Option<Value>) -> Option<(String, Option<String>)> {
+    parse_thread_archive_params(params)
+}
+
 pub(super) fn parse_thread_rename_params(
     params: &Option<Value>,
 ) -> Option<(String, Option<String>, String)> {
@@ -233,6 +627,76 @@ pub(super) fn parse_thread_rename_params(
     Some((chat_id, thread_id, title))
 }
 
+pub(super) fn parse_thread_fork_params(params: &Option<Value>) -> Option<(String, String)> {
+    let p = params.as_ref()?;
+    let chat_id = p.get("chat_id")?.as_str()?.to_string();
+    let up_to_turn_id = p
+        .get("up_to_turn_id")
+        .or_else(|| p.get("upToTurnId"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())?;
+    Some((chat_id, up_to_turn_id))
+}
+
+/// How a Codex `thread/read` result splits at `up_to_turn_id`: the number
+/// of turns up to and including it, and the number after it. Errors when the
+/// turn is not in the thread.
+pub(super) fn codex_fork_cut(
+    result: &Value,
+    up_to_turn_id: &str,
+) -> Result<(usize, usize), String> {
+    let turns = result
+        .pointer("/thread/turns")
+        .and_then(|v| v.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let end = turns
+        .iter()
+        .position(|turn| turn.get("id").and_then(|v| v.as_str()) == Some(up_to_turn_id))
+        .ok_or_else(|| format!("unknown turn: {up_to_turn_id}"))?;
+    Ok((end + 1, turns.len() - end - 1))
+}
+
+/// `chat.import` params: an exported thread and, optionally, the settings
+/// it was exported with.
+pub(super) struct ChatImportParams {
+    pub(super) thread: Value,
+    pub(super) settings: Option<Value>,
+}
+
+pub(super) fn parse_chat_import_params(params: &Option<Value>) -> Option<ChatImportParams> {
+    let p = params.as_ref()?;
+    let thread = p.get("thread").filter(|v| v.is_object())?.clone();
+    let settings = p.get("settings").filter(|v| v.is_object()).cloned();
+    Some(ChatImportParams { thread, settings })
+}
+
+pub(super) struct RegenerateParams {
+    pub(super) chat_id: String,
+    pub(super) turn_id: String,
+    pub(super) new_message: Option<String>,
+}
+
+pub(super) fn parse_regenerate_params(params: &Option<Value>) -> Option<RegenerateParams> {
+    let p = params.as_ref()?;
+    let chat_id = p.get("chat_id")?.as_str()?.to_string();
+    let turn_id = p
+        .get("turn_id")
+        .or_else(|| p.get("turnId"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())?;
+    let new_message = p
+        .get("new_message")
+        .or_else(|| p.get("newMessage"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    Some(RegenerateParams {
+        chat_id,
+        turn_id,
+        new_message,
+    })
+}
+
 pub(super) fn parse_approval_params(params: &Option<Value>) -> Option<(CodexRequestId, String)> {
     let p = params.as_ref()?;
     let raw = p.get("codex_request_id")?;
@@ -275,6 +739,212 @@ fn normalize_provider_id(raw: &str) -> Option<String> {
     }
 }
 
+pub(super) const DEFAULT_PROFILE: &str = "default";
+
+/// Credential profile preferred for `provider_id`: the chat's `profiles`
+/// setting first, then its `provider_profile`, then `[providers.profiles]`,
+/// then `default`.
+pub(super) fn preferred_profile(
+    provider_id: &str,
+    chat_settings: Option<&Value>,
+    providers: &ProvidersConfig,
+) -> String {
+    chat_settings
+        .and_then(|s| s.get("profiles"))
+        .and_then(|p| p.get(provider_id))
+        .and_then(|v| v.as_str())
+        .or_else(|| pinned_profile(chat_settings))
+        .or_else(|| providers.profiles.get(provider_id).map(String::as_str))
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .unwrap_or(DEFAULT_PROFILE)
+        .to_string()
+}
+
+/// Use `preferred` when it holds credentials, otherwise fall back to the
+/// default profile.
+pub(super) fn select_profile(preferred: String, has_token: impl Fn(&str) -> bool) -> String {
+    if preferred == DEFAULT_PROFILE || has_token(&preferred) {
+        return preferred;
+    }
+    tracing::debug!(profile = %preferred, "preferred profile has no credentials; using default");
+    DEFAULT_PROFILE.to_string()
+}
+
+/// Profile the chat's `provider_profile` setting pins for whichever provider
+/// its model runs on.
+pub(super) fn pinned_profile(chat_settings: Option<&Value>) -> Option<&str> {
+    chat_settings?
+        .get("provider_profile")?
+        .as_str()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+}
+
+/// A pinned profile must hold credentials: the run is refused rather than
+/// quietly using another account.
+pub(super) fn require_profile(
+    provider_id: &str,
+    profile: String,
+    has_token: impl Fn(&str) -> bool,
+) -> Result<String, String> {
+    if has_token(&profile) {
+        return Ok(profile);
+    }
+    Err(format!(
+        "no {provider_id} credentials stored for profile `{profile}`; \
+         sign in with chat.account.login.start"
+    ))
+}
+
+/// Tokens expiring within this many seconds are refreshed before a run
+/// starts, so they cannot lapse mid-request.
+pub(super) const TOKEN_REFRESH_WINDOW_SECS: i64 = 60;
+
+/// Return `token` if it outlives the refresh window, otherwise the result
+/// of `refresh`. A failed refresh asks the user to sign in again.
+pub(super) async fn refresh_if_expiring<T, E, Fut>(
+    provider_id: &str,
+    token: T,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    refresh: impl FnOnce() -> Fut,
+) -> Result<T, String>
+where
+    Fut: std::future::Future<Output = Result<T, E>>,
+    E: std::fmt::Display,
+{
+    let deadline = chrono::Utc::now() + chrono::Duration::seconds(TOKEN_REFRESH_WINDOW_SECS);
+    if expires_at.is_none_or(|at| at > deadline) {
+        return Ok(token);
+    }
+    refresh().await.map_err(|e| {
+        format!(
+            "{provider_id} token expired and could not be refreshed ({e}); \
+             sign in again with chat.account.login.start"
+        )
+    })
+}
+
+/// Validate the profile keys in a `chat.settings.update` payload: `profiles`
+/// is an object mapping provider ids to non-empty profile names and
+/// `provider_profile` a non-empty profile name; either may be `null` to clear.
+pub(super) fn validate_profile_settings(settings: &Value) -> Result<(), String> {
+    match settings.get("provider_profile") {
+        None | Some(Value::Null) => {}
+        Some(Value::String(name)) if !name.trim().is_empty() => {}
+        Some(_) => return Err("provider_profile must be a non-empty string".into()),
+    }
+    let Some(profiles) = settings.get("profiles").filter(|v| !v.is_null()) else {
+        return Ok(());
+    };
+    let profiles = profiles
+        .as_object()
+        .ok_or("profiles must be an object of provider -> profile")?;
+    for (provider, profile) in profiles {
+        if normalize_provider_id(provider).as_deref() != Some(provider.as_str()) {
+            return Err(format!("unknown provider in profiles: {provider}"));
+        }
+        match profile.as_str() {
+            Some(name) if !name.trim().is_empty() => {}
+            _ => return Err(format!("profile for {provider} must be a non-empty string")),
+        }
+    }
+    Ok(())
+}
+
+/// Longest generated chat title, in characters, before the ellipsis.
+const AUTO_TITLE_MAX_CHARS: usize = 50;
+
+/// Title stored in the chat settings, if any.
+pub(super) fn chat_title(settings: Option<&Value>) -> Option<&str> {
+    settings?
+        .get("title")?
+        .as_str()
+        .filter(|title| !title.trim().is_empty())
+}
+
+/// Model bound to a chat in its settings, if any.
+pub(super) fn chat_model(settings: Option<&Value>) -> Option<&str> {
+    settings?
+        .get("model")?
+        .as_str()
+        .map(str::trim)
+        .filter(|model| !model.is_empty())
+}
+
+/// Whether a chat is pinned to the top of `chat.list`.
+pub(super) fn chat_pinned(settings: Option<&Value>) -> bool {
+    settings
+        .and_then(|s| s.get("pinned"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Sidebar title derived from a chat's first message: whitespace collapsed
+/// and cut at a word boundary near `AUTO_TITLE_MAX_CHARS`.
+pub(super) fn auto_chat_title(message: &str) -> Option<String> {
+    let cleaned = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    if cleaned.is_empty() {
+        return None;
+    }
+    if cleaned.chars().count() <= AUTO_TITLE_MAX_CHARS {
+        return Some(cleaned);
+    }
+    let cut: String = cleaned.chars().take(AUTO_TITLE_MAX_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(idx) if idx > AUTO_TITLE_MAX_CHARS / 2 => &cut[..idx],
+        _ => cut.as_str(),
+    };
+    Some(format!("{}…", cut.trim_end()))
+}
+
+/// Validate the `read_only` key in a `chat.settings.update` payload: a
+/// boolean, or `null` to clear.
+pub(super) fn validate_read_only_settings(settings: &Value) -> Result<(), String> {
+    match settings.get("read_only") {
+        None | Some(Value::Null) | Some(Value::Bool(_)) => Ok(()),
+        Some(_) => Err("read_only must be a boolean".into()),
+    }
+}
+
+/// Whether the chat runs in read-only (plan-only) mode.
+pub(super) fn chat_read_only(settings: Option<&Value>) -> bool {
+    settings
+        .and_then(|s| s.get("read_only"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Bounds of a chat's `stream_idle_timeout_ms` override.
+const STREAM_IDLE_TIMEOUT_MIN_MS: u64 = 1_000;
+const STREAM_IDLE_TIMEOUT_MAX_MS: u64 = 600_000;
+
+pub(super) fn validate_stream_idle_timeout_settings(settings: &Value) -> Result<(), String> {
+    match settings.get("stream_idle_timeout_ms") {
+        None | Some(Value::Null) => Ok(()),
+        Some(value) => match value.as_u64() {
+            Some(ms) if (STREAM_IDLE_TIMEOUT_MIN_MS..=STREAM_IDLE_TIMEOUT_MAX_MS).contains(&ms) => {
+                Ok(())
+            }
+            _ => Err(format!(
+                "stream_idle_timeout_ms must be between {STREAM_IDLE_TIMEOUT_MIN_MS} and {STREAM_IDLE_TIMEOUT_MAX_MS}"
+            )),
+        },
+    }
+}
+
+/// Stream idle timeout for a chat's runs: its own override, else the
+/// global `chat.stream_idle_timeout_ms`.
+pub(super) fn chat_stream_idle_timeout(
+    settings: Option<&Value>,
+    default_ms: Option<u64>,
+) -> Option<u64> {
+    settings
+        .and_then(|s| s.get("stream_idle_timeout_ms"))
+        .and_then(Value::as_u64)
+        .or(default_ms)
+}
+
 pub(super) fn parse_device_code_session(
     params: &Map<String, Value>,
     provider_id: &str,
@@ -301,6 +971,17 @@ pub(super) fn parse_device_code_session(
     })
 }
 
+pub(super) fn login_session_from_record(rec: &LoginSessionRecord) -> DeviceCodeSession {
+    DeviceCodeSession {
+        provider: rec.provider.clone(),
+        verification_url: rec.verification_url.clone(),
+        user_code: rec.user_code.clone(),
+        device_code: rec.device_code.clone(),
+        interval_secs: rec.interval_secs,
+        expires_at: chrono::DateTime::from_timestamp(rec.expires_at as i64, 0).unwrap_or_default(),
+    }
+}
+
 pub(super) fn device_code_session_json(session: &DeviceCodeSession) -> Value {
     json!({
         "provider": session.provider,
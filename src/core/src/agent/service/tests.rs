@@ -1,21 +1,34 @@
 #[allow(clippy::module_inception)] // Intentional: keep existing tests namespace in tests.rs with minimal churn.
 mod tests {
     use crate::agent::process::CodexRequestId;
+    use crate::agent::roci_backend::default_roci_model;
     use crate::agent::service::dispatch::{AgentService, ChatService};
-    use crate::agent::service::events::codex_method_to_topics;
-    use crate::agent::service::models::{chrono_now, roci_model_catalog};
+    use crate::agent::service::events::{codex_method_to_topics, record_codex_usage};
+    use crate::agent::service::models::{
+        annotate_model_metadata, chrono_now, mark_model_availability, model_supports_reasoning,
+        roci_model_catalog, run_ignores_effort, unix_now,
+    };
     use crate::agent::service::params::{
-        normalize_model_selector, parse_approval_params, parse_cancel_params, parse_message_params,
-        parse_tool_channel, MessageParams,
+        auto_chat_title, chat_read_only, chat_stream_idle_timeout, codex_fork_cut,
+        normalize_model_selector, page_thread_turns, parse_approval_params, parse_cancel_params,
+        parse_message_params, parse_tool_channel, parse_turn_page_params, pinned_profile,
+        preferred_profile, refresh_if_expiring, require_profile, select_profile,
+        validate_profile_settings, validate_read_only_settings,
+        validate_stream_idle_timeout_settings, MessageParams,
     };
     use crate::agent::tools::TOOL_CHANNEL_DENIED_CODE;
+    use crate::authz::{AuthContext, MethodPolicy, Role, Scope};
     use crate::execpolicy::ExecPolicy;
-    use crate::homie_config::{HomieConfig, ProvidersConfig};
+    use crate::homie_config::{HomieConfig, ProvidersConfig, TokenBudgetMode};
     use crate::outbound::OutboundMessage;
-    use crate::storage::{ChatRecord, SessionStatus, SqliteStore, Store};
-    use crate::ServiceHandler;
+    use crate::storage::{
+        AttachmentRecord, ChatRecord, PendingRunRecord, SessionStatus, SqliteStore, Store,
+        ToolInvocationRecord, TurnUsageRecord,
+    };
+    use crate::{ConnectionContext, ServiceHandler};
     use homie_protocol::error_codes;
-    use serde_json::json;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
     use std::sync::Arc;
     use tokio::sync::mpsc;
     use uuid::Uuid;
@@ -24,6 +37,18 @@ mod tests {
         Arc::new(SqliteStore::open_memory().unwrap())
     }
 
+    #[test]
+    fn chat_methods_map_to_agent_scopes() {
+        let mut policy = MethodPolicy::new();
+        policy.extend(ChatService::METHOD_SCOPES);
+        assert_eq!(policy.scope_for("chat.tools.register"), Some(Scope::Admin));
+        assert_eq!(policy.scope_for("chat.search"), Some(Scope::AgentRead));
+        let viewer = AuthContext::new(Role::Viewer);
+        assert!(policy.allows(&viewer, "chat.thread.read"));
+        assert!(!policy.allows(&viewer, "chat.message.send"));
+        assert!(!policy.allows(&AuthContext::new(Role::User), "chat.tools.register"));
+    }
+
     #[test]
     fn codex_method_maps_agent_message_delta_to_chat_delta() {
         assert_eq!(
@@ -32,6 +57,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn codex_thread_read_is_paged_from_the_full_turn_list() {
+        assert!(parse_turn_page_params(&Some(json!({ "thread_id": "t" }))).is_none());
+        let page = parse_turn_page_params(&Some(json!({
+            "thread_id": "t",
+            "before_turn_id": "turn-3",
+            "limit": 1,
+        })))
+        .expect("page");
+
+        let mut result = json!({
+            "thread": {
+                "id": "t",
+                "turns": [{ "id": "turn-1" }, { "id": "turn-2" }, { "id": "turn-3" }],
+            }
+        });
+        page_thread_turns(&mut result, &page).expect("paged");
+        assert_eq!(result["thread"]["turns"], json!([{ "id": "turn-2" }]));
+        assert_eq!(result["total_turns"], 3);
+        assert_eq!(result["next_cursor"], "turn-2");
+
+        let mut missing = json!({ "thread": { "turns": [] } });
+        assert!(page_thread_turns(&mut missing, &page).is_err());
+    }
+
+    #[test]
+    fn codex_fork_cut_counts_the_turns_kept_and_dropped() {
+        let result = json!({
+            "thread": {
+                "id": "t",
+                "turns": [{ "id": "turn-1" }, { "id": "turn-2" }, { "id": "turn-3" }],
+            }
+        });
+        assert_eq!(codex_fork_cut(&result, "turn-2"), Ok((2, 1)));
+        assert_eq!(codex_fork_cut(&result, "turn-3"), Ok((3, 0)));
+        assert!(codex_fork_cut(&result, "turn-9").is_err());
+        assert!(codex_fork_cut(&json!({}), "turn-1").is_err());
+    }
+
     #[test]
     fn codex_method_maps_turn_events() {
         assert_eq!(
@@ -230,6 +294,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn model_metadata_is_added_for_known_models_only() {
+        let mut models = vec![
+            json!({ "model": "openai:gpt-4o", "provider": "openai" }),
+            json!({ "model": "openai-codex:gpt-5.1-codex", "provider": "openai-codex" }),
+            json!({ "model": "openai-compatible:llama3", "provider": "openai-compatible" }),
+        ];
+        annotate_model_metadata(&mut models);
+
+        assert_eq!(models[0]["context_window"], 128_000);
+        assert_eq!(models[0]["input_cost_per_mtok"], 2.5);
+        assert_eq!(models[0]["output_cost_per_mtok"], 10.0);
+        assert_eq!(models[0]["supports_reasoning"], false);
+
+        assert_eq!(models[1]["context_window"], 400_000);
+        assert_eq!(models[1]["supports_reasoning"], true);
+        assert!(models[1].get("input_cost_per_mtok").is_none());
+
+        assert_eq!(
+            models[2],
+            json!({ "model": "openai-compatible:llama3", "provider": "openai-compatible" })
+        );
+    }
+
+    #[test]
+    fn reasoning_support_comes_from_model_metadata() {
+        assert!(!model_supports_reasoning("openai:gpt-4o"));
+        assert!(!model_supports_reasoning("gpt-4.1-mini"));
+        assert!(model_supports_reasoning("openai-codex:gpt-5.1-codex"));
+        assert!(model_supports_reasoning("openai-compatible:llama3"));
+        assert!(run_ignores_effort(Some("openai:gpt-4o")));
+        assert!(!run_ignores_effort(Some("openai-codex:gpt-5.1-codex")));
+        assert_eq!(
+            run_ignores_effort(None),
+            !model_supports_reasoning(&default_roci_model())
+        );
+    }
+
     #[test]
     fn parse_tool_channel_requires_non_empty_value() {
         assert_eq!(parse_tool_channel(&None), None);
@@ -237,6 +339,26 @@ mod tests {
         assert_eq!(parse_tool_channel(&Some(json!({"channel": "   "}))), None);
     }
 
+    #[test]
+    fn mark_model_availability_sets_reason_per_provider() {
+        let mut models = vec![
+            json!({ "model": "openai-codex:gpt-5.2", "provider": "openai-codex" }),
+            json!({ "model": "anthropic:claude-3-opus-20240229", "provider": "anthropic" }),
+            json!({ "model": "github-copilot:gpt-5", "provider": "github-copilot" }),
+        ];
+        let availability = HashMap::from([
+            ("openai-codex".to_string(), Ok(())),
+            ("anthropic".to_string(), Err("token expired".to_string())),
+        ]);
+        mark_model_availability(&mut models, &availability);
+        assert_eq!(models[0]["available"], json!(true));
+        assert!(models[0].get("reason").is_none());
+        assert_eq!(models[1]["available"], json!(false));
+        assert_eq!(models[1]["reason"], json!("token expired"));
+        assert_eq!(models[2]["available"], json!(false));
+        assert_eq!(models[2]["reason"], json!("provider not checked"));
+    }
+
     #[test]
     fn parse_tool_channel_normalizes_value() {
         assert_eq!(
@@ -245,6 +367,125 @@ mod tests {
         );
     }
 
+    #[test]
+    fn chat_profile_preference_resolves_that_profiles_token() {
+        let mut providers = ProvidersConfig::default();
+        providers
+            .profiles
+            .insert("openai-codex".into(), "personal".into());
+        let settings = json!({ "profiles": { "github-copilot": "work" } });
+        let logged_in = ["default", "work", "personal"];
+        let has_token = |profile: &str| logged_in.contains(&profile);
+
+        let copilot = preferred_profile("github-copilot", Some(&settings), &providers);
+        assert_eq!(select_profile(copilot, has_token), "work");
+        let codex = preferred_profile("openai-codex", Some(&settings), &providers);
+        assert_eq!(select_profile(codex, has_token), "personal");
+        let claude = preferred_profile("claude-code", Some(&settings), &providers);
+        assert_eq!(select_profile(claude, has_token), "default");
+    }
+
+    #[test]
+    fn chat_profile_without_credentials_falls_back_to_default() {
+        let settings = json!({ "profiles": { "github-copilot": "work" } });
+        let preferred = preferred_profile(
+            "github-copilot",
+            Some(&settings),
+            &ProvidersConfig::default(),
+        );
+        assert_eq!(preferred, "work");
+        assert_eq!(select_profile(preferred, |p| p == "default"), "default");
+    }
+
+    #[test]
+    fn pinned_provider_profile_applies_to_every_provider() {
+        let mut providers = ProvidersConfig::default();
+        providers
+            .profiles
+            .insert("openai-codex".into(), "personal".into());
+        let settings = json!({
+            "provider_profile": "work",
+            "profiles": { "claude-code": "team" },
+        });
+        assert_eq!(pinned_profile(Some(&settings)), Some("work"));
+        assert_eq!(
+            preferred_profile("github-copilot", Some(&settings), &providers),
+            "work"
+        );
+        assert_eq!(
+            preferred_profile("openai-codex", Some(&settings), &providers),
+            "work"
+        );
+        assert_eq!(
+            preferred_profile("claude-code", Some(&settings), &providers),
+            "team"
+        );
+        assert_eq!(
+            pinned_profile(Some(&json!({ "provider_profile": " " }))),
+            None
+        );
+        assert_eq!(pinned_profile(None), None);
+    }
+
+    #[test]
+    fn pinned_profile_without_credentials_is_refused() {
+        let has_token = |profile: &str| profile == "default";
+        assert_eq!(
+            require_profile("github-copilot", "default".into(), has_token),
+            Ok("default".to_string())
+        );
+        let err = require_profile("github-copilot", "work".into(), has_token).unwrap_err();
+        assert!(err.contains("`work`"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn tokens_near_expiry_are_refreshed_before_a_run() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let refreshes = AtomicUsize::new(0);
+        let refresh = |result: Result<&'static str, &'static str>| {
+            let refreshes = &refreshes;
+            move || async move {
+                refreshes.fetch_add(1, Ordering::SeqCst);
+                result
+            }
+        };
+        let now = chrono::Utc::now();
+
+        let fresh = Some(now + chrono::Duration::hours(1));
+        let token = refresh_if_expiring("claude-code", "old", fresh, refresh(Ok("new"))).await;
+        assert_eq!(token, Ok("old"));
+        let token = refresh_if_expiring("claude-code", "old", None, refresh(Ok("new"))).await;
+        assert_eq!(token, Ok("old"));
+        assert_eq!(refreshes.load(Ordering::SeqCst), 0);
+
+        let expiring = Some(now + chrono::Duration::seconds(30));
+        let token = refresh_if_expiring("claude-code", "old", expiring, refresh(Ok("new"))).await;
+        assert_eq!(token, Ok("new"));
+        let err = refresh_if_expiring("openai-codex", "old", expiring, refresh(Err("revoked")))
+            .await
+            .unwrap_err();
+        assert!(err.contains("openai-codex"), "{err}");
+        assert!(err.contains("revoked"), "{err}");
+        assert!(err.contains("chat.account.login.start"), "{err}");
+        assert_eq!(refreshes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn validate_profile_settings_rejects_bad_shapes() {
+        assert!(
+            validate_profile_settings(&json!({ "profiles": { "github-copilot": "work" } })).is_ok()
+        );
+        assert!(validate_profile_settings(&json!({ "profiles": null })).is_ok());
+        assert!(validate_profile_settings(&json!({ "profiles": "work" })).is_err());
+        assert!(validate_profile_settings(&json!({ "profiles": { "nope": "work" } })).is_err());
+        assert!(validate_profile_settings(&json!({ "profiles": { "claude-code": "" } })).is_err());
+        assert!(validate_profile_settings(&json!({ "provider_profile": "work" })).is_ok());
+        assert!(validate_profile_settings(&json!({ "provider_profile": null })).is_ok());
+        assert!(validate_profile_settings(&json!({ "provider_profile": "" })).is_err());
+        assert!(validate_profile_settings(&json!({ "provider_profile": 1 })).is_err());
+    }
+
     #[test]
     fn parse_cancel_params_extracts_ids() {
         let params = Some(json!({
@@ -359,6 +600,7 @@ mod tests {
                 status: SessionStatus::Active,
                 event_pointer: 0,
                 settings: Some(settings.clone()),
+                owner: None,
             })
             .unwrap();
 
@@ -388,179 +630,1985 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn chat_thread_read_recovers_from_invalid_persisted_thread_state() {
-        let thread_id = "thread-invalid-state";
-        let chat_id = "chat-invalid-state";
-        let settings = json!({
-            "model": "openai-codex:gpt-5.1-codex",
-        });
-        let store = make_store();
-        store
-            .upsert_chat(&ChatRecord {
-                chat_id: chat_id.to_string(),
-                thread_id: thread_id.to_string(),
-                created_at: chrono_now(),
-                status: SessionStatus::Active,
-                event_pointer: 0,
-                settings: Some(settings.clone()),
-            })
-            .unwrap();
-        store
-            .upsert_chat_thread_state(thread_id, &json!({ "invalid": true }))
-            .unwrap();
-
-        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+    async fn chat_list_updates_follow_create_settings_and_archive() {
+        let (tx, mut rx) = mpsc::channel::<OutboundMessage>(32);
         let mut svc = ChatService::new(
             tx,
-            store,
+            make_store(),
             Arc::new(HomieConfig::default()),
             Arc::new(ExecPolicy::empty()),
         );
+        let created = svc
+            .handle_request(Uuid::new_v4(), "chat.create", None)
+            .await
+            .result
+            .expect("result");
+        let chat_id = created["chat_id"].as_str().expect("chat_id").to_string();
         let resp = svc
             .handle_request(
                 Uuid::new_v4(),
-                "chat.thread.read",
-                Some(json!({
-                    "chat_id": chat_id,
-                    "include_turns": true,
-                })),
+                "chat.settings.update",
+                Some(json!({ "chat_id": chat_id, "settings": { "effort": "low" } })),
+            )
+            .await;
+        assert!(resp.error.is_none(), "{:?}", resp.error);
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.thread.archive",
+                Some(json!({ "chat_id": chat_id })),
             )
             .await;
+        assert!(resp.error.is_none(), "{:?}", resp.error);
 
-        assert!(resp.error.is_none());
-        let result = resp.result.expect("thread read result");
-        assert_eq!(result["thread"]["id"], thread_id);
-        assert_eq!(result["settings"], settings);
+        let mut deltas = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            if let OutboundMessage::Event { topic, params } = msg {
+                if topic == "chat.list.updated" {
+                    deltas.push(params.expect("params"));
+                }
+            }
+        }
+        assert_eq!(deltas.len(), 3, "{deltas:?}");
+        assert_eq!(deltas[0]["op"], "upsert");
+        assert_eq!(deltas[0]["chat"]["chat_id"], chat_id.as_str());
+        assert_eq!(deltas[1]["op"], "upsert");
+        assert_eq!(deltas[1]["chat"]["settings"]["effort"], "low");
+        assert_eq!(deltas[2], json!({ "op": "remove", "chat_id": chat_id }));
     }
 
     #[tokio::test]
-    async fn chat_tools_list_returns_expected_shape() {
-        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+    async fn roci_rename_persists_title_and_notifies() {
+        let (tx, mut rx) = mpsc::channel::<OutboundMessage>(32);
+        let store = make_store();
         let mut svc = ChatService::new(
             tx,
-            make_store(),
+            store.clone(),
             Arc::new(HomieConfig::default()),
             Arc::new(ExecPolicy::empty()),
         );
-        let id = Uuid::new_v4();
+        let created = svc
+            .handle_request(Uuid::new_v4(), "chat.create", None)
+            .await
+            .result
+            .expect("result");
+        let chat_id = created["chat_id"].as_str().expect("chat_id").to_string();
         let resp = svc
-            .handle_request(id, "chat.tools.list", Some(json!({ "channel": "web" })))
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.thread.rename",
+                Some(json!({ "chat_id": chat_id, "title": "Release checklist" })),
+            )
             .await;
-        assert!(resp.error.is_none());
-        let result = resp.result.expect("result");
-        let data = result["data"].as_array().expect("data array");
-        assert!(!data.is_empty());
-        let read = data
-            .iter()
-            .find(|tool| tool.get("name").and_then(|v| v.as_str()) == Some("read"))
-            .expect("read tool");
-        assert_eq!(read["provider"], "core");
-        assert_eq!(read["provider_dynamic"], false);
-        assert!(read["input_schema"].is_object());
+        assert!(resp.error.is_none(), "{:?}", resp.error);
+
+        let rec = store.get_chat(&chat_id).unwrap().expect("chat");
+        assert_eq!(rec.settings.unwrap()["title"], "Release checklist");
+        let list = svc
+            .handle_request(Uuid::new_v4(), "chat.list", None)
+            .await
+            .result
+            .expect("result");
+        assert_eq!(list["chats"][0]["title"], "Release checklist");
+
+        let mut renamed = None;
+        while let Ok(msg) = rx.try_recv() {
+            if let OutboundMessage::Event { topic, params } = msg {
+                if topic == "chat.thread.renamed" {
+                    renamed = params;
+                }
+            }
+        }
+        let renamed = renamed.expect("chat.thread.renamed event");
+        assert_eq!(renamed["chat_id"], chat_id.as_str());
+        assert_eq!(renamed["thread_id"], created["thread_id"]);
+        assert_eq!(renamed["title"], "Release checklist");
     }
 
     #[tokio::test]
-    async fn chat_tools_list_applies_channel_gating() {
-        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
-        let mut config = HomieConfig::default();
-        config.tools.providers.insert(
-            "core".to_string(),
-            crate::homie_config::ToolProviderConfig {
-                enabled: Some(true),
-                channels: vec!["mobile".to_string()],
-                allow_tools: Vec::new(),
-                deny_tools: Vec::new(),
-            },
-        );
+    async fn pinned_chats_list_first_and_notify() {
+        let (tx, mut rx) = mpsc::channel::<OutboundMessage>(32);
+        let store = make_store();
         let mut svc = ChatService::new(
             tx,
-            make_store(),
-            Arc::new(config),
+            store.clone(),
+            Arc::new(HomieConfig::default()),
             Arc::new(ExecPolicy::empty()),
         );
-
-        let web_resp = svc
+        let mut chat_ids = Vec::new();
+        for _ in 0..2 {
+            let created = svc
+                .handle_request(Uuid::new_v4(), "chat.create", None)
+                .await
+                .result
+                .expect("result");
+            chat_ids.push(created["chat_id"].as_str().expect("chat_id").to_string());
+        }
+        let older = &chat_ids[0];
+        let resp = svc
             .handle_request(
                 Uuid::new_v4(),
-                "chat.tools.list",
-                Some(json!({ "channel": "web" })),
+                "chat.thread.pin",
+                Some(json!({ "chat_id": older })),
             )
             .await;
-        assert!(web_resp.error.is_none());
-        let web_tools = web_resp.result.expect("web result")["data"]
-            .as_array()
-            .expect("web data")
-            .clone();
-        assert!(web_tools.is_empty());
+        assert!(resp.error.is_none(), "{:?}", resp.error);
 
-        let mobile_resp = svc
+        let list = svc
+            .handle_request(Uuid::new_v4(), "chat.list", None)
+            .await
+            .result
+            .expect("result");
+        assert_eq!(list["chats"][0]["chat_id"], older.as_str());
+        assert_eq!(list["chats"][0]["pinned"], true);
+        assert_eq!(list["chats"][1]["pinned"], false);
+
+        let resp = svc
             .handle_request(
                 Uuid::new_v4(),
-                "chat.tools.list",
-                Some(json!({ "channel": "mobile" })),
+                "chat.thread.unpin",
+                Some(json!({ "chat_id": older })),
             )
             .await;
-        assert!(mobile_resp.error.is_none());
-        let mobile_tools = mobile_resp.result.expect("mobile result")["data"]
-            .as_array()
-            .expect("mobile data")
-            .clone();
-        assert!(mobile_tools
-            .iter()
-            .any(|tool| tool.get("provider").and_then(|v| v.as_str()) == Some("core")));
+        assert_eq!(resp.result.expect("result")["pinned"], false);
+
+        let mut pinned = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            if let OutboundMessage::Event { topic, params } = msg {
+                if topic == "chat.thread.pinned" {
+                    pinned.push(params.expect("params")["pinned"].clone());
+                }
+            }
+        }
+        assert_eq!(pinned, [json!(true), json!(false)]);
     }
 
     #[tokio::test]
-    async fn chat_tools_list_denies_unknown_or_undefined_channel() {
-        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+    async fn chat_create_binds_the_requested_model() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(32);
+        let store = make_store();
         let mut svc = ChatService::new(
             tx,
-            make_store(),
+            store.clone(),
             Arc::new(HomieConfig::default()),
             Arc::new(ExecPolicy::empty()),
         );
-        let missing_resp = svc
-            .handle_request(Uuid::new_v4(), "chat.tools.list", None)
-            .await;
-        assert!(missing_resp.result.is_none());
-        let missing_error = missing_resp.error.expect("missing error");
-        assert_eq!(missing_error.code, error_codes::INVALID_PARAMS);
-        assert!(missing_error.message.contains(TOOL_CHANNEL_DENIED_CODE));
-
-        let resp = svc
+        let created = svc
             .handle_request(
                 Uuid::new_v4(),
-                "chat.tools.list",
-                Some(json!({ "channel": "discord" })),
+                "chat.create",
+                Some(json!({ "model": " openai:gpt-5.1 " })),
             )
-            .await;
-        assert!(resp.result.is_none());
-        let error = resp.error.expect("error");
-        assert_eq!(error.code, error_codes::INVALID_PARAMS);
-        assert!(error.message.contains(TOOL_CHANNEL_DENIED_CODE));
+            .await
+            .result
+            .expect("result");
+        let chat_id = created["chat_id"].as_str().expect("chat_id");
+        let rec = store.get_chat(chat_id).unwrap().expect("chat record");
+        assert_eq!(rec.settings, Some(json!({ "model": "openai:gpt-5.1" })));
+
+        let created = svc
+            .handle_request(Uuid::new_v4(), "chat.create", Some(json!({})))
+            .await
+            .result
+            .expect("result");
+        let chat_id = created["chat_id"].as_str().expect("chat_id");
+        let rec = store.get_chat(chat_id).unwrap().expect("chat record");
+        assert_eq!(rec.settings, None);
+    }
+
+    /// A Codex chat core talking to a scripted app-server whose thread
+    /// `source` has three turns, logging every request to `log`. With
+    /// `fail_rollback`, `thread/rollback` answers with an error.
+    fn codex_core_with_script(
+        store: Arc<dyn Store>,
+        log: &std::path::Path,
+        fail_rollback: bool,
+    ) -> crate::agent::service::core::CodexChatCore {
+        let script = r#"
+while IFS= read -r line; do
+  printf '%s\n' "$line" >> "$LOG"
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9][0-9]*\).*/\1/p')
+  [ -n "$id" ] || continue
+  case "$line" in
+    *'"thread/read"'*) printf '{"id":%s,"result":{"thread":{"id":"source","turns":[{"id":"turn-1"},{"id":"turn-2"},{"id":"turn-3"}]}}}\n' "$id" ;;
+    *'"thread/fork"'*) printf '{"id":%s,"result":{"thread":{"id":"forked"}}}\n' "$id" ;;
+    *'"thread/rollback"'*)
+      if [ -n "$FAIL_ROLLBACK" ]; then
+        printf '{"id":%s,"error":{"code":-32000,"message":"rollback unavailable"}}\n' "$id"
+      else
+        printf '{"id":%s,"result":{"thread":{"id":"forked"}}}\n' "$id"
+      fi ;;
+    *) printf '{"id":%s,"result":{}}\n' "$id" ;;
+  esac
+done
+"#;
+        let mut command = tokio::process::Command::new("sh");
+        command.arg("-c").arg(script).env("LOG", log);
+        if fail_rollback {
+            command.env("FAIL_ROLLBACK", "1");
+        }
+        let (process, _events) =
+            crate::agent::process::CodexProcess::spawn_command(command).expect("spawn");
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(32);
+        let mut core = crate::agent::service::core::CodexChatCore::new(
+            tx,
+            store.clone(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+            None,
+            crate::admin::RunFreeze::new(),
+        );
+        core.backend = crate::agent::roci_backend::ChatBackend::Codex;
+        core.process = Some(process);
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: "source-chat".into(),
+                thread_id: "source".into(),
+                created_at: String::new(),
+                status: SessionStatus::Active,
+                event_pointer: 0,
+                settings: None,
+                owner: None,
+            })
+            .unwrap();
+        core
     }
 
     #[tokio::test]
-    async fn chat_tools_list_denies_channel_mismatch_with_bound_connection_channel() {
-        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
-        let mut svc = ChatService::new_with_channel(
+    async fn codex_fork_copies_the_thread_and_rolls_back_to_the_cut() {
+        let store = make_store();
+        let log = std::env::temp_dir().join(format!("homie-codex-fork-{}", Uuid::new_v4()));
+        let mut core = codex_core_with_script(store.clone(), &log, false);
+
+        let params = json!({ "chat_id": "source-chat", "up_to_turn_id": "turn-2" });
+        let resp = core.chat_thread_fork(Uuid::new_v4(), Some(params)).await;
+        let result = resp.result.expect("fork result");
+        assert_eq!(result["thread_id"], "forked");
+        assert_eq!(result["turns"], 2);
+        assert!(store.get_chat("forked").unwrap().is_some());
+
+        let requests = std::fs::read_to_string(&log).expect("log");
+        let sent = |method: &str| {
+            requests
+                .lines()
+                .find(|line| line.contains(&format!("\"{method}\"")))
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        };
+        assert_eq!(sent("thread/fork").unwrap()["params"]["threadId"], "source");
+        let rollback = sent("thread/rollback").expect("rollback sent");
+        assert_eq!(
+            rollback["params"],
+            json!({ "threadId": "forked", "numTurns": 1 })
+        );
+        assert!(sent("turn/start").is_none());
+        let _ = std::fs::remove_file(&log);
+    }
+
+    #[tokio::test]
+    async fn codex_read_only_chats_run_sandboxed_and_ask_for_every_command() {
+        let store = make_store();
+        let log = std::env::temp_dir().join(format!("homie-codex-send-{}", Uuid::new_v4()));
+        let mut core = codex_core_with_script(store.clone(), &log, false);
+        store
+            .update_chat_settings("source-chat", Some(&json!({ "read_only": true })))
+            .unwrap();
+
+        let params = json!({
+            "chat_id": "source-chat",
+            "message": "clean up the repo",
+            "approval_policy": "never",
+        });
+        let resp = core.chat_message_send(Uuid::new_v4(), Some(params)).await;
+        assert!(resp.result.is_some(), "{:?}", resp.error);
+
+        let requests = std::fs::read_to_string(&log).expect("log");
+        let start = requests
+            .lines()
+            .find(|line| line.contains("\"turn/start\""))
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .expect("turn/start sent");
+        assert_eq!(start["params"]["approvalPolicy"], "untrusted");
+        assert_eq!(
+            start["params"]["sandboxPolicy"],
+            json!({ "type": "readOnly" })
+        );
+        let _ = std::fs::remove_file(&log);
+    }
+
+    #[tokio::test]
+    async fn codex_fork_fails_and_archives_the_copy_when_the_chat_is_not_saved() {
+        let path = std::env::temp_dir().join(format!("homie-fork-{}.db", Uuid::new_v4()));
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open(&path).unwrap());
+        let log = std::env::temp_dir().join(format!("homie-codex-fork-{}", Uuid::new_v4()));
+        let mut core = codex_core_with_script(store.clone(), &log, false);
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER refuse_chats BEFORE INSERT ON chats
+                 BEGIN SELECT RAISE(FAIL, 'disk full'); END;",
+            )
+            .unwrap();
+
+        let params = json!({ "chat_id": "source-chat", "up_to_turn_id": "turn-3" });
+        let resp = core.chat_thread_fork(Uuid::new_v4(), Some(params)).await;
+        let err = resp.error.expect("fork error");
+        assert_eq!(err.code, error_codes::INTERNAL_ERROR);
+        assert!(err.message.contains("disk full"), "{}", err.message);
+        let requests = std::fs::read_to_string(&log).expect("log");
+        let archive = requests
+            .lines()
+            .find(|line| line.contains("\"thread/archive\""))
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .expect("thread/archive sent");
+        assert_eq!(archive["params"]["threadId"], "forked");
+        let _ = std::fs::remove_file(&log);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn codex_fork_reports_a_failed_rollback() {
+        let store = make_store();
+        let log = std::env::temp_dir().join(format!("homie-codex-fork-{}", Uuid::new_v4()));
+        let mut core = codex_core_with_script(store.clone(), &log, true);
+
+        let params = json!({ "chat_id": "source-chat", "up_to_turn_id": "turn-1" });
+        let resp = core.chat_thread_fork(Uuid::new_v4(), Some(params)).await;
+        let err = resp.error.expect("fork error");
+        assert_eq!(err.code, error_codes::INTERNAL_ERROR);
+        assert!(err.message.contains("rollback unavailable"));
+        assert!(store.get_chat("forked").unwrap().is_none());
+        let requests = std::fs::read_to_string(&log).expect("log");
+        assert!(requests.contains("\"thread/archive\""));
+        let _ = std::fs::remove_file(&log);
+    }
+
+    #[tokio::test]
+    async fn roci_fork_rejects_unknown_turns() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(32);
+        let store = make_store();
+        let mut svc = ChatService::new(
             tx,
-            make_store(),
+            store.clone(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let created = svc
+            .handle_request(Uuid::new_v4(), "chat.create", None)
+            .await
+            .result
+            .expect("result");
+        let chat_id = created["chat_id"].as_str().expect("chat_id").to_string();
+
+        for params in [
+            json!({ "chat_id": chat_id }),
+            json!({ "chat_id": chat_id, "up_to_turn_id": "no-such-turn" }),
+            json!({ "chat_id": "no-such-chat", "up_to_turn_id": "turn" }),
+        ] {
+            let resp = svc
+                .handle_request(Uuid::new_v4(), "chat.thread.fork", Some(params))
+                .await;
+            let err = resp.error.expect("fork error");
+            assert_eq!(err.code, error_codes::INVALID_PARAMS);
+        }
+        assert_eq!(store.list_chats().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn roci_import_creates_a_readable_chat_with_fresh_ids() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(32);
+        let store = make_store();
+        let mut svc = ChatService::new(
+            tx,
+            store.clone(),
             Arc::new(HomieConfig::default()),
             Arc::new(ExecPolicy::empty()),
-            Some("web".to_string()),
         );
+        let thread = json!({
+            "id": "exported",
+            "created_at": 1,
+            "updated_at": 2,
+            "turns": [{
+                "id": "turn-1",
+                "items": [
+                    { "type": "userMessage", "id": "u1", "content": [{ "type": "text", "text": "hi" }] },
+                    { "type": "agentMessage", "id": "a1", "text": "hello" },
+                ],
+            }],
+        });
+
         let resp = svc
             .handle_request(
                 Uuid::new_v4(),
-                "chat.tools.list",
-                Some(json!({ "channel": "mobile" })),
+                "chat.import",
+                Some(json!({ "thread": thread, "settings": { "read_only": true } })),
+            )
+            .await;
+        let result = resp.result.expect("import result");
+        assert_eq!(result["turns"], 1);
+        let chat_id = result["chat_id"].as_str().expect("chat_id").to_string();
+        let rec = store.get_chat(&chat_id).unwrap().expect("chat record");
+        assert_eq!(rec.settings, Some(json!({ "read_only": true })));
+
+        let read = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.thread.read",
+                Some(json!({ "chat_id": chat_id, "include_turns": true })),
+            )
+            .await
+            .result
+            .expect("thread read");
+        let turn = &read["thread"]["turns"][0];
+        assert_ne!(turn["id"], "turn-1");
+        assert_ne!(turn["items"][0]["id"], "u1");
+        assert_eq!(turn["items"][1]["text"], "hello");
+
+        for params in [
+            json!({}),
+            json!({ "thread": { "id": "t", "created_at": 1, "updated_at": 1, "turns": [] } }),
+            json!({ "thread": { "id": "t", "created_at": 1, "updated_at": 1,
+                "turns": [{ "id": "x", "items": [{ "type": "bogus", "id": "i" }] }] } }),
+            json!({ "thread": { "id": "t", "created_at": 1, "updated_at": 1,
+                "turns": [{ "id": "x", "items": [] }] } }),
+            json!({ "thread": thread, "settings": { "read_only": "yes" } }),
+        ] {
+            let resp = svc
+                .handle_request(Uuid::new_v4(), "chat.import", Some(params))
+                .await;
+            assert_eq!(
+                resp.error.expect("import error").code,
+                error_codes::INVALID_PARAMS
+            );
+        }
+        assert_eq!(store.list_chats().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn chat_import_rejects_oversized_threads() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(32);
+        let mut config = HomieConfig::default();
+        config.chat.max_import_bytes = 64;
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(config),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let thread = json!({
+            "id": "t",
+            "created_at": 1,
+            "updated_at": 1,
+            "turns": [{ "id": "x", "items": [{ "type": "agentMessage", "id": "a", "text": "x".repeat(100) }] }],
+        });
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.import",
+                Some(json!({ "thread": thread })),
+            )
+            .await;
+        let err = resp.error.expect("import error");
+        assert_eq!(err.code, error_codes::INVALID_PARAMS);
+        assert!(err.message.contains("exceeds 64 bytes"));
+    }
+
+    #[tokio::test]
+    async fn chat_import_checks_settings_and_fails_when_the_chat_is_not_saved() {
+        let path = std::env::temp_dir().join(format!("homie-import-{}.db", Uuid::new_v4()));
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open(&path).unwrap());
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER refuse_chats BEFORE INSERT ON chats
+                 BEGIN SELECT RAISE(FAIL, 'disk full'); END;",
+            )
+            .unwrap();
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(32);
+        let mut svc = ChatService::new(
+            tx,
+            store,
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let thread = json!({
+            "id": "t",
+            "created_at": 1,
+            "updated_at": 1,
+            "turns": [{ "id": "x", "items": [{ "type": "agentMessage", "id": "a", "text": "hi" }] }],
+        });
+        let import = |settings: Value| json!({ "thread": thread, "settings": settings });
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.import",
+                Some(import(json!({ "no_such_setting": true }))),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::INVALID_PARAMS);
+
+        let resp = svc
+            .handle_request(Uuid::new_v4(), "chat.import", Some(import(json!({}))))
+            .await;
+        let err = resp.error.expect("import error");
+        assert_eq!(err.code, error_codes::INTERNAL_ERROR);
+        assert!(err.message.contains("disk full"), "{}", err.message);
+        let threads = svc
+            .handle_request(Uuid::new_v4(), "chat.thread.list", None)
+            .await
+            .result
+            .expect("threads");
+        assert_eq!(threads["threads"], json!([]));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn roci_regenerate_rejects_unknown_turns() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(32);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let created = svc
+            .handle_request(Uuid::new_v4(), "chat.create", None)
+            .await
+            .result
+            .expect("result");
+        let chat_id = created["chat_id"].as_str().expect("chat_id").to_string();
+
+        for params in [
+            json!({ "chat_id": chat_id }),
+            json!({ "chat_id": chat_id, "turn_id": "no-such-turn" }),
+            json!({ "chat_id": "no-such-chat", "turn_id": "turn", "new_message": "hi" }),
+        ] {
+            let resp = svc
+                .handle_request(Uuid::new_v4(), "chat.message.regenerate", Some(params))
+                .await;
+            let err = resp.error.expect("regenerate error");
+            assert_eq!(err.code, error_codes::INVALID_PARAMS);
+        }
+    }
+
+    #[test]
+    fn auto_chat_title_cleans_and_truncates_message() {
+        assert_eq!(auto_chat_title("  \n "), None);
+        assert_eq!(
+            auto_chat_title("Fix  the\nlogin   bug").as_deref(),
+            Some("Fix the login bug")
+        );
+        let long =
+            "Please refactor the websocket reconnect logic so that it backs off exponentially";
+        let title = auto_chat_title(long).unwrap();
+        assert_eq!(title, "Please refactor the websocket reconnect logic so…");
+        assert!(title.chars().count() <= 51);
+    }
+
+    #[tokio::test]
+    async fn chat_settings_update_validates_known_keys() {
+        let store = make_store();
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store.clone(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let created = svc
+            .handle_request(Uuid::new_v4(), "chat.create", None)
+            .await
+            .result
+            .expect("result");
+        let chat_id = created["chat_id"].as_str().expect("chat_id").to_string();
+
+        for settings in [
+            json!({ "aproval_policy": "never" }),
+            json!({ "approval_policy": "sometimes" }),
+            json!({ "effort": "extreme" }),
+            json!({ "collaboration_mode": { "mode": "pair" } }),
+            json!({ "model": 7 }),
+            json!("high"),
+        ] {
+            let resp = svc
+                .handle_request(
+                    Uuid::new_v4(),
+                    "chat.settings.update",
+                    Some(json!({ "chat_id": chat_id, "settings": settings })),
+                )
+                .await;
+            let err = resp.error.expect("validation error");
+            assert_eq!(err.code, error_codes::INVALID_PARAMS, "{settings}");
+        }
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.settings.update",
+                Some(json!({
+                    "chat_id": chat_id,
+                    "settings": { "approval_policy": "never", "collaboration_mode": { "mode": "plan" } },
+                    "validate_only": true,
+                })),
+            )
+            .await;
+        let result = resp.result.expect("validated");
+        assert_eq!(result["settings"]["approval_policy"], "never");
+        assert_eq!(result["validate_only"], true);
+        let rec = store.get_chat(&chat_id).unwrap().expect("chat record");
+        assert_eq!(rec.settings, None);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.settings.update",
+                Some(json!({
+                    "chat_id": chat_id,
+                    "settings": { "client_theme": "dark" },
+                    "force": true,
+                })),
+            )
+            .await;
+        assert!(resp.error.is_none(), "{:?}", resp.error);
+        let rec = store.get_chat(&chat_id).unwrap().expect("chat record");
+        assert_eq!(rec.settings, Some(json!({ "client_theme": "dark" })));
+    }
+
+    #[tokio::test]
+    async fn chat_settings_update_persists_system_prompt_override() {
+        let chat_id = "chat-prompt";
+        let store = make_store();
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: chat_id.to_string(),
+                thread_id: "thread-prompt".to_string(),
+                created_at: chrono_now(),
+                status: SessionStatus::Active,
+                event_pointer: 0,
+                settings: Some(json!({ "effort": "high" })),
+                owner: None,
+            })
+            .unwrap();
+
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store.clone(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.settings.update",
+                Some(json!({
+                    "chat_id": chat_id,
+                    "settings": { "system_prompt": 42 },
+                })),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::INVALID_PARAMS);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.settings.update",
+                Some(json!({
+                    "chat_id": chat_id,
+                    "settings": { "system_prompt": "You are a terse shell assistant." },
+                })),
+            )
+            .await;
+        assert!(resp.error.is_none());
+        let settings = store
+            .get_chat(chat_id)
+            .unwrap()
+            .and_then(|rec| rec.settings)
+            .expect("settings");
+        assert_eq!(
+            settings["system_prompt"],
+            "You are a terse shell assistant."
+        );
+        assert_eq!(settings["effort"], "high");
+    }
+
+    #[test]
+    fn read_only_setting_must_be_boolean() {
+        assert!(validate_read_only_settings(&json!({ "read_only": true })).is_ok());
+        assert!(validate_read_only_settings(&json!({ "read_only": null })).is_ok());
+        assert!(validate_read_only_settings(&json!({ "effort": "high" })).is_ok());
+        assert!(validate_read_only_settings(&json!({ "read_only": "yes" })).is_err());
+
+        assert!(chat_read_only(Some(&json!({ "read_only": true }))));
+        assert!(!chat_read_only(Some(&json!({ "read_only": false }))));
+        assert!(!chat_read_only(None));
+    }
+
+    #[test]
+    fn stream_idle_timeout_override_is_bounded_and_falls_back() {
+        let ok = json!({ "stream_idle_timeout_ms": 120_000 });
+        assert!(validate_stream_idle_timeout_settings(&ok).is_ok());
+        assert!(
+            validate_stream_idle_timeout_settings(&json!({ "stream_idle_timeout_ms": null }))
+                .is_ok()
+        );
+        assert!(
+            validate_stream_idle_timeout_settings(&json!({ "stream_idle_timeout_ms": 500 }))
+                .is_err()
+        );
+        assert!(validate_stream_idle_timeout_settings(
+            &json!({ "stream_idle_timeout_ms": 600_001 })
+        )
+        .is_err());
+        assert!(validate_stream_idle_timeout_settings(
+            &json!({ "stream_idle_timeout_ms": "60000" })
+        )
+        .is_err());
+
+        assert_eq!(
+            chat_stream_idle_timeout(Some(&ok), Some(30_000)),
+            Some(120_000)
+        );
+        assert_eq!(
+            chat_stream_idle_timeout(Some(&json!({})), Some(30_000)),
+            Some(30_000)
+        );
+        assert_eq!(chat_stream_idle_timeout(None, None), None);
+    }
+
+    fn events_since_service(store: Arc<dyn Store>, persist: bool) -> ChatService {
+        let mut config = HomieConfig::default();
+        config.debug.persist_raw_provider_events = persist;
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        ChatService::new(tx, store, Arc::new(config), Arc::new(ExecPolicy::empty()))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn background_task_handles_are_always_kept_for_shutdown() {
+        for _ in 0..20 {
+            let mut config = HomieConfig::default();
+            config.chat.codex_idle_secs = 1;
+            let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+            let (svc, _agent) = ChatService::new_shared(
+                tx,
+                make_store(),
+                Arc::new(config),
+                Arc::new(ExecPolicy::empty()),
+            );
+            let core = svc.core.lock().await;
+            assert!(core.turn_cancel_listener.is_some());
+            assert!(core.codex_idle_watcher.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_events_since_replays_missed_events_in_order() {
+        let thread_id = "thread-replay";
+        let chat_id = "chat-replay";
+        let store = make_store();
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: chat_id.to_string(),
+                thread_id: thread_id.to_string(),
+                created_at: chrono_now(),
+                status: SessionStatus::Active,
+                event_pointer: 0,
+                settings: None,
+                owner: None,
+            })
+            .unwrap();
+        // Mirrors the forwarder: store the event, then advance the pointer
+        // when it is delivered.
+        let mut pointer = 0;
+        for (method, params) in [
+            (
+                "turn/started",
+                json!({ "threadId": thread_id, "turnId": "t1" }),
+            ),
+            ("codex/internal", json!({ "threadId": thread_id })),
+            (
+                "item/agentMessage/delta",
+                json!({ "threadId": thread_id, "turnId": "t1", "delta": "hi" }),
+            ),
+            (
+                "turn/completed",
+                json!({ "threadId": thread_id, "turnId": "t1" }),
+            ),
+        ] {
+            store
+                .insert_chat_raw_event("t1", thread_id, method, &params)
+                .unwrap();
+            if method != "codex/internal" {
+                pointer += 1;
+                store.update_event_pointer(chat_id, pointer).unwrap();
+            }
+        }
+
+        let mut svc = events_since_service(store, true);
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.events.since",
+                Some(json!({ "chat_id": chat_id, "pointer": 0, "limit": 2 })),
+            )
+            .await;
+        let first = resp.result.expect("first page");
+        assert_eq!(first["thread_id"], thread_id);
+        assert_eq!(first["has_more"], true);
+        assert_eq!(first["reset_required"], false);
+        assert_eq!(first["pointer"], 0);
+        let topics: Vec<_> = first["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["topic"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(topics, vec!["chat.turn.started"]);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.events.since",
+                Some(json!({
+                    "chat_id": chat_id,
+                    "pointer": first["pointer"],
+                    "cursor": first["cursor"],
+                })),
+            )
+            .await;
+        let rest = resp.result.expect("second page");
+        assert_eq!(rest["has_more"], false);
+        let events = rest["events"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["topic"], "chat.message.delta");
+        assert_eq!(events[0]["params"]["delta"], "hi");
+        assert_eq!(events[1]["topic"], "chat.turn.completed");
+        assert_eq!(rest["pointer"], 3);
+        assert_eq!(rest["cursor"], events[1]["seq"]);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.events.since",
+                Some(json!({
+                    "chat_id": chat_id,
+                    "pointer": rest["pointer"],
+                    "cursor": rest["cursor"],
+                })),
+            )
+            .await;
+        let caught_up = resp.result.expect("caught up");
+        assert!(caught_up["events"].as_array().unwrap().is_empty());
+        assert_eq!(caught_up["pointer"], rest["pointer"]);
+        assert_eq!(caught_up["reset_required"], false);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.events.since",
+                Some(json!({ "chat_id": "missing" })),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn chat_events_since_flags_a_pruned_gap() {
+        let store = make_store();
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: "chat-gap".to_string(),
+                thread_id: "thread-gap".to_string(),
+                created_at: chrono_now(),
+                status: SessionStatus::Active,
+                event_pointer: 7,
+                settings: None,
+                owner: None,
+            })
+            .unwrap();
+        store
+            .insert_chat_raw_event(
+                "t9",
+                "thread-gap",
+                "turn/started",
+                &json!({ "threadId": "thread-gap", "turnId": "t9" }),
+            )
+            .unwrap();
+        store.update_event_pointer("chat-gap", 8).unwrap();
+
+        let mut svc = events_since_service(store.clone(), true);
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.events.since",
+                Some(json!({ "chat_id": "chat-gap", "pointer": 2 })),
+            )
+            .await;
+        let gap = resp.result.expect("gap");
+        assert_eq!(gap["reset_required"], true);
+        assert_eq!(gap["pointer"], 8);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.events.since",
+                Some(json!({ "chat_id": "chat-gap", "pointer": 7 })),
+            )
+            .await;
+        let resumed = resp.result.expect("resumed");
+        assert_eq!(resumed["reset_required"], false);
+        assert_eq!(resumed["events"].as_array().unwrap().len(), 1);
+
+        let mut svc = events_since_service(store, false);
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.events.since",
+                Some(json!({ "chat_id": "chat-gap", "pointer": 7 })),
+            )
+            .await;
+        assert_eq!(
+            resp.error.expect("disabled").code,
+            error_codes::METHOD_NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_tools_audit_lists_thread_invocations_newest_first() {
+        let store = make_store();
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: "chat-audit".to_string(),
+                thread_id: "thread-audit".to_string(),
+                created_at: chrono_now(),
+                status: SessionStatus::Active,
+                event_pointer: 0,
+                settings: None,
+                owner: None,
+            })
+            .unwrap();
+        for (idx, (thread_id, tool)) in [
+            ("thread-audit", "ls"),
+            ("thread-other", "exec"),
+            ("thread-audit", "read"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            store
+                .insert_tool_invocation(&ToolInvocationRecord {
+                    invocation_id: format!("inv-{idx}"),
+                    chat_id: "chat-audit".into(),
+                    thread_id: thread_id.into(),
+                    turn_id: "turn-1".into(),
+                    tool_call_id: format!("call-{idx}"),
+                    tool: tool.into(),
+                    args: json!({ "path": "." }),
+                    args_hash: "abc".into(),
+                    duration_ms: 5,
+                    outcome: "ok".into(),
+                    approver: Some("execpolicy".into()),
+                    created_at: 100 + idx as u64,
+                })
+                .unwrap();
+        }
+
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store,
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.audit",
+                Some(json!({ "chat_id": "chat-audit" })),
+            )
+            .await;
+        let result = resp.result.expect("audit result");
+        assert_eq!(result["thread_id"], "thread-audit");
+        let tools: Vec<_> = result["invocations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|inv| inv["tool"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(tools, vec!["read", "ls"]);
+        assert_eq!(result["invocations"][0]["approver"], "execpolicy");
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.audit",
+                Some(json!({ "chat_id": "missing" })),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn chat_usage_summary_totals_codex_usage_reports() {
+        let store = make_store();
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: "thread-usage".to_string(),
+                thread_id: "thread-usage".to_string(),
+                created_at: chrono_now(),
+                status: SessionStatus::Active,
+                event_pointer: 0,
+                settings: Some(json!({ "model": "gpt-5.1-codex" })),
+                owner: None,
+            })
+            .unwrap();
+        for turn_id in ["turn-1", "turn-1", "turn-2"] {
+            record_codex_usage(
+                store.as_ref(),
+                None,
+                &json!({
+                    "threadId": "thread-usage",
+                    "turnId": turn_id,
+                    "tokenUsage": {
+                        "total": { "inputTokens": 999, "outputTokens": 999, "totalTokens": 1998 },
+                        "last": { "inputTokens": 120, "outputTokens": 30, "totalTokens": 150 },
+                    },
+                }),
+            );
+        }
+
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store,
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.usage.summary",
+                Some(json!({ "chat_id": "thread-usage", "group_by": "model" })),
+            )
+            .await;
+        let result = resp.result.expect("usage result");
+        assert_eq!(result["buckets"][0]["key"], "gpt-5.1-codex");
+        assert_eq!(result["buckets"][0]["input"], 360);
+        assert_eq!(result["grand_total"]["total"], 450);
+
+        let resp = svc
+            .handle_request(Uuid::new_v4(), "chat.usage.summary", None)
+            .await;
+        let result = resp.result.expect("usage result");
+        assert_eq!(result["buckets"].as_array().unwrap().len(), 1);
+        assert_eq!(result["grand_total"]["output"], 90);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.usage.summary",
+                Some(json!({ "group_by": "week" })),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn chat_usage_summary_refuses_other_identities_chats() {
+        let store = make_store();
+        for (chat_id, owner) in [("alice-chat", "alice"), ("bob-chat", "bob")] {
+            store
+                .upsert_chat(&ChatRecord {
+                    chat_id: chat_id.to_string(),
+                    thread_id: chat_id.to_string(),
+                    created_at: chrono_now(),
+                    status: SessionStatus::Active,
+                    event_pointer: 0,
+                    settings: None,
+                    owner: Some(owner.to_string()),
+                })
+                .unwrap();
+        }
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store,
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let mut context = ConnectionContext::new(Role::User);
+        context.principal = Some("alice".to_string());
+        svc.attach(Arc::new(context));
+
+        let summary = |chat_id: &str| json!({ "chat_id": chat_id });
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.usage.summary",
+                Some(summary("alice-chat")),
+            )
+            .await;
+        assert!(resp.result.is_some());
+        for chat_id in ["bob-chat", "no-such-chat"] {
+            let resp = svc
+                .handle_request(Uuid::new_v4(), "chat.usage.summary", Some(summary(chat_id)))
+                .await;
+            assert_eq!(resp.error.expect("error").code, error_codes::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_attachment_read_refuses_other_identities_attachments() {
+        let store = make_store();
+        for (chat_id, owner) in [("alice-chat", "alice"), ("bob-chat", "bob")] {
+            store
+                .upsert_chat(&ChatRecord {
+                    chat_id: chat_id.to_string(),
+                    thread_id: format!("{chat_id}-thread"),
+                    created_at: chrono_now(),
+                    status: SessionStatus::Active,
+                    event_pointer: 0,
+                    settings: None,
+                    owner: Some(owner.to_string()),
+                })
+                .unwrap();
+        }
+        for (attachment_id, thread_id) in [
+            ("alice-png", "alice-chat-thread"),
+            ("bob-png", "bob-chat-thread"),
+            ("orphan-png", "no-such-thread"),
+        ] {
+            store
+                .insert_attachment(&AttachmentRecord {
+                    attachment_id: attachment_id.to_string(),
+                    thread_id: thread_id.to_string(),
+                    mime_type: "image/png".to_string(),
+                    data: vec![1, 2, 3],
+                    created_at: 1,
+                })
+                .unwrap();
+        }
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store,
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let mut context = ConnectionContext::new(Role::User);
+        context.principal = Some("alice".to_string());
+        svc.attach(Arc::new(context));
+
+        let read = |attachment_id: &str| json!({ "attachment_id": attachment_id });
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.attachment.read",
+                Some(read("alice-png")),
+            )
+            .await;
+        assert_eq!(resp.result.expect("result")["data"], "AQID");
+        for attachment_id in ["bob-png", "orphan-png"] {
+            let resp = svc
+                .handle_request(
+                    Uuid::new_v4(),
+                    "chat.attachment.read",
+                    Some(read(attachment_id)),
+                )
+                .await;
+            assert_eq!(resp.error.expect("error").code, error_codes::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn attaching_restores_released_runs_of_the_callers_chats() {
+        let store = make_store();
+        for (chat_id, owner) in [("alice-chat", "alice"), ("bob-chat", "bob")] {
+            store
+                .upsert_chat(&ChatRecord {
+                    chat_id: chat_id.to_string(),
+                    thread_id: chat_id.to_string(),
+                    created_at: chrono_now(),
+                    status: SessionStatus::Active,
+                    event_pointer: 0,
+                    settings: None,
+                    owner: Some(owner.to_string()),
+                })
+                .unwrap();
+            store
+                .insert_pending_run(&PendingRunRecord {
+                    turn_id: format!("{chat_id}-turn"),
+                    chat_id: chat_id.to_string(),
+                    thread_id: chat_id.to_string(),
+                    message: "queued before restart".to_string(),
+                    settings: None,
+                    queued_at: 0,
+                })
+                .unwrap();
+        }
+        store.release_all_pending_runs().unwrap();
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store.clone(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let mut context = ConnectionContext::new(Role::User);
+        context.principal = Some("alice".to_string());
+        svc.attach(Arc::new(context));
+
+        // Alice's run is restored without a chat.resume (its turn is gone,
+        // so it is dropped); Bob's waits for a connection of his own.
+        let restored = async {
+            while store.list_released_pending_run_chats().unwrap() != ["bob-chat"] {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), restored)
+            .await
+            .expect("alice's run restored");
+        assert!(store.take_pending_runs("alice-chat").unwrap().is_empty());
+        assert_eq!(store.take_pending_runs("bob-chat").unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn chat_thread_read_recovers_from_invalid_persisted_thread_state() {
+        let thread_id = "thread-invalid-state";
+        let chat_id = "chat-invalid-state";
+        let settings = json!({
+            "model": "openai-codex:gpt-5.1-codex",
+        });
+        let store = make_store();
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: chat_id.to_string(),
+                thread_id: thread_id.to_string(),
+                created_at: chrono_now(),
+                status: SessionStatus::Active,
+                event_pointer: 0,
+                settings: Some(settings.clone()),
+                owner: None,
+            })
+            .unwrap();
+        store
+            .upsert_chat_thread_state(thread_id, &json!({ "invalid": true }))
+            .unwrap();
+
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store,
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.thread.read",
+                Some(json!({
+                    "chat_id": chat_id,
+                    "include_turns": true,
+                })),
+            )
+            .await;
+
+        assert!(resp.error.is_none());
+        let result = resp.result.expect("thread read result");
+        assert_eq!(result["thread"]["id"], thread_id);
+        assert_eq!(result["settings"], settings);
+    }
+
+    #[tokio::test]
+    async fn chat_tools_list_returns_expected_shape() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let id = Uuid::new_v4();
+        let resp = svc
+            .handle_request(id, "chat.tools.list", Some(json!({ "channel": "web" })))
+            .await;
+        assert!(resp.error.is_none());
+        let result = resp.result.expect("result");
+        let data = result["data"].as_array().expect("data array");
+        assert!(!data.is_empty());
+        let read = data
+            .iter()
+            .find(|tool| tool.get("name").and_then(|v| v.as_str()) == Some("read"))
+            .expect("read tool");
+        assert_eq!(read["provider"], "core");
+        assert_eq!(read["provider_dynamic"], false);
+        assert!(read["input_schema"].is_object());
+    }
+
+    #[tokio::test]
+    async fn chat_tools_list_applies_channel_gating() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut config = HomieConfig::default();
+        config.tools.providers.insert(
+            "core".to_string(),
+            crate::homie_config::ToolProviderConfig {
+                enabled: Some(true),
+                channels: vec!["mobile".to_string()],
+                allow_tools: Vec::new(),
+                deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
+            },
+        );
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(config),
+            Arc::new(ExecPolicy::empty()),
+        );
+
+        let web_resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.list",
+                Some(json!({ "channel": "web" })),
+            )
+            .await;
+        assert!(web_resp.error.is_none());
+        let web_tools = web_resp.result.expect("web result")["data"]
+            .as_array()
+            .expect("web data")
+            .clone();
+        assert!(web_tools.is_empty());
+
+        let mobile_resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.list",
+                Some(json!({ "channel": "mobile" })),
+            )
+            .await;
+        assert!(mobile_resp.error.is_none());
+        let mobile_tools = mobile_resp.result.expect("mobile result")["data"]
+            .as_array()
+            .expect("mobile data")
+            .clone();
+        assert!(mobile_tools
+            .iter()
+            .any(|tool| tool.get("provider").and_then(|v| v.as_str()) == Some("core")));
+    }
+
+    #[tokio::test]
+    async fn chat_tools_list_denies_unknown_or_undefined_channel() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let missing_resp = svc
+            .handle_request(Uuid::new_v4(), "chat.tools.list", None)
+            .await;
+        assert!(missing_resp.result.is_none());
+        let missing_error = missing_resp.error.expect("missing error");
+        assert_eq!(missing_error.code, error_codes::INVALID_PARAMS);
+        assert!(missing_error.message.contains(TOOL_CHANNEL_DENIED_CODE));
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.list",
+                Some(json!({ "channel": "discord" })),
+            )
+            .await;
+        assert!(resp.result.is_none());
+        let error = resp.error.expect("error");
+        assert_eq!(error.code, error_codes::INVALID_PARAMS);
+        assert!(error.message.contains(TOOL_CHANNEL_DENIED_CODE));
+    }
+
+    #[tokio::test]
+    async fn chat_tools_list_denies_channel_mismatch_with_bound_connection_channel() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new_with_channel(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+            Some("web".to_string()),
+        );
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.list",
+                Some(json!({ "channel": "mobile" })),
+            )
+            .await;
+        assert!(resp.result.is_none());
+        let error = resp.error.expect("error");
+        assert_eq!(error.code, error_codes::INVALID_PARAMS);
+        assert!(error.message.contains(TOOL_CHANNEL_DENIED_CODE));
+    }
+
+    #[tokio::test]
+    async fn chat_tools_describe_adds_examples_and_channels() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.describe",
+                Some(json!({ "name": "read", "channel": "web" })),
+            )
+            .await;
+        let result = resp.result.expect("result");
+        assert_eq!(result["name"], "read");
+        assert_eq!(result["provider"], "core");
+        assert!(result["input_schema"].is_object());
+        assert_eq!(
+            result["output_schema"]["properties"]["content"]["type"],
+            "string"
+        );
+        assert!(!result["examples"].as_array().expect("examples").is_empty());
+        assert_eq!(result["channels"], json!(["web", "mobile", "whatsapp"]));
+        assert!(result.get("provider_reachable").is_none());
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.describe",
+                Some(json!({ "name": "teleport", "channel": "web" })),
+            )
+            .await;
+        assert_eq!(
+            resp.error.expect("error").code,
+            error_codes::METHOD_NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_process_list_and_kill_validate_ids() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+
+        let resp = svc
+            .handle_request(Uuid::new_v4(), "chat.process.list", None)
+            .await;
+        assert_eq!(resp.result.expect("result"), json!({ "processes": [] }));
+
+        let resp = svc
+            .handle_request(Uuid::new_v4(), "chat.process.kill", Some(json!({})))
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::INVALID_PARAMS);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.process.kill",
+                Some(json!({ "process_id": "missing" })),
+            )
+            .await;
+        let error = resp.error.expect("error");
+        assert_eq!(error.code, error_codes::INVALID_PARAMS);
+        assert!(error.message.contains("process not found"));
+    }
+
+    #[tokio::test]
+    async fn chat_approvals_pending_lists_nothing_for_an_idle_chat() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+
+        let resp = svc
+            .handle_request(Uuid::new_v4(), "chat.approvals.pending", Some(json!({})))
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::INVALID_PARAMS);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.approvals.pending",
+                Some(json!({ "chat_id": "chat-1", "thread_id": "thread-1" })),
+            )
+            .await;
+        let result = resp.result.expect("result");
+        assert_eq!(result["thread_id"], "thread-1");
+        assert_eq!(result["approvals"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn chat_message_send_rejects_models_outside_the_role_allowlist() {
+        let mut config = HomieConfig::default();
+        config.models.allow = vec!["openai-codex:*".into()];
+        config
+            .models
+            .allow_by_role
+            .insert("viewer".into(), vec!["openai-compatible:*".into()]);
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(config),
+            Arc::new(ExecPolicy::empty()),
+        );
+        svc.attach(Arc::new(ConnectionContext::new(Role::Viewer)));
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.message.send",
+                Some(json!({
+                    "chat_id": "chat-1",
+                    "message": "hi",
+                    "model": "openai-codex:gpt-5.1-codex",
+                })),
+            )
+            .await;
+        let error = resp.error.expect("error");
+        assert_eq!(error.code, error_codes::INVALID_PARAMS);
+        assert!(error.message.contains("model not allowed"));
+    }
+
+    #[tokio::test]
+    async fn chat_runs_check_the_bound_or_default_model_against_the_allowlist() {
+        let mut config = HomieConfig::default();
+        config
+            .models
+            .allow_by_role
+            .insert("viewer".into(), vec!["openai-compatible:*".into()]);
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(config),
+            Arc::new(ExecPolicy::empty()),
+        );
+        svc.attach(Arc::new(ConnectionContext::new(Role::Viewer)));
+        let created = svc
+            .handle_request(Uuid::new_v4(), "chat.create", Some(json!({})))
+            .await
+            .result
+            .expect("chat created");
+        let chat_id = created["chat_id"].as_str().expect("chat_id");
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.settings.update",
+                Some(json!({
+                    "chat_id": chat_id,
+                    "settings": { "model": "openai-codex:gpt-5.1-codex" },
+                })),
+            )
+            .await;
+        let error = resp.error.expect("settings error");
+        assert_eq!(error.code, error_codes::INVALID_PARAMS);
+        assert!(error.message.contains("model not allowed"));
+
+        // No model on the message or chat: the backend default is checked.
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.message.send",
+                Some(json!({ "chat_id": chat_id, "message": "hi" })),
+            )
+            .await;
+        let error = resp.error.expect("send error");
+        assert_eq!(error.code, error_codes::INVALID_PARAMS);
+        assert!(
+            error.message.contains("model not allowed"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_message_send_refuses_runs_over_a_blocking_token_budget() {
+        let mut config = HomieConfig::default();
+        config.chat.token_budget.limit = 1_000_000;
+        config
+            .chat
+            .token_budget
+            .limit_by_role
+            .insert("viewer".into(), 100);
+        config.chat.token_budget.mode = TokenBudgetMode::Block;
+        let store = make_store();
+        record_codex_usage(
+            store.as_ref(),
+            None,
+            &json!({
+                "threadId": "chat-1",
+                "turnId": "turn-1",
+                "tokenUsage": { "last": { "inputTokens": 90, "outputTokens": 30 } },
+            }),
+        );
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(tx, store, Arc::new(config), Arc::new(ExecPolicy::empty()));
+        svc.attach(Arc::new(ConnectionContext::new(Role::Viewer)));
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.message.send",
+                Some(json!({ "chat_id": "chat-1", "message": "hi" })),
+            )
+            .await;
+        let error = resp.error.expect("error");
+        assert_eq!(error.code, error_codes::BUDGET_EXCEEDED);
+        let data = error.data.expect("budget data");
+        assert_eq!(data["limit"], 100);
+        assert_eq!(data["used"], 120);
+        assert_eq!(data["remaining"], 0);
+        assert_eq!(data["scope"], "chat");
+        // Waiting does not help until the window moves on.
+        assert_eq!(error.retryable, Some(false));
+    }
+
+    #[tokio::test]
+    async fn chat_message_regenerate_checks_the_token_budget_before_truncating() {
+        let mut config = HomieConfig::default();
+        config.chat.token_budget.limit = 100;
+        config.chat.token_budget.mode = TokenBudgetMode::Block;
+        let store = make_store();
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            store.clone(),
+            Arc::new(config),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let created = svc
+            .handle_request(Uuid::new_v4(), "chat.create", None)
+            .await
+            .result
+            .expect("result");
+        let chat_id = created["chat_id"].as_str().expect("chat_id").to_string();
+        store
+            .add_turn_usage(&TurnUsageRecord {
+                chat_id: chat_id.clone(),
+                thread_id: chat_id.clone(),
+                turn_id: "turn-1".into(),
+                identity: None,
+                model: None,
+                input: 90,
+                output: 30,
+                total: 120,
+                created_at: unix_now(),
+            })
+            .expect("usage");
+
+        // The budget is refused before the turn is looked up and dropped.
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.message.regenerate",
+                Some(json!({ "chat_id": chat_id, "turn_id": "no-such-turn" })),
+            )
+            .await;
+        assert_eq!(
+            resp.error.expect("error").code,
+            error_codes::BUDGET_EXCEEDED
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_tools_invoke_runs_tool_and_reports_errors() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut config = HomieConfig::default();
+        config.tools.fs.allowed_roots = vec![std::env::temp_dir().to_string_lossy().to_string()];
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(config),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let path = std::env::temp_dir().join(format!("homie-invoke-{}.txt", Uuid::new_v4()));
+        std::fs::write(&path, "hello from tools.invoke\n").unwrap();
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.invoke",
+                Some(json!({
+                    "name": "read",
+                    "input": { "path": path.to_string_lossy() },
+                    "channel": "web",
+                })),
+            )
+            .await;
+        let result = resp.result.expect("result");
+        assert_eq!(result["is_error"], false);
+        assert!(result["result"]
+            .to_string()
+            .contains("hello from tools.invoke"));
+        std::fs::remove_file(&path).ok();
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.invoke",
+                Some(json!({
+                    "name": "read",
+                    "input": { "path": path.to_string_lossy() },
+                    "channel": "web",
+                })),
+            )
+            .await;
+        assert_eq!(resp.result.expect("result")["is_error"], true);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.invoke",
+                Some(json!({ "name": "teleport", "channel": "web" })),
+            )
+            .await;
+        assert_eq!(
+            resp.error.expect("error").code,
+            error_codes::METHOD_NOT_FOUND
+        );
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.invoke",
+                Some(json!({
+                    "name": "exec",
+                    "input": { "command": "echo hi" },
+                    "channel": "web",
+                })),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::UNAUTHORIZED);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.invoke",
+                Some(json!({
+                    "name": "apply_patch",
+                    "input": { "patch": "" },
+                    "channel": "web",
+                })),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn chat_tools_invoke_honors_channel_gating() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut config = HomieConfig::default();
+        config.tools.providers.insert(
+            "core".to_string(),
+            crate::homie_config::ToolProviderConfig {
+                enabled: Some(true),
+                channels: vec!["mobile".to_string()],
+                allow_tools: Vec::new(),
+                deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
+            },
+        );
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(config),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.invoke",
+                Some(json!({ "name": "ls", "input": {}, "channel": "web" })),
             )
             .await;
-        assert!(resp.result.is_none());
         let error = resp.error.expect("error");
         assert_eq!(error.code, error_codes::INVALID_PARAMS);
         assert!(error.message.contains(TOOL_CHANNEL_DENIED_CODE));
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.invoke",
+                Some(json!({ "name": "ls", "input": { "depth": 1 }, "channel": "mobile" })),
+            )
+            .await;
+        assert!(resp.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn chat_tools_register_adds_session_tool() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let url = "https://tools.example.com/orders";
+        let schema = json!({
+            "type": "object",
+            "properties": { "order_id": { "type": "string" } },
+            "required": ["order_id"],
+        });
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.register",
+                Some(json!({
+                    "name": "lookup_order",
+                    "description": "Look up an order",
+                    "input_schema": schema,
+                    "url": url,
+                })),
+            )
+            .await;
+        assert!(resp.error.is_none(), "{:?}", resp.error);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.list",
+                Some(json!({ "channel": "web" })),
+            )
+            .await;
+        let result = resp.result.expect("result");
+        let tool = result["data"]
+            .as_array()
+            .expect("data array")
+            .iter()
+            .find(|tool| tool["name"] == "lookup_order")
+            .expect("registered tool listed")
+            .clone();
+        assert_eq!(tool["provider"], "session");
+        assert_eq!(tool["description"], "Look up an order");
+        assert_eq!(tool["input_schema"], schema);
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.tools.invoke",
+                Some(json!({
+                    "name": "lookup_order",
+                    "input": { "order_id": "A-1" },
+                    "channel": "web",
+                })),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn chat_tools_register_validates_spec_and_name() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(HomieConfig::default()),
+            Arc::new(ExecPolicy::empty()),
+        );
+        let valid = json!({
+            "name": "lookup_order",
+            "description": "Look up an order",
+            "input_schema": { "type": "object" },
+            "url": "https://tools.example.com/orders",
+        });
+        let invalid = [
+            json!({ "input_schema": "not a schema" }),
+            json!({ "url": "file:///etc/passwd" }),
+            json!({ "name": "read" }),
+            json!({ "url": null }),
+        ];
+        for overrides in invalid {
+            let mut params = valid.clone();
+            for (key, value) in overrides.as_object().unwrap() {
+                params[key] = value.clone();
+            }
+            let resp = svc
+                .handle_request(Uuid::new_v4(), "chat.tools.register", Some(params.clone()))
+                .await;
+            assert_eq!(
+                resp.error.expect("error").code,
+                error_codes::INVALID_PARAMS,
+                "{params}"
+            );
+        }
+
+        // Re-registering a session tool replaces it.
+        for _ in 0..2 {
+            let resp = svc
+                .handle_request(Uuid::new_v4(), "chat.tools.register", Some(valid.clone()))
+                .await;
+            assert!(resp.error.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_account_logout_clears_profile_and_blocks_cli_import() {
+        let (tx, _rx) = mpsc::channel::<OutboundMessage>(16);
+        let mut config = HomieConfig::default();
+        let tmp_dir = std::env::temp_dir().join(format!("homie-logout-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        config.paths.credentials_dir = Some(tmp_dir.to_string_lossy().to_string());
+        config.providers.claude_code.enabled = false;
+        let mut svc = ChatService::new(
+            tx,
+            make_store(),
+            Arc::new(config),
+            Arc::new(ExecPolicy::empty()),
+        );
+
+        for params in [
+            json!({}),
+            json!({ "provider": "nope" }),
+            json!({ "provider": "claude-code" }),
+        ] {
+            let resp = svc
+                .handle_request(Uuid::new_v4(), "chat.account.logout", Some(params))
+                .await;
+            assert_eq!(resp.error.expect("error").code, error_codes::INVALID_PARAMS);
+        }
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "chat.account.logout",
+                Some(json!({ "provider": "github_copilot", "profile": "default" })),
+            )
+            .await;
+        assert_eq!(resp.result.expect("result"), json!({ "ok": true }));
+        assert!(tmp_dir.join(".github-copilot.logged-out").exists());
+
+        let list = svc
+            .handle_request(Uuid::new_v4(), "chat.account.list", None)
+            .await
+            .result
+            .expect("result");
+        let copilot = list["providers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|p| p["id"] == "github-copilot")
+            .expect("copilot status");
+        assert_eq!(copilot["logged_in"], false);
+        let _ = std::fs::remove_dir_all(&tmp_dir);
     }
 
     #[tokio::test]
@@ -0,0 +1,530 @@
+//! Chunked file uploads over binary frames.
+//!
+//! `chat.file.upload.begin` registers an upload and returns its id; the
+//! client then sends `StreamType::Upload` binary frames whose `session_id`
+//! is that id, in order; `chat.file.upload.commit` checks the size and
+//! SHA-256 and moves the file to its final path under the Homie home.
+//! Each upload has a writer task that appends its chunks with `tokio::fs`
+//! as they arrive, so the connection never blocks on disk. Uploads left
+//! idle past [`UPLOAD_IDLE_TIMEOUT`] are discarded, and committed files are
+//! deleted after [`COMMITTED_UPLOAD_RETENTION`].
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::paths::{create_private_dir, homie_uploads_dir};
+
+/// Largest file a single upload may declare.
+pub(super) const MAX_UPLOAD_BYTES: u64 = 64 * 1024 * 1024;
+/// Uploads a connection may have open at once.
+const MAX_PENDING_UPLOADS: usize = 8;
+/// How long an unfinished upload may go without a chunk before it is
+/// dropped, including partial files a previous process left behind.
+const UPLOAD_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// How long a committed upload is kept before it is deleted.
+const COMMITTED_UPLOAD_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A finished upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct CommittedUpload {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// What an upload's writer task hands back once its chunks are on disk.
+type Written = Result<(tokio::fs::File, Sha256), String>;
+
+struct PendingUpload {
+    dir: PathBuf,
+    name: String,
+    size: u64,
+    sha256: String,
+    /// Feeds the writer task; `None` once the upload failed.
+    chunks: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    /// The writer task; taken once commit has awaited it.
+    writer: Option<JoinHandle<Written>>,
+    /// Bytes handed to the writer so far.
+    received: u64,
+    /// First chunk failure; reported by commit.
+    error: Option<String>,
+    /// When the upload began or last received a chunk.
+    touched: Instant,
+}
+
+impl PendingUpload {
+    fn part_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.part", self.name))
+    }
+
+    fn queue_chunk(&mut self, chunk: &[u8]) -> Result<(), String> {
+        let received = self.received.saturating_add(chunk.len() as u64);
+        if received > self.size {
+            return Err(format!(
+                "upload exceeds declared size of {} bytes",
+                self.size
+            ));
+        }
+        let chunks = self.chunks.as_ref().ok_or("upload file is closed")?;
+        // A closed channel means the writer failed; commit reports why.
+        let _ = chunks.send(chunk.to_vec());
+        self.received = received;
+        self.touched = Instant::now();
+        Ok(())
+    }
+
+    /// Stop the writer and delete the upload's directory once the writer
+    /// has let go of the file.
+    fn discard(self) {
+        drop(self.chunks);
+        let (writer, dir) = (self.writer, self.dir);
+        match (writer, tokio::runtime::Handle::try_current()) {
+            (Some(writer), Ok(handle)) => {
+                writer.abort();
+                handle.spawn(async move {
+                    let _ = writer.await;
+                    let _ = tokio::fs::remove_dir_all(&dir).await;
+                });
+            }
+            (writer, _) => {
+                if let Some(writer) = writer {
+                    writer.abort();
+                }
+                let _ = fs::remove_dir_all(&dir);
+            }
+        }
+    }
+}
+
+/// Append every chunk to `file` in order, hashing as it goes.
+async fn write_upload(
+    mut file: tokio::fs::File,
+    mut chunks: mpsc::UnboundedReceiver<Vec<u8>>,
+) -> Written {
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = chunks.recv().await {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("write upload chunk: {e}"))?;
+        hasher.update(&chunk);
+    }
+    Ok((file, hasher))
+}
+
+/// A connection's in-progress uploads. Clones share the same uploads, so
+/// the chat service can feed binary frames while requests begin and commit.
+#[derive(Clone)]
+pub(super) struct FileUploads {
+    root: PathBuf,
+    idle_timeout: Duration,
+    retention: Duration,
+    pending: Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
+}
+
+impl Default for FileUploads {
+    /// Uploads go under the Homie home; without one, under a fresh private
+    /// directory in the system temp dir rather than a shared one.
+    fn default() -> Self {
+        let root = homie_uploads_dir().unwrap_or_else(|e| {
+            tracing::warn!("uploads fall back to a temp dir: {e}");
+            std::env::temp_dir().join(format!("homie-uploads-{}", Uuid::new_v4()))
+        });
+        Self::new(root)
+    }
+}
+
+impl FileUploads {
+    /// Store uploads under `root`, one directory per upload.
+    pub(super) fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            idle_timeout: UPLOAD_IDLE_TIMEOUT,
+            retention: COMMITTED_UPLOAD_RETENTION,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    #[cfg(test)]
+    fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    #[cfg(test)]
+    fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Drop this connection's uploads that have gone idle.
+    fn expire_idle(&self, pending: &mut HashMap<Uuid, PendingUpload>) {
+        let stale: Vec<Uuid> = pending
+            .iter()
+            .filter(|(_, upload)| upload.touched.elapsed() >= self.idle_timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in stale {
+            if let Some(upload) = pending.remove(&id) {
+                tracing::debug!(upload_id = %id, "discarding idle upload");
+                upload.discard();
+            }
+        }
+    }
+
+    /// Start an upload of `size` bytes named `name` that must hash to
+    /// `sha256` (hex). Returns the id its chunks are tagged with.
+    pub(super) async fn begin(&self, name: &str, size: u64, sha256: &str) -> Result<Uuid, String> {
+        let name = upload_file_name(name)?;
+        if size > MAX_UPLOAD_BYTES {
+            return Err(format!("upload exceeds {MAX_UPLOAD_BYTES} bytes"));
+        }
+        let sha256 = sha256.trim().to_ascii_lowercase();
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("sha256 must be 64 hex characters".into());
+        }
+        {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            self.expire_idle(&mut pending);
+            if pending.len() >= MAX_PENDING_UPLOADS {
+                return Err(format!(
+                    "too many uploads in progress (max {MAX_PENDING_UPLOADS})"
+                ));
+            }
+        }
+
+        let upload_id = Uuid::new_v4();
+        let (root, idle_timeout, retention) =
+            (self.root.clone(), self.idle_timeout, self.retention);
+        let dir = tokio::task::spawn_blocking(move || {
+            sweep_upload_dirs(&root, idle_timeout, retention);
+            create_private_dir(&root)?;
+            let dir = root.join(upload_id.to_string());
+            fs::create_dir_all(&dir)?;
+            Ok::<_, std::io::Error>(dir)
+        })
+        .await
+        .map_err(|e| format!("create upload dir: {e}"))?
+        .map_err(|e| format!("create upload dir: {e}"))?;
+        let part_path = dir.join(format!("{name}.part"));
+        let file = match tokio::fs::File::create(&part_path).await {
+            Ok(file) => file,
+            Err(e) => {
+                let _ = tokio::fs::remove_dir_all(&dir).await;
+                return Err(format!("create upload file: {e}"));
+            }
+        };
+        let (chunks, rx) = mpsc::unbounded_channel();
+        let upload = PendingUpload {
+            dir,
+            name,
+            size,
+            sha256,
+            chunks: Some(chunks),
+            writer: Some(tokio::spawn(write_upload(file, rx))),
+            received: 0,
+            error: None,
+            touched: Instant::now(),
+        };
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.len() >= MAX_PENDING_UPLOADS {
+            upload.discard();
+            return Err(format!(
+                "too many uploads in progress (max {MAX_PENDING_UPLOADS})"
+            ));
+        }
+        pending.insert(upload_id, upload);
+        Ok(upload_id)
+    }
+
+    /// Queue one chunk for the upload's writer. Failures are kept and
+    /// reported by `commit`; later chunks of a failed upload are dropped.
+    pub(super) fn write_chunk(&self, upload_id: Uuid, chunk: &[u8]) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(upload) = pending.get_mut(&upload_id) else {
+            tracing::debug!(%upload_id, "chunk for unknown upload");
+            return;
+        };
+        if upload.error.is_some() {
+            return;
+        }
+        if let Err(e) = upload.queue_chunk(chunk) {
+            tracing::debug!(%upload_id, "upload failed: {e}");
+            upload.chunks = None;
+            upload.error = Some(e);
+        }
+    }
+
+    /// Finish an upload: every declared byte must have been written and
+    /// match the checksum. The upload is removed either way.
+    pub(super) async fn commit(&self, upload_id: Uuid) -> Result<CommittedUpload, String> {
+        let mut upload = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&upload_id)
+            .ok_or_else(|| format!("unknown upload: {upload_id}"))?;
+        if let Some(error) = upload.error.clone() {
+            upload.discard();
+            return Err(error);
+        }
+        if upload.received != upload.size {
+            let error = format!(
+                "upload incomplete: received {} of {} bytes",
+                upload.received, upload.size
+            );
+            upload.discard();
+            return Err(error);
+        }
+
+        // Every chunk is queued; closing the channel lets the writer finish.
+        upload.chunks = None;
+        let written = match upload.writer.take() {
+            Some(writer) => writer
+                .await
+                .unwrap_or_else(|e| Err(format!("upload writer failed: {e}"))),
+            None => Err("upload file is closed".into()),
+        };
+        let (file, hasher) = match written {
+            Ok(written) => written,
+            Err(e) => {
+                upload.discard();
+                return Err(e);
+            }
+        };
+        let digest = hex(&hasher.finalize());
+        if digest != upload.sha256 {
+            drop(file);
+            upload.discard();
+            return Err(format!("checksum mismatch: got sha256 {digest}"));
+        }
+        if let Err(e) = file.sync_all().await {
+            drop(file);
+            upload.discard();
+            return Err(format!("flush upload: {e}"));
+        }
+        drop(file);
+        let path = upload.dir.join(&upload.name);
+        if let Err(e) = tokio::fs::rename(upload.part_path(), &path).await {
+            upload.discard();
+            return Err(format!("finalize upload: {e}"));
+        }
+        Ok(CommittedUpload {
+            path,
+            size: upload.size,
+            sha256: upload.sha256,
+        })
+    }
+
+    /// Drop every unfinished upload and its partial file.
+    pub(super) fn clear(&self) {
+        let uploads: Vec<PendingUpload> = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain()
+            .map(|(_, upload)| upload)
+            .collect();
+        for upload in uploads {
+            upload.discard();
+        }
+    }
+}
+
+/// Delete upload directories under `root` nothing has written to for a
+/// while: partial uploads after `idle_timeout`, such as ones a crashed
+/// process left behind, and committed ones after `retention`.
+fn sweep_upload_dirs(root: &Path, idle_timeout: Duration, retention: Duration) {
+    let Ok(entries) = fs::read_dir(root) else {
+        return;
+    };
+    let now = SystemTime::now();
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    for dir in entries.flatten().map(|entry| entry.path()) {
+        let Ok(files) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut partial = false;
+        let mut newest = modified(&dir);
+        for file in files.flatten().map(|entry| entry.path()) {
+            partial |= file.extension().is_some_and(|ext| ext == "part");
+            newest = newest.max(modified(&file));
+        }
+        let keep_for = if partial { idle_timeout } else { retention };
+        let expired = newest
+            .and_then(|at| now.duration_since(at).ok())
+            .is_some_and(|age| age >= keep_for);
+        if expired {
+            let _ = fs::remove_dir_all(&dir);
+        }
+    }
+}
+
+/// The final path component of `name`, so uploads cannot escape their
+/// directory.
+fn upload_file_name(name: &str) -> Result<String, String> {
+    let file_name = Path::new(name.trim())
+        .file_name()
+        .and_then(|n| n.to_str())
+        .filter(|n| !n.is_empty())
+        .ok_or("name must be a file name")?;
+    Ok(file_name.to_string())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uploads() -> (FileUploads, PathBuf) {
+        let root = std::env::temp_dir().join(format!("homie-uploads-{}", Uuid::new_v4()));
+        (FileUploads::new(root.clone()), root)
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        hex(&Sha256::digest(data))
+    }
+
+    /// Discarded uploads are deleted in the background; wait until `root`
+    /// holds `expected` upload directories.
+    async fn settle(root: &Path, expected: usize) {
+        for _ in 0..200 {
+            if fs::read_dir(root).map_or(0, |dirs| dirs.count()) == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(fs::read_dir(root).unwrap().count(), expected);
+    }
+
+    #[tokio::test]
+    async fn assembles_chunks_and_verifies_checksum() {
+        let (uploads, root) = uploads();
+        let data = b"hello upload world";
+        let id = uploads
+            .begin("../notes.txt", data.len() as u64, &sha256_hex(data))
+            .await
+            .unwrap();
+        for chunk in data.chunks(5) {
+            uploads.write_chunk(id, chunk);
+        }
+        let committed = uploads.commit(id).await.unwrap();
+        assert_eq!(committed.path, root.join(id.to_string()).join("notes.txt"));
+        assert_eq!(fs::read(&committed.path).unwrap(), data);
+        assert!(uploads.commit(id).await.is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_uploads() {
+        let (uploads, root) = uploads();
+        let data = b"abc";
+        let sha = sha256_hex(data);
+        assert!(uploads
+            .begin("big.bin", MAX_UPLOAD_BYTES + 1, &sha)
+            .await
+            .is_err());
+        assert!(uploads.begin("x.txt", 3, "nothex").await.is_err());
+        assert!(uploads.begin("..", 3, &sha).await.is_err());
+
+        let short = uploads.begin("a.txt", 3, &sha).await.unwrap();
+        uploads.write_chunk(short, b"ab");
+        let err = uploads.commit(short).await.unwrap_err();
+        assert!(err.contains("incomplete"));
+
+        let long = uploads.begin("b.txt", 3, &sha).await.unwrap();
+        uploads.write_chunk(long, b"abcd");
+        uploads.write_chunk(long, b"c");
+        let err = uploads.commit(long).await.unwrap_err();
+        assert!(err.contains("declared size"));
+
+        let wrong = uploads.begin("c.txt", 3, &sha).await.unwrap();
+        uploads.write_chunk(wrong, b"abd");
+        let err = uploads.commit(wrong).await.unwrap_err();
+        assert!(err.contains("checksum"));
+
+        let abandoned = uploads.begin("d.txt", 3, &sha).await.unwrap();
+        uploads.clear();
+        assert!(uploads.commit(abandoned).await.is_err());
+        settle(&root, 0).await;
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn idle_uploads_expire_but_committed_files_stay() {
+        let (uploads, root) = uploads();
+        let data = b"kept";
+        let id = uploads
+            .begin("kept.txt", 4, &sha256_hex(data))
+            .await
+            .unwrap();
+        uploads.write_chunk(id, data);
+        let kept = uploads.commit(id).await.unwrap();
+        let sha = sha256_hex(b"abc");
+        let idle = uploads.begin("idle.txt", 3, &sha).await.unwrap();
+        // Another process's leftover partial upload.
+        let leftover = FileUploads::new(root.clone());
+        leftover.begin("crashed.txt", 3, &sha).await.unwrap();
+
+        let expiring = uploads.clone().with_idle_timeout(Duration::ZERO);
+        let fresh = expiring.begin("fresh.txt", 3, &sha).await.unwrap();
+        let err = expiring.commit(idle).await.unwrap_err();
+        assert!(err.contains("unknown upload"));
+        assert!(kept.path.exists());
+        // The kept upload plus the one just begun.
+        settle(&root, 2).await;
+        expiring.clear();
+        assert!(expiring.commit(fresh).await.is_err());
+        leftover.clear();
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn committed_uploads_expire_after_retention() {
+        let (uploads, root) = uploads();
+        let data = b"old";
+        let id = uploads
+            .begin("old.txt", 3, &sha256_hex(data))
+            .await
+            .unwrap();
+        uploads.write_chunk(id, data);
+        let old = uploads.commit(id).await.unwrap();
+
+        let expiring = uploads.with_retention(Duration::ZERO);
+        expiring
+            .begin("new.txt", 3, &sha256_hex(b"new"))
+            .await
+            .unwrap();
+        assert!(!old.path.exists());
+        settle(&root, 1).await;
+        expiring.clear();
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn upload_root_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (uploads, root) = uploads();
+        uploads
+            .begin("a.txt", 3, &sha256_hex(b"abc"))
+            .await
+            .unwrap();
+        let mode = fs::metadata(&root).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        uploads.clear();
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -49,6 +49,51 @@ struct UpdateChunk {
     eof: bool,
 }
 
+/// Lines one patch adds to and removes from a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchFileStats {
+    /// Path as written in the patch; the destination for moved files.
+    pub path: String,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+/// Per-file line counts of an `apply_patch` patch, in patch order. Every
+/// line of an added file counts as an addition; deleted files count no
+/// lines, since the patch does not carry their contents.
+pub fn patch_file_stats(patch: &str) -> Vec<PatchFileStats> {
+    let mut files: Vec<PatchFileStats> = Vec::new();
+    let mut adding = false;
+    for line in patch.lines() {
+        let trimmed = line.trim_start();
+        let header = [ADD_FILE, DELETE_FILE, UPDATE_FILE]
+            .into_iter()
+            .find_map(|prefix| Some((prefix, trimmed.strip_prefix(prefix)?)));
+        if let Some((prefix, path)) = header {
+            adding = prefix == ADD_FILE;
+            files.push(PatchFileStats {
+                path: path.trim().to_string(),
+                additions: 0,
+                deletions: 0,
+            });
+            continue;
+        }
+        let Some(file) = files.last_mut() else {
+            continue;
+        };
+        if let Some(path) = trimmed.strip_prefix(MOVE_TO) {
+            file.path = path.trim().to_string();
+        } else if trimmed.starts_with("*** ") {
+            continue;
+        } else if adding || line.starts_with('+') {
+            file.additions += 1;
+        } else if line.starts_with('-') {
+            file.deletions += 1;
+        }
+    }
+    files
+}
+
 pub fn apply_patch_tool(ctx: ToolContext) -> Arc<dyn Tool> {
     let params = AgentToolParameters::object()
         .string("patch", "Patch text", true)
@@ -76,6 +121,8 @@ async fn apply_patch_impl(
         .as_deref()
         .and_then(|p| super::fs::resolve_path(p, &ctx.cwd))
         .unwrap_or_else(|| ctx.cwd.clone());
+    let roots = ctx.allowed_roots();
+    let cwd = super::fs::confine_tool_path(ctx, "apply_patch", &cwd)?;
     if super::debug_tools_enabled() {
         tracing::debug!(
             cwd = %cwd.to_string_lossy(),
@@ -87,7 +134,7 @@ async fn apply_patch_impl(
         tool_name: "apply_patch".into(),
         message: e,
     })?;
-    let changes = apply_hunks(&cwd, hunks).map_err(|e| RociError::ToolExecution {
+    let changes = apply_hunks(&cwd, &roots, hunks).map_err(|e| RociError::ToolExecution {
         tool_name: "apply_patch".into(),
         message: e,
     })?;
@@ -255,12 +302,16 @@ fn parse_update(
     Ok((move_path, chunks, idx))
 }
 
-fn apply_hunks(cwd: &Path, hunks: Vec<PatchHunk>) -> Result<Vec<serde_json::Value>, String> {
+fn apply_hunks(
+    cwd: &Path,
+    roots: &[PathBuf],
+    hunks: Vec<PatchHunk>,
+) -> Result<Vec<serde_json::Value>, String> {
     let mut changes = Vec::new();
     for hunk in hunks {
         match hunk {
             PatchHunk::Add { path, contents } => {
-                let file_path = resolve_hunk_path(cwd, &path)?;
+                let file_path = resolve_hunk_path(cwd, roots, &path)?;
                 if file_path.exists() {
                     return Err(format!("file already exists: {}", file_path.display()));
                 }
@@ -274,7 +325,7 @@ fn apply_hunks(cwd: &Path, hunks: Vec<PatchHunk>) -> Result<Vec<serde_json::Valu
                 changes.push(json_change("add", &file_path));
             }
             PatchHunk::Delete { path } => {
-                let file_path = resolve_hunk_path(cwd, &path)?;
+                let file_path = resolve_hunk_path(cwd, roots, &path)?;
                 if !file_path.exists() {
                     return Err(format!("file not found: {}", file_path.display()));
                 }
@@ -286,7 +337,7 @@ fn apply_hunks(cwd: &Path, hunks: Vec<PatchHunk>) -> Result<Vec<serde_json::Valu
                 move_path,
                 chunks,
             } => {
-                let file_path = resolve_hunk_path(cwd, &path)?;
+                let file_path = resolve_hunk_path(cwd, roots, &path)?;
                 let mut content = std::fs::read_to_string(&file_path)
                     .map_err(|e| format!("failed to read file: {e}"))?;
                 let had_trailing_newline = content.ends_with('\n');
@@ -329,7 +380,7 @@ fn apply_hunks(cwd: &Path, hunks: Vec<PatchHunk>) -> Result<Vec<serde_json::Valu
                     .map_err(|e| format!("failed to write file: {e}"))?;
 
                 if let Some(target) = move_path {
-                    let target_path = resolve_hunk_path(cwd, &target)?;
+                    let target_path = resolve_hunk_path(cwd, roots, &target)?;
                     if let Some(parent) = target_path.parent() {
                         std::fs::create_dir_all(parent)
                             .map_err(|e| format!("failed to create dirs: {e}"))?;
@@ -346,16 +397,18 @@ fn apply_hunks(cwd: &Path, hunks: Vec<PatchHunk>) -> Result<Vec<serde_json::Valu
     Ok(changes)
 }
 
-fn resolve_hunk_path(cwd: &Path, raw: &str) -> Result<PathBuf, String> {
+fn resolve_hunk_path(cwd: &Path, roots: &[PathBuf], raw: &str) -> Result<PathBuf, String> {
     let trimmed = raw.trim();
     if trimmed.is_empty() {
         return Err("empty patch path".into());
     }
     let path = PathBuf::from(trimmed);
-    if path.is_absolute() {
-        return Ok(path);
-    }
-    Ok(cwd.join(path))
+    let path = if path.is_absolute() {
+        path
+    } else {
+        cwd.join(path)
+    };
+    super::fs::confine_path(&path, roots)
 }
 
 fn find_anchor(lines: &[String], context: &str, start: usize) -> Option<usize> {
@@ -408,7 +461,38 @@ mod tests {
     use roci::tools::ToolArguments;
     use serde_json::json;
 
-    use super::parse_apply_patch_request;
+    use super::{apply_hunks, parse_apply_patch_request, parse_patch, patch_file_stats};
+
+    #[test]
+    fn patch_file_stats_count_lines_per_file() {
+        let patch = "*** Begin Patch
+*** Add File: notes.md
++# Notes
++hello
+*** Update File: src/lib.rs
+*** Move to: src/core.rs
+@@ fn main
+ fn main() {
+-    old();
++    new();
++    newer();
+ }
+*** Delete File: stale.txt
+*** End Patch";
+        let stats = patch_file_stats(patch);
+        let counts: Vec<(&str, usize, usize)> = stats
+            .iter()
+            .map(|file| (file.path.as_str(), file.additions, file.deletions))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("notes.md", 2, 0),
+                ("src/core.rs", 2, 1),
+                ("stale.txt", 0, 0)
+            ]
+        );
+    }
 
     #[test]
     fn apply_patch_request_accepts_literal_patch() {
@@ -434,4 +518,26 @@ mod tests {
         let err = parse_apply_patch_request(&args).expect_err("empty patch should fail");
         assert_eq!(err.to_string(), "Invalid argument: patch must not be empty");
     }
+
+    #[test]
+    fn hunks_outside_the_allowed_roots_are_rejected() {
+        let root = std::env::temp_dir().join(format!("homie-patch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let roots = vec![root.clone()];
+
+        let escape =
+            parse_patch("*** Begin Patch\n*** Add File: ../escape.txt\n+nope\n*** End Patch")
+                .expect("parse patch");
+        let err = apply_hunks(&root, &roots, escape).expect_err("traversal should fail");
+        assert!(err.contains("outside the allowed roots"), "{err}");
+        assert!(!root.parent().unwrap().join("escape.txt").exists());
+
+        let inside =
+            parse_patch("*** Begin Patch\n*** Add File: notes/new.txt\n+ok\n*** End Patch")
+                .expect("parse patch");
+        apply_hunks(&root, &roots, inside).expect("patch inside root");
+        assert!(root.join("notes/new.txt").exists());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
 }
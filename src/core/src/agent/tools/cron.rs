@@ -152,6 +152,10 @@ fn create_cron(store: &dyn Store, params: CronToolParams) -> Result<serde_json::
         updated_at: now,
         next_run_at: None,
         last_run_at: None,
+        max_retries: 0,
+        backoff_secs: 0,
+        retry_count: 0,
+        next_retry_at: None,
     };
 
     store
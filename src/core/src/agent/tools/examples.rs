@@ -0,0 +1,207 @@
+use serde_json::{json, Value};
+
+/// Hand-written example calls for a core tool, each `{description, input}`.
+/// Tools from other providers have none.
+pub fn tool_examples(name: &str) -> Vec<Value> {
+    let examples: Vec<(&str, Value)> = match name {
+        "read" => vec![
+            ("Read a whole file", json!({ "path": "src/main.rs" })),
+            (
+                "Read 50 lines starting at line 120",
+                json!({ "path": "src/main.rs", "offset": 120, "limit": 50 }),
+            ),
+        ],
+        "ls" => vec![
+            ("List the working directory", json!({})),
+            (
+                "List one level of a subdirectory",
+                json!({ "path": "src", "depth": 1 }),
+            ),
+        ],
+        "find" => vec![(
+            "Find Rust sources under src",
+            json!({ "pattern": "**/*.rs", "path": "src" }),
+        )],
+        "grep" => vec![(
+            "Search TypeScript files for a symbol",
+            json!({ "pattern": "normalizeModelOptions", "include": "*.ts", "limit": 20 }),
+        )],
+        "apply_patch" => vec![(
+            "Replace a line in a file",
+            json!({
+                "patch": "*** Begin Patch\n*** Update File: README.md\n@@\n-old line\n+new line\n*** End Patch",
+            }),
+        )],
+        "exec" => vec![
+            (
+                "Run a command and wait for it",
+                json!({ "command": "cargo test", "timeout": 300 }),
+            ),
+            (
+                "Start a long-running command in the background",
+                json!({ "command": "npm run dev", "background": true }),
+            ),
+        ],
+        "process" => vec![
+            ("List background processes", json!({ "action": "list" })),
+            (
+                "Read the tail of a process's output",
+                json!({ "action": "output", "id": "proc-1", "tail": 4096 }),
+            ),
+        ],
+        "browser" => vec![
+            (
+                "Open a page",
+                json!({ "command": "open https://example.com" }),
+            ),
+            (
+                "Snapshot interactive elements",
+                json!({ "command": "snapshot -i" }),
+            ),
+        ],
+        "cron" => vec![
+            ("List cron jobs", json!({ "action": "list" })),
+            (
+                "Add a nightly job",
+                json!({
+                    "action": "add",
+                    "name": "nightly-backup",
+                    "schedule": "0 3 * * *",
+                    "command": "./scripts/backup.sh",
+                }),
+            ),
+        ],
+        "web_fetch" => vec![(
+            "Fetch a page as markdown",
+            json!({ "url": "https://example.com", "extractMode": "markdown" }),
+        )],
+        "web_search" => vec![(
+            "Search the web",
+            json!({ "query": "rust tokio broadcast channel", "count": 5 }),
+        )],
+        _ => vec![],
+    };
+    examples
+        .into_iter()
+        .map(|(description, input)| json!({ "description": description, "input": input }))
+        .collect()
+}
+
+/// Hand-written JSON schema of what a core tool returns on success. Tools
+/// from other providers declare none, so this is `None` for them.
+pub fn tool_output_schema(name: &str) -> Option<Value> {
+    let object = |properties: Value| json!({ "type": "object", "properties": properties });
+    let schema = match name {
+        "read" => object(json!({
+            "path": { "type": "string" },
+            "offset": { "type": "integer" },
+            "limit": { "type": "integer" },
+            "content": { "type": "string" },
+        })),
+        "ls" => object(json!({
+            "path": { "type": "string" },
+            "entries": {
+                "type": "array",
+                "items": object(json!({
+                    "path": { "type": "string" },
+                    "relative_path": { "type": "string" },
+                    "type": { "type": "string", "enum": ["file", "dir"] },
+                })),
+            },
+        })),
+        "find" | "grep" => object(json!({
+            "path": { "type": "string" },
+            "matches": { "type": "array", "items": { "type": "object" } },
+        })),
+        "apply_patch" => object(json!({
+            "status": { "const": "ok" },
+            "changes": { "type": "array", "items": { "type": "object" } },
+            "diff": { "type": "string" },
+        })),
+        "exec" => object(json!({
+            "status": { "type": "string", "enum": ["completed", "running"] },
+            "exit_code": { "type": ["integer", "null"] },
+            "stdout": { "type": "string" },
+            "stderr": { "type": "string" },
+            "output": { "type": "string", "description": "Combined stdout and stderr in raw format." },
+            "stdout_truncated": { "type": "boolean" },
+            "stderr_truncated": { "type": "boolean" },
+            "output_truncated": { "type": "boolean" },
+            "duration_ms": { "type": "integer" },
+            "cwd": { "type": "string" },
+            "command": { "type": "string" },
+            "pid": { "type": ["integer", "null"] },
+            "process_id": {
+                "type": ["string", "null"],
+                "description": "Set for background commands and for output too long to return inline; read it with the process tool.",
+            },
+        })),
+        "process" => object(json!({
+            "processes": { "type": "array", "items": { "type": "object" } },
+            "id": { "type": "string" },
+            "status": { "type": "string", "enum": ["running", "exited", "killed"] },
+            "command": { "type": "string" },
+            "cwd": { "type": "string" },
+            "started_at": { "type": "string" },
+            "pid": { "type": ["integer", "null"] },
+            "exit_code": { "type": ["integer", "null"] },
+            "output": { "type": "string" },
+            "output_tail": { "type": "string" },
+        })),
+        "browser" => object(json!({
+            "ok": { "type": "boolean" },
+            "tool": { "const": "browser" },
+            "data": {},
+            "error": object(json!({
+                "code": { "type": "string" },
+                "message": { "type": "string" },
+                "retryable": { "type": "boolean" },
+            })),
+        })),
+        "cron" => object(json!({
+            "cron": { "type": "object" },
+            "crons": { "type": "array", "items": { "type": "object" } },
+            "cron_id": { "type": "string" },
+            "removed": { "type": "boolean" },
+            "queued": { "type": "boolean" },
+            "woke": { "type": "integer" },
+            "next_run_at": { "type": "integer" },
+            "runs": { "type": "array", "items": { "type": "object" } },
+        })),
+        "web_fetch" => object(json!({
+            "url": { "type": "string" },
+            "finalUrl": { "type": "string" },
+            "status": { "type": "integer" },
+            "contentType": { "type": ["string", "null"] },
+            "title": { "type": "string" },
+            "extractMode": { "type": "string", "enum": ["markdown", "text"] },
+            "extractor": { "type": "string" },
+            "backend": { "type": "string" },
+            "truncated": { "type": "boolean" },
+            "length": { "type": "integer" },
+            "fetchedAt": { "type": "string" },
+            "tookMs": { "type": "integer" },
+            "text": { "type": "string" },
+            "warning": { "type": "string" },
+        })),
+        "web_search" => object(json!({
+            "query": { "type": "string" },
+            "provider": { "type": "string", "enum": ["brave", "searxng"] },
+            "count": { "type": "integer" },
+            "results": {
+                "type": "array",
+                "items": object(json!({
+                    "title": { "type": "string" },
+                    "url": { "type": "string" },
+                    "snippet": { "type": "string" },
+                    "published": { "type": ["string", "null"] },
+                    "siteName": { "type": ["string", "null"] },
+                })),
+            },
+            "error": { "type": "string" },
+            "message": { "type": "string" },
+        })),
+        _ => return None,
+    };
+    Some(schema)
+}
@@ -2,7 +2,7 @@
 #![allow(clippy::result_large_err)]
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
@@ -13,8 +13,11 @@ use tokio::io::AsyncReadExt;
 use tokio::process::Command;
 use tokio::time::{timeout, Duration};
 
+use crate::homie_config::ExecOutputFormat;
+
 use super::args::ParsedToolArgs;
-use super::{ProcessRegistry, ToolContext};
+use super::output::{read_coalesced, OutputStream, OutputTarget};
+use super::{ToolContext, ToolOutputSender};
 
 const DEFAULT_TIMEOUT_SECS: u64 = 60;
 
@@ -26,6 +29,7 @@ struct ExecRequest {
     background: bool,
     yield_ms: Option<u64>,
     timeout_secs: u64,
+    format: Option<ExecOutputFormat>,
 }
 
 pub fn exec_tool(ctx: ToolContext) -> Arc<dyn Tool> {
@@ -39,6 +43,11 @@ pub fn exec_tool(ctx: ToolContext) -> Arc<dyn Tool> {
         )
         .boolean("background", "Run in background immediately", false)
         .number("timeout", "Timeout in seconds", false)
+        .string(
+            "format",
+            "Result shape: structured (stdout/stderr/exit_code) or raw (combined output)",
+            false,
+        )
         .build();
 
     Arc::new(AgentTool::new(
@@ -69,6 +78,14 @@ fn parse_exec_request(args: &ToolArguments) -> Result<ExecRequest, RociError> {
     let timeout_secs = parsed
         .get_u64_any(&["timeout", "timeout_secs", "timeoutSeconds"])?
         .unwrap_or(DEFAULT_TIMEOUT_SECS);
+    let format = match parsed.get_string_any(&["format", "output_format", "outputFormat"])? {
+        Some(label) => Some(ExecOutputFormat::from_label(&label).ok_or_else(|| {
+            RociError::InvalidArgument(format!(
+                "format must be `structured` or `raw`, got `{label}`"
+            ))
+        })?),
+        None => None,
+    };
 
     Ok(ExecRequest {
         command,
@@ -77,9 +94,18 @@ fn parse_exec_request(args: &ToolArguments) -> Result<ExecRequest, RociError> {
         background,
         yield_ms,
         timeout_secs,
+        format,
     })
 }
 
+/// Shell words of the command an `exec` call would run, for checking a
+/// direct invocation against the exec policy.
+pub fn exec_command_argv(input: &serde_json::Value) -> Result<Vec<String>, String> {
+    let parsed = parse_exec_request(&ToolArguments::new(input.clone()))
+        .map_err(|e| format!("invalid exec input: {e}"))?;
+    shell_words::split(&parsed.command).map_err(|e| format!("invalid exec command: {e}"))
+}
+
 fn clean_string(value: Option<String>) -> Option<String> {
     value
         .map(|entry| entry.trim().to_string())
@@ -103,6 +129,7 @@ async fn exec_impl(
     let background = parsed.background;
     let yield_ms = parsed.yield_ms;
     let timeout_secs = parsed.timeout_secs;
+    let format = parsed.format.unwrap_or(ctx.exec.output_format);
 
     if super::debug_tools_enabled() {
         tracing::debug!(
@@ -116,7 +143,7 @@ async fn exec_impl(
     }
 
     if background {
-        let id = spawn_background(ctx.processes.clone(), command.as_str(), &cwd, &env).await?;
+        let id = spawn_background(ctx, command.as_str(), &cwd, &env).await?;
         let info = ctx
             .processes
             .info(&id, 0)
@@ -136,35 +163,37 @@ async fn exec_impl(
     let start = Instant::now();
     let mut cmd = build_shell_command(command.as_str());
     cmd.current_dir(&cwd);
-    if !env.is_empty() {
-        cmd.envs(&env);
-    }
+    apply_env(&mut cmd, ctx, &env);
     cmd.stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped());
+        .stderr(std::process::Stdio::piped())
+        // Foreground commands belong to the tool call; if the run is
+        // cancelled the call future is dropped and the command goes with it.
+        .kill_on_drop(true);
 
     let child = cmd.spawn().map_err(|e| RociError::ToolExecution {
         tool_name: "exec".into(),
         message: format!("spawn failed: {e}"),
     })?;
 
+    let waiting = wait_for_output(child, ctx.output.clone(), args.raw());
     let output = if timeout_secs > 0 {
-        timeout(Duration::from_secs(timeout_secs), child.wait_with_output())
+        timeout(Duration::from_secs(timeout_secs), waiting)
             .await
             .map_err(|_| RociError::ToolExecution {
                 tool_name: "exec".into(),
                 message: format!("command timed out after {timeout_secs}s"),
             })??
     } else {
-        child
-            .wait_with_output()
-            .await
-            .map_err(|e| RociError::ToolExecution {
-                tool_name: "exec".into(),
-                message: format!("command failed: {e}"),
-            })?
+        waiting.await.map_err(|e| RociError::ToolExecution {
+            tool_name: "exec".into(),
+            message: format!("command failed: {e}"),
+        })?
     };
 
     let duration_ms = start.elapsed().as_millis() as u64;
+    if format == ExecOutputFormat::Raw {
+        return Ok(raw_exec_result(ctx, &command, &cwd, &output, duration_ms));
+    }
     let stdout_truncated = output_truncated_flag(&output.stdout);
     let stderr_truncated = output_truncated_flag(&output.stderr);
     let stdout = truncate_output(&output.stdout);
@@ -193,19 +222,92 @@ async fn exec_impl(
     }))
 }
 
+/// Wait for a foreground command and collect its output. With a sender,
+/// stdout and stderr are also streamed while the command runs.
+async fn wait_for_output(
+    mut child: tokio::process::Child,
+    sender: Option<ToolOutputSender>,
+    input: &serde_json::Value,
+) -> std::io::Result<std::process::Output> {
+    let Some(sender) = sender else {
+        return child.wait_with_output().await;
+    };
+    let target = |stream| OutputTarget {
+        sender: sender.clone(),
+        tool: "exec",
+        input: input.clone(),
+        stream,
+    };
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let read_stdout = async {
+        match stdout {
+            Some(out) => read_coalesced(out, target(OutputStream::Stdout)).await,
+            None => Ok(Vec::new()),
+        }
+    };
+    let read_stderr = async {
+        match stderr {
+            Some(err) => read_coalesced(err, target(OutputStream::Stderr)).await,
+            None => Ok(Vec::new()),
+        }
+    };
+    let (stdout, stderr, status) = tokio::try_join!(read_stdout, read_stderr, child.wait())?;
+    Ok(std::process::Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Raw result: stdout followed by stderr in one `output` string, plus the
+/// exit code so failures stay visible.
+fn raw_exec_result(
+    ctx: &ToolContext,
+    command: &str,
+    cwd: &Path,
+    output: &std::process::Output,
+    duration_ms: u64,
+) -> serde_json::Value {
+    let mut combined = output.stdout.clone();
+    combined.extend_from_slice(&output.stderr);
+    let output_truncated = output_truncated_flag(&combined);
+    let text = truncate_output(&combined);
+    let process_id = output_truncated.then(|| {
+        ctx.processes.insert_completed(
+            command.to_string(),
+            cwd.to_string_lossy().to_string(),
+            output.status.code(),
+            combine_output(&output.stdout, &output.stderr),
+        )
+    });
+    serde_json::json!({
+        "status": "completed",
+        "exit_code": output.status.code(),
+        "output": text,
+        "duration_ms": duration_ms,
+        "cwd": cwd.to_string_lossy(),
+        "process_id": process_id,
+        "output_truncated": output_truncated,
+    })
+}
+
 async fn spawn_background(
-    registry: Arc<ProcessRegistry>,
+    ctx: &ToolContext,
     command: &str,
     cwd: &PathBuf,
     env: &HashMap<String, String>,
 ) -> Result<String, RociError> {
+    let registry = ctx.processes.clone();
     let mut cmd = build_shell_command(command);
     cmd.current_dir(cwd);
-    if !env.is_empty() {
-        cmd.envs(env);
-    }
+    apply_env(&mut cmd, ctx, env);
     cmd.stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped());
+    // Own process group, so killing the process also stops whatever the
+    // shell started.
+    #[cfg(unix)]
+    cmd.process_group(0);
     let mut child = cmd.spawn().map_err(|e| RociError::ToolExecution {
         tool_name: "exec".into(),
         message: format!("spawn failed: {e}"),
@@ -276,6 +378,17 @@ fn resolve_cwd(ctx: &ToolContext, override_path: Option<&str>) -> PathBuf {
     ctx.cwd.clone()
 }
 
+/// Drop the server variables `[env]` keeps from child processes, then add the
+/// call's own `env`, which is always kept.
+fn apply_env(cmd: &mut Command, ctx: &ToolContext, env: &HashMap<String, String>) {
+    for name in ctx.child_env.scrubbed_vars() {
+        cmd.env_remove(name);
+    }
+    if !env.is_empty() {
+        cmd.envs(env);
+    }
+}
+
 fn build_shell_command(command: &str) -> Command {
     #[cfg(target_os = "windows")]
     {
@@ -393,9 +506,28 @@ fn combine_output(stdout: &[u8], stderr: &[u8]) -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use roci::tools::ToolArguments;
-    use serde_json::json;
+    use serde_json::{json, Value};
 
-    use super::{parse_exec_request, DEFAULT_TIMEOUT_SECS};
+    use super::{
+        exec_command_argv, exec_impl, parse_exec_request, OutputStream, DEFAULT_TIMEOUT_SECS,
+    };
+    use crate::agent::tools::ToolContext;
+    use crate::homie_config::ExecOutputFormat;
+    use crate::HomieConfig;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    const MIXED_OUTPUT_COMMAND: &str = "printf out; printf err >&2; exit 3";
+
+    /// Commands run through a login shell, so the user's profile may print
+    /// ahead of them; only the tail of each stream is the command's own.
+    fn stream_tail(value: &Value, expected: &str) {
+        let text = value.as_str().expect("stream text");
+        assert!(
+            text.ends_with(expected),
+            "{text:?} should end with {expected:?}"
+        );
+    }
 
     #[test]
     fn exec_request_accepts_literal_command_with_defaults() {
@@ -407,6 +539,7 @@ mod tests {
         assert!(!parsed.background);
         assert_eq!(parsed.yield_ms, None);
         assert_eq!(parsed.timeout_secs, DEFAULT_TIMEOUT_SECS);
+        assert_eq!(parsed.format, None);
     }
 
     #[test]
@@ -440,4 +573,121 @@ mod tests {
             "Invalid argument: command must not be empty"
         );
     }
+
+    #[test]
+    fn exec_command_argv_splits_shell_words() {
+        let argv = exec_command_argv(&json!({ "cmd": "git log --format='%h %s'" })).expect("argv");
+        assert_eq!(argv, vec!["git", "log", "--format=%h %s"]);
+        assert!(exec_command_argv(&json!({ "command": "echo 'unterminated" })).is_err());
+    }
+
+    #[test]
+    fn exec_request_parses_output_format() {
+        let args = ToolArguments::new(json!({ "command": "ls", "format": "RAW" }));
+        let parsed = parse_exec_request(&args).expect("parse exec request");
+        assert_eq!(parsed.format, Some(ExecOutputFormat::Raw));
+
+        let args = ToolArguments::new(json!({ "command": "ls", "output_format": "xml" }));
+        let err = parse_exec_request(&args).expect_err("unknown format");
+        assert!(err.to_string().contains("format must be"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_structured_output_separates_streams() {
+        let ctx = ToolContext::new(Arc::new(HomieConfig::default()));
+        let args = ToolArguments::new(json!({ "command": MIXED_OUTPUT_COMMAND }));
+        let result = exec_impl(&ctx, &args).await.expect("exec result");
+        assert_eq!(result["status"], "completed");
+        assert_eq!(result["exit_code"], 3);
+        stream_tail(&result["stdout"], "out");
+        stream_tail(&result["stderr"], "err");
+        assert!(result["duration_ms"].is_u64());
+        assert!(result.get("output").is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_raw_output_combines_streams() {
+        let ctx = ToolContext::new(Arc::new(HomieConfig::default()));
+        let args = ToolArguments::new(json!({ "command": MIXED_OUTPUT_COMMAND, "format": "raw" }));
+        let result = exec_impl(&ctx, &args).await.expect("exec result");
+        assert_eq!(result["exit_code"], 3);
+        assert_raw_output(&result["output"]);
+        assert_eq!(result["output_truncated"], false);
+        assert!(result.get("stdout").is_none());
+        assert!(result.get("stderr").is_none());
+
+        let mut config = HomieConfig::default();
+        config.tools.exec.output_format = ExecOutputFormat::Raw;
+        let ctx = ToolContext::new(Arc::new(config));
+        let args = ToolArguments::new(json!({ "command": MIXED_OUTPUT_COMMAND }));
+        let result = exec_impl(&ctx, &args).await.expect("exec result");
+        assert_raw_output(&result["output"]);
+
+        let args = ToolArguments::new(json!({
+            "command": MIXED_OUTPUT_COMMAND,
+            "format": "structured",
+        }));
+        let result = exec_impl(&ctx, &args).await.expect("exec result");
+        stream_tail(&result["stdout"], "out");
+    }
+
+    /// Raw output is all of stdout followed by all of stderr.
+    fn assert_raw_output(value: &Value) {
+        let text = value.as_str().expect("raw output");
+        let stdout = text
+            .strip_suffix("err")
+            .expect("raw output ends with stderr");
+        assert!(stdout.contains("out"), "{text:?}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_children_do_not_inherit_denied_vars() {
+        // Unique names so parallel tests never see these variables.
+        let prefix = format!("HOMIE_EXEC_TEST_{}", uuid::Uuid::new_v4().simple());
+        let (token, key) = (format!("{prefix}_TOKEN"), format!("{prefix}_KEY"));
+        std::env::set_var(&token, "secret");
+        std::env::set_var(&key, "kept");
+        let mut config = HomieConfig::default();
+        config.env.allow = vec![key.clone()];
+        let ctx = ToolContext::new(Arc::new(config));
+        let command = format!("printf '%s|%s|%s' \"${token}\" \"${key}\" \"$CALL_TOKEN\"");
+        let args = ToolArguments::new(json!({
+            "command": command,
+            "env": { "CALL_TOKEN": "passed" },
+        }));
+        let result = exec_impl(&ctx, &args).await;
+        std::env::remove_var(&token);
+        std::env::remove_var(&key);
+        assert_eq!(result.expect("exec result")["stdout"], "|kept|passed");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_streams_output_while_running() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let ctx = ToolContext::new(Arc::new(HomieConfig::default())).with_output(tx);
+        let input = json!({ "command": MIXED_OUTPUT_COMMAND });
+        let result = exec_impl(&ctx, &ToolArguments::new(input.clone()))
+            .await
+            .expect("exec result");
+        stream_tail(&result["stdout"], "out");
+        stream_tail(&result["stderr"], "err");
+        drop(ctx);
+
+        let mut streamed = HashMap::new();
+        while let Some(chunk) = rx.recv().await {
+            assert_eq!(chunk.tool, "exec");
+            assert_eq!(chunk.input, input);
+            streamed
+                .entry(chunk.stream)
+                .or_insert_with(String::new)
+                .push_str(&chunk.chunk);
+        }
+        // What was streamed is exactly what the result reports.
+        assert_eq!(streamed[&OutputStream::Stdout], result["stdout"]);
+        assert_eq!(streamed[&OutputStream::Stderr], result["stderr"]);
+    }
 }
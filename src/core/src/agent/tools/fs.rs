@@ -145,6 +145,53 @@ pub fn resolve_path(path: &str, cwd: &Path) -> Option<PathBuf> {
     Some(path_buf)
 }
 
+/// Canonical `path` if it lies under one of `roots`, after resolving `..`
+/// and symlinks on both sides. Trailing components that do not exist yet
+/// (files about to be created) are allowed.
+pub fn confine_path(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, String> {
+    let canonical = canonicalize_lenient(path)
+        .ok_or_else(|| format!("cannot resolve path: {}", path.display()))?;
+    let inside = roots
+        .iter()
+        .filter_map(|root| std::fs::canonicalize(root).ok())
+        .any(|root| canonical.starts_with(root));
+    if inside {
+        Ok(canonical)
+    } else {
+        Err(format!(
+            "path is outside the allowed roots: {}",
+            path.display()
+        ))
+    }
+}
+
+/// Canonicalize the longest existing prefix of `path` and append the rest.
+/// A missing component followed by `..` cannot be resolved safely, so it
+/// fails.
+fn canonicalize_lenient(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(base) = std::fs::canonicalize(existing) {
+            return Some(missing.iter().rev().fold(base, |acc, name| acc.join(name)));
+        }
+        missing.push(existing.file_name()?);
+        existing = existing.parent()?;
+    }
+}
+
+/// [`confine_path`] for a tool call, as a tool error.
+pub(super) fn confine_tool_path(
+    ctx: &ToolContext,
+    tool_name: &str,
+    path: &Path,
+) -> Result<PathBuf, RociError> {
+    confine_path(path, &ctx.allowed_roots()).map_err(|message| RociError::ToolExecution {
+        tool_name: tool_name.into(),
+        message,
+    })
+}
+
 fn parse_read_request(args: &ToolArguments) -> Result<ReadRequest, RociError> {
     let parsed = ParsedToolArgs::new(args)?;
     let path = clean_string(parsed.get_string_any(&["path", "file", "file_path", "filepath"])?)
@@ -235,6 +282,7 @@ async fn read_impl(
     let parsed = parse_read_request(args)?;
     let path = resolve_path(&parsed.path, &ctx.cwd)
         .ok_or_else(|| RociError::InvalidArgument("path must not be empty".into()))?;
+    let path = confine_tool_path(ctx, "read", &path)?;
     let offset = parsed.offset;
     let limit = parsed.limit;
 
@@ -299,6 +347,7 @@ async fn ls_impl(ctx: &ToolContext, args: &ToolArguments) -> Result<serde_json::
         .as_deref()
         .and_then(|p| resolve_path(p, &ctx.cwd))
         .unwrap_or_else(|| ctx.cwd.clone());
+    let base = confine_tool_path(ctx, "ls", &base)?;
     let depth = parsed.depth;
     let limit = parsed.limit;
 
@@ -395,6 +444,7 @@ async fn find_impl(
         .as_deref()
         .and_then(|p| resolve_path(p, &ctx.cwd))
         .unwrap_or_else(|| ctx.cwd.clone());
+    let base = confine_tool_path(ctx, "find", &base)?;
 
     if super::debug_tools_enabled() {
         tracing::debug!(
@@ -457,6 +507,7 @@ async fn grep_impl(
         .as_deref()
         .and_then(|p| resolve_path(p, &ctx.cwd))
         .unwrap_or_else(|| ctx.cwd.clone());
+    let base = confine_tool_path(ctx, "grep", &base)?;
 
     if super::debug_tools_enabled() {
         tracing::debug!(
@@ -523,7 +574,7 @@ mod tests {
     use serde_json::json;
 
     use super::{
-        parse_find_request, parse_grep_request, parse_ls_request, parse_read_request,
+        confine_path, parse_find_request, parse_grep_request, parse_ls_request, parse_read_request,
         DEFAULT_FIND_LIMIT, DEFAULT_LS_DEPTH, DEFAULT_LS_LIMIT, DEFAULT_READ_LIMIT,
     };
 
@@ -632,4 +683,45 @@ mod tests {
             "Invalid argument: pattern must not be empty"
         );
     }
+
+    fn temp_root(label: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("homie-{label}-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("inside")).unwrap();
+        std::fs::write(root.join("inside/notes.txt"), "hi").unwrap();
+        std::fs::write(root.join("secret.txt"), "no").unwrap();
+        root
+    }
+
+    #[test]
+    fn confine_path_rejects_dot_dot_traversal() {
+        let root = temp_root("confine");
+        let roots = vec![root.join("inside")];
+
+        let ok = confine_path(&root.join("inside/notes.txt"), &roots).expect("inside root");
+        assert!(ok.ends_with("inside/notes.txt"));
+        assert!(confine_path(&root.join("inside/new/file.txt"), &roots).is_ok());
+
+        let err = confine_path(&root.join("inside/../secret.txt"), &roots)
+            .expect_err("traversal should fail");
+        assert!(err.contains("outside the allowed roots"), "{err}");
+        assert!(confine_path(&root.join("inside/missing/../../secret.txt"), &roots).is_err());
+        assert!(confine_path(std::path::Path::new("/etc/passwd"), &roots).is_err());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn confine_path_rejects_symlink_escapes() {
+        let root = temp_root("symlink");
+        let roots = vec![root.join("inside")];
+        std::os::unix::fs::symlink(root.join("secret.txt"), root.join("inside/link.txt")).unwrap();
+        std::os::unix::fs::symlink(&root, root.join("inside/up")).unwrap();
+
+        assert!(confine_path(&root.join("inside/link.txt"), &roots).is_err());
+        assert!(confine_path(&root.join("inside/up/secret.txt"), &roots).is_err());
+        assert!(confine_path(&root.join("inside/up/new.txt"), &roots).is_err());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
 }
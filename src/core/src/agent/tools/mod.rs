@@ -3,7 +3,7 @@ use std::sync::Arc;
 
 use roci::tools::Tool;
 
-use crate::homie_config::WebToolsConfig;
+use crate::homie_config::{ChildEnvConfig, ExecToolConfig, ToolsConfig, WebToolsConfig};
 use crate::storage::Store;
 use crate::HomieConfig;
 
@@ -11,15 +11,26 @@ mod apply_patch;
 mod args;
 mod browser;
 mod cron;
+mod examples;
 mod exec;
 mod fs;
+mod output;
 mod process;
 mod process_registry;
+mod reconnect;
 mod registry;
+mod session;
 mod web;
 
-pub use process_registry::{ProcessInfo, ProcessRegistry};
+pub use apply_patch::{patch_file_stats, PatchFileStats};
+pub use examples::{tool_examples, tool_output_schema};
+pub use exec::exec_command_argv;
+pub use fs::confine_path;
+pub use output::ToolOutputSender;
+pub use process_registry::{ProcessInfo, ProcessRegistry, ProcessStatus};
 pub use registry::{ListedTool, ToolProvider, ToolRegistry};
+pub use session::{SessionToolSpec, SessionTools};
+pub(crate) use web::ip_is_private;
 
 pub const TOOL_CHANNEL_WEB: &str = "web";
 pub const TOOL_CHANNEL_MOBILE: &str = "mobile";
@@ -34,7 +45,15 @@ pub struct ToolContext {
     pub channel: Option<String>,
     pub processes: Arc<ProcessRegistry>,
     pub web: WebToolsConfig,
+    pub exec: ExecToolConfig,
+    /// Server variables kept out of `exec` commands.
+    pub child_env: ChildEnvConfig,
     pub store: Option<Arc<dyn Store>>,
+    pub session_tools: SessionTools,
+    /// Receives live output of running commands when set.
+    pub output: Option<ToolOutputSender>,
+    /// `tools.fs.allowed_roots`; empty confines file tools to `cwd`.
+    pub fs_roots: Vec<PathBuf>,
 }
 
 impl ToolContext {
@@ -53,6 +72,21 @@ impl ToolContext {
         self
     }
 
+    pub fn with_session_tools(mut self, session_tools: SessionTools) -> Self {
+        self.session_tools = session_tools;
+        self
+    }
+
+    pub fn with_cwd(mut self, cwd: PathBuf) -> Self {
+        self.cwd = cwd;
+        self
+    }
+
+    pub fn with_output(mut self, output: ToolOutputSender) -> Self {
+        self.output = Some(output);
+        self
+    }
+
     pub fn with_processes_and_channel(
         processes: Arc<ProcessRegistry>,
         homie_config: Arc<HomieConfig>,
@@ -60,32 +94,84 @@ impl ToolContext {
     ) -> Self {
         let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
         let web = homie_config.tools.web.clone();
-        let channel = resolve_tool_channel(channel);
+        let exec = homie_config.tools.exec.clone();
+        let child_env = homie_config.env.clone();
+        let fs_roots = homie_config.tools.fs.roots();
+        let channel = resolve_tool_channel(channel, &homie_config.tools);
         Self {
             cwd,
             channel,
             processes,
             web,
+            exec,
+            child_env,
             store: None,
+            session_tools: SessionTools::default(),
+            output: None,
+            fs_roots,
+        }
+    }
+
+    /// Directories the file tools may touch.
+    pub fn allowed_roots(&self) -> Vec<PathBuf> {
+        if self.fs_roots.is_empty() {
+            vec![self.cwd.clone()]
+        } else {
+            self.fs_roots.clone()
+        }
+    }
+}
+
+/// Built-in channels followed by any extra `tools.channels`, lowercased and
+/// deduplicated.
+pub fn canonical_tool_channels(config: &ToolsConfig) -> Vec<String> {
+    let mut channels: Vec<String> = CANONICAL_TOOL_CHANNELS
+        .iter()
+        .map(|channel| channel.to_string())
+        .collect();
+    for channel in &config.channels {
+        let channel = channel.trim().to_lowercase();
+        if !channel.is_empty() && !channels.contains(&channel) {
+            channels.push(channel);
         }
     }
+    channels
 }
 
-pub fn resolve_tool_channel(channel: Option<&str>) -> Option<String> {
+pub fn resolve_tool_channel(channel: Option<&str>, config: &ToolsConfig) -> Option<String> {
     let normalized = channel
         .map(str::trim)
         .filter(|value| !value.is_empty())
         .map(|value| value.to_lowercase())?;
-    if CANONICAL_TOOL_CHANNELS
-        .iter()
-        .any(|candidate| candidate.eq_ignore_ascii_case(&normalized))
-    {
+    if canonical_tool_channels(config).contains(&normalized) {
         Some(normalized)
     } else {
         None
     }
 }
 
+/// What a tool can change outside the conversation. Read-only runs send
+/// every call that is not [`ToolSideEffect::ReadOnly`] to the client for
+/// approval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolSideEffect {
+    ReadOnly,
+    /// Writes files or other persistent state.
+    Write,
+    /// Runs commands or spawns processes.
+    Exec,
+}
+
+/// Side-effect class of a tool by name. Tools Homie does not know, such as
+/// client-registered session tools, count as writes.
+pub fn tool_side_effect(name: &str) -> ToolSideEffect {
+    match name {
+        "read" | "ls" | "find" | "grep" | "web_fetch" | "web_search" => ToolSideEffect::ReadOnly,
+        "exec" | "process" | "browser" | "cron" => ToolSideEffect::Exec,
+        _ => ToolSideEffect::Write,
+    }
+}
+
 pub fn build_tools(
     ctx: ToolContext,
     homie_config: &HomieConfig,
@@ -93,11 +179,43 @@ pub fn build_tools(
     ToolRegistry::new().build_tools(ctx, &homie_config.tools)
 }
 
+/// Start reconnection loops for the enabled dynamic tool providers; their
+/// status changes go out on `events`.
+pub fn supervise_tool_providers(
+    homie_config: &HomieConfig,
+    events: tokio::sync::broadcast::Sender<crate::router::ReapEvent>,
+) -> Vec<tokio::task::JoinHandle<bool>> {
+    ToolRegistry::new().supervise_dynamic_providers(&homie_config.tools, events)
+}
+
 pub fn list_tools(ctx: ToolContext, homie_config: &HomieConfig) -> Result<Vec<ListedTool>, String> {
     ToolRegistry::new().list_tools(ctx, &homie_config.tools)
 }
 
+pub fn describe_tool(
+    ctx: ToolContext,
+    homie_config: &HomieConfig,
+    name: &str,
+) -> Result<Option<ListedTool>, String> {
+    ToolRegistry::new().describe_tool(ctx, &homie_config.tools, name)
+}
+
 pub fn debug_tools_enabled() -> bool {
     matches!(std::env::var("HOMIE_DEBUG").as_deref(), Ok("1"))
         || matches!(std::env::var("HOME_DEBUG").as_deref(), Ok("1"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{tool_side_effect, ToolSideEffect};
+
+    #[test]
+    fn classifies_tool_side_effects() {
+        assert_eq!(tool_side_effect("read"), ToolSideEffect::ReadOnly);
+        assert_eq!(tool_side_effect("web_fetch"), ToolSideEffect::ReadOnly);
+        assert_eq!(tool_side_effect("apply_patch"), ToolSideEffect::Write);
+        assert_eq!(tool_side_effect("exec"), ToolSideEffect::Exec);
+        assert_eq!(tool_side_effect("process"), ToolSideEffect::Exec);
+        assert_eq!(tool_side_effect("lookup_order"), ToolSideEffect::Write);
+    }
+}
@@ -0,0 +1,189 @@
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, MissedTickBehavior};
+
+/// How long output may sit in the buffer before it is sent.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(150);
+/// Buffered bytes that force a send before the interval is up.
+const FLUSH_BYTES: usize = 16 * 1024;
+
+/// Which pipe a chunk of live tool output came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+        }
+    }
+}
+
+/// Output a tool call produced while it was still running. `input` is the
+/// call's arguments, which the run uses to find the tool item it belongs to.
+#[derive(Debug, Clone)]
+pub struct ToolOutputChunk {
+    pub tool: &'static str,
+    pub input: Value,
+    pub stream: OutputStream,
+    pub chunk: String,
+}
+
+pub type ToolOutputSender = mpsc::UnboundedSender<ToolOutputChunk>;
+
+/// Where a reader sends its coalesced chunks.
+pub(super) struct OutputTarget {
+    pub(super) sender: ToolOutputSender,
+    pub(super) tool: &'static str,
+    pub(super) input: Value,
+    pub(super) stream: OutputStream,
+}
+
+impl OutputTarget {
+    /// Send the complete UTF-8 prefix of `pending`, keeping a split
+    /// character for the next flush (or everything, when `last`).
+    fn flush(&self, pending: &mut Vec<u8>, last: bool) {
+        let ready = if last {
+            pending.len()
+        } else {
+            utf8_prefix_len(pending)
+        };
+        if ready == 0 {
+            return;
+        }
+        let bytes: Vec<u8> = pending.drain(..ready).collect();
+        let _ = self.sender.send(ToolOutputChunk {
+            tool: self.tool,
+            input: self.input.clone(),
+            stream: self.stream,
+            chunk: String::from_utf8_lossy(&bytes).to_string(),
+        });
+    }
+}
+
+/// Read `reader` to the end and return everything it produced, sending it
+/// to `target` along the way in chunks of at most one flush interval. A
+/// read error ends the stream: what was read is still sent, then the error
+/// is returned.
+pub(super) async fn read_coalesced<R>(
+    mut reader: R,
+    target: OutputTarget,
+) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut collected = Vec::new();
+    let mut pending = Vec::new();
+    let mut buf = [0u8; 4096];
+    let mut tick = tokio::time::interval(FLUSH_INTERVAL);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            read = reader.read(&mut buf) => match read {
+                Ok(0) => break,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    target.flush(&mut pending, true);
+                    return Err(err);
+                }
+                Ok(n) => {
+                    collected.extend_from_slice(&buf[..n]);
+                    pending.extend_from_slice(&buf[..n]);
+                    if pending.len() >= FLUSH_BYTES {
+                        target.flush(&mut pending, false);
+                    }
+                }
+            },
+            _ = tick.tick() => target.flush(&mut pending, false),
+        }
+    }
+    target.flush(&mut pending, true);
+    Ok(collected)
+}
+
+/// Length of the longest prefix of `bytes` that does not end inside a
+/// multi-byte character. Invalid bytes count as complete.
+fn utf8_prefix_len(bytes: &[u8]) -> usize {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => bytes.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => bytes.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn split_characters_wait_for_the_next_flush() {
+        let text = "héllo".as_bytes();
+        assert_eq!(utf8_prefix_len(&text[..2]), 1);
+        assert_eq!(utf8_prefix_len(text), text.len());
+        assert_eq!(utf8_prefix_len(&[0xff, b'a']), 2);
+    }
+
+    #[tokio::test]
+    async fn reader_output_is_collected_and_forwarded() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let target = OutputTarget {
+            sender: tx,
+            tool: "exec",
+            input: json!({ "command": "make" }),
+            stream: OutputStream::Stderr,
+        };
+        let collected = read_coalesced(&b"line one\nline two\n"[..], target)
+            .await
+            .unwrap();
+        assert_eq!(collected, b"line one\nline two\n");
+
+        let mut forwarded = String::new();
+        while let Some(chunk) = rx.recv().await {
+            assert_eq!(chunk.stream, OutputStream::Stderr);
+            assert_eq!(chunk.input, json!({ "command": "make" }));
+            forwarded.push_str(&chunk.chunk);
+        }
+        assert_eq!(forwarded, "line one\nline two\n");
+    }
+
+    struct FailingReader {
+        sent: bool,
+    }
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if self.sent {
+                return std::task::Poll::Ready(Err(std::io::Error::other("pipe broke")));
+            }
+            self.sent = true;
+            buf.put_slice(b"partial");
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn read_errors_are_returned_after_sending_what_was_read() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let target = OutputTarget {
+            sender: tx,
+            tool: "exec",
+            input: json!({}),
+            stream: OutputStream::Stdout,
+        };
+        let err = read_coalesced(FailingReader { sent: false }, target)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "pipe broke");
+        assert_eq!(rx.recv().await.unwrap().chunk, "partial");
+    }
+}
@@ -135,11 +135,29 @@ impl ProcessRegistry {
             child
         };
         let mut child = child;
-        child
-            .kill()
+        kill_process_group(&mut child)
+            .await
+            .map_err(|e| format!("kill failed: {e}"))
+    }
+
+    /// Kill `id` if it is still running and drop it from the registry.
+    /// Returns whether a live process was killed; the entry of a process
+    /// that already exited is just removed.
+    pub async fn kill_and_remove(&self, id: &str) -> Result<bool, String> {
+        let child = {
+            let mut guard = self.inner.lock().unwrap();
+            let Some(entry) = guard.remove(id) else {
+                return Err("process not found".to_string());
+            };
+            entry.child
+        };
+        let Some(mut child) = child else {
+            return Ok(false);
+        };
+        kill_process_group(&mut child)
             .await
             .map_err(|e| format!("kill failed: {e}"))?;
-        Ok(())
+        Ok(true)
     }
 
     pub fn try_wait(&self, id: &str) -> Option<Option<i32>> {
@@ -211,3 +229,86 @@ fn trim_output(mut output: Vec<u8>) -> Vec<u8> {
     output.drain(0..start);
     output
 }
+
+/// Kill `child` and reap it. Background commands lead their own process
+/// group, so on unix the whole group goes: a shell's own children do not
+/// outlive it.
+async fn kill_process_group(child: &mut tokio::process::Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // Fails with ESRCH when the child does not lead a group; plain
+        // `kill` below still stops it.
+        // SAFETY: killpg only sends a signal; it touches no memory.
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    child.kill().await
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn kill_and_remove_is_safe_after_exit() {
+        let registry = ProcessRegistry::new();
+        let child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .expect("spawn sleep");
+        let id = registry.insert("sleep 30".into(), "/".into(), child);
+        assert_eq!(registry.kill_and_remove(&id).await, Ok(true));
+        assert!(registry.info(&id, 0).is_none());
+        assert!(registry.kill_and_remove(&id).await.is_err());
+
+        let done = registry.insert_completed("true".into(), "/".into(), Some(0), Vec::new());
+        assert_eq!(registry.kill_and_remove(&done).await, Ok(false));
+        assert!(registry.list(0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn kill_and_remove_stops_the_whole_process_group() {
+        use tokio::io::AsyncBufReadExt;
+
+        let registry = ProcessRegistry::new();
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(std::process::Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .expect("spawn sh");
+        let stdout = child.stdout.take().expect("stdout");
+        let mut line = String::new();
+        tokio::io::BufReader::new(stdout)
+            .read_line(&mut line)
+            .await
+            .expect("grandchild pid");
+        let grandchild: libc::pid_t = line.trim().parse().expect("pid");
+        let id = registry.insert("sh".into(), "/".into(), child);
+
+        assert_eq!(registry.kill_and_remove(&id).await, Ok(true));
+        let gone = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+            // Dead once it no longer exists or is a zombie awaiting reaping.
+            loop {
+                let stat = std::fs::read_to_string(format!("/proc/{grandchild}/stat"));
+                let alive = match stat {
+                    Ok(stat) => !stat
+                        .rsplit(')')
+                        .next()
+                        .unwrap_or("")
+                        .trim_start()
+                        .starts_with('Z'),
+                    // SAFETY: signal 0 only checks that the pid exists.
+                    Err(_) => unsafe { libc::kill(grandchild, 0) == 0 },
+                };
+                if !alive {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        })
+        .await;
+        assert!(gone.is_ok(), "grandchild {grandchild} outlived the kill");
+    }
+}
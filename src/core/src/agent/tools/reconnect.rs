@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::broadcast;
+
+use crate::homie_config::ToolProviderConfig;
+use crate::router::ReapEvent;
+
+use super::ToolProvider;
+
+const DEFAULT_RECONNECT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_RECONNECT_BACKOFF_MS: u64 = 1_000;
+const MAX_RECONNECT_BACKOFF_MS: u64 = 60_000;
+
+/// Backoff settings used when (re)attaching a dynamic tool provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Failed connection attempts tolerated before giving up.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles per attempt up to a cap.
+    pub initial_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_RECONNECT_MAX_ATTEMPTS,
+            initial_backoff: Duration::from_millis(DEFAULT_RECONNECT_BACKOFF_MS),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn from_config(config: Option<&ToolProviderConfig>) -> Self {
+        let defaults = Self::default();
+        let Some(config) = config else {
+            return defaults;
+        };
+        Self {
+            max_attempts: config
+                .reconnect_max_attempts
+                .unwrap_or(defaults.max_attempts),
+            initial_backoff: config
+                .reconnect_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.initial_backoff),
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(Duration::from_millis(MAX_RECONNECT_BACKOFF_MS))
+    }
+}
+
+/// Connection state of a supervised dynamic provider.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ProviderStatus {
+    Connecting,
+    Available,
+    Reconnecting { attempt: u32 },
+    Failed { error: String },
+}
+
+/// Shared view of which dynamic providers are currently attached.
+///
+/// Providers without an entry are treated as available so unsupervised
+/// providers keep their existing behavior.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderHealth {
+    statuses: Arc<Mutex<HashMap<String, ProviderStatus>>>,
+}
+
+impl ProviderHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The view shared by every [`super::ToolRegistry::new`] in the process.
+    pub fn shared() -> Self {
+        static SHARED: OnceLock<ProviderHealth> = OnceLock::new();
+        SHARED.get_or_init(Self::new).clone()
+    }
+
+    pub fn status(&self, provider_id: &str) -> Option<ProviderStatus> {
+        self.statuses
+            .lock()
+            .ok()
+            .and_then(|statuses| statuses.get(provider_id).cloned())
+    }
+
+    pub fn is_available(&self, provider_id: &str) -> bool {
+        matches!(
+            self.status(provider_id),
+            None | Some(ProviderStatus::Available)
+        )
+    }
+
+    fn set(&self, provider_id: &str, status: ProviderStatus) {
+        if let Ok(mut statuses) = self.statuses.lock() {
+            statuses.insert(provider_id.to_string(), status);
+        }
+    }
+}
+
+/// Attach a dynamic provider, retrying with backoff until it connects or the
+/// policy's attempt budget runs out. Returns true once the provider is
+/// available again.
+pub async fn supervise_provider(
+    provider: Arc<dyn ToolProvider>,
+    policy: ReconnectPolicy,
+    health: ProviderHealth,
+    events: broadcast::Sender<ReapEvent>,
+) -> bool {
+    let provider_id = provider.id();
+    let mut attempt: u32 = 0;
+    health.set(provider_id, ProviderStatus::Connecting);
+    loop {
+        match provider.connect().await {
+            Ok(()) => {
+                health.set(provider_id, ProviderStatus::Available);
+                let _ = events.send(ReapEvent::new(
+                    "tools.provider.available",
+                    Some(json!({ "provider_id": provider_id, "attempts": attempt + 1 })),
+                ));
+                return true;
+            }
+            Err(error) => {
+                attempt += 1;
+                if attempt > policy.max_attempts {
+                    tracing::warn!(
+                        provider = provider_id,
+                        attempts = attempt,
+                        %error,
+                        "tool provider reconnection failed permanently"
+                    );
+                    health.set(
+                        provider_id,
+                        ProviderStatus::Failed {
+                            error: error.clone(),
+                        },
+                    );
+                    let _ = events.send(ReapEvent::new(
+                        "tools.provider.failed",
+                        Some(json!({
+                            "provider_id": provider_id,
+                            "attempts": attempt,
+                            "error": error,
+                        })),
+                    ));
+                    return false;
+                }
+                let delay = policy.backoff(attempt);
+                tracing::debug!(
+                    provider = provider_id,
+                    attempt,
+                    delay_ms = delay.as_millis() as u64,
+                    %error,
+                    "tool provider unavailable; retrying"
+                );
+                health.set(provider_id, ProviderStatus::Reconnecting { attempt });
+                let _ = events.send(ReapEvent::new(
+                    "tools.provider.reconnecting",
+                    Some(json!({
+                        "provider_id": provider_id,
+                        "attempt": attempt,
+                        "retry_in_ms": delay.as_millis() as u64,
+                        "error": error,
+                    })),
+                ));
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        let policy = ReconnectPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(500),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_millis(1_000));
+        assert_eq!(policy.backoff(3), Duration::from_millis(2_000));
+        assert_eq!(
+            policy.backoff(40),
+            Duration::from_millis(MAX_RECONNECT_BACKOFF_MS)
+        );
+    }
+
+    #[test]
+    fn unsupervised_providers_are_available() {
+        let health = ProviderHealth::new();
+        assert!(health.is_available("anything"));
+        health.set("mcp", ProviderStatus::Reconnecting { attempt: 1 });
+        assert!(!health.is_available("mcp"));
+    }
+}
@@ -1,10 +1,17 @@
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
 use roci::tools::Tool;
 use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
 
 use crate::homie_config::{ToolProviderConfig, ToolsConfig};
+use crate::router::ReapEvent;
 
+use super::reconnect::{supervise_provider, ProviderHealth, ReconnectPolicy};
+use super::session::SessionToolProvider;
 use super::{apply_patch, browser, cron, exec, fs, process, web, ToolContext};
 
 pub trait ToolProvider: Send + Sync {
@@ -12,6 +19,11 @@ pub trait ToolProvider: Send + Sync {
     fn is_dynamic(&self) -> bool {
         false
     }
+    /// (Re)attach the provider's backing tool server. Static providers are
+    /// always connected.
+    fn connect(&self) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send + '_>> {
+        Box::pin(async { Ok(()) })
+    }
     fn tools(&self, ctx: ToolContext) -> Vec<Arc<dyn Tool>>;
 }
 
@@ -22,15 +34,32 @@ pub struct ListedTool {
     pub name: String,
     pub description: String,
     pub input_schema: Value,
+    /// Whether the provider's tool server is attached; always true for
+    /// static providers.
+    pub provider_available: bool,
 }
 
 #[derive(Clone)]
 struct ProvidedTool {
     provider_id: String,
     provider_dynamic: bool,
+    provider_available: bool,
     tool: Arc<dyn Tool>,
 }
 
+impl ProvidedTool {
+    fn listed(self) -> ListedTool {
+        ListedTool {
+            provider_id: self.provider_id,
+            provider_dynamic: self.provider_dynamic,
+            name: self.tool.name().to_string(),
+            description: self.tool.description().to_string(),
+            input_schema: self.tool.parameters().schema.clone(),
+            provider_available: self.provider_available,
+        }
+    }
+}
+
 pub struct CoreToolProvider;
 
 impl ToolProvider for CoreToolProvider {
@@ -62,27 +91,73 @@ impl ToolProvider for CoreToolProvider {
 
 pub struct ToolRegistry {
     providers: Vec<Arc<dyn ToolProvider>>,
+    health: ProviderHealth,
 }
 
 impl ToolRegistry {
+    /// Registry of the built-in providers, sharing the process-wide
+    /// [`ProviderHealth`] the startup supervisor reports into.
     pub fn new() -> Self {
         Self {
-            providers: vec![Arc::new(CoreToolProvider)],
+            providers: vec![Arc::new(CoreToolProvider), Arc::new(SessionToolProvider)],
+            health: ProviderHealth::shared(),
         }
     }
 
-    #[cfg_attr(not(test), allow(dead_code))]
+    #[cfg(test)]
     pub fn with_provider(mut self, provider: Arc<dyn ToolProvider>) -> Self {
         self.providers.push(provider);
         self
     }
 
+    #[cfg(test)]
+    pub fn with_health(mut self, health: ProviderHealth) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Spawn a reconnection loop for every enabled dynamic provider. Tools
+    /// from a provider are hidden until its loop reports it available.
+    pub fn supervise_dynamic_providers(
+        &self,
+        config: &ToolsConfig,
+        events: broadcast::Sender<ReapEvent>,
+    ) -> Vec<JoinHandle<bool>> {
+        self.providers
+            .iter()
+            .filter(|provider| provider.is_dynamic())
+            .filter(|provider| {
+                config
+                    .providers
+                    .get(provider.id())
+                    .and_then(|cfg| cfg.enabled)
+                    .unwrap_or(false)
+            })
+            .map(|provider| self.spawn_supervisor(provider.clone(), config, events.clone()))
+            .collect()
+    }
+
+    fn spawn_supervisor(
+        &self,
+        provider: Arc<dyn ToolProvider>,
+        config: &ToolsConfig,
+        events: broadcast::Sender<ReapEvent>,
+    ) -> JoinHandle<bool> {
+        let policy = ReconnectPolicy::from_config(config.providers.get(provider.id()));
+        tokio::spawn(supervise_provider(
+            provider,
+            policy,
+            self.health.clone(),
+            events,
+        ))
+    }
+
     pub fn build_tools(
         &self,
         ctx: ToolContext,
         config: &ToolsConfig,
     ) -> Result<Vec<Arc<dyn Tool>>, String> {
-        let tools = self.collect_tools(ctx, config)?;
+        let tools = self.collect_tools(ctx, config, false)?;
         Ok(tools.into_iter().map(|entry| entry.tool).collect())
     }
 
@@ -91,17 +166,23 @@ impl ToolRegistry {
         ctx: ToolContext,
         config: &ToolsConfig,
     ) -> Result<Vec<ListedTool>, String> {
-        let tools = self.collect_tools(ctx, config)?;
+        let tools = self.collect_tools(ctx, config, false)?;
+        Ok(tools.into_iter().map(ProvidedTool::listed).collect())
+    }
+
+    /// Tool `name` as offered to `ctx`. Unlike [`Self::list_tools`], tools
+    /// of an unreachable dynamic provider are included, marked unavailable.
+    pub fn describe_tool(
+        &self,
+        ctx: ToolContext,
+        config: &ToolsConfig,
+        name: &str,
+    ) -> Result<Option<ListedTool>, String> {
+        let tools = self.collect_tools(ctx, config, true)?;
         Ok(tools
             .into_iter()
-            .map(|entry| ListedTool {
-                provider_id: entry.provider_id,
-                provider_dynamic: entry.provider_dynamic,
-                name: entry.tool.name().to_string(),
-                description: entry.tool.description().to_string(),
-                input_schema: entry.tool.parameters().schema.clone(),
-            })
-            .collect())
+            .find(|entry| entry.tool.name() == name)
+            .map(ProvidedTool::listed))
     }
 
     fn validate_provider_overrides(&self, config: &ToolsConfig) -> Result<(), String> {
@@ -159,6 +240,7 @@ impl ToolRegistry {
         &self,
         ctx: ToolContext,
         config: &ToolsConfig,
+        include_unavailable: bool,
     ) -> Result<Vec<ProvidedTool>, String> {
         self.validate_provider_overrides(config)?;
         let mut tools = Vec::new();
@@ -179,6 +261,14 @@ impl ToolRegistry {
             if !provider_allowed {
                 continue;
             }
+            let available = !provider.is_dynamic() || self.health.is_available(provider.id());
+            if !available && !include_unavailable {
+                tracing::debug!(
+                    provider = provider.id(),
+                    "tool provider unavailable; skipping"
+                );
+                continue;
+            }
             let provider_tools = provider.tools(ctx.clone());
             self.validate_tool_overrides(provider.id(), override_cfg, &provider_tools)?;
             for tool in provider_tools {
@@ -205,6 +295,7 @@ impl ToolRegistry {
                 tools.push(ProvidedTool {
                     provider_id: provider.id().to_string(),
                     provider_dynamic: provider.is_dynamic(),
+                    provider_available: available,
                     tool,
                 });
             }
@@ -265,13 +356,19 @@ fn tool_allowed(tool_name: &str, override_cfg: Option<&ToolProviderConfig>) -> b
 
 #[cfg(test)]
 mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicU32, Ordering};
     use std::sync::Arc;
 
     use roci::tools::{AgentTool, AgentToolParameters, Tool};
     use serde_json::json;
+    use tokio::sync::broadcast;
 
     use crate::homie_config::ToolsConfig;
+    use crate::router::ReapEvent;
 
+    use super::super::reconnect::{ProviderHealth, ProviderStatus};
     use super::{ToolContext, ToolProvider, ToolRegistry};
 
     struct StaticProvider {
@@ -304,6 +401,65 @@ mod tests {
         }
     }
 
+    /// Dynamic provider whose tool server refuses the first `failures` connects.
+    struct FlakyProvider {
+        failures: AtomicU32,
+    }
+
+    impl ToolProvider for FlakyProvider {
+        fn id(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn is_dynamic(&self) -> bool {
+            true
+        }
+
+        fn connect(&self) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send + '_>> {
+            Box::pin(async move {
+                let remaining = self.failures.load(Ordering::SeqCst);
+                if remaining > 0 {
+                    self.failures.store(remaining - 1, Ordering::SeqCst);
+                    return Err("connection refused".to_string());
+                }
+                Ok(())
+            })
+        }
+
+        fn tools(&self, _ctx: ToolContext) -> Vec<Arc<dyn Tool>> {
+            vec![Arc::new(AgentTool::new(
+                "flaky_tool",
+                "test",
+                AgentToolParameters::empty(),
+                |_args, _ctx| async move { Ok(json!({"ok": true})) },
+            )) as Arc<dyn Tool>]
+        }
+    }
+
+    fn flaky_config(max_attempts: u32) -> ToolsConfig {
+        let mut config = ToolsConfig::default();
+        config.providers.insert(
+            "flaky".into(),
+            crate::homie_config::ToolProviderConfig {
+                enabled: Some(true),
+                channels: Vec::new(),
+                allow_tools: Vec::new(),
+                deny_tools: Vec::new(),
+                reconnect_max_attempts: Some(max_attempts),
+                reconnect_backoff_ms: Some(1),
+            },
+        );
+        config
+    }
+
+    fn drain_topics(rx: &mut broadcast::Receiver<ReapEvent>) -> Vec<String> {
+        let mut topics = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            topics.push(event.topic);
+        }
+        topics
+    }
+
     fn dummy_ctx() -> ToolContext {
         dummy_ctx_for_channel("web")
     }
@@ -336,6 +492,8 @@ mod tests {
                 channels: Vec::new(),
                 allow_tools: Vec::new(),
                 deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
             },
         );
         let error = match ToolRegistry::new().build_tools(dummy_ctx(), &config) {
@@ -366,6 +524,8 @@ mod tests {
                 channels: Vec::new(),
                 allow_tools: Vec::new(),
                 deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
             },
         );
         config.providers.insert(
@@ -375,6 +535,8 @@ mod tests {
                 channels: Vec::new(),
                 allow_tools: Vec::new(),
                 deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
             },
         );
         let error = match registry.build_tools(dummy_ctx(), &config) {
@@ -399,6 +561,8 @@ mod tests {
                 channels: vec!["mobile".to_string()],
                 allow_tools: Vec::new(),
                 deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
             },
         );
         let tools = registry
@@ -422,6 +586,8 @@ mod tests {
                 channels: vec!["mobile".to_string()],
                 allow_tools: Vec::new(),
                 deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
             },
         );
         let tools = registry
@@ -445,6 +611,8 @@ mod tests {
                 channels: vec!["mobile".to_string()],
                 allow_tools: Vec::new(),
                 deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
             },
         );
 
@@ -484,4 +652,113 @@ mod tests {
             .any(|tool| tool.name() == "channel_tool");
         assert_eq!(list_unknown, run_unknown);
     }
+
+    #[test]
+    fn configured_channels_participate_in_gating() {
+        let processes = Arc::new(super::super::ProcessRegistry::new());
+        let default_ctx = ToolContext::with_processes_and_channel(
+            processes.clone(),
+            Arc::new(crate::HomieConfig::default()),
+            Some("discord"),
+        );
+        assert_eq!(default_ctx.channel, None);
+
+        let mut homie_config = crate::HomieConfig::default();
+        homie_config.tools.channels = vec![" Discord ".into(), "slack".into()];
+        homie_config.tools.providers.insert(
+            "core".into(),
+            crate::homie_config::ToolProviderConfig {
+                enabled: Some(true),
+                channels: vec!["slack".to_string()],
+                allow_tools: Vec::new(),
+                deny_tools: Vec::new(),
+                reconnect_max_attempts: None,
+                reconnect_backoff_ms: None,
+            },
+        );
+        let homie_config = Arc::new(homie_config);
+        let ctx = ToolContext::with_processes_and_channel(
+            processes.clone(),
+            homie_config.clone(),
+            Some("DISCORD"),
+        );
+        assert_eq!(ctx.channel.as_deref(), Some("discord"));
+        let tools = ToolRegistry::new()
+            .list_tools(ctx, &homie_config.tools)
+            .expect("list discord");
+        assert!(tools.is_empty());
+
+        let ctx =
+            ToolContext::with_processes_and_channel(processes, homie_config.clone(), Some("slack"));
+        let tools = ToolRegistry::new()
+            .list_tools(ctx, &homie_config.tools)
+            .expect("list slack");
+        assert!(tools.iter().any(|tool| tool.name == "read"));
+    }
+
+    #[tokio::test]
+    async fn flaky_dynamic_provider_reconnects_and_tools_reappear() {
+        let health = ProviderHealth::new();
+        let registry = ToolRegistry::new()
+            .with_provider(Arc::new(FlakyProvider {
+                failures: AtomicU32::new(2),
+            }))
+            .with_health(health.clone());
+        let config = flaky_config(3);
+        let (events, mut rx) = broadcast::channel(16);
+
+        let handles = registry.supervise_dynamic_providers(&config, events.clone());
+        assert_eq!(handles.len(), 1);
+        for handle in handles {
+            assert!(handle.await.expect("supervisor join"));
+        }
+
+        assert_eq!(health.status("flaky"), Some(ProviderStatus::Available));
+        assert_eq!(
+            drain_topics(&mut rx),
+            vec![
+                "tools.provider.reconnecting",
+                "tools.provider.reconnecting",
+                "tools.provider.available",
+            ]
+        );
+        let tools = registry.build_tools(dummy_ctx(), &config).expect("build");
+        assert!(tools.iter().any(|tool| tool.name() == "flaky_tool"));
+    }
+
+    #[tokio::test]
+    async fn dynamic_provider_tools_hidden_after_permanent_failure() {
+        let health = ProviderHealth::new();
+        let registry = ToolRegistry::new()
+            .with_provider(Arc::new(FlakyProvider {
+                failures: AtomicU32::new(5),
+            }))
+            .with_health(health.clone());
+        let config = flaky_config(1);
+        let (events, mut rx) = broadcast::channel(16);
+
+        let handles = registry.supervise_dynamic_providers(&config, events);
+        assert_eq!(handles.len(), 1);
+        for handle in handles {
+            assert!(!handle.await.expect("supervisor join"));
+        }
+
+        assert!(matches!(
+            health.status("flaky"),
+            Some(ProviderStatus::Failed { .. })
+        ));
+        assert_eq!(
+            drain_topics(&mut rx).last().map(String::as_str),
+            Some("tools.provider.failed")
+        );
+        let tools = registry.build_tools(dummy_ctx(), &config).expect("build");
+        assert!(!tools.iter().any(|tool| tool.name() == "flaky_tool"));
+
+        let described = registry
+            .describe_tool(dummy_ctx(), &config, "flaky_tool")
+            .expect("describe")
+            .expect("unreachable tools are still described");
+        assert_eq!(described.provider_id, "flaky");
+        assert!(!described.provider_available);
+    }
 }
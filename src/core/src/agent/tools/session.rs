@@ -0,0 +1,288 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use roci::error::RociError;
+use roci::tools::tool::ToolExecutionContext;
+use roci::tools::{AgentTool, AgentToolParameters, Tool, ToolArguments};
+use serde_json::Value;
+use url::Url;
+
+use super::registry::ToolProvider;
+use super::ToolContext;
+
+pub const SESSION_PROVIDER_ID: &str = "session";
+
+const MAX_TOOL_NAME_LEN: usize = 64;
+const MAX_DESCRIPTION_LEN: usize = 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An HTTP-backed tool registered by a client for the lifetime of its
+/// connection. Calls POST the tool arguments as JSON to `url`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionToolSpec {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Value,
+    pub url: String,
+}
+
+impl SessionToolSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_tool_name(&self.name)?;
+        let description = self.description.trim();
+        if description.is_empty() {
+            return Err("description is required".into());
+        }
+        if description.len() > MAX_DESCRIPTION_LEN {
+            return Err(format!(
+                "description exceeds {MAX_DESCRIPTION_LEN} characters"
+            ));
+        }
+        validate_input_schema(&self.input_schema)?;
+        validate_tool_url(&self.url)
+    }
+}
+
+/// Session tools shared between a connection's backend and its tool
+/// requests. Clones share the same list.
+#[derive(Clone, Default)]
+pub struct SessionTools {
+    specs: Arc<RwLock<Vec<SessionToolSpec>>>,
+}
+
+impl SessionTools {
+    /// Add `spec`, replacing an earlier registration with the same name.
+    pub fn register(&self, spec: SessionToolSpec) {
+        let mut specs = self.specs.write().unwrap_or_else(|e| e.into_inner());
+        specs.retain(|existing| existing.name != spec.name);
+        specs.push(spec);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.specs().iter().any(|spec| spec.name == name)
+    }
+
+    pub fn specs(&self) -> Vec<SessionToolSpec> {
+        self.specs.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Exposes the connection's session tools through the tool registry so
+/// channel policy and name-conflict checks apply to them too.
+pub struct SessionToolProvider;
+
+impl ToolProvider for SessionToolProvider {
+    fn id(&self) -> &'static str {
+        SESSION_PROVIDER_ID
+    }
+
+    fn tools(&self, ctx: ToolContext) -> Vec<Arc<dyn Tool>> {
+        ctx.session_tools
+            .specs()
+            .into_iter()
+            .map(http_tool)
+            .collect()
+    }
+}
+
+fn http_tool(spec: SessionToolSpec) -> Arc<dyn Tool> {
+    let mut params = AgentToolParameters::object().build();
+    params.schema = spec.input_schema.clone();
+    let name = spec.name.clone();
+    let description = spec.description.clone();
+    let spec = Arc::new(spec);
+    Arc::new(AgentTool::new(
+        name,
+        description,
+        params,
+        move |args: ToolArguments, _ctx: ToolExecutionContext| {
+            let spec = spec.clone();
+            async move { call_http_tool(&spec, &args).await }
+        },
+    ))
+}
+
+async fn call_http_tool(spec: &SessionToolSpec, args: &ToolArguments) -> Result<Value, RociError> {
+    let tool_error = |message: String| RociError::ToolExecution {
+        tool_name: spec.name.clone(),
+        message,
+    };
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| tool_error(format!("failed to build http client: {e}")))?;
+    let response = client
+        .post(&spec.url)
+        .json(args.raw())
+        .send()
+        .await
+        .map_err(|e| tool_error(format!("request failed: {e}")))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| tool_error(format!("failed to read response: {e}")))?;
+    if !status.is_success() {
+        return Err(tool_error(format!("endpoint returned {status}: {body}")));
+    }
+    Ok(serde_json::from_str(&body).unwrap_or(Value::String(body)))
+}
+
+fn validate_tool_name(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return Err("name is required".into());
+    };
+    if name.len() > MAX_TOOL_NAME_LEN {
+        return Err(format!("name exceeds {MAX_TOOL_NAME_LEN} characters"));
+    }
+    if !first.is_ascii_alphabetic()
+        || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(
+            "name must start with a letter and contain only letters, digits, `_` or `-`".into(),
+        );
+    }
+    Ok(())
+}
+
+fn validate_input_schema(schema: &Value) -> Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        return Err("input_schema must be a JSON object".into());
+    };
+    if schema.get("type").and_then(Value::as_str) != Some("object") {
+        return Err("input_schema type must be \"object\"".into());
+    }
+    if let Some(properties) = schema.get("properties") {
+        let Some(properties) = properties.as_object() else {
+            return Err("input_schema properties must be an object".into());
+        };
+        if let Some((key, _)) = properties.iter().find(|(_, value)| !value.is_object()) {
+            return Err(format!("input_schema property `{key}` must be an object"));
+        }
+    }
+    if let Some(required) = schema.get("required") {
+        let valid = required
+            .as_array()
+            .is_some_and(|items| items.iter().all(Value::is_string));
+        if !valid {
+            return Err("input_schema required must be an array of strings".into());
+        }
+    }
+    Ok(())
+}
+
+fn validate_tool_url(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| format!("invalid url: {e}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("url must use http or https".into());
+    }
+    if parsed.host_str().unwrap_or_default().is_empty() {
+        return Err("url must include a host".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn spec() -> SessionToolSpec {
+        SessionToolSpec {
+            name: "lookup_order".into(),
+            description: "Look up an order".into(),
+            input_schema: json!({
+                "type": "object",
+                "properties": { "order_id": { "type": "string" } },
+                "required": ["order_id"],
+            }),
+            url: "https://tools.example.com/orders".into(),
+        }
+    }
+
+    #[test]
+    fn validates_spec() {
+        assert_eq!(spec().validate(), Ok(()));
+
+        let cases = [
+            SessionToolSpec {
+                name: "1bad".into(),
+                ..spec()
+            },
+            SessionToolSpec {
+                name: "has space".into(),
+                ..spec()
+            },
+            SessionToolSpec {
+                description: "  ".into(),
+                ..spec()
+            },
+            SessionToolSpec {
+                input_schema: json!({ "type": "string" }),
+                ..spec()
+            },
+            SessionToolSpec {
+                input_schema: json!({ "type": "object", "properties": [] }),
+                ..spec()
+            },
+            SessionToolSpec {
+                input_schema: json!({ "type": "object", "required": [1] }),
+                ..spec()
+            },
+            SessionToolSpec {
+                url: "ftp://tools.example.com".into(),
+                ..spec()
+            },
+            SessionToolSpec {
+                url: "not a url".into(),
+                ..spec()
+            },
+        ];
+        for case in cases {
+            assert!(case.validate().is_err(), "{case:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn register_replaces_same_name() {
+        let tools = SessionTools::default();
+        assert!(tools.specs().is_empty());
+        tools.register(spec());
+        tools.register(SessionToolSpec {
+            description: "Updated".into(),
+            ..spec()
+        });
+        let specs = tools.clone().specs();
+        assert_eq!(specs.len(), 1);
+        assert_eq!(specs[0].description, "Updated");
+        assert!(tools.contains("lookup_order"));
+    }
+
+    #[tokio::test]
+    async fn http_tool_posts_arguments_and_returns_json() {
+        let app = axum::Router::new().route(
+            "/tool",
+            axum::routing::post(|axum::Json(body): axum::Json<Value>| async move {
+                axum::Json(json!({ "echo": body }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let tool = http_tool(SessionToolSpec {
+            url: format!("http://{addr}/tool"),
+            ..spec()
+        });
+        let args = ToolArguments::new(json!({ "order_id": "A-1" }));
+        let result = tool
+            .execute(&args, &ToolExecutionContext::default())
+            .await
+            .expect("tool result");
+        assert_eq!(result, json!({ "echo": { "order_id": "A-1" } }));
+    }
+}
@@ -1075,7 +1075,9 @@ async fn ensure_url_safe(url: &Url) -> Result<(), RociError> {
     Ok(())
 }
 
-fn ip_is_private(ip: IpAddr) -> bool {
+/// Loopback, private, link-local and other addresses not reachable on the
+/// public internet.
+pub(crate) fn ip_is_private(ip: IpAddr) -> bool {
     match ip {
         IpAddr::V4(v4) => {
             v4.is_private()
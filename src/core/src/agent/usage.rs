@@ -0,0 +1,80 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Token counts for one turn, in the same shape for every provider.
+/// `input` counts every prompt token, cached or not; `cache_read` and
+/// `cache_write` are the parts of it read from or written to the provider's
+/// prompt cache. Counts a provider does not report are zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct TokenUsage {
+    pub(crate) input: u64,
+    pub(crate) output: u64,
+    pub(crate) cache_read: u64,
+    pub(crate) cache_write: u64,
+    pub(crate) total: u64,
+}
+
+impl TokenUsage {
+    /// Usage from counts Homie estimated itself; nothing is known about
+    /// the cache.
+    pub(crate) fn estimated(input: u64, output: u64) -> Self {
+        Self {
+            input,
+            output,
+            total: input + output,
+            ..Self::default()
+        }
+    }
+
+    /// Normalize a Codex app-server usage report (`tokenUsage.last`).
+    /// Cached input is part of `inputTokens`. The roci backend gets no
+    /// provider counts from its run loop, so its usage is always
+    /// [`TokenUsage::estimated`].
+    pub(crate) fn from_codex(raw: &Value) -> Self {
+        let count = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| raw.get(*key)?.as_u64())
+                .unwrap_or(0)
+        };
+        let input = count(&["inputTokens", "input_tokens"]);
+        let output = count(&["outputTokens", "output_tokens"]);
+        let total = match count(&["totalTokens", "total_tokens"]) {
+            0 => input + output,
+            total => total,
+        };
+        Self {
+            input,
+            output,
+            cache_read: count(&["cachedInputTokens", "cached_input_tokens"]),
+            cache_write: 0,
+            total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn codex_counts_and_missing_fields_are_zero() {
+        let raw = json!({ "inputTokens": 12, "cachedInputTokens": 4, "outputTokens": 3 });
+        assert_eq!(
+            TokenUsage::from_codex(&raw),
+            TokenUsage {
+                input: 12,
+                output: 3,
+                cache_read: 4,
+                cache_write: 0,
+                total: 15,
+            }
+        );
+        let snake = json!({ "input_tokens": 12, "output_tokens": 3, "total_tokens": 20 });
+        assert_eq!(TokenUsage::from_codex(&snake).total, 20);
+        assert_eq!(
+            serde_json::to_value(TokenUsage::from_codex(&json!({}))).unwrap(),
+            json!({ "input": 0, "output": 0, "cache_read": 0, "cache_write": 0, "total": 0 })
+        );
+    }
+}
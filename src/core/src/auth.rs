@@ -3,10 +3,14 @@ use std::net::IpAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use axum::http::HeaderMap;
+use axum::http::{header, HeaderMap};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::process::Command;
 
+use crate::authz::Role;
+use crate::config::{ApiKey, AuthMode, ServerConfig};
+
 /// Identity resolved from Tailscale headers + whois verification.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TailscaleIdentity {
@@ -25,6 +29,10 @@ pub enum AuthOutcome {
     Lan,
     /// Tailscale Serve identity verified.
     Tailscale(TailscaleIdentity),
+    /// Bearer token matched a configured API key.
+    ApiKey { name: String, role: Role },
+    /// `AuthMode::Open` — accepted without credentials.
+    Open,
     /// Rejected with reason.
     Rejected(String),
 }
@@ -45,6 +53,8 @@ impl AuthOutcome {
     pub fn identity_string(&self) -> Option<String> {
         match self {
             Self::Tailscale(id) => Some(id.login.clone()),
+            Self::ApiKey { name, .. } => Some(format!("api-key:{name}")),
+            Self::Open => Some("open".into()),
             Self::Local => Some("local".into()),
             Self::Lan => Some("lan".into()),
             Self::Rejected(_) => None,
@@ -165,8 +175,140 @@ impl TailscaleWhois for LiveWhois {
     }
 }
 
+// ── API keys ─────────────────────────────────────────────────────────
+
+/// Lowercase hex SHA-256 of a raw API key, the form stored in config.
+pub fn hash_api_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Parse a `name:role:sha256` key spec (as used by `HOMIE_API_KEYS`).
+pub fn parse_api_key_spec(spec: &str) -> Result<ApiKey, String> {
+    let mut parts = spec.trim().splitn(3, ':');
+    let (Some(name), Some(role), Some(hash)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(format!(
+            "api key spec must be name:role:sha256, got {spec:?}"
+        ));
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("api key name is empty".into());
+    }
+    let role = match role.trim().to_ascii_lowercase().as_str() {
+        "owner" => Role::Owner,
+        "user" => Role::User,
+        "viewer" => Role::Viewer,
+        other => return Err(format!("unknown role for api key {name}: {other}")),
+    };
+    let hash = hash.trim().to_ascii_lowercase();
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("api key {name} must be a hex sha256 digest"));
+    }
+    Ok(ApiKey {
+        name: name.to_string(),
+        sha256: hash,
+        role,
+    })
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    Some(token.trim()).filter(|t| !t.is_empty())
+}
+
+/// Compare without short-circuiting so timing does not leak how many
+/// leading bytes matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Find the configured key matching `token`. Every key is checked so the
+/// match position is not observable.
+fn verify_api_key<'a>(token: &str, keys: &'a [ApiKey]) -> Option<&'a ApiKey> {
+    let digest = hash_api_key(token);
+    let mut found = None;
+    for key in keys {
+        if constant_time_eq(digest.as_bytes(), key.sha256.as_bytes()) && found.is_none() {
+            found = Some(key);
+        }
+    }
+    found
+}
+
 // ── Resolve auth for an incoming WS upgrade ──────────────────────────
 
+/// Authenticate an incoming connection according to `config.auth_mode`.
+///
+/// - `Tailscale` → [`authenticate`]
+/// - `ApiKey` → a bearer token must match a configured key. Requests
+///   without one fall back to [`authenticate`] when LAN access is allowed,
+///   otherwise only direct (unproxied) loopback is accepted.
+/// - `Open` → loopback is `Local`, everyone else `Open`
+pub async fn authenticate_with_config(
+    headers: &HeaderMap,
+    remote_ip: IpAddr,
+    config: &ServerConfig,
+    whois: &Arc<dyn TailscaleWhois>,
+) -> AuthOutcome {
+    match config.auth_mode {
+        AuthMode::Tailscale => {
+            authenticate(
+                headers,
+                remote_ip,
+                config.tailscale_serve,
+                config.allow_lan,
+                whois,
+            )
+            .await
+        }
+        AuthMode::ApiKey => {
+            if let Some(token) = bearer_token(headers) {
+                return match verify_api_key(token, &config.api_keys) {
+                    Some(key) => AuthOutcome::ApiKey {
+                        name: key.name.clone(),
+                        role: key.role,
+                    },
+                    None => AuthOutcome::Rejected("invalid api key".into()),
+                };
+            }
+            if config.allow_lan {
+                return authenticate(
+                    headers,
+                    remote_ip,
+                    config.tailscale_serve,
+                    config.allow_lan,
+                    whois,
+                )
+                .await;
+            }
+            // A reverse proxy on this host connects from loopback; only
+            // trust loopback when nothing was forwarded.
+            if remote_ip.is_loopback() && forwarded_client_ip(headers).is_none() {
+                AuthOutcome::Local
+            } else {
+                AuthOutcome::Rejected("missing api key".into())
+            }
+        }
+        AuthMode::Open => {
+            if remote_ip.is_loopback() && forwarded_client_ip(headers).is_none() {
+                AuthOutcome::Local
+            } else {
+                AuthOutcome::Open
+            }
+        }
+    }
+}
+
 /// Authenticate an incoming connection.
 ///
 /// - Loopback connections without Tailscale Serve → `AuthOutcome::Local`
@@ -341,6 +483,108 @@ mod tests {
         assert!(matches!(result, AuthOutcome::Rejected(_)));
     }
 
+    fn api_key_config(allow_lan: bool) -> ServerConfig {
+        ServerConfig {
+            auth_mode: AuthMode::ApiKey,
+            allow_lan,
+            api_keys: vec![
+                ApiKey {
+                    name: "ci".into(),
+                    sha256: hash_api_key("ci-secret"),
+                    role: Role::Viewer,
+                },
+                ApiKey {
+                    name: "ops".into(),
+                    sha256: hash_api_key("ops-secret"),
+                    role: Role::Owner,
+                },
+            ],
+            ..ServerConfig::default()
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+        h
+    }
+
+    #[tokio::test]
+    async fn api_key_maps_to_configured_role() {
+        let config = api_key_config(false);
+        let result =
+            authenticate_with_config(&bearer("ops-secret"), remote(), &config, &stub(None)).await;
+        match result {
+            AuthOutcome::ApiKey { name, role } => {
+                assert_eq!(name, "ops");
+                assert_eq!(role, Role::Owner);
+            }
+            other => panic!("expected ApiKey, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn api_key_invalid_or_missing_rejected() {
+        let config = api_key_config(false);
+        let wrong =
+            authenticate_with_config(&bearer("nope"), loopback(), &config, &stub(None)).await;
+        assert!(matches!(wrong, AuthOutcome::Rejected(_)));
+
+        let missing =
+            authenticate_with_config(&HeaderMap::new(), remote(), &config, &stub(None)).await;
+        assert!(matches!(missing, AuthOutcome::Rejected(_)));
+
+        let mut proxied = HeaderMap::new();
+        proxied.insert("x-forwarded-for", HeaderValue::from_static("203.0.113.9"));
+        let proxied = authenticate_with_config(&proxied, loopback(), &config, &stub(None)).await;
+        assert!(matches!(proxied, AuthOutcome::Rejected(_)));
+
+        let direct =
+            authenticate_with_config(&HeaderMap::new(), loopback(), &config, &stub(None)).await;
+        assert!(matches!(direct, AuthOutcome::Local));
+    }
+
+    #[tokio::test]
+    async fn api_key_mode_falls_back_to_lan_when_allowed() {
+        let config = api_key_config(true);
+        let lan = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+        let result = authenticate_with_config(&HeaderMap::new(), lan, &config, &stub(None)).await;
+        assert!(matches!(result, AuthOutcome::Lan));
+    }
+
+    #[tokio::test]
+    async fn open_mode_accepts_remote() {
+        let config = ServerConfig {
+            auth_mode: AuthMode::Open,
+            ..ServerConfig::default()
+        };
+        let result =
+            authenticate_with_config(&HeaderMap::new(), remote(), &config, &stub(None)).await;
+        assert!(matches!(result, AuthOutcome::Open));
+    }
+
+    #[test]
+    fn api_key_spec_parsing() {
+        let hash = hash_api_key("secret");
+        let key = parse_api_key_spec(&format!("deploy:User:{}", hash.to_uppercase())).unwrap();
+        assert_eq!(key.name, "deploy");
+        assert_eq!(key.role, Role::User);
+        assert_eq!(key.sha256, hash);
+        assert!(parse_api_key_spec("deploy:user").is_err());
+        assert!(parse_api_key_spec("deploy:root:abcd").is_err());
+        assert!(parse_api_key_spec(&format!("deploy:user:{}", &hash[..10])).is_err());
+    }
+
+    #[test]
+    fn constant_time_eq_requires_equal_length_and_bytes() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+
     #[tokio::test]
     async fn case_insensitive_login_match() {
         let headers = make_tailscale_headers("Alice");
@@ -1,3 +1,11 @@
+//! Per-role method authorization. Every service lists the methods it
+//! handles and the scope each requires in a `METHOD_SCOPES` const, which
+//! it returns from `ServiceHandler::method_scopes`; the router merges those
+//! rows into a [`MethodPolicy`], and [`AuthContext::allows`] decides which
+//! roles hold each scope.
+
+use std::collections::HashMap;
+
 use crate::auth::AuthOutcome;
 use crate::config::ServerConfig;
 
@@ -9,6 +17,16 @@ pub enum Role {
     Viewer,
 }
 
+impl Role {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::User => "user",
+            Self::Viewer => "viewer",
+        }
+    }
+}
+
 /// Scopes required by specific methods.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Scope {
@@ -27,6 +45,28 @@ pub enum Scope {
     PairingWrite,
     NotificationsRead,
     NotificationsWrite,
+    StateRead,
+    StateWrite,
+    SystemRead,
+    Admin,
+}
+
+impl Scope {
+    /// Whether methods with this scope change state; those are audited.
+    pub fn is_mutating(self) -> bool {
+        matches!(
+            self,
+            Self::TerminalWrite
+                | Self::AgentWrite
+                | Self::PresenceWrite
+                | Self::JobsWrite
+                | Self::CronWrite
+                | Self::PairingWrite
+                | Self::NotificationsWrite
+                | Self::StateWrite
+                | Self::Admin
+        )
+    }
 }
 
 /// Authorization context derived from the authenticated connection.
@@ -58,10 +98,16 @@ impl AuthContext {
                     | Scope::PresenceWrite
                     | Scope::CronRead
                     | Scope::CronWrite
+                    | Scope::StateRead
+                    | Scope::StateWrite
             ),
             Role::Viewer => matches!(
                 scope,
-                Scope::TerminalRead | Scope::AgentRead | Scope::Events | Scope::PresenceRead
+                Scope::TerminalRead
+                    | Scope::AgentRead
+                    | Scope::Events
+                    | Scope::PresenceRead
+                    | Scope::StateRead
             ),
         }
     }
@@ -72,91 +118,110 @@ pub fn context_for_outcome(outcome: &AuthOutcome, config: &ServerConfig) -> Auth
         AuthOutcome::Local => config.local_role,
         AuthOutcome::Lan => config.local_role,
         AuthOutcome::Tailscale(_) => config.tailscale_role,
+        AuthOutcome::ApiKey { role, .. } => *role,
+        AuthOutcome::Open => config.open_role,
         AuthOutcome::Rejected(_) => Role::Viewer,
     };
     AuthContext::new(role)
 }
 
-pub fn scope_for_method(method: &str) -> Option<Scope> {
-    match method {
-        "events.subscribe" | "events.unsubscribe" => Some(Scope::Events),
-        "terminal.session.list"
-        | "terminal.session.attach"
-        | "terminal.session.preview"
-        | "terminal.tmux.list" => Some(Scope::TerminalRead),
-        "terminal.session.start"
-        | "terminal.session.resize"
-        | "terminal.session.input"
-        | "terminal.session.kill"
-        | "terminal.session.remove"
-        | "terminal.session.rename"
-        | "terminal.tmux.attach"
-        | "terminal.tmux.kill" => Some(Scope::TerminalWrite),
-        "agent.chat.list" | "agent.codex.list" => Some(Scope::AgentRead),
-        "chat.list"
-        | "chat.thread.read"
-        | "chat.thread.list"
-        | "chat.account.read"
-        | "chat.account.list"
-        | "chat.skills.list"
-        | "chat.model.list"
-        | "chat.collaboration.mode.list"
-        | "chat.files.search" => Some(Scope::AgentRead),
-        "agent.chat.create"
-        | "agent.chat.message.send"
-        | "agent.chat.cancel"
-        | "agent.chat.approval.respond"
-        | "agent.codex.create"
-        | "agent.codex.message.send"
-        | "agent.codex.cancel"
-        | "agent.codex.approval.respond"
-        | "chat.create"
-        | "chat.resume"
-        | "chat.message.send"
-        | "chat.cancel"
-        | "chat.approval.respond"
-        | "chat.thread.archive"
-        | "chat.thread.rename"
-        | "chat.settings.update"
-        | "chat.skills.config.write"
-        | "chat.account.login.start"
-        | "chat.account.login.poll" => Some(Scope::AgentWrite),
-        "presence.list" => Some(Scope::PresenceRead),
-        "presence.register" | "presence.heartbeat" | "presence.unregister" => {
-            Some(Scope::PresenceWrite)
+/// Methods the connection handles itself rather than routing to a service.
+pub(crate) const CONNECTION_METHOD_SCOPES: &[(&str, Scope)] = &[
+    ("events.subscribe", Scope::Events),
+    ("events.unsubscribe", Scope::Events),
+    ("agent.chat.event.subscribe", Scope::Events),
+    ("agent.codex.event.subscribe", Scope::Events),
+    ("chat.event.subscribe", Scope::Events),
+    ("chat.list.subscribe", Scope::AgentRead),
+    ("system.metrics", Scope::SystemRead),
+    ("system.subscriptions", Scope::SystemRead),
+    ("system.audit", Scope::Admin),
+];
+
+/// Declarative method → scope table. Services contribute their rows through
+/// `ServiceHandler::method_scopes`; a method missing from the table is
+/// owner-only, so a new method is never silently open to every role.
+#[derive(Debug, Clone, Default)]
+pub struct MethodPolicy {
+    scopes: HashMap<String, Scope>,
+}
+
+impl MethodPolicy {
+    /// A policy holding only the connection's built-in methods.
+    pub fn new() -> Self {
+        let mut policy = Self::default();
+        policy.extend(CONNECTION_METHOD_SCOPES);
+        policy
+    }
+
+    /// Add `(method, scope)` rows, replacing earlier rows for the same method.
+    pub fn extend(&mut self, rows: &[(&str, Scope)]) {
+        for (method, scope) in rows {
+            self.scopes.insert((*method).to_string(), *scope);
         }
-        "jobs.status" | "jobs.logs.tail" => Some(Scope::JobsRead),
-        "jobs.start" | "jobs.cancel" => Some(Scope::JobsWrite),
-        "cron.list" | "cron.status" | "cron.runs" | "cron.logs.tail" => Some(Scope::CronRead),
-        "cron.add" | "cron.update" | "cron.remove" | "cron.run" | "cron.run.force"
-        | "cron.start" | "cron.cancel" => Some(Scope::CronWrite),
-        "pairing.list" => Some(Scope::PairingRead),
-        "pairing.request" | "pairing.approve" | "pairing.revoke" => Some(Scope::PairingWrite),
-        "notifications.list" => Some(Scope::NotificationsRead),
-        "notifications.register" | "notifications.send" => Some(Scope::NotificationsWrite),
-        "agent.chat.event.subscribe" | "agent.codex.event.subscribe" | "chat.event.subscribe" => {
-            Some(Scope::Events)
+    }
+
+    pub fn scope_for(&self, method: &str) -> Option<Scope> {
+        self.scopes.get(method).copied()
+    }
+
+    /// Whether `ctx` may call `method`.
+    pub fn allows(&self, ctx: &AuthContext, method: &str) -> bool {
+        match self.scope_for(method) {
+            Some(scope) => ctx.allows(scope),
+            None => ctx.role() == Role::Owner,
         }
-        _ => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{scope_for_method, Scope};
+    use super::{AuthContext, MethodPolicy, Role, Scope};
+
+    #[test]
+    fn unmapped_methods_are_owner_only() {
+        let policy = MethodPolicy::new();
+        assert!(policy.allows(&AuthContext::new(Role::Owner), "chat.secret.thing"));
+        assert!(!policy.allows(&AuthContext::new(Role::User), "chat.secret.thing"));
+        assert!(!policy.allows(&AuthContext::new(Role::Viewer), "chat.secret.thing"));
+        assert!(!policy.allows(&AuthContext::new(Role::Viewer), "system.metrics"));
+    }
+
+    #[test]
+    fn roles_hold_only_their_baseline_scopes() {
+        let user = AuthContext::new(Role::User);
+        let viewer = AuthContext::new(Role::Viewer);
+        for scope in [Scope::CronRead, Scope::CronWrite, Scope::StateWrite] {
+            assert!(user.allows(scope));
+            assert!(!viewer.allows(scope));
+        }
+        for scope in [
+            Scope::JobsRead,
+            Scope::JobsWrite,
+            Scope::NotificationsRead,
+            Scope::NotificationsWrite,
+            Scope::PairingRead,
+            Scope::SystemRead,
+            Scope::Admin,
+        ] {
+            assert!(!user.allows(scope));
+            assert!(!viewer.allows(scope));
+        }
+        for scope in [Scope::AgentRead, Scope::TerminalRead, Scope::StateRead] {
+            assert!(viewer.allows(scope));
+        }
+    }
 
     #[test]
-    fn cron_methods_map_to_cron_scopes() {
-        assert_eq!(scope_for_method("cron.start"), Some(Scope::CronWrite));
-        assert_eq!(scope_for_method("cron.cancel"), Some(Scope::CronWrite));
-        assert_eq!(scope_for_method("cron.add"), Some(Scope::CronWrite));
-        assert_eq!(scope_for_method("cron.update"), Some(Scope::CronWrite));
-        assert_eq!(scope_for_method("cron.remove"), Some(Scope::CronWrite));
-        assert_eq!(scope_for_method("cron.run"), Some(Scope::CronWrite));
-        assert_eq!(scope_for_method("cron.run.force"), Some(Scope::CronWrite));
-        assert_eq!(scope_for_method("cron.status"), Some(Scope::CronRead));
-        assert_eq!(scope_for_method("cron.runs"), Some(Scope::CronRead));
-        assert_eq!(scope_for_method("cron.logs.tail"), Some(Scope::CronRead));
-        assert_eq!(scope_for_method("cron.list"), Some(Scope::CronRead));
+    fn extend_replaces_earlier_rows() {
+        let mut policy = MethodPolicy::new();
+        let user = AuthContext::new(Role::User);
+        assert!(policy.allows(&user, "events.subscribe"));
+        policy.extend(&[
+            ("events.subscribe", Scope::Admin),
+            ("demo.read", Scope::AgentRead),
+        ]);
+        assert!(!policy.allows(&user, "events.subscribe"));
+        assert!(policy.allows(&user, "demo.read"));
     }
 }
@@ -1,7 +1,72 @@
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::time::Duration;
 
+use homie_protocol::{
+    FrameLimits, ReconnectHint, DEFAULT_MAX_BINARY_BYTES, DEFAULT_MAX_MESSAGE_BYTES,
+};
+
 use crate::authz::Role;
+use crate::outbound::OverflowPolicy;
+use crate::router::{RequestTimeouts, UNTIMED_METHODS};
+use crate::storage::{RetentionPolicy, CHAT_RAW_EVENT_MAX_RUNS};
+
+/// How non-loopback connections are authenticated during the WS upgrade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthMode {
+    /// Tailscale Serve identity headers verified via whois.
+    #[default]
+    Tailscale,
+    /// `Authorization: Bearer <key>` checked against `ServerConfig::api_keys`.
+    /// Without a header, LAN clients fall back to the Tailscale flow when
+    /// `allow_lan` is set.
+    ApiKey,
+    /// Accept every connection. Non-loopback clients get `open_role`.
+    Open,
+}
+
+impl AuthMode {
+    pub fn from_label(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "tailscale" => Some(Self::Tailscale),
+            "api_key" | "api-key" | "apikey" => Some(Self::ApiKey),
+            "open" => Some(Self::Open),
+            _ => None,
+        }
+    }
+}
+
+/// How server-wide events reach the other nodes of a deployment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventBusKind {
+    /// Single node: events stay in this process.
+    #[default]
+    Local,
+    /// Nodes sharing one store exchange events through it. The store is a
+    /// SQLite file, so the nodes must run on the same host.
+    Store,
+}
+
+impl EventBusKind {
+    pub fn from_label(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "local" => Some(Self::Local),
+            "store" => Some(Self::Store),
+            _ => None,
+        }
+    }
+}
+
+/// A bearer token accepted in `AuthMode::ApiKey`. Only the SHA-256 digest of
+/// the key is kept in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    /// Label reported as the connection identity.
+    pub name: String,
+    /// Lowercase hex SHA-256 of the raw key.
+    pub sha256: String,
+    pub role: Role,
+}
 
 /// Server configuration.
 #[derive(Debug, Clone)]
@@ -14,10 +79,19 @@ pub struct ServerConfig {
     pub tailscale_serve: bool,
     /// Allow non-loopback LAN connections without Tailscale Serve.
     pub allow_lan: bool,
+    /// Authentication scheme for non-loopback connections.
+    pub auth_mode: AuthMode,
+    /// Keys accepted when `auth_mode` is `ApiKey`.
+    pub api_keys: Vec<ApiKey>,
+    /// Role assigned to non-loopback clients when `auth_mode` is `Open`.
+    pub open_role: Role,
     /// Interval between server→client pings.
     pub heartbeat_interval: Duration,
     /// Close the connection after this duration without any message.
     pub idle_timeout: Duration,
+    /// Close detached terminal sessions after this long without input or
+    /// output (zero disables).
+    pub terminal_idle_timeout: Duration,
     /// Role assigned to loopback clients.
     pub local_role: Role,
     /// Role assigned to authenticated Tailscale clients.
@@ -40,6 +114,54 @@ pub struct ServerConfig {
     pub cron_max_run_records: usize,
     /// Maximum number of concurrently running cron jobs.
     pub cron_max_concurrent_runs: usize,
+    /// Retention window for stored terminal recordings, in days.
+    pub terminal_recording_retention_days: u64,
+    /// Maximum number of terminal recordings to retain.
+    pub terminal_recording_max_records: usize,
+    /// Retention window for audit log entries, in days.
+    pub audit_retention_days: u64,
+    /// Retention window for audited tool invocations, in days.
+    pub tool_invocation_retention_days: u64,
+    /// Interval between store maintenance passes (prune + optimize).
+    pub maintenance_interval: Duration,
+    /// Sustained RPC requests allowed per connection per second (0 disables).
+    pub max_requests_per_sec: u32,
+    /// Requests a connection may burst above the sustained rate.
+    pub request_burst: u32,
+    /// Close a connection after this many consecutive rate-limited requests (0 = never).
+    pub rate_limit_close_after: u32,
+    /// First reconnect delay advertised to clients in the handshake.
+    pub reconnect_min_backoff: Duration,
+    /// Largest reconnect delay advertised to clients in the handshake.
+    pub reconnect_max_backoff: Duration,
+    /// Delay clients are asked to wait before reconnecting after a graceful
+    /// shutdown.
+    pub shutdown_retry_after: Duration,
+    /// Messages queued for one connection's socket before the overflow
+    /// policy applies.
+    pub outbound_capacity: usize,
+    /// What happens to events once a connection's queue is full.
+    pub outbound_overflow: OverflowPolicy,
+    /// Largest inbound JSON message, in bytes; bigger ones are rejected
+    /// with `PAYLOAD_TOO_LARGE` without being parsed.
+    pub max_message_bytes: usize,
+    /// Largest inbound binary frame (PTY input, upload chunk), in bytes.
+    pub max_binary_bytes: usize,
+    /// Let owner connections subscribe to `debug.events`, a mirror of
+    /// every event the server sends.
+    pub debug_events: bool,
+    /// Longest a request may run before it fails with `REQUEST_TIMEOUT`
+    /// (zero disables).
+    pub request_timeout: Duration,
+    /// `request_timeout` overrides by method name or dotted method prefix;
+    /// zero exempts. Defaults exempt `UNTIMED_METHODS`; mutating methods
+    /// are only timed through an override.
+    pub request_timeout_overrides: HashMap<String, Duration>,
+    /// How server-wide events (cron, terminal, chat list) are shared with
+    /// other nodes.
+    pub event_bus: EventBusKind,
+    /// How often a shared event bus is checked for other nodes' events.
+    pub event_bus_poll_interval: Duration,
 }
 
 impl Default for ServerConfig {
@@ -49,8 +171,12 @@ impl Default for ServerConfig {
             tailnet_bind: None,
             tailscale_serve: false,
             allow_lan: false,
+            auth_mode: AuthMode::Tailscale,
+            api_keys: Vec::new(),
+            open_role: Role::Viewer,
             heartbeat_interval: Duration::from_secs(15),
             idle_timeout: Duration::from_secs(120),
+            terminal_idle_timeout: Duration::from_secs(24 * 60 * 60),
             local_role: Role::Owner,
             tailscale_role: Role::User,
             node_timeout: Duration::from_secs(60),
@@ -62,6 +188,71 @@ impl Default for ServerConfig {
             cron_retention_days: 30,
             cron_max_run_records: 500,
             cron_max_concurrent_runs: 5,
+            terminal_recording_retention_days: 30,
+            terminal_recording_max_records: 100,
+            audit_retention_days: 90,
+            tool_invocation_retention_days: 90,
+            maintenance_interval: Duration::from_secs(60 * 60),
+            max_requests_per_sec: 50,
+            request_burst: 100,
+            rate_limit_close_after: 0,
+            reconnect_min_backoff: Duration::from_millis(500),
+            reconnect_max_backoff: Duration::from_secs(30),
+            shutdown_retry_after: Duration::from_secs(5),
+            outbound_capacity: 256,
+            outbound_overflow: OverflowPolicy::DropNewest,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            max_binary_bytes: DEFAULT_MAX_BINARY_BYTES,
+            debug_events: false,
+            request_timeout: Duration::from_secs(120),
+            request_timeout_overrides: UNTIMED_METHODS
+                .iter()
+                .map(|method| (method.to_string(), Duration::ZERO))
+                .collect(),
+            event_bus: EventBusKind::Local,
+            event_bus_poll_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Retention limits for `Store::run_maintenance`.
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            job_retention_days: self.job_retention_days,
+            job_max_records: self.job_max_records,
+            pairing_retention_secs: self.pairing_retention_secs,
+            notification_retention_days: self.notification_retention_days,
+            chat_raw_event_max_runs: CHAT_RAW_EVENT_MAX_RUNS,
+            cron_retention_days: self.cron_retention_days,
+            cron_max_run_records: self.cron_max_run_records,
+            terminal_recording_retention_days: self.terminal_recording_retention_days,
+            terminal_recording_max_records: self.terminal_recording_max_records,
+            audit_retention_days: self.audit_retention_days,
+            tool_invocation_retention_days: self.tool_invocation_retention_days,
+        }
+    }
+
+    /// Inbound frame size limits for each connection.
+    pub fn frame_limits(&self) -> FrameLimits {
+        FrameLimits {
+            max_message_bytes: self.max_message_bytes,
+            max_binary_bytes: self.max_binary_bytes,
+        }
+    }
+
+    /// Per-method request deadlines for each connection's router.
+    pub fn request_timeouts(&self) -> RequestTimeouts {
+        RequestTimeouts::new(self.request_timeout, self.request_timeout_overrides.clone())
+    }
+
+    /// Reconnect backoff advertised in `ServerHello`.
+    pub fn reconnect_hint(&self) -> ReconnectHint {
+        let min_backoff_ms = self.reconnect_min_backoff.as_millis() as u64;
+        ReconnectHint {
+            min_backoff_ms,
+            max_backoff_ms: (self.reconnect_max_backoff.as_millis() as u64).max(min_backoff_ms),
+            jitter: true,
         }
     }
 }
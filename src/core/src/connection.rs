@@ -1,3 +1,4 @@
+use std::net::IpAddr;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -7,30 +8,45 @@ use futures::{SinkExt, StreamExt};
 use serde::Deserialize;
 use serde_json::json;
 use serde_json::Value;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
 use homie_protocol::{
-    decode_message, encode_message, error_codes, ClientHello, HandshakeResponse, HelloReject,
-    HelloRejectCode, Message as ProtoMessage, Response, ServerHello, VersionRange,
-    PROTOCOL_VERSION,
+    check_frame_compression, decode_envelope_frame_limited, decode_message, encode_envelope_frame,
+    encode_message, error_codes, negotiate_compression, peek_request_id, ClientHello, Compression,
+    FrameLimits, HandshakeResponse, HelloReject, HelloRejectCode, Message as ProtoMessage, Ping,
+    Pong, ProtocolError, Response, ServerHello, StreamType, VersionRange, HEARTBEAT_CAPABILITY,
+    SERVER_COMPRESSION,
 };
 
+use crate::admin::{AdminService, RunFreeze};
 use crate::agent::ChatService;
+use crate::agent::RunSlots;
 use crate::auth::AuthOutcome;
-use crate::authz::{context_for_outcome, scope_for_method, AuthContext, Scope};
+use crate::authz::Scope;
 use crate::config::ServerConfig;
 use crate::debug_bytes::{fmt_bytes, terminal_debug_enabled_for};
 use crate::notifications::NotificationsService;
-use crate::outbound::OutboundMessage;
+use crate::outbound::{outbound_queue, Outbound, OutboundMessage, OverflowPolicy};
 use crate::pairing::PairingService;
-use crate::presence::{NodeRegistry, PresenceService};
-use crate::router::{MessageRouter, ServiceRegistry, SubscriptionManager};
+use crate::presence::{ConnectionRoster, NodeRegistry, PresenceService, RosterMember};
+use crate::router::{
+    AuditLog, ConnectionContext, ConnectionGuard, DebugEventTap, MessageRouter, MetricsRegistry,
+    RateLimiter, RequestTimeouts, ServiceRegistry, SubscriptionDirectory, SubscriptionManager,
+    DEBUG_EVENTS_TOPIC,
+};
+use crate::shutdown::ShutdownSignal;
+use crate::state::StateService;
 use crate::storage::Store;
 use crate::terminal::{TerminalRegistry, TerminalService};
 use crate::{CronService, JobsService};
 use crate::{ExecPolicy, HomieConfig};
 
+/// `system.audit` entries returned when no `limit` is given, and the most
+/// one request may ask for.
+const AUDIT_DEFAULT_LIMIT: usize = 100;
+const AUDIT_MAX_LIMIT: usize = 1000;
+
 /// Represents an authenticated WS connection after handshake.
 #[derive(Debug)]
 pub struct Connection {
@@ -39,11 +55,12 @@ pub struct Connection {
     pub negotiated_version: u16,
 }
 
-/// Parameters required to run a connection session.
 /// Parameters required to run a connection session.
 #[derive(Clone)]
 pub struct ConnectionParams {
     pub config: ServerConfig,
+    /// Peer address of the WebSocket upgrade.
+    pub remote: IpAddr,
     pub heartbeat_interval: Duration,
     pub idle_timeout: Duration,
     pub registry: ServiceRegistry,
@@ -56,15 +73,22 @@ pub struct ConnectionParams {
     pub exec_policy: Arc<ExecPolicy>,
     pub pairing_default_ttl_secs: u64,
     pub pairing_retention_secs: u64,
+    pub metrics: MetricsRegistry,
+    pub subscriptions: SubscriptionDirectory,
+    pub roster: ConnectionRoster,
+    pub debug_tap: DebugEventTap,
+    pub run_freeze: RunFreeze,
+    pub run_slots: RunSlots,
+    pub shutdown: ShutdownSignal,
 }
 
 /// Parameters required for the message loop lifecycle.
 #[derive(Clone)]
 struct MessageLoopParams {
     conn_id: Uuid,
+    context: ConnectionContext,
     heartbeat_interval: Duration,
     idle_timeout: Duration,
-    authz: AuthContext,
     store: Arc<dyn Store>,
     nodes: Arc<Mutex<NodeRegistry>>,
     terminal_registry: Arc<Mutex<TerminalRegistry>>,
@@ -74,7 +98,22 @@ struct MessageLoopParams {
     exec_policy: Arc<ExecPolicy>,
     pairing_default_ttl_secs: u64,
     pairing_retention_secs: u64,
-    tool_channel: Option<String>,
+    rate_limiter: RateLimiter,
+    metrics: MetricsRegistry,
+    subscription_directory: SubscriptionDirectory,
+    roster: ConnectionRoster,
+    member: RosterMember,
+    debug_tap: DebugEventTap,
+    run_freeze: RunFreeze,
+    run_slots: RunSlots,
+    compression: Option<Compression>,
+    envelope_heartbeat: bool,
+    shutdown: ShutdownSignal,
+    shutdown_retry_after: Duration,
+    outbound_capacity: usize,
+    outbound_overflow: OverflowPolicy,
+    frame_limits: FrameLimits,
+    request_timeouts: RequestTimeouts,
 }
 
 /// Run the full connection lifecycle: handshake → message loop with
@@ -82,6 +121,7 @@ struct MessageLoopParams {
 pub async fn run_connection(socket: WebSocket, auth: AuthOutcome, params: ConnectionParams) {
     let ConnectionParams {
         config,
+        remote,
         heartbeat_interval,
         idle_timeout,
         registry,
@@ -94,6 +134,13 @@ pub async fn run_connection(socket: WebSocket, auth: AuthOutcome, params: Connec
         exec_policy,
         pairing_default_ttl_secs,
         pairing_retention_secs,
+        metrics,
+        subscriptions: subscription_directory,
+        roster,
+        debug_tap,
+        run_freeze,
+        run_slots,
+        shutdown,
     } = params;
     let conn_id = Uuid::new_v4();
     let span = tracing::info_span!("conn", id = %conn_id);
@@ -126,31 +173,38 @@ pub async fn run_connection(socket: WebSocket, auth: AuthOutcome, params: Connec
         }
     };
 
-    let server_range = VersionRange::new(PROTOCOL_VERSION, PROTOCOL_VERSION);
+    let server_range = VersionRange::supported();
     let negotiated = match server_range.negotiate(&hello.protocol) {
         Some(v) => v,
         None => {
-            send_reject(
-                &mut sink,
-                HelloRejectCode::VersionMismatch,
-                &format!(
-                    "no common version: server={}-{} client={}-{}",
-                    server_range.min, server_range.max, hello.protocol.min, hello.protocol.max,
-                ),
-            )
-            .await;
+            send_version_reject(&mut sink, &server_range, &hello.protocol).await;
             return;
         }
     };
+    if negotiated < hello.protocol.max {
+        tracing::info!(
+            client_max = hello.protocol.max,
+            negotiated,
+            "client protocol negotiated down"
+        );
+    }
 
     let identity = auth.identity_string();
-    let authz = context_for_outcome(&auth, &config);
+    let context = ConnectionContext::from_auth(&auth, &config).with_remote(remote);
+    let compression = negotiate_compression(&hello.compression, &SERVER_COMPRESSION);
+    let envelope_heartbeat = hello
+        .capabilities
+        .iter()
+        .any(|capability| capability == HEARTBEAT_CAPABILITY);
 
     let server_hello = HandshakeResponse::Hello(ServerHello {
         protocol_version: negotiated,
         server_id: format!("homie-gateway/{}", env!("CARGO_PKG_VERSION")),
         identity: identity.clone(),
         services: registry.capabilities(),
+        compression,
+        supported_versions: Some(server_range),
+        reconnect: Some(config.reconnect_hint()),
     });
 
     let json = match serde_json::to_string(&server_hello) {
@@ -166,12 +220,24 @@ pub async fn run_connection(socket: WebSocket, auth: AuthOutcome, params: Connec
         identity,
         negotiated_version: negotiated,
     };
-    let tool_channel = infer_tool_channel_from_client_id(&hello.client_id);
+    let context = context.with_channel(infer_tool_channel_from_client_id(&hello.client_id));
+    let member = RosterMember::new(
+        context.principal.clone(),
+        hello.device_id.as_deref(),
+        &hello.client_id,
+    );
+    let rate_limiter = RateLimiter::new(
+        config.max_requests_per_sec,
+        config.request_burst,
+        config.rate_limit_close_after,
+    );
 
     tracing::info!(
         conn_id = %conn.id,
         identity = ?conn.identity,
         version = conn.negotiated_version,
+        compression = ?compression,
+        envelope_heartbeat,
         "handshake complete"
     );
 
@@ -179,9 +245,9 @@ pub async fn run_connection(socket: WebSocket, auth: AuthOutcome, params: Connec
     drop(_enter);
     let loop_params = MessageLoopParams {
         conn_id,
+        context,
         heartbeat_interval,
         idle_timeout,
-        authz,
         store,
         nodes,
         terminal_registry,
@@ -191,7 +257,22 @@ pub async fn run_connection(socket: WebSocket, auth: AuthOutcome, params: Connec
         exec_policy,
         pairing_default_ttl_secs,
         pairing_retention_secs,
-        tool_channel,
+        rate_limiter,
+        metrics,
+        subscription_directory,
+        roster,
+        member,
+        debug_tap,
+        run_freeze,
+        run_slots,
+        compression,
+        envelope_heartbeat,
+        shutdown,
+        shutdown_retry_after: config.shutdown_retry_after,
+        outbound_capacity: config.outbound_capacity,
+        outbound_overflow: config.outbound_overflow,
+        frame_limits: config.frame_limits(),
+        request_timeouts: config.request_timeouts(),
     };
 
     run_message_loop(&mut sink, &mut stream, loop_params).await;
@@ -206,9 +287,9 @@ async fn run_message_loop(
 ) {
     let MessageLoopParams {
         conn_id,
+        context,
         heartbeat_interval,
         idle_timeout,
-        authz,
         store,
         nodes,
         terminal_registry,
@@ -218,35 +299,82 @@ async fn run_message_loop(
         exec_policy,
         pairing_default_ttl_secs,
         pairing_retention_secs,
-        tool_channel,
+        mut rate_limiter,
+        metrics,
+        subscription_directory,
+        roster,
+        member,
+        debug_tap,
+        run_freeze,
+        run_slots,
+        compression,
+        envelope_heartbeat,
+        shutdown,
+        shutdown_retry_after,
+        outbound_capacity,
+        outbound_overflow,
+        frame_limits,
+        request_timeouts,
     } = params;
+    let connection_guard = metrics.connection_opened(conn_id);
     let mut idle_deadline = tokio::time::Instant::now() + idle_timeout;
     let mut heartbeat = tokio::time::interval(heartbeat_interval);
     heartbeat.tick().await; // consume immediate first tick
+    let mut pings =
+        envelope_heartbeat.then(|| EnvelopeHeartbeat::new(heartbeat_interval, idle_timeout));
 
     // Outbound channel: services push PTY output + events here.
-    // Bounded for backpressure — services use try_send to avoid blocking.
-    let (outbound_tx, mut outbound_rx) = mpsc::channel::<OutboundMessage>(256);
+    // Bounded for backpressure — services use try_send to avoid blocking;
+    // events past the capacity are handled by the overflow policy.
+    let lagged_metrics = metrics.clone();
+    let dropped_metrics = metrics.clone();
+    let dropped_tap = debug_tap.clone();
+    let (outbound_tx, mut outbound_rx) =
+        outbound_queue(outbound_capacity, outbound_overflow, move |topic| {
+            dropped_metrics.record_dropped_event(conn_id);
+            dropped_tap.record(conn_id, topic, None, true);
+        });
 
     // Build the router with services.
-    let mut router = MessageRouter::new();
-    router.register(Box::new(TerminalService::new(
-        conn_id,
-        terminal_registry,
-        outbound_tx.clone(),
-        event_tx.clone(),
-    )));
+    let authz = context.auth();
+    let identity = context.principal.clone();
+    let tool_channel = context.channel.clone();
+    let mut router = MessageRouter::new()
+        .with_metrics(metrics)
+        .with_context(context)
+        .with_audit(store.clone())
+        .with_request_timeouts(request_timeouts);
+    router.register(Box::new(
+        TerminalService::new(
+            conn_id,
+            terminal_registry,
+            outbound_tx.clone(),
+            event_tx.clone(),
+        )
+        .with_config(homie_config.terminal.clone()),
+    ));
     let (chat_service, agent_service) = ChatService::new_shared_with_channel(
         outbound_tx.clone(),
         store.clone(),
-        homie_config,
+        homie_config.clone(),
         exec_policy,
         tool_channel,
+        run_freeze.clone(),
     );
+    let chat_service = chat_service
+        .with_list_events(event_tx.clone())
+        .with_run_slots(run_slots)
+        .with_shutdown(shutdown.clone())
+        .with_identity(identity.clone());
     router.register(Box::new(chat_service));
     router.register(Box::new(agent_service));
-    router.register(Box::new(PresenceService::new(nodes)));
-    router.register(Box::new(JobsService::new(store.clone())));
+    router.register(Box::new(
+        PresenceService::new(nodes).with_roster(roster.clone(), identity.clone()),
+    ));
+    router.register(Box::new(JobsService::new(
+        store.clone(),
+        run_freeze.clone(),
+    )));
     router.register(Box::new(PairingService::new(
         store.clone(),
         pairing_default_ttl_secs,
@@ -260,9 +388,27 @@ async fn run_message_loop(
         store.clone(),
         outbound_tx.clone(),
     )));
+    router.register(Box::new(StateService::new(store.clone(), identity.clone())));
+    router.register(Box::new(AdminService::new(
+        run_freeze,
+        store.clone(),
+        event_tx.clone(),
+        homie_config,
+    )));
 
     // Per-connection subscription manager.
-    let mut subscriptions = SubscriptionManager::new();
+    let mut subscriptions = SubscriptionManager::new()
+        .with_identity(identity)
+        .with_directory(subscription_directory, conn_id)
+        .with_debug_tap(
+            debug_tap.clone(),
+            conn_id,
+            outbound_tx.clone(),
+            authz.allows(Scope::Admin),
+        );
+    // Announce this client to the identity's other connections until the
+    // loop exits.
+    let _presence = roster.join(conn_id, member);
 
     let mut event_rx = event_tx.subscribe();
 
@@ -273,40 +419,108 @@ async fn run_message_loop(
                 match msg {
                     Some(Ok(Message::Text(text))) => {
                         idle_deadline = tokio::time::Instant::now() + idle_timeout;
-                        handle_text_message(
+                        if text.len() > frame_limits.max_text_frame_bytes() {
+                            tracing::warn!(len = text.len(), "closing connection after oversized text frame");
+                            let _ = sink
+                                .send(Message::Close(Some(axum::extract::ws::CloseFrame {
+                                    code: 1009,
+                                    reason: "message too big".into(),
+                                })))
+                                .await;
+                            break;
+                        }
+                        if let Err(e) = frame_limits.check_message(text.len()) {
+                            reject_oversized(sink, peek_request_id(&text), &e, compression).await;
+                            continue;
+                        }
+                        let outcome = handle_text_frame(
                             sink,
                             &text,
-                            authz,
                             &mut router,
                             &mut subscriptions,
+                            &mut rate_limiter,
+                            compression,
                         ).await;
+                        match outcome {
+                            FrameOutcome::Close => break,
+                            FrameOutcome::Pong(pong) => {
+                                record_pong(pings.as_mut(), pong, &connection_guard);
+                            }
+                            FrameOutcome::Continue => {}
+                        }
                     }
                     Some(Ok(Message::Binary(data))) => {
                         idle_deadline = tokio::time::Instant::now() + idle_timeout;
-                        if authz.allows(Scope::TerminalWrite) {
-                            let decoded = homie_protocol::BinaryFrame::decode(&data);
-                            match decoded {
-                                Ok(frame) => {
-                                    if terminal_debug_enabled_for(frame.session_id) {
-                                        tracing::info!(
-                                            session = %frame.session_id,
-                                            stream = ?frame.stream,
-                                            msg = %fmt_bytes(&frame.payload, 80),
-                                            "terminal ws in binary"
-                                        );
+                        if let Err(e) = frame_limits.check_binary(data.len()) {
+                            reject_oversized(sink, None, &e, compression).await;
+                            continue;
+                        }
+                        if let Err(e) = check_frame_compression(&data, compression) {
+                            tracing::warn!(err = %e, "closing connection after unnegotiated compressed frame");
+                            let _ = sink
+                                .send(Message::Close(Some(axum::extract::ws::CloseFrame {
+                                    code: 1002,
+                                    reason: "compression not negotiated".into(),
+                                })))
+                                .await;
+                            break;
+                        }
+                        // Compressed envelopes share the binary channel with PTY input.
+                        match decode_envelope_frame_limited(&data, frame_limits.max_message_bytes) {
+                            Ok(Some(text)) => {
+                                let outcome = handle_text_frame(
+                                    sink,
+                                    &text,
+                                    &mut router,
+                                    &mut subscriptions,
+                                    &mut rate_limiter,
+                                    compression,
+                                ).await;
+                                match outcome {
+                                    FrameOutcome::Close => break,
+                                    FrameOutcome::Pong(pong) => {
+                                        record_pong(pings.as_mut(), pong, &connection_guard);
                                     }
-                                    router.route_binary(&frame);
+                                    FrameOutcome::Continue => {}
                                 }
-                                Err(e) => {
-                                    tracing::warn!(
-                                        err = %e,
-                                        msg = %fmt_bytes(&data, 64),
-                                        "invalid binary frame"
+                                continue;
+                            }
+                            Ok(None) => {}
+                            Err(e @ ProtocolError::PayloadTooLarge { .. }) => {
+                                reject_oversized(sink, None, &e, compression).await;
+                                continue;
+                            }
+                            Err(e) => {
+                                tracing::warn!(err = %e, "invalid compressed envelope frame");
+                                continue;
+                            }
+                        }
+                        match homie_protocol::BinaryFrame::decode_limited(
+                            &data,
+                            frame_limits.max_binary_bytes,
+                        ) {
+                            Ok(frame) if authz.allows(binary_frame_scope(&frame)) => {
+                                if terminal_debug_enabled_for(frame.session_id) {
+                                    tracing::info!(
+                                        session = %frame.session_id,
+                                        stream = ?frame.stream,
+                                        msg = %fmt_bytes(&frame.payload, 80),
+                                        "terminal ws in binary"
                                     );
                                 }
+                                router.route_binary(&frame);
+                            }
+                            Ok(frame) => router.refuse_binary(&frame),
+                            Err(e @ ProtocolError::PayloadTooLarge { .. }) => {
+                                reject_oversized(sink, None, &e, compression).await;
+                            }
+                            Err(e) => {
+                                tracing::warn!(
+                                    err = %e,
+                                    msg = %fmt_bytes(&data, 64),
+                                    "invalid binary frame"
+                                );
                             }
-                        } else {
-                            tracing::debug!("unauthorized binary frame ignored");
                         }
                     }
                     Some(Ok(Message::Ping(data))) => {
@@ -337,7 +551,8 @@ async fn run_message_loop(
             // Outbound messages from services (PTY output frames).
             msg = outbound_rx.recv() => {
                 match msg {
-                    Some(OutboundMessage::Raw(m)) => {
+                    Some(Outbound::Message(OutboundMessage::Raw(mut m))) => {
+                        let mut recompressed = None;
                         if let Message::Binary(data) = &m {
                             match homie_protocol::BinaryFrame::decode(data) {
                                 Ok(frame) => {
@@ -349,6 +564,9 @@ async fn run_message_loop(
                                             "terminal ws out binary"
                                         );
                                     }
+                                    if compression.is_some() {
+                                        recompressed = Some(frame.encode_with(compression));
+                                    }
                                 }
                                 Err(e) => {
                                     tracing::warn!(
@@ -359,19 +577,27 @@ async fn run_message_loop(
                                 }
                             }
                         }
+                        if let Some(bytes) = recompressed {
+                            m = Message::Binary(bytes.into());
+                        }
                         let _ = sink.send(m).await;
                     }
-                    Some(OutboundMessage::Event { topic, params }) => {
+                    Some(Outbound::Message(OutboundMessage::Event { topic, params })) => {
                         if subscriptions.matches(&topic) {
-                            let evt = ProtoMessage::Event(homie_protocol::Event {
-                                topic,
-                                params,
-                            });
-                            if let Ok(json) = encode_message(&evt) {
-                                let _ = sink.send(Message::Text(json.into())).await;
-                            }
+                            let event = homie_protocol::Event { topic, params };
+                            send_event(sink, event, compression, &debug_tap, conn_id).await;
                         }
                     }
+                    Some(Outbound::Overflow) => {
+                        tracing::warn!("closing slow connection after outbound overflow");
+                        let _ = sink
+                            .send(Message::Close(Some(axum::extract::ws::CloseFrame {
+                                code: 4009,
+                                reason: "outbound overflow".into(),
+                            })))
+                            .await;
+                        break;
+                    }
                     None => break,
                 }
             }
@@ -380,6 +606,41 @@ async fn run_message_loop(
                 if sink.send(Message::Ping(vec![].into())).await.is_err() {
                     break;
                 }
+                if let Some(pings) = pings.as_mut() {
+                    let Some(ping) = pings.next_ping(std::time::Instant::now()) else {
+                        tracing::info!(missed = pings.missed, "heartbeat timeout");
+                        let _ = sink
+                            .send(Message::Close(Some(axum::extract::ws::CloseFrame {
+                                code: 4000,
+                                reason: "heartbeat timeout".into(),
+                            })))
+                            .await;
+                        break;
+                    };
+                    if let Ok(json) = encode_message(&ProtoMessage::Ping(ping)) {
+                        if sink.send(text_frame(json, compression)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            // Graceful server shutdown: tell the client when to come back.
+            _ = shutdown.wait() => {
+                tracing::info!("closing connection for server shutdown");
+                let close = ProtoMessage::Close(homie_protocol::Close {
+                    reason: "server_shutdown".into(),
+                    retry_after_ms: Some(shutdown_retry_after.as_millis() as u64),
+                });
+                if let Ok(json) = encode_message(&close) {
+                    let _ = sink.send(text_frame(json, compression)).await;
+                }
+                let _ = sink
+                    .send(Message::Close(Some(axum::extract::ws::CloseFrame {
+                        code: 1012,
+                        reason: "server shutdown".into(),
+                    })))
+                    .await;
+                break;
             }
             // Idle timeout.
             _ = tokio::time::sleep_until(idle_deadline) => {
@@ -397,18 +658,17 @@ async fn run_message_loop(
                 match evt {
                     Ok(reap_event) => {
                         tracing::info!(topic = %reap_event.topic, "broadcast event");
-                        if subscriptions.matches(&reap_event.topic) {
-                            let evt = ProtoMessage::Event(homie_protocol::Event {
+                        if subscriptions.delivers(&reap_event) {
+                            let event = homie_protocol::Event {
                                 topic: reap_event.topic,
                                 params: reap_event.params,
-                            });
-                            if let Ok(json) = encode_message(&evt) {
-                                let _ = sink.send(Message::Text(json.into())).await;
-                            }
+                            };
+                            send_event(sink, event, compression, &debug_tap, conn_id).await;
                         }
                     }
-                    Err(broadcast::error::RecvError::Lagged(_)) => {
-                        tracing::warn!("event receiver lagged");
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "event receiver lagged");
+                        lagged_metrics.record_dropped_events(conn_id, skipped);
                     }
                     Err(broadcast::error::RecvError::Closed) => {
                         break;
@@ -435,21 +695,129 @@ fn infer_tool_channel_from_client_id(client_id: &str) -> Option<String> {
     }
 }
 
+/// What the message loop does after one inbound envelope.
+enum FrameOutcome {
+    Continue,
+    /// The client answered an envelope heartbeat.
+    Pong(Pong),
+    /// Sustained rate limiting; the close frame was already sent.
+    Close,
+}
+
+/// Envelope-level heartbeat state for clients that advertised the
+/// `heartbeat` capability: one `ping` per heartbeat tick, closing the
+/// connection once pongs stop for about `idle_timeout`.
+struct EnvelopeHeartbeat {
+    max_missed: u32,
+    missed: u32,
+    next_seq: u64,
+    /// Seq and send time of the ping awaiting a pong.
+    outstanding: Option<(u64, std::time::Instant)>,
+}
+
+impl EnvelopeHeartbeat {
+    fn new(heartbeat_interval: Duration, idle_timeout: Duration) -> Self {
+        let interval = heartbeat_interval.as_millis().max(1);
+        let max_missed = idle_timeout.as_millis().div_ceil(interval).max(1);
+        Self {
+            max_missed: u32::try_from(max_missed).unwrap_or(u32::MAX),
+            missed: 0,
+            next_seq: 0,
+            outstanding: None,
+        }
+    }
+
+    /// The next ping to send, or `None` once `max_missed` consecutive pings
+    /// went unanswered.
+    fn next_ping(&mut self, now: std::time::Instant) -> Option<Ping> {
+        if self.outstanding.is_some() {
+            self.missed += 1;
+            if self.missed >= self.max_missed {
+                return None;
+            }
+        }
+        self.next_seq += 1;
+        self.outstanding = Some((self.next_seq, now));
+        Some(Ping { seq: self.next_seq })
+    }
+
+    /// Any pong proves the client is alive; only the reply to the latest
+    /// ping yields a round-trip time.
+    fn pong(&mut self, seq: u64, now: std::time::Instant) -> Option<Duration> {
+        self.missed = 0;
+        match self.outstanding {
+            Some((outstanding, sent_at)) if outstanding == seq => {
+                self.outstanding = None;
+                Some(now.saturating_duration_since(sent_at))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Upload chunks feed `chat.file.upload.*`; every other frame is PTY input.
+fn binary_frame_scope(frame: &homie_protocol::BinaryFrame) -> Scope {
+    match frame.stream {
+        StreamType::Upload => Scope::AgentWrite,
+        StreamType::Stdout | StreamType::Stderr | StreamType::Stdin => Scope::TerminalWrite,
+    }
+}
+
+fn record_pong(pings: Option<&mut EnvelopeHeartbeat>, pong: Pong, guard: &ConnectionGuard) {
+    let Some(pings) = pings else {
+        tracing::debug!(seq = pong.seq, "unsolicited pong from client (ignored)");
+        return;
+    };
+    if let Some(rtt) = pings.pong(pong.seq, std::time::Instant::now()) {
+        guard.record_rtt(rtt);
+    }
+}
+
+/// Handle one inbound envelope, reporting heartbeat pongs back to the loop
+/// and whether sustained rate limiting means the connection should close.
+async fn handle_text_frame(
+    sink: &mut SplitSink<WebSocket, Message>,
+    text: &str,
+    router: &mut MessageRouter,
+    subscriptions: &mut SubscriptionManager,
+    rate_limiter: &mut RateLimiter,
+    compression: Option<Compression>,
+) -> FrameOutcome {
+    let pong =
+        handle_text_message(sink, text, router, subscriptions, rate_limiter, compression).await;
+    if rate_limiter.should_disconnect() {
+        tracing::warn!("closing connection after sustained rate limiting");
+        let _ = sink
+            .send(Message::Close(Some(axum::extract::ws::CloseFrame {
+                code: 4008,
+                reason: "rate limited".into(),
+            })))
+            .await;
+        return FrameOutcome::Close;
+    }
+    match pong {
+        Some(pong) => FrameOutcome::Pong(pong),
+        None => FrameOutcome::Continue,
+    }
+}
+
 async fn handle_text_message(
     sink: &mut SplitSink<WebSocket, Message>,
     text: &str,
-    authz: AuthContext,
     router: &mut MessageRouter,
     subscriptions: &mut SubscriptionManager,
-) {
+    rate_limiter: &mut RateLimiter,
+    compression: Option<Compression>,
+) -> Option<Pong> {
     match decode_message(text) {
         Ok(ProtoMessage::Request(req)) => {
             tracing::debug!(method = %req.method, id = %req.id, "request");
             route_and_respond(
                 sink,
-                authz,
                 router,
                 subscriptions,
+                rate_limiter,
+                compression,
                 RouteRequest {
                     req_id: req.id,
                     method: req.method,
@@ -459,6 +827,7 @@ async fn handle_text_message(
             )
             .await;
         }
+        Ok(ProtoMessage::Pong(pong)) => return Some(pong),
         Ok(other) => {
             tracing::debug!(?other, "non-request message from client (ignored)");
         }
@@ -472,9 +841,10 @@ async fn handle_text_message(
                 );
                 route_and_respond(
                     sink,
-                    authz,
                     router,
                     subscriptions,
+                    rate_limiter,
+                    compression,
                     RouteRequest {
                         req_id: legacy.req_id,
                         method: legacy.method,
@@ -492,6 +862,7 @@ async fn handle_text_message(
             }
         },
     }
+    None
 }
 
 struct RouteRequest {
@@ -503,9 +874,10 @@ struct RouteRequest {
 
 async fn route_and_respond(
     sink: &mut SplitSink<WebSocket, Message>,
-    authz: AuthContext,
     router: &mut MessageRouter,
     subscriptions: &mut SubscriptionManager,
+    rate_limiter: &mut RateLimiter,
+    compression: Option<Compression>,
     req: RouteRequest,
 ) {
     let RouteRequest {
@@ -515,12 +887,16 @@ async fn route_and_respond(
         response_id_override,
     } = req;
 
-    if let Some(scope) = scope_for_method(&method) {
-        if !authz.allows(scope) {
-            let resp = Response::error(req_id, error_codes::UNAUTHORIZED, "unauthorized");
-            send_response(sink, resp, response_id_override).await;
-            return;
-        }
+    if !rate_limiter.try_acquire() {
+        tracing::debug!(%method, "request rate limited");
+        let resp = Response::error(req_id, error_codes::RATE_LIMITED, "rate limited");
+        send_response(sink, resp, response_id_override, compression).await;
+        return;
+    }
+
+    if let Err(resp) = router.authorize(req_id, &method) {
+        send_response(sink, resp, response_id_override, compression).await;
+        return;
     }
 
     // Handle built-in subscription methods.
@@ -535,22 +911,60 @@ async fn route_and_respond(
             let params = chat_subscribe_params(params);
             handle_subscribe(req_id, params, subscriptions)
         }
+        "chat.list.subscribe" => {
+            let sub_id = subscriptions.subscribe("chat.list.updated");
+            tracing::debug!(%sub_id, "subscribed to chat list updates");
+            // Subscribe before snapshotting so no delta falls in between.
+            let snapshot = router.route_request(req_id, "chat.list", None).await;
+            match snapshot.result {
+                Some(result) => Response::success(
+                    req_id,
+                    json!({ "subscription_id": sub_id, "chats": result["chats"] }),
+                ),
+                None => {
+                    subscriptions.unsubscribe(sub_id);
+                    snapshot
+                }
+            }
+        }
+        "system.metrics" => Response::success(req_id, router.metrics().snapshot()),
+        "system.subscriptions" => Response::success(req_id, subscriptions.report()),
+        "system.audit" => handle_audit(req_id, params.as_ref(), router.audit_log().cloned()).await,
         _ => router.route_request(req_id, &method, params).await,
     };
 
-    send_response(sink, resp, response_id_override).await;
+    send_response(sink, resp, response_id_override, compression).await;
+}
+
+/// Answer a frame that broke the connection's size limits without parsing
+/// it. The error carries the request id when one could be read from the
+/// start of the frame, and the nil id otherwise.
+async fn reject_oversized(
+    sink: &mut SplitSink<WebSocket, Message>,
+    req_id: Option<Uuid>,
+    err: &ProtocolError,
+    compression: Option<Compression>,
+) {
+    tracing::warn!(err = %err, "oversized frame rejected");
+    let resp = Response::error(
+        req_id.unwrap_or(Uuid::nil()),
+        error_codes::PAYLOAD_TOO_LARGE,
+        err.to_string(),
+    );
+    send_response(sink, resp, None, compression).await;
 }
 
 async fn send_response(
     sink: &mut SplitSink<WebSocket, Message>,
     resp: Response,
     response_id_override: Option<Value>,
+    compression: Option<Compression>,
 ) {
     match response_id_override {
         None => {
             let msg = ProtoMessage::Response(resp);
             if let Ok(json) = encode_message(&msg) {
-                let _ = sink.send(Message::Text(json.into())).await;
+                let _ = sink.send(text_frame(json, compression)).await;
             }
         }
         Some(override_id) => {
@@ -569,12 +983,40 @@ async fn send_response(
                 }
             }
             if let Ok(text) = serde_json::to_string(&payload) {
-                let _ = sink.send(Message::Text(text.into())).await;
+                let _ = sink.send(text_frame(text, compression)).await;
             }
         }
     }
 }
 
+/// Write an event the connection subscribed to, mirroring it to
+/// `debug.events` watchers when there are any.
+async fn send_event(
+    sink: &mut SplitSink<WebSocket, Message>,
+    event: homie_protocol::Event,
+    compression: Option<Compression>,
+    debug_tap: &DebugEventTap,
+    conn_id: Uuid,
+) {
+    let traced_topic = debug_tap.is_watched().then(|| event.topic.clone());
+    let Ok(json) = encode_message(&ProtoMessage::Event(event)) else {
+        return;
+    };
+    if let Some(topic) = traced_topic {
+        debug_tap.record(conn_id, &topic, Some(json.len()), false);
+    }
+    let _ = sink.send(text_frame(json, compression)).await;
+}
+
+/// Wrap an outbound envelope, compressing it into a binary frame when the
+/// connection negotiated compression and the payload is large enough.
+fn text_frame(json: String, compression: Option<Compression>) -> Message {
+    match encode_envelope_frame(&json, compression) {
+        Some(frame) => Message::Binary(frame.into()),
+        None => Message::Text(json.into()),
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct LooseRequest {
     #[serde(rename = "type", default)]
@@ -647,6 +1089,11 @@ fn handle_subscribe(
 
     match topic {
         Some(pattern) => {
+            if pattern == DEBUG_EVENTS_TOPIC {
+                if let Err((code, message)) = subs.watch_debug_events() {
+                    return Response::error(req_id, code, message);
+                }
+            }
             let sub_id = subs.subscribe(pattern);
             tracing::debug!(%sub_id, pattern, "subscribed");
             Response::success(req_id, json!({ "subscription_id": sub_id }))
@@ -695,6 +1142,39 @@ fn handle_unsubscribe(
     }
 }
 
+/// Handle `system.audit` — list recent audit log entries, newest first.
+///
+/// Params: `{ "method"?: "chat.message.send", "identity"?: "...", "limit"?: 100 }`
+/// Returns: `{ "entries": [{timestamp, identity, role, method, target_id, outcome}] }`
+async fn handle_audit(
+    req_id: Uuid,
+    params: Option<&serde_json::Value>,
+    audit: Option<AuditLog>,
+) -> Response {
+    let Some(audit) = audit else {
+        return Response::success(req_id, json!({ "entries": [] }));
+    };
+    // This connection's own queued entries are listed too.
+    audit.flush().await;
+    let store = audit.store();
+    let filter = |key: &str| {
+        params
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    let limit = params
+        .and_then(|p| p.get("limit"))
+        .and_then(|v| v.as_u64())
+        .map(|v| (v as usize).clamp(1, AUDIT_MAX_LIMIT))
+        .unwrap_or(AUDIT_DEFAULT_LIMIT);
+    match store.list_audit_entries(filter("method"), filter("identity"), limit) {
+        Ok(entries) => Response::success(req_id, json!({ "entries": entries })),
+        Err(e) => Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+    }
+}
+
 fn agent_subscribe_params(params: Option<serde_json::Value>) -> Option<serde_json::Value> {
     let mut params = params.unwrap_or_else(|| json!({}));
     match params.as_object_mut() {
@@ -719,15 +1199,51 @@ fn chat_subscribe_params(params: Option<serde_json::Value>) -> Option<serde_json
     }
 }
 
+async fn send_version_reject(
+    sink: &mut SplitSink<WebSocket, Message>,
+    server_range: &VersionRange,
+    client_range: &VersionRange,
+) {
+    let supported = server_range
+        .versions()
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let reason = format!(
+        "no common protocol version: client supports {}-{}, server supports [{supported}]",
+        client_range.min, client_range.max,
+    );
+    send_reject_with(
+        sink,
+        HelloReject {
+            code: HelloRejectCode::VersionMismatch,
+            reason,
+            supported_versions: Some(server_range.clone()),
+        },
+    )
+    .await;
+}
+
 async fn send_reject(
     sink: &mut SplitSink<WebSocket, Message>,
     code: HelloRejectCode,
     reason: &str,
 ) {
-    let reject = HandshakeResponse::Reject(HelloReject {
-        code,
-        reason: reason.into(),
-    });
+    send_reject_with(
+        sink,
+        HelloReject {
+            code,
+            reason: reason.into(),
+            supported_versions: None,
+        },
+    )
+    .await;
+}
+
+async fn send_reject_with(sink: &mut SplitSink<WebSocket, Message>, reject: HelloReject) {
+    let reason = reject.reason.clone();
+    let reject = HandshakeResponse::Reject(reject);
     if let Ok(json) = serde_json::to_string(&reject) {
         let _ = sink.send(Message::Text(json.into())).await;
     }
@@ -741,10 +1257,32 @@ async fn send_reject(
 
 #[cfg(test)]
 mod tests {
-    use super::{decode_legacy_request, LegacyDecode};
+    use std::time::{Duration, Instant};
+
+    use super::{decode_legacy_request, EnvelopeHeartbeat, LegacyDecode};
     use serde_json::json;
     use uuid::Uuid;
 
+    #[test]
+    fn envelope_heartbeat_measures_rtt_and_expires_after_missed_pongs() {
+        let mut pings = EnvelopeHeartbeat::new(Duration::from_secs(15), Duration::from_secs(40));
+        assert_eq!(pings.max_missed, 3);
+
+        let start = Instant::now();
+        let first = pings.next_ping(start).expect("first ping");
+        let rtt = pings.pong(first.seq, start + Duration::from_millis(30));
+        assert_eq!(rtt, Some(Duration::from_millis(30)));
+
+        // Two unanswered pings are tolerated; the third miss closes.
+        let second = pings.next_ping(start).expect("second ping");
+        assert!(pings.next_ping(start).is_some());
+        // A late pong for an older ping still counts as liveness.
+        assert_eq!(pings.pong(second.seq, start), None);
+        assert!(pings.next_ping(start).is_some());
+        assert!(pings.next_ping(start).is_some());
+        assert!(pings.next_ping(start).is_none());
+    }
+
     #[test]
     fn decode_legacy_request_accepts_non_uuid_string_id() {
         let payload = json!({
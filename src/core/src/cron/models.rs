@@ -9,6 +9,8 @@ pub struct CronAddParams {
     pub command: String,
     pub status: Option<CronStatus>,
     pub skip_overlap: Option<bool>,
+    pub max_retries: Option<u32>,
+    pub backoff_secs: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -19,6 +21,8 @@ pub struct CronUpdateParams {
     pub command: Option<String>,
     pub status: Option<CronStatus>,
     pub skip_overlap: Option<bool>,
+    pub max_retries: Option<u32>,
+    pub backoff_secs: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1,5 +1,6 @@
+use std::collections::HashMap;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use chrono::{DateTime, TimeZone, Utc};
@@ -11,12 +12,21 @@ use tokio::task::JoinHandle;
 use tracing::warn;
 use uuid::Uuid;
 
+use crate::homie_config::ChildEnvConfig;
 use crate::router::ReapEvent;
 use crate::storage::{CronRecord, CronRunRecord, CronRunStatus, CronStatus, Store};
 
 const SCHEDULER_TICK_SECS: u64 = 1;
 const MAX_MISSED_RUNS: usize = 32;
 const MAX_OUTPUT_BYTES: usize = 16_384;
+/// Default base delay between retries when a cron enables retries without one.
+pub(crate) const DEFAULT_RETRY_BACKOFF_SECS: u64 = 30;
+/// Upper bound for the exponential retry backoff.
+const MAX_RETRY_BACKOFF_SECS: u64 = 60 * 60;
+/// How long a claimed cron stays leased to this worker without renewal.
+/// Leases are renewed while runs are in flight, so only a dead worker's
+/// lease runs out.
+const CRON_LEASE_SECS: u64 = 60;
 
 #[derive(Clone)]
 pub struct CronRunner {
@@ -24,6 +34,11 @@ pub struct CronRunner {
     event_tx: broadcast::Sender<ReapEvent>,
     max_concurrency: usize,
     run_slots: Arc<Semaphore>,
+    /// Identifies this scheduler's cron leases in a shared database.
+    worker_id: String,
+    /// In-flight runs per cron leased by this worker.
+    leases: Arc<Mutex<HashMap<String, usize>>>,
+    child_env: ChildEnvConfig,
 }
 
 impl CronRunner {
@@ -37,9 +52,18 @@ impl CronRunner {
             event_tx,
             max_concurrency: max_concurrent_runs,
             run_slots: Arc::new(Semaphore::new(max_concurrent_runs)),
+            worker_id: Uuid::new_v4().to_string(),
+            leases: Arc::new(Mutex::new(HashMap::new())),
+            child_env: ChildEnvConfig::default(),
         }
     }
 
+    /// Keep the server variables `child_env` denies out of cron commands.
+    pub fn with_child_env(mut self, child_env: ChildEnvConfig) -> Self {
+        self.child_env = child_env;
+        self
+    }
+
     pub fn spawn(
         self: Arc<Self>,
         prune_retention_days: u64,
@@ -48,8 +72,10 @@ impl CronRunner {
         tokio::spawn(async move {
             let mut tick = tokio::time::interval(Duration::from_secs(SCHEDULER_TICK_SECS));
             let mut prune_tick = tokio::time::interval(Duration::from_secs(60 * 60));
+            let mut lease_tick = tokio::time::interval(Duration::from_secs(CRON_LEASE_SECS / 3));
             tick.tick().await;
             prune_tick.tick().await;
+            lease_tick.tick().await;
             loop {
                 tokio::select! {
                     _ = tick.tick() => {
@@ -57,6 +83,7 @@ impl CronRunner {
                             warn!(error = %err, "cron scheduler tick failed");
                         }
                     }
+                    _ = lease_tick.tick() => self.renew_leases(),
                     _ = prune_tick.tick() => {
                         if let Err(err) = self.store.prune_cron_runs(prune_retention_days, prune_max_runs) {
                             warn!(error = %err, "cron run prune failed");
@@ -69,29 +96,97 @@ impl CronRunner {
 
     async fn tick_once(self: &Arc<Self>) -> Result<(), String> {
         let now = now_unix();
-        let mut crons = self.store.list_crons()?;
-        for mut cron in crons.drain(..) {
-            if cron.status != CronStatus::Active {
-                continue;
+        let crons = self
+            .store
+            .claim_due_crons(now, &self.worker_id, CRON_LEASE_SECS)?;
+        for cron in crons {
+            let cron_id = cron.cron_id.clone();
+            let result = self.process_claimed(cron, now).await;
+            self.release_if_idle(&cron_id);
+            if let Err(err) = result {
+                warn!(error = %err, cron_id = %cron_id, "cron tick failed");
             }
+        }
+        Ok(())
+    }
 
-            let Some(next_run_at) = cron.next_run_at else {
-                if let Ok(next_run_at) = schedule_next_after(&cron.schedule, now) {
-                    cron.next_run_at = Some(next_run_at);
-                    cron.updated_at = now;
-                    self.store.upsert_cron(&cron)?;
-                }
-                continue;
-            };
+    async fn process_claimed(
+        self: &Arc<Self>,
+        mut cron: CronRecord,
+        now: u64,
+    ) -> Result<(), String> {
+        if let Some(retry_at) = cron.next_retry_at.filter(|at| *at <= now) {
+            cron.next_retry_at = None;
+            self.store
+                .update_cron_retry(&cron.cron_id, cron.retry_count, None)?;
+            if let Err(err) = self.start_run(&cron, retry_at).await {
+                warn!(error = %err, cron_id = %cron.cron_id, "cron retry failed to start");
+            }
+        }
 
-            let due = due_runs(&cron.schedule, next_run_at, now, MAX_MISSED_RUNS)?;
-            if due.is_empty() {
-                continue;
+        let Some(next_run_at) = cron.next_run_at else {
+            if let Ok(next_run_at) = schedule_next_after(&cron.schedule, now) {
+                cron.next_run_at = Some(next_run_at);
+                cron.updated_at = now;
+                self.store.upsert_cron(&cron)?;
             }
+            return Ok(());
+        };
 
-            process_due_runs(self.clone(), cron, now, due).await?;
+        let due = due_runs(&cron.schedule, next_run_at, now, MAX_MISSED_RUNS)?;
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        process_due_runs(self.clone(), cron, now, due).await
+    }
+
+    /// Keep the lease on every cron with a run in flight.
+    fn renew_leases(&self) {
+        let cron_ids: Vec<String> = self.leases.lock().unwrap().keys().cloned().collect();
+        let expires_at = now_unix() + CRON_LEASE_SECS;
+        for cron_id in cron_ids {
+            match self
+                .store
+                .renew_cron_lease(&cron_id, &self.worker_id, expires_at)
+            {
+                Ok(true) => {}
+                Ok(false) => warn!(cron_id = %cron_id, "cron lease lost to another worker"),
+                Err(err) => warn!(error = %err, cron_id = %cron_id, "cron lease renewal failed"),
+            }
+        }
+    }
+
+    fn hold_lease(&self, cron_id: &str) {
+        *self
+            .leases
+            .lock()
+            .unwrap()
+            .entry(cron_id.to_string())
+            .or_default() += 1;
+    }
+
+    fn drop_lease(&self, cron_id: &str) {
+        {
+            let mut leases = self.leases.lock().unwrap();
+            if let Some(count) = leases.get_mut(cron_id) {
+                *count -= 1;
+                if *count == 0 {
+                    leases.remove(cron_id);
+                }
+            }
+        }
+        self.release_if_idle(cron_id);
+    }
+
+    /// Release the lease on `cron_id` unless one of its runs is in flight.
+    fn release_if_idle(&self, cron_id: &str) {
+        if self.leases.lock().unwrap().contains_key(cron_id) {
+            return;
+        }
+        if let Err(err) = self.store.release_cron_lease(cron_id, &self.worker_id) {
+            warn!(error = %err, cron_id = %cron_id, "cron lease release failed");
         }
-        Ok(())
     }
 
     pub async fn run_cron_now(self: &Arc<Self>, cron_id: &str) -> Result<CronRunRecord, String> {
@@ -175,6 +270,18 @@ impl CronRunner {
         );
     }
 
+    fn emit_retry_scheduled(&self, run: &CronRunRecord, retry_count: u32, next_retry_at: u64) {
+        self.emit(
+            "cron.run.retry_scheduled",
+            json!({
+                "cron_id": run.cron_id,
+                "run_id": run.run_id,
+                "retry_count": retry_count,
+                "next_retry_at": next_retry_at,
+            }),
+        );
+    }
+
     fn record_skipped_run(
         &self,
         cron: &CronRecord,
@@ -222,17 +329,19 @@ impl CronRunner {
         };
 
         self.store.upsert_cron_run(&run)?;
+        self.hold_lease(&run.cron_id);
         self.emit_run_started(&run);
 
         let mut run_for_task = run.clone();
         let store = self.store.clone();
         let command = cron.command.clone();
+        let scrubbed_vars = self.child_env.scrubbed_vars();
         let cron_id = run.cron_id.clone();
         let run_id = run.run_id.clone();
         let runner_for_task = self.clone();
 
         tokio::spawn(async move {
-            let output = execute_command(&command).await;
+            let output = execute_command(&command, &scrubbed_vars).await;
             let ended_at = now_unix();
             match output {
                 Ok((status, code, result, error)) => {
@@ -250,6 +359,22 @@ impl CronRunner {
                 }
             }
 
+            let retry = match store.get_cron(&cron_id) {
+                Ok(Some(cron)) => Some(next_retry_state(&cron, run_for_task.status, ended_at)),
+                Ok(None) => None,
+                Err(err) => {
+                    warn!(error = %err, cron_id = %cron_id, "failed to load cron retry state");
+                    None
+                }
+            };
+            if let Some(RetryState::Exhausted { attempts }) = retry {
+                let reason = format!("giving up after {attempts} retries");
+                run_for_task.error = Some(match run_for_task.error.take() {
+                    Some(err) => format!("{err}\n{reason}"),
+                    None => reason,
+                });
+            }
+
             if let Err(err) = store.upsert_cron_run(&run_for_task) {
                 warn!(error = %err, run_id = %run_id, cron_id = %cron_id, "failed to persist cron run result");
             }
@@ -258,7 +383,22 @@ impl CronRunner {
                 warn!(error = %err, cron_id = %cron_id, "failed to persist cron last_run_at");
             }
 
+            if let Some(retry) = retry {
+                let (retry_count, next_retry_at) = retry.persisted();
+                if let Err(err) = store.update_cron_retry(&cron_id, retry_count, next_retry_at) {
+                    warn!(error = %err, cron_id = %cron_id, "failed to persist cron retry state");
+                }
+                if let RetryState::Scheduled {
+                    retry_count,
+                    next_retry_at,
+                } = retry
+                {
+                    runner_for_task.emit_retry_scheduled(&run_for_task, retry_count, next_retry_at);
+                }
+            }
+
             runner_for_task.emit_run_completed(&run_for_task);
+            runner_for_task.drop_lease(&cron_id);
 
             drop(permit);
         });
@@ -396,6 +536,55 @@ fn touch_cron_last_run(
     store.upsert_cron(&cron)
 }
 
+/// Retry bookkeeping decided after a run finishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RetryState {
+    /// Run succeeded (or retries are disabled); clear any retry state.
+    Reset,
+    /// Another attempt is due at `next_retry_at`.
+    Scheduled {
+        retry_count: u32,
+        next_retry_at: u64,
+    },
+    /// All retries failed; the counter resets for the next scheduled run.
+    Exhausted { attempts: u32 },
+}
+
+impl RetryState {
+    fn persisted(self) -> (u32, Option<u64>) {
+        match self {
+            Self::Reset | Self::Exhausted { .. } => (0, None),
+            Self::Scheduled {
+                retry_count,
+                next_retry_at,
+            } => (retry_count, Some(next_retry_at)),
+        }
+    }
+}
+
+fn next_retry_state(cron: &CronRecord, status: CronRunStatus, now: u64) -> RetryState {
+    if status != CronRunStatus::Failed || cron.max_retries == 0 {
+        return RetryState::Reset;
+    }
+    if cron.retry_count >= cron.max_retries {
+        return RetryState::Exhausted {
+            attempts: cron.retry_count,
+        };
+    }
+    RetryState::Scheduled {
+        retry_count: cron.retry_count + 1,
+        next_retry_at: now.saturating_add(retry_backoff(cron.backoff_secs, cron.retry_count)),
+    }
+}
+
+/// Delay before retry number `attempt + 1`: `base * 2^attempt`, capped.
+fn retry_backoff(base_secs: u64, attempt: u32) -> u64 {
+    base_secs
+        .max(1)
+        .saturating_mul(1u64.checked_shl(attempt).unwrap_or(u64::MAX))
+        .min(MAX_RETRY_BACKOFF_SECS)
+}
+
 fn build_run_record(
     cron: &CronRecord,
     scheduled_at: u64,
@@ -428,6 +617,7 @@ fn unix_to_datetime(unix_secs: u64) -> Result<DateTime<Utc>, String> {
 
 async fn execute_command(
     command: &str,
+    scrubbed_vars: &[String],
 ) -> Result<(CronRunStatus, Option<i64>, Option<String>, Option<String>), String> {
     #[cfg(unix)]
     let mut cmd = {
@@ -443,6 +633,10 @@ async fn execute_command(
         c
     };
 
+    for name in scrubbed_vars {
+        cmd.env_remove(name);
+    }
+
     let command_output = cmd
         .output()
         .await
@@ -541,6 +735,10 @@ mod tests {
                 updated_at: now(),
                 last_run_at: None,
                 next_run_at: Some(now()),
+                max_retries: 0,
+                backoff_secs: 0,
+                retry_count: 0,
+                next_retry_at: None,
             })
             .unwrap();
 
@@ -583,6 +781,10 @@ mod tests {
                 updated_at: now,
                 last_run_at: None,
                 next_run_at: Some(now),
+                max_retries: 0,
+                backoff_secs: 0,
+                retry_count: 0,
+                next_retry_at: None,
             })
             .unwrap();
 
@@ -590,6 +792,20 @@ mod tests {
         assert_eq!(run.status, CronRunStatus::Skipped);
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cron_commands_do_not_inherit_scrubbed_vars() {
+        // Cargo sets this for the test process, so the child inherits it
+        // unless it is scrubbed.
+        let command = "printf %s \"${CARGO_PKG_NAME-unset}\"";
+        let (_, _, output, _) = execute_command(command, &[]).await.unwrap();
+        assert_eq!(output.as_deref(), Some(env!("CARGO_PKG_NAME")));
+        let (_, _, output, _) = execute_command(command, &["CARGO_PKG_NAME".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(output.as_deref(), Some("unset"));
+    }
+
     fn make_runner() -> Arc<CronRunner> {
         let store = Arc::new(SqliteStore::open_memory().unwrap());
         let (tx, _rx) = tokio::sync::broadcast::channel(16);
@@ -610,6 +826,10 @@ mod tests {
             updated_at: now(),
             next_run_at: Some(now() - 5),
             last_run_at: None,
+            max_retries: 0,
+            backoff_secs: 0,
+            retry_count: 0,
+            next_retry_at: None,
         };
         runner.store.upsert_cron(&cron).unwrap();
         runner
@@ -661,6 +881,10 @@ mod tests {
             updated_at: now(),
             next_run_at: Some(now() - 5),
             last_run_at: None,
+            max_retries: 0,
+            backoff_secs: 0,
+            retry_count: 0,
+            next_retry_at: None,
         };
         runner.store.upsert_cron(&cron).unwrap();
 
@@ -675,4 +899,164 @@ mod tests {
         let latest = runner.store.get_cron(&cron.cron_id).unwrap().unwrap();
         assert_eq!(latest.last_run_at, Some(*due.last().unwrap()));
     }
+
+    #[test]
+    fn retry_backoff_doubles_and_caps() {
+        assert_eq!(retry_backoff(10, 0), 10);
+        assert_eq!(retry_backoff(10, 1), 20);
+        assert_eq!(retry_backoff(10, 3), 80);
+        assert_eq!(retry_backoff(10, 40), MAX_RETRY_BACKOFF_SECS);
+        assert_eq!(retry_backoff(10, 200), MAX_RETRY_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn next_retry_state_schedules_until_exhausted_and_resets_on_success() {
+        let mut cron = CronRecord {
+            cron_id: "cron-retry".into(),
+            name: "retry".into(),
+            schedule: "* * * * * *".into(),
+            command: "false".into(),
+            status: CronStatus::Active,
+            skip_overlap: true,
+            created_at: now(),
+            updated_at: now(),
+            next_run_at: None,
+            last_run_at: None,
+            max_retries: 2,
+            backoff_secs: 5,
+            retry_count: 0,
+            next_retry_at: None,
+        };
+
+        assert_eq!(
+            next_retry_state(&cron, CronRunStatus::Failed, now()),
+            RetryState::Scheduled {
+                retry_count: 1,
+                next_retry_at: now() + 5,
+            }
+        );
+
+        cron.retry_count = 1;
+        assert_eq!(
+            next_retry_state(&cron, CronRunStatus::Failed, now()),
+            RetryState::Scheduled {
+                retry_count: 2,
+                next_retry_at: now() + 10,
+            }
+        );
+
+        cron.retry_count = 2;
+        let exhausted = next_retry_state(&cron, CronRunStatus::Failed, now());
+        assert_eq!(exhausted, RetryState::Exhausted { attempts: 2 });
+        assert_eq!(exhausted.persisted(), (0, None));
+
+        assert_eq!(
+            next_retry_state(&cron, CronRunStatus::Succeeded, now()),
+            RetryState::Reset
+        );
+
+        cron.max_retries = 0;
+        cron.retry_count = 0;
+        assert_eq!(
+            next_retry_state(&cron, CronRunStatus::Failed, now()),
+            RetryState::Reset
+        );
+    }
+
+    #[tokio::test]
+    async fn tick_holds_the_cron_lease_until_its_run_finishes() {
+        let runner = make_runner();
+        let now = now_unix();
+        runner
+            .store
+            .upsert_cron(&CronRecord {
+                cron_id: "cron-leased".into(),
+                name: "leased".into(),
+                schedule: "* * * * * *".into(),
+                command: "echo leased".into(),
+                status: CronStatus::Active,
+                skip_overlap: true,
+                created_at: now,
+                updated_at: now,
+                last_run_at: None,
+                next_run_at: Some(now),
+                max_retries: 0,
+                backoff_secs: 0,
+                retry_count: 0,
+                next_retry_at: None,
+            })
+            .unwrap();
+
+        runner.tick_once().await.unwrap();
+        assert!(runner
+            .store
+            .renew_cron_lease("cron-leased", &runner.worker_id, now + CRON_LEASE_SECS)
+            .unwrap());
+
+        let mut released = false;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            released = !runner
+                .store
+                .renew_cron_lease("cron-leased", &runner.worker_id, now + CRON_LEASE_SECS)
+                .unwrap();
+            if released {
+                break;
+            }
+        }
+        assert!(released);
+        let runs = runner.store.list_cron_runs("cron-leased", 10).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].status, CronRunStatus::Succeeded);
+    }
+
+    #[tokio::test]
+    async fn tick_keeps_going_past_a_cron_that_fails() {
+        let runner = make_runner();
+        let now = now_unix();
+        let cron = |cron_id: &str, schedule: &str, next_run_at: u64| CronRecord {
+            cron_id: cron_id.into(),
+            name: cron_id.into(),
+            schedule: schedule.into(),
+            command: "echo ok".into(),
+            status: CronStatus::Active,
+            skip_overlap: true,
+            created_at: now,
+            updated_at: now,
+            last_run_at: None,
+            next_run_at: Some(next_run_at),
+            max_retries: 0,
+            backoff_secs: 0,
+            retry_count: 0,
+            next_retry_at: None,
+        };
+        runner
+            .store
+            // Claimed newest first, so this one is processed first.
+            .upsert_cron(&CronRecord {
+                created_at: now + 1,
+                ..cron("cron-broken", "not a schedule", now)
+            })
+            .unwrap();
+        runner
+            .store
+            .upsert_cron(&cron("cron-fine", "* * * * * *", now))
+            .unwrap();
+
+        runner.tick_once().await.unwrap();
+        let mut runs = Vec::new();
+        for _ in 0..100 {
+            runs = runner.store.list_cron_runs("cron-fine", 10).unwrap();
+            if !runs.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(runs.len(), 1);
+        assert!(runner
+            .store
+            .list_cron_runs("cron-broken", 10)
+            .unwrap()
+            .is_empty());
+    }
 }
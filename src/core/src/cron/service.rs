@@ -5,7 +5,8 @@ use serde_json::{json, Value};
 
 use homie_protocol::{error_codes, BinaryFrame, Response};
 
-use crate::cron::scheduler::schedule_next_after;
+use crate::authz::Scope;
+use crate::cron::scheduler::{schedule_next_after, DEFAULT_RETRY_BACKOFF_SECS};
 use crate::cron::CronRunner;
 use crate::router::{ReapEvent, ServiceHandler};
 use crate::storage::{CronRecord, CronStatus, Store};
@@ -71,6 +72,10 @@ impl CronService {
             updated_at: now,
             last_run_at: None,
             next_run_at,
+            max_retries: params.max_retries.unwrap_or(0),
+            backoff_secs: params.backoff_secs.unwrap_or(DEFAULT_RETRY_BACKOFF_SECS),
+            retry_count: 0,
+            next_retry_at: None,
         };
 
         if let Err(e) = self.store.upsert_cron(&cron) {
@@ -162,6 +167,12 @@ impl CronService {
         if let Some(skip_overlap) = params.skip_overlap {
             cron.skip_overlap = skip_overlap;
         }
+        if let Some(max_retries) = params.max_retries {
+            cron.max_retries = max_retries;
+        }
+        if let Some(backoff_secs) = params.backoff_secs {
+            cron.backoff_secs = backoff_secs;
+        }
         cron.updated_at = now_unix();
 
         if let Err(e) = self.store.upsert_cron(&cron) {
@@ -365,11 +376,31 @@ impl CronService {
     }
 }
 
+impl CronService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("cron.list", Scope::CronRead),
+        ("cron.status", Scope::CronRead),
+        ("cron.runs", Scope::CronRead),
+        ("cron.logs.tail", Scope::CronRead),
+        ("cron.add", Scope::CronWrite),
+        ("cron.update", Scope::CronWrite),
+        ("cron.remove", Scope::CronWrite),
+        ("cron.run", Scope::CronWrite),
+        ("cron.run.force", Scope::CronWrite),
+        ("cron.start", Scope::CronWrite),
+        ("cron.cancel", Scope::CronWrite),
+    ];
+}
+
 impl ServiceHandler for CronService {
     fn namespace(&self) -> &str {
         "cron"
     }
 
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
     fn handle_request(
         &mut self,
         id: uuid::Uuid,
@@ -418,6 +449,7 @@ fn now_unix() -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::authz::{AuthContext, MethodPolicy, Role};
     use crate::storage::SqliteStore;
     use crate::storage::{CronRunRecord, CronRunStatus};
 
@@ -430,6 +462,31 @@ mod tests {
         Arc::new(CronRunner::new(store, 1, tx))
     }
 
+    #[test]
+    fn cron_methods_map_to_cron_scopes() {
+        let mut policy = MethodPolicy::new();
+        policy.extend(CronService::METHOD_SCOPES);
+        for method in [
+            "cron.start",
+            "cron.cancel",
+            "cron.add",
+            "cron.update",
+            "cron.remove",
+            "cron.run",
+            "cron.run.force",
+        ] {
+            assert_eq!(policy.scope_for(method), Some(Scope::CronWrite), "{method}");
+        }
+        for method in ["cron.status", "cron.runs", "cron.logs.tail", "cron.list"] {
+            assert_eq!(policy.scope_for(method), Some(Scope::CronRead), "{method}");
+        }
+        let user = AuthContext::new(Role::User);
+        assert!(policy.allows(&user, "cron.add"));
+        let viewer = AuthContext::new(Role::Viewer);
+        assert!(!policy.allows(&viewer, "cron.list"));
+        assert!(!policy.allows(&viewer, "cron.add"));
+    }
+
     #[tokio::test]
     async fn cron_add_and_list_are_scoped_by_status() {
         let store = make_store();
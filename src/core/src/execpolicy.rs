@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use std::path::Path;
 
+use crate::homie_config::resolve_program;
+
 #[derive(Debug, Clone)]
 pub struct ExecPolicy {
     rules: Vec<CompiledRule>,
@@ -14,13 +16,36 @@ impl ExecPolicy {
     pub fn load_from_str(raw: &str) -> Result<Self, String> {
         let file: ExecPolicyFile =
             toml::from_str(raw).map_err(|e| format!("parse execpolicy: {e}"))?;
-        let mut compiled = Vec::new();
-        for rule in file.rules {
-            if let Some(rule) = CompiledRule::from_rule(rule)? {
-                compiled.push(rule);
+        Self::empty().with_rules(&file.rules)
+    }
+
+    /// Append `rules` after the ones already loaded. Rules are evaluated in
+    /// order and the first match wins.
+    pub(crate) fn with_rules(mut self, rules: &[ExecPolicyRule]) -> Result<Self, String> {
+        for rule in rules {
+            if let Some(rule) = CompiledRule::from_rule(rule.clone())? {
+                self.rules.push(rule);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Like [`with_rules`](Self::with_rules), but an invalid rule is left
+    /// out on its own instead of failing the batch. Returns one error per
+    /// rule left out.
+    pub(crate) fn with_valid_rules(mut self, rules: &[ExecPolicyRule]) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        for (index, rule) in rules.iter().enumerate() {
+            match CompiledRule::from_rule(rule.clone()) {
+                Ok(Some(rule)) => self.rules.push(rule),
+                Ok(None) => {}
+                Err(err) => {
+                    let id = rule.id.as_deref().unwrap_or("unnamed");
+                    errors.push(format!("rule {index} ({id}): {err}"));
+                }
             }
         }
-        Ok(Self { rules: compiled })
+        (self, errors)
     }
 
     pub fn load_from_path(path: &Path) -> Result<Self, String> {
@@ -59,14 +84,23 @@ impl Default for ExecPolicyFile {
     }
 }
 
+/// One `[[rule]]` entry, from the execpolicy file or `[[tools.exec.rule]]`.
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
-struct ExecPolicyRule {
+pub struct ExecPolicyRule {
     id: Option<String>,
     effect: ExecPolicyEffect,
     argv_exact: Option<Vec<String>>,
     argv_glob: Option<Vec<String>>,
     argv_shorthand: Option<String>,
+    /// Program name, path or glob, matched against the file argv[0] runs.
+    program: Option<String>,
+    /// Globs the leading arguments must match; later arguments are free.
+    args_prefix: Option<Vec<String>>,
+    /// Patterns that must each match some argument, in any position. Flag
+    /// patterns match the way the program parses them; `|` separates
+    /// alternatives.
+    args_contain: Option<Vec<String>>,
 }
 
 impl Default for ExecPolicyRule {
@@ -77,6 +111,9 @@ impl Default for ExecPolicyRule {
             argv_exact: None,
             argv_glob: None,
             argv_shorthand: None,
+            program: None,
+            args_prefix: None,
+            args_contain: None,
         }
     }
 }
@@ -92,6 +129,11 @@ enum ExecPolicyEffect {
 enum RuleMatcher {
     Exact(Vec<String>),
     Glob(Vec<String>),
+    Program {
+        program: String,
+        prefix: Vec<String>,
+        contain: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -110,6 +152,18 @@ impl CompiledRule {
             RuleMatcher::Glob(tokens)
         } else if let Some(shorthand) = rule.argv_shorthand.as_ref() {
             RuleMatcher::Glob(parse_shorthand(shorthand)?)
+        } else if let Some(program) = rule.program.as_ref() {
+            let program = program.trim();
+            if program.is_empty() {
+                return Err("execpolicy rule program is empty".to_string());
+            }
+            RuleMatcher::Program {
+                program: program.to_string(),
+                prefix: rule.args_prefix.clone().unwrap_or_default(),
+                contain: rule.args_contain.clone().unwrap_or_default(),
+            }
+        } else if rule.args_prefix.is_some() || rule.args_contain.is_some() {
+            return Err("execpolicy args_prefix/args_contain require program".to_string());
         } else {
             return Ok(None);
         };
@@ -125,6 +179,14 @@ impl CompiledRule {
         match &self.matcher {
             RuleMatcher::Exact(tokens) => match_exact(tokens, argv),
             RuleMatcher::Glob(tokens) => match_glob(tokens, argv),
+            RuleMatcher::Program {
+                program,
+                prefix,
+                contain,
+            } => {
+                let deny = matches!(self.effect, ExecPolicyEffect::Deny);
+                match_program(program, deny, prefix, contain, argv)
+            }
         }
     }
 }
@@ -182,6 +244,105 @@ fn match_glob(pattern: &[String], argv: &[String]) -> bool {
     idx == argv.len()
 }
 
+fn match_program(
+    program: &str,
+    deny: bool,
+    prefix: &[String],
+    contain: &[String],
+    argv: &[String],
+) -> bool {
+    let Some((cmd, args)) = argv.split_first() else {
+        return false;
+    };
+    if !program_matches(program, cmd, deny) {
+        return false;
+    }
+    if prefix.len() > args.len() {
+        return false;
+    }
+    if !prefix
+        .iter()
+        .zip(args)
+        .all(|(pattern, arg)| match_token(pattern, arg))
+    {
+        return false;
+    }
+    contain.iter().all(|pattern| {
+        pattern
+            .split('|')
+            .any(|alternative| args_contain(alternative, args))
+    })
+}
+
+/// Whether `cmd` runs `program`. Both are resolved the way the command
+/// would be (a bare name through `PATH`) and compared as files, so a local
+/// `./rm` does not pass for `rm`; a glob is matched against the resolved
+/// path or its file name. Deny rules also match on the name as written, so
+/// a command that does not resolve is still refused.
+fn program_matches(program: &str, cmd: &str, deny: bool) -> bool {
+    if deny && name_matches(program, cmd) {
+        return true;
+    }
+    let Some(resolved) = resolve_program(cmd) else {
+        return false;
+    };
+    if program.contains('*') {
+        return name_matches(program, &resolved.to_string_lossy());
+    }
+    resolve_program(program).is_some_and(|expected| same_file(&expected, &resolved))
+}
+
+fn name_matches(program: &str, cmd: &str) -> bool {
+    let file_name = Path::new(cmd)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(cmd);
+    match_token(program, cmd) || match_token(program, file_name)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Whether some argument matches `pattern`. Options stop at `--`. A short
+/// flag pattern (`-rf`) needs each of its letters set, alone or bundled
+/// (`-fr`, `-r -f`); a long one (`--recursive`) also matches `--name=value`
+/// and abbreviations (`--rec`). Anything else is a glob over each argument.
+fn args_contain(pattern: &str, args: &[String]) -> bool {
+    let options = args.iter().take_while(|arg| arg.as_str() != "--");
+    if let Some(name) = long_flag(pattern) {
+        return options.filter_map(|arg| long_flag(arg)).any(|given| {
+            let given = given.split_once('=').map_or(given, |(name, _)| name);
+            name.starts_with(given)
+        });
+    }
+    if let Some(letters) = short_flags(pattern) {
+        let given: Vec<&str> = options.filter_map(|arg| short_flags(arg)).collect();
+        return letters
+            .chars()
+            .all(|letter| given.iter().any(|flags| flags.contains(letter)));
+    }
+    args.iter().any(|arg| match_token(pattern, arg))
+}
+
+/// `name` of a `--name` argument.
+fn long_flag(arg: &str) -> Option<&str> {
+    arg.strip_prefix("--").filter(|name| {
+        name.chars()
+            .next()
+            .is_some_and(|first| first.is_ascii_alphanumeric())
+    })
+}
+
+/// Letters of a `-abc` argument.
+fn short_flags(arg: &str) -> Option<&str> {
+    arg.strip_prefix('-')
+        .filter(|letters| !letters.is_empty() && letters.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
 fn token_eq(pattern: &str, value: &str) -> bool {
     let (p, v) = normalize_pair(pattern, value);
     p == v
@@ -199,7 +360,8 @@ fn normalize_pair<'a>(pattern: &'a str, value: &'a str) -> (String, String) {
     (pattern.to_string(), value.to_string())
 }
 
-fn wildcard_match(pattern: &str, text: &str) -> bool {
+/// `*`-glob match of `text` against `pattern`.
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
     let p = pattern.as_bytes();
     let t = text.as_bytes();
     let mut pi = 0usize;
@@ -281,6 +443,198 @@ argv_glob = ["npm", "**"]
         assert!(policy.is_allowed(&argv(&["npm", "test", "--", "x"])));
     }
 
+    #[test]
+    fn program_rules_match_prefix_and_contained_args() {
+        let raw = r#"
+version = 1
+
+[[rule]]
+effect = "deny"
+program = "rm"
+args_contain = ["-rf"]
+
+[[rule]]
+effect = "allow"
+program = "rm"
+
+[[rule]]
+effect = "allow"
+program = "git"
+args_prefix = ["status"]
+
+[[rule]]
+effect = "allow"
+program = "ls"
+"#;
+        let policy = ExecPolicy::load_from_str(raw).expect("parse");
+        assert!(policy.is_allowed(&argv(&["git", "status"])));
+        assert!(policy.is_allowed(&argv(&["git", "status", "-s"])));
+        assert!(!policy.is_allowed(&argv(&["git", "push"])));
+        assert!(!policy.is_allowed(&argv(&["git"])));
+        assert!(policy.is_allowed(&argv(&["ls"])));
+        assert!(policy.is_allowed(&argv(&["/bin/ls", "-la", "src"])));
+        assert!(policy.is_allowed(&argv(&["rm", "notes.txt"])));
+        assert!(!policy.is_allowed(&argv(&["rm", "notes.txt", "-rf"])));
+        assert!(!policy.is_allowed(&argv(&["/bin/rm", "-rf", "/"])));
+    }
+
+    #[test]
+    fn program_rules_match_the_resolved_program() {
+        let dir = std::env::temp_dir().join(format!("homie-execpolicy-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("dir");
+        let fake = dir.join("ls");
+        std::fs::write(&fake, "#!/bin/sh\n").expect("fake ls");
+        let raw = r#"
+[[rule]]
+effect = "allow"
+program = "ls"
+"#;
+        let policy = ExecPolicy::load_from_str(raw).expect("parse");
+        assert!(policy.is_allowed(&argv(&["ls"])));
+        assert!(!policy.is_allowed(&argv(&[fake.to_str().unwrap(), "-la"])));
+        assert!(!policy.is_allowed(&argv(&["./ls"])));
+
+        let glob = ExecPolicy::load_from_str(
+            r#"
+[[rule]]
+effect = "allow"
+program = "l*"
+"#,
+        )
+        .expect("parse");
+        assert!(glob.is_allowed(&argv(&["ls"])));
+        assert!(!glob.is_allowed(&argv(&["lsx-not-installed"])));
+
+        let deny = ExecPolicy::load_from_str(
+            r#"
+[[rule]]
+effect = "deny"
+program = "ls"
+
+[[rule]]
+effect = "allow"
+argv_glob = ["**"]
+"#,
+        )
+        .expect("parse");
+        assert!(!deny.is_allowed(&argv(&[fake.to_str().unwrap()])));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn contained_flags_match_bundled_and_long_forms() {
+        let raw = r#"
+[[rule]]
+effect = "deny"
+program = "rm"
+args_contain = ["-r|-R|--recursive", "-f|--force"]
+
+[[rule]]
+effect = "allow"
+program = "rm"
+"#;
+        let policy = ExecPolicy::load_from_str(raw).expect("parse");
+        for denied in [
+            &["rm", "-rf", "/"][..],
+            &["rm", "-fr", "/"],
+            &["rm", "-r", "-f", "/"],
+            &["rm", "-Rfv", "/"],
+            &["rm", "--recursive", "--force", "/"],
+            &["rm", "--rec", "-f", "/"],
+            &["rm", "--force=yes", "-r", "/"],
+        ] {
+            assert!(!policy.is_allowed(&argv(denied)), "{denied:?}");
+        }
+        assert!(policy.is_allowed(&argv(&["rm", "-f", "notes.txt"])));
+        assert!(policy.is_allowed(&argv(&["rm", "-f", "--", "-r"])));
+        assert!(policy.is_allowed(&argv(&["rm", "--reverse-nothing", "-f", "x"])));
+    }
+
+    #[test]
+    fn invalid_rules_are_left_out_one_by_one() {
+        let rules: ExecPolicyFile = toml::from_str(
+            r#"
+[[rule]]
+id = "broken"
+effect = "deny"
+args_contain = ["-rf"]
+
+[[rule]]
+effect = "allow"
+program = "ls"
+"#,
+        )
+        .expect("parse");
+        assert!(ExecPolicy::empty().with_rules(&rules.rules).is_err());
+        let (policy, errors) = ExecPolicy::empty().with_valid_rules(&rules.rules);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken"), "{errors:?}");
+        assert!(policy.is_allowed(&argv(&["ls"])));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let allow_first = r#"
+[[rule]]
+effect = "allow"
+program = "git"
+
+[[rule]]
+effect = "deny"
+program = "git"
+args_prefix = ["push"]
+"#;
+        let policy = ExecPolicy::load_from_str(allow_first).expect("parse");
+        assert!(policy.is_allowed(&argv(&["git", "push"])));
+
+        let deny_first = r#"
+[[rule]]
+effect = "deny"
+program = "git"
+args_prefix = ["push"]
+
+[[rule]]
+effect = "allow"
+program = "git"
+"#;
+        let policy = ExecPolicy::load_from_str(deny_first).expect("parse");
+        assert!(!policy.is_allowed(&argv(&["git", "push", "origin"])));
+        assert!(policy.is_allowed(&argv(&["git", "log"])));
+    }
+
+    #[test]
+    fn appended_rules_follow_loaded_rules() {
+        let file = r#"
+[[rule]]
+effect = "deny"
+argv_shorthand = "git push:*"
+"#;
+        let extra: ExecPolicyFile = toml::from_str(
+            r#"
+[[rule]]
+effect = "allow"
+program = "git"
+"#,
+        )
+        .expect("parse");
+        let policy = ExecPolicy::load_from_str(file)
+            .and_then(|policy| policy.with_rules(&extra.rules))
+            .expect("rules");
+        assert!(policy.is_allowed(&argv(&["git", "status"])));
+        assert!(!policy.is_allowed(&argv(&["git", "push", "--dry-run"])));
+        assert!(!ExecPolicy::empty().is_allowed(&argv(&["git", "status"])));
+    }
+
+    #[test]
+    fn argument_patterns_require_program() {
+        let raw = r#"
+[[rule]]
+effect = "deny"
+args_contain = ["-rf"]
+"#;
+        assert!(ExecPolicy::load_from_str(raw).is_err());
+    }
+
     #[test]
     fn shorthand_parses_colon_star() {
         let raw = r#"
@@ -1,13 +1,17 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use serde::de::Error as DeError;
 use serde::{Deserialize, Deserializer};
 
+use crate::authz::Role;
+use crate::execpolicy::{wildcard_match, ExecPolicyRule};
 use crate::paths::{
     homie_config_path, homie_credentials_dir, homie_execpolicy_path, homie_home_dir,
     homie_system_prompt_path, user_home_dir,
 };
+use crate::storage::CHAT_RAW_EVENT_MAX_RUNS;
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
@@ -17,6 +21,8 @@ pub struct HomieConfig {
     pub models: ModelsConfig,
     pub chat: ChatConfig,
     pub tools: ToolsConfig,
+    pub terminal: TerminalConfig,
+    pub env: ChildEnvConfig,
     pub providers: ProvidersConfig,
     pub paths: PathsConfig,
 }
@@ -29,6 +35,8 @@ impl Default for HomieConfig {
             models: ModelsConfig::default(),
             chat: ChatConfig::default(),
             tools: ToolsConfig::default(),
+            terminal: TerminalConfig::default(),
+            env: ChildEnvConfig::default(),
             providers: ProvidersConfig::default(),
             paths: PathsConfig::default(),
         }
@@ -139,21 +147,80 @@ impl Default for DebugConfig {
 #[serde(default)]
 pub struct ModelsConfig {
     pub catalog_ttl_secs: u64,
+    /// Model selectors (`provider:model`, `*` wildcards) chats may use;
+    /// empty allows every enabled provider's models.
+    pub allow: Vec<String>,
+    /// Allowlists by role (`owner`, `user`, `viewer`) that replace `allow`
+    /// for connections of that role. Other keys are rejected.
+    #[serde(deserialize_with = "deserialize_allow_by_role")]
+    pub allow_by_role: HashMap<String, Vec<String>>,
 }
 
 impl Default for ModelsConfig {
     fn default() -> Self {
         Self {
             catalog_ttl_secs: 300,
+            allow: Vec::new(),
+            allow_by_role: HashMap::new(),
         }
     }
 }
 
+impl ModelsConfig {
+    /// Whether a connection with `role` may use `model` (a normalized
+    /// `provider:model` selector).
+    pub fn allows(&self, role: Role, model: &str) -> bool {
+        let patterns = self.allow_by_role.get(role.label()).unwrap_or(&self.allow);
+        patterns.is_empty()
+            || patterns
+                .iter()
+                .any(|pattern| wildcard_match(pattern.trim(), model))
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct ChatConfig {
     pub system_prompt_path: Option<String>,
     pub stream_idle_timeout_ms: Option<u64>,
+    /// Most recent non-system messages a run starts from; `0`/unset keeps
+    /// the whole thread.
+    pub max_context_messages: Option<usize>,
+    /// Estimated tokens a run's history may reach before it is compacted;
+    /// `0`/unset only applies the built-in message cap.
+    pub max_context_tokens: Option<usize>,
+    /// How history over `max_context_tokens` is reduced.
+    pub compaction_strategy: CompactionStrategy,
+    /// Model (`provider:model`) that writes `summarize` compaction
+    /// summaries; unset uses the run's own model.
+    pub summary_model: Option<String>,
+    /// Seconds a summary may take before the run falls back to truncation.
+    pub summary_timeout_secs: u64,
+    /// Agent runs (roci backend) executing at once across all chats; further
+    /// runs wait for a slot.
+    pub max_concurrent_runs: usize,
+    /// Default IANA timezone for chats without their own `timezone` setting.
+    pub timezone: Option<String>,
+    /// Default locale (BCP 47) for chats without their own `locale` setting.
+    pub locale: Option<String>,
+    /// Largest serialized thread `chat.import` accepts.
+    pub max_import_bytes: usize,
+    /// Seconds a tool approval request waits for the client before it is
+    /// declined; `0` waits forever.
+    pub approval_timeout_secs: u64,
+    /// What happens to `{{name}}` placeholders in the system prompt that no
+    /// run context value matches.
+    pub unknown_placeholders: UnknownPlaceholders,
+    /// Most recent runs (turns, across all chats) whose raw provider events
+    /// are kept when raw event persistence is on; older runs' events are
+    /// pruned.
+    pub raw_event_retention: usize,
+    /// Tokens runs may use per window before new messages are warned about
+    /// or refused.
+    pub token_budget: TokenBudgetConfig,
+    /// Seconds the Codex app-server may go unused, with no turn running,
+    /// before it is stopped until the next request; `0` keeps it running.
+    pub codex_idle_secs: u64,
     #[serde(skip)]
     pub system_prompt: String,
 }
@@ -163,20 +230,377 @@ impl Default for ChatConfig {
         Self {
             system_prompt_path: None,
             stream_idle_timeout_ms: None,
+            max_context_messages: None,
+            max_context_tokens: None,
+            compaction_strategy: CompactionStrategy::default(),
+            summary_model: None,
+            summary_timeout_secs: DEFAULT_SUMMARY_TIMEOUT_SECS,
+            max_concurrent_runs: DEFAULT_MAX_CONCURRENT_RUNS,
+            timezone: None,
+            locale: None,
+            max_import_bytes: DEFAULT_MAX_IMPORT_BYTES,
+            approval_timeout_secs: DEFAULT_APPROVAL_TIMEOUT_SECS,
+            unknown_placeholders: UnknownPlaceholders::default(),
+            raw_event_retention: CHAT_RAW_EVENT_MAX_RUNS,
+            token_budget: TokenBudgetConfig::default(),
+            codex_idle_secs: 0,
             system_prompt: DEFAULT_SYSTEM_PROMPT.trim().to_string(),
         }
     }
 }
 
+impl ChatConfig {
+    /// `raw_event_retention`, at least one run so the run being recorded
+    /// is never pruned.
+    pub fn raw_event_runs(&self) -> usize {
+        self.raw_event_retention.max(1)
+    }
+
+    /// How long Codex may sit idle before it is unloaded, if ever.
+    pub fn codex_idle_timeout(&self) -> Option<Duration> {
+        (self.codex_idle_secs > 0).then(|| Duration::from_secs(self.codex_idle_secs))
+    }
+}
+
+/// `[chat.token_budget]`: a token allowance per chat or identity over a
+/// rolling window.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TokenBudgetConfig {
+    /// Tokens allowed per window; `0` disables the budget.
+    pub limit: u64,
+    /// Budgets by role (`owner`, `user`, `viewer`) that replace `limit`
+    /// for connections of that role.
+    pub limit_by_role: HashMap<String, u64>,
+    /// Length of the rolling window the budget covers.
+    pub window_hours: u64,
+    /// Whose turns count against the budget.
+    pub scope: TokenBudgetScope,
+    /// What happens to a message sent once the budget is spent.
+    pub mode: TokenBudgetMode,
+}
+
+impl Default for TokenBudgetConfig {
+    fn default() -> Self {
+        Self {
+            limit: 0,
+            limit_by_role: HashMap::new(),
+            window_hours: DEFAULT_TOKEN_BUDGET_WINDOW_HOURS,
+            scope: TokenBudgetScope::default(),
+            mode: TokenBudgetMode::default(),
+        }
+    }
+}
+
+impl TokenBudgetConfig {
+    /// Budget for a connection with `role`; `0` means unlimited.
+    pub fn limit_for(&self, role: Role) -> u64 {
+        self.limit_by_role
+            .get(role.label())
+            .copied()
+            .unwrap_or(self.limit)
+    }
+
+    pub fn window_secs(&self) -> u64 {
+        self.window_hours.max(1).saturating_mul(3600)
+    }
+}
+
+/// Whose turns a `chat.token_budget` adds up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenBudgetScope {
+    /// The chat the message is sent to.
+    #[default]
+    Chat,
+    /// Every chat of the sender's identity.
+    Identity,
+}
+
+impl TokenBudgetScope {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Chat => "chat",
+            Self::Identity => "identity",
+        }
+    }
+}
+
+/// What `chat.message.send` does once a `chat.token_budget` is spent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenBudgetMode {
+    /// Start the run and emit `chat.budget.warning`.
+    #[default]
+    Warn,
+    /// Refuse the message.
+    Block,
+}
+
+const DEFAULT_MAX_CONCURRENT_RUNS: usize = 4;
+const DEFAULT_TOKEN_BUDGET_WINDOW_HOURS: u64 = 24;
+const DEFAULT_MAX_IMPORT_BYTES: usize = 8 * 1024 * 1024;
+const DEFAULT_APPROVAL_TIMEOUT_SECS: u64 = 600;
+const DEFAULT_SUMMARY_TIMEOUT_SECS: u64 = 30;
+
+/// How a roci thread's history is reduced once it outgrows
+/// `chat.max_context_tokens`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompactionStrategy {
+    /// Drop the oldest messages.
+    #[default]
+    Truncate,
+    /// Replace the oldest messages with a model-written summary.
+    Summarize,
+}
+
+impl CompactionStrategy {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Truncate => "truncate",
+            Self::Summarize => "summarize",
+        }
+    }
+}
+
+/// Handling of system prompt placeholders with no value to expand to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownPlaceholders {
+    /// Leave `{{name}}` in the prompt as written.
+    #[default]
+    Keep,
+    /// Remove it.
+    Blank,
+}
+
 const DEFAULT_SYSTEM_PROMPT: &str = include_str!("../system_prompt.md");
 
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TerminalConfig {
+    /// Programs terminal sessions may run, with `*` wildcards; empty allows
+    /// any. A path pattern matches the program's absolute path; a bare name
+    /// matches only the program `PATH` resolves that name to.
+    pub allow_commands: Vec<String>,
+    /// Program and arguments every session is launched through, e.g.
+    /// `["firejail", "--"]`; the session's own command follows them. The
+    /// wrapper must exec that command so it keeps the terminal.
+    pub command_wrapper: Vec<String>,
+}
+
+impl TerminalConfig {
+    /// Whether a session may run `program`. With an allowlist set, the
+    /// program must exist and relative paths are refused, since they would
+    /// resolve against the session's working directory.
+    pub fn allows(&self, program: &str) -> bool {
+        if self.allow_commands.is_empty() {
+            return true;
+        }
+        let path = Path::new(program);
+        if !path.is_absolute() && path.components().count() > 1 {
+            return false;
+        }
+        let Some(resolved) = resolve_program(program) else {
+            return false;
+        };
+        let file_name = resolved
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(program);
+        let on_path = resolve_program(file_name).is_some_and(|found| same_file(&found, &resolved));
+        let full = resolved.to_string_lossy();
+        let canonical = std::fs::canonicalize(&resolved).ok();
+        self.allow_commands.iter().any(|pattern| {
+            let pattern = pattern.trim();
+            if Path::new(pattern).components().count() > 1 {
+                wildcard_match(pattern, &full)
+                    || canonical.as_ref().is_some_and(|canonical| {
+                        wildcard_match(pattern, &canonical.to_string_lossy())
+                    })
+            } else {
+                on_path && wildcard_match(pattern, file_name)
+            }
+        })
+    }
+
+    /// `command_wrapper` once its program is found: a path must name a
+    /// file, and a bare name must be on `PATH`.
+    pub fn checked_command_wrapper(&self) -> Result<Vec<String>, String> {
+        let Some(program) = self.command_wrapper.first() else {
+            return Ok(Vec::new());
+        };
+        if resolve_program(program).is_none() {
+            return Err(format!("command_wrapper program not found: {program}"));
+        }
+        Ok(self.command_wrapper.clone())
+    }
+}
+
+/// The file `program` runs: a path as given, or a bare name looked up on
+/// the server's `PATH`.
+pub(crate) fn resolve_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.is_absolute() || path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let dirs = std::env::var_os("PATH")?;
+    std::env::split_paths(&dirs).find_map(|dir| {
+        let candidate = dir.join(program);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        let exe = candidate.with_extension("exe");
+        (cfg!(windows) && exe.is_file()).then_some(exe)
+    })
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Server environment variables kept out of child processes (the Codex
+/// app-server, terminal sessions, `exec` commands and cron commands).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChildEnvConfig {
+    /// Variable names not inherited, with `*` wildcards; matched without
+    /// regard to case.
+    pub deny: Vec<String>,
+    /// Variables inherited even when `deny` matches them.
+    pub allow: Vec<String>,
+}
+
+const DEFAULT_ENV_DENY: &[&str] = &["*_KEY", "*_TOKEN", "*_SECRET", "HOMIE_DB_*"];
+
+impl Default for ChildEnvConfig {
+    fn default() -> Self {
+        Self {
+            deny: DEFAULT_ENV_DENY.iter().map(|s| s.to_string()).collect(),
+            allow: Vec::new(),
+        }
+    }
+}
+
+impl ChildEnvConfig {
+    /// Whether a child may inherit the variable `name`.
+    pub fn inherits(&self, name: &str) -> bool {
+        let matches = |patterns: &[String]| {
+            let name = name.to_ascii_uppercase();
+            patterns
+                .iter()
+                .any(|pattern| wildcard_match(&pattern.trim().to_ascii_uppercase(), &name))
+        };
+        !matches(&self.deny) || matches(&self.allow)
+    }
+
+    /// Names of the server's environment variables to remove from a
+    /// child's environment.
+    pub fn scrubbed_vars(&self) -> Vec<String> {
+        std::env::vars_os()
+            .filter_map(|(name, _)| name.into_string().ok())
+            .filter(|name| !self.inherits(name))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, Default, Deserialize)]
 #[serde(default)]
 pub struct ToolsConfig {
+    /// Extra first-class tool channels (e.g. `discord`) on top of the
+    /// built-in `web`, `mobile` and `whatsapp`.
+    pub channels: Vec<String>,
+    /// Record every agent tool call in the per-thread audit trail
+    /// (`chat.tools.audit`).
+    pub audit: bool,
     pub web: WebToolsConfig,
+    pub exec: ExecToolConfig,
+    pub file_search: FileSearchConfig,
+    pub fs: FsToolsConfig,
     pub providers: HashMap<String, ToolProviderConfig>,
 }
 
+/// Where the file tools (`read`, `ls`, `find`, `grep`, `apply_patch`) may
+/// reach.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FsToolsConfig {
+    /// Directories file tools are confined to. Empty confines them to the
+    /// run's working directory (the chat's attached folder, or the server's
+    /// cwd).
+    pub allowed_roots: Vec<String>,
+}
+
+impl FsToolsConfig {
+    /// `allowed_roots` with `~` expanded; relative entries are taken from
+    /// the Homie home directory. Blank or unresolvable entries are skipped.
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.allowed_roots
+            .iter()
+            .filter_map(|root| resolve_path(root).ok())
+            .collect()
+    }
+}
+
+/// Bounds of a `chat.files.search` walk.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FileSearchConfig {
+    /// Directory entries one search may read, across all roots.
+    pub max_visited: usize,
+    /// Directory levels below each root a search descends into; unset
+    /// walks the whole tree.
+    pub max_depth: Option<usize>,
+    /// Results returned when a request passes no `limit`.
+    pub default_limit: usize,
+}
+
+impl Default for FileSearchConfig {
+    fn default() -> Self {
+        Self {
+            max_visited: 25_000,
+            max_depth: None,
+            default_limit: 40,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExecToolConfig {
+    /// Result shape when a call does not pass its own `format`.
+    pub output_format: ExecOutputFormat,
+    /// Inline `[[tools.exec.rule]]` entries, evaluated after the execpolicy
+    /// file's rules.
+    #[serde(rename = "rule")]
+    pub policy_rules: Vec<ExecPolicyRule>,
+}
+
+/// Result shape of the `exec` tool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecOutputFormat {
+    /// `{stdout, stderr, exit_code, duration_ms, ...}`.
+    #[default]
+    Structured,
+    /// stdout and stderr combined into a single `output` string.
+    Raw,
+}
+
+impl ExecOutputFormat {
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "structured" => Some(Self::Structured),
+            "raw" => Some(Self::Raw),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, Deserialize)]
 #[serde(default)]
 pub struct ToolProviderConfig {
@@ -184,6 +608,10 @@ pub struct ToolProviderConfig {
     pub channels: Vec<String>,
     pub allow_tools: Vec<String>,
     pub deny_tools: Vec<String>,
+    /// Reconnection attempts for a dropped dynamic provider before giving up.
+    pub reconnect_max_attempts: Option<u32>,
+    /// Initial reconnection backoff in milliseconds; doubles per attempt.
+    pub reconnect_backoff_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -587,6 +1015,25 @@ fn parse_string_or_default(value: String, default: String) -> String {
     }
 }
 
+fn deserialize_allow_by_role<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let allow_by_role = HashMap::<String, Vec<String>>::deserialize(deserializer)?;
+    let roles = [Role::Owner, Role::User, Role::Viewer];
+    if let Some(unknown) = allow_by_role
+        .keys()
+        .find(|key| !roles.iter().any(|role| role.label() == key.as_str()))
+    {
+        return Err(D::Error::custom(format!(
+            "unknown role in models.allow_by_role: {unknown} (expected owner, user or viewer)"
+        )));
+    }
+    Ok(allow_by_role)
+}
+
 fn deserialize_web_fetch_enabled<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
@@ -776,6 +1223,28 @@ pub struct ProvidersConfig {
     pub github_copilot: GithubCopilotProviderConfig,
     pub openai_compatible: OpenAiCompatibleProviderConfig,
     pub claude_code: ClaudeCodeProviderConfig,
+    /// Credential profile to use per provider id (e.g. `"github-copilot" = "work"`)
+    /// when a chat does not pick one. Missing entries use `"default"`.
+    pub profiles: HashMap<String, String>,
+}
+
+impl ProvidersConfig {
+    /// Config section gating a roci model provider, and whether it is enabled.
+    /// `None` for providers configured only through the environment (`openai`)
+    /// or unknown to Homie.
+    pub fn model_provider_section(&self, provider: &str) -> Option<(&'static str, bool)> {
+        match provider {
+            "openai-codex" => Some(("providers.openai_codex", self.openai_codex.enabled)),
+            "github-copilot" => Some(("providers.github_copilot", self.github_copilot.enabled)),
+            // Copilot credentials also back openai-compatible models.
+            "openai-compatible" => Some((
+                "providers.openai_compatible",
+                self.openai_compatible.enabled || self.github_copilot.enabled,
+            )),
+            "anthropic" => Some(("providers.claude_code", self.claude_code.enabled)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -889,8 +1358,145 @@ fn resolve_path(value: &str) -> Result<PathBuf, String> {
 mod tests {
     use super::{
         default_brave_search_endpoint, default_firecrawl_base_url, default_searxng_api_key_header,
-        default_web_fetch_user_agent, default_web_search_provider, HomieConfig, ToolProviderConfig,
+        default_web_fetch_user_agent, default_web_search_provider, resolve_program, HomieConfig,
+        TokenBudgetMode, TokenBudgetScope, ToolProviderConfig,
     };
+    use crate::authz::Role;
+
+    #[test]
+    fn raw_event_retention_keeps_at_least_one_run() {
+        assert_eq!(HomieConfig::default().chat.raw_event_runs(), 10);
+        let config: HomieConfig =
+            toml::from_str("[chat]\nraw_event_retention = 250\n").expect("parse config");
+        assert_eq!(config.chat.raw_event_runs(), 250);
+        let config: HomieConfig =
+            toml::from_str("[chat]\nraw_event_retention = 0\n").expect("parse config");
+        assert_eq!(config.chat.raw_event_runs(), 1);
+    }
+
+    #[test]
+    fn token_budget_limits_by_role_override_the_default() {
+        let raw = r#"
+        [chat.token_budget]
+        limit = 500000
+        mode = "block"
+        scope = "identity"
+
+        [chat.token_budget.limit_by_role]
+        viewer = 50000
+        owner = 0
+        "#;
+        let config: HomieConfig = toml::from_str(raw).expect("parse config");
+        let budget = &config.chat.token_budget;
+        assert_eq!(budget.limit_for(Role::Viewer), 50_000);
+        assert_eq!(budget.limit_for(Role::User), 500_000);
+        assert_eq!(budget.limit_for(Role::Owner), 0);
+        assert_eq!(budget.mode, TokenBudgetMode::Block);
+        assert_eq!(budget.scope, TokenBudgetScope::Identity);
+        assert_eq!(budget.window_secs(), 24 * 3600);
+        assert_eq!(HomieConfig::default().chat.token_budget.limit, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminal_allowlist_resolves_bare_names_on_path() {
+        let raw = r#"
+        [terminal]
+        allow_commands = ["sh", "/usr/bin/e*"]
+        "#;
+        let config: HomieConfig = toml::from_str(raw).expect("parse config");
+        let sh = resolve_program("sh").expect("sh on PATH");
+        assert!(config.terminal.allows("sh"));
+        assert!(config.terminal.allows(&sh.to_string_lossy()));
+        assert!(config.terminal.allows("/usr/bin/env"));
+        assert!(!config.terminal.allows("/usr/local/bin/env"));
+        assert!(!config.terminal.allows("python3"));
+        assert!(!config.terminal.allows("./sh"));
+        assert!(HomieConfig::default().terminal.allows("python3"));
+
+        let dir = std::env::temp_dir().join(format!("homie-allow-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let imposter = dir.join("sh");
+        std::fs::copy(&sh, &imposter).unwrap();
+        assert!(!config.terminal.allows(&imposter.to_string_lossy()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminal_command_wrapper_program_must_exist() {
+        let raw = r#"
+        [terminal]
+        command_wrapper = ["env", "--"]
+        "#;
+        let config: HomieConfig = toml::from_str(raw).expect("parse config");
+        assert_eq!(
+            config.terminal.checked_command_wrapper(),
+            Ok(vec!["env".to_string(), "--".to_string()])
+        );
+        assert_eq!(
+            HomieConfig::default().terminal.checked_command_wrapper(),
+            Ok(Vec::new())
+        );
+
+        let mut terminal = config.terminal.clone();
+        terminal.command_wrapper = vec!["homie-no-such-wrapper".into()];
+        assert!(terminal.checked_command_wrapper().is_err());
+        terminal.command_wrapper = vec!["/no/such/dir/firejail".into()];
+        assert!(terminal.checked_command_wrapper().is_err());
+    }
+
+    #[test]
+    fn child_env_denies_secrets_unless_allowed() {
+        let defaults = HomieConfig::default().env;
+        assert!(!defaults.inherits("OPENAI_API_KEY"));
+        assert!(!defaults.inherits("github_token"));
+        assert!(!defaults.inherits("HOMIE_DB_URL"));
+        assert!(defaults.inherits("PATH"));
+        assert!(defaults.inherits("KEYBOARD"));
+
+        let raw = r#"
+        [env]
+        deny = ["*_KEY", "AWS_*"]
+        allow = ["ANTHROPIC_API_KEY"]
+        "#;
+        let config: HomieConfig = toml::from_str(raw).expect("parse config");
+        assert!(!config.env.inherits("OPENAI_API_KEY"));
+        assert!(config.env.inherits("ANTHROPIC_API_KEY"));
+        assert!(!config.env.inherits("AWS_REGION"));
+        assert!(config.env.inherits("GITHUB_TOKEN"));
+    }
+
+    #[test]
+    fn model_allowlists_match_globs_and_role_overrides() {
+        let raw = r#"
+        [models]
+        allow = ["openai-codex:*", "github-copilot:gpt-4.1"]
+
+        [models.allow_by_role]
+        viewer = ["openai-compatible:*"]
+        "#;
+        let config: HomieConfig = toml::from_str(raw).expect("parse config");
+        let models = &config.models;
+        assert!(models.allows(Role::User, "openai-codex:gpt-5.1-codex"));
+        assert!(models.allows(Role::Owner, "github-copilot:gpt-4.1"));
+        assert!(!models.allows(Role::User, "github-copilot:claude-opus-4"));
+        assert!(!models.allows(Role::Viewer, "openai-codex:gpt-5.1-codex"));
+        assert!(models.allows(Role::Viewer, "openai-compatible:qwen3"));
+        assert!(HomieConfig::default()
+            .models
+            .allows(Role::Viewer, "anything:at-all"));
+    }
+
+    #[test]
+    fn model_allowlist_rejects_unknown_roles() {
+        let raw = r#"
+        [models.allow_by_role]
+        viewers = ["openai-compatible:*"]
+        "#;
+        let err = toml::from_str::<HomieConfig>(raw).expect_err("unknown role");
+        assert!(err.to_string().contains("viewers"), "{err}");
+    }
 
     #[test]
     fn web_config_empty_override_strings_use_safe_defaults() {
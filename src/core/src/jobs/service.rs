@@ -6,6 +6,8 @@ use uuid::Uuid;
 
 use homie_protocol::{error_codes, BinaryFrame, Response};
 
+use crate::admin::RunFreeze;
+use crate::authz::Scope;
 use crate::router::{ReapEvent, ServiceHandler};
 use crate::storage::{JobRecord, JobStatus, Store};
 
@@ -30,11 +32,12 @@ struct TailParams {
 /// Jobs service backed by the persistent store.
 pub struct JobsService {
     store: Arc<dyn Store>,
+    run_freeze: RunFreeze,
 }
 
 impl JobsService {
-    pub fn new(store: Arc<dyn Store>) -> Self {
-        Self { store }
+    pub fn new(store: Arc<dyn Store>, run_freeze: RunFreeze) -> Self {
+        Self { store, run_freeze }
     }
 
     fn start(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
@@ -92,7 +95,10 @@ impl JobsService {
         }
     }
 
-    fn cancel(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+    /// Cancel a queued job, or abort the agent turn a running job drives.
+    /// A running job is only marked cancelled once a connection confirms
+    /// the abort; otherwise it stays running and the call fails.
+    async fn cancel(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
         let params: JobIdParams = match params {
             Some(v) => match serde_json::from_value(v) {
                 Ok(p) => p,
@@ -112,6 +118,37 @@ impl JobsService {
             Ok(None) => return Response::error(req_id, error_codes::INVALID_PARAMS, "unknown job"),
             Err(e) => return Response::error(req_id, error_codes::INTERNAL_ERROR, e),
         };
+        if job.status.is_terminal() {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!(
+                    "job already {}; only queued or running jobs can be cancelled",
+                    job.status.as_str()
+                ),
+            );
+        }
+
+        let previous_status = job.status;
+        if previous_status == JobStatus::Running {
+            if let Some((chat_id, turn_id)) = job_turn(&job.spec) {
+                let delivered = self.run_freeze.cancel_turn(chat_id, turn_id).await;
+                tracing::info!(
+                    job_id = %job.job_id,
+                    chat_id,
+                    turn_id,
+                    delivered,
+                    "cancelling job agent turn"
+                );
+                if !delivered {
+                    return Response::error(
+                        req_id,
+                        error_codes::INTERNAL_ERROR,
+                        format!("no connection aborted turn {turn_id}; job left running"),
+                    );
+                }
+            }
+        }
 
         let updated = JobRecord {
             status: JobStatus::Cancelled,
@@ -123,7 +160,10 @@ impl JobsService {
             return Response::error(req_id, error_codes::INTERNAL_ERROR, e);
         }
 
-        Response::success(req_id, json!({ "job": updated }))
+        Response::success(
+            req_id,
+            json!({ "ok": true, "previous_status": previous_status, "job": updated }),
+        )
     }
 
     fn logs_tail(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
@@ -157,21 +197,36 @@ impl JobsService {
     }
 }
 
+impl JobsService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("jobs.status", Scope::JobsRead),
+        ("jobs.logs.tail", Scope::JobsRead),
+        ("jobs.start", Scope::JobsWrite),
+        ("jobs.cancel", Scope::JobsWrite),
+    ];
+}
+
 impl ServiceHandler for JobsService {
     fn namespace(&self) -> &str {
         "jobs"
     }
 
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
     fn handle_request(
         &mut self,
         id: Uuid,
         method: &str,
         params: Option<Value>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + '_>> {
+        if method == "jobs.cancel" {
+            return Box::pin(self.cancel(id, params));
+        }
         let resp = match method {
             "jobs.start" => self.start(id, params),
             "jobs.status" => self.status(id, params),
-            "jobs.cancel" => self.cancel(id, params),
             "jobs.logs.tail" => self.logs_tail(id, params),
             _ => Response::error(
                 id,
@@ -191,9 +246,156 @@ impl ServiceHandler for JobsService {
     fn shutdown(&mut self) {}
 }
 
+/// Agent turn a job is driving, from `chat_id` / `turn_id` in its spec.
+fn job_turn(spec: &Value) -> Option<(&str, &str)> {
+    let chat_id = spec.get("chat_id")?.as_str()?;
+    let turn_id = spec.get("turn_id")?.as_str()?;
+    Some((chat_id, turn_id))
+}
+
 fn now_unix() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+
+    fn make_service() -> (JobsService, Arc<SqliteStore>, RunFreeze) {
+        let store = Arc::new(SqliteStore::open_memory().expect("store"));
+        let freeze = RunFreeze::new();
+        (
+            JobsService::new(store.clone(), freeze.clone()),
+            store,
+            freeze,
+        )
+    }
+
+    fn insert_job(store: &SqliteStore, job_id: &str, status: JobStatus, spec: Value) {
+        store
+            .upsert_job(&JobRecord {
+                job_id: job_id.into(),
+                name: "nightly".into(),
+                status,
+                created_at: 1,
+                updated_at: 1,
+                spec,
+                logs: Vec::new(),
+            })
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancel_queued_job_then_reject_second_cancel() {
+        let (mut svc, store, _freeze) = make_service();
+        insert_job(&store, "job-1", JobStatus::Queued, json!({}));
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "jobs.cancel",
+                Some(json!({ "job_id": "job-1" })),
+            )
+            .await;
+        let result = resp.result.expect("result");
+        assert_eq!(result["ok"], true);
+        assert_eq!(result["previous_status"], "queued");
+        assert_eq!(
+            store.get_job("job-1").unwrap().unwrap().status,
+            JobStatus::Cancelled
+        );
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "jobs.cancel",
+                Some(json!({ "job_id": "job-1" })),
+            )
+            .await;
+        let err = resp.error.expect("error");
+        assert_eq!(err.code, error_codes::INVALID_PARAMS);
+        assert!(err.message.contains("already cancelled"));
+    }
+
+    #[tokio::test]
+    async fn cancel_rejects_completed_jobs() {
+        let (mut svc, store, _freeze) = make_service();
+        insert_job(&store, "job-done", JobStatus::Succeeded, json!({}));
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "jobs.cancel",
+                Some(json!({ "job_id": "job-done" })),
+            )
+            .await;
+        assert!(resp
+            .error
+            .expect("error")
+            .message
+            .contains("already succeeded"));
+        assert_eq!(
+            store.get_job("job-done").unwrap().unwrap().status,
+            JobStatus::Succeeded
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_running_job_aborts_its_turn() {
+        let (mut svc, store, freeze) = make_service();
+        let mut turn_rx = freeze.turn_cancel_signal();
+        let listener = tokio::spawn(async move {
+            let cancel = turn_rx.recv().await.expect("turn cancel");
+            assert_eq!(cancel.chat_id, "chat-1");
+            assert_eq!(cancel.turn_id, "turn-1");
+            cancel.acknowledge();
+        });
+        insert_job(
+            &store,
+            "job-run",
+            JobStatus::Running,
+            json!({ "chat_id": "chat-1", "turn_id": "turn-1" }),
+        );
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "jobs.cancel",
+                Some(json!({ "job_id": "job-run" })),
+            )
+            .await;
+        assert_eq!(resp.result.expect("result")["previous_status"], "running");
+        listener.await.unwrap();
+        assert_eq!(
+            store.get_job("job-run").unwrap().unwrap().status,
+            JobStatus::Cancelled
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_running_job_fails_when_its_turn_is_not_aborted() {
+        let (mut svc, store, _freeze) = make_service();
+        insert_job(
+            &store,
+            "job-orphan",
+            JobStatus::Running,
+            json!({ "chat_id": "chat-1", "turn_id": "turn-1" }),
+        );
+
+        let resp = svc
+            .handle_request(
+                Uuid::new_v4(),
+                "jobs.cancel",
+                Some(json!({ "job_id": "job-orphan" })),
+            )
+            .await;
+        assert_eq!(resp.error.expect("error").code, error_codes::INTERNAL_ERROR);
+        assert_eq!(
+            store.get_job("job-orphan").unwrap().unwrap().status,
+            JobStatus::Running
+        );
+    }
+}
@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod agent;
 mod auth;
 mod authz;
@@ -15,22 +16,36 @@ mod paths;
 pub mod presence;
 pub mod router;
 mod server;
+mod shutdown;
+pub mod state;
 pub mod storage;
 pub mod terminal;
 
-pub use agent::{AgentService, ChatService};
-pub use auth::{AuthOutcome, LiveWhois, TailscaleIdentity, TailscaleWhois};
-pub use authz::{context_for_outcome, scope_for_method, AuthContext, Role, Scope};
-pub use config::ServerConfig;
+pub use admin::{AdminService, RunFreeze};
+pub use agent::{check_default_model, AgentService, ChatService, RunSlots};
+pub use auth::{
+    hash_api_key, parse_api_key_spec, AuthOutcome, LiveWhois, TailscaleIdentity, TailscaleWhois,
+};
+pub use authz::{context_for_outcome, AuthContext, Role, Scope};
+pub use config::{ApiKey, AuthMode, EventBusKind, ServerConfig};
 pub use connection::Connection;
 pub use cron::CronService;
 pub use execpolicy::ExecPolicy;
 pub use homie_config::HomieConfig;
 pub use jobs::JobsService;
 pub use notifications::NotificationsService;
-pub use outbound::OutboundMessage;
+pub use outbound::{OutboundMessage, OverflowPolicy};
 pub use pairing::PairingService;
-pub use router::{MessageRouter, ServiceHandler, ServiceRegistry, SubscriptionManager};
-pub use server::build_router;
-pub use storage::{ChatRecord, SessionStatus, SqliteStore, Store, TerminalRecord};
+pub use router::{
+    ConnectionContext, EventBus, LocalEventBus, MessageRouter, MetricsRegistry, RateLimiter,
+    RequestTimeouts, ServiceHandler, ServiceRegistry, StoreEventBus, SubscriptionManager,
+};
+#[cfg(unix)]
+pub use server::UnixPeer;
+pub use server::{build_router, build_router_with_shutdown};
+pub use shutdown::ShutdownSignal;
+pub use state::StateService;
+pub use storage::{
+    ChatRecord, LoginSessionRecord, SessionStatus, SqliteStore, Store, TerminalRecord,
+};
 pub use terminal::TerminalService;
@@ -1,3 +1,7 @@
 mod service;
+mod webhook;
+mod worker;
 
 pub use service::NotificationsService;
+pub use webhook::{notify_turn_finished, TurnNotification, WEBHOOK_KIND};
+pub use worker::{spawn_notification_worker, NotificationWorker};
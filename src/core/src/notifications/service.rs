@@ -6,10 +6,13 @@ use uuid::Uuid;
 
 use homie_protocol::{error_codes, BinaryFrame, Response};
 
+use crate::authz::Scope;
 use crate::outbound::OutboundMessage;
-use crate::router::{ReapEvent, ServiceHandler};
+use crate::router::{ConnectionContext, ReapEvent, ServiceHandler};
 use crate::storage::{NotificationEvent, NotificationSubscription, Store};
 
+use super::webhook::{check_webhook_url, is_webhook_url, WEBHOOK_KIND};
+
 #[derive(Debug, Deserialize)]
 struct RegisterParams {
     target: String,
@@ -27,6 +30,9 @@ struct SendParams {
 pub struct NotificationsService {
     store: Arc<dyn Store>,
     outbound_tx: tokio::sync::mpsc::Sender<OutboundMessage>,
+    /// Connection the service is attached to; its principal owns the
+    /// subscriptions it registers.
+    context: Option<Arc<ConnectionContext>>,
 }
 
 impl NotificationsService {
@@ -34,7 +40,11 @@ impl NotificationsService {
         store: Arc<dyn Store>,
         outbound_tx: tokio::sync::mpsc::Sender<OutboundMessage>,
     ) -> Self {
-        Self { store, outbound_tx }
+        Self {
+            store,
+            outbound_tx,
+            context: None,
+        }
     }
 
     fn register(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
@@ -52,6 +62,12 @@ impl NotificationsService {
             None => return Response::error(req_id, error_codes::INVALID_PARAMS, "missing params"),
         };
 
+        if params.kind.as_deref() == Some(WEBHOOK_KIND) || is_webhook_url(&params.target) {
+            if let Err(e) = check_webhook_url(&params.target) {
+                return Response::error(req_id, error_codes::INVALID_PARAMS, e);
+            }
+        }
+
         let now = now_unix();
         let subscription = NotificationSubscription {
             subscription_id: Uuid::new_v4().to_string(),
@@ -59,6 +75,7 @@ impl NotificationsService {
             kind: params.kind,
             created_at: now,
             updated_at: now,
+            owner: self.context.as_ref().and_then(|ctx| ctx.principal.clone()),
         };
 
         if let Err(e) = self.store.upsert_notification_subscription(&subscription) {
@@ -108,6 +125,14 @@ impl NotificationsService {
             body: params.body,
             target: params.target.clone(),
             created_at: now,
+            payload: None,
+            attempts: 0,
+            // Webhook targets are delivered by the background worker; other
+            // targets are reached through the `notifications.sent` event.
+            delivered_at: match params.target.as_deref() {
+                Some(target) if is_webhook_url(target) => None,
+                _ => Some(now),
+            },
         };
 
         if let Err(e) = self.store.insert_notification_event(&event) {
@@ -131,11 +156,27 @@ impl NotificationsService {
     }
 }
 
+impl NotificationsService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("notifications.list", Scope::NotificationsRead),
+        ("notifications.register", Scope::NotificationsWrite),
+        ("notifications.send", Scope::NotificationsWrite),
+    ];
+}
+
 impl ServiceHandler for NotificationsService {
     fn namespace(&self) -> &str {
         "notifications"
     }
 
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
+    fn attach(&mut self, ctx: Arc<ConnectionContext>) {
+        self.context = Some(ctx);
+    }
+
     fn handle_request(
         &mut self,
         id: Uuid,
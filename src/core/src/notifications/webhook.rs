@@ -0,0 +1,294 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use serde::Serialize;
+use url::{Host, Url};
+use uuid::Uuid;
+
+use crate::agent::ip_is_private;
+use crate::storage::{NotificationEvent, NotificationSubscription, Store};
+
+/// Subscription `kind` whose `target` is a URL that receives turn webhooks.
+pub const WEBHOOK_KIND: &str = "webhook";
+
+const SUMMARY_MAX_CHARS: usize = 280;
+
+/// Payload POSTed to webhook targets when an agent turn finishes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TurnNotification {
+    pub chat_id: String,
+    pub thread_id: String,
+    pub turn_id: String,
+    /// `completed` or `failed`.
+    pub status: String,
+    /// Start of the assistant reply, or the error for failed turns.
+    pub summary: String,
+}
+
+impl TurnNotification {
+    pub fn new(chat_id: &str, thread_id: &str, turn_id: &str, status: &str, summary: &str) -> Self {
+        Self {
+            chat_id: chat_id.to_string(),
+            thread_id: thread_id.to_string(),
+            turn_id: turn_id.to_string(),
+            status: status.to_string(),
+            summary: truncate_summary(summary),
+        }
+    }
+}
+
+/// Queue a finished turn for the webhook subscriptions of the chat's owner.
+/// Delivery happens in the notification worker. Returns the number of
+/// targets queued.
+pub fn notify_turn_finished(store: &Arc<dyn Store>, notification: TurnNotification) -> usize {
+    let owner = match store.get_chat(&notification.chat_id) {
+        Ok(chat) => chat.and_then(|chat| chat.owner),
+        Err(error) => {
+            tracing::warn!(%error, "failed to load chat for turn notification");
+            return 0;
+        }
+    };
+    let targets: Vec<String> = match store.list_notification_subscriptions() {
+        Ok(subs) => subs
+            .iter()
+            .filter(|sub| sub.owner == owner)
+            .filter_map(webhook_target)
+            .collect(),
+        Err(error) => {
+            tracing::warn!(%error, "failed to load notification subscriptions");
+            return 0;
+        }
+    };
+    if targets.is_empty() {
+        return 0;
+    }
+
+    let payload = match serde_json::to_value(&notification) {
+        Ok(payload) => payload,
+        Err(error) => {
+            tracing::warn!(%error, "failed to serialize turn notification");
+            return 0;
+        }
+    };
+    let now = now_unix();
+    let mut queued = 0;
+    for target in targets {
+        let event = NotificationEvent {
+            notification_id: Uuid::new_v4().to_string(),
+            title: format!("Turn {}", notification.status),
+            body: notification.summary.clone(),
+            target: Some(target.clone()),
+            created_at: now,
+            payload: Some(payload.clone()),
+            attempts: 0,
+            delivered_at: None,
+        };
+        match store.insert_notification_event(&event) {
+            Ok(()) => queued += 1,
+            Err(error) => tracing::warn!(%error, %target, "failed to queue turn notification"),
+        }
+    }
+    queued
+}
+
+/// Whether a notification target is an HTTP(S) URL the worker POSTs to.
+pub(crate) fn is_webhook_url(target: &str) -> bool {
+    let target = target.trim();
+    target.starts_with("http://") || target.starts_with("https://")
+}
+
+/// Parse a webhook target, refusing hosts that name this machine or a
+/// private network so subscriptions cannot reach internal services.
+pub(crate) fn check_webhook_url(target: &str) -> Result<Url, String> {
+    let url = Url::parse(target.trim()).map_err(|e| format!("invalid webhook url: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("webhook url must use http or https".into());
+    }
+    let blocked = match url.host() {
+        None => return Err("webhook url must include a host".into()),
+        Some(Host::Ipv4(ip)) => ip_is_private(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => ip_is_private(IpAddr::V6(ip)),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost") || domain.ends_with(".local")
+        }
+    };
+    if blocked {
+        return Err("webhook url must not point at a private or loopback host".into());
+    }
+    Ok(url)
+}
+
+/// Addresses to deliver a webhook to: those `url`'s host resolves to, when
+/// every one of them is public. Delivery connects only to these, so the
+/// name cannot be re-pointed at a private address in between.
+pub(crate) async fn resolve_webhook_target(url: &Url) -> Result<Vec<SocketAddr>, String> {
+    let url = check_webhook_url(url.as_str())?;
+    let host = url.host_str().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("dns lookup failed: {e}"))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{host} did not resolve"));
+    }
+    if addrs.iter().any(|addr| ip_is_private(addr.ip())) {
+        return Err(format!("{host} resolves to a private or loopback address"));
+    }
+    Ok(addrs)
+}
+
+fn webhook_target(sub: &NotificationSubscription) -> Option<String> {
+    if sub.kind.as_deref() != Some(WEBHOOK_KIND) || check_webhook_url(&sub.target).is_err() {
+        return None;
+    }
+    Some(sub.target.trim().to_string())
+}
+
+fn truncate_summary(summary: &str) -> String {
+    let summary = summary.trim();
+    match summary.char_indices().nth(SUMMARY_MAX_CHARS) {
+        Some((idx, _)) => format!("{}…", &summary[..idx]),
+        None => summary.to_string(),
+    }
+}
+
+fn now_unix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+
+    fn subscribe(store: &Arc<dyn Store>, target: &str, kind: Option<&str>) {
+        subscribe_as(store, target, kind, None);
+    }
+
+    fn subscribe_as(store: &Arc<dyn Store>, target: &str, kind: Option<&str>, owner: Option<&str>) {
+        store
+            .upsert_notification_subscription(&NotificationSubscription {
+                subscription_id: Uuid::new_v4().to_string(),
+                target: target.into(),
+                kind: kind.map(str::to_string),
+                created_at: 1,
+                updated_at: 1,
+                owner: owner.map(str::to_string),
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn finished_turn_is_queued_for_webhook_subscriptions() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        subscribe(&store, "https://example.com/hook", Some(WEBHOOK_KIND));
+        subscribe(&store, "device-1", None);
+
+        let notification =
+            TurnNotification::new("chat-1", "thread-1", "turn-1", "completed", "All done.");
+        assert_eq!(notify_turn_finished(&store, notification), 1);
+
+        let events = store
+            .list_undelivered_notification_events(5, now_unix(), 10)
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].title, "Turn completed");
+        assert_eq!(
+            events[0].target.as_deref(),
+            Some("https://example.com/hook")
+        );
+        assert_eq!(
+            events[0].payload,
+            Some(serde_json::json!({
+                "chat_id": "chat-1",
+                "thread_id": "thread-1",
+                "turn_id": "turn-1",
+                "status": "completed",
+                "summary": "All done.",
+            }))
+        );
+    }
+
+    #[test]
+    fn no_webhook_subscriptions_records_nothing() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        subscribe(&store, "device-1", None);
+        subscribe(&store, "not-a-url", Some(WEBHOOK_KIND));
+        let notification = TurnNotification::new("c", "t", "turn", "failed", "boom");
+        assert_eq!(notify_turn_finished(&store, notification), 0);
+        assert!(store.list_notification_events(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn turns_only_notify_the_chat_owners_subscriptions() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        store
+            .upsert_chat(&crate::storage::ChatRecord {
+                chat_id: "chat-1".into(),
+                thread_id: "chat-1".into(),
+                created_at: "2026-01-01T00:00:00Z".into(),
+                status: crate::storage::SessionStatus::Active,
+                event_pointer: 0,
+                settings: None,
+                owner: Some("alice".into()),
+            })
+            .unwrap();
+        subscribe_as(
+            &store,
+            "https://alice.example.com/hook",
+            Some(WEBHOOK_KIND),
+            Some("alice"),
+        );
+        subscribe_as(
+            &store,
+            "https://bob.example.com/hook",
+            Some(WEBHOOK_KIND),
+            Some("bob"),
+        );
+        subscribe(
+            &store,
+            "https://anyone.example.com/hook",
+            Some(WEBHOOK_KIND),
+        );
+
+        let notification = TurnNotification::new("chat-1", "chat-1", "turn", "completed", "ok");
+        assert_eq!(notify_turn_finished(&store, notification), 1);
+        let events = store
+            .list_undelivered_notification_events(5, now_unix(), 10)
+            .unwrap();
+        assert_eq!(
+            events[0].target.as_deref(),
+            Some("https://alice.example.com/hook")
+        );
+    }
+
+    #[test]
+    fn private_and_loopback_webhook_urls_are_refused() {
+        assert!(check_webhook_url("https://example.com/hook").is_ok());
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "http://192.168.1.10/hook",
+            "http://169.254.169.254/latest",
+            "http://[::1]/hook",
+            "http://printer.local/hook",
+            "ftp://example.com/hook",
+        ] {
+            assert!(check_webhook_url(url).is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn long_summaries_are_truncated() {
+        let long = "x".repeat(SUMMARY_MAX_CHARS + 50);
+        let notification = TurnNotification::new("c", "t", "turn", "completed", &long);
+        assert_eq!(notification.summary.chars().count(), SUMMARY_MAX_CHARS + 1);
+        assert!(notification.summary.ends_with('…'));
+    }
+}
@@ -0,0 +1,376 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::shutdown::ShutdownSignal;
+use crate::storage::{NotificationEvent, Store};
+
+use super::webhook::{is_webhook_url, resolve_webhook_target};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const BATCH_SIZE: usize = 32;
+/// Attempts per event before it is left undelivered for good.
+const MAX_DELIVERY_ATTEMPTS: u32 = 6;
+/// Delay before the first retry; doubles with every failed attempt.
+const BASE_BACKOFF_SECS: u64 = 5;
+const MAX_BACKOFF_SECS: u64 = 30 * 60;
+
+/// Drains undelivered webhook notification events from the store and POSTs
+/// them with exponential backoff. Delivery state lives in the store, so a
+/// restart resumes where the previous process stopped.
+pub struct NotificationWorker {
+    store: Arc<dyn Store>,
+    /// Skip the public-address check on targets; tests post to loopback.
+    allow_private_targets: bool,
+}
+
+impl NotificationWorker {
+    pub fn new(store: Arc<dyn Store>) -> Self {
+        Self {
+            store,
+            allow_private_targets: false,
+        }
+    }
+
+    #[cfg(test)]
+    fn allowing_private_targets(mut self) -> Self {
+        self.allow_private_targets = true;
+        self
+    }
+
+    pub fn spawn(self, shutdown: ShutdownSignal) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = shutdown.wait() => break,
+                    _ = tick.tick() => {}
+                }
+                if let Err(err) = self.drain_once(now_unix(), &shutdown).await {
+                    warn!(error = %err, "notification delivery pass failed");
+                }
+            }
+            tracing::debug!("notification worker stopped");
+        })
+    }
+
+    /// Attempt every due event once. Events still backing off are not
+    /// listed, so they never hold up due ones, and deliveries run
+    /// concurrently so a slow target does not delay the rest. No new
+    /// attempt starts once shutdown is triggered. Returns the number of
+    /// events attempted.
+    async fn drain_once(&self, now: u64, shutdown: &ShutdownSignal) -> Result<usize, String> {
+        if shutdown.is_triggered() {
+            return Ok(0);
+        }
+        let events = self.store.list_undelivered_notification_events(
+            MAX_DELIVERY_ATTEMPTS,
+            now,
+            BATCH_SIZE,
+        )?;
+        let mut deliveries = Vec::new();
+        for event in &events {
+            match event.target.as_deref().filter(|t| is_webhook_url(t)) {
+                Some(target) => deliveries.push(self.deliver(target, event, now)),
+                // Not a webhook target; nothing to send.
+                None => self.store.record_notification_attempt(
+                    &event.notification_id,
+                    Some(now),
+                    now,
+                )?,
+            }
+        }
+        let attempted = deliveries.len();
+        for result in futures::future::join_all(deliveries).await {
+            result?;
+        }
+        Ok(attempted)
+    }
+
+    /// Make one delivery attempt and record it. A failed attempt is retried
+    /// a backoff after `now`, the time of this attempt.
+    async fn deliver(
+        &self,
+        target: &str,
+        event: &NotificationEvent,
+        now: u64,
+    ) -> Result<(), String> {
+        let attempts = event.attempts + 1;
+        let delivered = match self.post(target, event).await {
+            Ok(()) => Some(now_unix()),
+            Err(err) => {
+                if attempts >= MAX_DELIVERY_ATTEMPTS {
+                    warn!(
+                        notification_id = %event.notification_id,
+                        %target,
+                        attempts,
+                        error = %err,
+                        "giving up on webhook notification"
+                    );
+                } else {
+                    tracing::debug!(
+                        notification_id = %event.notification_id,
+                        %target,
+                        attempts,
+                        error = %err,
+                        "webhook delivery failed; will retry"
+                    );
+                }
+                None
+            }
+        };
+        let retry_at = now.saturating_add(backoff_secs(attempts));
+        self.store
+            .record_notification_attempt(&event.notification_id, delivered, retry_at)
+    }
+
+    /// POST `event` to `target`, connecting only to the public addresses
+    /// its host resolves to. Redirects are not followed.
+    async fn post(&self, target: &str, event: &NotificationEvent) -> Result<(), String> {
+        let url = url::Url::parse(target).map_err(|e| format!("invalid webhook url: {e}"))?;
+        let mut builder = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none());
+        if !self.allow_private_targets {
+            let addrs = resolve_webhook_target(&url).await?;
+            builder = builder.resolve_to_addrs(url.host_str().unwrap_or_default(), &addrs);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| format!("build webhook http client: {e}"))?;
+        let body = event.payload.clone().unwrap_or_else(|| event_body(event));
+        let response = client
+            .post(url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("status {}", response.status()))
+        }
+    }
+}
+
+/// Spawn the notification delivery worker.
+pub fn spawn_notification_worker(
+    store: Arc<dyn Store>,
+    shutdown: ShutdownSignal,
+) -> JoinHandle<()> {
+    NotificationWorker::new(store).spawn(shutdown)
+}
+
+fn event_body(event: &NotificationEvent) -> Value {
+    json!({
+        "notification_id": event.notification_id,
+        "title": event.title,
+        "body": event.body,
+        "created_at": event.created_at,
+    })
+}
+
+fn backoff_secs(attempts: u32) -> u64 {
+    let exp = attempts.saturating_sub(1).min(16);
+    BASE_BACKOFF_SECS
+        .saturating_mul(1u64 << exp)
+        .min(MAX_BACKOFF_SECS)
+}
+
+fn now_unix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::Json;
+    use tokio::sync::mpsc;
+
+    async fn serve_hook(status: StatusCode) -> (String, mpsc::Receiver<Value>) {
+        let (tx, rx) = mpsc::channel::<Value>(8);
+        let app = axum::Router::new().route(
+            "/hook",
+            post(move |Json(body): Json<Value>| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(body).await;
+                    status
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{addr}/hook"), rx)
+    }
+
+    fn queue(store: &Arc<dyn Store>, id: &str, target: &str, created_at: u64) {
+        store
+            .insert_notification_event(&NotificationEvent {
+                notification_id: id.into(),
+                title: "Turn completed".into(),
+                body: "done".into(),
+                target: Some(target.into()),
+                created_at,
+                payload: Some(json!({ "turn_id": id })),
+                attempts: 0,
+                delivered_at: None,
+            })
+            .unwrap();
+    }
+
+    fn event(store: &Arc<dyn Store>, id: &str) -> NotificationEvent {
+        store
+            .list_notification_events(100)
+            .unwrap()
+            .into_iter()
+            .find(|e| e.notification_id == id)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_payload_once_and_marks_delivered() {
+        let (url, mut rx) = serve_hook(StatusCode::OK).await;
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        queue(&store, "turn-1", &url, now_unix());
+        let worker = NotificationWorker::new(store.clone()).allowing_private_targets();
+        let shutdown = ShutdownSignal::new();
+
+        assert_eq!(worker.drain_once(now_unix(), &shutdown).await.unwrap(), 1);
+        assert_eq!(rx.recv().await.unwrap(), json!({ "turn_id": "turn-1" }));
+        let delivered = event(&store, "turn-1");
+        assert_eq!(delivered.attempts, 1);
+        assert!(delivered.delivered_at.is_some());
+
+        // A fresh worker (e.g. after restart) does not send it again.
+        let worker = NotificationWorker::new(store.clone()).allowing_private_targets();
+        assert_eq!(worker.drain_once(now_unix(), &shutdown).await.unwrap(), 0);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_delivery_backs_off_then_gives_up() {
+        let (url, mut rx) = serve_hook(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        let created_at = 1_000;
+        queue(&store, "turn-1", &url, created_at);
+        let worker = NotificationWorker::new(store.clone()).allowing_private_targets();
+        let shutdown = ShutdownSignal::new();
+
+        assert_eq!(worker.drain_once(created_at, &shutdown).await.unwrap(), 1);
+        assert_eq!(event(&store, "turn-1").attempts, 1);
+        // Not due again until the first backoff has elapsed.
+        assert_eq!(
+            worker.drain_once(created_at + 1, &shutdown).await.unwrap(),
+            0
+        );
+
+        let mut now = created_at;
+        for _ in 1..MAX_DELIVERY_ATTEMPTS {
+            now += MAX_BACKOFF_SECS;
+            assert_eq!(worker.drain_once(now, &shutdown).await.unwrap(), 1);
+        }
+        let exhausted = event(&store, "turn-1");
+        assert_eq!(exhausted.attempts, MAX_DELIVERY_ATTEMPTS);
+        assert_eq!(exhausted.delivered_at, None);
+        assert_eq!(
+            worker
+                .drain_once(now + MAX_BACKOFF_SECS, &shutdown)
+                .await
+                .unwrap(),
+            0
+        );
+
+        let mut received = 0;
+        while rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, MAX_DELIVERY_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn retries_wait_from_the_last_attempt_without_blocking_due_events() {
+        let (failing, mut failing_rx) = serve_hook(StatusCode::INTERNAL_SERVER_ERROR).await;
+        tokio::spawn(async move { while failing_rx.recv().await.is_some() {} });
+        let (ok, mut ok_rx) = serve_hook(StatusCode::OK).await;
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        for n in 0..BATCH_SIZE {
+            queue(&store, &format!("failing-{n}"), &failing, 1_000);
+        }
+        let worker = NotificationWorker::new(store.clone()).allowing_private_targets();
+        let shutdown = ShutdownSignal::new();
+
+        // First attempted long after the event was created: the retry waits
+        // a full backoff from this attempt.
+        let first = 5_000;
+        assert_eq!(
+            worker.drain_once(first, &shutdown).await.unwrap(),
+            BATCH_SIZE
+        );
+        // A newer event is delivered while a full batch is backing off.
+        queue(&store, "ok", &ok, first + 1);
+        assert_eq!(worker.drain_once(first + 1, &shutdown).await.unwrap(), 1);
+        assert_eq!(ok_rx.recv().await.unwrap(), json!({ "turn_id": "ok" }));
+
+        let retry_at = first + backoff_secs(1);
+        assert_eq!(worker.drain_once(retry_at - 1, &shutdown).await.unwrap(), 0);
+        assert_eq!(
+            worker.drain_once(retry_at, &shutdown).await.unwrap(),
+            BATCH_SIZE
+        );
+        assert_eq!(event(&store, "failing-0").attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn stops_on_shutdown() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        queue(&store, "turn-1", "http://127.0.0.1:9/hook", now_unix());
+        let worker = NotificationWorker::new(store.clone()).allowing_private_targets();
+        let shutdown = ShutdownSignal::new();
+        shutdown.trigger();
+        assert_eq!(worker.drain_once(now_unix(), &shutdown).await.unwrap(), 0);
+        assert_eq!(event(&store, "turn-1").attempts, 0);
+
+        let handle = NotificationWorker::new(store).spawn(shutdown);
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("worker exits on shutdown")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn private_targets_are_not_delivered() {
+        let (url, mut rx) = serve_hook(StatusCode::OK).await;
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        queue(&store, "turn-1", &url, now_unix());
+        let worker = NotificationWorker::new(store.clone());
+        let shutdown = ShutdownSignal::new();
+
+        assert_eq!(worker.drain_once(now_unix(), &shutdown).await.unwrap(), 1);
+        let failed = event(&store, "turn-1");
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.delivered_at, None);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        assert_eq!(backoff_secs(1), BASE_BACKOFF_SECS);
+        assert_eq!(backoff_secs(2), BASE_BACKOFF_SECS * 2);
+        assert_eq!(backoff_secs(3), BASE_BACKOFF_SECS * 4);
+        assert_eq!(backoff_secs(30), MAX_BACKOFF_SECS);
+    }
+}
@@ -1,5 +1,9 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
 use axum::extract::ws::Message as WsMessage;
 use serde_json::Value;
+use tokio::sync::{mpsc, oneshot, Notify};
 
 /// Messages emitted by services to the WS connection loop.
 #[derive(Debug)]
@@ -22,4 +26,292 @@ impl OutboundMessage {
             params,
         }
     }
+
+    /// The event topic; empty for raw frames.
+    fn topic(&self) -> &str {
+        match self {
+            Self::Event { topic, .. } => topic,
+            Self::Raw(_) => "",
+        }
+    }
+}
+
+/// What a connection does with outbound events once `capacity` messages
+/// are already queued for its socket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued event to make room.
+    DropOldest,
+    /// Discard the new event.
+    #[default]
+    DropNewest,
+    /// Close the connection so the client reconnects and resyncs.
+    Disconnect,
+}
+
+impl OverflowPolicy {
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "drop_oldest" => Some(Self::DropOldest),
+            "drop_newest" => Some(Self::DropNewest),
+            "disconnect" => Some(Self::Disconnect),
+            _ => None,
+        }
+    }
+}
+
+/// Next step for the connection's socket writer.
+#[derive(Debug)]
+pub(crate) enum Outbound {
+    Message(OutboundMessage),
+    /// The queue overflowed under `OverflowPolicy::Disconnect`.
+    Overflow,
+}
+
+/// Receiving end of a connection's outbound queue.
+///
+/// Services keep sending into a plain `mpsc::Sender`; a relay task moves
+/// messages into this queue as they arrive and applies the overflow policy
+/// there, so a slow socket never leaves services facing a full channel.
+/// Raw frames (PTY output) are never dropped: the relay waits for room
+/// instead, as a bounded channel would.
+pub(crate) struct OutboundQueue {
+    shared: Arc<QueueShared>,
+    _stop: oneshot::Sender<()>,
+}
+
+struct QueueShared {
+    state: Mutex<QueueState>,
+    /// Signals the writer that a message (or overflow) is ready.
+    ready: Notify,
+    /// Signals the relay that the writer took a message.
+    room: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    messages: VecDeque<OutboundMessage>,
+    overflowed: bool,
+    closed: bool,
+}
+
+/// Create the sender services push into and the queue the connection loop
+/// reads. `on_drop` runs once per discarded event, with its topic.
+pub(crate) fn outbound_queue(
+    capacity: usize,
+    policy: OverflowPolicy,
+    on_drop: impl Fn(&str) + Send + 'static,
+) -> (mpsc::Sender<OutboundMessage>, OutboundQueue) {
+    let capacity = capacity.max(1);
+    let (tx, mut rx) = mpsc::channel::<OutboundMessage>(capacity);
+    let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+    let shared = Arc::new(QueueShared {
+        state: Mutex::new(QueueState::default()),
+        ready: Notify::new(),
+        room: Notify::new(),
+    });
+    let relay = shared.clone();
+    tokio::spawn(async move {
+        loop {
+            let msg = tokio::select! {
+                msg = rx.recv() => msg,
+                _ = &mut stop_rx => break,
+            };
+            let Some(mut msg) = msg else {
+                break;
+            };
+            loop {
+                let room = relay.room.notified();
+                match relay.push(msg, capacity, policy, &on_drop) {
+                    Push::Done => break,
+                    Push::Overflow => return,
+                    Push::Wait(back) => msg = back,
+                }
+                tokio::select! {
+                    _ = room => {}
+                    _ = &mut stop_rx => return,
+                }
+            }
+        }
+        relay.lock().closed = true;
+        relay.ready.notify_one();
+    });
+    (
+        tx,
+        OutboundQueue {
+            shared,
+            _stop: stop_tx,
+        },
+    )
+}
+
+enum Push {
+    Done,
+    /// The queue is full of frames that must not be dropped; retry once the
+    /// writer makes room.
+    Wait(OutboundMessage),
+    Overflow,
+}
+
+impl QueueShared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(
+        &self,
+        msg: OutboundMessage,
+        capacity: usize,
+        policy: OverflowPolicy,
+        on_drop: &impl Fn(&str),
+    ) -> Push {
+        let mut state = self.lock();
+        if state.messages.len() >= capacity {
+            if matches!(msg, OutboundMessage::Raw(_)) {
+                return Push::Wait(msg);
+            }
+            match policy {
+                OverflowPolicy::DropNewest => {
+                    on_drop(msg.topic());
+                    return Push::Done;
+                }
+                OverflowPolicy::DropOldest => {
+                    let oldest = state
+                        .messages
+                        .iter()
+                        .position(|m| matches!(m, OutboundMessage::Event { .. }));
+                    let Some(dropped) = oldest.and_then(|idx| state.messages.remove(idx)) else {
+                        return Push::Wait(msg);
+                    };
+                    on_drop(dropped.topic());
+                }
+                OverflowPolicy::Disconnect => {
+                    on_drop(msg.topic());
+                    state.overflowed = true;
+                    drop(state);
+                    self.ready.notify_one();
+                    return Push::Overflow;
+                }
+            }
+        }
+        state.messages.push_back(msg);
+        drop(state);
+        self.ready.notify_one();
+        Push::Done
+    }
+}
+
+impl OutboundQueue {
+    /// The next message to write, `Outbound::Overflow` once a
+    /// `Disconnect` queue overflowed, or `None` when every sender is gone.
+    pub(crate) async fn recv(&mut self) -> Option<Outbound> {
+        loop {
+            let ready = self.shared.ready.notified();
+            {
+                let mut state = self.shared.lock();
+                if state.overflowed {
+                    return Some(Outbound::Overflow);
+                }
+                if let Some(msg) = state.messages.pop_front() {
+                    drop(state);
+                    self.shared.room.notify_one();
+                    return Some(Outbound::Message(msg));
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            ready.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+
+    fn event(n: u64) -> OutboundMessage {
+        OutboundMessage::event("chat.message.delta", Some(serde_json::json!({ "n": n })))
+    }
+
+    fn event_n(item: Option<Outbound>) -> u64 {
+        match item {
+            Some(Outbound::Message(OutboundMessage::Event { params, .. })) => {
+                params.unwrap()["n"].as_u64().unwrap()
+            }
+            other => panic!("expected event, got {other:?}"),
+        }
+    }
+
+    async fn fill(
+        policy: OverflowPolicy,
+        count: u64,
+    ) -> (OutboundQueue, Arc<AtomicU64>, mpsc::Sender<OutboundMessage>) {
+        let dropped = Arc::new(AtomicU64::new(0));
+        let counter = dropped.clone();
+        let (tx, queue) = outbound_queue(2, policy, move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        for n in 0..count {
+            tx.send(event(n)).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        (queue, dropped, tx)
+    }
+
+    #[tokio::test]
+    async fn drop_newest_keeps_the_first_events() {
+        let (mut queue, dropped, _tx) = fill(OverflowPolicy::DropNewest, 4).await;
+        assert_eq!(dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(event_n(queue.recv().await), 0);
+        assert_eq!(event_n(queue.recv().await), 1);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_the_latest_events() {
+        let (mut queue, dropped, _tx) = fill(OverflowPolicy::DropOldest, 4).await;
+        assert_eq!(dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(event_n(queue.recv().await), 2);
+        assert_eq!(event_n(queue.recv().await), 3);
+    }
+
+    #[tokio::test]
+    async fn disconnect_reports_overflow() {
+        let (mut queue, dropped, _tx) = fill(OverflowPolicy::Disconnect, 3).await;
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+        assert!(matches!(queue.recv().await, Some(Outbound::Overflow)));
+    }
+
+    #[tokio::test]
+    async fn raw_frames_wait_for_room_and_closing_ends_the_queue() {
+        let (tx, mut queue) = outbound_queue(1, OverflowPolicy::DropNewest, |_| {});
+        for _ in 0..3 {
+            tx.send(OutboundMessage::raw(WsMessage::Binary(vec![1].into())))
+                .await
+                .unwrap();
+        }
+        drop(tx);
+        for _ in 0..3 {
+            assert!(matches!(
+                queue.recv().await,
+                Some(Outbound::Message(OutboundMessage::Raw(_)))
+            ));
+        }
+        assert!(queue.recv().await.is_none());
+    }
+
+    #[test]
+    fn overflow_policy_labels() {
+        assert_eq!(
+            OverflowPolicy::from_label(" Drop_Oldest "),
+            Some(OverflowPolicy::DropOldest)
+        );
+        assert_eq!(
+            OverflowPolicy::from_label("disconnect"),
+            Some(OverflowPolicy::Disconnect)
+        );
+        assert_eq!(OverflowPolicy::from_label("block"), None);
+    }
 }
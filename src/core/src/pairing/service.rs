@@ -6,6 +6,7 @@ use uuid::Uuid;
 
 use homie_protocol::{error_codes, BinaryFrame, Response};
 
+use crate::authz::Scope;
 use crate::router::{ReapEvent, ServiceHandler};
 use crate::storage::{PairingRecord, PairingStatus, Store};
 
@@ -21,7 +22,7 @@ struct ApproveParams {
 }
 
 #[derive(Debug, Deserialize)]
-struct RevokeParams {
+struct PairingIdParams {
     pairing_id: String,
 }
 
@@ -88,12 +89,9 @@ impl PairingService {
             None => return Response::error(req_id, error_codes::INVALID_PARAMS, "missing params"),
         };
 
-        let mut session = match self.store.get_pairing(&params.pairing_id) {
-            Ok(Some(s)) => s,
-            Ok(None) => {
-                return Response::error(req_id, error_codes::INVALID_PARAMS, "unknown pairing")
-            }
-            Err(e) => return Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+        let mut session = match self.load_pairing(req_id, &params.pairing_id) {
+            Ok(s) => s,
+            Err(resp) => return resp,
         };
 
         if now_unix() > session.expires_at {
@@ -111,26 +109,14 @@ impl PairingService {
     }
 
     fn revoke(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
-        let params: RevokeParams = match params {
-            Some(v) => match serde_json::from_value(v) {
-                Ok(p) => p,
-                Err(e) => {
-                    return Response::error(
-                        req_id,
-                        error_codes::INVALID_PARAMS,
-                        format!("invalid params: {e}"),
-                    )
-                }
-            },
-            None => return Response::error(req_id, error_codes::INVALID_PARAMS, "missing params"),
+        let params = match parse_pairing_id(req_id, params) {
+            Ok(p) => p,
+            Err(resp) => return resp,
         };
 
-        let mut session = match self.store.get_pairing(&params.pairing_id) {
-            Ok(Some(s)) => s,
-            Ok(None) => {
-                return Response::error(req_id, error_codes::INVALID_PARAMS, "unknown pairing")
-            }
-            Err(e) => return Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+        let mut session = match self.load_pairing(req_id, &params.pairing_id) {
+            Ok(s) => s,
+            Err(resp) => return resp,
         };
 
         session.status = PairingStatus::Revoked;
@@ -142,6 +128,82 @@ impl PairingService {
         Response::success(req_id, json!({ "pairing": session }))
     }
 
+    /// Give a pending pairing a fresh TTL and nonce, so a client can show a
+    /// new code instead of starting over. Pairings whose TTL lapsed can be
+    /// refreshed until they fall out of the retention window.
+    fn refresh(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let params = match parse_pairing_id(req_id, params) {
+            Ok(p) => p,
+            Err(resp) => return resp,
+        };
+        let mut session = match self.load_pairing(req_id, &params.pairing_id) {
+            Ok(s) => s,
+            Err(resp) => return resp,
+        };
+
+        let refreshable = match session.status {
+            PairingStatus::Pending => true,
+            PairingStatus::Expired => session.approved_by.is_none(),
+            PairingStatus::Approved | PairingStatus::Revoked => false,
+        };
+        if !refreshable {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("pairing is {}", session.status.as_str()),
+            );
+        }
+        let now = now_unix();
+        if now > session.expires_at.saturating_add(self.retention_secs) {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                "pairing is past its retention window",
+            );
+        }
+
+        session.status = PairingStatus::Pending;
+        session.nonce = Uuid::new_v4().to_string();
+        session.expires_at = now.saturating_add(self.default_ttl_secs);
+        if let Err(e) = self.store.upsert_pairing(&session) {
+            return Response::error(req_id, error_codes::INTERNAL_ERROR, e);
+        }
+
+        Response::success(req_id, json!({ "pairing": session }))
+    }
+
+    fn status(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let params = match parse_pairing_id(req_id, params) {
+            Ok(p) => p,
+            Err(resp) => return resp,
+        };
+        match self.load_pairing(req_id, &params.pairing_id) {
+            Ok(session) => Response::success(req_id, json!({ "pairing": session })),
+            Err(resp) => resp,
+        }
+    }
+
+    /// Fetch a pairing, marking it expired first if its TTL has lapsed.
+    fn load_pairing(&self, req_id: Uuid, pairing_id: &str) -> Result<PairingRecord, Response> {
+        let mut session = match self.store.get_pairing(pairing_id) {
+            Ok(Some(s)) => s,
+            Ok(None) => {
+                return Err(Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    "unknown pairing",
+                ))
+            }
+            Err(e) => return Err(Response::error(req_id, error_codes::INTERNAL_ERROR, e)),
+        };
+        if expire_if_lapsed(&mut session, now_unix()) {
+            if let Err(e) = self.store.upsert_pairing(&session) {
+                return Err(Response::error(req_id, error_codes::INTERNAL_ERROR, e));
+            }
+        }
+        Ok(session)
+    }
+
     fn list(&mut self, req_id: Uuid) -> Response {
         if let Err(e) = self.store.prune_pairings(self.retention_secs) {
             return Response::error(req_id, error_codes::INTERNAL_ERROR, e);
@@ -153,11 +215,26 @@ impl PairingService {
     }
 }
 
+impl PairingService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("pairing.list", Scope::PairingRead),
+        ("pairing.status", Scope::PairingRead),
+        ("pairing.request", Scope::PairingWrite),
+        ("pairing.approve", Scope::PairingWrite),
+        ("pairing.revoke", Scope::PairingWrite),
+        ("pairing.refresh", Scope::PairingWrite),
+    ];
+}
+
 impl ServiceHandler for PairingService {
     fn namespace(&self) -> &str {
         "pairing"
     }
 
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
     fn handle_request(
         &mut self,
         id: Uuid,
@@ -169,6 +246,8 @@ impl ServiceHandler for PairingService {
             "pairing.approve" => self.approve(id, params),
             "pairing.list" => self.list(id),
             "pairing.revoke" => self.revoke(id, params),
+            "pairing.refresh" => self.refresh(id, params),
+            "pairing.status" => self.status(id, params),
             _ => Response::error(
                 id,
                 error_codes::METHOD_NOT_FOUND,
@@ -187,9 +266,135 @@ impl ServiceHandler for PairingService {
     fn shutdown(&mut self) {}
 }
 
+fn parse_pairing_id(req_id: Uuid, params: Option<Value>) -> Result<PairingIdParams, Response> {
+    match params {
+        Some(v) => serde_json::from_value(v).map_err(|e| {
+            Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("invalid params: {e}"),
+            )
+        }),
+        None => Err(Response::error(
+            req_id,
+            error_codes::INVALID_PARAMS,
+            "missing params",
+        )),
+    }
+}
+
+/// Same rule `prune_pairings` applies in bulk. Returns whether the status
+/// changed.
+fn expire_if_lapsed(session: &mut PairingRecord, now: u64) -> bool {
+    let live = matches!(
+        session.status,
+        PairingStatus::Pending | PairingStatus::Approved
+    );
+    if live && session.expires_at < now {
+        session.status = PairingStatus::Expired;
+        return true;
+    }
+    false
+}
+
 fn now_unix() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+
+    const TTL: u64 = 300;
+    const RETENTION: u64 = 3_600;
+
+    fn service() -> (PairingService, Arc<dyn Store>) {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        (PairingService::new(store.clone(), TTL, RETENTION), store)
+    }
+
+    fn insert(store: &Arc<dyn Store>, id: &str, status: PairingStatus, expires_at: u64) {
+        store
+            .upsert_pairing(&PairingRecord {
+                pairing_id: id.into(),
+                nonce: "nonce-1".into(),
+                status,
+                created_at: expires_at.saturating_sub(TTL),
+                expires_at,
+                approved_by: None,
+            })
+            .unwrap();
+    }
+
+    fn pairing(resp: Response) -> Value {
+        assert!(resp.error.is_none(), "{:?}", resp.error);
+        resp.result.expect("result")["pairing"].clone()
+    }
+
+    #[test]
+    fn status_marks_lapsed_pairing_expired() {
+        let (mut svc, store) = service();
+        let now = now_unix();
+        insert(&store, "live", PairingStatus::Pending, now + 60);
+        insert(&store, "lapsed", PairingStatus::Pending, now - 10);
+
+        let live = pairing(svc.status(Uuid::new_v4(), Some(json!({ "pairing_id": "live" }))));
+        assert_eq!(live["status"], "pending");
+        let lapsed = pairing(svc.status(Uuid::new_v4(), Some(json!({ "pairing_id": "lapsed" }))));
+        assert_eq!(lapsed["status"], "expired");
+        assert_eq!(
+            store.get_pairing("lapsed").unwrap().unwrap().status,
+            PairingStatus::Expired
+        );
+
+        let resp = svc.status(Uuid::new_v4(), Some(json!({ "pairing_id": "missing" })));
+        assert_eq!(resp.error.unwrap().code, error_codes::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn refresh_extends_pending_and_lapsed_pairings() {
+        let (mut svc, store) = service();
+        let now = now_unix();
+        insert(&store, "lapsed", PairingStatus::Pending, now - 10);
+
+        let refreshed =
+            pairing(svc.refresh(Uuid::new_v4(), Some(json!({ "pairing_id": "lapsed" }))));
+        assert_eq!(refreshed["status"], "pending");
+        assert_ne!(refreshed["nonce"], "nonce-1");
+        let expires_at = refreshed["expires_at"].as_u64().unwrap();
+        assert!(expires_at >= now + TTL);
+        assert_eq!(
+            store.get_pairing("lapsed").unwrap().unwrap().expires_at,
+            expires_at
+        );
+    }
+
+    #[test]
+    fn refresh_rejects_settled_or_stale_pairings() {
+        let (mut svc, store) = service();
+        let now = now_unix();
+        insert(&store, "approved", PairingStatus::Approved, now + 60);
+        insert(&store, "revoked", PairingStatus::Revoked, now + 60);
+        insert(
+            &store,
+            "stale",
+            PairingStatus::Pending,
+            now - RETENTION - 10,
+        );
+
+        for id in ["approved", "revoked", "stale"] {
+            let resp = svc.refresh(Uuid::new_v4(), Some(json!({ "pairing_id": id })));
+            assert_eq!(
+                resp.error.unwrap().code,
+                error_codes::INVALID_PARAMS,
+                "{id}"
+            );
+        }
+        let resp = svc.refresh(Uuid::new_v4(), None);
+        assert_eq!(resp.error.unwrap().code, error_codes::INVALID_PARAMS);
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use directories::BaseDirs;
 
@@ -68,6 +68,25 @@ pub fn homie_credentials_dir() -> Result<PathBuf, String> {
     Ok(dir)
 }
 
+/// Where chat file uploads land, readable only by the server's user.
+pub fn homie_uploads_dir() -> Result<PathBuf, String> {
+    let dir = homie_home_dir()?.join("uploads");
+    create_private_dir(&dir).map_err(|e| format!("failed to create ~/.homie/uploads: {e}"))?;
+    Ok(dir)
+}
+
+/// Create `dir` (and its parents) and restrict it to the current user on
+/// unix, tightening it if it already existed.
+pub(crate) fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
 pub fn homie_execpolicy_path() -> Result<PathBuf, String> {
     Ok(homie_home_dir()?.join("execpolicy.toml"))
 }
@@ -1,5 +1,10 @@
 mod registry;
+mod roster;
 mod service;
 
 pub use registry::{NodeInfo, NodeRegistry, NodeSnapshot};
+pub use roster::{
+    ConnectionRoster, RosterGuard, RosterMember, PRESENCE_CONNECTED_TOPIC,
+    PRESENCE_DISCONNECTED_TOPIC,
+};
 pub use service::PresenceService;
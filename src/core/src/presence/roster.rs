@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::router::ReapEvent;
+
+/// Sent to an identity's other connections when one of its clients
+/// connects or disconnects.
+pub const PRESENCE_CONNECTED_TOPIC: &str = "presence.connected";
+pub const PRESENCE_DISCONNECTED_TOPIC: &str = "presence.disconnected";
+
+/// Longest client-provided device id kept; longer ones are cut.
+const MAX_DEVICE_ID_LEN: usize = 128;
+
+/// A live client connection, as its peers see it.
+#[derive(Debug, Clone)]
+pub struct RosterMember {
+    /// Principal the connection authenticated as; peers share it.
+    pub identity: Option<String>,
+    /// Stable id the client sent in its hello, if any.
+    pub device_id: Option<String>,
+    pub client_id: String,
+    pub connected_at: u64,
+}
+
+impl RosterMember {
+    pub fn new(identity: Option<String>, device_id: Option<&str>, client_id: &str) -> Self {
+        let device_id = device_id
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| id.chars().take(MAX_DEVICE_ID_LEN).collect());
+        Self {
+            identity,
+            device_id,
+            client_id: client_id.to_string(),
+            connected_at: now_unix(),
+        }
+    }
+
+    fn to_value(&self, conn_id: Uuid) -> Value {
+        json!({
+            "connection_id": conn_id,
+            "device_id": self.device_id,
+            "client_id": self.client_id,
+            "connected_at": self.connected_at,
+        })
+    }
+}
+
+/// Every live connection, so each identity's clients can see the others.
+///
+/// Cloning is cheap: all clones share the same map. Membership changes are
+/// broadcast as events addressed to the member's identity, so only that
+/// identity's subscribed connections get them; connections without an
+/// identity are never announced.
+#[derive(Clone)]
+pub struct ConnectionRoster {
+    connections: Arc<Mutex<HashMap<Uuid, RosterMember>>>,
+    events: broadcast::Sender<ReapEvent>,
+}
+
+impl ConnectionRoster {
+    pub fn new(events: broadcast::Sender<ReapEvent>) -> Self {
+        Self {
+            connections: Arc::default(),
+            events,
+        }
+    }
+
+    /// Add `conn_id` and announce it to its identity's connections. Call it
+    /// before the connection subscribes to the event channel, so it does not
+    /// see its own arrival. The connection leaves the roster when the
+    /// returned guard is dropped.
+    pub fn join(&self, conn_id: Uuid, member: RosterMember) -> RosterGuard {
+        let params = member.to_value(conn_id);
+        let identity = member.identity.clone();
+        if let Ok(mut map) = self.connections.lock() {
+            map.insert(conn_id, member);
+        }
+        self.announce(identity, PRESENCE_CONNECTED_TOPIC, params);
+        RosterGuard {
+            roster: self.clone(),
+            conn_id,
+        }
+    }
+
+    fn leave(&self, conn_id: Uuid) {
+        let Some(member) = self
+            .connections
+            .lock()
+            .ok()
+            .and_then(|mut map| map.remove(&conn_id))
+        else {
+            return;
+        };
+        let params = member.to_value(conn_id);
+        self.announce(member.identity, PRESENCE_DISCONNECTED_TOPIC, params);
+    }
+
+    fn announce(&self, identity: Option<String>, topic: &str, params: Value) {
+        if let Some(identity) = identity {
+            let _ = self
+                .events
+                .send(ReapEvent::for_identity(topic, Some(params), identity));
+        }
+    }
+
+    /// Connections sharing `identity`, oldest first.
+    pub fn members(&self, identity: Option<&str>) -> Vec<Value> {
+        let Some(identity) = identity else {
+            return Vec::new();
+        };
+        let Ok(map) = self.connections.lock() else {
+            return Vec::new();
+        };
+        let mut members: Vec<(&Uuid, &RosterMember)> = map
+            .iter()
+            .filter(|(_, member)| member.identity.as_deref() == Some(identity))
+            .collect();
+        members.sort_by_key(|(_, member)| member.connected_at);
+        members
+            .into_iter()
+            .map(|(conn_id, member)| member.to_value(*conn_id))
+            .collect()
+    }
+}
+
+/// Keeps a connection on the roster; dropping it announces the disconnect.
+pub struct RosterGuard {
+    roster: ConnectionRoster,
+    conn_id: Uuid,
+}
+
+impl Drop for RosterGuard {
+    fn drop(&mut self) {
+        self.roster.leave(self.conn_id);
+    }
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::SubscriptionManager;
+
+    /// Next event `subs` would deliver from `rx`.
+    fn recv_delivered(
+        rx: &mut broadcast::Receiver<ReapEvent>,
+        subs: &SubscriptionManager,
+    ) -> Option<(String, Value)> {
+        while let Ok(event) = rx.try_recv() {
+            if subs.delivers(&event) {
+                return Some((event.topic, event.params.unwrap_or_default()));
+            }
+        }
+        None
+    }
+
+    fn watcher(identity: &str) -> SubscriptionManager {
+        let mut subs = SubscriptionManager::new().with_identity(Some(identity.to_string()));
+        subs.subscribe("presence.*");
+        subs
+    }
+
+    #[test]
+    fn peers_with_the_same_identity_see_joins_and_leaves() {
+        let (events, _) = broadcast::channel(8);
+        let roster = ConnectionRoster::new(events.clone());
+        let laptop = Uuid::new_v4();
+        let _laptop = roster.join(
+            laptop,
+            RosterMember::new(Some("alice".into()), Some("laptop"), "homie-web/1"),
+        );
+        let _other = roster.join(
+            Uuid::new_v4(),
+            RosterMember::new(Some("bob".into()), None, "homie-web/1"),
+        );
+        let mut laptop_rx = events.subscribe();
+        let mut other_rx = events.subscribe();
+        let phone = Uuid::new_v4();
+        let phone_guard = roster.join(
+            phone,
+            RosterMember::new(Some("alice".into()), Some(" phone "), "homie-mobile/1"),
+        );
+        let _anonymous = roster.join(Uuid::new_v4(), RosterMember::new(None, None, "cli"));
+
+        let (topic, params) =
+            recv_delivered(&mut laptop_rx, &watcher("alice")).expect("connected event");
+        assert_eq!(topic, PRESENCE_CONNECTED_TOPIC);
+        assert_eq!(params["device_id"], "phone");
+        assert_eq!(params["connection_id"], json!(phone));
+        assert_eq!(roster.members(Some("alice")).len(), 2);
+        assert!(roster.members(None).is_empty());
+
+        drop(phone_guard);
+        let (topic, params) =
+            recv_delivered(&mut laptop_rx, &watcher("alice")).expect("disconnected event");
+        assert_eq!(topic, PRESENCE_DISCONNECTED_TOPIC);
+        assert_eq!(params["device_id"], "phone");
+        assert_eq!(roster.members(Some("alice")).len(), 1);
+        assert!(recv_delivered(&mut laptop_rx, &watcher("alice")).is_none());
+
+        assert!(recv_delivered(&mut other_rx, &watcher("bob")).is_none());
+    }
+}
@@ -6,9 +6,11 @@ use uuid::Uuid;
 
 use homie_protocol::{error_codes, BinaryFrame, Response, ServiceCapability};
 
+use crate::authz::Scope;
 use crate::router::{ReapEvent, ServiceHandler};
 
 use super::registry::{NodeInfo, NodeRegistry};
+use super::roster::ConnectionRoster;
 
 #[derive(Debug, Deserialize)]
 struct RegisterParams {
@@ -32,6 +34,8 @@ struct UnregisterParams {
 pub struct PresenceService {
     registry: Arc<Mutex<NodeRegistry>>,
     registered: Vec<String>,
+    /// Live connections, and the identity whose roster this connection sees.
+    roster: Option<(ConnectionRoster, Option<String>)>,
 }
 
 impl PresenceService {
@@ -39,9 +43,17 @@ impl PresenceService {
         Self {
             registry,
             registered: Vec::new(),
+            roster: None,
         }
     }
 
+    /// Answer `presence.connections` from `roster`, limited to the
+    /// connections of `identity`.
+    pub fn with_roster(mut self, roster: ConnectionRoster, identity: Option<String>) -> Self {
+        self.roster = Some((roster, identity));
+        self
+    }
+
     fn register(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
         let params: RegisterParams = match params {
             Some(v) => match serde_json::from_value(v) {
@@ -144,6 +156,25 @@ impl PresenceService {
         let nodes = registry.list();
         Response::success(req_id, json!({ "nodes": nodes }))
     }
+
+    fn connections(&self, req_id: Uuid) -> Response {
+        let connections = self
+            .roster
+            .as_ref()
+            .map(|(roster, identity)| roster.members(identity.as_deref()))
+            .unwrap_or_default();
+        Response::success(req_id, json!({ "connections": connections }))
+    }
+}
+
+impl PresenceService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("presence.list", Scope::PresenceRead),
+        ("presence.connections", Scope::PresenceRead),
+        ("presence.register", Scope::PresenceWrite),
+        ("presence.heartbeat", Scope::PresenceWrite),
+        ("presence.unregister", Scope::PresenceWrite),
+    ];
 }
 
 impl ServiceHandler for PresenceService {
@@ -151,6 +182,10 @@ impl ServiceHandler for PresenceService {
         "presence"
     }
 
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
     fn handle_request(
         &mut self,
         id: Uuid,
@@ -162,6 +197,7 @@ impl ServiceHandler for PresenceService {
             "presence.heartbeat" => self.heartbeat(id, params),
             "presence.unregister" => self.unregister(id, params),
             "presence.list" => self.list(id),
+            "presence.connections" => self.connections(id),
             _ => Response::error(
                 id,
                 error_codes::METHOD_NOT_FOUND,
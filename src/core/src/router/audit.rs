@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::storage::{AuditEntry, Store};
+
+/// Entries waiting to be written before new ones are dropped.
+const AUDIT_QUEUE_CAPACITY: usize = 4096;
+/// Most entries written in one transaction.
+const AUDIT_BATCH_SIZE: usize = 256;
+
+enum AuditMessage {
+    Entry(AuditEntry),
+    /// Answered once every entry queued before it is written.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Writes audit entries from a background task, so requests never wait on
+/// the database. Entries queued while a write is in flight are written
+/// together in one transaction. The task ends once every handle is dropped
+/// and the queue is drained.
+#[derive(Clone)]
+pub struct AuditLog {
+    store: Arc<dyn Store>,
+    tx: mpsc::Sender<AuditMessage>,
+}
+
+impl AuditLog {
+    /// Start the writer for `store`. Must be called inside a Tokio runtime.
+    pub fn spawn(store: Arc<dyn Store>) -> Self {
+        let (tx, rx) = mpsc::channel(AUDIT_QUEUE_CAPACITY);
+        tokio::spawn(write_entries(store.clone(), rx));
+        Self { store, tx }
+    }
+
+    pub fn store(&self) -> &Arc<dyn Store> {
+        &self.store
+    }
+
+    /// Queue `entry`. It is dropped, with a warning, when the writer has
+    /// fallen too far behind.
+    pub fn record(&self, entry: AuditEntry) {
+        if let Err(err) = self.tx.try_send(AuditMessage::Entry(entry)) {
+            let method = match err.into_inner() {
+                AuditMessage::Entry(entry) => entry.method,
+                AuditMessage::Flush(_) => String::new(),
+            };
+            tracing::warn!(method, "audit log queue full; entry dropped");
+        }
+    }
+
+    /// Wait until every entry recorded so far is written.
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(AuditMessage::Flush(done_tx)).await.is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+async fn write_entries(store: Arc<dyn Store>, mut rx: mpsc::Receiver<AuditMessage>) {
+    let mut batch = Vec::new();
+    let mut flushes = Vec::new();
+    while let Some(message) = rx.recv().await {
+        let mut next = Some(message);
+        while let Some(message) = next.take() {
+            match message {
+                AuditMessage::Entry(entry) => batch.push(entry),
+                AuditMessage::Flush(done) => flushes.push(done),
+            }
+            if batch.len() < AUDIT_BATCH_SIZE {
+                next = rx.try_recv().ok();
+            }
+        }
+        if !batch.is_empty() {
+            let entries = std::mem::take(&mut batch);
+            let store = store.clone();
+            let written =
+                tokio::task::spawn_blocking(move || store.insert_audit_entries(&entries)).await;
+            match written {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("audit log write failed: {e}"),
+                Err(e) => tracing::warn!("audit log writer panicked: {e}"),
+            }
+        }
+        for done in flushes.drain(..) {
+            let _ = done.send(());
+        }
+    }
+}
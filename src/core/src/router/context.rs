@@ -0,0 +1,103 @@
+use std::net::IpAddr;
+
+use crate::auth::{AuthOutcome, TailscaleIdentity};
+use crate::authz::{context_for_outcome, AuthContext, Role};
+use crate::config::ServerConfig;
+
+/// Who is on the other end of a connection. Built once after the
+/// handshake and handed to every service the connection's router holds,
+/// so handlers can authorize and attribute actions without re-deriving
+/// them.
+#[derive(Debug, Clone)]
+pub struct ConnectionContext {
+    /// Role the connection's requests are authorized as.
+    pub role: Role,
+    /// Verified tailnet user, for Tailscale Serve connections.
+    pub identity: Option<TailscaleIdentity>,
+    /// Who actions are attributed to: a tailnet login, `api-key:<name>`,
+    /// `local`, `lan` or `open`.
+    pub principal: Option<String>,
+    /// Tool channel inferred from the client id (`web`, `mobile`, ...).
+    pub channel: Option<String>,
+    /// Peer address the WebSocket upgrade came from.
+    pub remote: Option<IpAddr>,
+}
+
+impl ConnectionContext {
+    /// A context with `role` and nothing else known about the peer.
+    pub fn new(role: Role) -> Self {
+        Self {
+            role,
+            identity: None,
+            principal: None,
+            channel: None,
+            remote: None,
+        }
+    }
+
+    /// Context for a connection that authenticated as `auth`.
+    pub fn from_auth(auth: &AuthOutcome, config: &ServerConfig) -> Self {
+        let identity = match auth {
+            AuthOutcome::Tailscale(identity) => Some(identity.clone()),
+            _ => None,
+        };
+        Self {
+            role: context_for_outcome(auth, config).role(),
+            identity,
+            principal: auth.identity_string(),
+            channel: None,
+            remote: None,
+        }
+    }
+
+    pub fn with_channel(mut self, channel: Option<String>) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn with_remote(mut self, remote: IpAddr) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// Authorization view of [`Self::role`].
+    pub fn auth(&self) -> AuthContext {
+        AuthContext::new(self.role)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tailscale_connections_carry_their_identity() {
+        let config = ServerConfig::default();
+        let auth = AuthOutcome::Tailscale(TailscaleIdentity {
+            login: "alice@example.com".into(),
+            display_name: "Alice".into(),
+            profile_pic: None,
+            tailnet: None,
+        });
+        let ctx = ConnectionContext::from_auth(&auth, &config)
+            .with_channel(Some("web".into()))
+            .with_remote("100.64.0.7".parse().unwrap());
+        assert_eq!(ctx.role, Role::User);
+        assert_eq!(ctx.principal.as_deref(), Some("alice@example.com"));
+        assert_eq!(
+            ctx.identity.as_ref().map(|id| id.login.as_str()),
+            Some("alice@example.com")
+        );
+        assert_eq!(ctx.channel.as_deref(), Some("web"));
+        assert!(ctx.remote.is_some());
+
+        let auth = AuthOutcome::ApiKey {
+            name: "ci".into(),
+            role: Role::Viewer,
+        };
+        let ctx = ConnectionContext::from_auth(&auth, &config);
+        assert_eq!(ctx.auth(), AuthContext::new(Role::Viewer));
+        assert_eq!(ctx.principal.as_deref(), Some("api-key:ci"));
+        assert!(ctx.identity.is_none());
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::outbound::OutboundMessage;
+
+/// Topic that mirrors every outbound event, for debugging event flow.
+pub const DEBUG_EVENTS_TOPIC: &str = "debug.events";
+
+/// Tee of every event the server writes to (or drops for) a connection,
+/// delivered as `debug.events` to the connections watching it.
+///
+/// Cloning is cheap: all clones share the same watchers. With no watchers
+/// `record` is a single atomic load.
+#[derive(Debug, Clone, Default)]
+pub struct DebugEventTap {
+    inner: Arc<TapInner>,
+}
+
+#[derive(Debug, Default)]
+struct TapInner {
+    enabled: bool,
+    watching: AtomicUsize,
+    watchers: RwLock<HashMap<Uuid, mpsc::Sender<OutboundMessage>>>,
+}
+
+impl DebugEventTap {
+    /// A tap that only accepts watchers when `enabled`.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            inner: Arc::new(TapInner {
+                enabled,
+                ..TapInner::default()
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled
+    }
+
+    /// Whether any connection is watching; callers check this before
+    /// gathering anything to `record`.
+    pub fn is_watched(&self) -> bool {
+        self.inner.watching.load(Ordering::Relaxed) > 0
+    }
+
+    pub(crate) fn watch(&self, conn_id: Uuid, outbound: mpsc::Sender<OutboundMessage>) {
+        if let Ok(mut watchers) = self.inner.watchers.write() {
+            watchers.insert(conn_id, outbound);
+            self.inner.watching.store(watchers.len(), Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn unwatch(&self, conn_id: Uuid) {
+        if let Ok(mut watchers) = self.inner.watchers.write() {
+            watchers.remove(&conn_id);
+            self.inner.watching.store(watchers.len(), Ordering::Relaxed);
+        }
+    }
+
+    /// Mirror one event written to (or dropped for) `conn_id`. `bytes` is
+    /// the serialized size when it is known. `debug.events` itself is never
+    /// mirrored.
+    pub fn record(&self, conn_id: Uuid, topic: &str, bytes: Option<usize>, dropped: bool) {
+        if !self.is_watched() || topic == DEBUG_EVENTS_TOPIC {
+            return;
+        }
+        let Ok(watchers) = self.inner.watchers.read() else {
+            return;
+        };
+        let params = json!({
+            "connection_id": conn_id,
+            "topic": topic,
+            "bytes": bytes,
+            "dropped": dropped,
+            "timestamp_ms": now_millis(),
+        });
+        for outbound in watchers.values() {
+            let _ = outbound.try_send(OutboundMessage::event(
+                DEBUG_EVENTS_TOPIC,
+                Some(params.clone()),
+            ));
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchers_get_mirrored_events_but_not_their_own_topic() {
+        let tap = DebugEventTap::new(true);
+        let conn = Uuid::new_v4();
+        tap.record(conn, "chat.message.delta", Some(10), false);
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let watcher = Uuid::new_v4();
+        tap.watch(watcher, tx);
+        assert!(tap.is_watched());
+        tap.record(conn, "chat.message.delta", Some(42), false);
+        tap.record(conn, "chat.turn.completed", None, true);
+        tap.record(watcher, DEBUG_EVENTS_TOPIC, Some(99), false);
+
+        let mut seen = Vec::new();
+        while let Ok(OutboundMessage::Event { topic, params }) = rx.try_recv() {
+            assert_eq!(topic, DEBUG_EVENTS_TOPIC);
+            seen.push(params.unwrap());
+        }
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0]["topic"], "chat.message.delta");
+        assert_eq!(seen[0]["bytes"], 42);
+        assert_eq!(seen[0]["connection_id"], json!(conn));
+        assert_eq!(seen[1]["dropped"], true);
+
+        tap.unwatch(watcher);
+        assert!(!tap.is_watched());
+    }
+}
@@ -1,47 +1,207 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use serde_json::Value;
+use tracing::Instrument;
 use uuid::Uuid;
 
-use homie_protocol::{error_codes, BinaryFrame, Response};
+use homie_protocol::{error_codes, BinaryFrame, Response, StreamType};
 
+use super::audit::AuditLog;
+use super::context::ConnectionContext;
 use super::handler::{ReapEvent, ServiceHandler};
+use super::metrics::MetricsRegistry;
+use super::timeout::RequestTimeouts;
+use crate::authz::{AuthContext, MethodPolicy, Role, Scope};
+use crate::storage::{AuditEntry, Store};
+
+/// Params checked, in order, for the id a mutating request acts on.
+const AUDIT_TARGET_KEYS: &[&str] = &[
+    "chat_id",
+    "thread_id",
+    "session_id",
+    "job_id",
+    "cron_id",
+    "pairing_id",
+    "id",
+];
 
 /// Routes RPC requests to the correct service handler based on method prefix.
 ///
 /// Method names use `service.method` convention (e.g. "terminal.session.start").
 /// The router extracts the first dotted segment as the namespace and delegates
-/// to the matching `ServiceHandler`.
+/// to the matching `ServiceHandler`, after checking the connection's role
+/// against the `MethodPolicy` its services contribute to.
 pub struct MessageRouter {
     /// namespace → handler
     services: HashMap<String, Box<dyn ServiceHandler>>,
+    /// Shared per-method request metrics.
+    metrics: MetricsRegistry,
+    policy: MethodPolicy,
+    context: Arc<ConnectionContext>,
+    /// Where mutating requests are audited; `None` disables the audit log.
+    audit: Option<AuditLog>,
+    /// Terminal sessions whose binary stdin was already audited, with the
+    /// outcome; a session's keystrokes are logged once per outcome.
+    stdin_audited: HashSet<(Uuid, &'static str)>,
+    /// Deadlines handlers must answer within; none by default.
+    timeouts: RequestTimeouts,
 }
 
 impl MessageRouter {
+    /// A router for an owner connection; see [`MessageRouter::with_context`].
     pub fn new() -> Self {
         Self {
             services: HashMap::new(),
+            metrics: MetricsRegistry::new(),
+            policy: MethodPolicy::new(),
+            context: Arc::new(ConnectionContext::new(Role::Owner)),
+            audit: None,
+            stdin_audited: HashSet::new(),
+            timeouts: RequestTimeouts::default(),
         }
     }
 
-    /// Register a service handler. The handler's `namespace()` is used as key.
-    pub fn register(&mut self, handler: Box<dyn ServiceHandler>) {
+    /// Serve the connection described by `context`: requests are
+    /// authorized as its role, and services registered afterwards are
+    /// attached to it.
+    pub fn with_context(mut self, context: ConnectionContext) -> Self {
+        self.context = Arc::new(context);
+        self
+    }
+
+    pub fn context(&self) -> &ConnectionContext {
+        &self.context
+    }
+
+    /// Record every mutating request, allowed or not, in `store`'s audit
+    /// log. Entries are written by a background task; see [`AuditLog`].
+    pub fn with_audit(mut self, store: Arc<dyn Store>) -> Self {
+        self.audit = Some(AuditLog::spawn(store));
+        self
+    }
+
+    pub fn audit_log(&self) -> Option<&AuditLog> {
+        self.audit.as_ref()
+    }
+
+    /// Answer requests whose handler outlives its deadline with
+    /// `REQUEST_TIMEOUT`.
+    pub fn with_request_timeouts(mut self, timeouts: RequestTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Use a metrics registry shared with other connections.
+    pub fn with_metrics(mut self, metrics: MetricsRegistry) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn metrics(&self) -> &MetricsRegistry {
+        &self.metrics
+    }
+
+    /// Register a service handler. The handler's `namespace()` is used as key
+    /// and its `method_scopes()` join the policy.
+    pub fn register(&mut self, mut handler: Box<dyn ServiceHandler>) {
+        handler.attach(self.context.clone());
         let ns = handler.namespace().to_string();
+        self.policy.extend(handler.method_scopes());
         self.services.insert(ns, handler);
     }
 
+    /// `FORBIDDEN` unless the connection's role may call `method`. Methods
+    /// of unregistered services pass, so `route_request` can answer them
+    /// with `METHOD_NOT_FOUND`.
+    pub fn authorize(&self, id: Uuid, method: &str) -> Result<(), Response> {
+        let known = self.policy.scope_for(method).is_some()
+            || Self::extract_namespace(method).is_some_and(|ns| self.services.contains_key(ns));
+        if !known || self.policy.allows(&self.context.auth(), method) {
+            return Ok(());
+        }
+        if self.audited(method) {
+            self.record_audit(method, None, "forbidden".into());
+        }
+        Err(forbidden(id, method, self.context.auth()))
+    }
+
+    /// Whether requests for `method` go to the audit log: everything but
+    /// read-only methods. Unlisted methods are owner-only and count as
+    /// mutating.
+    fn audited(&self, method: &str) -> bool {
+        self.audit.is_some() && self.policy.scope_for(method).is_none_or(Scope::is_mutating)
+    }
+
+    fn record_audit(&self, method: &str, target_id: Option<String>, outcome: String) {
+        let Some(audit) = self.audit.as_ref() else {
+            return;
+        };
+        let entry = AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            identity: self.context.principal.clone(),
+            role: self.context.role.label().to_string(),
+            method: method.to_string(),
+            target_id,
+            outcome,
+        };
+        audit.record(entry);
+    }
+
+    /// Audit PTY stdin written over binary frames as
+    /// `terminal.session.input`, once per session and outcome.
+    fn audit_stdin(&mut self, frame: &BinaryFrame, outcome: &'static str) {
+        if self.audit.is_none()
+            || frame.stream != StreamType::Stdin
+            || !self.stdin_audited.insert((frame.session_id, outcome))
+        {
+            return;
+        }
+        self.record_audit(
+            "terminal.session.input",
+            Some(frame.session_id.to_string()),
+            outcome.to_string(),
+        );
+    }
+
     /// Extract namespace from a dotted method name.
     /// e.g. "terminal.session.start" → "terminal"
     fn extract_namespace(method: &str) -> Option<&str> {
         method.split('.').next().filter(|s| !s.is_empty())
     }
 
-    /// Route an RPC request to the appropriate service handler.
+    /// Span wrapping one request's handling, so every log emitted while it
+    /// runs, including from tasks it spawns with the span, carries the same
+    /// `request_id`. The client's request id is adopted; a nil id gets a
+    /// fresh one.
+    pub fn request_span(id: Uuid, method: &str) -> tracing::Span {
+        let request_id = if id.is_nil() { Uuid::new_v4() } else { id };
+        tracing::info_span!("request", %request_id, method)
+    }
+
+    /// Route an RPC request to the appropriate service handler, inside its
+    /// [`MessageRouter::request_span`].
     pub async fn route_request(
         &mut self,
         id: Uuid,
         method: &str,
         params: Option<Value>,
+    ) -> Response {
+        let span = Self::request_span(id, method);
+        self.route_request_inner(id, method, params)
+            .instrument(span)
+            .await
+    }
+
+    async fn route_request_inner(
+        &mut self,
+        id: Uuid,
+        method: &str,
+        params: Option<Value>,
     ) -> Response {
         let ns = match Self::extract_namespace(method) {
             Some(ns) => ns,
@@ -54,30 +214,80 @@ impl MessageRouter {
             }
         };
 
-        // Look up the handler by namespace.
-        match self.services.get_mut(ns) {
-            Some(handler) => handler.handle_request(id, method, params).await,
-            None => Response::error(
+        // Look up the handler by namespace, then check the caller's role.
+        let audited = self.audited(method);
+        let target_id = if audited {
+            audit_target(params.as_ref())
+        } else {
+            None
+        };
+        let Some(handler) = self.services.get_mut(ns) else {
+            return Response::error(
                 id,
                 error_codes::METHOD_NOT_FOUND,
                 format!("unknown service: {ns}"),
-            ),
+            );
+        };
+        if !self.policy.allows(&self.context.auth(), method) {
+            if audited {
+                self.record_audit(method, target_id, "forbidden".into());
+            }
+            return forbidden(id, method, self.context.auth());
+        }
+        let started = Instant::now();
+        let handled = handler.handle_request(id, method, params);
+        let mutating = self.policy.scope_for(method).is_none_or(Scope::is_mutating);
+        let resp = match self.timeouts.for_method(method, mutating) {
+            // Dropping the handler's future on timeout cancels whatever it
+            // was awaiting; work it already spawned keeps running.
+            Some(limit) => match tokio::time::timeout(limit, handled).await {
+                Ok(resp) => resp,
+                Err(_) => {
+                    tracing::warn!(
+                        method,
+                        timeout_ms = limit.as_millis() as u64,
+                        "request timed out"
+                    );
+                    Response::error(
+                        id,
+                        error_codes::REQUEST_TIMEOUT,
+                        format!("{method} did not finish within {}ms", limit.as_millis()),
+                    )
+                }
+            },
+            None => handled.await,
+        };
+        self.metrics
+            .record(method, started.elapsed(), resp.error.is_some());
+        if audited {
+            let outcome = match &resp.error {
+                Some(error) => format!("error:{}", error.code),
+                None => "ok".to_string(),
+            };
+            self.record_audit(method, target_id, outcome);
         }
+        resp
     }
 
-    /// Route a binary frame. Currently uses the "terminal" service for all
-    /// binary frames (PTY stdin). Future services may use a header byte to
-    /// disambiguate.
+    /// Route a binary frame by its stream byte: upload chunks go to the
+    /// "chat" service, everything else (PTY stdin) to "terminal".
     pub fn route_binary(&mut self, frame: &BinaryFrame) {
-        // Binary frames are currently always PTY data → terminal service.
-        // Future: could add a service discriminator to the frame header.
-        if let Some(handler) = self.services.get_mut("terminal") {
+        self.audit_stdin(frame, "ok");
+        let ns = binary_namespace(frame);
+        if let Some(handler) = self.services.get_mut(ns) {
             handler.handle_binary(frame);
         } else {
-            tracing::debug!("no terminal service registered for binary frame");
+            tracing::debug!(namespace = ns, "no service registered for binary frame");
         }
     }
 
+    /// Drop a binary frame the connection's role may not send, auditing
+    /// refused PTY stdin.
+    pub fn refuse_binary(&mut self, frame: &BinaryFrame) {
+        tracing::debug!(stream = ?frame.stream, "unauthorized binary frame ignored");
+        self.audit_stdin(frame, "forbidden");
+    }
+
     /// Poll all services for reap events.
     pub fn reap_all(&mut self) -> Vec<ReapEvent> {
         let mut events = Vec::new();
@@ -100,6 +310,35 @@ impl MessageRouter {
     }
 }
 
+fn forbidden(id: Uuid, method: &str, auth: AuthContext) -> Response {
+    tracing::debug!(method, role = ?auth.role(), "method forbidden for role");
+    Response::error(
+        id,
+        error_codes::FORBIDDEN,
+        format!("{method} is not allowed for this role"),
+    )
+}
+
+/// The id a request acts on, from the first of `AUDIT_TARGET_KEYS` it sets.
+fn audit_target(params: Option<&Value>) -> Option<String> {
+    let params = params?;
+    AUDIT_TARGET_KEYS
+        .iter()
+        .find_map(|key| match params.get(*key)? {
+            Value::String(id) => Some(id.clone()),
+            Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        })
+}
+
+/// Service namespace that handles `frame`.
+fn binary_namespace(frame: &BinaryFrame) -> &'static str {
+    match frame.stream {
+        StreamType::Upload => "chat",
+        StreamType::Stdout | StreamType::Stderr | StreamType::Stdin => "terminal",
+    }
+}
+
 impl Default for MessageRouter {
     fn default() -> Self {
         Self::new()
@@ -109,9 +348,11 @@ impl Default for MessageRouter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use homie_protocol::{error_codes, StreamType};
+    use crate::authz::Scope;
+    use homie_protocol::error_codes;
     use serde_json::json;
     use std::pin::Pin;
+    use std::time::Duration;
 
     /// Minimal stub service for testing the router.
     struct StubService {
@@ -119,6 +360,7 @@ mod tests {
         last_method: Option<String>,
         binary_count: usize,
         reap_events: Vec<ReapEvent>,
+        context: Option<Arc<ConnectionContext>>,
     }
 
     impl StubService {
@@ -128,6 +370,7 @@ mod tests {
                 last_method: None,
                 binary_count: 0,
                 reap_events: vec![],
+                context: None,
             }
         }
     }
@@ -137,6 +380,19 @@ mod tests {
             &self.ns
         }
 
+        fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+            &[
+                ("terminal.session.list", Scope::TerminalRead),
+                ("terminal.session.start", Scope::TerminalWrite),
+                ("terminal.session.hang", Scope::TerminalRead),
+                ("terminal.input.hang", Scope::TerminalWrite),
+            ]
+        }
+
+        fn attach(&mut self, ctx: Arc<ConnectionContext>) {
+            self.context = Some(ctx);
+        }
+
         fn handle_request(
             &mut self,
             id: Uuid,
@@ -144,7 +400,12 @@ mod tests {
             _params: Option<Value>,
         ) -> Pin<Box<dyn std::future::Future<Output = Response> + Send + '_>> {
             self.last_method = Some(method.to_string());
-            let resp = Response::success(id, json!({ "routed_to": self.ns }));
+            if method.ends_with(".hang") {
+                return Box::pin(std::future::pending());
+            }
+            let principal = self.context.as_ref().and_then(|ctx| ctx.principal.clone());
+            let resp =
+                Response::success(id, json!({ "routed_to": self.ns, "principal": principal }));
             Box::pin(async move { resp })
         }
 
@@ -178,6 +439,35 @@ mod tests {
         assert_eq!(resp2.result.unwrap()["routed_to"], "agent");
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn handlers_past_their_deadline_time_out() {
+        let timeouts = RequestTimeouts::new(Duration::from_secs(5), HashMap::new());
+        let mut router = MessageRouter::new().with_request_timeouts(timeouts);
+        router.register(Box::new(StubService::new("terminal")));
+
+        let id = Uuid::new_v4();
+        let resp = router
+            .route_request(id, "terminal.session.hang", None)
+            .await;
+        let error = resp.error.expect("timeout error");
+        assert_eq!(error.code, error_codes::REQUEST_TIMEOUT);
+        assert_eq!(error.retryable, Some(true));
+        assert_eq!(resp.id, id);
+
+        let resp = router
+            .route_request(Uuid::new_v4(), "terminal.session.list", None)
+            .await;
+        assert!(resp.error.is_none());
+
+        // Mutating methods are not cut off halfway.
+        let routed = tokio::time::timeout(
+            Duration::from_secs(3600),
+            router.route_request(Uuid::new_v4(), "terminal.input.hang", None),
+        )
+        .await;
+        assert!(routed.is_err());
+    }
+
     #[tokio::test]
     async fn unknown_service_returns_error() {
         let mut router = MessageRouter::new();
@@ -197,6 +487,171 @@ mod tests {
         assert!(resp.error.is_some());
     }
 
+    #[tokio::test]
+    async fn routed_requests_are_recorded_in_metrics() {
+        let metrics = MetricsRegistry::new();
+        let mut router = MessageRouter::new().with_metrics(metrics.clone());
+        router.register(Box::new(StubService::new("terminal")));
+
+        router
+            .route_request(Uuid::new_v4(), "terminal.session.list", None)
+            .await;
+        router
+            .route_request(Uuid::new_v4(), "terminal.session.list", None)
+            .await;
+        router
+            .route_request(Uuid::new_v4(), "files.list", None)
+            .await;
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["methods"]["terminal.session.list"]["count"], 2);
+        assert_eq!(snapshot["methods"]["terminal.session.list"]["errors"], 0);
+        assert!(snapshot["methods"].get("files.list").is_none());
+    }
+
+    #[tokio::test]
+    async fn requests_are_checked_against_the_role_policy() {
+        let mut router = MessageRouter::new().with_context(ConnectionContext::new(Role::Viewer));
+        router.register(Box::new(StubService::new("terminal")));
+
+        let id = Uuid::new_v4();
+        let resp = router
+            .route_request(id, "terminal.session.list", None)
+            .await;
+        assert!(resp.error.is_none());
+
+        let resp = router
+            .route_request(id, "terminal.session.start", None)
+            .await;
+        assert_eq!(resp.error.unwrap().code, error_codes::FORBIDDEN);
+        let resp = router.route_request(id, "terminal.unlisted", None).await;
+        assert_eq!(resp.error.unwrap().code, error_codes::FORBIDDEN);
+        assert!(router.authorize(id, "terminal.session.start").is_err());
+
+        // Unknown services are still reported as such.
+        assert!(router.authorize(id, "files.list").is_ok());
+        let resp = router.route_request(id, "files.list", None).await;
+        assert_eq!(resp.error.unwrap().code, error_codes::METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn services_see_the_connection_context() {
+        let mut context = ConnectionContext::new(Role::User).with_channel(Some("web".into()));
+        context.principal = Some("alice@example.com".into());
+        let mut router = MessageRouter::new().with_context(context);
+        router.register(Box::new(StubService::new("terminal")));
+
+        assert_eq!(router.context().channel.as_deref(), Some("web"));
+        let resp = router
+            .route_request(Uuid::new_v4(), "terminal.session.list", None)
+            .await;
+        assert_eq!(resp.result.unwrap()["principal"], "alice@example.com");
+    }
+
+    #[tokio::test]
+    async fn mutating_requests_are_audited() {
+        let store: Arc<dyn Store> = Arc::new(crate::storage::SqliteStore::open_memory().unwrap());
+        let mut context = ConnectionContext::new(Role::Viewer);
+        context.principal = Some("api-key:ci".into());
+        let mut router = MessageRouter::new()
+            .with_context(context)
+            .with_audit(store.clone());
+        router.register(Box::new(StubService::new("terminal")));
+
+        router
+            .route_request(Uuid::new_v4(), "terminal.session.list", None)
+            .await;
+        router
+            .route_request(
+                Uuid::new_v4(),
+                "terminal.session.start",
+                Some(json!({ "session_id": "s-1" })),
+            )
+            .await;
+        assert!(router
+            .authorize(Uuid::new_v4(), "terminal.unlisted")
+            .is_err());
+        router.audit_log().unwrap().flush().await;
+
+        let entries = store.list_audit_entries(None, None, 10).unwrap();
+        let summary: Vec<(&str, Option<&str>, &str)> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.method.as_str(),
+                    e.target_id.as_deref(),
+                    e.outcome.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("terminal.unlisted", None, "forbidden"),
+                ("terminal.session.start", Some("s-1"), "forbidden"),
+            ]
+        );
+        assert_eq!(entries[0].identity.as_deref(), Some("api-key:ci"));
+        assert_eq!(entries[0].role, "viewer");
+
+        let mut router = MessageRouter::new().with_audit(store.clone());
+        router.register(Box::new(StubService::new("terminal")));
+        router
+            .route_request(
+                Uuid::new_v4(),
+                "terminal.session.start",
+                Some(json!({ "session_id": "s-2" })),
+            )
+            .await;
+        router.audit_log().unwrap().flush().await;
+        let latest = &store.list_audit_entries(None, None, 1).unwrap()[0];
+        assert_eq!(latest.target_id.as_deref(), Some("s-2"));
+        assert_eq!(latest.outcome, "ok");
+        assert_eq!(latest.role, "owner");
+    }
+
+    #[tokio::test]
+    async fn binary_stdin_is_audited_once_per_session_and_outcome() {
+        let store: Arc<dyn Store> = Arc::new(crate::storage::SqliteStore::open_memory().unwrap());
+        let mut router = MessageRouter::new().with_audit(store.clone());
+        router.register(Box::new(StubService::new("terminal")));
+        let session_id = Uuid::new_v4();
+        let frame = |stream| BinaryFrame {
+            session_id,
+            stream,
+            payload: vec![0x41],
+        };
+        router.route_binary(&frame(StreamType::Stdin));
+        router.route_binary(&frame(StreamType::Stdin));
+        router.refuse_binary(&frame(StreamType::Stdin));
+        router.route_binary(&frame(StreamType::Upload));
+        router.audit_log().unwrap().flush().await;
+
+        let entries = store.list_audit_entries(None, None, 10).unwrap();
+        let summary: Vec<(&str, Option<&str>, &str)> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.method.as_str(),
+                    e.target_id.as_deref(),
+                    e.outcome.as_str(),
+                )
+            })
+            .collect();
+        let session = session_id.to_string();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "terminal.session.input",
+                    Some(session.as_str()),
+                    "forbidden"
+                ),
+                ("terminal.session.input", Some(session.as_str()), "ok"),
+            ]
+        );
+    }
+
     #[test]
     fn binary_routes_to_terminal() {
         let mut router = MessageRouter::new();
@@ -211,13 +666,24 @@ mod tests {
         // No panic = success; the stub increments binary_count internally.
     }
 
+    #[test]
+    fn upload_chunks_route_to_chat() {
+        let frame = |stream| BinaryFrame {
+            session_id: Uuid::new_v4(),
+            stream,
+            payload: vec![0x41],
+        };
+        assert_eq!(binary_namespace(&frame(StreamType::Upload)), "chat");
+        assert_eq!(binary_namespace(&frame(StreamType::Stdin)), "terminal");
+    }
+
     #[test]
     fn reap_collects_from_all_services() {
         let mut svc = StubService::new("terminal");
-        svc.reap_events.push(ReapEvent {
-            topic: "terminal.session.exit".into(),
-            params: Some(json!({"session_id": "abc"})),
-        });
+        svc.reap_events.push(ReapEvent::new(
+            "terminal.session.exit",
+            Some(json!({"session_id": "abc"})),
+        ));
 
         let mut router = MessageRouter::new();
         router.register(Box::new(svc));
@@ -0,0 +1,259 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::handler::ReapEvent;
+use crate::shutdown::ShutdownSignal;
+use crate::storage::{BusEventRecord, Store};
+
+/// Bus events read per poll; a backlog drains over several polls.
+const BUS_BATCH: usize = 256;
+/// How long shared events stay in the store for slow nodes to catch up.
+const BUS_EVENT_RETENTION_SECS: u64 = 10 * 60;
+/// Seconds between prunes of expired bus events.
+const BUS_PRUNE_INTERVAL_SECS: u64 = 60;
+
+/// Carries server-wide events (`ReapEvent`s) between the nodes serving one
+/// deployment, so a client connected to one node sees events raised on
+/// another, e.g. a cron run's completion.
+///
+/// The event bridge (`spawn_event_bridge`) publishes every event raised
+/// locally and puts events received from other nodes on the local
+/// broadcast channel, with `origin` set. Implementations must not return a
+/// node's own events to it.
+pub trait EventBus: Send + Sync + 'static {
+    /// Share an event raised on this node with the other nodes.
+    fn publish(&self, event: &ReapEvent);
+
+    /// Events other nodes published since the previous call, oldest first.
+    fn receive(&self) -> Vec<ReapEvent>;
+}
+
+/// Single-node bus: nothing is shared, and the local broadcast channel
+/// alone delivers events.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalEventBus;
+
+impl EventBus for LocalEventBus {
+    fn publish(&self, _event: &ReapEvent) {}
+
+    fn receive(&self) -> Vec<ReapEvent> {
+        Vec::new()
+    }
+}
+
+/// Bus for nodes sharing one store: events are appended to its
+/// `bus_events` table and each node reads what the others appended since
+/// its cursor. Events older than ten minutes are pruned.
+///
+/// The store is a SQLite file, so this only spans processes on one host
+/// (e.g. a blue/green pair behind one proxy). SQLite locking is not
+/// reliable over network filesystems; nodes on different hosts need a
+/// network bus.
+pub struct StoreEventBus {
+    store: Arc<dyn Store>,
+    node_id: String,
+    state: Mutex<StoreBusState>,
+}
+
+struct StoreBusState {
+    /// Highest `seq` read so far.
+    cursor: u64,
+    last_prune: u64,
+}
+
+impl StoreEventBus {
+    /// A bus for the node `node_id`. Only events published after this call
+    /// are received.
+    pub fn new(store: Arc<dyn Store>, node_id: impl Into<String>) -> Self {
+        let cursor = store.latest_bus_event_seq().unwrap_or_else(|e| {
+            tracing::warn!("failed to read event bus position: {e}");
+            0
+        });
+        Self {
+            store,
+            node_id: node_id.into(),
+            state: Mutex::new(StoreBusState {
+                cursor,
+                last_prune: 0,
+            }),
+        }
+    }
+
+    fn prune(&self, state: &mut StoreBusState, now: u64) {
+        if now.saturating_sub(state.last_prune) < BUS_PRUNE_INTERVAL_SECS {
+            return;
+        }
+        state.last_prune = now;
+        if let Err(e) = self
+            .store
+            .prune_bus_events(now.saturating_sub(BUS_EVENT_RETENTION_SECS))
+        {
+            tracing::warn!("failed to prune event bus: {e}");
+        }
+    }
+}
+
+impl EventBus for StoreEventBus {
+    fn publish(&self, event: &ReapEvent) {
+        let record = BusEventRecord {
+            seq: 0,
+            origin: self.node_id.clone(),
+            topic: event.topic.clone(),
+            params: event.params.clone(),
+            created_at: now_unix(),
+        };
+        if let Err(e) = self.store.insert_bus_event(&record) {
+            tracing::warn!(topic = %event.topic, "failed to publish event to bus: {e}");
+        }
+    }
+
+    fn receive(&self) -> Vec<ReapEvent> {
+        let Ok(mut state) = self.state.lock() else {
+            return Vec::new();
+        };
+        self.prune(&mut state, now_unix());
+        let records = match self.store.list_bus_events_after(state.cursor, BUS_BATCH) {
+            Ok(records) => records,
+            Err(e) => {
+                tracing::warn!("failed to read event bus: {e}");
+                return Vec::new();
+            }
+        };
+        if let Some(last) = records.last() {
+            state.cursor = last.seq;
+        }
+        records
+            .into_iter()
+            .filter(|record| record.origin != self.node_id)
+            .map(|record| ReapEvent {
+                topic: record.topic,
+                params: record.params,
+                origin: Some(record.origin),
+                audience: None,
+            })
+            .collect()
+    }
+}
+
+/// Connect the local broadcast channel `event_tx` to `bus`: events raised
+/// here are published, and events from other nodes are polled every
+/// `poll_interval` and broadcast locally. Relayed events are never
+/// published again, so no node sees an event twice. Events for one
+/// identity's connections are not shared. Bus calls may block on I/O, so
+/// they run on the blocking pool.
+pub fn spawn_event_bridge(
+    bus: Arc<dyn EventBus>,
+    event_tx: broadcast::Sender<ReapEvent>,
+    poll_interval: Duration,
+    shutdown: ShutdownSignal,
+) -> JoinHandle<()> {
+    let mut local_rx = event_tx.subscribe();
+    tokio::spawn(async move {
+        let mut poll = tokio::time::interval(poll_interval.max(Duration::from_millis(10)));
+        loop {
+            tokio::select! {
+                event = local_rx.recv() => match event {
+                    Ok(event) if event.origin.is_none() && event.audience.is_none() => {
+                        let bus = bus.clone();
+                        let published =
+                            tokio::task::spawn_blocking(move || bus.publish(&event)).await;
+                        if let Err(e) = published {
+                            tracing::warn!("event bus publish task failed: {e}");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "event bridge lagged; events not shared");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = poll.tick() => {
+                    let bus = bus.clone();
+                    let received = match tokio::task::spawn_blocking(move || bus.receive()).await {
+                        Ok(events) => events,
+                        Err(e) => {
+                            tracing::warn!("event bus receive task failed: {e}");
+                            Vec::new()
+                        }
+                    };
+                    for event in received {
+                        let _ = event_tx.send(event);
+                    }
+                }
+                _ = shutdown.wait() => break,
+            }
+        }
+    })
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+    use serde_json::json;
+
+    #[test]
+    fn store_bus_delivers_other_nodes_events_once() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        let before = StoreEventBus::new(store.clone(), "node-a");
+        before.publish(&ReapEvent::new("cron.run.started", None));
+
+        let node_a = StoreEventBus::new(store.clone(), "node-a");
+        let node_b = StoreEventBus::new(store, "node-b");
+        node_a.publish(&ReapEvent::new(
+            "cron.run.completed",
+            Some(json!({ "cron_id": "c1" })),
+        ));
+
+        assert!(node_a.receive().is_empty());
+        let received = node_b.receive();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].topic, "cron.run.completed");
+        assert_eq!(received[0].params, Some(json!({ "cron_id": "c1" })));
+        assert_eq!(received[0].origin.as_deref(), Some("node-a"));
+        assert!(node_b.receive().is_empty());
+    }
+
+    #[tokio::test]
+    async fn bridge_shares_local_events_and_broadcasts_remote_ones() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        let shutdown = ShutdownSignal::new();
+        let (a_tx, _) = broadcast::channel(16);
+        let (b_tx, mut b_rx) = broadcast::channel(16);
+        let mut a_rx = a_tx.subscribe();
+        for (node, tx) in [("node-a", &a_tx), ("node-b", &b_tx)] {
+            spawn_event_bridge(
+                Arc::new(StoreEventBus::new(store.clone(), node)),
+                tx.clone(),
+                Duration::from_millis(10),
+                shutdown.clone(),
+            );
+        }
+        tokio::task::yield_now().await;
+
+        a_tx.send(ReapEvent::new("terminal.session.exit", None))
+            .unwrap();
+        let relayed = tokio::time::timeout(Duration::from_secs(2), b_rx.recv())
+            .await
+            .expect("event relayed to node b")
+            .unwrap();
+        assert_eq!(relayed.topic, "terminal.session.exit");
+        assert_eq!(relayed.origin.as_deref(), Some("node-a"));
+
+        // Node a only ever sees its own event once.
+        assert_eq!(a_rx.recv().await.unwrap().origin, None);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(a_rx.try_recv().is_err());
+        shutdown.trigger();
+    }
+}
@@ -1,13 +1,24 @@
+use std::sync::Arc;
+
 use serde_json::Value;
 use uuid::Uuid;
 
 use homie_protocol::{BinaryFrame, Response};
 
+use super::context::ConnectionContext;
+use crate::authz::Scope;
+
 /// Event emitted by a service that should be published to subscribers.
 #[derive(Debug, Clone)]
 pub struct ReapEvent {
     pub topic: String,
     pub params: Option<Value>,
+    /// Node the event was relayed from by the `EventBus`; `None` for events
+    /// raised on this node.
+    pub origin: Option<String>,
+    /// Identity whose connections alone receive the event; `None` for
+    /// every subscriber. Such events stay on the node that raised them.
+    pub audience: Option<String>,
 }
 
 impl ReapEvent {
@@ -15,6 +26,20 @@ impl ReapEvent {
         Self {
             topic: topic.into(),
             params,
+            origin: None,
+            audience: None,
+        }
+    }
+
+    /// An event only `identity`'s connections receive.
+    pub fn for_identity(
+        topic: impl Into<String>,
+        params: Option<Value>,
+        identity: impl Into<String>,
+    ) -> Self {
+        Self {
+            audience: Some(identity.into()),
+            ..Self::new(topic, params)
         }
     }
 }
@@ -27,6 +52,17 @@ pub trait ServiceHandler: Send {
     /// The service namespace prefix (e.g. "terminal").
     fn namespace(&self) -> &str;
 
+    /// `(method, scope)` rows this service adds to the router's
+    /// `MethodPolicy`. Methods left out are owner-only.
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        &[]
+    }
+
+    /// Called once when the service joins a connection's router, before
+    /// any request is routed to it. Services that authorize or attribute
+    /// actions keep the context.
+    fn attach(&mut self, _ctx: Arc<ConnectionContext>) {}
+
     /// Handle an RPC request. `method` is the full dotted method name
     /// (e.g. "terminal.session.start").
     fn handle_request(
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+/// Upper bounds (microseconds) of the latency histogram buckets. Requests
+/// slower than the last bound land in an overflow bucket.
+const LATENCY_BUCKETS_US: [u64; 16] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// Per-method request counters shared by every connection.
+///
+/// Cloning is cheap: all clones point at the same counters. Recording takes a
+/// read lock on the method map and then only touches atomics, so concurrent
+/// connections do not contend once a method has been seen.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsRegistry {
+    inner: Arc<MetricsInner>,
+}
+
+#[derive(Debug, Default)]
+struct MetricsInner {
+    methods: RwLock<HashMap<String, Arc<MethodStats>>>,
+    active_connections: AtomicU64,
+    /// Last heartbeat round-trip per connection, in microseconds.
+    connection_rtt_us: RwLock<HashMap<Uuid, u64>>,
+    /// Events discarded by each open connection's overflow policy.
+    connection_dropped_events: RwLock<HashMap<Uuid, u64>>,
+    /// Events discarded across all connections since startup.
+    dropped_events: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct MethodStats {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    buckets: [AtomicU64; LATENCY_BUCKETS_US.len() + 1],
+}
+
+/// Decrements the active connection count when dropped.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    inner: Arc<MetricsInner>,
+    conn_id: Uuid,
+}
+
+impl ConnectionGuard {
+    /// Record the connection's latest heartbeat round-trip time.
+    pub fn record_rtt(&self, rtt: Duration) {
+        let micros = u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX);
+        if let Ok(mut map) = self.inner.connection_rtt_us.write() {
+            map.insert(self.conn_id, micros);
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.inner
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+        if let Ok(mut map) = self.inner.connection_rtt_us.write() {
+            map.remove(&self.conn_id);
+        }
+        if let Ok(mut map) = self.inner.connection_dropped_events.write() {
+            map.remove(&self.conn_id);
+        }
+    }
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one completed request for `method`.
+    pub fn record(&self, method: &str, elapsed: Duration, is_error: bool) {
+        let stats = self.stats_for(method);
+        stats.requests.fetch_add(1, Ordering::Relaxed);
+        if is_error {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let bucket = LATENCY_BUCKETS_US
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        stats.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a connection as active until the returned guard is dropped.
+    pub fn connection_opened(&self, conn_id: Uuid) -> ConnectionGuard {
+        self.inner
+            .active_connections
+            .fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            inner: self.inner.clone(),
+            conn_id,
+        }
+    }
+
+    /// Count one event dropped for `conn_id` because its outbound queue
+    /// was full.
+    pub fn record_dropped_event(&self, conn_id: Uuid) {
+        self.record_dropped_events(conn_id, 1);
+    }
+
+    /// Count `count` events dropped for `conn_id`, e.g. broadcast events it
+    /// fell too far behind to receive.
+    pub fn record_dropped_events(&self, conn_id: Uuid, count: u64) {
+        self.inner
+            .dropped_events
+            .fetch_add(count, Ordering::Relaxed);
+        if let Ok(mut map) = self.inner.connection_dropped_events.write() {
+            *map.entry(conn_id).or_default() += count;
+        }
+    }
+
+    pub fn active_connections(&self) -> u64 {
+        self.inner.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// JSON snapshot returned by `system.metrics`.
+    pub fn snapshot(&self) -> Value {
+        let mut methods = Map::new();
+        if let Ok(map) = self.inner.methods.read() {
+            for (method, stats) in map.iter() {
+                methods.insert(method.clone(), stats.snapshot());
+            }
+        }
+        let mut connection_rtt_ms = Map::new();
+        if let Ok(map) = self.inner.connection_rtt_us.read() {
+            for (conn_id, micros) in map.iter() {
+                connection_rtt_ms.insert(conn_id.to_string(), json!(*micros as f64 / 1_000.0));
+            }
+        }
+        let mut connection_dropped_events = Map::new();
+        if let Ok(map) = self.inner.connection_dropped_events.read() {
+            for (conn_id, dropped) in map.iter() {
+                connection_dropped_events.insert(conn_id.to_string(), json!(dropped));
+            }
+        }
+        json!({
+            "active_connections": self.active_connections(),
+            "connection_rtt_ms": connection_rtt_ms,
+            "connection_dropped_events": connection_dropped_events,
+            "dropped_events": self.inner.dropped_events.load(Ordering::Relaxed),
+            "methods": methods,
+        })
+    }
+
+    fn stats_for(&self, method: &str) -> Arc<MethodStats> {
+        if let Ok(map) = self.inner.methods.read() {
+            if let Some(stats) = map.get(method) {
+                return stats.clone();
+            }
+        }
+        match self.inner.methods.write() {
+            Ok(mut map) => map.entry(method.to_string()).or_default().clone(),
+            Err(_) => Arc::new(MethodStats::default()),
+        }
+    }
+}
+
+impl MethodStats {
+    fn snapshot(&self) -> Value {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        json!({
+            "count": self.requests.load(Ordering::Relaxed),
+            "errors": self.errors.load(Ordering::Relaxed),
+            "p50_ms": percentile_ms(&counts, 0.50),
+            "p95_ms": percentile_ms(&counts, 0.95),
+        })
+    }
+}
+
+/// Estimate a latency percentile as the upper bound of the bucket containing
+/// it. Overflow samples report the largest finite bound.
+fn percentile_ms(counts: &[u64], quantile: f64) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let target = ((total as f64) * quantile).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (index, count) in counts.iter().enumerate() {
+        seen += count;
+        if seen >= target {
+            let bound = LATENCY_BUCKETS_US
+                .get(index)
+                .or(LATENCY_BUCKETS_US.last())
+                .copied()
+                .unwrap_or(0);
+            return bound as f64 / 1_000.0;
+        }
+    }
+    0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_counts_errors_and_percentiles() {
+        let metrics = MetricsRegistry::new();
+        for _ in 0..19 {
+            metrics.record("chat.list", Duration::from_micros(800), false);
+        }
+        metrics.record("chat.list", Duration::from_millis(40), true);
+
+        let snapshot = metrics.snapshot();
+        let chat = &snapshot["methods"]["chat.list"];
+        assert_eq!(chat["count"], 20);
+        assert_eq!(chat["errors"], 1);
+        assert_eq!(chat["p50_ms"], 1.0);
+        assert_eq!(chat["p95_ms"], 1.0);
+
+        metrics.record("chat.list", Duration::from_millis(40), false);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["methods"]["chat.list"]["p95_ms"], 50.0);
+    }
+
+    #[test]
+    fn overflow_latency_reports_largest_bucket() {
+        let metrics = MetricsRegistry::new();
+        metrics.record("slow", Duration::from_secs(60), false);
+        assert_eq!(metrics.snapshot()["methods"]["slow"]["p50_ms"], 10_000.0);
+    }
+
+    #[test]
+    fn connection_guard_tracks_active_connections() {
+        let metrics = MetricsRegistry::new();
+        let first = metrics.connection_opened(Uuid::new_v4());
+        let second_id = Uuid::new_v4();
+        let second = metrics.clone().connection_opened(second_id);
+        assert_eq!(metrics.active_connections(), 2);
+        drop(first);
+        assert_eq!(metrics.snapshot()["active_connections"], 1);
+
+        second.record_rtt(Duration::from_micros(2_500));
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["connection_rtt_ms"][second_id.to_string()], 2.5);
+        drop(second);
+        assert_eq!(metrics.active_connections(), 0);
+        assert_eq!(metrics.snapshot()["connection_rtt_ms"], json!({}));
+    }
+
+    #[test]
+    fn dropped_events_are_counted_per_connection() {
+        let metrics = MetricsRegistry::new();
+        let conn_id = Uuid::new_v4();
+        let guard = metrics.connection_opened(conn_id);
+        metrics.record_dropped_event(conn_id);
+        metrics.record_dropped_event(conn_id);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["dropped_events"], 2);
+        assert_eq!(
+            snapshot["connection_dropped_events"][conn_id.to_string()],
+            2
+        );
+
+        drop(guard);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["dropped_events"], 2);
+        assert_eq!(snapshot["connection_dropped_events"], json!({}));
+    }
+
+    #[test]
+    fn concurrent_recording_is_not_lost() {
+        let metrics = MetricsRegistry::new();
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let metrics = metrics.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..500 {
+                        metrics.record("terminal.session.list", Duration::from_micros(50), false);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().expect("join");
+        }
+        assert_eq!(
+            metrics.snapshot()["methods"]["terminal.session.list"]["count"],
+            4_000
+        );
+    }
+}
@@ -1,9 +1,23 @@
+mod audit;
+mod context;
+mod debug_events;
 mod dispatch;
+mod event_bus;
 mod handler;
+mod metrics;
+mod rate_limit;
 mod registry;
 mod subscriptions;
+mod timeout;
 
+pub use audit::AuditLog;
+pub use context::ConnectionContext;
+pub use debug_events::{DebugEventTap, DEBUG_EVENTS_TOPIC};
 pub use dispatch::MessageRouter;
+pub use event_bus::{spawn_event_bridge, EventBus, LocalEventBus, StoreEventBus};
 pub use handler::{ReapEvent, ServiceHandler};
+pub use metrics::{ConnectionGuard, MetricsRegistry};
+pub use rate_limit::RateLimiter;
 pub use registry::ServiceRegistry;
-pub use subscriptions::SubscriptionManager;
+pub use subscriptions::{SubscriptionDirectory, SubscriptionManager};
+pub use timeout::{RequestTimeouts, UNTIMED_METHODS};
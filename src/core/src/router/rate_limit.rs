@@ -0,0 +1,114 @@
+use std::time::Instant;
+
+/// Token-bucket limiter for inbound RPC requests on a single connection.
+///
+/// Only requests are metered; heartbeats, pongs, and binary frames never
+/// reach the limiter. A rate of `0` disables limiting entirely.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Option<TokenBucket>,
+    /// Consecutive rejections that trigger a disconnect (0 = never).
+    close_after: u32,
+    consecutive_rejections: u32,
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests_per_sec: u32, burst: u32, close_after: u32) -> Self {
+        let bucket = (max_requests_per_sec > 0).then(|| {
+            let capacity = f64::from(burst.max(1));
+            TokenBucket {
+                capacity,
+                refill_per_sec: f64::from(max_requests_per_sec),
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }
+        });
+        Self {
+            bucket,
+            close_after,
+            consecutive_rejections: 0,
+        }
+    }
+
+    /// Take one token for an incoming request. Returns false when the
+    /// connection has exhausted its budget.
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        let Some(bucket) = self.bucket.as_mut() else {
+            return true;
+        };
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * bucket.refill_per_sec).min(bucket.capacity);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            self.consecutive_rejections = 0;
+            true
+        } else {
+            self.consecutive_rejections = self.consecutive_rejections.saturating_add(1);
+            false
+        }
+    }
+
+    /// True once the client has kept exceeding its budget long enough that
+    /// the connection should be dropped.
+    pub fn should_disconnect(&self) -> bool {
+        self.close_after > 0 && self.consecutive_rejections >= self.close_after
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn rejects_past_burst_and_refills_over_time() {
+        let mut limiter = RateLimiter::new(2, 3, 0);
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start));
+        assert!(!limiter.try_acquire_at(start));
+
+        assert!(limiter.try_acquire_at(start + Duration::from_millis(500)));
+        assert!(!limiter.try_acquire_at(start + Duration::from_millis(500)));
+        assert!(!limiter.should_disconnect());
+    }
+
+    #[test]
+    fn zero_rate_disables_limiting() {
+        let mut limiter = RateLimiter::new(0, 0, 1);
+        for _ in 0..1000 {
+            assert!(limiter.try_acquire());
+        }
+        assert!(!limiter.should_disconnect());
+    }
+
+    #[test]
+    fn sustained_rejections_trigger_disconnect() {
+        let mut limiter = RateLimiter::new(1, 1, 3);
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at(start));
+        assert!(!limiter.try_acquire_at(start));
+        assert!(!limiter.try_acquire_at(start));
+        assert!(!limiter.should_disconnect());
+        assert!(!limiter.try_acquire_at(start));
+        assert!(limiter.should_disconnect());
+
+        assert!(limiter.try_acquire_at(start + Duration::from_secs(1)));
+        assert!(!limiter.should_disconnect());
+    }
+}
@@ -1,7 +1,15 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 
+use homie_protocol::error_codes;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
+use super::debug_events::{DebugEventTap, DEBUG_EVENTS_TOPIC};
+use super::handler::ReapEvent;
+use crate::outbound::OutboundMessage;
+
 /// Manages event subscriptions per-connection.
 ///
 /// Clients subscribe to topic patterns using `events.subscribe` and
@@ -12,7 +20,10 @@ use uuid::Uuid;
 /// - Exact match: `"terminal.session.exit"` matches only that topic.
 /// - Prefix match with `*` wildcard: `"terminal.*"` matches any topic
 ///   starting with `"terminal."`.
+/// - Prefix match with a trailing dot: `"chat."` is the same as `"chat.*"`.
 /// - Bare `"*"` matches all topics.
+///
+/// A topic matching several subscriptions is still delivered once.
 #[derive(Debug, Default)]
 pub struct SubscriptionManager {
     /// subscription_id → pattern
@@ -23,6 +34,104 @@ pub struct SubscriptionManager {
     exact_index: HashMap<String, HashSet<Uuid>>,
     /// subscription_ids that match everything ("*")
     catch_all: HashSet<Uuid>,
+    /// Shared listing this connection's subscriptions are mirrored into.
+    directory: Option<(SubscriptionDirectory, Uuid)>,
+    /// Where `debug.events` come from, if this connection may watch them.
+    debug: Option<DebugWatch>,
+    /// Principal of the connection; events addressed to another identity
+    /// are never delivered.
+    identity: Option<String>,
+}
+
+#[derive(Debug)]
+struct DebugWatch {
+    tap: DebugEventTap,
+    conn_id: Uuid,
+    outbound: mpsc::Sender<OutboundMessage>,
+    /// Only owners may watch other connections' traffic.
+    allowed: bool,
+}
+
+/// Subscription patterns of every live connection, behind
+/// `system.subscriptions`.
+///
+/// Cloning is cheap: all clones share the same map.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionDirectory {
+    /// connection id → subscription_id → pattern
+    connections: Arc<RwLock<HashMap<Uuid, HashMap<Uuid, String>>>>,
+}
+
+impl SubscriptionDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn insert(&self, conn_id: Uuid, sub_id: Uuid, pattern: &str) {
+        if let Ok(mut map) = self.connections.write() {
+            map.entry(conn_id)
+                .or_default()
+                .insert(sub_id, pattern.to_string());
+        }
+    }
+
+    fn remove(&self, conn_id: Uuid, sub_id: Uuid) {
+        if let Ok(mut map) = self.connections.write() {
+            if let Some(subs) = map.get_mut(&conn_id) {
+                subs.remove(&sub_id);
+                if subs.is_empty() {
+                    map.remove(&conn_id);
+                }
+            }
+        }
+    }
+
+    fn remove_connection(&self, conn_id: Uuid) {
+        if let Ok(mut map) = self.connections.write() {
+            map.remove(&conn_id);
+        }
+    }
+
+    /// Every subscribed pattern with the number of connections holding it
+    /// (`subscribers`) and of subscriptions to it (`subscriptions`), busiest
+    /// first, plus the total number of subscriptions (`total_fan_out`).
+    pub fn snapshot(&self) -> Value {
+        let Ok(map) = self.connections.read() else {
+            return json!({ "topics": [], "connections": 0, "total_fan_out": 0 });
+        };
+        let mut topics: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for subs in map.values() {
+            let mut seen = HashSet::new();
+            for pattern in subs.values() {
+                let entry = topics.entry(pattern.as_str()).or_default();
+                entry.1 += 1;
+                if seen.insert(pattern.as_str()) {
+                    entry.0 += 1;
+                }
+            }
+        }
+        let total: usize = topics
+            .values()
+            .map(|(_, subscriptions)| subscriptions)
+            .sum();
+        let mut topics: Vec<(&str, (usize, usize))> = topics.into_iter().collect();
+        topics.sort_by_key(|(_, (connections, _))| std::cmp::Reverse(*connections));
+        let topics: Vec<Value> = topics
+            .into_iter()
+            .map(|(topic, (subscribers, subscriptions))| {
+                json!({
+                    "topic": topic,
+                    "subscribers": subscribers,
+                    "subscriptions": subscriptions,
+                })
+            })
+            .collect();
+        json!({
+            "topics": topics,
+            "connections": map.len(),
+            "total_fan_out": total,
+        })
+    }
 }
 
 impl SubscriptionManager {
@@ -30,6 +139,64 @@ impl SubscriptionManager {
         Self::default()
     }
 
+    /// Mirror this connection's subscriptions into `directory`.
+    pub fn with_directory(mut self, directory: SubscriptionDirectory, conn_id: Uuid) -> Self {
+        for (sub_id, pattern) in &self.subscriptions {
+            directory.insert(conn_id, *sub_id, pattern);
+        }
+        self.directory = Some((directory, conn_id));
+        self
+    }
+
+    /// Deliver events addressed to `identity` (see [`Self::delivers`]).
+    pub fn with_identity(mut self, identity: Option<String>) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Feed `debug.events` from `tap` into `outbound` once this connection
+    /// subscribes to the topic. `allowed` is false for non-owner roles.
+    pub fn with_debug_tap(
+        mut self,
+        tap: DebugEventTap,
+        conn_id: Uuid,
+        outbound: mpsc::Sender<OutboundMessage>,
+        allowed: bool,
+    ) -> Self {
+        self.debug = Some(DebugWatch {
+            tap,
+            conn_id,
+            outbound,
+            allowed,
+        });
+        self
+    }
+
+    /// Start mirroring every outbound event to this connection, before it
+    /// subscribes to `debug.events`. Fails with an error code and message
+    /// when the tap is disabled or the connection is not an owner.
+    pub fn watch_debug_events(&self) -> Result<(), (i32, &'static str)> {
+        let watch = self
+            .debug
+            .as_ref()
+            .filter(|watch| watch.tap.is_enabled())
+            .ok_or((
+                error_codes::INVALID_PARAMS,
+                "debug.events is disabled; set HOMIE_DEBUG_EVENTS=1",
+            ))?;
+        if !watch.allowed {
+            return Err((error_codes::FORBIDDEN, "debug.events is owner-only"));
+        }
+        watch.tap.watch(watch.conn_id, watch.outbound.clone());
+        Ok(())
+    }
+
+    fn unwatch_debug_events(&self) {
+        if let Some(watch) = &self.debug {
+            watch.tap.unwatch(watch.conn_id);
+        }
+    }
+
     /// Add a subscription. Returns a subscription ID.
     pub fn subscribe(&mut self, pattern: impl Into<String>) -> Uuid {
         let pattern = pattern.into();
@@ -37,12 +204,8 @@ impl SubscriptionManager {
 
         if pattern == "*" {
             self.catch_all.insert(sub_id);
-        } else if let Some(prefix) = pattern.strip_suffix(".*") {
-            let prefix_with_dot = format!("{prefix}.");
-            self.prefix_index
-                .entry(prefix_with_dot)
-                .or_default()
-                .insert(sub_id);
+        } else if let Some(prefix) = topic_prefix(&pattern) {
+            self.prefix_index.entry(prefix).or_default().insert(sub_id);
         } else {
             self.exact_index
                 .entry(pattern.clone())
@@ -50,6 +213,9 @@ impl SubscriptionManager {
                 .insert(sub_id);
         }
 
+        if let Some((directory, conn_id)) = &self.directory {
+            directory.insert(*conn_id, sub_id, &pattern);
+        }
         self.subscriptions.insert(sub_id, pattern);
         sub_id
     }
@@ -60,15 +226,22 @@ impl SubscriptionManager {
             Some(p) => p,
             None => return false,
         };
+        if let Some((directory, conn_id)) = &self.directory {
+            directory.remove(*conn_id, sub_id);
+        }
+        if pattern == DEBUG_EVENTS_TOPIC
+            && !self.subscriptions.values().any(|p| p == DEBUG_EVENTS_TOPIC)
+        {
+            self.unwatch_debug_events();
+        }
 
         if pattern == "*" {
             self.catch_all.remove(&sub_id);
-        } else if let Some(prefix) = pattern.strip_suffix(".*") {
-            let prefix_with_dot = format!("{prefix}.");
-            if let Some(set) = self.prefix_index.get_mut(&prefix_with_dot) {
+        } else if let Some(prefix) = topic_prefix(&pattern) {
+            if let Some(set) = self.prefix_index.get_mut(&prefix) {
                 set.remove(&sub_id);
                 if set.is_empty() {
-                    self.prefix_index.remove(&prefix_with_dot);
+                    self.prefix_index.remove(&prefix);
                 }
             }
         } else if let Some(set) = self.exact_index.get_mut(&pattern) {
@@ -107,6 +280,16 @@ impl SubscriptionManager {
         false
     }
 
+    /// Whether `event` goes to this connection: it matches a subscription
+    /// and, when addressed to an identity, that is the connection's.
+    pub fn delivers(&self, event: &ReapEvent) -> bool {
+        let addressed = match event.audience.as_deref() {
+            Some(audience) => self.identity.as_deref() == Some(audience),
+            None => true,
+        };
+        addressed && self.matches(&event.topic)
+    }
+
     /// Return true if there are no subscriptions.
     pub fn is_empty(&self) -> bool {
         self.subscriptions.is_empty()
@@ -117,8 +300,33 @@ impl SubscriptionManager {
         self.subscriptions.len()
     }
 
+    /// This connection's subscribed patterns, sorted.
+    pub fn patterns(&self) -> Vec<String> {
+        let mut patterns: Vec<String> = self.subscriptions.values().cloned().collect();
+        patterns.sort();
+        patterns
+    }
+
+    /// `system.subscriptions` result: the shared directory's listing plus
+    /// this connection's own patterns under `own`.
+    pub fn report(&self) -> Value {
+        let mut report = match &self.directory {
+            Some((directory, _)) => directory.snapshot(),
+            None => json!({}),
+        };
+        report["own"] = json!({
+            "connection_id": self.directory.as_ref().map(|(_, conn_id)| conn_id),
+            "topics": self.patterns(),
+        });
+        report
+    }
+
     /// Remove all subscriptions.
     pub fn clear(&mut self) {
+        if let Some((directory, conn_id)) = &self.directory {
+            directory.remove_connection(*conn_id);
+        }
+        self.unwatch_debug_events();
         self.subscriptions.clear();
         self.prefix_index.clear();
         self.exact_index.clear();
@@ -126,6 +334,26 @@ impl SubscriptionManager {
     }
 }
 
+/// The dotted prefix a wildcard pattern (`"chat.*"` or `"chat."`) matches.
+fn topic_prefix(pattern: &str) -> Option<String> {
+    if let Some(prefix) = pattern.strip_suffix(".*") {
+        return Some(format!("{prefix}."));
+    }
+    pattern
+        .ends_with('.')
+        .then(|| pattern.to_string())
+        .filter(|prefix| prefix.len() > 1)
+}
+
+impl Drop for SubscriptionManager {
+    fn drop(&mut self) {
+        if let Some((directory, conn_id)) = &self.directory {
+            directory.remove_connection(*conn_id);
+        }
+        self.unwatch_debug_events();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,6 +379,39 @@ mod tests {
         assert!(!mgr.matches("agent.chat.delta"));
     }
 
+    #[test]
+    fn trailing_dot_prefix_match() {
+        let mut mgr = SubscriptionManager::new();
+        mgr.subscribe("chat.");
+        mgr.subscribe("agent.chat.");
+
+        assert!(mgr.matches("chat.message.delta"));
+        assert!(mgr.matches("chat.turn.completed"));
+        assert!(mgr.matches("agent.chat.delta"));
+        assert!(!mgr.matches("chat"));
+        assert!(!mgr.matches("chatter.message"));
+        assert!(!mgr.matches("agent.codex.delta"));
+        assert!(!mgr.matches("terminal.session.exit"));
+    }
+
+    #[test]
+    fn exact_and_covering_prefix_match_once() {
+        let mut mgr = SubscriptionManager::new();
+        let exact = mgr.subscribe("chat.turn.completed");
+        let prefix = mgr.subscribe("chat.");
+        let delivered = ["chat.turn.completed", "chat.message.delta", "jobs.done"]
+            .iter()
+            .filter(|topic| mgr.matches(topic))
+            .count();
+        assert_eq!(delivered, 2);
+
+        assert!(mgr.unsubscribe(prefix));
+        assert!(mgr.matches("chat.turn.completed"));
+        assert!(!mgr.matches("chat.message.delta"));
+        assert!(mgr.unsubscribe(exact));
+        assert!(!mgr.matches("chat.turn.completed"));
+    }
+
     #[test]
     fn catch_all() {
         let mut mgr = SubscriptionManager::new();
@@ -224,6 +485,38 @@ mod tests {
         assert!(!mgr.matches("terminal.session.exit"));
     }
 
+    #[test]
+    fn directory_lists_subscriptions_across_connections() {
+        let directory = SubscriptionDirectory::new();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut a = SubscriptionManager::new().with_directory(directory.clone(), alice);
+        let mut b = SubscriptionManager::new().with_directory(directory.clone(), bob);
+        a.subscribe("chat.*");
+        a.subscribe("chat.*");
+        a.subscribe("terminal.session.exit");
+        let b_sub = b.subscribe("chat.*");
+
+        let report = a.report();
+        assert_eq!(report["connections"], 2);
+        assert_eq!(report["total_fan_out"], 4);
+        assert_eq!(report["topics"][0]["topic"], "chat.*");
+        assert_eq!(report["topics"][0]["subscribers"], 2);
+        assert_eq!(report["topics"][0]["subscriptions"], 3);
+        assert_eq!(report["topics"][1]["topic"], "terminal.session.exit");
+        assert_eq!(report["topics"][1]["subscribers"], 1);
+        assert_eq!(report["own"]["connection_id"], alice.to_string());
+        assert_eq!(
+            report["own"]["topics"],
+            json!(["chat.*", "chat.*", "terminal.session.exit"])
+        );
+
+        b.unsubscribe(b_sub);
+        assert_eq!(directory.snapshot()["connections"], 1);
+        drop(a);
+        assert_eq!(directory.snapshot()["total_fan_out"], 0);
+        assert_eq!(b.report()["own"]["topics"], json!([]));
+    }
+
     #[test]
     fn len_tracks_subscriptions() {
         let mut mgr = SubscriptionManager::new();
@@ -238,4 +531,19 @@ mod tests {
         mgr.unsubscribe(id);
         assert_eq!(mgr.len(), 1);
     }
+
+    #[test]
+    fn identity_events_reach_only_that_identity() {
+        let mut alice = SubscriptionManager::new().with_identity(Some("alice".into()));
+        alice.subscribe("presence.*");
+        let mut anonymous = SubscriptionManager::new();
+        anonymous.subscribe("*");
+
+        let event = ReapEvent::for_identity("presence.connected", None, "alice");
+        assert!(alice.delivers(&event));
+        assert!(!anonymous.delivers(&event));
+        assert!(!alice.delivers(&ReapEvent::for_identity("presence.connected", None, "bob")));
+        assert!(anonymous.delivers(&ReapEvent::new("cron.run.completed", None)));
+        assert!(!alice.delivers(&ReapEvent::new("cron.run.completed", None)));
+    }
 }
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Read-only methods that run no deadline by default: log tails and
+/// terminal attaches stay open as long as the client watches. Mutating
+/// methods are exempt without being listed here.
+pub const UNTIMED_METHODS: &[&str] = &[
+    "jobs.logs.tail",
+    "cron.logs.tail",
+    "terminal.session.attach",
+];
+
+/// How long the router lets each method's handler run before answering
+/// `REQUEST_TIMEOUT` and dropping (cancelling) the handler's future.
+///
+/// A zero duration means no deadline. Overrides are keyed by full method
+/// name or by a dotted prefix (`chat.account` covers
+/// `chat.account.login.start`); the longest matching key wins. Methods that
+/// change state only get a deadline from an override: cancelling one
+/// halfway could leave its change partly applied.
+#[derive(Debug, Clone, Default)]
+pub struct RequestTimeouts {
+    default: Duration,
+    by_method: HashMap<String, Duration>,
+}
+
+impl RequestTimeouts {
+    pub fn new(default: Duration, by_method: HashMap<String, Duration>) -> Self {
+        Self { default, by_method }
+    }
+
+    /// The deadline for `method`, or `None` when it runs unbounded.
+    /// `mutating` methods without an override run unbounded.
+    pub fn for_method(&self, method: &str, mutating: bool) -> Option<Duration> {
+        let timeout = match self.override_for(method) {
+            Some(timeout) => timeout,
+            None if mutating => Duration::ZERO,
+            None => self.default,
+        };
+        (!timeout.is_zero()).then_some(timeout)
+    }
+
+    fn override_for(&self, method: &str) -> Option<Duration> {
+        let mut key = method;
+        loop {
+            if let Some(timeout) = self.by_method.get(key) {
+                return Some(*timeout);
+            }
+            key = key.rsplit_once('.')?.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_match_the_longest_method_prefix() {
+        let timeouts = RequestTimeouts::new(
+            Duration::from_secs(30),
+            HashMap::from([
+                ("chat".to_string(), Duration::from_secs(60)),
+                ("chat.account.login".to_string(), Duration::from_secs(300)),
+                ("chat.tools.invoke".to_string(), Duration::ZERO),
+            ]),
+        );
+        assert_eq!(
+            timeouts.for_method("terminal.session.list", false),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            timeouts.for_method("chat.message.send", true),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            timeouts.for_method("chat.account.login.poll", true),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(timeouts.for_method("chat.tools.invoke", true), None);
+        assert_eq!(
+            RequestTimeouts::default().for_method("chat.list", false),
+            None
+        );
+    }
+
+    #[test]
+    fn mutating_methods_are_untimed_without_an_override() {
+        let timeouts = RequestTimeouts::new(Duration::from_secs(30), HashMap::new());
+        assert_eq!(timeouts.for_method("terminal.session.start", true), None);
+        assert_eq!(
+            timeouts.for_method("terminal.session.list", false),
+            Some(Duration::from_secs(30))
+        );
+    }
+}
@@ -4,21 +4,33 @@ use std::time::Duration;
 
 use axum::extract::ws::WebSocketUpgrade;
 use axum::extract::State;
-use axum::http::{HeaderMap, Request};
+use axum::http::{HeaderMap, Request, StatusCode};
 use axum::middleware;
 use axum::response::IntoResponse;
 use axum::routing::get;
-use axum::Router;
+use axum::{Json, Router};
+use homie_protocol::PROTOCOL_VERSION;
+use serde_json::{json, Value};
 use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
 use tower_http::trace::TraceLayer;
+use uuid::Uuid;
 
-use crate::auth::{authenticate, AuthOutcome, TailscaleWhois};
+use crate::admin::RunFreeze;
+use crate::agent::{supervise_tool_providers, RunSlots};
+use crate::auth::{authenticate_with_config, AuthOutcome, TailscaleWhois};
+use crate::config::EventBusKind;
 use crate::config::ServerConfig;
 use crate::connection::{run_connection, ConnectionParams};
 use crate::cron::{spawn_cron_scheduler, CronRunner};
-use crate::presence::NodeRegistry;
-use crate::router::{ReapEvent, ServiceRegistry};
-use crate::storage::Store;
+use crate::notifications::spawn_notification_worker;
+use crate::presence::{ConnectionRoster, NodeRegistry};
+use crate::router::{
+    spawn_event_bridge, DebugEventTap, EventBus, LocalEventBus, MetricsRegistry, ReapEvent,
+    ServiceRegistry, StoreEventBus, SubscriptionDirectory,
+};
+use crate::shutdown::ShutdownSignal;
+use crate::storage::{spawn_store_maintenance, RetentionPolicy, Store};
 use crate::terminal::TerminalRegistry;
 use crate::{ExecPolicy, HomieConfig};
 
@@ -35,49 +47,150 @@ pub(crate) struct AppState {
     pub cron_runner: Arc<CronRunner>,
     pub homie_config: Arc<HomieConfig>,
     pub exec_policy: Arc<ExecPolicy>,
+    pub metrics: MetricsRegistry,
+    pub subscriptions: SubscriptionDirectory,
+    pub roster: ConnectionRoster,
+    pub debug_tap: DebugEventTap,
+    pub run_freeze: RunFreeze,
+    pub run_slots: RunSlots,
+    pub shutdown: ShutdownSignal,
+    pub workers: BackgroundWorkers,
+}
+
+/// Handles of the background workers `build_router` spawns, watched by
+/// `/readyz`.
+#[derive(Clone)]
+pub(crate) struct BackgroundWorkers {
+    cron: Arc<JoinHandle<()>>,
+    /// `None` when periodic maintenance is disabled.
+    maintenance: Option<Arc<JoinHandle<()>>>,
+}
+
+impl BackgroundWorkers {
+    fn report(&self) -> (bool, Value) {
+        let status = |handle: &JoinHandle<()>| {
+            if handle.is_finished() {
+                "stopped"
+            } else {
+                "running"
+            }
+        };
+        let cron = status(&self.cron);
+        let maintenance = self.maintenance.as_deref().map_or("disabled", status);
+        let ready = cron != "stopped" && maintenance != "stopped";
+        (ready, json!({ "cron": cron, "maintenance": maintenance }))
+    }
+}
+
+/// Connect info for clients on a Unix domain socket listener.
+///
+/// Serve a `UnixListener` with
+/// `into_make_service_with_connect_info::<UnixPeer>()`. UDS peers have no
+/// socket address and are authenticated like loopback clients.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy)]
+pub struct UnixPeer {
+    /// Peer process uid, when the platform reports credentials.
+    pub uid: Option<u32>,
+}
+
+#[cfg(unix)]
+impl
+    axum::extract::connect_info::Connected<
+        axum::serve::IncomingStream<'_, tokio::net::UnixListener>,
+    > for UnixPeer
+{
+    fn connect_info(stream: axum::serve::IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        Self {
+            uid: stream.io().peer_cred().ok().map(|cred| cred.uid()),
+        }
+    }
 }
 
 /// Build the axum router for the WS server.
 ///
-/// The router exposes `/ws` (WebSocket upgrade) and `/health`.
+/// The router exposes `/ws` (WebSocket upgrade) plus unauthenticated
+/// `/health`, `/healthz` (liveness with a store ping) and `/readyz` (also
+/// checks the background workers).
 /// Callers should use `into_make_service_with_connect_info::<SocketAddr>()`
-/// when binding to get remote address extraction.
+/// (or [`UnixPeer`] for a Unix socket) when binding to get remote address
+/// extraction.
 ///
 /// On startup, marks all previously-active sessions as inactive so clients
-/// see them as stale until reattached.
+/// see them as stale until reattached, and releases chat runs left queued by
+/// the previous process so resuming their chat restores them.
 pub fn build_router(
     config: ServerConfig,
     whois: impl TailscaleWhois,
     store: Arc<dyn Store>,
 ) -> Router {
+    build_router_with_shutdown(config, whois, store, ShutdownSignal::new())
+}
+
+/// [`build_router`] with a shutdown signal that stops the background
+/// workers it spawns.
+pub fn build_router_with_shutdown(
+    config: ServerConfig,
+    whois: impl TailscaleWhois,
+    store: Arc<dyn Store>,
+    shutdown: ShutdownSignal,
+) -> Router {
+    let homie_config = load_homie_config();
+    let retention = RetentionPolicy {
+        chat_raw_event_max_runs: homie_config.chat.raw_event_runs(),
+        ..config.retention_policy()
+    };
     if let Err(e) = store.mark_all_inactive() {
         tracing::warn!("failed to mark sessions inactive on startup: {e}");
     }
-    if let Err(e) = store.prune_jobs(config.job_retention_days, config.job_max_records) {
-        tracing::warn!("failed to prune jobs on startup: {e}");
+    if let Err(e) = store.release_all_pending_runs() {
+        tracing::warn!("failed to release queued chat runs on startup: {e}");
     }
-    if let Err(e) = store.prune_pairings(config.pairing_retention_secs) {
-        tracing::warn!("failed to prune pairings on startup: {e}");
-    }
-    if let Err(e) = store.prune_notifications(config.notification_retention_days) {
-        tracing::warn!("failed to prune notifications on startup: {e}");
-    }
-    if let Err(e) = store.prune_cron_runs(config.cron_retention_days, config.cron_max_run_records) {
-        tracing::warn!("failed to prune cron runs on startup: {e}");
+    match store.run_maintenance(&retention) {
+        Ok(report) => tracing::debug!(?report, "startup store maintenance finished"),
+        Err(e) => tracing::warn!("failed to run store maintenance on startup: {e}"),
     }
 
-    let terminal_registry = Arc::new(Mutex::new(TerminalRegistry::new(store.clone())));
+    let terminal_registry = Arc::new(Mutex::new(
+        TerminalRegistry::new(store.clone())
+            .with_child_env(homie_config.env.clone())
+            .with_command_wrapper(&homie_config.terminal),
+    ));
     let (event_tx, _event_rx) = broadcast::channel::<ReapEvent>(256);
-    let cron_runner = Arc::new(CronRunner::new(
-        store.clone(),
-        config.cron_max_concurrent_runs,
+    let event_bus: Arc<dyn EventBus> = match config.event_bus {
+        EventBusKind::Local => Arc::new(LocalEventBus),
+        EventBusKind::Store => Arc::new(StoreEventBus::new(
+            store.clone(),
+            Uuid::new_v4().to_string(),
+        )),
+    };
+    spawn_event_bridge(
+        event_bus,
         event_tx.clone(),
-    ));
-    let _cron_scheduler = spawn_cron_scheduler(
+        config.event_bus_poll_interval,
+        shutdown.clone(),
+    );
+    let cron_runner = Arc::new(
+        CronRunner::new(
+            store.clone(),
+            config.cron_max_concurrent_runs,
+            event_tx.clone(),
+        )
+        .with_child_env(homie_config.env.clone()),
+    );
+    let cron_scheduler = spawn_cron_scheduler(
         cron_runner.clone(),
         config.cron_retention_days,
         config.cron_max_run_records,
     );
+    let _notification_worker = spawn_notification_worker(store.clone(), shutdown.clone());
+    let _tool_supervisors = supervise_tool_providers(&homie_config, event_tx.clone());
+    let store_maintenance = spawn_store_maintenance(
+        store.clone(),
+        retention,
+        config.maintenance_interval,
+        shutdown.clone(),
+    );
 
     let mut registry = ServiceRegistry::new();
     registry.register("terminal", "1.0");
@@ -88,13 +201,15 @@ pub fn build_router(
     registry.register("cron", "0.1");
     registry.register("pairing", "0.1");
     registry.register("notifications", "0.1");
+    registry.register("state", "0.1");
+    registry.register("admin", "0.1");
 
-    let homie_config = load_homie_config();
     let exec_policy = load_exec_policy(&homie_config);
     let nodes = Arc::new(Mutex::new(NodeRegistry::new(config.node_timeout)));
 
     let reaper_registry = terminal_registry.clone();
     let reaper_tx = event_tx.clone();
+    let terminal_idle_timeout = config.terminal_idle_timeout;
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(2));
         interval.tick().await;
@@ -105,7 +220,9 @@ pub fn build_router(
                     Ok(v) => v,
                     Err(_) => continue,
                 };
-                registry.reap_exited()
+                let mut events = registry.reap_exited();
+                events.extend(registry.reap_idle(terminal_idle_timeout));
+                events
             };
             for evt in events {
                 let _ = reaper_tx.send(evt);
@@ -113,6 +230,9 @@ pub fn build_router(
         }
     });
 
+    let debug_tap = DebugEventTap::new(config.debug_events);
+    let run_slots = RunSlots::new(homie_config.chat.max_concurrent_runs);
+    let roster = ConnectionRoster::new(event_tx.clone());
     let state = AppState {
         config,
         whois: Arc::new(whois),
@@ -124,11 +244,24 @@ pub fn build_router(
         cron_runner,
         homie_config,
         exec_policy,
+        metrics: MetricsRegistry::new(),
+        subscriptions: SubscriptionDirectory::new(),
+        roster,
+        debug_tap,
+        run_freeze: RunFreeze::new(),
+        run_slots,
+        shutdown,
+        workers: BackgroundWorkers {
+            cron: Arc::new(cron_scheduler),
+            maintenance: store_maintenance.map(Arc::new),
+        },
     };
 
     Router::new()
         .route("/ws", get(ws_upgrade))
         .route("/health", get(health))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
         .layer(middleware::from_fn(extract_remote_ip))
         .layer(TraceLayer::new_for_http())
         .with_state(state)
@@ -141,11 +274,24 @@ async fn extract_remote_ip(
     req: Request<axum::body::Body>,
     next: middleware::Next,
 ) -> impl IntoResponse {
-    let remote_ip = req
+    let remote_ip = match req
         .extensions()
         .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
-        .map(|ci| ci.0.ip())
-        .unwrap_or(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST));
+    {
+        Some(ci) => ci.0.ip(),
+        // Unix socket peers share the host, so they get loopback trust;
+        // forwarded headers from a local proxy still apply as for TCP.
+        None => {
+            #[cfg(unix)]
+            if let Some(ci) = req
+                .extensions()
+                .get::<axum::extract::ConnectInfo<UnixPeer>>()
+            {
+                tracing::trace!(uid = ?ci.0.uid, "unix socket peer");
+            }
+            IpAddr::V4(std::net::Ipv4Addr::LOCALHOST)
+        }
+    };
 
     let mut req = req;
     req.extensions_mut().insert(RemoteIp(remote_ip));
@@ -156,6 +302,59 @@ async fn health() -> &'static str {
     "ok"
 }
 
+fn version_info() -> Value {
+    json!({ "build": env!("CARGO_PKG_VERSION"), "protocol": PROTOCOL_VERSION })
+}
+
+/// `"ok"` or `"error"`; the store's error is logged, never sent to the
+/// unauthenticated caller.
+fn db_status(store: &dyn Store) -> &'static str {
+    match store.ping() {
+        Ok(()) => "ok",
+        Err(e) => {
+            tracing::warn!("health check store ping failed: {e}");
+            "error"
+        }
+    }
+}
+
+/// Liveness: 200 while the store answers, 503 otherwise.
+async fn healthz(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let db = db_status(state.store.as_ref());
+    let ok = db == "ok";
+    let code = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if ok { "ok" } else { "error" },
+        "db": db,
+        "version": version_info(),
+    });
+    (code, Json(body))
+}
+
+/// Readiness: like `/healthz`, and the cron scheduler and store
+/// maintenance workers must still be running.
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let db = db_status(state.store.as_ref());
+    let (workers_ok, workers) = state.workers.report();
+    let ready = db == "ok" && workers_ok && !state.shutdown.is_triggered();
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "db": db,
+        "workers": workers,
+        "version": version_info(),
+    });
+    (code, Json(body))
+}
+
 async fn ws_upgrade(
     State(state): State<AppState>,
     axum::Extension(RemoteIp(remote_ip)): axum::Extension<RemoteIp>,
@@ -166,16 +365,10 @@ async fn ws_upgrade(
         %remote_ip,
         tailscale_serve = state.config.tailscale_serve,
         allow_lan = state.config.allow_lan,
+        auth_mode = ?state.config.auth_mode,
         "ws upgrade requested"
     );
-    let auth = authenticate(
-        &headers,
-        remote_ip,
-        state.config.tailscale_serve,
-        state.config.allow_lan,
-        &state.whois,
-    )
-    .await;
+    let auth = authenticate_with_config(&headers, remote_ip, &state.config, &state.whois).await;
 
     if let AuthOutcome::Rejected(reason) = &auth {
         tracing::warn!(%remote_ip, %reason, "ws upgrade rejected");
@@ -198,6 +391,7 @@ async fn ws_upgrade(
     let event_tx = state.event_tx.clone();
     let params = ConnectionParams {
         config,
+        remote: remote_ip,
         heartbeat_interval: heartbeat,
         idle_timeout: idle,
         registry,
@@ -210,9 +404,21 @@ async fn ws_upgrade(
         exec_policy,
         pairing_default_ttl_secs: state.config.pairing_default_ttl_secs,
         pairing_retention_secs: state.config.pairing_retention_secs,
+        metrics: state.metrics.clone(),
+        subscriptions: state.subscriptions.clone(),
+        roster: state.roster.clone(),
+        debug_tap: state.debug_tap.clone(),
+        run_freeze: state.run_freeze.clone(),
+        run_slots: state.run_slots.clone(),
+        shutdown: state.shutdown.clone(),
     };
 
-    ws.on_upgrade(move |socket| run_connection(socket, auth, params))
+    // Cap what the transport buffers; the message and binary limits are
+    // checked per frame so oversized requests get an error response.
+    let max_frame = state.config.frame_limits().max_frame_bytes();
+    ws.max_message_size(max_frame)
+        .max_frame_size(max_frame)
+        .on_upgrade(move |socket| run_connection(socket, auth, params))
         .into_response()
 }
 
@@ -237,11 +443,16 @@ fn load_exec_policy(config: &HomieConfig) -> Arc<ExecPolicy> {
             return Arc::new(ExecPolicy::empty());
         }
     };
-    match ExecPolicy::load_from_path(&path) {
-        Ok(policy) => Arc::new(policy),
+    let policy = match ExecPolicy::load_from_path(&path) {
+        Ok(policy) => policy,
         Err(err) => {
             tracing::warn!(path = %path.display(), error = %err, "failed to load execpolicy");
-            Arc::new(ExecPolicy::empty())
+            ExecPolicy::empty()
         }
+    };
+    let (policy, errors) = policy.with_valid_rules(&config.tools.exec.policy_rules);
+    for err in errors {
+        tracing::error!(error = %err, "invalid tools.exec.rule; leaving it out");
     }
+    Arc::new(policy)
 }
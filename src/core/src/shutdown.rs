@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Process-wide graceful shutdown flag.
+///
+/// Clones share the same state. The gateway triggers it on SIGINT/SIGTERM;
+/// background workers select on [`ShutdownSignal::wait`] and exit after
+/// finishing their current unit of work.
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        let (tx, _rx) = watch::channel(false);
+        Self { tx: Arc::new(tx) }
+    }
+
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Resolves once [`ShutdownSignal::trigger`] has been called.
+    pub async fn wait(&self) {
+        let mut rx = self.tx.subscribe();
+        let _ = rx.wait_for(|triggered| *triggered).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn clones_observe_trigger() {
+        let signal = ShutdownSignal::new();
+        let waiter = signal.clone();
+        let task = tokio::spawn(async move { waiter.wait().await });
+        assert!(!signal.is_triggered());
+
+        signal.trigger();
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .expect("wait resolves")
+            .unwrap();
+        assert!(signal.clone().is_triggered());
+        // Waiting after the fact returns immediately.
+        signal.wait().await;
+    }
+}
@@ -0,0 +1,283 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use homie_protocol::{error_codes, BinaryFrame, Response};
+
+use crate::authz::Scope;
+use crate::router::{ReapEvent, ServiceHandler};
+use crate::storage::Store;
+
+/// Largest serialized value a single key may hold.
+const MAX_VALUE_BYTES: usize = 64 * 1024;
+/// Longest key accepted.
+const MAX_KEY_LEN: usize = 256;
+/// Keys one identity may store.
+const MAX_KEYS_PER_SCOPE: usize = 1_000;
+
+#[derive(Debug, Deserialize)]
+struct KeyParams {
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetParams {
+    key: String,
+    value: Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ListParams {
+    #[serde(default)]
+    prefix: String,
+}
+
+/// Small per-identity key-value store for client UI state (last selected
+/// chat, theme, feature flags). Each authenticated identity sees only its
+/// own keys.
+pub struct StateService {
+    store: Arc<dyn Store>,
+    scope: String,
+}
+
+impl StateService {
+    /// `identity` is the connection's authenticated identity; connections
+    /// without one share the `anonymous` scope.
+    pub fn new(store: Arc<dyn Store>, identity: Option<String>) -> Self {
+        Self {
+            store,
+            scope: identity.unwrap_or_else(|| "anonymous".into()),
+        }
+    }
+
+    fn get(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let params: KeyParams = match parse_params(req_id, params) {
+            Ok(p) => p,
+            Err(resp) => return resp,
+        };
+        match self.store.get_state(&self.scope, &params.key) {
+            Ok(Some(entry)) => Response::success(
+                req_id,
+                json!({ "key": entry.key, "value": entry.value, "updated_at": entry.updated_at }),
+            ),
+            Ok(None) => Response::success(
+                req_id,
+                json!({ "key": params.key, "value": null, "updated_at": null }),
+            ),
+            Err(e) => Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+        }
+    }
+
+    fn set(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let params: SetParams = match parse_params(req_id, params) {
+            Ok(p) => p,
+            Err(resp) => return resp,
+        };
+        if let Err(msg) = validate_key(&params.key) {
+            return Response::error(req_id, error_codes::INVALID_PARAMS, msg);
+        }
+        let size = serde_json::to_vec(&params.value)
+            .map(|v| v.len())
+            .unwrap_or(usize::MAX);
+        if size > MAX_VALUE_BYTES {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("value exceeds {MAX_VALUE_BYTES} bytes"),
+            );
+        }
+
+        match self
+            .store
+            .set_state(&self.scope, &params.key, &params.value, MAX_KEYS_PER_SCOPE)
+        {
+            Ok(true) => Response::success(req_id, json!({ "ok": true })),
+            Ok(false) => Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("too many keys (max {MAX_KEYS_PER_SCOPE})"),
+            ),
+            Err(e) => Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+        }
+    }
+
+    fn delete(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let params: KeyParams = match parse_params(req_id, params) {
+            Ok(p) => p,
+            Err(resp) => return resp,
+        };
+        match self.store.delete_state(&self.scope, &params.key) {
+            Ok(deleted) => Response::success(req_id, json!({ "deleted": deleted })),
+            Err(e) => Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+        }
+    }
+
+    fn list(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let params: ListParams = match params {
+            Some(v) => match parse_params(req_id, Some(v)) {
+                Ok(p) => p,
+                Err(resp) => return resp,
+            },
+            None => ListParams::default(),
+        };
+        match self.store.list_state(&self.scope, &params.prefix) {
+            Ok(entries) => Response::success(req_id, json!({ "entries": entries })),
+            Err(e) => Response::error(req_id, error_codes::INTERNAL_ERROR, e),
+        }
+    }
+}
+
+impl StateService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("state.get", Scope::StateRead),
+        ("state.list", Scope::StateRead),
+        ("state.set", Scope::StateWrite),
+        ("state.delete", Scope::StateWrite),
+    ];
+}
+
+impl ServiceHandler for StateService {
+    fn namespace(&self) -> &str {
+        "state"
+    }
+
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
+    fn handle_request(
+        &mut self,
+        id: Uuid,
+        method: &str,
+        params: Option<Value>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + '_>> {
+        let resp = match method {
+            "state.get" => self.get(id, params),
+            "state.set" => self.set(id, params),
+            "state.delete" => self.delete(id, params),
+            "state.list" => self.list(id, params),
+            _ => Response::error(
+                id,
+                error_codes::METHOD_NOT_FOUND,
+                format!("unknown method: {method}"),
+            ),
+        };
+        Box::pin(async move { resp })
+    }
+
+    fn handle_binary(&mut self, _frame: &BinaryFrame) {}
+
+    fn reap(&mut self) -> Vec<ReapEvent> {
+        Vec::new()
+    }
+
+    fn shutdown(&mut self) {}
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(
+    req_id: Uuid,
+    params: Option<Value>,
+) -> Result<T, Response> {
+    match params {
+        Some(v) => serde_json::from_value(v).map_err(|e| {
+            Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("invalid params: {e}"),
+            )
+        }),
+        None => Err(Response::error(
+            req_id,
+            error_codes::INVALID_PARAMS,
+            "missing params",
+        )),
+    }
+}
+
+fn validate_key(key: &str) -> Result<(), String> {
+    if key.trim().is_empty() {
+        return Err("key must not be empty".into());
+    }
+    if key.len() > MAX_KEY_LEN {
+        return Err(format!("key exceeds {MAX_KEY_LEN} bytes"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+
+    fn result(resp: Response) -> Value {
+        assert!(resp.error.is_none(), "{:?}", resp.error);
+        resp.result.expect("result")
+    }
+
+    #[test]
+    fn set_get_list_delete_roundtrip() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        let mut svc = StateService::new(store.clone(), Some("alice@example.com".into()));
+        let mut other = StateService::new(store, Some("bob@example.com".into()));
+        let id = Uuid::new_v4();
+
+        result(svc.set(id, Some(json!({ "key": "ui.theme", "value": "dark" }))));
+        result(svc.set(
+            id,
+            Some(json!({ "key": "ui.last_chat", "value": { "chat_id": "c1" } })),
+        ));
+        result(svc.set(id, Some(json!({ "key": "flags", "value": [1, 2] }))));
+        result(svc.set(id, Some(json!({ "key": "ui.theme", "value": "light" }))));
+
+        let got = result(svc.get(id, Some(json!({ "key": "ui.theme" }))));
+        assert_eq!(got["value"], "light");
+        let missing = result(other.get(id, Some(json!({ "key": "ui.theme" }))));
+        assert!(missing["value"].is_null());
+
+        let listed = result(svc.list(id, Some(json!({ "prefix": "ui." }))));
+        let keys: Vec<&str> = listed["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["key"].as_str().unwrap())
+            .collect();
+        assert_eq!(keys, vec!["ui.last_chat", "ui.theme"]);
+        assert_eq!(
+            result(svc.list(id, None))["entries"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+        assert!(result(other.list(id, None))["entries"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            result(svc.delete(id, Some(json!({ "key": "flags" }))))["deleted"],
+            true
+        );
+        assert_eq!(
+            result(svc.delete(id, Some(json!({ "key": "flags" }))))["deleted"],
+            false
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_values_and_bad_keys() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        let mut svc = StateService::new(store, None);
+        let id = Uuid::new_v4();
+
+        let big = "x".repeat(MAX_VALUE_BYTES);
+        let resp = svc.set(id, Some(json!({ "key": "big", "value": big })));
+        assert_eq!(resp.error.unwrap().code, error_codes::INVALID_PARAMS);
+        let resp = svc.set(id, Some(json!({ "key": " ", "value": 1 })));
+        assert_eq!(resp.error.unwrap().code, error_codes::INVALID_PARAMS);
+        let resp = svc.set(id, Some(json!({ "value": 1 })));
+        assert_eq!(resp.error.unwrap().code, error_codes::INVALID_PARAMS);
+    }
+}
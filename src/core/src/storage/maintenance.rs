@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use super::{RetentionPolicy, Store};
+use crate::shutdown::ShutdownSignal;
+
+/// Raw provider events are kept for this many most recent runs.
+pub const CHAT_RAW_EVENT_MAX_RUNS: usize = 10;
+
+/// Run `Store::run_maintenance` every `interval` until `shutdown` fires.
+/// The first pass runs one interval after startup. A zero interval disables
+/// the task.
+pub fn spawn_store_maintenance(
+    store: Arc<dyn Store>,
+    policy: RetentionPolicy,
+    interval: Duration,
+    shutdown: ShutdownSignal,
+) -> Option<JoinHandle<()>> {
+    if interval.is_zero() {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let mut tick = tokio::time::interval(interval);
+        tick.tick().await;
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {}
+                _ = shutdown.wait() => break,
+            }
+            let store = store.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let report = store.run_maintenance(&policy)?;
+                Ok::<_, String>((report, store.count_chat_raw_events().unwrap_or_default()))
+            })
+            .await;
+            match result {
+                Ok(Ok((report, raw_events))) => info!(
+                    rows_removed = report.rows_removed(),
+                    vacuumed = report.vacuumed,
+                    raw_events = raw_events.events,
+                    raw_event_runs = raw_events.runs,
+                    "store maintenance finished"
+                ),
+                Ok(Err(err)) => warn!(error = %err, "store maintenance failed"),
+                Err(err) => warn!(error = %err, "store maintenance task panicked"),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+
+    fn policy() -> RetentionPolicy {
+        RetentionPolicy {
+            job_retention_days: 7,
+            job_max_records: 1,
+            pairing_retention_secs: 86_400,
+            notification_retention_days: 30,
+            chat_raw_event_max_runs: CHAT_RAW_EVENT_MAX_RUNS,
+            cron_retention_days: 30,
+            cron_max_run_records: 500,
+            terminal_recording_retention_days: 30,
+            terminal_recording_max_records: 100,
+            audit_retention_days: 90,
+            tool_invocation_retention_days: 90,
+        }
+    }
+
+    #[tokio::test]
+    async fn maintenance_task_stops_on_shutdown() {
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        let shutdown = ShutdownSignal::new();
+        let task =
+            spawn_store_maintenance(store, policy(), Duration::from_millis(10), shutdown.clone())
+                .expect("task spawned");
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .expect("task exits")
+            .unwrap();
+        assert!(spawn_store_maintenance(
+            Arc::new(SqliteStore::open_memory().unwrap()),
+            policy(),
+            Duration::ZERO,
+            ShutdownSignal::new(),
+        )
+        .is_none());
+    }
+}
@@ -1,11 +1,18 @@
+mod maintenance;
+mod search;
 mod sqlite;
 mod types;
 
+pub use maintenance::{spawn_store_maintenance, CHAT_RAW_EVENT_MAX_RUNS};
+
 pub use sqlite::SqliteStore;
 pub use types::{
-    ChatRawEventRecord, ChatRecord, CronRecord, CronRunRecord, CronRunStatus, CronStatus,
-    JobRecord, JobStatus, NotificationEvent, NotificationSubscription, PairingRecord,
-    PairingStatus, SessionStatus, TerminalRecord,
+    AttachmentRecord, AuditEntry, BusEventRecord, ChatRawEventRecord, ChatRecord, ChatSearchHit,
+    CronRecord, CronRunRecord, CronRunStatus, CronStatus, JobRecord, JobStatus, LoginSessionRecord,
+    MaintenanceReport, NotificationEvent, NotificationSubscription, PairingRecord, PairingStatus,
+    PendingRunRecord, RawEventCounts, RetentionPolicy, SessionStatus, StateEntry, TerminalRecord,
+    TerminalRecordingRecord, TerminalSpawn, ToolInvocationRecord, TurnUsageRecord, UsageBucket,
+    UsageGroupBy, UsageQuery,
 };
 
 use uuid::Uuid;
@@ -22,7 +29,11 @@ pub trait Store: Send + Sync + 'static {
     /// Get a chat by ID.
     fn get_chat(&self, chat_id: &str) -> Result<Option<ChatRecord>, String>;
 
-    /// List all chats, ordered by created_at descending.
+    /// Get the chat a thread belongs to.
+    fn get_chat_by_thread(&self, thread_id: &str) -> Result<Option<ChatRecord>, String>;
+
+    /// List all chats: pinned ones (`"pinned": true` in their settings)
+    /// first, then by created_at descending.
     fn list_chats(&self) -> Result<Vec<ChatRecord>, String>;
 
     /// Delete a chat by ID.
@@ -51,6 +62,31 @@ pub trait Store: Send + Sync + 'static {
     /// Delete persisted provider thread state by thread ID.
     fn delete_chat_thread_state(&self, thread_id: &str) -> Result<(), String>;
 
+    /// Persist a run queued behind its thread's active run or waiting for a
+    /// run slot. Persisting a run again keeps its place in the queue.
+    fn insert_pending_run(&self, run: &PendingRunRecord) -> Result<(), String>;
+
+    /// Forget a queued run once it starts or is canceled.
+    fn delete_pending_run(&self, turn_id: &str) -> Result<(), String>;
+
+    /// Forget every queued run of a thread.
+    fn delete_pending_runs_for_thread(&self, thread_id: &str) -> Result<(), String>;
+
+    /// Hand a queued run whose owner went away over to the next caller of
+    /// `take_pending_runs`.
+    fn release_pending_run(&self, turn_id: &str) -> Result<(), String>;
+
+    /// Release every queued run. Called on startup, when no run has an owner.
+    fn release_all_pending_runs(&self) -> Result<(), String>;
+
+    /// Chats with released queued runs, in the order their runs were queued.
+    fn list_released_pending_run_chats(&self) -> Result<Vec<String>, String>;
+
+    /// Claim and return a chat's released queued runs, oldest first. Claim
+    /// and read are atomic so only one caller restores each run; the runs
+    /// stay persisted until they start or are deleted.
+    fn take_pending_runs(&self, chat_id: &str) -> Result<Vec<PendingRunRecord>, String>;
+
     /// Persist or update a terminal session record.
     fn upsert_terminal(&self, rec: &TerminalRecord) -> Result<(), String>;
 
@@ -63,9 +99,29 @@ pub trait Store: Send + Sync + 'static {
     /// Delete a terminal session record by ID.
     fn delete_terminal(&self, session_id: Uuid) -> Result<(), String>;
 
+    /// Persist a finished terminal recording.
+    fn insert_terminal_recording(&self, rec: &TerminalRecordingRecord) -> Result<(), String>;
+
+    /// Get a terminal recording by ID.
+    fn get_terminal_recording(
+        &self,
+        recording_id: &str,
+    ) -> Result<Option<TerminalRecordingRecord>, String>;
+
+    /// Remove recordings older than the retention window or beyond the
+    /// newest `max_records`. Returns the number of rows removed.
+    fn prune_terminal_recordings(
+        &self,
+        retention_days: u64,
+        max_records: usize,
+    ) -> Result<usize, String>;
+
     /// Mark all active sessions as inactive (used on server restart).
     fn mark_all_inactive(&self) -> Result<(), String>;
 
+    /// Cheap round trip to the backend, for health checks.
+    fn ping(&self) -> Result<(), String>;
+
     /// Persist or update a job record.
     fn upsert_job(&self, job: &JobRecord) -> Result<(), String>;
 
@@ -75,8 +131,8 @@ pub trait Store: Send + Sync + 'static {
     /// List all jobs, ordered by created_at descending.
     fn list_jobs(&self) -> Result<Vec<JobRecord>, String>;
 
-    /// Remove expired or excess jobs.
-    fn prune_jobs(&self, retention_days: u64, max_jobs: usize) -> Result<(), String>;
+    /// Remove expired or excess jobs. Returns the number of rows removed.
+    fn prune_jobs(&self, retention_days: u64, max_jobs: usize) -> Result<usize, String>;
 
     /// Persist or update a pairing record.
     fn upsert_pairing(&self, pairing: &PairingRecord) -> Result<(), String>;
@@ -88,7 +144,33 @@ pub trait Store: Send + Sync + 'static {
     fn list_pairings(&self) -> Result<Vec<PairingRecord>, String>;
 
     /// Remove expired pairings beyond retention window.
-    fn prune_pairings(&self, retention_secs: u64) -> Result<(), String>;
+    /// Returns the number of rows removed.
+    fn prune_pairings(&self, retention_secs: u64) -> Result<usize, String>;
+
+    /// Persist or replace the pending device-code login for a provider/profile.
+    fn upsert_login_session(&self, session: &LoginSessionRecord) -> Result<(), String>;
+
+    /// Get the pending device-code login for a provider/profile.
+    fn get_login_session(
+        &self,
+        provider: &str,
+        profile: &str,
+    ) -> Result<Option<LoginSessionRecord>, String>;
+
+    /// List pending device-code logins, ordered by created_at descending.
+    fn list_login_sessions(&self) -> Result<Vec<LoginSessionRecord>, String>;
+
+    /// Delete pending logins, optionally filtered by provider and/or profile.
+    /// Returns the number of rows removed.
+    fn delete_login_sessions(
+        &self,
+        provider: Option<&str>,
+        profile: Option<&str>,
+    ) -> Result<usize, String>;
+
+    /// Remove pending logins whose device code has expired.
+    /// Returns the number of rows removed.
+    fn prune_login_sessions(&self) -> Result<usize, String>;
 
     /// Persist or update a notification subscription.
     fn upsert_notification_subscription(
@@ -105,8 +187,30 @@ pub trait Store: Send + Sync + 'static {
     /// Insert a notification event for audit/retention.
     fn insert_notification_event(&self, event: &NotificationEvent) -> Result<(), String>;
 
+    /// List notification events, newest first.
+    fn list_notification_events(&self, limit: usize) -> Result<Vec<NotificationEvent>, String>;
+
+    /// List undelivered notification events with fewer than `max_attempts`
+    /// attempts that are due for delivery at `now`, longest due first.
+    fn list_undelivered_notification_events(
+        &self,
+        max_attempts: u32,
+        now: u64,
+        limit: usize,
+    ) -> Result<Vec<NotificationEvent>, String>;
+
+    /// Count one delivery attempt, marking the event delivered when
+    /// `delivered_at` is set and otherwise not due again before `retry_at`.
+    fn record_notification_attempt(
+        &self,
+        notification_id: &str,
+        delivered_at: Option<u64>,
+        retry_at: u64,
+    ) -> Result<(), String>;
+
     /// Remove notification records beyond retention window.
-    fn prune_notifications(&self, retention_days: u64) -> Result<(), String>;
+    /// Returns the number of rows removed.
+    fn prune_notifications(&self, retention_days: u64) -> Result<usize, String>;
 
     /// Insert a raw provider event for debugging.
     fn insert_chat_raw_event(
@@ -124,18 +228,152 @@ pub trait Store: Send + Sync + 'static {
         limit: usize,
     ) -> Result<Vec<ChatRawEventRecord>, String>;
 
+    /// List raw provider events for a thread stored at or after chat event
+    /// pointer `pointer` with `seq > after_seq`, in sequence order.
+    fn list_chat_raw_events_since(
+        &self,
+        thread_id: &str,
+        pointer: u64,
+        after_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<ChatRawEventRecord>, String>;
+
+    /// Lowest event pointer still retained for a thread's raw events.
+    fn oldest_chat_raw_event_pointer(&self, thread_id: &str) -> Result<Option<u64>, String>;
+
+    /// Full-text search over the text of chats `owner` may see: unowned
+    /// chats and the ones it created. Returns the best-matching message per
+    /// chat, highest score first.
+    fn search_chats(
+        &self,
+        query: &str,
+        owner: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ChatSearchHit>, String>;
+
+    /// Drop the given items of a thread from the search index, e.g. turns
+    /// cut off by a regenerate.
+    fn unindex_chat_items(&self, thread_id: &str, item_ids: &[String]) -> Result<(), String>;
+
+    /// Drop indexed messages of threads no chat refers to any more.
+    /// Returns the number of messages removed.
+    fn prune_search_index(&self) -> Result<usize, String>;
+
     /// Prune raw provider events to keep only the latest runs.
-    fn prune_chat_raw_events(&self, max_runs: usize) -> Result<(), String>;
+    /// Returns the number of rows removed.
+    fn prune_chat_raw_events(&self, max_runs: usize) -> Result<usize, String>;
+
+    /// How many raw provider events and runs are stored, for sizing
+    /// `chat.raw_event_retention`.
+    fn count_chat_raw_events(&self) -> Result<RawEventCounts, String>;
+
+    /// Record a tool invocation in the per-thread audit trail.
+    fn insert_tool_invocation(&self, record: &ToolInvocationRecord) -> Result<(), String>;
+
+    /// List audited tool invocations for a thread, newest first.
+    fn list_tool_invocations(
+        &self,
+        thread_id: &str,
+        limit: usize,
+    ) -> Result<Vec<ToolInvocationRecord>, String>;
+
+    /// Remove tool invocations older than the retention window. Returns the
+    /// number removed.
+    fn prune_tool_invocations(&self, retention_days: u64) -> Result<usize, String>;
+
+    /// Store a tool result attachment.
+    fn insert_attachment(&self, attachment: &AttachmentRecord) -> Result<(), String>;
+
+    /// Get an attachment by ID.
+    fn get_attachment(&self, attachment_id: &str) -> Result<Option<AttachmentRecord>, String>;
+
+    /// Remove every attachment of a thread. Returns the number removed.
+    fn delete_attachments_for_thread(&self, thread_id: &str) -> Result<usize, String>;
+
+    /// Add a usage report to its turn's totals, creating the turn's row on
+    /// the first report.
+    fn add_turn_usage(&self, usage: &TurnUsageRecord) -> Result<(), String>;
+
+    /// Usage of the turns `query` selects, one bucket per day or model,
+    /// ordered by key.
+    fn usage_summary(&self, query: &UsageQuery) -> Result<Vec<UsageBucket>, String>;
+
+    /// Append an event to the cross-node event bus. Returns its `seq`.
+    fn insert_bus_event(&self, event: &BusEventRecord) -> Result<u64, String>;
+
+    /// Up to `limit` bus events with a `seq` above `after_seq`, oldest
+    /// first.
+    fn list_bus_events_after(
+        &self,
+        after_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<BusEventRecord>, String>;
+
+    /// Highest bus event `seq`, or 0 when there are none.
+    fn latest_bus_event_seq(&self) -> Result<u64, String>;
+
+    /// Remove bus events created before `before`. Returns the number
+    /// removed.
+    fn prune_bus_events(&self, before: u64) -> Result<usize, String>;
+
+    /// Append entries to the connection audit log in one transaction.
+    fn insert_audit_entries(&self, entries: &[AuditEntry]) -> Result<(), String>;
+
+    /// Remove audit log entries older than the retention window. Returns
+    /// the number removed.
+    fn prune_audit_log(&self, retention_days: u64) -> Result<usize, String>;
+
+    /// List audit log entries, newest first, optionally only those for
+    /// `method` and/or `identity`.
+    fn list_audit_entries(
+        &self,
+        method: Option<&str>,
+        identity: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>, String>;
 
     /// Persist or update a cron record.
     fn upsert_cron(&self, cron: &CronRecord) -> Result<(), String>;
 
+    /// Update only the retry state of a cron (count and pending retry time).
+    fn update_cron_retry(
+        &self,
+        cron_id: &str,
+        retry_count: u32,
+        next_retry_at: Option<u64>,
+    ) -> Result<(), String>;
+
     /// Get a cron by ID.
     fn get_cron(&self, cron_id: &str) -> Result<Option<CronRecord>, String>;
 
     /// List all crons, ordered by created_at descending.
     fn list_crons(&self) -> Result<Vec<CronRecord>, String>;
 
+    /// Atomically lease the active crons that are due at `now` (a scheduled
+    /// or retry time has passed, or the next run was never computed) to
+    /// `worker_id` for `lease_secs`, and return them. Crons leased to
+    /// another worker are skipped until that lease expires; runs still
+    /// marked running under an expired lease are failed first, since the
+    /// worker that owned them is gone.
+    fn claim_due_crons(
+        &self,
+        now: u64,
+        worker_id: &str,
+        lease_secs: u64,
+    ) -> Result<Vec<CronRecord>, String>;
+
+    /// Extend `worker_id`'s lease on a cron to `expires_at`. Returns false
+    /// when the worker no longer holds the lease.
+    fn renew_cron_lease(
+        &self,
+        cron_id: &str,
+        worker_id: &str,
+        expires_at: u64,
+    ) -> Result<bool, String>;
+
+    /// Drop `worker_id`'s lease on a cron, if it still holds it.
+    fn release_cron_lease(&self, cron_id: &str, worker_id: &str) -> Result<(), String>;
+
     /// Remove a cron by ID.
     fn delete_cron(&self, cron_id: &str) -> Result<(), String>;
 
@@ -157,6 +395,31 @@ pub trait Store: Send + Sync + 'static {
     /// Return true if there is an in-flight run for the cron.
     fn cron_has_running(&self, cron_id: &str) -> Result<bool, String>;
 
-    /// Remove old/inactive cron runs.
-    fn prune_cron_runs(&self, retention_days: u64, max_runs: usize) -> Result<(), String>;
+    /// Remove old/inactive cron runs. Returns the number of rows removed.
+    fn prune_cron_runs(&self, retention_days: u64, max_runs: usize) -> Result<usize, String>;
+
+    /// Get a state value by key within `scope`.
+    fn get_state(&self, scope: &str, key: &str) -> Result<Option<StateEntry>, String>;
+
+    /// Persist or replace a state value within `scope`. A new key is only
+    /// added while `scope` holds fewer than `max_keys`; returns `false`
+    /// without writing when it is full. The check and the write share one
+    /// transaction.
+    fn set_state(
+        &self,
+        scope: &str,
+        key: &str,
+        value: &serde_json::Value,
+        max_keys: usize,
+    ) -> Result<bool, String>;
+
+    /// Delete a state value. Returns whether the key existed.
+    fn delete_state(&self, scope: &str, key: &str) -> Result<bool, String>;
+
+    /// List state entries within `scope` whose key starts with `prefix`,
+    /// ordered by key.
+    fn list_state(&self, scope: &str, prefix: &str) -> Result<Vec<StateEntry>, String>;
+
+    /// Run every prune with `policy`, then let the backend reclaim space.
+    fn run_maintenance(&self, policy: &RetentionPolicy) -> Result<MaintenanceReport, String>;
 }
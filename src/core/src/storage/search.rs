@@ -0,0 +1,384 @@
+//! Full-text index over chat message text, backing `Store::search_chats`.
+//!
+//! Messages are indexed as they are written: completed user/assistant items
+//! from raw provider events, and every turn of each thread snapshot.
+//! `chat_search_items` maps `(thread_id, item_id)` to the FTS row and keeps a
+//! text hash so unchanged messages are not rewritten.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use super::types::ChatSearchHit;
+
+/// Rows fetched per requested hit; several hits can belong to one chat.
+const HITS_PER_RESULT: usize = 4;
+
+/// Create the index tables. Returns `true` when they did not exist yet, so
+/// the caller can backfill existing chats once.
+pub(super) fn create_index(conn: &Connection) -> Result<bool, String> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'chat_search')",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("migrate chat_search lookup: {e}"))?;
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS chat_search_items (
+            id         INTEGER PRIMARY KEY,
+            thread_id  TEXT NOT NULL,
+            item_id    TEXT NOT NULL,
+            role       TEXT NOT NULL,
+            text_hash  TEXT NOT NULL,
+            UNIQUE (thread_id, item_id)
+        );
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS chat_search USING fts5(
+            text,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+        ",
+    )
+    .map_err(|e| format!("migrate chat_search: {e}"))?;
+    Ok(!exists)
+}
+
+/// Index every message already persisted in snapshots and raw events.
+pub(super) fn backfill(conn: &Connection) -> Result<(), String> {
+    let states = {
+        let mut stmt = conn
+            .prepare("SELECT thread_id, state_json FROM chat_thread_states")
+            .map_err(|e| format!("chat_search backfill prepare: {e}"))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| format!("chat_search backfill query: {e}"))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("chat_search backfill collect: {e}"))?
+    };
+    for (thread_id, raw) in states {
+        let Ok(state) = serde_json::from_str::<Value>(&raw) else {
+            continue;
+        };
+        for turn in snapshot_turns(&state) {
+            index_turn(conn, &thread_id, turn)?;
+        }
+    }
+
+    let events = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT thread_id, params_json FROM chat_raw_events
+                 WHERE method = 'item/completed'
+                 ORDER BY rowid ASC",
+            )
+            .map_err(|e| format!("chat_search backfill prepare: {e}"))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| format!("chat_search backfill query: {e}"))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("chat_search backfill collect: {e}"))?
+    };
+    for (thread_id, raw) in events {
+        if let Ok(params) = serde_json::from_str::<Value>(&raw) {
+            index_raw_event(conn, &thread_id, "item/completed", &params)?;
+        }
+    }
+    Ok(())
+}
+
+/// Index the message carried by a completed item event, if any.
+pub(super) fn index_raw_event(
+    conn: &Connection,
+    thread_id: &str,
+    method: &str,
+    params: &Value,
+) -> Result<(), String> {
+    if method != "item/completed" {
+        return Ok(());
+    }
+    match params.get("item").and_then(message_from_item) {
+        Some((item_id, role, text)) => index_message(conn, thread_id, item_id, role, &text),
+        None => Ok(()),
+    }
+}
+
+/// Index every turn of a thread snapshot, so turns written all at once
+/// (imports, forks) or edited after the fact are found too. Messages whose
+/// text is unchanged are skipped.
+pub(super) fn index_thread_state(
+    conn: &Connection,
+    thread_id: &str,
+    state: &Value,
+) -> Result<(), String> {
+    for turn in snapshot_turns(state) {
+        index_turn(conn, thread_id, turn)?;
+    }
+    Ok(())
+}
+
+/// Drop every indexed message of a thread.
+pub(super) fn delete_thread(conn: &Connection, thread_id: &str) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM chat_search
+         WHERE rowid IN (SELECT id FROM chat_search_items WHERE thread_id = ?1)",
+        params![thread_id],
+    )
+    .map_err(|e| format!("chat_search delete: {e}"))?;
+    conn.execute(
+        "DELETE FROM chat_search_items WHERE thread_id = ?1",
+        params![thread_id],
+    )
+    .map_err(|e| format!("chat_search delete items: {e}"))?;
+    Ok(())
+}
+
+/// Drop the indexed messages of `item_ids` in a thread.
+pub(super) fn delete_items(
+    conn: &Connection,
+    thread_id: &str,
+    item_ids: &[String],
+) -> Result<(), String> {
+    for item_id in item_ids {
+        conn.execute(
+            "DELETE FROM chat_search
+             WHERE rowid IN (SELECT id FROM chat_search_items
+                             WHERE thread_id = ?1 AND item_id = ?2)",
+            params![thread_id, item_id],
+        )
+        .map_err(|e| format!("chat_search delete: {e}"))?;
+        conn.execute(
+            "DELETE FROM chat_search_items WHERE thread_id = ?1 AND item_id = ?2",
+            params![thread_id, item_id],
+        )
+        .map_err(|e| format!("chat_search delete items: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Drop indexed messages of threads that no chat points at, e.g. left
+/// behind by a thread that was never saved as a chat. Returns the number of
+/// messages removed.
+pub(super) fn delete_orphans(conn: &Connection) -> Result<usize, String> {
+    const ORPHANS: &str = "SELECT id FROM chat_search_items
+         WHERE thread_id NOT IN (SELECT thread_id FROM chats WHERE thread_id IS NOT NULL)";
+    conn.execute(
+        &format!("DELETE FROM chat_search WHERE rowid IN ({ORPHANS})"),
+        [],
+    )
+    .map_err(|e| format!("chat_search prune: {e}"))?;
+    conn.execute(
+        &format!("DELETE FROM chat_search_items WHERE id IN ({ORPHANS})"),
+        [],
+    )
+    .map_err(|e| format!("chat_search prune items: {e}"))
+}
+
+/// Best-matching message per chat open to `owner` (unowned chats and
+/// chats `owner` created), highest `score` first.
+pub(super) fn search(
+    conn: &Connection,
+    query: &str,
+    owner: Option<&str>,
+    limit: usize,
+) -> Result<Vec<ChatSearchHit>, String> {
+    let Some(fts_query) = fts_query(query) else {
+        return Ok(Vec::new());
+    };
+    let limit = limit.clamp(1, 200);
+    let mut stmt = conn
+        .prepare(
+            "SELECT c.chat_id, i.thread_id,
+                    snippet(chat_search, 0, '[', ']', '…', 12),
+                    bm25(chat_search)
+             FROM chat_search
+             JOIN chat_search_items i ON i.id = chat_search.rowid
+             JOIN chats c ON c.thread_id = i.thread_id
+             WHERE chat_search MATCH ?1
+               AND (c.owner IS NULL OR c.owner = ?3)
+             ORDER BY bm25(chat_search)
+             LIMIT ?2",
+        )
+        .map_err(|e| format!("search_chats prepare: {e}"))?;
+    let rows = stmt
+        .query_map(
+            params![fts_query, (limit * HITS_PER_RESULT) as i64, owner],
+            |row| {
+                Ok(ChatSearchHit {
+                    chat_id: row.get(0)?,
+                    thread_id: row.get(1)?,
+                    snippet: row.get(2)?,
+                    // bm25 is lower-is-better; flip it so clients sort descending.
+                    score: -row.get::<_, f64>(3)?,
+                })
+            },
+        )
+        .map_err(|e| format!("search_chats query: {e}"))?;
+
+    let mut hits: Vec<ChatSearchHit> = Vec::new();
+    for row in rows {
+        let hit = row.map_err(|e| format!("search_chats row: {e}"))?;
+        if hits.iter().any(|existing| existing.chat_id == hit.chat_id) {
+            continue;
+        }
+        hits.push(hit);
+        if hits.len() == limit {
+            break;
+        }
+    }
+    Ok(hits)
+}
+
+fn index_turn(conn: &Connection, thread_id: &str, turn: &Value) -> Result<(), String> {
+    let Some(items) = turn.get("items").and_then(Value::as_array) else {
+        return Ok(());
+    };
+    for (item_id, role, text) in items.iter().filter_map(message_from_item) {
+        index_message(conn, thread_id, item_id, role, &text)?;
+    }
+    Ok(())
+}
+
+fn index_message(
+    conn: &Connection,
+    thread_id: &str,
+    item_id: &str,
+    role: &str,
+    text: &str,
+) -> Result<(), String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(());
+    }
+    let text_hash = text_hash(text);
+    let existing: Option<(i64, String)> = conn
+        .query_row(
+            "SELECT id, text_hash FROM chat_search_items WHERE thread_id = ?1 AND item_id = ?2",
+            params![thread_id, item_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| format!("chat_search lookup: {e}"))?;
+    let id = match existing {
+        Some((_, hash)) if hash == text_hash => return Ok(()),
+        Some((id, _)) => {
+            conn.execute("DELETE FROM chat_search WHERE rowid = ?1", params![id])
+                .map_err(|e| format!("chat_search replace: {e}"))?;
+            conn.execute(
+                "UPDATE chat_search_items SET text_hash = ?1 WHERE id = ?2",
+                params![text_hash, id],
+            )
+            .map_err(|e| format!("chat_search replace item: {e}"))?;
+            id
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO chat_search_items (thread_id, item_id, role, text_hash)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![thread_id, item_id, role, text_hash],
+            )
+            .map_err(|e| format!("chat_search insert item: {e}"))?;
+            conn.last_insert_rowid()
+        }
+    };
+    conn.execute(
+        "INSERT INTO chat_search (rowid, text) VALUES (?1, ?2)",
+        params![id, text],
+    )
+    .map_err(|e| format!("chat_search insert: {e}"))?;
+    Ok(())
+}
+
+/// Turns of a persisted snapshot, accepting both the current
+/// `{ "thread": { "turns": [...] } }` shape and a bare legacy thread.
+fn snapshot_turns(state: &Value) -> &[Value] {
+    state
+        .get("thread")
+        .unwrap_or(state)
+        .get("turns")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// `(item_id, role, text)` for user and assistant message items.
+fn message_from_item(item: &Value) -> Option<(&str, &'static str, String)> {
+    let item_id = item.get("id")?.as_str()?;
+    match item.get("type")?.as_str()? {
+        "userMessage" => {
+            let text = match item.get("text").and_then(Value::as_str) {
+                Some(text) => text.to_string(),
+                None => item
+                    .get("content")?
+                    .as_array()?
+                    .iter()
+                    .filter(|part| part.get("type").and_then(Value::as_str) == Some("text"))
+                    .filter_map(|part| part.get("text").and_then(Value::as_str))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            Some((item_id, "user", text))
+        }
+        "agentMessage" => Some((
+            item_id,
+            "assistant",
+            item.get("text")?.as_str()?.to_string(),
+        )),
+        _ => None,
+    }
+}
+
+/// Quote each term so user input never reaches the FTS5 query syntax; the
+/// terms are ANDed and the last one matches as a prefix.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", terms.join(" ")))
+}
+
+fn text_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fts_query_quotes_terms() {
+        assert_eq!(fts_query("   "), None);
+        assert_eq!(
+            fts_query(r#"deploy "prod" OR"#).as_deref(),
+            Some(r#""deploy" """prod""" "OR"*"#)
+        );
+    }
+
+    #[test]
+    fn extracts_messages_from_items() {
+        let user = json!({
+            "id": "u1",
+            "type": "userMessage",
+            "content": [{ "type": "text", "text": "hello" }, { "type": "image" }],
+        });
+        assert_eq!(
+            message_from_item(&user),
+            Some(("u1", "user", "hello".to_string()))
+        );
+        let tool = json!({ "id": "t1", "type": "mcpToolCall", "tool": "exec" });
+        assert_eq!(message_from_item(&tool), None);
+    }
+}
@@ -1,17 +1,23 @@
 use std::path::Path;
 use std::sync::Mutex;
 
-use rusqlite::{params, types::Type, Connection};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, TransactionBehavior};
 use uuid::Uuid;
 
+use super::search;
 use super::types::{
-    ChatRawEventRecord, ChatRecord, CronRecord, CronRunRecord, CronRunStatus, CronStatus,
-    JobRecord, JobStatus, NotificationEvent, NotificationSubscription, PairingRecord,
-    PairingStatus, SessionStatus, TerminalRecord,
+    AttachmentRecord, AuditEntry, BusEventRecord, ChatRawEventRecord, ChatRecord, ChatSearchHit,
+    CronRecord, CronRunRecord, CronRunStatus, CronStatus, JobRecord, JobStatus, LoginSessionRecord,
+    MaintenanceReport, NotificationEvent, NotificationSubscription, PairingRecord, PairingStatus,
+    PendingRunRecord, RawEventCounts, RetentionPolicy, SessionStatus, StateEntry, TerminalRecord,
+    TerminalRecordingRecord, ToolInvocationRecord, TurnUsageRecord, UsageBucket, UsageGroupBy,
+    UsageQuery,
 };
 use super::Store;
 
 const MAX_RAW_EVENT_BYTES: usize = 64 * 1024;
+/// `run_maintenance` vacuums once at least 1/N of the pages are free.
+const VACUUM_FREE_PAGE_RATIO: i64 = 4;
 
 /// SQLite-backed store for chat + terminal metadata.
 ///
@@ -43,10 +49,82 @@ impl SqliteStore {
     }
 
     fn migrate(&self) -> Result<(), String> {
-        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        apply_migrations(&mut conn, MIGRATIONS)
+    }
+}
 
-        conn.execute_batch(
-            "
+/// One schema step, run inside a transaction.
+type Migration = fn(&Connection) -> Result<(), String>;
+
+/// Ordered schema migrations: `MIGRATIONS[n]` upgrades a database at version
+/// `n` to `n + 1`. Append new steps; never edit or reorder released ones.
+const MIGRATIONS: &[Migration] = &[
+    migrate_base_schema,
+    migrate_chat_search,
+    migrate_terminal_recordings,
+    migrate_state_entries,
+    migrate_pending_runs,
+    migrate_audit_log,
+    migrate_terminal_spawn,
+    migrate_cron_leases,
+    migrate_chat_attachments,
+    migrate_turn_usage,
+    migrate_bus_events,
+    migrate_owners,
+    migrate_raw_event_pointers,
+    migrate_notification_retry_schedule,
+    migrate_audit_log_timestamp_index,
+];
+
+/// Bring the database up to `migrations.len()`, recording progress in
+/// `schema_version`. Databases created before versioning start at 0, so the
+/// first steps must tolerate tables and columns that already exist.
+fn apply_migrations(conn: &mut Connection, migrations: &[Migration]) -> Result<(), String> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
+        .map_err(|e| format!("migrate schema_version: {e}"))?;
+    let current = schema_version(conn)?;
+    if current > migrations.len() {
+        return Err(format!(
+            "database schema version {current} is newer than this build supports ({}); \
+             upgrade homie or point HOMIE_DB_PATH at another database",
+            migrations.len()
+        ));
+    }
+    for (index, migration) in migrations.iter().enumerate().skip(current) {
+        let version = index + 1;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("migrate {version} begin: {e}"))?;
+        migration(&tx).map_err(|e| format!("migration {version}: {e}"))?;
+        tx.execute("DELETE FROM schema_version", [])
+            .and_then(|_| {
+                tx.execute(
+                    "INSERT INTO schema_version (version) VALUES (?1)",
+                    params![version as i64],
+                )
+            })
+            .map_err(|e| format!("migrate {version} record: {e}"))?;
+        tx.commit()
+            .map_err(|e| format!("migrate {version} commit: {e}"))?;
+    }
+    Ok(())
+}
+
+fn schema_version(conn: &Connection) -> Result<usize, String> {
+    let version: Option<i64> = conn
+        .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| format!("read schema_version: {e}"))?;
+    Ok(version.unwrap_or(0).max(0) as usize)
+}
+
+/// Tables as of the first versioned release, plus the columns added to them
+/// before versioning existed.
+fn migrate_base_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
             CREATE TABLE IF NOT EXISTS chats (
                 chat_id       TEXT PRIMARY KEY,
                 thread_id     TEXT NOT NULL,
@@ -92,6 +170,18 @@ impl SqliteStore {
                 approved_by TEXT
             );
 
+            CREATE TABLE IF NOT EXISTS login_sessions (
+                provider         TEXT NOT NULL,
+                profile          TEXT NOT NULL,
+                device_code      TEXT NOT NULL,
+                user_code        TEXT NOT NULL,
+                verification_url TEXT NOT NULL,
+                interval_secs    INTEGER NOT NULL,
+                created_at       INTEGER NOT NULL,
+                expires_at       INTEGER NOT NULL,
+                PRIMARY KEY (provider, profile)
+            );
+
             CREATE TABLE IF NOT EXISTS notification_subscriptions (
                 subscription_id TEXT PRIMARY KEY,
                 target          TEXT NOT NULL,
@@ -108,6 +198,24 @@ impl SqliteStore {
                 created_at      INTEGER NOT NULL
             );
 
+            CREATE TABLE IF NOT EXISTS tool_invocations (
+                invocation_id TEXT PRIMARY KEY,
+                chat_id       TEXT NOT NULL,
+                thread_id     TEXT NOT NULL,
+                turn_id       TEXT NOT NULL,
+                tool_call_id  TEXT NOT NULL,
+                tool          TEXT NOT NULL,
+                args_json     TEXT NOT NULL,
+                args_hash     TEXT NOT NULL,
+                duration_ms   INTEGER NOT NULL,
+                outcome       TEXT NOT NULL,
+                approver      TEXT,
+                created_at    INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_tool_invocations_thread
+                ON tool_invocations (thread_id, created_at DESC);
+
             CREATE TABLE IF NOT EXISTS chat_runs (
                 run_id      TEXT PRIMARY KEY,
                 thread_id   TEXT NOT NULL,
@@ -133,7 +241,11 @@ impl SqliteStore {
                 created_at    INTEGER NOT NULL,
                 updated_at    INTEGER NOT NULL,
                 last_run_at   INTEGER,
-                next_run_at   INTEGER
+                next_run_at   INTEGER,
+                max_retries   INTEGER NOT NULL DEFAULT 0,
+                backoff_secs  INTEGER NOT NULL DEFAULT 0,
+                retry_count   INTEGER NOT NULL DEFAULT 0,
+                next_retry_at INTEGER
             );
 
             CREATE TABLE IF NOT EXISTS cron_runs (
@@ -151,25 +263,270 @@ impl SqliteStore {
             CREATE INDEX IF NOT EXISTS idx_cron_runs_cron_id
                 ON cron_runs (cron_id, scheduled_at DESC);
             ",
-        )
-        .map_err(|e| format!("migrate: {e}"))?;
+    )
+    .map_err(|e| format!("migrate: {e}"))?;
 
-        if let Err(e) = conn.execute("ALTER TABLE terminals ADD COLUMN name TEXT", []) {
-            let msg = e.to_string().to_lowercase();
-            if !msg.contains("duplicate column") {
-                return Err(format!("migrate add terminals.name: {e}"));
+    if let Err(e) = conn.execute("ALTER TABLE terminals ADD COLUMN name TEXT", []) {
+        let msg = e.to_string().to_lowercase();
+        if !msg.contains("duplicate column") {
+            return Err(format!("migrate add terminals.name: {e}"));
+        }
+    }
+
+    if let Err(e) = conn.execute("ALTER TABLE chats ADD COLUMN settings_json TEXT", []) {
+        let msg = e.to_string().to_lowercase();
+        if !msg.contains("duplicate column") {
+            return Err(format!("migrate add chats.settings_json: {e}"));
+        }
+    }
+
+    for (column, ddl) in [
+        ("payload_json", "TEXT"),
+        ("attempts", "INTEGER NOT NULL DEFAULT 0"),
+        ("delivered_at", "INTEGER"),
+    ] {
+        let sql = format!("ALTER TABLE notification_events ADD COLUMN {column} {ddl}");
+        match conn.execute(&sql, []) {
+            // Events recorded before delivery tracking existed were never
+            // queued for delivery; don't send them now.
+            Ok(_) if column == "delivered_at" => {
+                conn.execute(
+                    "UPDATE notification_events SET delivered_at = created_at",
+                    [],
+                )
+                .map_err(|e| format!("migrate backfill notification_events: {e}"))?;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let msg = e.to_string().to_lowercase();
+                if !msg.contains("duplicate column") {
+                    return Err(format!("migrate add notification_events.{column}: {e}"));
+                }
             }
         }
+    }
 
-        if let Err(e) = conn.execute("ALTER TABLE chats ADD COLUMN settings_json TEXT", []) {
+    for (column, ddl) in [
+        ("max_retries", "INTEGER NOT NULL DEFAULT 0"),
+        ("backoff_secs", "INTEGER NOT NULL DEFAULT 0"),
+        ("retry_count", "INTEGER NOT NULL DEFAULT 0"),
+        ("next_retry_at", "INTEGER"),
+    ] {
+        let sql = format!("ALTER TABLE cron_jobs ADD COLUMN {column} {ddl}");
+        if let Err(e) = conn.execute(&sql, []) {
             let msg = e.to_string().to_lowercase();
             if !msg.contains("duplicate column") {
-                return Err(format!("migrate add chats.settings_json: {e}"));
+                return Err(format!("migrate add cron_jobs.{column}: {e}"));
             }
         }
+    }
 
-        Ok(())
+    Ok(())
+}
+
+fn migrate_chat_search(conn: &Connection) -> Result<(), String> {
+    if search::create_index(conn)? {
+        search::backfill(conn)?;
     }
+    Ok(())
+}
+
+fn migrate_terminal_recordings(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            CREATE TABLE IF NOT EXISTS terminal_recordings (
+                recording_id TEXT PRIMARY KEY,
+                session_id   TEXT NOT NULL,
+                started_at   INTEGER NOT NULL,
+                duration_ms  INTEGER NOT NULL,
+                bytes        INTEGER NOT NULL,
+                truncated    INTEGER NOT NULL DEFAULT 0,
+                cast_text    TEXT NOT NULL,
+                created_at   INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_terminal_recordings_created
+                ON terminal_recordings (created_at DESC);
+            ",
+    )
+    .map_err(|e| format!("migrate terminal_recordings: {e}"))
+}
+
+fn migrate_state_entries(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            CREATE TABLE IF NOT EXISTS state_entries (
+                scope      TEXT NOT NULL,
+                key        TEXT NOT NULL,
+                value_json TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (scope, key)
+            );
+            ",
+    )
+    .map_err(|e| format!("migrate state_entries: {e}"))
+}
+
+fn migrate_pending_runs(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            CREATE TABLE IF NOT EXISTS chat_pending_runs (
+                seq           INTEGER PRIMARY KEY AUTOINCREMENT,
+                turn_id       TEXT NOT NULL UNIQUE,
+                chat_id       TEXT NOT NULL,
+                thread_id     TEXT NOT NULL,
+                message       TEXT NOT NULL,
+                settings_json TEXT,
+                queued_at     INTEGER NOT NULL,
+                released      INTEGER NOT NULL DEFAULT 0
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_chat_pending_runs_chat
+                ON chat_pending_runs (chat_id, seq);
+            ",
+    )
+    .map_err(|e| format!("migrate chat_pending_runs: {e}"))
+}
+
+fn migrate_audit_log(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            CREATE TABLE IF NOT EXISTS audit_log (
+                seq       INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                identity  TEXT,
+                role      TEXT NOT NULL,
+                method    TEXT NOT NULL,
+                target_id TEXT,
+                outcome   TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_audit_log_method
+                ON audit_log (method, seq DESC);
+            CREATE INDEX IF NOT EXISTS idx_audit_log_identity
+                ON audit_log (identity, seq DESC);
+            ",
+    )
+    .map_err(|e| format!("migrate audit_log: {e}"))
+}
+
+fn migrate_terminal_spawn(conn: &Connection) -> Result<(), String> {
+    conn.execute("ALTER TABLE terminals ADD COLUMN spawn_json TEXT", [])
+        .map(|_| ())
+        .map_err(|e| format!("migrate add terminals.spawn_json: {e}"))
+}
+
+fn migrate_cron_leases(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            ALTER TABLE cron_jobs ADD COLUMN lease_owner TEXT;
+            ALTER TABLE cron_jobs ADD COLUMN lease_expires_at INTEGER;
+            ",
+    )
+    .map_err(|e| format!("migrate add cron_jobs lease: {e}"))
+}
+
+fn migrate_chat_attachments(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            CREATE TABLE IF NOT EXISTS chat_attachments (
+                attachment_id TEXT PRIMARY KEY,
+                thread_id     TEXT NOT NULL,
+                mime_type     TEXT NOT NULL,
+                data          BLOB NOT NULL,
+                created_at    INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_chat_attachments_thread
+                ON chat_attachments (thread_id);
+            ",
+    )
+    .map_err(|e| format!("migrate chat_attachments: {e}"))
+}
+
+fn migrate_turn_usage(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            CREATE TABLE IF NOT EXISTS turn_usage (
+                thread_id  TEXT NOT NULL,
+                turn_id    TEXT NOT NULL,
+                chat_id    TEXT NOT NULL,
+                identity   TEXT,
+                model      TEXT,
+                input      INTEGER NOT NULL DEFAULT 0,
+                output     INTEGER NOT NULL DEFAULT 0,
+                total      INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (thread_id, turn_id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_turn_usage_chat
+                ON turn_usage (chat_id, created_at);
+            CREATE INDEX IF NOT EXISTS idx_turn_usage_identity
+                ON turn_usage (identity, created_at);
+            ",
+    )
+    .map_err(|e| format!("migrate turn_usage: {e}"))
+}
+
+fn migrate_bus_events(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            CREATE TABLE IF NOT EXISTS bus_events (
+                seq         INTEGER PRIMARY KEY AUTOINCREMENT,
+                origin      TEXT NOT NULL,
+                topic       TEXT NOT NULL,
+                params_json TEXT,
+                created_at  INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_bus_events_created
+                ON bus_events (created_at);
+            ",
+    )
+    .map_err(|e| format!("migrate bus_events: {e}"))
+}
+
+fn migrate_owners(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            ALTER TABLE chats ADD COLUMN owner TEXT;
+            ALTER TABLE notification_subscriptions ADD COLUMN owner TEXT;
+            ",
+    )
+    .map_err(|e| format!("migrate owners: {e}"))
+}
+
+/// Tag raw events with the chat's event pointer so replay can key on it.
+/// Rows stored before this step keep a NULL pointer and are never replayed.
+fn migrate_raw_event_pointers(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            ALTER TABLE chat_raw_events ADD COLUMN event_pointer INTEGER;
+            CREATE INDEX IF NOT EXISTS idx_chat_raw_events_pointer
+                ON chat_raw_events(thread_id, event_pointer);
+            ",
+    )
+    .map_err(|e| format!("migrate raw event pointers: {e}"))
+}
+
+fn migrate_notification_retry_schedule(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+            ALTER TABLE notification_events
+                ADD COLUMN next_attempt_at INTEGER NOT NULL DEFAULT 0;
+            CREATE INDEX IF NOT EXISTS idx_notification_events_due
+                ON notification_events(next_attempt_at) WHERE delivered_at IS NULL;
+            ",
+    )
+    .map_err(|e| format!("migrate notification retry schedule: {e}"))
+}
+
+fn migrate_audit_log_timestamp_index(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp ON audit_log (timestamp);",
+    )
+    .map_err(|e| format!("migrate audit_log timestamp index: {e}"))
 }
 
 impl Store for SqliteStore {
@@ -177,13 +534,14 @@ impl Store for SqliteStore {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         let settings_json = serialize_settings(chat.settings.as_ref())?;
         conn.execute(
-            "INSERT INTO chats (chat_id, thread_id, created_at, status, event_pointer, settings_json)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "INSERT INTO chats (chat_id, thread_id, created_at, status, event_pointer, settings_json, owner)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
              ON CONFLICT(chat_id) DO UPDATE SET
                 thread_id = excluded.thread_id,
                 status = excluded.status,
                 event_pointer = excluded.event_pointer,
-                settings_json = COALESCE(excluded.settings_json, chats.settings_json)",
+                settings_json = COALESCE(excluded.settings_json, chats.settings_json),
+                owner = COALESCE(chats.owner, excluded.owner)",
             params![
                 chat.chat_id,
                 chat.thread_id,
@@ -191,6 +549,7 @@ impl Store for SqliteStore {
                 chat.status.as_str(),
                 chat.event_pointer as i64,
                 settings_json,
+                chat.owner,
             ],
         )
         .map_err(|e| format!("upsert_chat: {e}"))?;
@@ -199,55 +558,42 @@ impl Store for SqliteStore {
 
     fn get_chat(&self, chat_id: &str) -> Result<Option<ChatRecord>, String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
-        let mut stmt = conn
-            .prepare(
-                "SELECT chat_id, thread_id, created_at, status, event_pointer, settings_json
-                 FROM chats WHERE chat_id = ?1",
-            )
-            .map_err(|e| format!("get_chat prepare: {e}"))?;
-
-        let mut rows = stmt
-            .query_map(params![chat_id], |row| {
-                let settings = parse_settings_json(row.get(5)?)?;
-                Ok(ChatRecord {
-                    chat_id: row.get(0)?,
-                    thread_id: row.get(1)?,
-                    created_at: row.get(2)?,
-                    status: SessionStatus::from_label(&row.get::<_, String>(3)?),
-                    event_pointer: row.get::<_, i64>(4)? as u64,
-                    settings,
-                })
-            })
-            .map_err(|e| format!("get_chat query: {e}"))?;
+        conn.query_row(
+            "SELECT chat_id, thread_id, created_at, status, event_pointer, settings_json, owner
+             FROM chats WHERE chat_id = ?1",
+            params![chat_id],
+            chat_from_row,
+        )
+        .optional()
+        .map_err(|e| format!("get_chat: {e}"))
+    }
 
-        match rows.next() {
-            Some(Ok(rec)) => Ok(Some(rec)),
-            Some(Err(e)) => Err(format!("get_chat row: {e}")),
-            None => Ok(None),
-        }
+    fn get_chat_by_thread(&self, thread_id: &str) -> Result<Option<ChatRecord>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.query_row(
+            "SELECT chat_id, thread_id, created_at, status, event_pointer, settings_json, owner
+             FROM chats WHERE thread_id = ?1
+             ORDER BY created_at LIMIT 1",
+            params![thread_id],
+            chat_from_row,
+        )
+        .optional()
+        .map_err(|e| format!("get_chat_by_thread: {e}"))
     }
 
     fn list_chats(&self) -> Result<Vec<ChatRecord>, String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         let mut stmt = conn
             .prepare(
-                "SELECT chat_id, thread_id, created_at, status, event_pointer, settings_json
-                 FROM chats ORDER BY created_at DESC",
+                "SELECT chat_id, thread_id, created_at, status, event_pointer, settings_json, owner
+                 FROM chats
+                 ORDER BY COALESCE(json_extract(settings_json, '$.pinned'), 0) = 1 DESC,
+                          created_at DESC",
             )
             .map_err(|e| format!("list_chats prepare: {e}"))?;
 
         let rows = stmt
-            .query_map([], |row| {
-                let settings = parse_settings_json(row.get(5)?)?;
-                Ok(ChatRecord {
-                    chat_id: row.get(0)?,
-                    thread_id: row.get(1)?,
-                    created_at: row.get(2)?,
-                    status: SessionStatus::from_label(&row.get::<_, String>(3)?),
-                    event_pointer: row.get::<_, i64>(4)? as u64,
-                    settings,
-                })
-            })
+            .query_map([], chat_from_row)
             .map_err(|e| format!("list_chats query: {e}"))?;
 
         rows.collect::<Result<Vec<_>, _>>()
@@ -256,6 +602,18 @@ impl Store for SqliteStore {
 
     fn delete_chat(&self, chat_id: &str) -> Result<(), String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let thread_id: Option<String> = conn
+            .query_row(
+                "SELECT thread_id FROM chats WHERE chat_id = ?1",
+                params![chat_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("delete_chat lookup: {e}"))?;
+        if let Some(thread_id) = thread_id {
+            search::delete_thread(&conn, &thread_id)?;
+            delete_thread_attachments(&conn, &thread_id)?;
+        }
         conn.execute("DELETE FROM chats WHERE chat_id = ?1", params![chat_id])
             .map_err(|e| format!("delete_chat: {e}"))?;
         Ok(())
@@ -302,7 +660,7 @@ impl Store for SqliteStore {
             params![thread_id, state_json, now_unix() as i64],
         )
         .map_err(|e| format!("upsert_chat_thread_state: {e}"))?;
-        Ok(())
+        search::index_thread_state(&conn, thread_id, state)
     }
 
     fn get_chat_thread_state(&self, thread_id: &str) -> Result<Option<serde_json::Value>, String> {
@@ -332,14 +690,138 @@ impl Store for SqliteStore {
             params![thread_id],
         )
         .map_err(|e| format!("delete_chat_thread_state: {e}"))?;
+        delete_thread_attachments(&conn, thread_id)?;
+        Ok(())
+    }
+
+    fn insert_pending_run(&self, run: &PendingRunRecord) -> Result<(), String> {
+        let settings_json = serialize_settings(run.settings.as_ref())?;
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "INSERT INTO chat_pending_runs
+                (turn_id, chat_id, thread_id, message, settings_json, queued_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(turn_id) DO UPDATE SET
+                chat_id = excluded.chat_id,
+                thread_id = excluded.thread_id,
+                message = excluded.message,
+                settings_json = excluded.settings_json,
+                released = 0",
+            params![
+                run.turn_id,
+                run.chat_id,
+                run.thread_id,
+                run.message,
+                settings_json,
+                run.queued_at as i64,
+            ],
+        )
+        .map_err(|e| format!("insert_pending_run: {e}"))?;
         Ok(())
     }
 
+    fn delete_pending_run(&self, turn_id: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "DELETE FROM chat_pending_runs WHERE turn_id = ?1",
+            params![turn_id],
+        )
+        .map_err(|e| format!("delete_pending_run: {e}"))?;
+        Ok(())
+    }
+
+    fn delete_pending_runs_for_thread(&self, thread_id: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "DELETE FROM chat_pending_runs WHERE thread_id = ?1",
+            params![thread_id],
+        )
+        .map_err(|e| format!("delete_pending_runs_for_thread: {e}"))?;
+        Ok(())
+    }
+
+    fn release_pending_run(&self, turn_id: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "UPDATE chat_pending_runs SET released = 1 WHERE turn_id = ?1",
+            params![turn_id],
+        )
+        .map_err(|e| format!("release_pending_run: {e}"))?;
+        Ok(())
+    }
+
+    fn release_all_pending_runs(&self) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute("UPDATE chat_pending_runs SET released = 1", [])
+            .map_err(|e| format!("release_all_pending_runs: {e}"))?;
+        Ok(())
+    }
+
+    fn list_released_pending_run_chats(&self) -> Result<Vec<String>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT chat_id FROM chat_pending_runs WHERE released = 1
+                 GROUP BY chat_id ORDER BY MIN(seq)",
+            )
+            .map_err(|e| format!("list_released_pending_run_chats prepare: {e}"))?;
+        let rows = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| format!("list_released_pending_run_chats query: {e}"))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_released_pending_run_chats collect: {e}"))
+    }
+
+    fn take_pending_runs(&self, chat_id: &str) -> Result<Vec<PendingRunRecord>, String> {
+        let mut conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("take_pending_runs begin: {e}"))?;
+        let runs = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT turn_id, chat_id, thread_id, message, settings_json, queued_at
+                     FROM chat_pending_runs WHERE chat_id = ?1 AND released = 1
+                     ORDER BY seq",
+                )
+                .map_err(|e| format!("take_pending_runs prepare: {e}"))?;
+            let rows = stmt
+                .query_map(params![chat_id], |row| {
+                    Ok(PendingRunRecord {
+                        turn_id: row.get(0)?,
+                        chat_id: row.get(1)?,
+                        thread_id: row.get(2)?,
+                        message: row.get(3)?,
+                        settings: parse_settings_json(row.get(4)?)?,
+                        queued_at: row.get::<_, i64>(5)? as u64,
+                    })
+                })
+                .map_err(|e| format!("take_pending_runs query: {e}"))?;
+            rows.collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("take_pending_runs collect: {e}"))?
+        };
+        tx.execute(
+            "UPDATE chat_pending_runs SET released = 0 WHERE chat_id = ?1 AND released = 1",
+            params![chat_id],
+        )
+        .map_err(|e| format!("take_pending_runs claim: {e}"))?;
+        tx.commit()
+            .map_err(|e| format!("take_pending_runs commit: {e}"))?;
+        Ok(runs)
+    }
+
     fn upsert_terminal(&self, rec: &TerminalRecord) -> Result<(), String> {
+        let spawn_json = rec
+            .spawn
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| format!("upsert_terminal encode spawn: {e}"))?;
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         conn.execute(
-            "INSERT INTO terminals (session_id, name, shell, cols, rows, started_at, status, exit_code)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "INSERT INTO terminals
+                (session_id, name, shell, cols, rows, started_at, status, exit_code, spawn_json)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
              ON CONFLICT(session_id) DO UPDATE SET
                 name = excluded.name,
                 status = excluded.status,
@@ -355,6 +837,7 @@ impl Store for SqliteStore {
                 rec.started_at,
                 rec.status.as_str(),
                 rec.exit_code,
+                spawn_json,
             ],
         )
         .map_err(|e| format!("upsert_terminal: {e}"))?;
@@ -365,7 +848,8 @@ impl Store for SqliteStore {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         let mut stmt = conn
             .prepare(
-                "SELECT session_id, name, shell, cols, rows, started_at, status, exit_code
+                "SELECT session_id, name, shell, cols, rows, started_at, status, exit_code,
+                        spawn_json
                  FROM terminals WHERE session_id = ?1",
             )
             .map_err(|e| format!("get_terminal prepare: {e}"))?;
@@ -382,6 +866,9 @@ impl Store for SqliteStore {
                     started_at: row.get(5)?,
                     status: SessionStatus::from_label(&row.get::<_, String>(6)?),
                     exit_code: row.get(7)?,
+                    spawn: row
+                        .get::<_, Option<String>>(8)?
+                        .and_then(|raw| serde_json::from_str(&raw).ok()),
                 })
             })
             .map_err(|e| format!("get_terminal query: {e}"))?;
@@ -397,7 +884,8 @@ impl Store for SqliteStore {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         let mut stmt = conn
             .prepare(
-                "SELECT session_id, name, shell, cols, rows, started_at, status, exit_code
+                "SELECT session_id, name, shell, cols, rows, started_at, status, exit_code,
+                        spawn_json
                  FROM terminals ORDER BY started_at DESC",
             )
             .map_err(|e| format!("list_terminals prepare: {e}"))?;
@@ -414,6 +902,9 @@ impl Store for SqliteStore {
                     started_at: row.get(5)?,
                     status: SessionStatus::from_label(&row.get::<_, String>(6)?),
                     exit_code: row.get(7)?,
+                    spawn: row
+                        .get::<_, Option<String>>(8)?
+                        .and_then(|raw| serde_json::from_str(&raw).ok()),
                 })
             })
             .map_err(|e| format!("list_terminals query: {e}"))?;
@@ -432,6 +923,82 @@ impl Store for SqliteStore {
         Ok(())
     }
 
+    fn insert_terminal_recording(&self, rec: &TerminalRecordingRecord) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "INSERT INTO terminal_recordings
+                (recording_id, session_id, started_at, duration_ms, bytes, truncated, cast_text,
+                 created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                rec.recording_id,
+                rec.session_id.to_string(),
+                rec.started_at as i64,
+                rec.duration_ms as i64,
+                rec.bytes as i64,
+                rec.truncated,
+                rec.cast,
+                rec.created_at as i64,
+            ],
+        )
+        .map_err(|e| format!("insert_terminal_recording: {e}"))?;
+        Ok(())
+    }
+
+    fn get_terminal_recording(
+        &self,
+        recording_id: &str,
+    ) -> Result<Option<TerminalRecordingRecord>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.query_row(
+            "SELECT recording_id, session_id, started_at, duration_ms, bytes, truncated, cast_text,
+                    created_at
+             FROM terminal_recordings WHERE recording_id = ?1",
+            params![recording_id],
+            |row| {
+                let sid: String = row.get(1)?;
+                Ok(TerminalRecordingRecord {
+                    recording_id: row.get(0)?,
+                    session_id: sid.parse().unwrap_or(Uuid::nil()),
+                    started_at: row.get::<_, i64>(2)? as u64,
+                    duration_ms: row.get::<_, i64>(3)? as u64,
+                    bytes: row.get::<_, i64>(4)? as u64,
+                    truncated: row.get(5)?,
+                    cast: row.get(6)?,
+                    created_at: row.get::<_, i64>(7)? as u64,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| format!("get_terminal_recording: {e}"))
+    }
+
+    fn prune_terminal_recordings(
+        &self,
+        retention_days: u64,
+        max_records: usize,
+    ) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let cutoff = now_unix().saturating_sub(retention_days.saturating_mul(86_400));
+        let expired = conn
+            .execute(
+                "DELETE FROM terminal_recordings WHERE created_at < ?1",
+                params![cutoff as i64],
+            )
+            .map_err(|e| format!("prune_terminal_recordings cutoff: {e}"))?;
+        let excess = conn
+            .execute(
+                "DELETE FROM terminal_recordings WHERE recording_id IN (
+                    SELECT recording_id FROM terminal_recordings
+                    ORDER BY created_at DESC, rowid DESC
+                    LIMIT -1 OFFSET ?1
+                )",
+                params![max_records as i64],
+            )
+            .map_err(|e| format!("prune_terminal_recordings excess: {e}"))?;
+        Ok(expired + excess)
+    }
+
     fn mark_all_inactive(&self) -> Result<(), String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         conn.execute_batch(
@@ -442,6 +1009,13 @@ impl Store for SqliteStore {
         Ok(())
     }
 
+    fn ping(&self) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))
+            .map_err(|e| format!("ping: {e}"))?;
+        Ok(())
+    }
+
     fn upsert_job(&self, job: &JobRecord) -> Result<(), String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         let spec = serde_json::to_string(&job.spec).map_err(|e| format!("job spec: {e}"))?;
@@ -558,14 +1132,15 @@ impl Store for SqliteStore {
             .map_err(|e| format!("list_jobs collect: {e}"))
     }
 
-    fn prune_jobs(&self, retention_days: u64, max_jobs: usize) -> Result<(), String> {
+    fn prune_jobs(&self, retention_days: u64, max_jobs: usize) -> Result<usize, String> {
         let cutoff = now_unix().saturating_sub(retention_days.saturating_mul(86_400));
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
-        conn.execute(
-            "DELETE FROM jobs WHERE created_at < ?1",
-            params![cutoff as i64],
-        )
-        .map_err(|e| format!("prune_jobs: {e}"))?;
+        let mut removed = conn
+            .execute(
+                "DELETE FROM jobs WHERE created_at < ?1",
+                params![cutoff as i64],
+            )
+            .map_err(|e| format!("prune_jobs: {e}"))?;
 
         let mut stmt = conn
             .prepare("SELECT job_id FROM jobs ORDER BY created_at DESC")
@@ -578,12 +1153,13 @@ impl Store for SqliteStore {
 
         if ids.len() > max_jobs {
             for job_id in ids.iter().skip(max_jobs) {
-                conn.execute("DELETE FROM jobs WHERE job_id = ?1", params![job_id])
+                removed += conn
+                    .execute("DELETE FROM jobs WHERE job_id = ?1", params![job_id])
                     .map_err(|e| format!("prune_jobs delete: {e}"))?;
             }
         }
 
-        Ok(())
+        Ok(removed)
     }
 
     fn upsert_pairing(&self, pairing: &PairingRecord) -> Result<(), String> {
@@ -663,7 +1239,7 @@ impl Store for SqliteStore {
             .map_err(|e| format!("list_pairings collect: {e}"))
     }
 
-    fn prune_pairings(&self, retention_secs: u64) -> Result<(), String> {
+    fn prune_pairings(&self, retention_secs: u64) -> Result<usize, String> {
         let now = now_unix();
         let cutoff = now.saturating_sub(retention_secs);
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
@@ -678,18 +1254,111 @@ impl Store for SqliteStore {
             "DELETE FROM pairings WHERE expires_at < ?1",
             params![cutoff as i64],
         )
-        .map_err(|e| format!("prune_pairings delete: {e}"))?;
+        .map_err(|e| format!("prune_pairings delete: {e}"))
+    }
+
+    fn upsert_login_session(&self, session: &LoginSessionRecord) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "INSERT INTO login_sessions (provider, profile, device_code, user_code,
+                verification_url, interval_secs, created_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(provider, profile) DO UPDATE SET
+                device_code = excluded.device_code,
+                user_code = excluded.user_code,
+                verification_url = excluded.verification_url,
+                interval_secs = excluded.interval_secs,
+                created_at = excluded.created_at,
+                expires_at = excluded.expires_at",
+            params![
+                session.provider,
+                session.profile,
+                session.device_code,
+                session.user_code,
+                session.verification_url,
+                session.interval_secs as i64,
+                session.created_at as i64,
+                session.expires_at as i64,
+            ],
+        )
+        .map_err(|e| format!("upsert_login_session: {e}"))?;
         Ok(())
     }
 
+    fn get_login_session(
+        &self,
+        provider: &str,
+        profile: &str,
+    ) -> Result<Option<LoginSessionRecord>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT provider, profile, device_code, user_code, verification_url,
+                        interval_secs, created_at, expires_at
+                 FROM login_sessions WHERE provider = ?1 AND profile = ?2",
+            )
+            .map_err(|e| format!("get_login_session prepare: {e}"))?;
+
+        let mut rows = stmt
+            .query_map(params![provider, profile], login_session_from_row)
+            .map_err(|e| format!("get_login_session query: {e}"))?;
+
+        match rows.next() {
+            Some(Ok(rec)) => Ok(Some(rec)),
+            Some(Err(e)) => Err(format!("get_login_session row: {e}")),
+            None => Ok(None),
+        }
+    }
+
+    fn list_login_sessions(&self) -> Result<Vec<LoginSessionRecord>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT provider, profile, device_code, user_code, verification_url,
+                        interval_secs, created_at, expires_at
+                 FROM login_sessions ORDER BY created_at DESC",
+            )
+            .map_err(|e| format!("list_login_sessions prepare: {e}"))?;
+
+        let rows = stmt
+            .query_map([], login_session_from_row)
+            .map_err(|e| format!("list_login_sessions query: {e}"))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_login_sessions collect: {e}"))
+    }
+
+    fn delete_login_sessions(
+        &self,
+        provider: Option<&str>,
+        profile: Option<&str>,
+    ) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "DELETE FROM login_sessions
+             WHERE (?1 IS NULL OR provider = ?1) AND (?2 IS NULL OR profile = ?2)",
+            params![provider, profile],
+        )
+        .map_err(|e| format!("delete_login_sessions: {e}"))
+    }
+
+    fn prune_login_sessions(&self) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "DELETE FROM login_sessions WHERE expires_at < ?1",
+            params![now_unix() as i64],
+        )
+        .map_err(|e| format!("prune_login_sessions: {e}"))
+    }
+
     fn upsert_notification_subscription(
         &self,
         subscription: &NotificationSubscription,
     ) -> Result<(), String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         conn.execute(
-            "INSERT INTO notification_subscriptions (subscription_id, target, kind, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)
+            "INSERT INTO notification_subscriptions (subscription_id, target, kind, created_at, updated_at, owner)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
              ON CONFLICT(subscription_id) DO UPDATE SET
                 target = excluded.target,
                 kind = excluded.kind,
@@ -700,6 +1369,7 @@ impl Store for SqliteStore {
                 subscription.kind,
                 subscription.created_at as i64,
                 subscription.updated_at as i64,
+                subscription.owner,
             ],
         )
         .map_err(|e| format!("upsert_notification_subscription: {e}"))?;
@@ -710,7 +1380,7 @@ impl Store for SqliteStore {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         let mut stmt = conn
             .prepare(
-                "SELECT subscription_id, target, kind, created_at, updated_at
+                "SELECT subscription_id, target, kind, created_at, updated_at, owner
                  FROM notification_subscriptions ORDER BY created_at DESC",
             )
             .map_err(|e| format!("list_notification_subscriptions prepare: {e}"))?;
@@ -723,6 +1393,7 @@ impl Store for SqliteStore {
                     kind: row.get(2)?,
                     created_at: row.get::<_, i64>(3)? as u64,
                     updated_at: row.get::<_, i64>(4)? as u64,
+                    owner: row.get(5)?,
                 })
             })
             .map_err(|e| format!("list_notification_subscriptions query: {e}"))?;
@@ -743,37 +1414,114 @@ impl Store for SqliteStore {
     }
 
     fn insert_notification_event(&self, event: &NotificationEvent) -> Result<(), String> {
+        let payload_json = event
+            .payload
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| format!("serialize notification payload: {e}"))?;
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         conn.execute(
-            "INSERT INTO notification_events (notification_id, title, body, target, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO notification_events
+                (notification_id, title, body, target, created_at, payload_json, attempts, delivered_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
                 event.notification_id,
                 event.title,
                 event.body,
                 event.target,
                 event.created_at as i64,
+                payload_json,
+                event.attempts as i64,
+                event.delivered_at.map(|v| v as i64),
             ],
         )
         .map_err(|e| format!("insert_notification_event: {e}"))?;
         Ok(())
     }
 
-    fn prune_notifications(&self, retention_days: u64) -> Result<(), String> {
-        let cutoff = now_unix().saturating_sub(retention_days.saturating_mul(86_400));
+    fn list_notification_events(&self, limit: usize) -> Result<Vec<NotificationEvent>, String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
-        conn.execute(
-            "DELETE FROM notification_events WHERE created_at < ?1",
-            params![cutoff as i64],
-        )
-        .map_err(|e| format!("prune_notifications events: {e}"))?;
-        conn.execute(
-            "DELETE FROM notification_subscriptions WHERE updated_at < ?1",
-            params![cutoff as i64],
-        )
-        .map_err(|e| format!("prune_notifications subs: {e}"))?;
-        Ok(())
-    }
+        let mut stmt = conn
+            .prepare(
+                "SELECT notification_id, title, body, target, created_at, payload_json, attempts, delivered_at
+                 FROM notification_events ORDER BY created_at DESC LIMIT ?1",
+            )
+            .map_err(|e| format!("list_notification_events prepare: {e}"))?;
+
+        let rows = stmt
+            .query_map(params![limit as i64], notification_event_from_row)
+            .map_err(|e| format!("list_notification_events query: {e}"))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_notification_events collect: {e}"))
+    }
+
+    fn list_undelivered_notification_events(
+        &self,
+        max_attempts: u32,
+        now: u64,
+        limit: usize,
+    ) -> Result<Vec<NotificationEvent>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT notification_id, title, body, target, created_at, payload_json, attempts, delivered_at
+                 FROM notification_events
+                 WHERE delivered_at IS NULL AND attempts < ?1 AND next_attempt_at <= ?2
+                 ORDER BY next_attempt_at ASC, created_at ASC LIMIT ?3",
+            )
+            .map_err(|e| format!("list_undelivered_notification_events prepare: {e}"))?;
+
+        let rows = stmt
+            .query_map(
+                params![max_attempts as i64, now as i64, limit as i64],
+                notification_event_from_row,
+            )
+            .map_err(|e| format!("list_undelivered_notification_events query: {e}"))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_undelivered_notification_events collect: {e}"))
+    }
+
+    fn record_notification_attempt(
+        &self,
+        notification_id: &str,
+        delivered_at: Option<u64>,
+        retry_at: u64,
+    ) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "UPDATE notification_events
+             SET attempts = attempts + 1, delivered_at = ?2, next_attempt_at = ?3
+             WHERE notification_id = ?1 AND delivered_at IS NULL",
+            params![
+                notification_id,
+                delivered_at.map(|v| v as i64),
+                retry_at as i64
+            ],
+        )
+        .map_err(|e| format!("record_notification_attempt: {e}"))?;
+        Ok(())
+    }
+
+    fn prune_notifications(&self, retention_days: u64) -> Result<usize, String> {
+        let cutoff = now_unix().saturating_sub(retention_days.saturating_mul(86_400));
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let events = conn
+            .execute(
+                "DELETE FROM notification_events WHERE created_at < ?1",
+                params![cutoff as i64],
+            )
+            .map_err(|e| format!("prune_notifications events: {e}"))?;
+        let subscriptions = conn
+            .execute(
+                "DELETE FROM notification_subscriptions WHERE updated_at < ?1",
+                params![cutoff as i64],
+            )
+            .map_err(|e| format!("prune_notifications subs: {e}"))?;
+        Ok(events + subscriptions)
+    }
 
     fn insert_chat_raw_event(
         &self,
@@ -796,8 +1544,10 @@ impl Store for SqliteStore {
         )
         .map_err(|e| format!("insert_chat_raw_event run: {e}"))?;
         conn.execute(
-            "INSERT INTO chat_raw_events (event_id, run_id, thread_id, method, params_json, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO chat_raw_events
+                (event_id, run_id, thread_id, method, params_json, created_at, event_pointer)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6,
+                (SELECT COALESCE(MAX(event_pointer), 0) FROM chats WHERE thread_id = ?3))",
             params![
                 Uuid::new_v4().to_string(),
                 run_id,
@@ -808,7 +1558,7 @@ impl Store for SqliteStore {
             ],
         )
         .map_err(|e| format!("insert_chat_raw_event event: {e}"))?;
-        Ok(())
+        search::index_raw_event(&conn, thread_id, method, params)
     }
 
     fn list_chat_raw_events(
@@ -820,7 +1570,7 @@ impl Store for SqliteStore {
         let max_rows = limit.clamp(1, 10_000) as i64;
         let mut stmt = conn
             .prepare(
-                "SELECT run_id, thread_id, method, params_json, created_at
+                "SELECT rowid, run_id, thread_id, method, params_json, created_at, event_pointer
                  FROM chat_raw_events
                  WHERE thread_id = ?1
                  ORDER BY created_at ASC, rowid ASC
@@ -829,48 +1579,438 @@ impl Store for SqliteStore {
             .map_err(|e| format!("list_chat_raw_events prepare: {e}"))?;
 
         let rows = stmt
-            .query_map(params![thread_id, max_rows], |row| {
-                let raw: String = row.get(3)?;
-                Ok(ChatRawEventRecord {
-                    run_id: row.get(0)?,
+            .query_map(params![thread_id, max_rows], chat_raw_event_from_row)
+            .map_err(|e| format!("list_chat_raw_events query: {e}"))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_chat_raw_events collect: {e}"))
+    }
+
+    fn list_chat_raw_events_since(
+        &self,
+        thread_id: &str,
+        pointer: u64,
+        after_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<ChatRawEventRecord>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let max_rows = limit.clamp(1, 10_000) as i64;
+        let mut stmt = conn
+            .prepare(
+                "SELECT rowid, run_id, thread_id, method, params_json, created_at, event_pointer
+                 FROM chat_raw_events
+                 WHERE thread_id = ?1 AND event_pointer >= ?2 AND rowid > ?3
+                 ORDER BY rowid ASC
+                 LIMIT ?4",
+            )
+            .map_err(|e| format!("list_chat_raw_events_since prepare: {e}"))?;
+
+        let rows = stmt
+            .query_map(
+                params![
+                    thread_id,
+                    pointer.min(i64::MAX as u64) as i64,
+                    after_seq.min(i64::MAX as u64) as i64,
+                    max_rows
+                ],
+                chat_raw_event_from_row,
+            )
+            .map_err(|e| format!("list_chat_raw_events_since query: {e}"))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_chat_raw_events_since collect: {e}"))
+    }
+
+    fn oldest_chat_raw_event_pointer(&self, thread_id: &str) -> Result<Option<u64>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.query_row(
+            "SELECT MIN(event_pointer) FROM chat_raw_events WHERE thread_id = ?1",
+            params![thread_id],
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .map(|pointer| pointer.map(|p| p as u64))
+        .map_err(|e| format!("oldest_chat_raw_event_pointer: {e}"))
+    }
+
+    fn insert_tool_invocation(&self, record: &ToolInvocationRecord) -> Result<(), String> {
+        let args_json = serde_json::to_string(&record.args)
+            .map_err(|e| format!("serialize tool invocation args: {e}"))?;
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "INSERT INTO tool_invocations
+                (invocation_id, chat_id, thread_id, turn_id, tool_call_id, tool, args_json,
+                 args_hash, duration_ms, outcome, approver, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                record.invocation_id,
+                record.chat_id,
+                record.thread_id,
+                record.turn_id,
+                record.tool_call_id,
+                record.tool,
+                args_json,
+                record.args_hash,
+                record.duration_ms as i64,
+                record.outcome,
+                record.approver,
+                record.created_at as i64,
+            ],
+        )
+        .map_err(|e| format!("insert_tool_invocation: {e}"))?;
+        Ok(())
+    }
+
+    fn list_tool_invocations(
+        &self,
+        thread_id: &str,
+        limit: usize,
+    ) -> Result<Vec<ToolInvocationRecord>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT invocation_id, chat_id, thread_id, turn_id, tool_call_id, tool, args_json,
+                        args_hash, duration_ms, outcome, approver, created_at
+                 FROM tool_invocations
+                 WHERE thread_id = ?1
+                 ORDER BY created_at DESC, rowid DESC
+                 LIMIT ?2",
+            )
+            .map_err(|e| format!("list_tool_invocations prepare: {e}"))?;
+
+        let rows = stmt
+            .query_map(params![thread_id, limit as i64], tool_invocation_from_row)
+            .map_err(|e| format!("list_tool_invocations query: {e}"))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_tool_invocations collect: {e}"))
+    }
+
+    fn prune_tool_invocations(&self, retention_days: u64) -> Result<usize, String> {
+        let cutoff = now_unix().saturating_sub(retention_days.saturating_mul(86_400));
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "DELETE FROM tool_invocations WHERE created_at < ?1",
+            params![cutoff as i64],
+        )
+        .map_err(|e| format!("prune_tool_invocations: {e}"))
+    }
+
+    fn insert_attachment(&self, attachment: &AttachmentRecord) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "INSERT INTO chat_attachments (attachment_id, thread_id, mime_type, data, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                attachment.attachment_id,
+                attachment.thread_id,
+                attachment.mime_type,
+                attachment.data,
+                attachment.created_at as i64,
+            ],
+        )
+        .map_err(|e| format!("insert_attachment: {e}"))?;
+        Ok(())
+    }
+
+    fn get_attachment(&self, attachment_id: &str) -> Result<Option<AttachmentRecord>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.query_row(
+            "SELECT attachment_id, thread_id, mime_type, data, created_at
+             FROM chat_attachments WHERE attachment_id = ?1",
+            params![attachment_id],
+            |row| {
+                let created_at: i64 = row.get(4)?;
+                Ok(AttachmentRecord {
+                    attachment_id: row.get(0)?,
                     thread_id: row.get(1)?,
-                    method: row.get(2)?,
-                    params: parse_chat_thread_state_json(raw)?,
-                    created_at: row.get::<_, i64>(4)? as u64,
+                    mime_type: row.get(2)?,
+                    data: row.get(3)?,
+                    created_at: created_at as u64,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| format!("get_attachment: {e}"))
+    }
+
+    fn delete_attachments_for_thread(&self, thread_id: &str) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        delete_thread_attachments(&conn, thread_id)
+    }
+
+    fn add_turn_usage(&self, usage: &TurnUsageRecord) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "INSERT INTO turn_usage
+                (thread_id, turn_id, chat_id, identity, model, input, output, total, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT(thread_id, turn_id) DO UPDATE SET
+                input = input + excluded.input,
+                output = output + excluded.output,
+                total = total + excluded.total,
+                model = COALESCE(turn_usage.model, excluded.model)",
+            params![
+                usage.thread_id,
+                usage.turn_id,
+                usage.chat_id,
+                usage.identity,
+                usage.model,
+                usage.input as i64,
+                usage.output as i64,
+                usage.total as i64,
+                usage.created_at as i64,
+            ],
+        )
+        .map_err(|e| format!("add_turn_usage: {e}"))?;
+        Ok(())
+    }
+
+    fn usage_summary(&self, query: &UsageQuery) -> Result<Vec<UsageBucket>, String> {
+        let key = match query.group_by {
+            UsageGroupBy::Day => "strftime('%Y-%m-%d', created_at, 'unixepoch')",
+            UsageGroupBy::Model => "COALESCE(model, 'unknown')",
+        };
+        let scope = if query.chat_id.is_some() {
+            "chat_id = ?1"
+        } else {
+            "identity IS ?1"
+        };
+        let sql = format!(
+            "SELECT {key} AS bucket, SUM(input), SUM(output), SUM(total)
+             FROM turn_usage
+             WHERE {scope} AND created_at BETWEEN ?2 AND ?3
+             GROUP BY bucket
+             ORDER BY bucket"
+        );
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("usage_summary prepare: {e}"))?;
+        let scope_value = query.chat_id.as_deref().or(query.identity.as_deref());
+        let to = query.to.min(i64::MAX as u64) as i64;
+        let rows = stmt
+            .query_map(params![scope_value, query.from as i64, to], |row| {
+                let input: i64 = row.get(1)?;
+                let output: i64 = row.get(2)?;
+                let total: i64 = row.get(3)?;
+                Ok(UsageBucket {
+                    key: row.get(0)?,
+                    input: input as u64,
+                    output: output as u64,
+                    total: total as u64,
                 })
             })
-            .map_err(|e| format!("list_chat_raw_events query: {e}"))?;
+            .map_err(|e| format!("usage_summary query: {e}"))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("usage_summary collect: {e}"))
+    }
+
+    fn insert_bus_event(&self, event: &BusEventRecord) -> Result<u64, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let params_json = serialize_settings(event.params.as_ref())?;
+        conn.execute(
+            "INSERT INTO bus_events (origin, topic, params_json, created_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                event.origin,
+                event.topic,
+                params_json,
+                event.created_at as i64
+            ],
+        )
+        .map_err(|e| format!("insert_bus_event: {e}"))?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
 
+    fn list_bus_events_after(
+        &self,
+        after_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<BusEventRecord>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT seq, origin, topic, params_json, created_at
+                 FROM bus_events WHERE seq > ?1 ORDER BY seq LIMIT ?2",
+            )
+            .map_err(|e| format!("list_bus_events_after prepare: {e}"))?;
+        let rows = stmt
+            .query_map(params![after_seq as i64, limit as i64], |row| {
+                Ok(BusEventRecord {
+                    seq: row.get::<_, i64>(0)? as u64,
+                    origin: row.get(1)?,
+                    topic: row.get(2)?,
+                    params: parse_settings_json(row.get(3)?)?,
+                    created_at: row.get::<_, i64>(4)? as u64,
+                })
+            })
+            .map_err(|e| format!("list_bus_events_after query: {e}"))?;
         rows.collect::<Result<Vec<_>, _>>()
-            .map_err(|e| format!("list_chat_raw_events collect: {e}"))
+            .map_err(|e| format!("list_bus_events_after collect: {e}"))
+    }
+
+    fn latest_bus_event_seq(&self) -> Result<u64, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM bus_events", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map(|seq| seq as u64)
+        .map_err(|e| format!("latest_bus_event_seq: {e}"))
+    }
+
+    fn prune_bus_events(&self, before: u64) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "DELETE FROM bus_events WHERE created_at < ?1",
+            params![before as i64],
+        )
+        .map_err(|e| format!("prune_bus_events: {e}"))
+    }
+
+    fn insert_audit_entries(&self, entries: &[AuditEntry]) -> Result<(), String> {
+        let mut conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("insert_audit_entries begin: {e}"))?;
+        {
+            let mut stmt = tx
+                .prepare_cached(
+                    "INSERT INTO audit_log (timestamp, identity, role, method, target_id, outcome)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .map_err(|e| format!("insert_audit_entries prepare: {e}"))?;
+            for entry in entries {
+                stmt.execute(params![
+                    entry.timestamp as i64,
+                    entry.identity,
+                    entry.role,
+                    entry.method,
+                    entry.target_id,
+                    entry.outcome,
+                ])
+                .map_err(|e| format!("insert_audit_entries: {e}"))?;
+            }
+        }
+        tx.commit()
+            .map_err(|e| format!("insert_audit_entries commit: {e}"))
     }
 
-    fn prune_chat_raw_events(&self, max_runs: usize) -> Result<(), String> {
+    fn prune_audit_log(&self, retention_days: u64) -> Result<usize, String> {
+        let cutoff = now_unix().saturating_sub(retention_days.saturating_mul(86_400));
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         conn.execute(
-            "DELETE FROM chat_raw_events
+            "DELETE FROM audit_log WHERE timestamp < ?1",
+            params![cutoff as i64],
+        )
+        .map_err(|e| format!("prune_audit_log: {e}"))
+    }
+
+    fn list_audit_entries(
+        &self,
+        method: Option<&str>,
+        identity: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT timestamp, identity, role, method, target_id, outcome
+                 FROM audit_log
+                 WHERE (?1 IS NULL OR method = ?1) AND (?2 IS NULL OR identity = ?2)
+                 ORDER BY seq DESC
+                 LIMIT ?3",
+            )
+            .map_err(|e| format!("list_audit_entries prepare: {e}"))?;
+        let rows = stmt
+            .query_map(params![method, identity, limit as i64], |row| {
+                Ok(AuditEntry {
+                    timestamp: row.get::<_, i64>(0)? as u64,
+                    identity: row.get(1)?,
+                    role: row.get(2)?,
+                    method: row.get(3)?,
+                    target_id: row.get(4)?,
+                    outcome: row.get(5)?,
+                })
+            })
+            .map_err(|e| format!("list_audit_entries query: {e}"))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_audit_entries collect: {e}"))
+    }
+
+    fn search_chats(
+        &self,
+        query: &str,
+        owner: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ChatSearchHit>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        search::search(&conn, query, owner, limit)
+    }
+
+    fn unindex_chat_items(&self, thread_id: &str, item_ids: &[String]) -> Result<(), String> {
+        let mut conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("unindex_chat_items begin: {e}"))?;
+        search::delete_items(&tx, thread_id, item_ids)?;
+        tx.commit()
+            .map_err(|e| format!("unindex_chat_items commit: {e}"))
+    }
+
+    fn prune_search_index(&self) -> Result<usize, String> {
+        let mut conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("prune_search_index begin: {e}"))?;
+        let removed = search::delete_orphans(&tx)?;
+        tx.commit()
+            .map_err(|e| format!("prune_search_index commit: {e}"))?;
+        Ok(removed)
+    }
+
+    fn prune_chat_raw_events(&self, max_runs: usize) -> Result<usize, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let events = conn
+            .execute(
+                "DELETE FROM chat_raw_events
              WHERE run_id NOT IN (
                 SELECT run_id FROM chat_runs ORDER BY started_at DESC LIMIT ?1
              )",
-            params![max_runs as i64],
-        )
-        .map_err(|e| format!("prune_chat_raw_events events: {e}"))?;
-        conn.execute(
-            "DELETE FROM chat_runs
+                params![max_runs as i64],
+            )
+            .map_err(|e| format!("prune_chat_raw_events events: {e}"))?;
+        let runs = conn
+            .execute(
+                "DELETE FROM chat_runs
              WHERE run_id NOT IN (
                 SELECT run_id FROM chat_runs ORDER BY started_at DESC LIMIT ?1
              )",
-            params![max_runs as i64],
-        )
-        .map_err(|e| format!("prune_chat_raw_events runs: {e}"))?;
-        Ok(())
+                params![max_runs as i64],
+            )
+            .map_err(|e| format!("prune_chat_raw_events runs: {e}"))?;
+        Ok(events + runs)
+    }
+
+    fn count_chat_raw_events(&self) -> Result<RawEventCounts, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let count = |table: &str| {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|n| n as usize)
+            .map_err(|e| format!("count_chat_raw_events {table}: {e}"))
+        };
+        Ok(RawEventCounts {
+            events: count("chat_raw_events")?,
+            runs: count("chat_runs")?,
+        })
     }
 
     fn upsert_cron(&self, cron: &CronRecord) -> Result<(), String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         conn.execute(
-            "INSERT INTO cron_jobs (cron_id, name, schedule, command, status, skip_overlap, created_at, updated_at, last_run_at, next_run_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            "INSERT INTO cron_jobs (cron_id, name, schedule, command, status, skip_overlap, created_at, updated_at, last_run_at, next_run_at, max_retries, backoff_secs, retry_count, next_retry_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
              ON CONFLICT(cron_id) DO UPDATE SET
                 name = excluded.name,
                 schedule = excluded.schedule,
@@ -879,7 +2019,9 @@ impl Store for SqliteStore {
                 skip_overlap = excluded.skip_overlap,
                 updated_at = excluded.updated_at,
                 last_run_at = excluded.last_run_at,
-                next_run_at = excluded.next_run_at",
+                next_run_at = excluded.next_run_at,
+                max_retries = excluded.max_retries,
+                backoff_secs = excluded.backoff_secs",
             params![
                 cron.cron_id,
                 cron.name,
@@ -891,18 +2033,38 @@ impl Store for SqliteStore {
                 cron.updated_at as i64,
                 cron.last_run_at.map(|v| v as i64),
                 cron.next_run_at.map(|v| v as i64),
+                cron.max_retries as i64,
+                cron.backoff_secs as i64,
+                cron.retry_count as i64,
+                cron.next_retry_at.map(|v| v as i64),
             ],
         )
         .map_err(|e| format!("upsert_cron: {e}"))?;
         Ok(())
     }
 
+    fn update_cron_retry(
+        &self,
+        cron_id: &str,
+        retry_count: u32,
+        next_retry_at: Option<u64>,
+    ) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "UPDATE cron_jobs SET retry_count = ?1, next_retry_at = ?2 WHERE cron_id = ?3",
+            params![retry_count as i64, next_retry_at.map(|v| v as i64), cron_id],
+        )
+        .map_err(|e| format!("update_cron_retry: {e}"))?;
+        Ok(())
+    }
+
     fn get_cron(&self, cron_id: &str) -> Result<Option<CronRecord>, String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         let mut stmt = conn
             .prepare(
                 "SELECT cron_id, name, schedule, command, status, skip_overlap, created_at,
-                        updated_at, last_run_at, next_run_at
+                        updated_at, last_run_at, next_run_at, max_retries, backoff_secs,
+                        retry_count, next_retry_at
                  FROM cron_jobs WHERE cron_id = ?1",
             )
             .map_err(|e| format!("get_cron prepare: {e}"))?;
@@ -920,6 +2082,10 @@ impl Store for SqliteStore {
                     updated_at: row.get::<_, i64>(7)? as u64,
                     last_run_at: row.get::<_, Option<i64>>(8)?.map(|v| v as u64),
                     next_run_at: row.get::<_, Option<i64>>(9)?.map(|v| v as u64),
+                    max_retries: row.get::<_, i64>(10)? as u32,
+                    backoff_secs: row.get::<_, i64>(11)? as u64,
+                    retry_count: row.get::<_, i64>(12)? as u32,
+                    next_retry_at: row.get::<_, Option<i64>>(13)?.map(|v| v as u64),
                 })
             })
             .map_err(|e| format!("get_cron query: {e}"))?;
@@ -936,7 +2102,8 @@ impl Store for SqliteStore {
         let mut stmt = conn
             .prepare(
                 "SELECT cron_id, name, schedule, command, status, skip_overlap, created_at,
-                        updated_at, last_run_at, next_run_at
+                        updated_at, last_run_at, next_run_at, max_retries, backoff_secs,
+                        retry_count, next_retry_at
                  FROM cron_jobs
                  ORDER BY created_at DESC",
             )
@@ -955,6 +2122,10 @@ impl Store for SqliteStore {
                     updated_at: row.get::<_, i64>(7)? as u64,
                     last_run_at: row.get::<_, Option<i64>>(8)?.map(|v| v as u64),
                     next_run_at: row.get::<_, Option<i64>>(9)?.map(|v| v as u64),
+                    max_retries: row.get::<_, i64>(10)? as u32,
+                    backoff_secs: row.get::<_, i64>(11)? as u64,
+                    retry_count: row.get::<_, i64>(12)? as u32,
+                    next_retry_at: row.get::<_, Option<i64>>(13)?.map(|v| v as u64),
                 })
             })
             .map_err(|e| format!("list_crons query: {e}"))?;
@@ -963,6 +2134,111 @@ impl Store for SqliteStore {
             .map_err(|e| format!("list_crons collect: {e}"))
     }
 
+    fn claim_due_crons(
+        &self,
+        now: u64,
+        worker_id: &str,
+        lease_secs: u64,
+    ) -> Result<Vec<CronRecord>, String> {
+        let mut conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        // Take the write lock up front so two gateways sharing the file
+        // can't both read the same due rows before either updates them.
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|e| format!("claim_due_crons begin: {e}"))?;
+        let now_i = now as i64;
+        tx.execute(
+            "UPDATE cron_runs
+             SET status = 'failed', finished_at = ?1, error = 'worker lease expired'
+             WHERE status = 'running' AND cron_id IN (
+                SELECT cron_id FROM cron_jobs
+                WHERE lease_owner IS NOT NULL AND lease_owner != ?2 AND lease_expires_at <= ?1
+             )",
+            params![now_i, worker_id],
+        )
+        .map_err(|e| format!("claim_due_crons recover: {e}"))?;
+        tx.execute(
+            "UPDATE cron_jobs SET lease_owner = NULL, lease_expires_at = NULL
+             WHERE lease_owner IS NOT NULL AND lease_owner != ?2 AND lease_expires_at <= ?1",
+            params![now_i, worker_id],
+        )
+        .map_err(|e| format!("claim_due_crons expire: {e}"))?;
+        let crons = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT cron_id, name, schedule, command, status, skip_overlap, created_at,
+                            updated_at, last_run_at, next_run_at, max_retries, backoff_secs,
+                            retry_count, next_retry_at
+                     FROM cron_jobs
+                     WHERE status = 'active'
+                       AND (lease_owner IS NULL OR lease_owner = ?2)
+                       AND (next_run_at IS NULL OR next_run_at <= ?1 OR next_retry_at <= ?1)
+                     ORDER BY created_at DESC",
+                )
+                .map_err(|e| format!("claim_due_crons prepare: {e}"))?;
+            let rows = stmt
+                .query_map(params![now_i, worker_id], |row| {
+                    Ok(CronRecord {
+                        cron_id: row.get(0)?,
+                        name: row.get(1)?,
+                        schedule: row.get(2)?,
+                        command: row.get(3)?,
+                        status: CronStatus::from_label(&row.get::<_, String>(4)?),
+                        skip_overlap: row.get(5)?,
+                        created_at: row.get::<_, i64>(6)? as u64,
+                        updated_at: row.get::<_, i64>(7)? as u64,
+                        last_run_at: row.get::<_, Option<i64>>(8)?.map(|v| v as u64),
+                        next_run_at: row.get::<_, Option<i64>>(9)?.map(|v| v as u64),
+                        max_retries: row.get::<_, i64>(10)? as u32,
+                        backoff_secs: row.get::<_, i64>(11)? as u64,
+                        retry_count: row.get::<_, i64>(12)? as u32,
+                        next_retry_at: row.get::<_, Option<i64>>(13)?.map(|v| v as u64),
+                    })
+                })
+                .map_err(|e| format!("claim_due_crons query: {e}"))?;
+            rows.collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("claim_due_crons collect: {e}"))?
+        };
+        let expires_at = now.saturating_add(lease_secs) as i64;
+        for cron in &crons {
+            tx.execute(
+                "UPDATE cron_jobs SET lease_owner = ?1, lease_expires_at = ?2 WHERE cron_id = ?3",
+                params![worker_id, expires_at, cron.cron_id],
+            )
+            .map_err(|e| format!("claim_due_crons lease: {e}"))?;
+        }
+        tx.commit()
+            .map_err(|e| format!("claim_due_crons commit: {e}"))?;
+        Ok(crons)
+    }
+
+    fn renew_cron_lease(
+        &self,
+        cron_id: &str,
+        worker_id: &str,
+        expires_at: u64,
+    ) -> Result<bool, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let updated = conn
+            .execute(
+                "UPDATE cron_jobs SET lease_expires_at = ?1 WHERE cron_id = ?2 AND lease_owner = ?3",
+                params![expires_at as i64, cron_id, worker_id],
+            )
+            .map_err(|e| format!("renew_cron_lease: {e}"))?;
+        Ok(updated > 0)
+    }
+
+    fn release_cron_lease(&self, cron_id: &str, worker_id: &str) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute(
+            "UPDATE cron_jobs SET lease_owner = NULL, lease_expires_at = NULL
+             WHERE cron_id = ?1 AND lease_owner = ?2",
+            params![cron_id, worker_id],
+        )
+        .map_err(|e| format!("release_cron_lease: {e}"))?;
+        Ok(())
+    }
+
     fn delete_cron(&self, cron_id: &str) -> Result<(), String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         conn.execute("DELETE FROM cron_jobs WHERE cron_id = ?1", params![cron_id])
@@ -1115,14 +2391,15 @@ impl Store for SqliteStore {
         Ok(rows.next().is_some())
     }
 
-    fn prune_cron_runs(&self, retention_days: u64, max_runs: usize) -> Result<(), String> {
+    fn prune_cron_runs(&self, retention_days: u64, max_runs: usize) -> Result<usize, String> {
         let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
         let cutoff = now_unix().saturating_sub(retention_days.saturating_mul(86_400));
-        conn.execute(
-            "DELETE FROM cron_runs WHERE finished_at IS NOT NULL AND finished_at < ?1",
-            params![cutoff as i64],
-        )
-        .map_err(|e| format!("prune_cron_runs cutoff: {e}"))?;
+        let mut removed = conn
+            .execute(
+                "DELETE FROM cron_runs WHERE finished_at IS NOT NULL AND finished_at < ?1",
+                params![cutoff as i64],
+            )
+            .map_err(|e| format!("prune_cron_runs cutoff: {e}"))?;
 
         let mut stmt = conn
             .prepare("SELECT DISTINCT cron_id FROM cron_runs")
@@ -1151,12 +2428,200 @@ impl Store for SqliteStore {
                 .map_err(|e| format!("prune_cron_runs run id collect: {e}"))?;
 
             for run_id in run_ids {
-                conn.execute("DELETE FROM cron_runs WHERE run_id = ?1", params![run_id])
+                removed += conn
+                    .execute("DELETE FROM cron_runs WHERE run_id = ?1", params![run_id])
                     .map_err(|e| format!("prune_cron_runs delete run: {e}"))?;
             }
         }
-        Ok(())
+        Ok(removed)
+    }
+
+    fn get_state(&self, scope: &str, key: &str) -> Result<Option<StateEntry>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.query_row(
+            "SELECT key, value_json, updated_at FROM state_entries
+             WHERE scope = ?1 AND key = ?2",
+            params![scope, key],
+            state_entry_from_row,
+        )
+        .optional()
+        .map_err(|e| format!("get_state: {e}"))
+    }
+
+    fn set_state(
+        &self,
+        scope: &str,
+        key: &str,
+        value: &serde_json::Value,
+        max_keys: usize,
+    ) -> Result<bool, String> {
+        let value_json =
+            serde_json::to_string(value).map_err(|e| format!("serialize state value: {e}"))?;
+        let mut conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|e| format!("set_state begin: {e}"))?;
+        let exists = tx
+            .query_row(
+                "SELECT 1 FROM state_entries WHERE scope = ?1 AND key = ?2",
+                params![scope, key],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| format!("set_state lookup: {e}"))?
+            .is_some();
+        if !exists {
+            let count: i64 = tx
+                .query_row(
+                    "SELECT COUNT(*) FROM state_entries WHERE scope = ?1",
+                    params![scope],
+                    |row| row.get(0),
+                )
+                .map_err(|e| format!("set_state count: {e}"))?;
+            if count as usize >= max_keys {
+                return Ok(false);
+            }
+        }
+        tx.execute(
+            "INSERT INTO state_entries (scope, key, value_json, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(scope, key) DO UPDATE SET
+                value_json = excluded.value_json,
+                updated_at = excluded.updated_at",
+            params![scope, key, value_json, now_unix() as i64],
+        )
+        .map_err(|e| format!("set_state: {e}"))?;
+        tx.commit().map_err(|e| format!("set_state commit: {e}"))?;
+        Ok(true)
     }
+
+    fn delete_state(&self, scope: &str, key: &str) -> Result<bool, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        let removed = conn
+            .execute(
+                "DELETE FROM state_entries WHERE scope = ?1 AND key = ?2",
+                params![scope, key],
+            )
+            .map_err(|e| format!("delete_state: {e}"))?;
+        Ok(removed > 0)
+    }
+
+    fn list_state(&self, scope: &str, prefix: &str) -> Result<Vec<StateEntry>, String> {
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        // substr() rather than LIKE so `%` and `_` in prefixes match literally.
+        let mut stmt = conn
+            .prepare(
+                "SELECT key, value_json, updated_at FROM state_entries
+                 WHERE scope = ?1 AND substr(key, 1, length(?2)) = ?2
+                 ORDER BY key",
+            )
+            .map_err(|e| format!("list_state prepare: {e}"))?;
+        let rows = stmt
+            .query_map(params![scope, prefix], state_entry_from_row)
+            .map_err(|e| format!("list_state query: {e}"))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("list_state collect: {e}"))
+    }
+
+    fn run_maintenance(&self, policy: &RetentionPolicy) -> Result<MaintenanceReport, String> {
+        let mut report = MaintenanceReport {
+            jobs: self.prune_jobs(policy.job_retention_days, policy.job_max_records)?,
+            pairings: self.prune_pairings(policy.pairing_retention_secs)?,
+            login_sessions: self.prune_login_sessions()?,
+            notifications: self.prune_notifications(policy.notification_retention_days)?,
+            chat_raw_events: self.prune_chat_raw_events(policy.chat_raw_event_max_runs)?,
+            cron_runs: self
+                .prune_cron_runs(policy.cron_retention_days, policy.cron_max_run_records)?,
+            terminal_recordings: self.prune_terminal_recordings(
+                policy.terminal_recording_retention_days,
+                policy.terminal_recording_max_records,
+            )?,
+            audit_log: self.prune_audit_log(policy.audit_retention_days)?,
+            tool_invocations: self.prune_tool_invocations(policy.tool_invocation_retention_days)?,
+            search_index: self.prune_search_index()?,
+            vacuumed: false,
+        };
+
+        let conn = self.conn.lock().map_err(|e| format!("lock: {e}"))?;
+        conn.execute_batch("PRAGMA optimize")
+            .map_err(|e| format!("maintenance optimize: {e}"))?;
+        let pragma = |name: &str| -> Result<i64, String> {
+            conn.query_row(&format!("PRAGMA {name}"), [], |row| row.get(0))
+                .map_err(|e| format!("maintenance {name}: {e}"))
+        };
+        // VACUUM rewrites the whole file, so only pay for it once enough
+        // pages sit unused.
+        let free_pages = pragma("freelist_count")?;
+        let total_pages = pragma("page_count")?;
+        if free_pages > 0 && free_pages.saturating_mul(VACUUM_FREE_PAGE_RATIO) >= total_pages {
+            conn.execute_batch("VACUUM")
+                .map_err(|e| format!("maintenance vacuum: {e}"))?;
+            report.vacuumed = true;
+        }
+        Ok(report)
+    }
+}
+
+fn tool_invocation_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ToolInvocationRecord> {
+    let args_json: String = row.get(6)?;
+    let args = serde_json::from_str(&args_json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(e)))?;
+    Ok(ToolInvocationRecord {
+        invocation_id: row.get(0)?,
+        chat_id: row.get(1)?,
+        thread_id: row.get(2)?,
+        turn_id: row.get(3)?,
+        tool_call_id: row.get(4)?,
+        tool: row.get(5)?,
+        args,
+        args_hash: row.get(7)?,
+        duration_ms: row.get::<_, i64>(8)? as u64,
+        outcome: row.get(9)?,
+        approver: row.get(10)?,
+        created_at: row.get::<_, i64>(11)? as u64,
+    })
+}
+
+fn state_entry_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<StateEntry> {
+    let raw: String = row.get(1)?;
+    Ok(StateEntry {
+        key: row.get(0)?,
+        value: parse_chat_thread_state_json(raw)?,
+        updated_at: row.get::<_, i64>(2)? as u64,
+    })
+}
+
+fn notification_event_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<NotificationEvent> {
+    Ok(NotificationEvent {
+        notification_id: row.get(0)?,
+        title: row.get(1)?,
+        body: row.get(2)?,
+        target: row.get(3)?,
+        created_at: row.get::<_, i64>(4)? as u64,
+        payload: parse_settings_json(row.get(5)?)?,
+        attempts: row.get::<_, i64>(6)? as u32,
+        delivered_at: row.get::<_, Option<i64>>(7)?.map(|v| v as u64),
+    })
+}
+
+fn chat_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ChatRecord> {
+    Ok(ChatRecord {
+        chat_id: row.get(0)?,
+        thread_id: row.get(1)?,
+        created_at: row.get(2)?,
+        status: SessionStatus::from_label(&row.get::<_, String>(3)?),
+        event_pointer: row.get::<_, i64>(4)? as u64,
+        settings: parse_settings_json(row.get(5)?)?,
+        owner: row.get(6)?,
+    })
+}
+
+fn delete_thread_attachments(conn: &Connection, thread_id: &str) -> Result<usize, String> {
+    conn.execute(
+        "DELETE FROM chat_attachments WHERE thread_id = ?1",
+        params![thread_id],
+    )
+    .map_err(|e| format!("delete_thread_attachments: {e}"))
 }
 
 fn parse_settings_json(raw: Option<String>) -> Result<Option<serde_json::Value>, rusqlite::Error> {
@@ -1186,6 +2651,32 @@ fn parse_chat_thread_state_json(raw: String) -> Result<serde_json::Value, rusqli
         .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
 }
 
+fn chat_raw_event_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ChatRawEventRecord> {
+    let raw: String = row.get(4)?;
+    Ok(ChatRawEventRecord {
+        seq: row.get::<_, i64>(0)? as u64,
+        run_id: row.get(1)?,
+        thread_id: row.get(2)?,
+        method: row.get(3)?,
+        params: parse_chat_thread_state_json(raw)?,
+        created_at: row.get::<_, i64>(5)? as u64,
+        event_pointer: row.get::<_, Option<i64>>(6)?.map(|p| p as u64),
+    })
+}
+
+fn login_session_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<LoginSessionRecord> {
+    Ok(LoginSessionRecord {
+        provider: row.get(0)?,
+        profile: row.get(1)?,
+        device_code: row.get(2)?,
+        user_code: row.get(3)?,
+        verification_url: row.get(4)?,
+        interval_secs: row.get::<_, i64>(5)? as u64,
+        created_at: row.get::<_, i64>(6)? as u64,
+        expires_at: row.get::<_, i64>(7)? as u64,
+    })
+}
+
 fn now_unix() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -1196,6 +2687,7 @@ fn now_unix() -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::TerminalSpawn;
 
     fn make_store() -> SqliteStore {
         SqliteStore::open_memory().unwrap()
@@ -1218,6 +2710,7 @@ mod tests {
             status: SessionStatus::Active,
             event_pointer: 0,
             settings: None,
+            owner: None,
         };
         store.upsert_chat(&chat).unwrap();
 
@@ -1237,6 +2730,7 @@ mod tests {
             status: SessionStatus::Active,
             event_pointer: 0,
             settings: None,
+            owner: None,
         };
         store.upsert_chat(&chat).unwrap();
 
@@ -1252,6 +2746,161 @@ mod tests {
         assert_eq!(loaded.event_pointer, 42);
     }
 
+    #[test]
+    fn attachments_live_until_their_thread_is_deleted() {
+        let store = make_store();
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: "c1".into(),
+                thread_id: "t1".into(),
+                created_at: "100s".into(),
+                status: SessionStatus::Active,
+                event_pointer: 0,
+                settings: None,
+                owner: None,
+            })
+            .unwrap();
+        for (attachment_id, thread_id) in [("a1", "t1"), ("a2", "t1"), ("b1", "t2")] {
+            store
+                .insert_attachment(&AttachmentRecord {
+                    attachment_id: attachment_id.into(),
+                    thread_id: thread_id.into(),
+                    mime_type: "image/png".into(),
+                    data: vec![0x89, b'P', b'N', b'G'],
+                    created_at: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            store.get_chat_by_thread("t1").unwrap().unwrap().chat_id,
+            "c1"
+        );
+        assert!(store.get_chat_by_thread("t2").unwrap().is_none());
+        let loaded = store.get_attachment("a1").unwrap().unwrap();
+        assert_eq!(loaded.data, vec![0x89, b'P', b'N', b'G']);
+        assert_eq!(loaded.thread_id, "t1");
+
+        store.delete_chat("c1").unwrap();
+        assert!(store.get_attachment("a1").unwrap().is_none());
+        assert!(store.get_attachment("a2").unwrap().is_none());
+        assert_eq!(store.delete_attachments_for_thread("t2").unwrap(), 1);
+        assert!(store.get_attachment("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn bus_events_list_in_order_after_a_cursor_and_prune_by_age() {
+        let store = make_store();
+        assert_eq!(store.latest_bus_event_seq().unwrap(), 0);
+        let event = |origin: &str, topic: &str, at: u64| BusEventRecord {
+            seq: 0,
+            origin: origin.into(),
+            topic: topic.into(),
+            params: Some(serde_json::json!({ "at": at })),
+            created_at: at,
+        };
+        let first = store
+            .insert_bus_event(&event("node-a", "cron.run.completed", 10))
+            .unwrap();
+        let second = store
+            .insert_bus_event(&event("node-b", "terminal.session.exit", 20))
+            .unwrap();
+        assert!(second > first);
+        assert_eq!(store.latest_bus_event_seq().unwrap(), second);
+
+        let after_first = store.list_bus_events_after(first, 10).unwrap();
+        assert_eq!(after_first.len(), 1);
+        assert_eq!(after_first[0].origin, "node-b");
+        assert_eq!(after_first[0].params, Some(serde_json::json!({ "at": 20 })));
+        assert_eq!(store.list_bus_events_after(0, 1).unwrap()[0].seq, first);
+
+        assert_eq!(store.prune_bus_events(15).unwrap(), 1);
+        assert_eq!(store.list_bus_events_after(0, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn usage_summary_buckets_turns_by_day_and_model() {
+        let store = make_store();
+        let day = 86_400;
+        let usage = |thread: &str, turn: &str, identity: &str, model: Option<&str>, at: u64| {
+            TurnUsageRecord {
+                chat_id: format!("chat-{thread}"),
+                thread_id: thread.into(),
+                turn_id: turn.into(),
+                identity: Some(identity.into()),
+                model: model.map(str::to_string),
+                input: 100,
+                output: 10,
+                total: 110,
+                created_at: at,
+            }
+        };
+        store
+            .add_turn_usage(&usage("t1", "r1", "alice", Some("gpt-5"), day))
+            .unwrap();
+        // A second report for the same turn adds to it.
+        store
+            .add_turn_usage(&usage("t1", "r1", "alice", Some("gpt-5"), day + 5))
+            .unwrap();
+        store
+            .add_turn_usage(&usage("t1", "r2", "alice", None, 2 * day + 1))
+            .unwrap();
+        store
+            .add_turn_usage(&usage("t2", "r1", "alice", Some("o3"), 2 * day + 2))
+            .unwrap();
+        store
+            .add_turn_usage(&usage("t3", "r1", "bob", Some("o3"), 2 * day + 3))
+            .unwrap();
+
+        let by_day = store
+            .usage_summary(&UsageQuery {
+                identity: Some("alice".into()),
+                to: u64::MAX,
+                ..UsageQuery::default()
+            })
+            .unwrap();
+        assert_eq!(
+            by_day,
+            vec![
+                UsageBucket {
+                    key: "1970-01-02".into(),
+                    input: 200,
+                    output: 20,
+                    total: 220,
+                },
+                UsageBucket {
+                    key: "1970-01-03".into(),
+                    input: 200,
+                    output: 20,
+                    total: 220,
+                },
+            ]
+        );
+
+        let by_model = store
+            .usage_summary(&UsageQuery {
+                chat_id: Some("chat-t1".into()),
+                to: u64::MAX,
+                group_by: UsageGroupBy::Model,
+                ..UsageQuery::default()
+            })
+            .unwrap();
+        let keys: Vec<&str> = by_model.iter().map(|bucket| bucket.key.as_str()).collect();
+        assert_eq!(keys, vec!["gpt-5", "unknown"]);
+        assert_eq!(by_model[0].total, 220);
+
+        let windowed = store
+            .usage_summary(&UsageQuery {
+                identity: Some("alice".into()),
+                from: 2 * day,
+                to: 3 * day,
+                group_by: UsageGroupBy::Model,
+                ..UsageQuery::default()
+            })
+            .unwrap();
+        let keys: Vec<&str> = windowed.iter().map(|bucket| bucket.key.as_str()).collect();
+        assert_eq!(keys, vec!["o3", "unknown"]);
+    }
+
     #[test]
     fn list_chats_ordered() {
         let store = make_store();
@@ -1264,6 +2913,7 @@ mod tests {
                     status: SessionStatus::Active,
                     event_pointer: 0,
                     settings: None,
+                    owner: None,
                 })
                 .unwrap();
         }
@@ -1275,6 +2925,36 @@ mod tests {
         assert_eq!(chats[2].chat_id, "a");
     }
 
+    #[test]
+    fn list_chats_puts_pinned_first() {
+        let store = make_store();
+        for (id, ts, settings) in [
+            ("a", "100s", Some(serde_json::json!({ "pinned": true }))),
+            ("b", "200s", Some(serde_json::json!({ "pinned": false }))),
+            ("c", "150s", None),
+            ("d", "050s", Some(serde_json::json!({ "pinned": true }))),
+        ] {
+            store
+                .upsert_chat(&ChatRecord {
+                    chat_id: id.into(),
+                    thread_id: id.into(),
+                    created_at: ts.into(),
+                    status: SessionStatus::Active,
+                    event_pointer: 0,
+                    settings,
+                    owner: None,
+                })
+                .unwrap();
+        }
+        let order: Vec<String> = store
+            .list_chats()
+            .unwrap()
+            .into_iter()
+            .map(|chat| chat.chat_id)
+            .collect();
+        assert_eq!(order, ["a", "d", "b", "c"]);
+    }
+
     #[test]
     fn update_event_pointer() {
         let store = make_store();
@@ -1286,6 +2966,7 @@ mod tests {
                 status: SessionStatus::Active,
                 event_pointer: 0,
                 settings: None,
+                owner: None,
             })
             .unwrap();
 
@@ -1294,6 +2975,24 @@ mod tests {
         assert_eq!(loaded.event_pointer, 99);
     }
 
+    #[test]
+    fn set_state_caps_new_keys_but_still_updates_existing_ones() {
+        let store = make_store();
+        let value = serde_json::json!(1);
+        assert!(store.set_state("alice", "a", &value, 2).unwrap());
+        assert!(store.set_state("alice", "b", &value, 2).unwrap());
+        assert!(!store.set_state("alice", "c", &value, 2).unwrap());
+        assert!(store.get_state("alice", "c").unwrap().is_none());
+
+        let updated = serde_json::json!(2);
+        assert!(store.set_state("alice", "a", &updated, 2).unwrap());
+        assert_eq!(
+            store.get_state("alice", "a").unwrap().unwrap().value,
+            updated
+        );
+        assert!(store.set_state("bob", "c", &value, 2).unwrap());
+    }
+
     #[test]
     fn chat_thread_state_roundtrip_save_load_delete() {
         let store = make_store();
@@ -1336,6 +3035,91 @@ mod tests {
         assert!(deleted.is_none());
     }
 
+    #[test]
+    fn released_pending_runs_are_taken_once_in_queue_order() {
+        let store = make_store();
+        let run = |turn_id: &str, chat_id: &str| PendingRunRecord {
+            turn_id: turn_id.to_string(),
+            chat_id: chat_id.to_string(),
+            thread_id: format!("thread-{chat_id}"),
+            message: format!("message for {turn_id}"),
+            settings: Some(serde_json::json!({ "model": "openai:gpt-4o" })),
+            queued_at: 100,
+        };
+        store.insert_pending_run(&run("t1", "c1")).unwrap();
+        store.insert_pending_run(&run("t2", "c1")).unwrap();
+        store.insert_pending_run(&run("t3", "c1")).unwrap();
+        store.insert_pending_run(&run("t4", "c2")).unwrap();
+        store.delete_pending_run("t2").unwrap();
+        assert!(store.take_pending_runs("c1").unwrap().is_empty());
+
+        store.release_pending_run("t3").unwrap();
+        assert_eq!(store.list_released_pending_run_chats().unwrap(), vec!["c1"]);
+        assert_eq!(
+            store.take_pending_runs("c1").unwrap(),
+            vec![run("t3", "c1")]
+        );
+        assert!(store.list_released_pending_run_chats().unwrap().is_empty());
+        // A taken run stays persisted; queueing it again keeps its place.
+        store.insert_pending_run(&run("t3", "c1")).unwrap();
+        store.insert_pending_run(&run("t5", "c1")).unwrap();
+        store.release_all_pending_runs().unwrap();
+        assert_eq!(
+            store.list_released_pending_run_chats().unwrap(),
+            vec!["c1", "c2"]
+        );
+        assert_eq!(
+            store.take_pending_runs("c1").unwrap(),
+            vec![run("t1", "c1"), run("t3", "c1"), run("t5", "c1")]
+        );
+        assert!(store.take_pending_runs("c1").unwrap().is_empty());
+
+        store.delete_pending_runs_for_thread("thread-c2").unwrap();
+        assert!(store.take_pending_runs("c2").unwrap().is_empty());
+    }
+
+    #[test]
+    fn audit_entries_filter_by_method_and_identity() {
+        let store = make_store();
+        let entry = |timestamp: u64, identity: &str, method: &str| AuditEntry {
+            timestamp,
+            identity: Some(identity.to_string()),
+            role: "user".into(),
+            method: method.to_string(),
+            target_id: Some("chat-1".into()),
+            outcome: "ok".into(),
+        };
+        store
+            .insert_audit_entries(&[
+                entry(1, "alice", "chat.message.send"),
+                entry(2, "bob", "chat.message.send"),
+            ])
+            .unwrap();
+        store
+            .insert_audit_entries(&[entry(3, "alice", "jobs.cancel")])
+            .unwrap();
+
+        let all = store.list_audit_entries(None, None, 10).unwrap();
+        assert_eq!(
+            all.iter().map(|e| e.timestamp).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+        assert_eq!(
+            store
+                .list_audit_entries(Some("chat.message.send"), Some("alice"), 10)
+                .unwrap(),
+            vec![entry(1, "alice", "chat.message.send")]
+        );
+        assert_eq!(
+            store
+                .list_audit_entries(None, Some("bob"), 10)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(store.list_audit_entries(None, None, 2).unwrap().len(), 2);
+    }
+
     #[test]
     fn upsert_and_get_terminal() {
         let store = make_store();
@@ -1349,6 +3133,11 @@ mod tests {
             started_at: "100s".into(),
             status: SessionStatus::Active,
             exit_code: None,
+            spawn: Some(TerminalSpawn {
+                command: vec!["bash".into(), "-l".into()],
+                cwd: Some("/tmp".into()),
+                env: [("EDITOR".to_string(), "vim".to_string())].into(),
+            }),
         };
         store.upsert_terminal(&rec).unwrap();
 
@@ -1357,6 +3146,7 @@ mod tests {
         assert_eq!(loaded.shell, "/bin/bash");
         assert_eq!(loaded.status, SessionStatus::Active);
         assert_eq!(loaded.exit_code, None);
+        assert_eq!(loaded.spawn, rec.spawn);
     }
 
     #[test]
@@ -1372,6 +3162,7 @@ mod tests {
             started_at: "100s".into(),
             status: SessionStatus::Active,
             exit_code: None,
+            spawn: None,
         };
         store.upsert_terminal(&rec).unwrap();
 
@@ -1387,6 +3178,35 @@ mod tests {
         assert_eq!(loaded.exit_code, Some(0));
     }
 
+    #[test]
+    fn terminal_recordings_roundtrip_and_prune() {
+        let store = make_store();
+        let now = now_unix();
+        for (id, created_at) in [("old", 0), ("mid", now - 10), ("new", now)] {
+            store
+                .insert_terminal_recording(&TerminalRecordingRecord {
+                    recording_id: id.into(),
+                    session_id: Uuid::from_u128(1),
+                    started_at: created_at,
+                    duration_ms: 1500,
+                    bytes: 5,
+                    truncated: false,
+                    cast: format!("{{\"version\":2}}\n[0.5,\"o\",\"{id}\"]\n"),
+                    created_at,
+                })
+                .unwrap();
+        }
+        let loaded = store.get_terminal_recording("new").unwrap().unwrap();
+        assert_eq!(loaded.session_id, Uuid::from_u128(1));
+        assert_eq!(loaded.duration_ms, 1500);
+        assert!(loaded.cast.contains("\"new\""));
+
+        assert_eq!(store.prune_terminal_recordings(7, 1).unwrap(), 2);
+        assert!(store.get_terminal_recording("old").unwrap().is_none());
+        assert!(store.get_terminal_recording("mid").unwrap().is_none());
+        assert!(store.get_terminal_recording("new").unwrap().is_some());
+    }
+
     #[test]
     fn list_terminals_ordered() {
         let store = make_store();
@@ -1401,6 +3221,7 @@ mod tests {
                     started_at: ts.into(),
                     status: SessionStatus::Active,
                     exit_code: None,
+                    spawn: None,
                 })
                 .unwrap();
         }
@@ -1420,6 +3241,7 @@ mod tests {
                 status: SessionStatus::Active,
                 event_pointer: 0,
                 settings: None,
+                owner: None,
             })
             .unwrap();
         let sid = Uuid::new_v4();
@@ -1433,6 +3255,7 @@ mod tests {
                 started_at: "100s".into(),
                 status: SessionStatus::Active,
                 exit_code: None,
+                spawn: None,
             })
             .unwrap();
 
@@ -1462,6 +3285,7 @@ mod tests {
                 status: SessionStatus::Exited,
                 event_pointer: 5,
                 settings: None,
+                owner: None,
             })
             .unwrap();
 
@@ -1571,6 +3395,78 @@ mod tests {
         assert_eq!(pairings[0].status, PairingStatus::Expired);
     }
 
+    fn login_session(provider: &str, profile: &str, expires_at: u64) -> LoginSessionRecord {
+        LoginSessionRecord {
+            provider: provider.into(),
+            profile: profile.into(),
+            device_code: format!("dc-{provider}-{profile}"),
+            user_code: "ABCD-EFGH".into(),
+            verification_url: "https://example.com/device".into(),
+            interval_secs: 5,
+            created_at: now_unix(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn login_sessions_replace_per_provider_profile() {
+        let store = make_store();
+        let now = now_unix();
+        store
+            .upsert_login_session(&login_session("github-copilot", "default", now + 600))
+            .unwrap();
+        let mut again = login_session("github-copilot", "default", now + 900);
+        again.user_code = "WXYZ-1234".into();
+        store.upsert_login_session(&again).unwrap();
+        store
+            .upsert_login_session(&login_session("openai-codex", "work", now + 600))
+            .unwrap();
+
+        assert_eq!(store.list_login_sessions().unwrap().len(), 2);
+        let loaded = store
+            .get_login_session("github-copilot", "default")
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.user_code, "WXYZ-1234");
+        assert_eq!(loaded.expires_at, now + 900);
+        assert!(store
+            .get_login_session("github-copilot", "work")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn login_sessions_delete_filters_and_prune() {
+        let store = make_store();
+        let now = now_unix();
+        store
+            .upsert_login_session(&login_session("github-copilot", "default", now + 600))
+            .unwrap();
+        store
+            .upsert_login_session(&login_session("openai-codex", "default", now + 600))
+            .unwrap();
+        store
+            .upsert_login_session(&login_session(
+                "openai-codex",
+                "work",
+                now.saturating_sub(5),
+            ))
+            .unwrap();
+
+        assert_eq!(store.prune_login_sessions().unwrap(), 1);
+        assert_eq!(
+            store
+                .delete_login_sessions(Some("openai-codex"), None)
+                .unwrap(),
+            1
+        );
+        let remaining = store.list_login_sessions().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].provider, "github-copilot");
+        assert_eq!(store.delete_login_sessions(None, None).unwrap(), 1);
+        assert!(store.list_login_sessions().unwrap().is_empty());
+    }
+
     #[test]
     fn notifications_store_and_prune() {
         let store = make_store();
@@ -1581,6 +3477,7 @@ mod tests {
             kind: None,
             created_at: now,
             updated_at: now,
+            owner: None,
         };
         store.upsert_notification_subscription(&sub).unwrap();
         assert!(store.has_notification_target("device-1").unwrap());
@@ -1591,14 +3488,78 @@ mod tests {
             body: "b".into(),
             target: Some("device-1".into()),
             created_at: now.saturating_sub(900_000),
+            payload: None,
+            attempts: 0,
+            delivered_at: Some(now),
         };
         store.insert_notification_event(&event).unwrap();
+        let events = store.list_notification_events(10).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].target.as_deref(), Some("device-1"));
 
         store.prune_notifications(1).unwrap();
+        assert!(store.list_notification_events(10).unwrap().is_empty());
         let subs = store.list_notification_subscriptions().unwrap();
         assert_eq!(subs.len(), 1);
     }
 
+    #[test]
+    fn notification_delivery_attempts_are_tracked() {
+        let store = make_store();
+        let now = now_unix();
+        for (id, created_at) in [("old", now - 10), ("new", now)] {
+            store
+                .insert_notification_event(&NotificationEvent {
+                    notification_id: id.into(),
+                    title: "Turn completed".into(),
+                    body: "done".into(),
+                    target: Some("https://example.com/hook".into()),
+                    created_at,
+                    payload: Some(serde_json::json!({ "status": "completed" })),
+                    attempts: 0,
+                    delivered_at: None,
+                })
+                .unwrap();
+        }
+
+        let pending = store
+            .list_undelivered_notification_events(3, now, 10)
+            .unwrap();
+        let ids: Vec<_> = pending.iter().map(|e| e.notification_id.as_str()).collect();
+        assert_eq!(ids, ["old", "new"]);
+        assert_eq!(pending[0].payload.as_ref().unwrap()["status"], "completed");
+
+        store
+            .record_notification_attempt("old", Some(now), 0)
+            .unwrap();
+        store
+            .record_notification_attempt("new", None, now + 60)
+            .unwrap();
+        // Not due until its retry time.
+        assert!(store
+            .list_undelivered_notification_events(3, now, 10)
+            .unwrap()
+            .is_empty());
+        let pending = store
+            .list_undelivered_notification_events(3, now + 60, 10)
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].notification_id, "new");
+        assert_eq!(pending[0].attempts, 1);
+
+        // Delivered events are never touched again; exhausted ones drop out.
+        store.record_notification_attempt("old", None, 0).unwrap();
+        store.record_notification_attempt("new", None, 0).unwrap();
+        store.record_notification_attempt("new", None, 0).unwrap();
+        assert!(store
+            .list_undelivered_notification_events(3, now, 10)
+            .unwrap()
+            .is_empty());
+        let events = store.list_notification_events(10).unwrap();
+        let old = events.iter().find(|e| e.notification_id == "old").unwrap();
+        assert_eq!((old.attempts, old.delivered_at), (1, Some(now)));
+    }
+
     #[test]
     fn raw_event_pruning_keeps_the_latest_runs() {
         let store = make_store();
@@ -1632,7 +3593,15 @@ mod tests {
             .unwrap();
         }
 
+        assert_eq!(
+            store.count_chat_raw_events().unwrap(),
+            RawEventCounts { events: 3, runs: 3 }
+        );
         store.prune_chat_raw_events(2).unwrap();
+        assert_eq!(
+            store.count_chat_raw_events().unwrap(),
+            RawEventCounts { events: 2, runs: 2 }
+        );
 
         let conn = store.conn.lock().unwrap();
         let run_count: i64 = conn
@@ -1657,6 +3626,125 @@ mod tests {
         assert_eq!(event_count, 0);
     }
 
+    #[test]
+    fn search_chats_indexes_snapshots_and_raw_events() {
+        let store = make_store();
+        for (chat_id, thread_id) in [("chat-a", "thread-a"), ("chat-b", "thread-b")] {
+            store
+                .upsert_chat(&ChatRecord {
+                    chat_id: chat_id.into(),
+                    thread_id: thread_id.into(),
+                    created_at: "2025-01-01T00:00:00Z".into(),
+                    status: SessionStatus::Active,
+                    event_pointer: 0,
+                    settings: None,
+                    owner: None,
+                })
+                .unwrap();
+        }
+        let snapshot = |text: &str| {
+            serde_json::json!({
+                "thread": {
+                    "id": "thread-a",
+                    "turns": [{
+                        "id": "t1",
+                        "items": [
+                            {"type": "userMessage", "id": "u1", "content": [{"type": "text", "text": "How do I rotate the tls certificates?"}]},
+                            {"type": "agentMessage", "id": "a1", "text": text},
+                        ],
+                    }],
+                },
+            })
+        };
+        store
+            .upsert_chat_thread_state("thread-a", &snapshot("Run certbot"))
+            .unwrap();
+        store
+            .upsert_chat_thread_state("thread-a", &snapshot("Run certbot renew nightly"))
+            .unwrap();
+        store
+            .insert_chat_raw_event(
+                "run-b",
+                "thread-b",
+                "item/completed",
+                &serde_json::json!({
+                    "threadId": "thread-b",
+                    "turnId": "t1",
+                    "item": {"id": "a1", "type": "agentMessage", "text": "Certificates live in /etc/ssl"},
+                }),
+            )
+            .unwrap();
+
+        let hits = store.search_chats("certbot renew", None, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].chat_id, "chat-a");
+        assert_eq!(hits[0].snippet, "Run [certbot] [renew] nightly");
+
+        // Prefix match on the last term; one hit per chat.
+        let hits = store.search_chats("certif", None, 10).unwrap();
+        let mut chats: Vec<_> = hits.iter().map(|hit| hit.chat_id.as_str()).collect();
+        chats.sort();
+        assert_eq!(chats, ["chat-a", "chat-b"]);
+
+        store.delete_chat("chat-b").unwrap();
+        let hits = store.search_chats("etc ssl", None, 10).unwrap();
+        assert!(hits.is_empty());
+        assert!(store
+            .search_chats("\"unbalanced", None, 10)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn search_chats_indexes_every_turn_and_filters_by_owner() {
+        let store = make_store();
+        for (chat_id, owner) in [("alice-chat", "alice"), ("bob-chat", "bob")] {
+            store
+                .upsert_chat(&ChatRecord {
+                    chat_id: chat_id.into(),
+                    thread_id: format!("{chat_id}-thread"),
+                    created_at: "2025-01-01T00:00:00Z".into(),
+                    status: SessionStatus::Active,
+                    event_pointer: 0,
+                    settings: None,
+                    owner: Some(owner.into()),
+                })
+                .unwrap();
+            // Imported in one write: the first turn is not the latest.
+            let turn = |id: &str, text: &str| {
+                serde_json::json!({
+                    "id": id,
+                    "items": [{"type": "agentMessage", "id": format!("{id}-a"), "text": text}],
+                })
+            };
+            store
+                .upsert_chat_thread_state(
+                    &format!("{chat_id}-thread"),
+                    &serde_json::json!({
+                        "thread": {
+                            "id": format!("{chat_id}-thread"),
+                            "turns": [turn("t1", "zeppelin hangar"), turn("t2", "later reply")],
+                        },
+                    }),
+                )
+                .unwrap();
+        }
+
+        let chats = |owner| {
+            let mut chats: Vec<String> = store
+                .search_chats("zeppelin", owner, 10)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.chat_id)
+                .collect();
+            chats.sort();
+            chats
+        };
+        assert_eq!(chats(Some("alice")), ["alice-chat"]);
+        assert_eq!(chats(Some("bob")), ["bob-chat"]);
+        assert!(chats(None).is_empty());
+    }
+
     #[test]
     fn list_chat_raw_events_returns_thread_events_in_order() {
         let store = make_store();
@@ -1698,6 +3786,79 @@ mod tests {
         assert_eq!(events[1].thread_id, thread_id);
     }
 
+    #[test]
+    fn list_chat_raw_events_since_keys_on_event_pointer() {
+        let store = make_store();
+        let thread_id = "thread-since";
+        store
+            .upsert_chat(&ChatRecord {
+                chat_id: "chat-since".into(),
+                thread_id: thread_id.into(),
+                created_at: "2026-01-01T00:00:00Z".into(),
+                status: SessionStatus::Active,
+                event_pointer: 0,
+                settings: None,
+                owner: None,
+            })
+            .unwrap();
+        assert_eq!(
+            store.oldest_chat_raw_event_pointer(thread_id).unwrap(),
+            None
+        );
+        for (pointer, method) in ["turn/started", "item/started", "item/completed"]
+            .into_iter()
+            .enumerate()
+        {
+            store
+                .update_event_pointer("chat-since", pointer as u64 + 3)
+                .unwrap();
+            store
+                .insert_chat_raw_event(
+                    "run-since",
+                    thread_id,
+                    method,
+                    &serde_json::json!({"threadId": thread_id}),
+                )
+                .unwrap();
+            store
+                .insert_chat_raw_event(
+                    "run-noise",
+                    "thread-noise",
+                    method,
+                    &serde_json::json!({"threadId": "thread-noise"}),
+                )
+                .unwrap();
+        }
+
+        let all = store
+            .list_chat_raw_events_since(thread_id, 0, 0, 10)
+            .unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+        let pointers: Vec<_> = all.iter().map(|e| e.event_pointer).collect();
+        assert_eq!(pointers, vec![Some(3), Some(4), Some(5)]);
+        assert_eq!(
+            store.oldest_chat_raw_event_pointer(thread_id).unwrap(),
+            Some(3)
+        );
+
+        let rest = store
+            .list_chat_raw_events_since(thread_id, 4, 0, 10)
+            .unwrap();
+        let methods: Vec<_> = rest.iter().map(|e| e.method.as_str()).collect();
+        assert_eq!(methods, vec!["item/started", "item/completed"]);
+
+        let page = store
+            .list_chat_raw_events_since(thread_id, 4, all[1].seq, 10)
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].seq, all[2].seq);
+        assert!(store
+            .list_chat_raw_events_since(thread_id, 6, 0, 10)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn cron_upsert_and_get() {
         let store = make_store();
@@ -1713,6 +3874,10 @@ mod tests {
             updated_at: now,
             next_run_at: Some(now + 60),
             last_run_at: None,
+            max_retries: 0,
+            backoff_secs: 0,
+            retry_count: 0,
+            next_retry_at: None,
         };
         store.upsert_cron(&cron).unwrap();
 
@@ -1723,6 +3888,42 @@ mod tests {
         assert_eq!(loaded.next_run_at, Some(now + 60));
     }
 
+    #[test]
+    fn cron_retry_state_survives_upsert() {
+        let store = make_store();
+        let now = now_unix();
+        let mut cron = CronRecord {
+            cron_id: "cron-retry".into(),
+            name: "flaky".into(),
+            schedule: "* * * * * *".into(),
+            command: "false".into(),
+            status: CronStatus::Active,
+            skip_overlap: true,
+            created_at: now,
+            updated_at: now,
+            next_run_at: None,
+            last_run_at: None,
+            max_retries: 3,
+            backoff_secs: 15,
+            retry_count: 0,
+            next_retry_at: None,
+        };
+        store.upsert_cron(&cron).unwrap();
+        store
+            .update_cron_retry("cron-retry", 2, Some(now + 30))
+            .unwrap();
+
+        cron.name = "renamed".into();
+        store.upsert_cron(&cron).unwrap();
+
+        let loaded = store.get_cron("cron-retry").unwrap().unwrap();
+        assert_eq!(loaded.name, "renamed");
+        assert_eq!(loaded.max_retries, 3);
+        assert_eq!(loaded.backoff_secs, 15);
+        assert_eq!(loaded.retry_count, 2);
+        assert_eq!(loaded.next_retry_at, Some(now + 30));
+    }
+
     #[test]
     fn cron_list_and_update_ordered_and_remove() {
         let store = make_store();
@@ -1738,6 +3939,10 @@ mod tests {
             updated_at: now,
             next_run_at: Some(now + 60),
             last_run_at: None,
+            max_retries: 0,
+            backoff_secs: 0,
+            retry_count: 0,
+            next_retry_at: None,
         };
         let b = CronRecord {
             cron_id: "b".into(),
@@ -1750,6 +3955,10 @@ mod tests {
             updated_at: now + 1,
             next_run_at: Some(now + 30),
             last_run_at: None,
+            max_retries: 0,
+            backoff_secs: 0,
+            retry_count: 0,
+            next_retry_at: None,
         };
         store.upsert_cron(&a).unwrap();
         store.upsert_cron(&b).unwrap();
@@ -1836,6 +4045,110 @@ mod tests {
         assert_eq!(runs[0].run_id, "run-5");
     }
 
+    #[test]
+    fn due_crons_are_claimed_by_one_worker_until_the_lease_expires() {
+        let store = make_store();
+        let now = 1_700_000_000;
+        let cron = |cron_id: &str, next_run_at: u64| CronRecord {
+            cron_id: cron_id.into(),
+            name: cron_id.into(),
+            schedule: "* * * * * *".into(),
+            command: "echo hi".into(),
+            status: CronStatus::Active,
+            skip_overlap: true,
+            created_at: now,
+            updated_at: now,
+            next_run_at: Some(next_run_at),
+            last_run_at: None,
+            max_retries: 0,
+            backoff_secs: 0,
+            retry_count: 0,
+            next_retry_at: None,
+        };
+        store.upsert_cron(&cron("cron-due", now)).unwrap();
+        store.upsert_cron(&cron("cron-later", now + 60)).unwrap();
+
+        let claimed = store.claim_due_crons(now, "worker-a", 30).unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].cron_id, "cron-due");
+        assert!(store
+            .claim_due_crons(now, "worker-b", 30)
+            .unwrap()
+            .is_empty());
+        // Re-claiming by the holder is fine.
+        assert_eq!(store.claim_due_crons(now, "worker-a", 30).unwrap().len(), 1);
+
+        assert!(store
+            .renew_cron_lease("cron-due", "worker-a", now + 60)
+            .unwrap());
+        assert!(!store
+            .renew_cron_lease("cron-due", "worker-b", now + 60)
+            .unwrap());
+        assert!(store
+            .claim_due_crons(now + 45, "worker-b", 30)
+            .unwrap()
+            .is_empty());
+
+        store.release_cron_lease("cron-due", "worker-a").unwrap();
+        let claimed = store.claim_due_crons(now + 45, "worker-b", 30).unwrap();
+        assert_eq!(claimed.len(), 1);
+    }
+
+    #[test]
+    fn expired_leases_fail_the_dead_workers_runs() {
+        let store = make_store();
+        let now = 1_700_000_000;
+        store
+            .upsert_cron(&CronRecord {
+                cron_id: "cron-orphan".into(),
+                name: "orphan".into(),
+                schedule: "* * * * * *".into(),
+                command: "sleep 600".into(),
+                status: CronStatus::Active,
+                skip_overlap: true,
+                created_at: now,
+                updated_at: now,
+                next_run_at: Some(now),
+                last_run_at: None,
+                max_retries: 0,
+                backoff_secs: 0,
+                retry_count: 0,
+                next_retry_at: None,
+            })
+            .unwrap();
+        assert_eq!(store.claim_due_crons(now, "worker-a", 30).unwrap().len(), 1);
+        store
+            .upsert_cron_run(&CronRunRecord {
+                run_id: "orphan-run".into(),
+                cron_id: "cron-orphan".into(),
+                scheduled_at: now,
+                started_at: Some(now),
+                finished_at: None,
+                status: CronRunStatus::Running,
+                exit_code: None,
+                output: None,
+                error: None,
+            })
+            .unwrap();
+
+        // worker-a dies; nothing renews its lease.
+        assert!(store
+            .claim_due_crons(now + 10, "worker-b", 30)
+            .unwrap()
+            .is_empty());
+        assert!(store.cron_has_running("cron-orphan").unwrap());
+
+        let claimed = store.claim_due_crons(now + 31, "worker-b", 30).unwrap();
+        assert_eq!(claimed.len(), 1);
+        let run = store.get_cron_run("orphan-run").unwrap().unwrap();
+        assert_eq!(run.status, CronRunStatus::Failed);
+        assert_eq!(run.finished_at, Some(now + 31));
+        assert_eq!(run.error.as_deref(), Some("worker lease expired"));
+        assert!(!store
+            .renew_cron_lease("cron-orphan", "worker-a", now + 90)
+            .unwrap());
+    }
+
     #[test]
     fn cron_has_running_detects_running_runs() {
         let store = make_store();
@@ -1888,6 +4201,10 @@ mod tests {
                 updated_at: now,
                 next_run_at: None,
                 last_run_at: None,
+                max_retries: 0,
+                backoff_secs: 0,
+                retry_count: 0,
+                next_retry_at: None,
             })
             .unwrap();
 
@@ -1926,4 +4243,158 @@ mod tests {
         assert_eq!(runs.len(), 3);
         assert!(!runs.iter().any(|run| run.run_id == "old"));
     }
+
+    fn schema_version_of(path: &Path) -> usize {
+        let conn = Connection::open(path).unwrap();
+        schema_version(&conn).unwrap()
+    }
+
+    #[test]
+    fn migrations_upgrade_unversioned_database() {
+        let path = std::env::temp_dir().join(format!("homie-migrate-{}.db", Uuid::new_v4()));
+        {
+            // Layout from before settings, terminal names and search existed.
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "
+                CREATE TABLE chats (
+                    chat_id       TEXT PRIMARY KEY,
+                    thread_id     TEXT NOT NULL,
+                    created_at    TEXT NOT NULL,
+                    status        TEXT NOT NULL DEFAULT 'active',
+                    event_pointer INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE TABLE terminals (
+                    session_id TEXT PRIMARY KEY,
+                    shell      TEXT NOT NULL,
+                    cols       INTEGER NOT NULL,
+                    rows       INTEGER NOT NULL,
+                    started_at TEXT NOT NULL,
+                    status     TEXT NOT NULL DEFAULT 'active',
+                    exit_code  INTEGER
+                );
+                INSERT INTO chats (chat_id, thread_id, created_at) VALUES ('c-old', 't-old', '1s');
+                ",
+            )
+            .unwrap();
+        }
+
+        let store = SqliteStore::open(&path).unwrap();
+        let chat = store.get_chat("c-old").unwrap().expect("chat kept");
+        assert_eq!(chat.thread_id, "t-old");
+        assert!(chat.settings.is_none());
+        assert!(store.list_terminals().unwrap().is_empty());
+        drop(store);
+        assert_eq!(schema_version_of(&path), MIGRATIONS.len());
+
+        // Reopening is a no-op.
+        SqliteStore::open(&path).unwrap();
+        assert_eq!(schema_version_of(&path), MIGRATIONS.len());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn migrations_reject_newer_schema() {
+        let path = std::env::temp_dir().join(format!("homie-migrate-{}.db", Uuid::new_v4()));
+        drop(SqliteStore::open(&path).unwrap());
+        Connection::open(&path)
+            .unwrap()
+            .execute(
+                "UPDATE schema_version SET version = ?1",
+                params![MIGRATIONS.len() as i64 + 1],
+            )
+            .unwrap();
+
+        let err = SqliteStore::open(&path)
+            .err()
+            .expect("newer schema rejected");
+        assert!(err.contains("newer than this build"), "{err}");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn run_maintenance_prunes_everything_and_reports_counts() {
+        let store = make_store();
+        let now = now_unix();
+        for (job_id, created_at) in [("old", 0), ("older", now - 10), ("newest", now)] {
+            store
+                .upsert_job(&JobRecord {
+                    job_id: job_id.into(),
+                    name: job_id.into(),
+                    status: JobStatus::Succeeded,
+                    created_at,
+                    updated_at: created_at,
+                    spec: serde_json::json!({}),
+                    logs: vec![],
+                })
+                .unwrap();
+        }
+        let policy = RetentionPolicy {
+            job_retention_days: 7,
+            job_max_records: 1,
+            pairing_retention_secs: 86_400,
+            notification_retention_days: 30,
+            chat_raw_event_max_runs: 10,
+            cron_retention_days: 30,
+            cron_max_run_records: 500,
+            terminal_recording_retention_days: 30,
+            terminal_recording_max_records: 100,
+            audit_retention_days: 90,
+            tool_invocation_retention_days: 90,
+        };
+        let audit = |timestamp: u64| AuditEntry {
+            timestamp,
+            identity: None,
+            role: "owner".into(),
+            method: "jobs.cancel".into(),
+            target_id: None,
+            outcome: "ok".into(),
+        };
+        store.insert_audit_entries(&[audit(0), audit(now)]).unwrap();
+        for (invocation_id, created_at) in [("stale", 0), ("fresh", now)] {
+            store
+                .insert_tool_invocation(&ToolInvocationRecord {
+                    invocation_id: invocation_id.into(),
+                    chat_id: "c1".into(),
+                    thread_id: "t1".into(),
+                    turn_id: "turn".into(),
+                    tool_call_id: invocation_id.into(),
+                    tool: "shell".into(),
+                    args: serde_json::json!({}),
+                    args_hash: "h".into(),
+                    duration_ms: 1,
+                    outcome: "ok".into(),
+                    approver: None,
+                    created_at,
+                })
+                .unwrap();
+        }
+        // Indexed, but never saved as a chat.
+        store
+            .upsert_chat_thread_state(
+                "unsaved-thread",
+                &serde_json::json!({"thread": {"turns": [{
+                    "id": "t1",
+                    "items": [{"type": "agentMessage", "id": "a1", "text": "zeppelin"}],
+                }]}}),
+            )
+            .unwrap();
+
+        let report = store.run_maintenance(&policy).unwrap();
+        assert_eq!(report.jobs, 2);
+        assert_eq!(report.audit_log, 1);
+        assert_eq!(report.tool_invocations, 1);
+        assert_eq!(report.search_index, 1);
+        assert_eq!(report.rows_removed(), 5);
+        assert_eq!(store.list_audit_entries(None, None, 10).unwrap().len(), 1);
+        let invocations = store.list_tool_invocations("t1", 10).unwrap();
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].invocation_id, "fresh");
+        let jobs = store.list_jobs().unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].job_id, "newest");
+
+        let again = store.run_maintenance(&policy).unwrap();
+        assert_eq!(again, MaintenanceReport::default());
+    }
 }
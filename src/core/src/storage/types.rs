@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
@@ -41,16 +43,50 @@ pub struct ChatRecord {
     /// Persisted chat settings (model/effort/approval/collaboration).
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub settings: Option<Value>,
+    /// Principal of the connection that created the chat; kept once set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+/// A chat run queued behind its thread's active run that has not started
+/// yet, kept so it survives a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingRunRecord {
+    pub turn_id: String,
+    pub chat_id: String,
+    pub thread_id: String,
+    pub message: String,
+    /// Chat settings when the run was queued; model and credentials are
+    /// resolved from these on restore.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<Value>,
+    pub queued_at: u64,
+}
+
+/// One `Store::search_chats` match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSearchHit {
+    pub chat_id: String,
+    pub thread_id: String,
+    /// Excerpt of the matching message with matches wrapped in `[` `]`.
+    pub snippet: String,
+    /// Relevance; higher is better.
+    pub score: f64,
 }
 
 /// Persisted raw provider event row.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatRawEventRecord {
+    /// Monotonic insertion sequence; replay cursors compare against this.
+    pub seq: u64,
     pub run_id: String,
     pub thread_id: String,
     pub method: String,
     pub params: Value,
     pub created_at: u64,
+    /// Chat event pointer when the event was stored; `None` for rows that
+    /// predate pointer tagging.
+    pub event_pointer: Option<u64>,
 }
 
 /// Persisted terminal session metadata.
@@ -64,6 +100,60 @@ pub struct TerminalRecord {
     pub started_at: String,
     pub status: SessionStatus,
     pub exit_code: Option<u32>,
+    /// Launch parameters of a `terminal.spawn` session.
+    pub spawn: Option<TerminalSpawn>,
+}
+
+/// What a `terminal.spawn` session runs and where.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalSpawn {
+    /// Program followed by its arguments.
+    pub command: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Variables added to the inherited environment. Only their names are
+    /// kept once the session starts; see [`TerminalSpawn::redacted`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl TerminalSpawn {
+    /// A copy with every `env` value blanked, for storing and reporting.
+    pub fn redacted(&self) -> Self {
+        Self {
+            command: self.command.clone(),
+            cwd: self.cwd.clone(),
+            env: self
+                .env
+                .keys()
+                .map(|key| (key.clone(), String::new()))
+                .collect(),
+        }
+    }
+}
+
+/// A finished terminal recording in asciinema v2 (`.cast`) format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalRecordingRecord {
+    pub recording_id: String,
+    pub session_id: Uuid,
+    /// Unix seconds when recording started.
+    pub started_at: u64,
+    pub duration_ms: u64,
+    /// Bytes of event data captured.
+    pub bytes: u64,
+    /// Set when the in-memory limit cut the recording short.
+    pub truncated: bool,
+    pub cast: String,
+    pub created_at: u64,
+}
+
+/// One key in a client's persisted `state.*` scratchpad.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateEntry {
+    pub key: String,
+    pub value: Value,
+    pub updated_at: u64,
 }
 
 /// Status for a scheduled cron job.
@@ -106,6 +196,18 @@ pub struct CronRecord {
     pub next_run_at: Option<u64>,
     /// Last run's scheduled unix epoch.
     pub last_run_at: Option<u64>,
+    /// Retry attempts allowed after a failed run (0 disables retries).
+    #[serde(default)]
+    pub max_retries: u32,
+    /// Base delay before the first retry; doubles per attempt up to a cap.
+    #[serde(default)]
+    pub backoff_secs: u64,
+    /// Retries already attempted since the last successful run.
+    #[serde(default)]
+    pub retry_count: u32,
+    /// Unix epoch of the pending retry attempt, if any.
+    #[serde(default)]
+    pub next_retry_at: Option<u64>,
 }
 
 /// Status for an individual cron run.
@@ -177,6 +279,11 @@ impl JobStatus {
         }
     }
 
+    /// Succeeded, failed and cancelled jobs never change status again.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+
     pub fn from_label(s: &str) -> Self {
         match s {
             "running" => Self::Running,
@@ -241,6 +348,20 @@ pub struct PairingRecord {
     pub approved_by: Option<String>,
 }
 
+/// Pending OAuth device-code login, persisted so a poll can resume after a
+/// restart and stuck flows can be inspected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginSessionRecord {
+    pub provider: String,
+    pub profile: String,
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_url: String,
+    pub interval_secs: u64,
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
 /// Persisted notification subscription.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationSubscription {
@@ -249,9 +370,13 @@ pub struct NotificationSubscription {
     pub kind: Option<String>,
     pub created_at: u64,
     pub updated_at: u64,
+    /// Principal of the connection that registered it. Turn webhooks only
+    /// go to subscriptions with the same owner as the chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
 }
 
-/// Persisted notification event for auditing.
+/// Persisted notification event for auditing and webhook delivery.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationEvent {
     pub notification_id: String,
@@ -259,4 +384,190 @@ pub struct NotificationEvent {
     pub body: String,
     pub target: Option<String>,
     pub created_at: u64,
+    /// JSON body POSTed to webhook targets; `None` sends the event fields.
+    #[serde(default)]
+    pub payload: Option<Value>,
+    /// Delivery attempts made so far.
+    #[serde(default)]
+    pub attempts: u32,
+    /// Set once the event has been delivered; never re-delivered after that.
+    #[serde(default)]
+    pub delivered_at: Option<u64>,
+}
+
+/// Binary artifact a tool produced (screenshot, chart, file), too large to
+/// send inline. Kept until its thread is deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentRecord {
+    pub attachment_id: String,
+    pub thread_id: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+    pub created_at: u64,
+}
+
+/// Tokens one turn consumed, added up from the provider's usage reports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnUsageRecord {
+    pub chat_id: String,
+    pub thread_id: String,
+    pub turn_id: String,
+    /// Principal of the connection that ran the turn.
+    pub identity: Option<String>,
+    /// Chat model when the turn ran, if the chat had one set.
+    pub model: Option<String>,
+    pub input: u64,
+    pub output: u64,
+    pub total: u64,
+    pub created_at: u64,
+}
+
+/// How `Store::usage_summary` buckets usage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UsageGroupBy {
+    /// UTC calendar day, `YYYY-MM-DD`.
+    #[default]
+    Day,
+    Model,
+}
+
+impl UsageGroupBy {
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "day" => Some(Self::Day),
+            "model" => Some(Self::Model),
+            _ => None,
+        }
+    }
+}
+
+/// Which turns `Store::usage_summary` adds up: one chat's when `chat_id`
+/// is set, otherwise every turn attributed to `identity`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageQuery {
+    pub chat_id: Option<String>,
+    pub identity: Option<String>,
+    /// Inclusive lower bound on `created_at` (unix seconds).
+    pub from: u64,
+    /// Inclusive upper bound on `created_at` (unix seconds).
+    pub to: u64,
+    pub group_by: UsageGroupBy,
+}
+
+/// Summed usage of one `usage_summary` bucket.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageBucket {
+    /// Day or model name; turns without a model are `unknown`.
+    pub key: String,
+    pub input: u64,
+    pub output: u64,
+    pub total: u64,
+}
+
+/// An event one node shared with the others through the store.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BusEventRecord {
+    /// Assigned by the store on insert; increases with every event.
+    pub seq: u64,
+    /// Node that raised the event.
+    pub origin: String,
+    pub topic: String,
+    pub params: Option<Value>,
+    pub created_at: u64,
+}
+
+/// Audit record of one tool call made by an agent run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInvocationRecord {
+    pub invocation_id: String,
+    pub chat_id: String,
+    pub thread_id: String,
+    pub turn_id: String,
+    pub tool_call_id: String,
+    pub tool: String,
+    /// Arguments with sensitive values redacted.
+    pub args: Value,
+    /// SHA-256 of the unredacted arguments, for matching identical calls.
+    pub args_hash: String,
+    pub duration_ms: u64,
+    /// `ok`, `error` or `declined`.
+    pub outcome: String,
+    /// Who allowed the call: a client identity, `execpolicy` or `session`.
+    /// `None` when no approval was required.
+    pub approver: Option<String>,
+    pub created_at: u64,
+}
+
+/// One mutating request in the connection audit log (`system.audit`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    /// Who sent the request: a tailnet login, `api-key:<name>`, `local`,
+    /// `lan` or `open`.
+    pub identity: Option<String>,
+    /// Role the request was authorized as (`owner`, `user`, `viewer`).
+    pub role: String,
+    pub method: String,
+    /// Chat, session, job or other id the request acted on, if it named one.
+    pub target_id: Option<String>,
+    /// `ok`, `forbidden` or `error:<code>`.
+    pub outcome: String,
+}
+
+/// Retention limits applied by `Store::run_maintenance`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub job_retention_days: u64,
+    pub job_max_records: usize,
+    pub pairing_retention_secs: u64,
+    pub notification_retention_days: u64,
+    /// Raw provider events are kept for this many most recent runs.
+    pub chat_raw_event_max_runs: usize,
+    pub cron_retention_days: u64,
+    pub cron_max_run_records: usize,
+    pub terminal_recording_retention_days: u64,
+    pub terminal_recording_max_records: usize,
+    pub audit_retention_days: u64,
+    pub tool_invocation_retention_days: u64,
+}
+
+/// Raw provider events currently stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RawEventCounts {
+    pub events: usize,
+    /// Runs (turns) the events belong to; retention counts these.
+    pub runs: usize,
+}
+
+/// Rows removed by one `Store::run_maintenance` pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MaintenanceReport {
+    pub jobs: usize,
+    pub pairings: usize,
+    pub login_sessions: usize,
+    pub notifications: usize,
+    pub chat_raw_events: usize,
+    pub cron_runs: usize,
+    pub terminal_recordings: usize,
+    pub audit_log: usize,
+    pub tool_invocations: usize,
+    /// Indexed messages of threads no chat refers to.
+    pub search_index: usize,
+    /// Whether the database file was compacted.
+    pub vacuumed: bool,
+}
+
+impl MaintenanceReport {
+    pub fn rows_removed(&self) -> usize {
+        self.jobs
+            + self.pairings
+            + self.login_sessions
+            + self.notifications
+            + self.chat_raw_events
+            + self.cron_runs
+            + self.terminal_recordings
+            + self.audit_log
+            + self.tool_invocations
+            + self.search_index
+    }
 }
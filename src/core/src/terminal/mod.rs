@@ -1,7 +1,8 @@
+mod recording;
 mod registry;
 mod runtime;
 mod service;
 
-pub use registry::{SessionInfo, TerminalError, TerminalRegistry};
-pub use runtime::SessionRuntime;
+pub use registry::{SessionInfo, TerminalError, TerminalListEntry, TerminalRegistry};
+pub use runtime::{OutputPacing, SessionRuntime};
 pub use service::TerminalService;
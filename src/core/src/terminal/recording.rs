@@ -0,0 +1,215 @@
+//! asciinema v2 recordings of terminal output.
+//!
+//! A recording keeps output chunks and resizes with their offset from the
+//! start, and renders them as a `.cast` file: a JSON header line followed by
+//! one `[time, code, data]` event per line.
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde_json::json;
+
+/// Slot the PTY reader tees output into while a recording is active.
+pub(crate) type RecorderSlot = Arc<Mutex<Option<Recording>>>;
+
+pub(crate) struct Recording {
+    started: Instant,
+    /// Unix seconds at start, written to the header.
+    timestamp: u64,
+    width: u16,
+    height: u16,
+    events: Vec<CastEvent>,
+    /// Trailing bytes of an incomplete UTF-8 sequence, held until the next
+    /// chunk completes it.
+    pending: Vec<u8>,
+    bytes: usize,
+    max_bytes: usize,
+    truncated: bool,
+}
+
+struct CastEvent {
+    time: f64,
+    code: &'static str,
+    data: String,
+}
+
+impl Recording {
+    /// Start recording a `cols`x`rows` terminal, keeping at most `max_bytes`
+    /// of event data in memory.
+    pub(crate) fn new(cols: u16, rows: u16, max_bytes: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            timestamp: unix_now(),
+            width: cols,
+            height: rows,
+            events: Vec::new(),
+            pending: Vec::new(),
+            bytes: 0,
+            max_bytes,
+            truncated: false,
+        }
+    }
+
+    /// Append a chunk of PTY output as an `"o"` event.
+    pub(crate) fn push_output(&mut self, chunk: &[u8]) {
+        if self.truncated || chunk.is_empty() {
+            return;
+        }
+        self.pending.extend_from_slice(chunk);
+        let data = take_utf8(&mut self.pending);
+        if !data.is_empty() {
+            self.push_event("o", data);
+        }
+    }
+
+    /// Record a terminal resize as an `"r"` event (`"COLSxROWS"`).
+    pub(crate) fn push_resize(&mut self, cols: u16, rows: u16) {
+        if self.truncated {
+            return;
+        }
+        self.push_event("r", format!("{cols}x{rows}"));
+    }
+
+    /// Once the buffer is full the recording stops growing and is marked
+    /// truncated, so the cast stays a faithful prefix of the session.
+    fn push_event(&mut self, code: &'static str, data: String) {
+        if self.bytes + data.len() > self.max_bytes {
+            self.truncated = true;
+            self.pending.clear();
+            return;
+        }
+        self.bytes += data.len();
+        self.events.push(CastEvent {
+            time: self.started.elapsed().as_secs_f64(),
+            code,
+            data,
+        });
+    }
+
+    pub(crate) fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub(crate) fn duration_secs(&self) -> f64 {
+        self.events.last().map(|e| e.time).unwrap_or(0.0)
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub(crate) fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Render the asciinema v2 `.cast` text.
+    pub(crate) fn to_cast(&self) -> String {
+        let header = json!({
+            "version": 2,
+            "width": self.width,
+            "height": self.height,
+            "timestamp": self.timestamp,
+            "env": { "TERM": "xterm-256color" },
+        });
+        let mut out = header.to_string();
+        out.push('\n');
+        for event in &self.events {
+            let time = (event.time * 1_000_000.0).round() / 1_000_000.0;
+            out.push_str(&json!([time, event.code, event.data]).to_string());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Decode the longest valid UTF-8 prefix of `buf`, leaving an incomplete
+/// trailing sequence in place. Invalid bytes become U+FFFD.
+fn take_utf8(buf: &mut Vec<u8>) -> String {
+    let mut out = String::new();
+    let mut rest: &[u8] = buf;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(text) => {
+                out.push_str(text);
+                rest = &[];
+                break;
+            }
+            Err(err) => {
+                let (valid, after) = rest.split_at(err.valid_up_to());
+                out.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                match err.error_len() {
+                    Some(len) => {
+                        out.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[len..];
+                    }
+                    None => {
+                        rest = after;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    let keep = rest.len();
+    buf.drain(..buf.len() - keep);
+    out
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(cast: &str) -> Vec<serde_json::Value> {
+        cast.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn renders_header_output_and_resize_events() {
+        let mut rec = Recording::new(80, 24, 1024);
+        rec.push_output(b"hello\r\n");
+        rec.push_resize(120, 40);
+        let cast = lines(&rec.to_cast());
+        assert_eq!(cast.len(), 3);
+        assert_eq!(cast[0]["version"], 2);
+        assert_eq!(cast[0]["width"], 80);
+        assert_eq!(cast[0]["height"], 24);
+        assert_eq!(cast[1][1], "o");
+        assert_eq!(cast[1][2], "hello\r\n");
+        assert_eq!(cast[2][1], "r");
+        assert_eq!(cast[2][2], "120x40");
+        assert!(cast[2][0].as_f64().unwrap() >= cast[1][0].as_f64().unwrap());
+    }
+
+    #[test]
+    fn joins_utf8_split_across_chunks() {
+        let mut rec = Recording::new(80, 24, 1024);
+        let bytes = "é!".as_bytes();
+        rec.push_output(&bytes[..1]);
+        rec.push_output(&bytes[1..]);
+        rec.push_output(&[0xff]);
+        let cast = lines(&rec.to_cast());
+        assert_eq!(cast.len(), 3);
+        assert_eq!(cast[1][2], "é!");
+        assert_eq!(cast[2][2], "\u{fffd}");
+    }
+
+    #[test]
+    fn stops_growing_at_the_byte_limit() {
+        let mut rec = Recording::new(80, 24, 8);
+        rec.push_output(b"12345");
+        rec.push_output(b"6789");
+        rec.push_output(b"0");
+        assert!(rec.truncated());
+        assert_eq!(rec.bytes(), 5);
+        assert_eq!(lines(&rec.to_cast()).len(), 2);
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use axum::extract::ws::Message as WsMessage;
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
@@ -10,15 +12,20 @@ use tokio::sync::mpsc;
 use uuid::Uuid;
 
 use crate::debug_bytes::{contains_subseq, fmt_bytes, terminal_debug_enabled_for};
+use crate::homie_config::{ChildEnvConfig, TerminalConfig};
 use homie_protocol::{BinaryFrame, StreamType};
 
-use super::runtime::SessionRuntime;
+use super::recording::{RecorderSlot, Recording};
+use super::runtime::{OutputPacing, SessionRuntime};
 use crate::outbound::OutboundMessage;
 use crate::router::ReapEvent;
-use crate::storage::{SessionStatus, Store, TerminalRecord};
+use crate::storage::{
+    SessionStatus, Store, TerminalRecord, TerminalRecordingRecord, TerminalSpawn,
+};
 
 const HISTORY_CHUNK_BYTES: usize = 16 * 1024;
 const DEFAULT_HISTORY_BYTES: usize = 2 * 1024 * 1024;
+const DEFAULT_RECORDING_BYTES: usize = 8 * 1024 * 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionInfo {
@@ -28,6 +35,26 @@ pub struct SessionInfo {
     pub cols: u16,
     pub rows: u16,
     pub started_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn: Option<TerminalSpawn>,
+}
+
+/// A `terminal.list` entry: a stored session merged with its state in
+/// this server.
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalListEntry {
+    pub session_id: Uuid,
+    pub name: Option<String>,
+    pub shell: String,
+    pub status: SessionStatus,
+    pub started_at: String,
+    pub cols: u16,
+    pub rows: u16,
+    /// Connections currently attached to the session's output.
+    pub attached_clients: usize,
+    /// Whether the session's process is running in this server. Stored
+    /// sessions left over from an earlier run are not.
+    pub alive: bool,
 }
 
 #[derive(Debug)]
@@ -50,6 +77,18 @@ struct ActiveSession {
     output_task: tokio::task::JoinHandle<()>,
     subscribers: Arc<Mutex<HashMap<Uuid, mpsc::Sender<OutboundMessage>>>>,
     history: Arc<Mutex<HistoryBuffer>>,
+    /// Last input, output, attach or resize.
+    last_activity: Arc<Mutex<Instant>>,
+}
+
+impl ActiveSession {
+    fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
+    }
 }
 
 struct HistoryBuffer {
@@ -94,6 +133,9 @@ impl HistoryBuffer {
 pub struct TerminalRegistry {
     sessions: HashMap<Uuid, ActiveSession>,
     store: Arc<dyn Store>,
+    child_env: ChildEnvConfig,
+    /// `terminal.command_wrapper`, or why it cannot be used.
+    command_wrapper: Result<Vec<String>, String>,
 }
 
 impl TerminalRegistry {
@@ -101,13 +143,62 @@ impl TerminalRegistry {
         Self {
             sessions: HashMap::new(),
             store,
+            child_env: ChildEnvConfig::default(),
+            command_wrapper: Ok(Vec::new()),
         }
     }
 
+    /// Keep the server variables `child_env` denies out of sessions.
+    pub fn with_child_env(mut self, child_env: ChildEnvConfig) -> Self {
+        self.child_env = child_env;
+        self
+    }
+
+    /// Launch every session through `terminal.command_wrapper`. A wrapper
+    /// whose program is missing is reported here, and sessions then fail to
+    /// start instead of running unwrapped.
+    pub fn with_command_wrapper(mut self, config: &TerminalConfig) -> Self {
+        let wrapper = config.checked_command_wrapper();
+        if let Err(e) = &wrapper {
+            tracing::error!("terminal sessions cannot start: {e}");
+        }
+        self.command_wrapper = wrapper;
+        self
+    }
+
     pub fn list_sessions(&self) -> Result<Vec<TerminalRecord>, String> {
         self.store.list_terminals().map_err(|e| e.to_string())
     }
 
+    /// Stored sessions with their live state: running sessions report
+    /// their current size and attached clients.
+    pub fn list_live_sessions(&self) -> Result<Vec<TerminalListEntry>, String> {
+        let records = self.store.list_terminals()?;
+        let mut entries: Vec<TerminalListEntry> = records
+            .into_iter()
+            .map(|record| match self.sessions.get(&record.session_id) {
+                Some(active) => live_entry(active, record.status),
+                None => TerminalListEntry {
+                    session_id: record.session_id,
+                    name: record.name,
+                    shell: record.shell,
+                    status: record.status,
+                    started_at: record.started_at,
+                    cols: record.cols,
+                    rows: record.rows,
+                    attached_clients: 0,
+                    alive: false,
+                },
+            })
+            .collect();
+        for (session_id, active) in &self.sessions {
+            if !entries.iter().any(|entry| entry.session_id == *session_id) {
+                entries.push(live_entry(active, SessionStatus::Active));
+            }
+        }
+        Ok(entries)
+    }
+
     pub fn start_session(
         &mut self,
         shell: String,
@@ -115,7 +206,35 @@ impl TerminalRegistry {
         rows: u16,
     ) -> Result<SessionInfo, TerminalError> {
         let (display_shell, cmd) = build_shell_command(&shell);
-        self.start_session_with_command(display_shell, cmd, cols, rows, None)
+        self.start_session_with_command(display_shell, cmd, cols, rows, None, None)
+    }
+
+    /// Start a session running `spawn.command` in `spawn.cwd`, with
+    /// `spawn.env` added to the environment. A lone program goes through
+    /// the same shell handling as [`Self::start_session`]. The session
+    /// records the spawn with its env values redacted.
+    pub fn spawn_session(
+        &mut self,
+        spawn: TerminalSpawn,
+        cols: u16,
+        rows: u16,
+    ) -> Result<SessionInfo, TerminalError> {
+        let (display, mut cmd) = match spawn.command.as_slice() {
+            [] => return Err(TerminalError::Internal("empty command".into())),
+            [program] => build_shell_command(program),
+            [program, args @ ..] => {
+                let mut cmd = CommandBuilder::new(program);
+                cmd.args(args);
+                (shell_words::join(&spawn.command), cmd)
+            }
+        };
+        if let Some(cwd) = &spawn.cwd {
+            cmd.cwd(cwd);
+        }
+        for (key, value) in &spawn.env {
+            cmd.env(key, value);
+        }
+        self.start_session_with_command(display, cmd, cols, rows, None, Some(spawn.redacted()))
     }
 
     pub fn list_tmux_sessions(&self) -> Result<(bool, Vec<TmuxSessionInfo>), TerminalError> {
@@ -183,7 +302,7 @@ impl TerminalRegistry {
         cmd.arg("-t");
         cmd.arg(&session_name);
         let display = format!("tmux:{session_name}");
-        self.start_session_with_command(display, cmd, cols, rows, Some(session_name))
+        self.start_session_with_command(display, cmd, cols, rows, Some(session_name), None)
     }
 
     pub fn kill_tmux_session(&self, session_name: String) -> Result<(), TerminalError> {
@@ -216,7 +335,18 @@ impl TerminalRegistry {
         cols: u16,
         rows: u16,
         name: Option<String>,
+        spawn: Option<TerminalSpawn>,
     ) -> Result<SessionInfo, TerminalError> {
+        let wrapper = self
+            .command_wrapper
+            .as_ref()
+            .map_err(|e| TerminalError::Internal(e.clone()))?;
+        if !wrapper.is_empty() {
+            let argv = cmd.get_argv_mut();
+            let inner = std::mem::take(argv);
+            argv.extend(wrapper.iter().map(OsString::from));
+            argv.extend(inner);
+        }
         let pty_system = native_pty_system();
         let size = PtySize {
             rows,
@@ -229,6 +359,15 @@ impl TerminalRegistry {
             .openpty(size)
             .map_err(|e| TerminalError::Internal(format!("failed to open pty: {e}")))?;
 
+        // Variables the spawn request sets itself are kept.
+        for name in self.child_env.scrubbed_vars() {
+            if !spawn
+                .as_ref()
+                .is_some_and(|spawn| spawn.env.contains_key(&name))
+            {
+                cmd.env_remove(name);
+            }
+        }
         if cfg!(not(target_os = "windows")) {
             cmd.env("TERM", "xterm-256color");
             cmd.env("COLORTERM", "truecolor");
@@ -249,9 +388,17 @@ impl TerminalRegistry {
         let (output_tx, output_rx) = mpsc::channel::<Vec<u8>>(256);
         let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
 
-        let reader_handle =
-            SessionRuntime::spawn_reader(&*pair.master, session_id, output_tx, shutdown_rx)
-                .map_err(|e| TerminalError::Internal(format!("failed to spawn reader: {e}")))?;
+        let recorder: RecorderSlot = Arc::new(Mutex::new(None));
+        let pacing = OutputPacing::from_env();
+        let reader_handle = SessionRuntime::spawn_reader(
+            &*pair.master,
+            session_id,
+            output_tx,
+            shutdown_rx,
+            recorder.clone(),
+            pacing.rate_limit,
+        )
+        .map_err(|e| TerminalError::Internal(format!("failed to spawn reader: {e}")))?;
 
         let runtime = SessionRuntime::new(
             session_id,
@@ -260,6 +407,7 @@ impl TerminalRegistry {
             child,
             reader_handle,
             shutdown_tx,
+            recorder,
         );
 
         let info = SessionInfo {
@@ -269,6 +417,7 @@ impl TerminalRegistry {
             cols,
             rows,
             started_at: chrono_now(),
+            spawn,
         };
 
         let rec = TerminalRecord {
@@ -280,6 +429,7 @@ impl TerminalRegistry {
             started_at: info.started_at.clone(),
             status: SessionStatus::Active,
             exit_code: None,
+            spawn: info.spawn.clone(),
         };
         if let Err(e) = self.store.upsert_terminal(&rec) {
             tracing::warn!(%session_id, "failed to persist terminal start: {e}");
@@ -287,11 +437,14 @@ impl TerminalRegistry {
 
         let subscribers = Arc::new(Mutex::new(HashMap::new()));
         let history = Arc::new(Mutex::new(HistoryBuffer::new(history_limit_bytes())));
+        let last_activity = Arc::new(Mutex::new(Instant::now()));
         let output_task = tokio::spawn(forward_pty_output(
             session_id,
             output_rx,
             subscribers.clone(),
             history.clone(),
+            last_activity.clone(),
+            pacing,
         ));
 
         self.sessions.insert(
@@ -302,6 +455,7 @@ impl TerminalRegistry {
                 output_task,
                 subscribers,
                 history,
+                last_activity,
             },
         );
 
@@ -322,6 +476,7 @@ impl TerminalRegistry {
                 .sessions
                 .get_mut(&session_id)
                 .ok_or(TerminalError::NotFound(session_id))?;
+            active.touch();
             let mut subs = active.subscribers.lock().unwrap();
             let already_attached = subs.contains_key(&subscriber_id);
             subs.insert(subscriber_id, outbound_tx.clone());
@@ -394,6 +549,7 @@ impl TerminalRegistry {
             .runtime
             .resize(rows, cols)
             .map_err(|e| TerminalError::Internal(format!("resize failed: {e}")))?;
+        active.touch();
         active.info.cols = cols;
         active.info.rows = rows;
         Ok(())
@@ -408,6 +564,7 @@ impl TerminalRegistry {
             .runtime
             .write_input(data.as_bytes())
             .map_err(|e| TerminalError::Internal(format!("write_input failed: {e}")))?;
+        active.touch();
         Ok(())
     }
 
@@ -423,6 +580,7 @@ impl TerminalRegistry {
             .runtime
             .write_input(&frame.payload)
             .map_err(|e| TerminalError::Internal(format!("write_input failed: {e}")))?;
+        active.touch();
         Ok(())
     }
 
@@ -440,6 +598,7 @@ impl TerminalRegistry {
             started_at: active.info.started_at.clone(),
             status: SessionStatus::Exited,
             exit_code: None,
+            spawn: active.info.spawn.clone(),
         };
         if let Err(e) = self.store.upsert_terminal(&rec) {
             tracing::warn!(%session_id, "failed to persist terminal kill: {e}");
@@ -467,6 +626,7 @@ impl TerminalRegistry {
                     started_at: active.info.started_at.clone(),
                     status: SessionStatus::Exited,
                     exit_code: Some(*code),
+                    spawn: active.info.spawn.clone(),
                 };
                 if let Err(e) = self.store.upsert_terminal(&rec) {
                     tracing::warn!(%id, "failed to persist terminal exit: {e}");
@@ -489,6 +649,36 @@ impl TerminalRegistry {
             .collect()
     }
 
+    /// Close sessions with no attached client and no activity for `timeout`:
+    /// the PTY is killed and the record marked inactive. A zero timeout
+    /// disables reaping.
+    pub fn reap_idle(&mut self, timeout: Duration) -> Vec<ReapEvent> {
+        if timeout.is_zero() {
+            return Vec::new();
+        }
+        let idle: Vec<SessionInfo> = self
+            .sessions
+            .values()
+            .filter(|active| active.subscribers.lock().unwrap().is_empty())
+            .filter(|active| active.idle_for() >= timeout)
+            .map(|active| active.info.clone())
+            .collect();
+        let mut events = Vec::with_capacity(idle.len());
+        for info in idle {
+            self.persist_status(&info, SessionStatus::Inactive, None);
+            self.remove_session(info.session_id);
+            tracing::info!(session_id = %info.session_id, "idle session closed");
+            events.push(ReapEvent::new(
+                "terminal.session.closed",
+                Some(json!({
+                    "session_id": info.session_id,
+                    "reason": "idle",
+                })),
+            ));
+        }
+        events
+    }
+
     pub fn remove_record(&mut self, session_id: Uuid) -> Result<(), TerminalError> {
         if self.sessions.contains_key(&session_id) {
             return Err(TerminalError::Internal(
@@ -572,10 +762,90 @@ impl TerminalRegistry {
         Ok(String::from_utf8_lossy(slice).to_string())
     }
 
+    /// Start recording a session's output in asciinema v2 format.
+    pub fn start_recording(&mut self, session_id: Uuid) -> Result<(), TerminalError> {
+        let active = self
+            .sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        active
+            .runtime
+            .start_recording(active.info.cols, active.info.rows, recording_limit_bytes())
+            .map_err(TerminalError::Missing)
+    }
+
+    /// Stop a session's recording and store it.
+    pub fn stop_recording(
+        &mut self,
+        session_id: Uuid,
+    ) -> Result<TerminalRecordingRecord, TerminalError> {
+        let active = self
+            .sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        let recording = active
+            .runtime
+            .stop_recording()
+            .ok_or_else(|| TerminalError::Missing("session is not recording".into()))?;
+        self.save_recording(session_id, recording)
+            .map_err(TerminalError::Internal)
+    }
+
+    /// The cast of a session's in-progress recording.
+    pub fn recording_cast(&self, session_id: Uuid) -> Result<String, TerminalError> {
+        let active = self
+            .sessions
+            .get(&session_id)
+            .ok_or(TerminalError::NotFound(session_id))?;
+        active
+            .runtime
+            .recording_cast()
+            .ok_or_else(|| TerminalError::Missing("session is not recording".into()))
+    }
+
+    /// A stored recording by ID.
+    pub fn stored_recording(
+        &self,
+        recording_id: &str,
+    ) -> Result<TerminalRecordingRecord, TerminalError> {
+        self.store
+            .get_terminal_recording(recording_id)
+            .map_err(TerminalError::Internal)?
+            .ok_or_else(|| TerminalError::Missing(format!("recording not found: {recording_id}")))
+    }
+
+    fn save_recording(
+        &self,
+        session_id: Uuid,
+        recording: Recording,
+    ) -> Result<TerminalRecordingRecord, String> {
+        let rec = TerminalRecordingRecord {
+            recording_id: Uuid::new_v4().to_string(),
+            session_id,
+            started_at: recording.timestamp(),
+            duration_ms: (recording.duration_secs() * 1000.0) as u64,
+            bytes: recording.bytes() as u64,
+            truncated: recording.truncated(),
+            cast: recording.to_cast(),
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        self.store.insert_terminal_recording(&rec)?;
+        Ok(rec)
+    }
+
     fn remove_session(&mut self, id: Uuid) {
         if let Some(mut active) = self.sessions.remove(&id) {
             active.output_task.abort();
             active.runtime.shutdown();
+            // A session that ends mid-recording keeps what was captured.
+            if let Some(recording) = active.runtime.stop_recording() {
+                if let Err(e) = self.save_recording(id, recording) {
+                    tracing::warn!(%id, "failed to persist terminal recording: {e}");
+                }
+            }
         }
     }
 
@@ -589,6 +859,7 @@ impl TerminalRegistry {
             started_at: info.started_at.clone(),
             status,
             exit_code,
+            spawn: info.spawn.clone(),
         };
         if let Err(e) = self.store.upsert_terminal(&rec) {
             tracing::warn!(%info.session_id, "failed to persist terminal status: {e}");
@@ -596,13 +867,41 @@ impl TerminalRegistry {
     }
 }
 
+fn live_entry(active: &ActiveSession, status: SessionStatus) -> TerminalListEntry {
+    let info = &active.info;
+    TerminalListEntry {
+        session_id: info.session_id,
+        name: info.name.clone(),
+        shell: info.shell.clone(),
+        status,
+        started_at: info.started_at.clone(),
+        cols: info.cols,
+        rows: info.rows,
+        attached_clients: active.subscribers.lock().map_or(0, |subs| subs.len()),
+        alive: true,
+    }
+}
+
 async fn forward_pty_output(
     session_id: Uuid,
     mut output_rx: mpsc::Receiver<Vec<u8>>,
     subscribers: Arc<Mutex<HashMap<Uuid, mpsc::Sender<OutboundMessage>>>>,
     history: Arc<Mutex<HistoryBuffer>>,
+    last_activity: Arc<Mutex<Instant>>,
+    pacing: OutputPacing,
 ) {
-    while let Some(data) = output_rx.recv().await {
+    while let Some(mut data) = output_rx.recv().await {
+        // Batch whatever follows within the flush window into one frame.
+        let deadline = tokio::time::Instant::now() + pacing.flush_interval;
+        while data.len() < pacing.flush_bytes {
+            match tokio::time::timeout_at(deadline, output_rx.recv()).await {
+                Ok(Some(more)) => data.extend_from_slice(&more),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        if let Ok(mut last) = last_activity.lock() {
+            *last = Instant::now();
+        }
         if terminal_debug_enabled_for(session_id) {
             let has_dsr = contains_subseq(&data, b"\x1b[6n") || contains_subseq(&data, b"[6n");
             tracing::info!(
@@ -660,6 +959,13 @@ fn history_limit_bytes() -> usize {
         .unwrap_or(DEFAULT_HISTORY_BYTES)
 }
 
+fn recording_limit_bytes() -> usize {
+    std::env::var("HOMIE_RECORDING_BYTES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_RECORDING_BYTES)
+}
+
 fn tmux_supported() -> bool {
     if cfg!(target_os = "windows") {
         return false;
@@ -719,3 +1025,88 @@ fn build_shell_command(shell: &str) -> (String, CommandBuilder) {
         (shell.to_string(), CommandBuilder::new(shell))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStore;
+    use std::collections::BTreeMap;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sessions_launch_through_the_command_wrapper() {
+        let marker = std::env::temp_dir().join(format!("homie-wrapper-{}", Uuid::new_v4()));
+        let config = TerminalConfig {
+            command_wrapper: vec![
+                "sh".into(),
+                "-c".into(),
+                "touch \"$0\" && exec \"$@\"".into(),
+                marker.to_string_lossy().into_owned(),
+            ],
+            ..TerminalConfig::default()
+        };
+        let store: Arc<dyn Store> = Arc::new(SqliteStore::open_memory().unwrap());
+        let mut registry = TerminalRegistry::new(store.clone()).with_command_wrapper(&config);
+        let spawn = TerminalSpawn {
+            command: vec!["sleep".into(), "5".into()],
+            cwd: None,
+            env: BTreeMap::new(),
+        };
+        let info = registry.spawn_session(spawn, 80, 24).expect("spawn");
+        assert_eq!(info.shell, "sleep 5");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !marker.exists() {
+            assert!(Instant::now() < deadline, "wrapper did not run");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        registry.kill_session(info.session_id).unwrap();
+        let _ = std::fs::remove_file(&marker);
+
+        let missing = TerminalConfig {
+            command_wrapper: vec!["homie-no-such-wrapper".into()],
+            ..TerminalConfig::default()
+        };
+        let mut registry = TerminalRegistry::new(store).with_command_wrapper(&missing);
+        assert!(matches!(
+            registry.start_session("sh".into(), 80, 24),
+            Err(TerminalError::Internal(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn bursts_of_output_are_coalesced_into_one_frame() {
+        let session_id = Uuid::new_v4();
+        let (output_tx, output_rx) = mpsc::channel(16);
+        let (sub_tx, mut sub_rx) = mpsc::channel(16);
+        let subscribers = Arc::new(Mutex::new(HashMap::from([(Uuid::new_v4(), sub_tx)])));
+        let history = Arc::new(Mutex::new(HistoryBuffer::new(1024)));
+        let last_activity = Arc::new(Mutex::new(Instant::now()));
+
+        for chunk in [&b"one "[..], b"two ", b"three"] {
+            output_tx.send(chunk.to_vec()).await.unwrap();
+        }
+        drop(output_tx);
+        forward_pty_output(
+            session_id,
+            output_rx,
+            subscribers,
+            history,
+            last_activity,
+            OutputPacing::default(),
+        )
+        .await;
+
+        let mut frames = Vec::new();
+        while let Ok(msg) = sub_rx.try_recv() {
+            frames.push(msg);
+        }
+        assert_eq!(frames.len(), 1);
+        let OutboundMessage::Raw(WsMessage::Binary(bytes)) = &frames[0] else {
+            panic!("expected a binary frame");
+        };
+        let frame = BinaryFrame::decode(bytes).unwrap();
+        assert_eq!(frame.session_id, session_id);
+        assert_eq!(frame.payload, b"one two three");
+    }
+}
@@ -1,10 +1,57 @@
 use std::io::{Read, Write};
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use portable_pty::{Child, MasterPty, PtySize};
 use tokio::sync::oneshot;
 use uuid::Uuid;
 
+use super::recording::{RecorderSlot, Recording};
+
+const DEFAULT_FLUSH_MS: u64 = 16;
+const DEFAULT_FLUSH_BYTES: usize = 32 * 1024;
+
+/// How PTY output is paced on its way to clients.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputPacing {
+    /// Longest output waits to be batched with what follows into one frame.
+    pub flush_interval: Duration,
+    /// Batched bytes that send a frame before the interval is up.
+    pub flush_bytes: usize,
+    /// Bytes per second read from the PTY; `0` reads as fast as the
+    /// program writes. Past the cap the reader pauses, so the PTY fills
+    /// and the program blocks on its own output.
+    pub rate_limit: u64,
+}
+
+impl Default for OutputPacing {
+    fn default() -> Self {
+        Self {
+            flush_interval: Duration::from_millis(DEFAULT_FLUSH_MS),
+            flush_bytes: DEFAULT_FLUSH_BYTES,
+            rate_limit: 0,
+        }
+    }
+}
+
+impl OutputPacing {
+    /// Defaults overridden by `HOMIE_TERMINAL_FLUSH_MS`,
+    /// `HOMIE_TERMINAL_FLUSH_BYTES` and `HOMIE_TERMINAL_RATE_LIMIT`.
+    pub fn from_env() -> Self {
+        let env = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let defaults = Self::default();
+        Self {
+            flush_interval: env("HOMIE_TERMINAL_FLUSH_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.flush_interval),
+            flush_bytes: env("HOMIE_TERMINAL_FLUSH_BYTES")
+                .map(|bytes| bytes.max(1) as usize)
+                .unwrap_or(defaults.flush_bytes),
+            rate_limit: env("HOMIE_TERMINAL_RATE_LIMIT").unwrap_or(defaults.rate_limit),
+        }
+    }
+}
+
 /// Holds the PTY master, writer, child process, and reader thread for one
 /// terminal session. Dropping the runtime triggers graceful shutdown.
 pub struct SessionRuntime {
@@ -14,16 +61,18 @@ pub struct SessionRuntime {
     child: Box<dyn Child + Send + Sync>,
     reader_handle: Option<JoinHandle<()>>,
     shutdown_tx: Option<oneshot::Sender<()>>,
+    recorder: RecorderSlot,
 }
 
 impl SessionRuntime {
-    pub fn new(
+    pub(crate) fn new(
         id: Uuid,
         master: Box<dyn MasterPty + Send>,
         writer: Box<dyn Write + Send>,
         child: Box<dyn Child + Send + Sync>,
         reader_handle: JoinHandle<()>,
         shutdown_tx: oneshot::Sender<()>,
+        recorder: RecorderSlot,
     ) -> Self {
         Self {
             id,
@@ -32,6 +81,7 @@ impl SessionRuntime {
             child,
             reader_handle: Some(reader_handle),
             shutdown_tx: Some(shutdown_tx),
+            recorder,
         }
     }
 
@@ -58,7 +108,35 @@ impl SessionRuntime {
                 pixel_width: 0,
                 pixel_height: 0,
             })
-            .map_err(|e| e.to_string())
+            .map_err(|e| e.to_string())?;
+        if let Some(recording) = self.recorder.lock().unwrap().as_mut() {
+            recording.push_resize(cols, rows);
+        }
+        Ok(())
+    }
+
+    /// Start teeing output into a new recording of at most `max_bytes`.
+    pub fn start_recording(&self, cols: u16, rows: u16, max_bytes: usize) -> Result<(), String> {
+        let mut slot = self.recorder.lock().unwrap();
+        if slot.is_some() {
+            return Err("session is already recording".into());
+        }
+        *slot = Some(Recording::new(cols, rows, max_bytes));
+        Ok(())
+    }
+
+    /// Stop the active recording and hand it back.
+    pub(crate) fn stop_recording(&self) -> Option<Recording> {
+        self.recorder.lock().unwrap().take()
+    }
+
+    /// The active recording rendered as `.cast` text so far.
+    pub fn recording_cast(&self) -> Option<String> {
+        self.recorder
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(Recording::to_cast)
     }
 
     /// Graceful shutdown: signal reader, kill child, join reader thread.
@@ -99,12 +177,16 @@ impl SessionRuntime {
     }
 
     /// Spawn the reader thread that reads PTY output and sends it via an mpsc
-    /// channel. The thread exits on EOF, read error, or shutdown signal.
-    pub fn spawn_reader(
+    /// channel, also teeing it into `recorder` while a recording is active.
+    /// Reading pauses once `rate_limit` bytes were read within a second.
+    /// The thread exits on EOF, read error, or shutdown signal.
+    pub(crate) fn spawn_reader(
         master: &dyn MasterPty,
         session_id: Uuid,
         output_tx: tokio::sync::mpsc::Sender<Vec<u8>>,
         shutdown_rx: oneshot::Receiver<()>,
+        recorder: RecorderSlot,
+        rate_limit: u64,
     ) -> Result<JoinHandle<()>, String> {
         let mut reader = master
             .try_clone_reader()
@@ -122,13 +204,33 @@ impl SessionRuntime {
                 });
 
                 let mut buf = [0u8; 32768];
+                let mut window_start = Instant::now();
+                let mut window_bytes = 0u64;
                 loop {
                     if shutdown.load(std::sync::atomic::Ordering::Relaxed) {
                         break;
                     }
+                    if rate_limit > 0 && window_bytes >= rate_limit {
+                        // Stop reading until the window ends; the PTY
+                        // buffer fills and throttles the program.
+                        let resume = window_start + Duration::from_secs(1);
+                        while Instant::now() < resume
+                            && !shutdown.load(std::sync::atomic::Ordering::Relaxed)
+                        {
+                            std::thread::sleep(Duration::from_millis(10));
+                        }
+                    }
+                    if window_start.elapsed() >= Duration::from_secs(1) {
+                        window_start = Instant::now();
+                        window_bytes = 0;
+                    }
                     match reader.read(&mut buf) {
                         Ok(0) => break, // EOF
                         Ok(n) => {
+                            window_bytes += n as u64;
+                            if let Some(recording) = recorder.lock().unwrap().as_mut() {
+                                recording.push_output(&buf[..n]);
+                            }
                             if output_tx.blocking_send(buf[..n].to_vec()).is_err() {
                                 break; // receiver dropped
                             }
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::sync::{Arc, Mutex, OnceLock};
 
 use serde_json::{json, Value};
@@ -7,10 +7,14 @@ use uuid::Uuid;
 
 use homie_protocol::{error_codes, BinaryFrame, Response, StreamType};
 
+use crate::authz::Scope;
 use crate::debug_bytes::{contains_subseq, fmt_bytes, terminal_debug_enabled_for};
+use crate::execpolicy::wildcard_match;
+use crate::homie_config::TerminalConfig;
 use crate::outbound::OutboundMessage;
 use crate::router::{ReapEvent, ServiceHandler};
-use crate::terminal::{TerminalError, TerminalRegistry};
+use crate::storage::TerminalSpawn;
+use crate::terminal::{SessionInfo, TerminalError, TerminalRegistry};
 
 /// Terminal service: manages session RPCs for a single connection.
 pub struct TerminalService {
@@ -19,6 +23,7 @@ pub struct TerminalService {
     subscriber_id: Uuid,
     event_tx: tokio::sync::broadcast::Sender<ReapEvent>,
     attached: HashSet<Uuid>,
+    config: TerminalConfig,
 }
 
 impl TerminalService {
@@ -34,15 +39,53 @@ impl TerminalService {
             subscriber_id,
             event_tx,
             attached: HashSet::new(),
+            config: TerminalConfig::default(),
         }
     }
 
+    /// Apply `[terminal]` settings such as the command allowlist.
+    pub fn with_config(mut self, config: TerminalConfig) -> Self {
+        self.config = config;
+        self
+    }
+
     fn session_start(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
         let (shell, cols, rows) = parse_start_params(&params);
+        if !self.config.allows(&shell) {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("command not allowed: {shell}"),
+            );
+        }
         let info = {
             let mut registry = self.registry.lock().unwrap();
             registry.start_session(shell, cols, rows)
         };
+        self.session_started(req_id, info)
+    }
+
+    fn session_spawn(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let (spawn, cols, rows) = match parse_spawn_params(&params) {
+            Ok(v) => v,
+            Err(msg) => return Response::error(req_id, error_codes::INVALID_PARAMS, msg),
+        };
+        if !self.config.allows(&spawn.command[0]) {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                format!("command not allowed: {}", spawn.command[0]),
+            );
+        }
+        let info = {
+            let mut registry = self.registry.lock().unwrap();
+            registry.spawn_session(spawn, cols, rows)
+        };
+        self.session_started(req_id, info)
+    }
+
+    /// Announce a newly started session, or map its start error.
+    fn session_started(&self, req_id: Uuid, info: Result<SessionInfo, TerminalError>) -> Response {
         match info {
             Ok(info) => {
                 let _ = self.event_tx.send(ReapEvent::new(
@@ -54,6 +97,7 @@ impl TerminalService {
                         "cols": info.cols,
                         "rows": info.rows,
                         "started_at": info.started_at,
+                        "spawn": info.spawn,
                     })),
                 ));
                 Response::success(req_id, json!({ "session_id": info.session_id }))
@@ -373,6 +417,13 @@ impl TerminalService {
                 return Response::error(req_id, error_codes::INVALID_PARAMS, "missing session_name")
             }
         };
+        if !self.config.allows("tmux") {
+            return Response::error(
+                req_id,
+                error_codes::INVALID_PARAMS,
+                "command not allowed: tmux",
+            );
+        }
         let cols = p.get("cols").and_then(|v| v.as_u64()).unwrap_or(80) as u16;
         let rows = p.get("rows").and_then(|v| v.as_u64()).unwrap_or(24) as u16;
 
@@ -439,6 +490,21 @@ impl TerminalService {
         }
     }
 
+    fn terminal_list(&self, req_id: Uuid) -> Response {
+        let entries = {
+            let registry = self.registry.lock().unwrap();
+            registry.list_live_sessions()
+        };
+        match entries {
+            Ok(sessions) => Response::success(req_id, json!({ "sessions": sessions })),
+            Err(e) => Response::error(
+                req_id,
+                error_codes::INTERNAL_ERROR,
+                format!("list failed: {e}"),
+            ),
+        }
+    }
+
     fn session_list(&self, req_id: Uuid) -> Response {
         let records = {
             let registry = self.registry.lock().unwrap();
@@ -459,6 +525,7 @@ impl TerminalService {
                             "started_at": r.started_at,
                             "status": r.status,
                             "exit_code": r.exit_code,
+                            "spawn": r.spawn,
                         })
                     })
                     .collect();
@@ -514,6 +581,117 @@ impl TerminalService {
         }
     }
 
+    fn record_start(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let session_id = match parse_session_id(&params) {
+            Some(id) => id,
+            None => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    "missing or invalid session_id",
+                )
+            }
+        };
+
+        let result = {
+            let mut registry = self.registry.lock().unwrap();
+            registry.start_recording(session_id)
+        };
+
+        match result {
+            Ok(()) => Response::success(req_id, json!({ "ok": true })),
+            Err(err) => recording_error(req_id, session_id, err),
+        }
+    }
+
+    fn record_stop(&mut self, req_id: Uuid, params: Option<Value>) -> Response {
+        let session_id = match parse_session_id(&params) {
+            Some(id) => id,
+            None => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    "missing or invalid session_id",
+                )
+            }
+        };
+
+        let result = {
+            let mut registry = self.registry.lock().unwrap();
+            registry.stop_recording(session_id)
+        };
+
+        match result {
+            Ok(rec) => Response::success(
+                req_id,
+                json!({
+                    "recording_id": rec.recording_id,
+                    "session_id": rec.session_id,
+                    "duration_ms": rec.duration_ms,
+                    "bytes": rec.bytes,
+                    "truncated": rec.truncated,
+                }),
+            ),
+            Err(err) => recording_error(req_id, session_id, err),
+        }
+    }
+
+    fn record_export(&self, req_id: Uuid, params: Option<Value>) -> Response {
+        let recording_id = params
+            .as_ref()
+            .and_then(|v| v.get("recording_id"))
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty());
+        if let Some(recording_id) = recording_id {
+            let result = {
+                let registry = self.registry.lock().unwrap();
+                registry.stored_recording(recording_id)
+            };
+            return match result {
+                Ok(rec) => Response::success(
+                    req_id,
+                    json!({
+                        "recording_id": rec.recording_id,
+                        "session_id": rec.session_id,
+                        "cast": rec.cast,
+                    }),
+                ),
+                Err(TerminalError::Internal(msg)) => {
+                    Response::error(req_id, error_codes::INTERNAL_ERROR, msg)
+                }
+                Err(TerminalError::Missing(msg)) => {
+                    Response::error(req_id, error_codes::INVALID_PARAMS, msg)
+                }
+                Err(TerminalError::NotFound(_)) => Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    format!("recording not found: {recording_id}"),
+                ),
+            };
+        }
+
+        let session_id = match parse_session_id(&params) {
+            Some(id) => id,
+            None => {
+                return Response::error(
+                    req_id,
+                    error_codes::INVALID_PARAMS,
+                    "missing recording_id or session_id",
+                )
+            }
+        };
+        let result = {
+            let registry = self.registry.lock().unwrap();
+            registry.recording_cast(session_id)
+        };
+        match result {
+            Ok(cast) => {
+                Response::success(req_id, json!({ "session_id": session_id, "cast": cast }))
+            }
+            Err(err) => recording_error(req_id, session_id, err),
+        }
+    }
+
     fn detach_all(&mut self) {
         let session_ids: Vec<Uuid> = self.attached.iter().copied().collect();
         for session_id in session_ids {
@@ -525,11 +703,38 @@ impl TerminalService {
     }
 }
 
+impl TerminalService {
+    pub(crate) const METHOD_SCOPES: &'static [(&'static str, Scope)] = &[
+        ("terminal.list", Scope::TerminalRead),
+        ("terminal.session.list", Scope::TerminalRead),
+        ("terminal.session.attach", Scope::TerminalRead),
+        ("terminal.session.detach", Scope::TerminalRead),
+        ("terminal.session.preview", Scope::TerminalRead),
+        ("terminal.record.export", Scope::TerminalRead),
+        ("terminal.tmux.list", Scope::TerminalRead),
+        ("terminal.session.start", Scope::TerminalWrite),
+        ("terminal.spawn", Scope::TerminalWrite),
+        ("terminal.session.resize", Scope::TerminalWrite),
+        ("terminal.session.input", Scope::TerminalWrite),
+        ("terminal.session.kill", Scope::TerminalWrite),
+        ("terminal.session.remove", Scope::TerminalWrite),
+        ("terminal.session.rename", Scope::TerminalWrite),
+        ("terminal.record.start", Scope::TerminalWrite),
+        ("terminal.record.stop", Scope::TerminalWrite),
+        ("terminal.tmux.attach", Scope::TerminalWrite),
+        ("terminal.tmux.kill", Scope::TerminalWrite),
+    ];
+}
+
 impl ServiceHandler for TerminalService {
     fn namespace(&self) -> &str {
         "terminal"
     }
 
+    fn method_scopes(&self) -> &'static [(&'static str, Scope)] {
+        Self::METHOD_SCOPES
+    }
+
     fn handle_request(
         &mut self,
         id: Uuid,
@@ -538,6 +743,7 @@ impl ServiceHandler for TerminalService {
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Response> + Send + '_>> {
         let resp = match method {
             "terminal.session.start" => self.session_start(id, params),
+            "terminal.spawn" => self.session_spawn(id, params),
             "terminal.session.attach" => self.session_attach(id, params),
             "terminal.session.detach" => self.session_detach(id, params),
             "terminal.session.resize" => self.session_resize(id, params),
@@ -545,8 +751,12 @@ impl ServiceHandler for TerminalService {
             "terminal.session.kill" => self.session_kill(id, params),
             "terminal.session.remove" => self.session_remove(id, params),
             "terminal.session.rename" => self.session_rename(id, params),
+            "terminal.list" => self.terminal_list(id),
             "terminal.session.list" => self.session_list(id),
             "terminal.session.preview" => self.session_preview(id, params),
+            "terminal.record.start" => self.record_start(id, params),
+            "terminal.record.stop" => self.record_stop(id, params),
+            "terminal.record.export" => self.record_export(id, params),
             "terminal.tmux.list" => self.tmux_list(id),
             "terminal.tmux.attach" => self.tmux_attach(id, params),
             "terminal.tmux.kill" => self.tmux_kill(id, params),
@@ -602,6 +812,18 @@ impl Drop for TerminalService {
     }
 }
 
+fn recording_error(req_id: Uuid, session_id: Uuid, err: TerminalError) -> Response {
+    match err {
+        TerminalError::NotFound(_) => Response::error(
+            req_id,
+            error_codes::SESSION_NOT_FOUND,
+            format!("session not found: {session_id}"),
+        ),
+        TerminalError::Missing(msg) => Response::error(req_id, error_codes::INVALID_PARAMS, msg),
+        TerminalError::Internal(msg) => Response::error(req_id, error_codes::INTERNAL_ERROR, msg),
+    }
+}
+
 fn parse_start_params(params: &Option<Value>) -> (String, u16, u16) {
     let default_shell = detect_default_shell();
     let p = params.as_ref();
@@ -621,6 +843,73 @@ fn parse_start_params(params: &Option<Value>) -> (String, u16, u16) {
     (shell, cols, rows)
 }
 
+/// Variables `terminal.spawn` may not set: they pick which binaries run or
+/// load code into them, getting around `allow_commands`.
+const SPAWN_ENV_DENY: &[&str] = &[
+    "PATH",
+    "LD_*",
+    "DYLD_*",
+    "BASH_ENV",
+    "ENV",
+    "SHELLOPTS",
+    "BASHOPTS",
+    "PROMPT_COMMAND",
+    "IFS",
+];
+
+/// `terminal.spawn` params: `command` (default: the login shell), `cwd`
+/// (an existing directory), `env` (string values, none in
+/// [`SPAWN_ENV_DENY`]) and the PTY size.
+fn parse_spawn_params(params: &Option<Value>) -> Result<(TerminalSpawn, u16, u16), String> {
+    let (default_shell, cols, rows) = parse_start_params(params);
+    let p = params.as_ref();
+    let command = match p.and_then(|v| v.get("command")) {
+        None | Some(Value::Null) => vec![default_shell],
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("command must be an array of strings")?,
+        Some(_) => return Err("command must be an array of strings".into()),
+    };
+    if command
+        .first()
+        .is_none_or(|program| program.trim().is_empty())
+    {
+        return Err("command must name a program".into());
+    }
+    let cwd = match p.and_then(|v| v.get("cwd")) {
+        None | Some(Value::Null) => None,
+        Some(Value::String(cwd)) if std::path::Path::new(cwd).is_dir() => Some(cwd.clone()),
+        Some(Value::String(cwd)) => return Err(format!("cwd is not a directory: {cwd}")),
+        Some(_) => return Err("cwd must be a string".into()),
+    };
+    let mut env = BTreeMap::new();
+    match p.and_then(|v| v.get("env")) {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(vars)) => {
+            for (key, value) in vars {
+                if key.is_empty() || key.contains(['=', '\0']) {
+                    return Err(format!("invalid env name: {key:?}"));
+                }
+                let upper = key.to_ascii_uppercase();
+                if SPAWN_ENV_DENY
+                    .iter()
+                    .any(|pattern| wildcard_match(pattern, &upper))
+                {
+                    return Err(format!("env {key} may not be set"));
+                }
+                let Some(value) = value.as_str() else {
+                    return Err(format!("env {key} must be a string"));
+                };
+                env.insert(key.clone(), value.to_string());
+            }
+        }
+        Some(_) => return Err("env must be an object".into()),
+    }
+    Ok((TerminalSpawn { command, cwd, env }, cols, rows))
+}
+
 fn parse_session_id(params: &Option<Value>) -> Option<Uuid> {
     params
         .as_ref()?
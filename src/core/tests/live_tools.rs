@@ -148,6 +148,8 @@ async fn connect_and_handshake(addr: SocketAddr) -> WsStream {
         client_id: "live-tools-test/0.1.0".into(),
         auth_token: None,
         capabilities: vec![],
+        compression: vec![],
+        device_id: None,
     })
     .unwrap();
 
@@ -403,10 +405,10 @@ async fn live_parallel_tool_calls_ls_and_find_single_turn() {
                     _ => {}
                 }
             }
-            "chat.turn.completed" => {
-                if params.get("status").and_then(Value::as_str) == Some("completed") {
-                    break;
-                }
+            "chat.turn.completed"
+                if params.get("status").and_then(Value::as_str) == Some("completed") =>
+            {
+                break;
             }
             _ => {}
         }
@@ -550,10 +552,10 @@ async fn live_parallel_tool_calls_web_search_and_web_fetch_single_turn() {
                     _ => {}
                 }
             }
-            "chat.turn.completed" => {
-                if params.get("status").and_then(Value::as_str) == Some("completed") {
-                    break;
-                }
+            "chat.turn.completed"
+                if params.get("status").and_then(Value::as_str) == Some("completed") =>
+            {
+                break;
             }
             _ => {}
         }
@@ -51,6 +51,8 @@ async fn connect_and_handshake(addr: SocketAddr) -> WsStream {
         client_id: "test-client/0.1.0".into(),
         auth_token: None,
         capabilities: vec![],
+        compression: vec![],
+        device_id: None,
     })
     .unwrap();
 
@@ -296,6 +298,8 @@ async fn handshake_includes_cron_service() {
         client_id: "test-client/0.1.0".into(),
         auth_token: None,
         capabilities: vec![],
+        compression: vec![],
+        device_id: None,
     })
     .unwrap();
     ws.send(text_msg(hello)).await.unwrap();
@@ -329,6 +333,33 @@ async fn pairing_request_and_approve_updates_status() {
     assert_eq!(approved["pairing"]["status"], "approved");
 }
 
+#[tokio::test]
+async fn pairing_status_and_refresh_return_record() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_and_handshake(addr).await;
+
+    let result = rpc(&mut ws, "pairing.request", Some(json!({ "ttl_secs": 5 }))).await;
+    let pairing_id = result["pairing"]["pairing_id"].clone();
+    let expires_at = result["pairing"]["expires_at"].as_u64().unwrap();
+
+    let status = rpc(
+        &mut ws,
+        "pairing.status",
+        Some(json!({ "pairing_id": pairing_id })),
+    )
+    .await;
+    assert_eq!(status["pairing"]["status"], "pending");
+
+    let refreshed = rpc(
+        &mut ws,
+        "pairing.refresh",
+        Some(json!({ "pairing_id": pairing_id })),
+    )
+    .await;
+    assert_eq!(refreshed["pairing"]["status"], "pending");
+    assert!(refreshed["pairing"]["expires_at"].as_u64().unwrap() > expires_at);
+}
+
 #[tokio::test]
 async fn notifications_register_and_send_emits_event() {
     let addr = start_server(ServerConfig::default()).await;
@@ -635,6 +666,8 @@ async fn handshake_advertises_registered_services() {
         client_id: "test-client/0.1.0".into(),
         auth_token: None,
         capabilities: vec![],
+        compression: vec![],
+        device_id: None,
     })
     .unwrap();
 
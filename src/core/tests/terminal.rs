@@ -53,6 +53,8 @@ async fn connect_and_handshake(addr: SocketAddr) -> WsStream {
         client_id: "test-client/0.1.0".into(),
         auth_token: None,
         capabilities: vec![],
+        compression: vec![],
+        device_id: None,
     })
     .unwrap();
 
@@ -309,6 +311,69 @@ async fn session_start_produces_pty_output() {
     assert!(!frame.payload.is_empty(), "expected non-empty PTY output");
 }
 
+#[tokio::test]
+async fn spawn_runs_the_command_in_cwd_with_env() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_and_handshake(addr).await;
+
+    let cwd = std::env::temp_dir().to_string_lossy().to_string();
+    let result = rpc(
+        &mut ws,
+        "terminal.spawn",
+        Some(json!({
+            "command": ["/bin/sh", "-c", "echo \"$GREETING from $(pwd)\"; sleep 5"],
+            "cwd": cwd,
+            "env": { "GREETING": "hello-spawn" },
+            "cols": 80,
+            "rows": 24,
+        })),
+    )
+    .await;
+    let sid = extract_session_id(&result);
+    rpc(
+        &mut ws,
+        "terminal.session.attach",
+        Some(json!({ "session_id": sid, "replay": true })),
+    )
+    .await;
+
+    let mut output = String::new();
+    while !output.contains("hello-spawn from") {
+        let frame = BinaryFrame::decode(&next_binary(&mut ws).await).unwrap();
+        output.push_str(&String::from_utf8_lossy(&frame.payload));
+    }
+
+    let list = rpc(&mut ws, "terminal.session.list", None).await;
+    let session = list["sessions"]
+        .as_array()
+        .expect("sessions array")
+        .iter()
+        .find(|s| s["session_id"].as_str() == Some(&sid))
+        .expect("session listed")
+        .clone();
+    assert_eq!(session["spawn"]["command"][0], "/bin/sh");
+    assert_eq!(session["spawn"]["cwd"].as_str(), Some(cwd.as_str()));
+    assert_eq!(session["spawn"]["env"]["GREETING"], "");
+
+    let err = rpc_err(
+        &mut ws,
+        "terminal.spawn",
+        Some(json!({ "command": ["/bin/sh"], "env": { "LD_PRELOAD": "/tmp/x.so" } })),
+    )
+    .await;
+    assert_eq!(err.code, homie_protocol::error_codes::INVALID_PARAMS);
+
+    let err = rpc_err(
+        &mut ws,
+        "terminal.spawn",
+        Some(json!({ "command": ["/bin/sh"], "cwd": "/definitely/not/here" })),
+    )
+    .await;
+    assert_eq!(err.code, homie_protocol::error_codes::INVALID_PARAMS);
+    let err = rpc_err(&mut ws, "terminal.spawn", Some(json!({ "command": [] }))).await;
+    assert_eq!(err.code, homie_protocol::error_codes::INVALID_PARAMS);
+}
+
 #[tokio::test]
 async fn session_attach_returns_info() {
     let addr = start_server(ServerConfig::default()).await;
@@ -609,6 +674,55 @@ async fn session_input_binary_frame() {
     assert!(found, "expected BIN_TEST in PTY output, got: {accumulated}");
 }
 
+#[tokio::test]
+async fn terminal_list_reports_attached_clients_and_liveness() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_and_handshake(addr).await;
+    let mut other = connect_and_handshake(addr).await;
+
+    let result = rpc(
+        &mut ws,
+        "terminal.session.start",
+        Some(json!({ "cols": 80, "rows": 24 })),
+    )
+    .await;
+    let sid = extract_session_id(&result);
+    rpc(
+        &mut ws,
+        "terminal.session.attach",
+        Some(json!({ "session_id": sid })),
+    )
+    .await;
+
+    let find = |list: &serde_json::Value| {
+        list["sessions"]
+            .as_array()
+            .expect("sessions array")
+            .iter()
+            .find(|s| s["session_id"].as_str() == Some(&sid))
+            .cloned()
+            .expect("session missing")
+    };
+    let listed = find(&rpc(&mut other, "terminal.list", None).await);
+    assert_eq!(listed["alive"], true);
+    assert_eq!(listed["attached_clients"], 1);
+    assert_eq!(listed["status"], "active");
+    assert_eq!(listed["cols"], 80);
+    assert_eq!(listed["rows"], 24);
+    assert!(listed["started_at"].is_string());
+
+    rpc(
+        &mut ws,
+        "terminal.session.kill",
+        Some(json!({ "session_id": sid })),
+    )
+    .await;
+    let listed = find(&rpc(&mut other, "terminal.list", None).await);
+    assert_eq!(listed["alive"], false);
+    assert_eq!(listed["attached_clients"], 0);
+    assert_eq!(listed["status"], "exited");
+}
+
 #[tokio::test]
 async fn session_kill() {
     let addr = start_server(ServerConfig::default()).await;
@@ -641,6 +755,102 @@ async fn session_kill() {
     assert_eq!(err.code, homie_protocol::error_codes::SESSION_NOT_FOUND);
 }
 
+#[tokio::test]
+async fn session_recording_exports_asciinema_cast() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_and_handshake(addr).await;
+
+    let result = rpc(
+        &mut ws,
+        "terminal.session.start",
+        Some(json!({ "shell": "/bin/sh", "cols": 80, "rows": 24 })),
+    )
+    .await;
+    let sid = extract_session_id(&result);
+
+    rpc(
+        &mut ws,
+        "terminal.record.start",
+        Some(json!({ "session_id": sid })),
+    )
+    .await;
+    let err = rpc_err(
+        &mut ws,
+        "terminal.record.start",
+        Some(json!({ "session_id": sid })),
+    )
+    .await;
+    assert_eq!(err.code, homie_protocol::error_codes::INVALID_PARAMS);
+
+    rpc(
+        &mut ws,
+        "terminal.session.input",
+        Some(json!({ "session_id": sid, "data": "echo REC_TEST\n" })),
+    )
+    .await;
+    rpc(
+        &mut ws,
+        "terminal.session.resize",
+        Some(json!({ "session_id": sid, "cols": 100, "rows": 30 })),
+    )
+    .await;
+
+    // Wait for the echoed output to reach the recording.
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    loop {
+        let live = rpc(
+            &mut ws,
+            "terminal.record.export",
+            Some(json!({ "session_id": sid })),
+        )
+        .await;
+        if live["cast"].as_str().unwrap().contains("REC_TEST") {
+            break;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "recording never saw output"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let stopped = rpc(
+        &mut ws,
+        "terminal.record.stop",
+        Some(json!({ "session_id": sid })),
+    )
+    .await;
+    let recording_id = stopped["recording_id"].as_str().unwrap().to_string();
+    assert_eq!(stopped["truncated"].as_bool(), Some(false));
+
+    let exported = rpc(
+        &mut ws,
+        "terminal.record.export",
+        Some(json!({ "recording_id": recording_id })),
+    )
+    .await;
+    let cast = exported["cast"].as_str().unwrap();
+    let mut lines = cast.lines();
+    let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert_eq!(header["version"], 2);
+    assert_eq!(header["width"], 80);
+    assert_eq!(header["height"], 24);
+    let events: Vec<serde_json::Value> = lines.map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert!(events.iter().all(|e| e[0].is_f64() || e[0].is_u64()));
+    assert!(events.iter().any(|e| e[1] == "r" && e[2] == "100x30"));
+    assert!(events
+        .iter()
+        .any(|e| e[1] == "o" && e[2].as_str().unwrap().contains("REC_TEST")));
+
+    let err = rpc_err(
+        &mut ws,
+        "terminal.record.stop",
+        Some(json!({ "session_id": sid })),
+    )
+    .await;
+    assert_eq!(err.code, homie_protocol::error_codes::INVALID_PARAMS);
+}
+
 #[tokio::test]
 async fn session_exit_event_on_process_exit() {
     let addr = start_server(ServerConfig::default()).await;
@@ -703,6 +913,80 @@ async fn session_exit_event_on_process_exit() {
     assert!(got_exit, "expected terminal.session.exit event");
 }
 
+#[tokio::test]
+async fn idle_detached_session_is_closed() {
+    let addr = start_server(ServerConfig {
+        terminal_idle_timeout: Duration::from_millis(300),
+        ..ServerConfig::default()
+    })
+    .await;
+    let mut ws = connect_and_handshake(addr).await;
+
+    rpc(
+        &mut ws,
+        "events.subscribe",
+        Some(json!({ "topic": "terminal.*" })),
+    )
+    .await;
+
+    let attached = extract_session_id(
+        &rpc(
+            &mut ws,
+            "terminal.session.start",
+            Some(json!({ "shell": "/bin/sh", "cols": 80, "rows": 24 })),
+        )
+        .await,
+    );
+    rpc(
+        &mut ws,
+        "terminal.session.attach",
+        Some(json!({ "session_id": attached })),
+    )
+    .await;
+    let detached = extract_session_id(
+        &rpc(
+            &mut ws,
+            "terminal.session.start",
+            Some(json!({ "shell": "/bin/sh", "cols": 80, "rows": 24 })),
+        )
+        .await,
+    );
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    let mut closed = None;
+    while closed.is_none() {
+        tokio::select! {
+            msg = next_msg(&mut ws) => {
+                if let WsMsg::Text(t) = msg {
+                    if let Ok(homie_protocol::Message::Event(evt)) =
+                        serde_json::from_str::<homie_protocol::Message>(&t)
+                    {
+                        if evt.topic == "terminal.session.closed" {
+                            closed = evt.params;
+                        }
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(deadline) => break,
+        }
+    }
+    let closed = closed.expect("expected terminal.session.closed event");
+    assert_eq!(closed["session_id"].as_str(), Some(detached.as_str()));
+    assert_eq!(closed["reason"].as_str(), Some("idle"));
+
+    let list = rpc(&mut ws, "terminal.session.list", None).await;
+    let status = |sid: &str| {
+        list["sessions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|s| s["session_id"].as_str() == Some(sid))
+            .map(|s| s["status"].as_str().unwrap().to_string())
+    };
+    assert_eq!(status(&detached).as_deref(), Some("inactive"));
+    assert_eq!(status(&attached).as_deref(), Some("active"));
+}
+
 #[tokio::test]
 async fn session_cleanup_on_disconnect() {
     let addr = start_server(ServerConfig::default()).await;
@@ -5,13 +5,18 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use futures::{SinkExt, StreamExt};
-use homie_core::{Role, ServerConfig, SqliteStore, TailscaleIdentity, TailscaleWhois};
+use homie_core::{
+    hash_api_key, ApiKey, AuthMode, Role, ServerConfig, ShutdownSignal, SqliteStore,
+    TailscaleIdentity, TailscaleWhois,
+};
 use homie_protocol::{
-    ClientHello, HandshakeResponse, HelloRejectCode, Request, VersionRange, PROTOCOL_VERSION,
+    decode_envelope_frame, encode_envelope_frame, ClientHello, Compression, HandshakeResponse,
+    HelloRejectCode, Request, VersionRange, PROTOCOL_VERSION,
 };
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 
 type WsStream =
     tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
@@ -27,8 +32,12 @@ impl TailscaleWhois for NoopWhois {
 }
 
 async fn start_server(config: ServerConfig) -> SocketAddr {
+    start_server_with_shutdown(config, ShutdownSignal::new()).await
+}
+
+async fn start_server_with_shutdown(config: ServerConfig, shutdown: ShutdownSignal) -> SocketAddr {
     let store = Arc::new(SqliteStore::open_memory().unwrap());
-    let app = homie_core::build_router(config, NoopWhois, store);
+    let app = homie_core::build_router_with_shutdown(config, NoopWhois, store, shutdown);
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
 
@@ -56,6 +65,8 @@ fn client_hello(min: u16, max: u16) -> String {
         client_id: "test-client/0.1.0".into(),
         auth_token: None,
         capabilities: vec![],
+        compression: vec![],
+        device_id: None,
     })
     .unwrap()
 }
@@ -120,6 +131,26 @@ async fn rpc_err(
     }
 }
 
+async fn rpc_ok(
+    ws: &mut WsStream,
+    method: &str,
+    params: Option<serde_json::Value>,
+) -> serde_json::Value {
+    let req = homie_protocol::Message::Request(Request::new(method, params));
+    let json = homie_protocol::encode_message(&req).unwrap();
+    ws.send(text_msg(json)).await.unwrap();
+
+    let t = next_text(ws).await;
+    let msg: homie_protocol::Message = serde_json::from_str(&t).unwrap();
+    match msg {
+        homie_protocol::Message::Response(r) => {
+            assert!(r.error.is_none(), "unexpected error: {:?}", r.error);
+            r.result.expect("expected result")
+        }
+        other => panic!("expected response, got {other:?}"),
+    }
+}
+
 // ── Tests ────────────────────────────────────────────────────────────
 
 #[tokio::test]
@@ -137,6 +168,31 @@ async fn health_endpoint() {
     assert!(response.contains("ok"));
 }
 
+#[tokio::test]
+async fn healthz_and_readyz_report_store_and_version() {
+    let addr = start_server(ServerConfig::default()).await;
+
+    for (path, status) in [
+        ("/healthz", "\"status\":\"ok\""),
+        ("/readyz", "\"status\":\"ready\""),
+    ] {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let req = format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+        stream.write_all(req.as_bytes()).await.unwrap();
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        let response = String::from_utf8_lossy(&buf);
+        assert!(response.starts_with("HTTP/1.1 200"), "{path}: {response}");
+        assert!(response.contains(status), "{path}: {response}");
+        assert!(response.contains("\"db\":\"ok\""), "{path}: {response}");
+        assert!(
+            response.contains(env!("CARGO_PKG_VERSION")),
+            "{path}: {response}"
+        );
+    }
+}
+
 #[tokio::test]
 async fn successful_handshake() {
     let addr = start_server(ServerConfig::default()).await;
@@ -152,11 +208,39 @@ async fn successful_handshake() {
             assert_eq!(hello.protocol_version, PROTOCOL_VERSION);
             assert_eq!(hello.identity.as_deref(), Some("local"));
             assert!(!hello.services.is_empty());
+            let reconnect = hello.reconnect.expect("reconnect hint");
+            assert_eq!(reconnect.min_backoff_ms, 500);
+            assert_eq!(reconnect.max_backoff_ms, 30_000);
+            assert!(reconnect.jitter);
         }
         HandshakeResponse::Reject(r) => panic!("unexpected reject: {r:?}"),
     }
 }
 
+#[tokio::test]
+async fn graceful_shutdown_sends_retry_after() {
+    let config = ServerConfig {
+        shutdown_retry_after: Duration::from_millis(2_500),
+        ..Default::default()
+    };
+    let shutdown = ShutdownSignal::new();
+    let addr = start_server_with_shutdown(config, shutdown.clone()).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await;
+
+    shutdown.trigger();
+    let close: serde_json::Value = serde_json::from_str(&next_text(&mut ws).await).unwrap();
+    assert_eq!(close["type"], "close");
+    assert_eq!(close["reason"], "server_shutdown");
+    assert_eq!(close["retry_after_ms"], 2_500);
+    assert!(
+        expect_close(&mut ws, Duration::from_secs(5)).await,
+        "expected connection to close after shutdown"
+    );
+}
+
 #[tokio::test]
 async fn version_mismatch_rejected() {
     let addr = start_server(ServerConfig::default()).await;
@@ -170,11 +254,32 @@ async fn version_mismatch_rejected() {
     match resp {
         HandshakeResponse::Reject(r) => {
             assert_eq!(r.code, HelloRejectCode::VersionMismatch);
+            assert_eq!(r.supported_versions, Some(VersionRange::supported()));
+            assert!(r.reason.contains("server supports [1]"), "{}", r.reason);
         }
         HandshakeResponse::Hello(_) => panic!("expected reject"),
     }
 }
 
+#[tokio::test]
+async fn newer_client_negotiates_down_to_server_max() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 5))).await.unwrap();
+
+    let t = next_text(&mut ws).await;
+    let resp: HandshakeResponse = serde_json::from_str(&t).unwrap();
+
+    match resp {
+        HandshakeResponse::Hello(h) => {
+            assert_eq!(h.protocol_version, PROTOCOL_VERSION);
+            assert_eq!(h.supported_versions, Some(VersionRange::supported()));
+        }
+        HandshakeResponse::Reject(r) => panic!("unexpected reject: {}", r.reason),
+    }
+}
+
 #[tokio::test]
 async fn method_not_found_after_handshake() {
     let addr = start_server(ServerConfig::default()).await;
@@ -202,6 +307,155 @@ async fn method_not_found_after_handshake() {
     }
 }
 
+#[tokio::test]
+async fn oversized_frames_are_rejected_without_closing() {
+    let config = ServerConfig {
+        max_message_bytes: 4096,
+        max_binary_bytes: 8192,
+        ..ServerConfig::default()
+    };
+    let addr = start_server(config).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await; // consume ServerHello
+
+    let req = Request::new(
+        "chat.message.send",
+        Some(serde_json::json!({ "chat_id": "c1", "message": "x".repeat(10_000) })),
+    );
+    let req_id = req.id;
+    let json = homie_protocol::encode_message(&homie_protocol::Message::Request(req)).unwrap();
+    ws.send(text_msg(json)).await.unwrap();
+    let resp: homie_protocol::Response = serde_json::from_str(&next_text(&mut ws).await).unwrap();
+    assert_eq!(resp.id, req_id);
+    assert_eq!(
+        resp.error.unwrap().code,
+        homie_protocol::error_codes::PAYLOAD_TOO_LARGE
+    );
+
+    let frame = homie_protocol::BinaryFrame {
+        session_id: uuid::Uuid::new_v4(),
+        stream: homie_protocol::StreamType::Upload,
+        payload: vec![0; 9000],
+    };
+    ws.send(tungstenite::Message::Binary(frame.encode().into()))
+        .await
+        .unwrap();
+    let resp: homie_protocol::Response = serde_json::from_str(&next_text(&mut ws).await).unwrap();
+    assert!(resp.id.is_nil());
+    assert_eq!(
+        resp.error.unwrap().code,
+        homie_protocol::error_codes::PAYLOAD_TOO_LARGE
+    );
+
+    // The connection is still usable.
+    let err = rpc_err(&mut ws, "nonexistent.method", None).await;
+    assert_eq!(err.code, homie_protocol::error_codes::METHOD_NOT_FOUND);
+}
+
+#[tokio::test]
+async fn compressed_binary_frames_are_capped_after_inflating() {
+    let config = ServerConfig {
+        max_binary_bytes: 8192,
+        ..ServerConfig::default()
+    };
+    let addr = start_server(config).await;
+    let mut ws = connect_ws(addr).await;
+    ws.send(text_msg(client_hello_with_compression(vec![
+        Compression::Zstd,
+    ])))
+    .await
+    .unwrap();
+    let _ = next_text(&mut ws).await; // consume ServerHello
+
+    // A few hundred bytes on the wire, 1 MiB once inflated.
+    let frame = homie_protocol::BinaryFrame {
+        session_id: uuid::Uuid::new_v4(),
+        stream: homie_protocol::StreamType::Upload,
+        payload: vec![0; 1024 * 1024],
+    };
+    let encoded = frame.encode_with(Some(Compression::Zstd));
+    assert!(encoded.len() < 8192);
+    ws.send(tungstenite::Message::Binary(encoded.into()))
+        .await
+        .unwrap();
+    let (text, _) = next_envelope(&mut ws).await;
+    let resp: homie_protocol::Response = serde_json::from_str(&text).unwrap();
+    assert!(resp.id.is_nil());
+    assert_eq!(
+        resp.error.unwrap().code,
+        homie_protocol::error_codes::PAYLOAD_TOO_LARGE
+    );
+}
+
+#[tokio::test]
+async fn text_frames_far_over_the_message_limit_close_the_connection() {
+    let config = ServerConfig {
+        max_message_bytes: 4096,
+        max_binary_bytes: 1024 * 1024,
+        ..ServerConfig::default()
+    };
+    let addr = start_server(config).await;
+    let mut ws = connect_with_hello(addr).await;
+
+    ws.send(text_msg("x".repeat(4096 + 128 * 1024)))
+        .await
+        .unwrap();
+    loop {
+        match ws.next().await {
+            Some(Ok(tungstenite::Message::Close(frame))) => {
+                assert_eq!(u16::from(frame.unwrap().code), 1009);
+                break;
+            }
+            Some(Ok(_)) => continue,
+            other => panic!("expected close frame, got {other:?}"),
+        }
+    }
+}
+
+async fn connect_with_hello(addr: SocketAddr) -> WsStream {
+    let mut ws = connect_ws(addr).await;
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await; // consume ServerHello
+    ws
+}
+
+#[tokio::test]
+async fn debug_events_mirror_other_connections_events() {
+    let config = ServerConfig {
+        debug_events: true,
+        ..ServerConfig::default()
+    };
+    let addr = start_server(config).await;
+    let topic = |topic: &str| Some(serde_json::json!({ "topic": topic }));
+
+    let mut watcher = connect_with_hello(addr).await;
+    rpc_ok(&mut watcher, "events.subscribe", topic("debug.events")).await;
+    let mut peer = connect_with_hello(addr).await;
+    rpc_ok(&mut peer, "events.subscribe", topic("presence.*")).await;
+    let _newcomer = connect_with_hello(addr).await;
+
+    let event: serde_json::Value = serde_json::from_str(&next_text(&mut watcher).await).unwrap();
+    assert_eq!(event["topic"], "debug.events");
+    assert_eq!(event["params"]["topic"], "presence.connected");
+    assert_eq!(event["params"]["dropped"], false);
+    assert!(event["params"]["bytes"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn debug_events_are_off_by_default() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_with_hello(addr).await;
+    let err = rpc_err(
+        &mut ws,
+        "events.subscribe",
+        Some(serde_json::json!({ "topic": "debug.events" })),
+    )
+    .await;
+    assert_eq!(err.code, homie_protocol::error_codes::INVALID_PARAMS);
+}
+
 #[tokio::test]
 async fn server_sends_ping_connection_stays_alive() {
     let config = ServerConfig {
@@ -228,6 +482,54 @@ async fn server_sends_ping_connection_stays_alive() {
     assert!(matches!(resp, homie_protocol::Message::Response(_)));
 }
 
+#[tokio::test]
+async fn heartbeat_capability_gets_ping_envelopes_and_reports_rtt() {
+    let config = ServerConfig {
+        heartbeat_interval: Duration::from_millis(100),
+        idle_timeout: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let addr = start_server(config).await;
+    let mut ws = connect_ws(addr).await;
+
+    let hello = serde_json::to_string(&ClientHello {
+        protocol: VersionRange::new(1, 1),
+        client_id: "test-client/0.1.0".into(),
+        auth_token: None,
+        capabilities: vec![homie_protocol::HEARTBEAT_CAPABILITY.into()],
+        compression: vec![],
+        device_id: None,
+    })
+    .unwrap();
+    ws.send(text_msg(hello)).await.unwrap();
+    let _ = next_text(&mut ws).await;
+
+    let t = next_text(&mut ws).await;
+    let seq = match serde_json::from_str::<homie_protocol::Message>(&t).unwrap() {
+        homie_protocol::Message::Ping(ping) => ping.seq,
+        other => panic!("expected ping, got {other:?}"),
+    };
+    let pong = homie_protocol::Message::Pong(homie_protocol::Pong { seq });
+    ws.send(text_msg(homie_protocol::encode_message(&pong).unwrap()))
+        .await
+        .unwrap();
+
+    let req = homie_protocol::Message::Request(Request::new("system.metrics", None));
+    ws.send(text_msg(homie_protocol::encode_message(&req).unwrap()))
+        .await
+        .unwrap();
+    let metrics = loop {
+        let t = next_text(&mut ws).await;
+        match serde_json::from_str::<homie_protocol::Message>(&t).unwrap() {
+            homie_protocol::Message::Ping(_) => continue,
+            homie_protocol::Message::Response(r) => break r.result.expect("metrics result"),
+            other => panic!("expected response, got {other:?}"),
+        }
+    };
+    let rtts = metrics["connection_rtt_ms"].as_object().expect("rtt map");
+    assert_eq!(rtts.len(), 1);
+}
+
 #[tokio::test]
 async fn idle_timeout_closes_connection() {
     let config = ServerConfig {
@@ -261,7 +563,54 @@ async fn unauthorized_requests_are_rejected_for_viewer_role() {
     let _ = next_text(&mut ws).await;
 
     let err = rpc_err(&mut ws, "terminal.session.start", None).await;
-    assert_eq!(err.code, homie_protocol::error_codes::UNAUTHORIZED);
+    assert_eq!(err.code, homie_protocol::error_codes::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn requests_past_burst_are_rate_limited() {
+    let config = ServerConfig {
+        max_requests_per_sec: 1,
+        request_burst: 2,
+        ..Default::default()
+    };
+    let addr = start_server(config).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await;
+
+    for _ in 0..2 {
+        let err = rpc_err(&mut ws, "ping.test", None).await;
+        assert_eq!(err.code, homie_protocol::error_codes::METHOD_NOT_FOUND);
+    }
+    let err = rpc_err(&mut ws, "ping.test", None).await;
+    assert_eq!(err.code, homie_protocol::error_codes::RATE_LIMITED);
+}
+
+#[tokio::test]
+async fn sustained_rate_limiting_closes_connection() {
+    let config = ServerConfig {
+        max_requests_per_sec: 1,
+        request_burst: 1,
+        rate_limit_close_after: 2,
+        ..Default::default()
+    };
+    let addr = start_server(config).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await;
+
+    let _ = rpc_err(&mut ws, "ping.test", None).await;
+    let err = rpc_err(&mut ws, "ping.test", None).await;
+    assert_eq!(err.code, homie_protocol::error_codes::RATE_LIMITED);
+    let err = rpc_err(&mut ws, "ping.test", None).await;
+    assert_eq!(err.code, homie_protocol::error_codes::RATE_LIMITED);
+
+    assert!(
+        expect_close(&mut ws, Duration::from_secs(5)).await,
+        "expected connection to close after sustained rate limiting"
+    );
 }
 
 #[tokio::test]
@@ -286,5 +635,400 @@ async fn cron_requests_are_rejected_for_viewer_role() {
         })),
     )
     .await;
-    assert_eq!(err.code, homie_protocol::error_codes::UNAUTHORIZED);
+    assert_eq!(err.code, homie_protocol::error_codes::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn system_metrics_reports_routed_methods() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await;
+
+    let _ = rpc_ok(&mut ws, "presence.list", None).await;
+    let _ = rpc_ok(&mut ws, "presence.list", None).await;
+
+    let metrics = rpc_ok(&mut ws, "system.metrics", None).await;
+    assert_eq!(metrics["active_connections"], 1);
+    assert_eq!(metrics["methods"]["presence.list"]["count"], 2);
+    assert_eq!(metrics["methods"]["presence.list"]["errors"], 0);
+    assert!(metrics["methods"]["presence.list"]["p95_ms"].is_number());
+}
+
+#[tokio::test]
+async fn system_metrics_rejected_for_viewer_role() {
+    let config = ServerConfig {
+        local_role: Role::Viewer,
+        ..Default::default()
+    };
+    let addr = start_server(config).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await;
+
+    let err = rpc_err(&mut ws, "system.metrics", None).await;
+    assert_eq!(err.code, homie_protocol::error_codes::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn frozen_runs_are_refused_until_unfrozen() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut admin = connect_ws(addr).await;
+    admin.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut admin).await;
+
+    let status = rpc_ok(
+        &mut admin,
+        "admin.runs.freeze",
+        Some(serde_json::json!({ "reason": "incident" })),
+    )
+    .await;
+    assert_eq!(status["frozen"], true);
+
+    // The freeze is process-wide, so a second connection sees it too.
+    let mut client = connect_ws(addr).await;
+    client.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut client).await;
+    let err = rpc_err(
+        &mut client,
+        "chat.message.send",
+        Some(serde_json::json!({ "chat_id": "chat-1", "message": "hi" })),
+    )
+    .await;
+    assert_eq!(err.code, homie_protocol::error_codes::FROZEN);
+    assert!(err.message.contains("incident"));
+
+    let status = rpc_ok(&mut admin, "admin.runs.unfreeze", None).await;
+    assert_eq!(status["frozen"], false);
+    let status = rpc_ok(&mut client, "admin.runs.status", None).await;
+    assert_eq!(status["frozen"], false);
+}
+
+#[tokio::test]
+async fn admin_methods_rejected_for_user_role() {
+    let config = ServerConfig {
+        local_role: Role::User,
+        ..Default::default()
+    };
+    let addr = start_server(config).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await;
+
+    let err = rpc_err(&mut ws, "admin.runs.freeze", None).await;
+    assert_eq!(err.code, homie_protocol::error_codes::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn chat_list_subscribe_streams_deltas_to_other_connections() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut watcher = connect_ws(addr).await;
+    watcher.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut watcher).await;
+    let sub = rpc_ok(&mut watcher, "chat.list.subscribe", None).await;
+    assert!(sub["subscription_id"].is_string());
+    assert_eq!(sub["chats"], serde_json::json!([]));
+
+    let mut client = connect_ws(addr).await;
+    client.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut client).await;
+    let created = rpc_ok(&mut client, "chat.create", None).await;
+    let chat_id = created["chat_id"].as_str().expect("chat_id").to_string();
+    let _ = rpc_ok(
+        &mut client,
+        "chat.thread.archive",
+        Some(serde_json::json!({ "chat_id": chat_id })),
+    )
+    .await;
+
+    let mut deltas = Vec::new();
+    while deltas.len() < 2 {
+        let t = tokio::time::timeout(Duration::from_secs(5), next_text(&mut watcher))
+            .await
+            .expect("chat.list.updated event");
+        let homie_protocol::Message::Event(event) = serde_json::from_str(&t).unwrap() else {
+            continue;
+        };
+        assert_eq!(event.topic, "chat.list.updated");
+        deltas.push(event.params.expect("params"));
+    }
+    assert_eq!(deltas[0]["op"], "upsert");
+    assert_eq!(deltas[0]["chat"]["chat_id"], chat_id.as_str());
+    assert_eq!(deltas[1]["op"], "remove");
+    assert_eq!(deltas[1]["chat_id"], chat_id.as_str());
+}
+
+#[tokio::test]
+async fn chat_file_upload_streams_binary_chunks() {
+    use sha2::{Digest, Sha256};
+
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_ws(addr).await;
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let _ = next_text(&mut ws).await;
+
+    let data = b"uploaded over binary frames".repeat(100);
+    let sha256: String = Sha256::digest(&data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let begin = rpc_ok(
+        &mut ws,
+        "chat.file.upload.begin",
+        Some(serde_json::json!({ "name": "notes.txt", "size": data.len(), "sha256": sha256 })),
+    )
+    .await;
+    let upload_id: uuid::Uuid = begin["upload_id"].as_str().unwrap().parse().unwrap();
+
+    for chunk in data.chunks(1000) {
+        let frame = homie_protocol::BinaryFrame {
+            session_id: upload_id,
+            stream: homie_protocol::StreamType::Upload,
+            payload: chunk.to_vec(),
+        };
+        ws.send(tungstenite::Message::Binary(frame.encode().into()))
+            .await
+            .unwrap();
+    }
+    let committed = rpc_ok(
+        &mut ws,
+        "chat.file.upload.commit",
+        Some(serde_json::json!({ "upload_id": upload_id })),
+    )
+    .await;
+    let path = std::path::PathBuf::from(committed["path"].as_str().unwrap());
+    assert_eq!(path.file_name().unwrap(), "notes.txt");
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+    let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+    let err = rpc_err(
+        &mut ws,
+        "chat.file.upload.commit",
+        Some(serde_json::json!({ "upload_id": upload_id })),
+    )
+    .await;
+    assert_eq!(err.code, homie_protocol::error_codes::INVALID_PARAMS);
+}
+
+fn client_hello_with_compression(compression: Vec<Compression>) -> String {
+    serde_json::to_string(&ClientHello {
+        protocol: VersionRange::new(1, 1),
+        client_id: "test-client/0.1.0".into(),
+        auth_token: None,
+        capabilities: vec![],
+        compression,
+        device_id: None,
+    })
+    .unwrap()
+}
+
+/// Read the next envelope, unwrapping compressed binary frames. Returns the
+/// JSON text and whether it arrived compressed.
+async fn next_envelope(ws: &mut WsStream) -> (String, bool) {
+    loop {
+        match ws.next().await {
+            Some(Ok(tungstenite::Message::Text(t))) => return (t.to_string(), false),
+            Some(Ok(tungstenite::Message::Binary(data))) => {
+                let text = decode_envelope_frame(&data)
+                    .unwrap()
+                    .expect("binary frame should be an envelope");
+                return (text, true);
+            }
+            Some(Ok(tungstenite::Message::Ping(data))) => {
+                let _ = ws.send(tungstenite::Message::Pong(data)).await;
+            }
+            Some(Ok(tungstenite::Message::Pong(_))) => continue,
+            Some(Ok(other)) => panic!("unexpected message: {other:?}"),
+            Some(Err(e)) => panic!("ws error: {e}"),
+            None => panic!("ws stream ended unexpectedly"),
+        }
+    }
+}
+
+fn presence_register_request() -> String {
+    let req = homie_protocol::Message::Request(Request::new(
+        "presence.register",
+        Some(serde_json::json!({
+            "node_id": "node-big",
+            "name": "n".repeat(4096),
+        })),
+    ));
+    homie_protocol::encode_message(&req).unwrap()
+}
+
+fn presence_list_request() -> String {
+    let req = homie_protocol::Message::Request(Request::new("presence.list", None));
+    homie_protocol::encode_message(&req).unwrap()
+}
+
+#[tokio::test]
+async fn negotiated_compression_wraps_large_envelopes() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello_with_compression(vec![
+        Compression::Gzip,
+    ])))
+    .await
+    .unwrap();
+    let resp: HandshakeResponse = serde_json::from_str(&next_text(&mut ws).await).unwrap();
+    match resp {
+        HandshakeResponse::Hello(hello) => {
+            assert_eq!(hello.compression, Some(Compression::Gzip));
+        }
+        other => panic!("expected hello, got {other:?}"),
+    }
+
+    // Clients may send compressed requests too.
+    let frame = encode_envelope_frame(&presence_register_request(), Some(Compression::Gzip))
+        .expect("large request compresses");
+    ws.send(tungstenite::Message::Binary(frame.into()))
+        .await
+        .unwrap();
+    let (text, compressed) = next_envelope(&mut ws).await;
+    assert!(!compressed, "small responses stay uncompressed");
+    assert!(text.contains("\"ok\":true"));
+
+    ws.send(text_msg(presence_list_request())).await.unwrap();
+    let (text, compressed) = next_envelope(&mut ws).await;
+    assert!(compressed, "large responses are compressed");
+    assert!(text.contains("node-big"));
+}
+
+#[tokio::test]
+async fn clients_without_compression_receive_text_frames() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_ws(addr).await;
+
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let resp: HandshakeResponse = serde_json::from_str(&next_text(&mut ws).await).unwrap();
+    match resp {
+        HandshakeResponse::Hello(hello) => assert_eq!(hello.compression, None),
+        other => panic!("expected hello, got {other:?}"),
+    }
+
+    ws.send(text_msg(presence_register_request()))
+        .await
+        .unwrap();
+    let _ = next_text(&mut ws).await;
+    ws.send(text_msg(presence_list_request())).await.unwrap();
+    let text = next_text(&mut ws).await;
+    assert!(text.contains("node-big"));
+}
+
+#[tokio::test]
+async fn compressed_frames_without_negotiation_close_the_connection() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut ws = connect_with_hello(addr).await;
+
+    let frame = encode_envelope_frame(&presence_register_request(), Some(Compression::Gzip))
+        .expect("large request compresses");
+    ws.send(tungstenite::Message::Binary(frame.into()))
+        .await
+        .unwrap();
+    loop {
+        match ws.next().await {
+            Some(Ok(tungstenite::Message::Close(frame))) => {
+                assert_eq!(u16::from(frame.unwrap().code), 1002);
+                break;
+            }
+            Some(Ok(_)) => continue,
+            other => panic!("expected close frame, got {other:?}"),
+        }
+    }
+}
+
+async fn connect_ws_with_bearer(
+    addr: SocketAddr,
+    token: &str,
+) -> Result<WsStream, tungstenite::Error> {
+    let mut req = format!("ws://{addr}/ws").into_client_request().unwrap();
+    req.headers_mut()
+        .insert("authorization", format!("Bearer {token}").parse().unwrap());
+    tokio_tungstenite::connect_async(req)
+        .await
+        .map(|(stream, _)| stream)
+}
+
+#[tokio::test]
+async fn api_key_auth_assigns_key_role() {
+    let config = ServerConfig {
+        auth_mode: AuthMode::ApiKey,
+        api_keys: vec![ApiKey {
+            name: "dashboard".into(),
+            sha256: hash_api_key("dash-secret"),
+            role: Role::Viewer,
+        }],
+        ..Default::default()
+    };
+    let addr = start_server(config).await;
+
+    assert!(connect_ws_with_bearer(addr, "wrong-secret").await.is_err());
+
+    let mut ws = connect_ws_with_bearer(addr, "dash-secret").await.unwrap();
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let resp: HandshakeResponse = serde_json::from_str(&next_text(&mut ws).await).unwrap();
+    match resp {
+        HandshakeResponse::Hello(hello) => {
+            assert_eq!(hello.identity.as_deref(), Some("api-key:dashboard"))
+        }
+        other => panic!("expected hello, got {other:?}"),
+    }
+
+    let err = rpc_err(&mut ws, "admin.runs.status", None).await;
+    assert_eq!(err.code, homie_protocol::error_codes::FORBIDDEN);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_socket_clients_are_treated_as_local() {
+    let config = ServerConfig {
+        auth_mode: AuthMode::ApiKey,
+        api_keys: vec![ApiKey {
+            name: "dashboard".into(),
+            sha256: hash_api_key("dash-secret"),
+            role: Role::Viewer,
+        }],
+        ..Default::default()
+    };
+    let store = Arc::new(SqliteStore::open_memory().unwrap());
+    let app = homie_core::build_router(config, NoopWhois, store);
+    let path = std::env::temp_dir().join(format!("homie-{}.sock", uuid::Uuid::new_v4()));
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<homie_core::UnixPeer>(),
+        )
+        .await
+        .unwrap();
+    });
+
+    let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let (mut ws, _) = tokio_tungstenite::client_async("ws://localhost/ws", stream)
+        .await
+        .unwrap();
+    ws.send(text_msg(client_hello(1, 1))).await.unwrap();
+    let resp: HandshakeResponse = serde_json::from_str(&next_text_unix(&mut ws).await).unwrap();
+    match resp {
+        HandshakeResponse::Hello(hello) => assert_eq!(hello.identity.as_deref(), Some("local")),
+        other => panic!("expected hello, got {other:?}"),
+    }
+    std::fs::remove_file(&path).ok();
+}
+
+#[cfg(unix)]
+async fn next_text_unix(
+    ws: &mut tokio_tungstenite::WebSocketStream<tokio::net::UnixStream>,
+) -> String {
+    loop {
+        match ws.next().await {
+            Some(Ok(tungstenite::Message::Text(t))) => return t.to_string(),
+            Some(Ok(_)) => continue,
+            other => panic!("expected text message, got {other:?}"),
+        }
+    }
 }
@@ -1,11 +1,18 @@
+use std::collections::HashMap;
 use std::env;
 use std::net::IpAddr;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
-use homie_core::{build_router, LiveWhois, Role, ServerConfig, SqliteStore};
+#[cfg(unix)]
+use homie_core::UnixPeer;
+use homie_core::{
+    build_router_with_shutdown, check_default_model, parse_api_key_spec, ApiKey, AuthMode,
+    EventBusKind, HomieConfig, LiveWhois, OverflowPolicy, Role, ServerConfig, ShutdownSignal,
+    SqliteStore,
+};
 use tokio::net::TcpListener;
 
 #[tokio::main]
@@ -17,6 +24,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let defaults = ServerConfig::default();
 
     let bind = parse_socket("HOMIE_BIND", defaults.bind);
+    let unix_socket = parse_optional_path("HOMIE_UNIX_SOCKET");
+    // With a Unix socket configured, TCP is only served when HOMIE_BIND is
+    // set explicitly.
+    let serve_tcp = unix_socket.is_none() || env::var("HOMIE_BIND").is_ok();
     let tailnet_bind = parse_optional_socket("HOMIE_TAILNET_BIND");
     let tailscale_env = parse_bool("HOMIE_TAILSCALE", false);
     let tailscale_serve =
@@ -24,6 +35,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let allow_lan = parse_bool("HOMIE_ALLOW_LAN", defaults.allow_lan);
     let heartbeat_interval = parse_duration("HOMIE_HEARTBEAT_SECS", defaults.heartbeat_interval);
     let idle_timeout = parse_duration("HOMIE_IDLE_SECS", defaults.idle_timeout);
+    let terminal_idle_timeout =
+        parse_duration("HOMIE_TERMINAL_IDLE_SECS", defaults.terminal_idle_timeout);
     let node_timeout = parse_duration("HOMIE_NODE_TIMEOUT_SECS", defaults.node_timeout);
     let job_retention_days = parse_u64("HOMIE_JOB_RETENTION_DAYS", defaults.job_retention_days);
     let job_max_records = parse_usize("HOMIE_JOB_MAX_RECORDS", defaults.job_max_records);
@@ -44,16 +57,79 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "HOMIE_CRON_MAX_CONCURRENT_RUNS",
         defaults.cron_max_concurrent_runs,
     );
+    let terminal_recording_retention_days = parse_u64(
+        "HOMIE_TERMINAL_RECORDING_RETENTION_DAYS",
+        defaults.terminal_recording_retention_days,
+    );
+    let terminal_recording_max_records = parse_usize(
+        "HOMIE_TERMINAL_RECORDING_MAX_RECORDS",
+        defaults.terminal_recording_max_records,
+    );
+    let audit_retention_days =
+        parse_u64("HOMIE_AUDIT_RETENTION_DAYS", defaults.audit_retention_days);
+    let tool_invocation_retention_days = parse_u64(
+        "HOMIE_TOOL_INVOCATION_RETENTION_DAYS",
+        defaults.tool_invocation_retention_days,
+    );
+    let maintenance_interval = parse_duration(
+        "HOMIE_MAINTENANCE_INTERVAL_SECS",
+        defaults.maintenance_interval,
+    );
+    let max_requests_per_sec =
+        parse_u32("HOMIE_MAX_REQUESTS_PER_SEC", defaults.max_requests_per_sec);
+    let request_burst = parse_u32("HOMIE_REQUEST_BURST", defaults.request_burst);
+    let rate_limit_close_after = parse_u32(
+        "HOMIE_RATE_LIMIT_CLOSE_AFTER",
+        defaults.rate_limit_close_after,
+    );
+    let reconnect_min_backoff = parse_duration_ms(
+        "HOMIE_RECONNECT_MIN_BACKOFF_MS",
+        defaults.reconnect_min_backoff,
+    );
+    let reconnect_max_backoff = parse_duration_ms(
+        "HOMIE_RECONNECT_MAX_BACKOFF_MS",
+        defaults.reconnect_max_backoff,
+    );
+    let shutdown_retry_after = parse_duration_ms(
+        "HOMIE_SHUTDOWN_RETRY_AFTER_MS",
+        defaults.shutdown_retry_after,
+    );
+    let outbound_capacity = parse_usize("HOMIE_OUTBOUND_CAPACITY", defaults.outbound_capacity);
+    let outbound_overflow =
+        parse_overflow_policy("HOMIE_OUTBOUND_OVERFLOW", defaults.outbound_overflow);
+    let max_message_bytes = parse_usize("HOMIE_MAX_MESSAGE_BYTES", defaults.max_message_bytes);
+    let max_binary_bytes = parse_usize("HOMIE_MAX_BINARY_BYTES", defaults.max_binary_bytes);
+    let debug_events = parse_bool("HOMIE_DEBUG_EVENTS", defaults.debug_events);
+    let request_timeout = parse_duration("HOMIE_REQUEST_TIMEOUT_SECS", defaults.request_timeout);
+    let request_timeout_overrides = parse_request_timeouts(
+        "HOMIE_REQUEST_TIMEOUTS",
+        defaults.request_timeout_overrides.clone(),
+    );
+    let event_bus = parse_event_bus("HOMIE_EVENT_BUS", defaults.event_bus);
+    let event_bus_poll_interval =
+        parse_duration_ms("HOMIE_EVENT_BUS_POLL_MS", defaults.event_bus_poll_interval);
     let local_role = parse_role("HOMIE_LOCAL_ROLE", defaults.local_role);
     let tailscale_role = parse_role("HOMIE_TAILSCALE_ROLE", defaults.tailscale_role);
+    let auth_mode = parse_auth_mode("HOMIE_AUTH_MODE", defaults.auth_mode);
+    let api_keys = parse_api_keys("HOMIE_API_KEYS");
+    let open_role = parse_role("HOMIE_OPEN_ROLE", defaults.open_role);
+    if auth_mode == AuthMode::ApiKey && api_keys.is_empty() {
+        tracing::warn!(
+            "HOMIE_AUTH_MODE=api_key but HOMIE_API_KEYS is empty; remote clients will be rejected"
+        );
+    }
 
     let config = ServerConfig {
         bind,
         tailnet_bind,
         tailscale_serve,
         allow_lan,
+        auth_mode,
+        api_keys,
+        open_role,
         heartbeat_interval,
         idle_timeout,
+        terminal_idle_timeout,
         local_role,
         tailscale_role,
         node_timeout,
@@ -65,8 +141,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         cron_retention_days,
         cron_max_run_records,
         cron_max_concurrent_runs,
+        terminal_recording_retention_days,
+        terminal_recording_max_records,
+        audit_retention_days,
+        tool_invocation_retention_days,
+        maintenance_interval,
+        max_requests_per_sec,
+        request_burst,
+        rate_limit_close_after,
+        reconnect_min_backoff,
+        reconnect_max_backoff,
+        shutdown_retry_after,
+        outbound_capacity,
+        outbound_overflow,
+        max_message_bytes,
+        max_binary_bytes,
+        debug_events,
+        request_timeout,
+        request_timeout_overrides,
+        event_bus,
+        event_bus_poll_interval,
     };
 
+    check_roci_default_model()?;
+
     let db_path = env::var("HOMIE_DB_PATH").unwrap_or_else(|_| "homie.db".to_string());
     let store = SqliteStore::open(Path::new(&db_path))?;
     let store = Arc::new(store);
@@ -75,13 +173,41 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         ensure_tailscale_serve(config.bind).await;
     }
 
-    let app = build_router(config.clone(), LiveWhois, store);
+    let shutdown = ShutdownSignal::new();
+    tokio::spawn(trigger_on_signal(shutdown.clone()));
 
-    let listener = TcpListener::bind(config.bind).await?;
-    tracing::info!(addr = %config.bind, "listening");
+    let app = build_router_with_shutdown(config.clone(), LiveWhois, store, shutdown.clone());
+
+    #[cfg(unix)]
+    let unix_server = match unix_socket {
+        Some(path) => {
+            let listener = bind_unix_socket(&path)?;
+            tracing::info!(path = %path.display(), "listening on unix socket");
+            Some(tokio::spawn(serve_unix_socket(
+                listener,
+                path,
+                app.clone(),
+                shutdown.clone(),
+            )))
+        }
+        None => None,
+    };
+    #[cfg(not(unix))]
+    if unix_socket.is_some() {
+        tracing::warn!("HOMIE_UNIX_SOCKET is only supported on unix; ignoring");
+    }
+
+    let listener = if serve_tcp {
+        let listener = TcpListener::bind(config.bind).await?;
+        tracing::info!(addr = %config.bind, "listening");
+        Some(listener)
+    } else {
+        None
+    };
 
     if let Some(addr) = config.tailnet_bind {
         let app_tailnet = app.clone();
+        let shutdown_tailnet = shutdown.clone();
         tokio::spawn(async move {
             let listener = TcpListener::bind(addr).await;
             let listener = match listener {
@@ -96,6 +222,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 listener,
                 app_tailnet.into_make_service_with_connect_info::<SocketAddr>(),
             )
+            .with_graceful_shutdown(async move { shutdown_tailnet.wait().await })
             .await
             {
                 tracing::error!(error = %e, "tailnet server error");
@@ -103,15 +230,102 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
     }
 
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await?;
+    match listener {
+        Some(listener) => {
+            let shutdown = shutdown.clone();
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(async move { shutdown.wait().await })
+            .await?;
+        }
+        None => shutdown.wait().await,
+    }
+
+    #[cfg(unix)]
+    if let Some(handle) = unix_server {
+        let _ = handle.await;
+    }
 
+    tracing::info!("server stopped");
     Ok(())
 }
 
+/// Trigger graceful shutdown on Ctrl-C or SIGTERM.
+async fn trigger_on_signal(shutdown: ShutdownSignal) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!(error = %e, "failed to listen for ctrl-c");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("shutdown requested");
+    shutdown.trigger();
+}
+
+/// Bind a Unix socket at `path`, replacing a stale socket left by an
+/// earlier run. Refuses to remove anything that is not a socket.
+#[cfg(unix)]
+fn bind_unix_socket(path: &Path) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    tokio::net::UnixListener::bind(path)
+}
+
+/// Serve the router on a Unix socket until shutdown, then remove the
+/// socket file.
+#[cfg(unix)]
+async fn serve_unix_socket(
+    listener: tokio::net::UnixListener,
+    path: PathBuf,
+    app: axum::Router,
+    shutdown: ShutdownSignal,
+) {
+    if let Err(e) = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<UnixPeer>(),
+    )
+    .with_graceful_shutdown(async move { shutdown.wait().await })
+    .await
+    {
+        tracing::error!(error = %e, "unix socket server error");
+    }
+    if let Err(e) = std::fs::remove_file(&path) {
+        tracing::warn!(path = %path.display(), error = %e, "failed to remove unix socket");
+    }
+}
+
 async fn ensure_tailscale_serve(bind: SocketAddr) {
     let host = match bind.ip() {
         IpAddr::V4(ip) if ip.is_unspecified() => "127.0.0.1".to_string(),
@@ -137,6 +351,24 @@ async fn ensure_tailscale_serve(bind: SocketAddr) {
     }
 }
 
+/// Refuse to start with an unparseable default model; warn when its provider
+/// is disabled so the first run does not fail with a confusing auth error.
+fn check_roci_default_model() -> Result<(), String> {
+    // The router logs config load failures; defaults are enough here.
+    let homie_config = HomieConfig::load().unwrap_or_default();
+    match check_default_model(&homie_config.providers) {
+        Ok(None) => Ok(()),
+        Ok(Some(warning)) => {
+            tracing::warn!("{warning}");
+            Ok(())
+        }
+        Err(err) => {
+            tracing::error!("{err}");
+            Err(err)
+        }
+    }
+}
+
 fn parse_socket(key: &str, default: SocketAddr) -> SocketAddr {
     match env::var(key) {
         Ok(v) => v.parse().unwrap_or(default),
@@ -148,6 +380,13 @@ fn parse_optional_socket(key: &str) -> Option<SocketAddr> {
     env::var(key).ok().and_then(|v| v.parse().ok())
 }
 
+fn parse_optional_path(key: &str) -> Option<PathBuf> {
+    env::var(key)
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .map(PathBuf::from)
+}
+
 fn parse_bool(key: &str, default: bool) -> bool {
     match env::var(key) {
         Ok(v) => matches!(v.as_str(), "1" | "true" | "TRUE" | "yes" | "YES"),
@@ -162,6 +401,16 @@ fn parse_duration(key: &str, default: Duration) -> Duration {
     }
 }
 
+fn parse_duration_ms(key: &str, default: Duration) -> Duration {
+    match env::var(key) {
+        Ok(v) => v
+            .parse::<u64>()
+            .map(Duration::from_millis)
+            .unwrap_or(default),
+        Err(_) => default,
+    }
+}
+
 fn parse_u64(key: &str, default: u64) -> u64 {
     match env::var(key) {
         Ok(v) => v.parse::<u64>().unwrap_or(default),
@@ -169,6 +418,13 @@ fn parse_u64(key: &str, default: u64) -> u64 {
     }
 }
 
+fn parse_u32(key: &str, default: u32) -> u32 {
+    match env::var(key) {
+        Ok(v) => v.parse::<u32>().unwrap_or(default),
+        Err(_) => default,
+    }
+}
+
 fn parse_usize(key: &str, default: usize) -> usize {
     match env::var(key) {
         Ok(v) => v.parse::<usize>().unwrap_or(default),
@@ -188,6 +444,77 @@ fn parse_role(key: &str, default: Role) -> Role {
     }
 }
 
+fn parse_auth_mode(key: &str, default: AuthMode) -> AuthMode {
+    match env::var(key) {
+        Ok(v) => AuthMode::from_label(&v).unwrap_or_else(|| {
+            tracing::warn!(value = %v, "unknown {key}; using default");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+fn parse_event_bus(key: &str, default: EventBusKind) -> EventBusKind {
+    match env::var(key) {
+        Ok(v) => EventBusKind::from_label(&v).unwrap_or_else(|| {
+            tracing::warn!(value = %v, "unknown {key}; using default");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+fn parse_overflow_policy(key: &str, default: OverflowPolicy) -> OverflowPolicy {
+    match env::var(key) {
+        Ok(v) => OverflowPolicy::from_label(&v).unwrap_or_else(|| {
+            tracing::warn!(value = %v, "unknown {key}; using default");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// `method=secs` pairs, comma separated, layered over `defaults`; `0`
+/// exempts a method from the request timeout.
+fn parse_request_timeouts(
+    key: &str,
+    mut defaults: HashMap<String, Duration>,
+) -> HashMap<String, Duration> {
+    let Ok(raw) = env::var(key) else {
+        return defaults;
+    };
+    for spec in raw.split(',').filter(|spec| !spec.trim().is_empty()) {
+        let parsed = spec
+            .split_once('=')
+            .and_then(|(method, secs)| Some((method.trim(), secs.trim().parse::<u64>().ok()?)));
+        match parsed {
+            Some((method, secs)) if !method.is_empty() => {
+                defaults.insert(method.to_string(), Duration::from_secs(secs));
+            }
+            _ => tracing::warn!(entry = spec, "ignoring invalid request timeout entry"),
+        }
+    }
+    defaults
+}
+
+/// Comma-separated `name:role:sha256` entries. Invalid entries are skipped
+/// with a warning rather than failing startup.
+fn parse_api_keys(key: &str) -> Vec<ApiKey> {
+    let Ok(raw) = env::var(key) else {
+        return Vec::new();
+    };
+    raw.split(',')
+        .filter(|spec| !spec.trim().is_empty())
+        .filter_map(|spec| match parse_api_key_spec(spec) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                tracing::warn!(error = %e, "ignoring invalid api key entry");
+                None
+            }
+        })
+        .collect()
+}
+
 fn tracing_filter() -> tracing_subscriber::EnvFilter {
     let explicit = env::var("HOMIE_LOG").or_else(|_| env::var("RUST_LOG")).ok();
     if let Some(filter) = explicit {
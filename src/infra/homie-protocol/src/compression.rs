@@ -0,0 +1,152 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ProtocolError;
+
+/// Payloads smaller than this are sent uncompressed even when compression
+/// has been negotiated; the framing overhead is not worth it.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Per-message compression algorithms a peer can advertise in the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+/// Algorithms the server accepts, most preferred first.
+pub const SERVER_COMPRESSION: [Compression; 2] = [Compression::Zstd, Compression::Gzip];
+
+impl Compression {
+    /// Value stored in the upper nibble of a binary frame's stream byte.
+    /// `0` means the payload is not compressed.
+    pub fn to_flag(self) -> u8 {
+        match self {
+            Self::Gzip => 1,
+            Self::Zstd => 2,
+        }
+    }
+
+    pub fn from_flag(flag: u8) -> Result<Option<Self>, ProtocolError> {
+        match flag {
+            0 => Ok(None),
+            1 => Ok(Some(Self::Gzip)),
+            2 => Ok(Some(Self::Zstd)),
+            _ => Err(ProtocolError::InvalidCompression(flag)),
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        match self {
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Zstd => Ok(zstd::stream::encode_all(data, 0)?),
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        self.decompress_limited(data, usize::MAX)
+    }
+
+    /// Decompress, failing with [`ProtocolError::PayloadTooLarge`] as soon
+    /// as the output would exceed `limit` bytes instead of inflating it all.
+    pub fn decompress_limited(self, data: &[u8], limit: usize) -> Result<Vec<u8>, ProtocolError> {
+        let cap = (limit as u64).saturating_add(1);
+        let mut out = Vec::new();
+        match self {
+            Self::Gzip => {
+                flate2::read::GzDecoder::new(data)
+                    .take(cap)
+                    .read_to_end(&mut out)?;
+            }
+            Self::Zstd => {
+                zstd::stream::read::Decoder::new(data)?
+                    .take(cap)
+                    .read_to_end(&mut out)?;
+            }
+        }
+        if out.len() > limit {
+            return Err(ProtocolError::PayloadTooLarge {
+                limit,
+                got: out.len(),
+            });
+        }
+        Ok(out)
+    }
+}
+
+/// Pick the first algorithm in `server` preference order that the client
+/// also advertised. `None` keeps the connection uncompressed.
+pub fn negotiate_compression(
+    client: &[Compression],
+    server: &[Compression],
+) -> Option<Compression> {
+    server.iter().copied().find(|algo| client.contains(algo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_all_algorithms() {
+        let data = "terminal output ".repeat(200).into_bytes();
+        for algo in [Compression::Gzip, Compression::Zstd] {
+            let compressed = algo.compress(&data).unwrap();
+            assert!(compressed.len() < data.len());
+            assert_eq!(algo.decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn limited_decompression_stops_at_the_limit() {
+        let data = vec![b'a'; 64 * 1024];
+        for algo in [Compression::Gzip, Compression::Zstd] {
+            let compressed = algo.compress(&data).unwrap();
+            assert_eq!(
+                algo.decompress_limited(&compressed, data.len()).unwrap(),
+                data
+            );
+            assert!(matches!(
+                algo.decompress_limited(&compressed, 1024),
+                Err(ProtocolError::PayloadTooLarge { limit: 1024, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn negotiation_prefers_server_order() {
+        assert_eq!(
+            negotiate_compression(&[Compression::Gzip, Compression::Zstd], &SERVER_COMPRESSION),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            negotiate_compression(&[Compression::Gzip], &SERVER_COMPRESSION),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(negotiate_compression(&[], &SERVER_COMPRESSION), None);
+    }
+
+    #[test]
+    fn flag_roundtrip_and_rejects_unknown() {
+        for algo in [Compression::Gzip, Compression::Zstd] {
+            assert_eq!(Compression::from_flag(algo.to_flag()).unwrap(), Some(algo));
+        }
+        assert_eq!(Compression::from_flag(0).unwrap(), None);
+        assert!(Compression::from_flag(7).is_err());
+    }
+
+    #[test]
+    fn serializes_snake_case() {
+        assert_eq!(
+            serde_json::to_string(&Compression::Zstd).unwrap(),
+            "\"zstd\""
+        );
+    }
+}
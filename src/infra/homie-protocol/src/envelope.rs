@@ -8,12 +8,19 @@ use uuid::Uuid;
 /// - `request`  — client → server RPC
 /// - `response` — server → client RPC reply
 /// - `event`    — server → client push notification
+/// - `ping`     — server → client liveness probe
+/// - `pong`     — client → server reply to a `ping`
+/// - `close`    — server → client notice sent before the server closes
+///   the connection
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Message {
     Request(Request),
     Response(Response),
     Event(Event),
+    Ping(Ping),
+    Pong(Pong),
+    Close(Close),
 }
 
 /// Client → server RPC request.
@@ -51,6 +58,57 @@ pub struct RpcError {
     /// Optional structured data.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<Value>,
+    /// Kind of failure, derived from `code`, so clients can branch without
+    /// matching on messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<ErrorCategory>,
+    /// Whether repeating the same request later may succeed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retryable: Option<bool>,
+}
+
+/// Machine-readable kind of an `RpcError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Missing, invalid or insufficient credentials.
+    Auth,
+    /// The method, session or resource does not exist.
+    NotFound,
+    /// The request parameters were rejected.
+    InvalidParams,
+    /// Too many requests; back off and retry.
+    RateLimited,
+    /// A usage allowance is spent; retrying fails until its window moves on.
+    Quota,
+    /// A backend is temporarily refusing work (e.g. runs are frozen).
+    Backend,
+    /// The server failed to handle the request.
+    Internal,
+}
+
+impl ErrorCategory {
+    /// Category of a well-known `error_codes` value; `None` for codes
+    /// outside that table.
+    pub fn for_code(code: i32) -> Option<Self> {
+        match code {
+            error_codes::UNAUTHORIZED | error_codes::FORBIDDEN => Some(Self::Auth),
+            error_codes::METHOD_NOT_FOUND | error_codes::SESSION_NOT_FOUND => Some(Self::NotFound),
+            error_codes::INVALID_PARAMS | error_codes::PAYLOAD_TOO_LARGE => {
+                Some(Self::InvalidParams)
+            }
+            error_codes::RATE_LIMITED => Some(Self::RateLimited),
+            error_codes::BUDGET_EXCEEDED => Some(Self::Quota),
+            error_codes::FROZEN | error_codes::REQUEST_TIMEOUT => Some(Self::Backend),
+            error_codes::INTERNAL_ERROR => Some(Self::Internal),
+            _ => None,
+        }
+    }
+
+    /// Whether errors of this category may go away on their own.
+    pub fn retryable(self) -> bool {
+        matches!(self, Self::RateLimited | Self::Backend)
+    }
 }
 
 /// Well-known RPC error codes.
@@ -60,6 +118,17 @@ pub mod error_codes {
     pub const INTERNAL_ERROR: i32 = -32603;
     pub const UNAUTHORIZED: i32 = -32001;
     pub const SESSION_NOT_FOUND: i32 = -32002;
+    pub const RATE_LIMITED: i32 = -32003;
+    pub const FROZEN: i32 = -32004;
+    /// The connection's role may not call the method.
+    pub const FORBIDDEN: i32 = -32005;
+    /// The frame exceeded the connection's size limit and was not parsed.
+    pub const PAYLOAD_TOO_LARGE: i32 = -32006;
+    /// The caller's token budget for the current window is spent.
+    pub const BUDGET_EXCEEDED: i32 = -32007;
+    /// The request ran past the server's deadline for its method and was
+    /// cancelled.
+    pub const REQUEST_TIMEOUT: i32 = -32008;
 }
 
 /// Server → client push event.
@@ -72,6 +141,31 @@ pub struct Event {
     pub params: Option<Value>,
 }
 
+/// Server → client heartbeat, sent every heartbeat interval to clients that
+/// advertised the `heartbeat` capability. Unlike WS ping control frames it
+/// passes through proxies that terminate or strip them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ping {
+    /// Increases by one per ping on a connection.
+    pub seq: u64,
+}
+
+/// Client → server heartbeat reply; echoes the `seq` of the `Ping`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pong {
+    pub seq: u64,
+}
+
+/// Server → client notice that the connection is about to close.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Close {
+    /// Why the server is closing (e.g. "server_shutdown").
+    pub reason: String,
+    /// How long clients should wait before reconnecting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
 impl Request {
     pub fn new(method: impl Into<String>, params: Option<Value>) -> Self {
         Self {
@@ -99,9 +193,20 @@ impl Response {
                 code,
                 message: message.into(),
                 data: None,
+                category: ErrorCategory::for_code(code),
+                retryable: ErrorCategory::for_code(code).map(ErrorCategory::retryable),
             }),
         }
     }
+
+    /// An error carrying structured `data` for the client.
+    pub fn error_with_data(id: Uuid, code: i32, message: impl Into<String>, data: Value) -> Self {
+        let mut response = Self::error(id, code, message);
+        if let Some(error) = response.error.as_mut() {
+            error.data = Some(data);
+        }
+        response
+    }
 }
 
 /// Encode a `Message` to a JSON string for sending as a text WS frame.
@@ -114,6 +219,24 @@ pub fn decode_message(text: &str) -> Result<Message, serde_json::Error> {
     serde_json::from_str(text)
 }
 
+/// How far into a rejected frame [`peek_request_id`] looks.
+const PEEK_WINDOW: usize = 512;
+
+/// Best-effort `id` of a request that is too large to decode, so its error
+/// response can still be correlated. Only the first few hundred bytes are
+/// scanned, and only a top-level-looking `"id":"<uuid>"` is recognised.
+pub fn peek_request_id(text: &str) -> Option<Uuid> {
+    let mut end = text.len().min(PEEK_WINDOW);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let head = &text[..end];
+    let rest = &head[head.find("\"id\"")? + 4..];
+    let value = rest.trim_start().strip_prefix(':')?.trim_start();
+    let value = value.strip_prefix('"')?;
+    Uuid::parse_str(value.get(..36)?).ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -148,6 +271,53 @@ mod tests {
         assert_eq!(resp, decoded);
     }
 
+    #[test]
+    fn errors_carry_category_and_retryable() {
+        let id = Uuid::nil();
+        let resp = Response::error(id, error_codes::RATE_LIMITED, "slow down");
+        let encoded = serde_json::to_value(&resp).unwrap();
+        assert_eq!(encoded["error"]["category"], "rate_limited");
+        assert_eq!(encoded["error"]["retryable"], true);
+
+        let err = Response::error(id, error_codes::FORBIDDEN, "no")
+            .error
+            .unwrap();
+        assert_eq!(err.category, Some(ErrorCategory::Auth));
+        assert_eq!(err.retryable, Some(false));
+
+        let err = Response::error(id, error_codes::BUDGET_EXCEEDED, "spent")
+            .error
+            .unwrap();
+        assert_eq!(err.category, Some(ErrorCategory::Quota));
+        assert_eq!(err.retryable, Some(false));
+
+        let custom = Response::error(id, -1, "custom");
+        let encoded = serde_json::to_value(&custom).unwrap();
+        assert!(encoded["error"].get("category").is_none());
+        assert!(encoded["error"].get("retryable").is_none());
+
+        let legacy: Response = serde_json::from_value(json!({
+            "id": id,
+            "error": { "code": -32603, "message": "boom" },
+        }))
+        .unwrap();
+        assert_eq!(legacy.error.unwrap().category, None);
+    }
+
+    #[test]
+    fn peek_finds_the_id_of_a_truncated_request() {
+        let id = Uuid::new_v4();
+        let text = format!(
+            r#"{{"type":"request","id": "{id}","method":"chat.message.send","params":{{"message":"{}"}}}}"#,
+            "x".repeat(4096)
+        );
+        assert_eq!(peek_request_id(&text), Some(id));
+
+        let late = format!(r#"{{"params":"{}","id":"{id}"}}"#, "x".repeat(4096));
+        assert_eq!(peek_request_id(&late), None);
+        assert_eq!(peek_request_id(r#"{"id":42}"#), None);
+    }
+
     #[test]
     fn event_roundtrip() {
         let evt = Message::Event(Event {
@@ -159,6 +329,31 @@ mod tests {
         assert_eq!(evt, decoded);
     }
 
+    #[test]
+    fn ping_pong_roundtrip() {
+        let ping = Message::Ping(Ping { seq: 7 });
+        let encoded = encode_message(&ping).unwrap();
+        assert_eq!(encoded, r#"{"type":"ping","seq":7}"#);
+        assert_eq!(decode_message(&encoded).unwrap(), ping);
+
+        let pong = decode_message(r#"{"type":"pong","seq":7}"#).unwrap();
+        assert_eq!(pong, Message::Pong(Pong { seq: 7 }));
+    }
+
+    #[test]
+    fn close_roundtrip() {
+        let close = Message::Close(Close {
+            reason: "server_shutdown".into(),
+            retry_after_ms: Some(5_000),
+        });
+        let encoded = encode_message(&close).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"type":"close","reason":"server_shutdown","retry_after_ms":5000}"#
+        );
+        assert_eq!(decode_message(&encoded).unwrap(), close);
+    }
+
     #[test]
     fn response_omits_null_fields() {
         let id = Uuid::new_v4();
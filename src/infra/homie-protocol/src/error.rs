@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::Compression;
+
 /// Protocol-level errors.
 #[derive(Debug, Error)]
 pub enum ProtocolError {
@@ -9,6 +11,21 @@ pub enum ProtocolError {
     #[error("invalid stream type: {0}")]
     InvalidStreamType(u8),
 
+    #[error("invalid compression flag: {0}")]
+    InvalidCompression(u8),
+
+    #[error("frame compressed with {0:?}, which the connection did not negotiate")]
+    UnnegotiatedCompression(Compression),
+
+    #[error("compression error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("payload too large: {got} bytes exceeds the {limit} byte limit")]
+    PayloadTooLarge { limit: usize, got: usize },
+
+    #[error("invalid envelope frame: {0}")]
+    InvalidEnvelope(String),
+
     #[error("json error: {0}")]
     Json(#[from] serde_json::Error),
 
@@ -1,12 +1,14 @@
-use crate::ProtocolError;
+use crate::{Compression, ProtocolError, COMPRESSION_THRESHOLD};
 
-/// Stream type indicators for binary PTY frames.
+/// Stream type indicators for binary frames.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum StreamType {
     Stdout = 0,
     Stderr = 1,
     Stdin = 2,
+    /// A chunk of a `chat.file.upload`; `session_id` is the upload id.
+    Upload = 3,
 }
 
 impl StreamType {
@@ -15,6 +17,7 @@ impl StreamType {
             0 => Ok(Self::Stdout),
             1 => Ok(Self::Stderr),
             2 => Ok(Self::Stdin),
+            3 => Ok(Self::Upload),
             _ => Err(ProtocolError::InvalidStreamType(v)),
         }
     }
@@ -23,21 +26,93 @@ impl StreamType {
 /// Binary frame header size: 16 bytes session_id (UUID) + 1 byte stream_type.
 pub const BINARY_HEADER_SIZE: usize = 17;
 
-/// Binary frame layout for PTY data sent over WebSocket binary frames.
+/// Low-nibble stream value marking a compressed JSON envelope rather than
+/// PTY data. Envelope frames carry a nil session_id.
+pub const ENVELOPE_STREAM: u8 = 0x0F;
+
+const STREAM_MASK: u8 = 0x0F;
+const COMPRESSION_SHIFT: u8 = 4;
+
+/// Default cap on one JSON envelope (text frame, or a compressed envelope
+/// once inflated).
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+/// Default cap on one binary PTY or upload frame.
+pub const DEFAULT_MAX_BINARY_BYTES: usize = 16 * 1024 * 1024;
+/// How far past a limit a frame may go and still get an error response
+/// rather than a dropped connection.
+const FRAME_HEADROOM_BYTES: usize = 64 * 1024;
+
+/// Size limits for inbound frames. Oversized frames are rejected before
+/// they are parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLimits {
+    /// Largest JSON envelope accepted, in bytes.
+    pub max_message_bytes: usize,
+    /// Largest binary frame accepted, header included, in bytes.
+    pub max_binary_bytes: usize,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            max_binary_bytes: DEFAULT_MAX_BINARY_BYTES,
+        }
+    }
+}
+
+impl FrameLimits {
+    pub fn check_message(&self, len: usize) -> Result<(), ProtocolError> {
+        check_len(len, self.max_message_bytes)
+    }
+
+    pub fn check_binary(&self, len: usize) -> Result<(), ProtocolError> {
+        check_len(len, self.max_binary_bytes)
+    }
+
+    /// Largest text frame answered with an error response; anything bigger
+    /// closes the connection.
+    pub fn max_text_frame_bytes(&self) -> usize {
+        self.max_message_bytes.saturating_add(FRAME_HEADROOM_BYTES)
+    }
+
+    /// Largest WebSocket message the transport should buffer at all. The
+    /// transport cannot tell text from binary, so this follows the larger
+    /// limit; text frames are held to [`Self::max_text_frame_bytes`].
+    pub fn max_frame_bytes(&self) -> usize {
+        self.max_text_frame_bytes()
+            .max(self.max_binary_bytes.saturating_add(FRAME_HEADROOM_BYTES))
+    }
+}
+
+fn check_len(len: usize, limit: usize) -> Result<(), ProtocolError> {
+    if len > limit {
+        return Err(ProtocolError::PayloadTooLarge { limit, got: len });
+    }
+    Ok(())
+}
+
+/// Binary frame layout for PTY data and file upload chunks sent over
+/// WebSocket binary frames.
 ///
 /// ```text
 /// ┌──────────────────────────┬────────────┬──────────────────┐
 /// │  session_id (16 bytes)   │ stream (1) │  payload (N)     │
-/// │     UUID big-endian      │  0/1/2     │  raw PTY bytes   │
+/// │     UUID big-endian      │  0/1/2/3   │  raw bytes       │
 /// └──────────────────────────┴────────────┴──────────────────┘
 /// ```
+///
+/// The upper nibble of the stream byte is a compression flag (see
+/// [`Compression::to_flag`]). It is only ever set on connections that
+/// negotiated compression, so older peers always see `0`; see
+/// [`check_frame_compression`] for the inbound side.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BinaryFrame {
-    /// PTY session identifier.
+    /// PTY session identifier, or the upload id for `Upload` frames.
     pub session_id: uuid::Uuid,
     /// Which stream this data belongs to.
     pub stream: StreamType,
-    /// Raw PTY bytes.
+    /// Raw PTY bytes or upload chunk.
     pub payload: Vec<u8>,
 }
 
@@ -51,8 +126,35 @@ impl BinaryFrame {
         buf
     }
 
-    /// Decode from raw WebSocket binary frame bytes.
+    /// Encode, compressing the payload when `compression` is set and the
+    /// payload is at least [`COMPRESSION_THRESHOLD`] bytes.
+    pub fn encode_with(&self, compression: Option<Compression>) -> Vec<u8> {
+        let Some(algo) = compression.filter(|_| self.payload.len() >= COMPRESSION_THRESHOLD) else {
+            return self.encode();
+        };
+        match algo.compress(&self.payload) {
+            Ok(compressed) => {
+                let mut buf = Vec::with_capacity(BINARY_HEADER_SIZE + compressed.len());
+                buf.extend_from_slice(self.session_id.as_bytes());
+                buf.push(self.stream as u8 | (algo.to_flag() << COMPRESSION_SHIFT));
+                buf.extend_from_slice(&compressed);
+                buf
+            }
+            Err(_) => self.encode(),
+        }
+    }
+
+    /// Decode from raw WebSocket binary frame bytes, decompressing the
+    /// payload if the frame is flagged as compressed. Only for frames the
+    /// server produced itself; peer input goes through
+    /// [`Self::decode_limited`].
     pub fn decode(data: &[u8]) -> Result<Self, ProtocolError> {
+        Self::decode_limited(data, usize::MAX)
+    }
+
+    /// [`Self::decode`], rejecting payloads that inflate past
+    /// `max_binary_bytes` without decompressing the rest.
+    pub fn decode_limited(data: &[u8], max_binary_bytes: usize) -> Result<Self, ProtocolError> {
         if data.len() < BINARY_HEADER_SIZE {
             return Err(ProtocolError::FrameTooShort {
                 expected: BINARY_HEADER_SIZE,
@@ -62,8 +164,14 @@ impl BinaryFrame {
 
         let session_id =
             uuid::Uuid::from_bytes(data[..16].try_into().expect("slice is exactly 16 bytes"));
-        let stream = StreamType::from_u8(data[16])?;
-        let payload = data[BINARY_HEADER_SIZE..].to_vec();
+        let stream = StreamType::from_u8(data[16] & STREAM_MASK)?;
+        let payload = match Compression::from_flag(data[16] >> COMPRESSION_SHIFT)? {
+            Some(algo) => algo.decompress_limited(&data[BINARY_HEADER_SIZE..], max_binary_bytes)?,
+            None => {
+                check_len(data.len() - BINARY_HEADER_SIZE, max_binary_bytes)?;
+                data[BINARY_HEADER_SIZE..].to_vec()
+            }
+        };
 
         Ok(Self {
             session_id,
@@ -73,6 +181,67 @@ impl BinaryFrame {
     }
 }
 
+/// Fail unless a binary frame from the peer is uncompressed or compressed
+/// with the algorithm the handshake settled on; peers that did not opt in
+/// must not send compressed frames at all.
+pub fn check_frame_compression(
+    data: &[u8],
+    negotiated: Option<Compression>,
+) -> Result<(), ProtocolError> {
+    let Some(&stream) = data.get(16) else {
+        return Ok(());
+    };
+    match Compression::from_flag(stream >> COMPRESSION_SHIFT)? {
+        Some(algo) if Some(algo) != negotiated => Err(ProtocolError::UnnegotiatedCompression(algo)),
+        _ => Ok(()),
+    }
+}
+
+/// Wrap a JSON envelope in a compressed binary frame.
+///
+/// Returns `None` when the envelope should stay a plain text frame: no
+/// compression negotiated, below [`COMPRESSION_THRESHOLD`], or compression
+/// failed.
+pub fn encode_envelope_frame(json: &str, compression: Option<Compression>) -> Option<Vec<u8>> {
+    let algo = compression.filter(|_| json.len() >= COMPRESSION_THRESHOLD)?;
+    let compressed = algo.compress(json.as_bytes()).ok()?;
+    let mut buf = Vec::with_capacity(BINARY_HEADER_SIZE + compressed.len());
+    buf.extend_from_slice(uuid::Uuid::nil().as_bytes());
+    buf.push(ENVELOPE_STREAM | (algo.to_flag() << COMPRESSION_SHIFT));
+    buf.extend_from_slice(&compressed);
+    Some(buf)
+}
+
+/// Unwrap a compressed envelope frame back into its JSON text.
+///
+/// Returns `Ok(None)` for ordinary PTY frames so callers can fall through to
+/// [`BinaryFrame::decode`].
+pub fn decode_envelope_frame(data: &[u8]) -> Result<Option<String>, ProtocolError> {
+    decode_envelope_frame_limited(data, usize::MAX)
+}
+
+/// [`decode_envelope_frame`], rejecting envelopes that inflate past
+/// `max_message_bytes` without decompressing the rest.
+pub fn decode_envelope_frame_limited(
+    data: &[u8],
+    max_message_bytes: usize,
+) -> Result<Option<String>, ProtocolError> {
+    if data.len() < BINARY_HEADER_SIZE || data[16] & STREAM_MASK != ENVELOPE_STREAM {
+        return Ok(None);
+    }
+    let body = &data[BINARY_HEADER_SIZE..];
+    let payload = match Compression::from_flag(data[16] >> COMPRESSION_SHIFT)? {
+        Some(algo) => algo.decompress_limited(body, max_message_bytes)?,
+        None => {
+            check_len(body.len(), max_message_bytes)?;
+            body.to_vec()
+        }
+    };
+    String::from_utf8(payload)
+        .map(Some)
+        .map_err(|e| ProtocolError::InvalidEnvelope(e.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -114,6 +283,18 @@ mod tests {
         assert_eq!(frame, decoded);
     }
 
+    #[test]
+    fn roundtrip_upload_chunk() {
+        let frame = BinaryFrame {
+            session_id: Uuid::new_v4(),
+            stream: StreamType::Upload,
+            payload: vec![0, 159, 146, 150],
+        };
+        let encoded = frame.encode();
+        assert_eq!(encoded[16], 3);
+        assert_eq!(BinaryFrame::decode(&encoded).unwrap(), frame);
+    }
+
     #[test]
     fn roundtrip_empty_payload() {
         let frame = BinaryFrame {
@@ -145,4 +326,121 @@ mod tests {
     fn header_size_is_17() {
         assert_eq!(BINARY_HEADER_SIZE, 17);
     }
+
+    #[test]
+    fn compressed_roundtrip_above_threshold() {
+        for algo in [Compression::Gzip, Compression::Zstd] {
+            let frame = BinaryFrame {
+                session_id: Uuid::new_v4(),
+                stream: StreamType::Stdout,
+                payload: "ls -la output\n".repeat(200).into_bytes(),
+            };
+            let encoded = frame.encode_with(Some(algo));
+            assert!(encoded.len() < frame.encode().len());
+            assert_eq!(encoded[16] >> 4, algo.to_flag());
+            assert_eq!(BinaryFrame::decode(&encoded).unwrap(), frame);
+        }
+    }
+
+    #[test]
+    fn small_or_unnegotiated_frames_stay_uncompressed() {
+        let frame = BinaryFrame {
+            session_id: Uuid::new_v4(),
+            stream: StreamType::Stderr,
+            payload: b"short".to_vec(),
+        };
+        assert_eq!(frame.encode_with(Some(Compression::Zstd)), frame.encode());
+
+        let large = BinaryFrame {
+            payload: vec![b'x'; COMPRESSION_THRESHOLD * 2],
+            ..frame
+        };
+        assert_eq!(large.encode_with(None), large.encode());
+    }
+
+    #[test]
+    fn envelope_frame_roundtrip() {
+        let json = format!(
+            r#"{{"type":"event","topic":"t","params":"{}"}}"#,
+            "a".repeat(4096)
+        );
+        assert!(encode_envelope_frame(&json, None).is_none());
+        assert!(encode_envelope_frame("{}", Some(Compression::Gzip)).is_none());
+
+        let encoded = encode_envelope_frame(&json, Some(Compression::Gzip)).unwrap();
+        assert!(encoded.len() < json.len());
+        assert!(BinaryFrame::decode(&encoded).is_err());
+        assert_eq!(decode_envelope_frame(&encoded).unwrap(), Some(json));
+    }
+
+    #[test]
+    fn oversized_envelopes_are_rejected_while_inflating() {
+        let json = format!(r#"{{"type":"request","params":"{}"}}"#, "a".repeat(8192));
+        let encoded = encode_envelope_frame(&json, Some(Compression::Zstd)).unwrap();
+        assert!(matches!(
+            decode_envelope_frame_limited(&encoded, 1024),
+            Err(ProtocolError::PayloadTooLarge { limit: 1024, .. })
+        ));
+        assert_eq!(
+            decode_envelope_frame_limited(&encoded, json.len()).unwrap(),
+            Some(json)
+        );
+
+        let limits = FrameLimits {
+            max_message_bytes: 10,
+            max_binary_bytes: 100,
+        };
+        assert!(limits.check_message(10).is_ok());
+        assert!(limits.check_message(11).is_err());
+        assert!(limits.check_binary(100).is_ok());
+        assert_eq!(limits.max_text_frame_bytes(), 10 + FRAME_HEADROOM_BYTES);
+        assert_eq!(limits.max_frame_bytes(), 100 + FRAME_HEADROOM_BYTES);
+    }
+
+    #[test]
+    fn oversized_binary_payloads_are_rejected_while_inflating() {
+        let frame = BinaryFrame {
+            session_id: Uuid::new_v4(),
+            stream: StreamType::Stdin,
+            payload: vec![b'a'; 64 * 1024],
+        };
+        let encoded = frame.encode_with(Some(Compression::Zstd));
+        assert!(encoded.len() < 1024);
+        assert!(matches!(
+            BinaryFrame::decode_limited(&encoded, 1024),
+            Err(ProtocolError::PayloadTooLarge { limit: 1024, .. })
+        ));
+        assert_eq!(
+            BinaryFrame::decode_limited(&encoded, frame.payload.len()).unwrap(),
+            frame
+        );
+        assert!(BinaryFrame::decode_limited(&frame.encode(), 1024).is_err());
+    }
+
+    #[test]
+    fn compressed_frames_need_the_negotiated_algorithm() {
+        let frame = BinaryFrame {
+            session_id: Uuid::new_v4(),
+            stream: StreamType::Stdin,
+            payload: vec![b'a'; 4096],
+        };
+        let gzip = frame.encode_with(Some(Compression::Gzip));
+        assert!(check_frame_compression(&gzip, Some(Compression::Gzip)).is_ok());
+        assert!(matches!(
+            check_frame_compression(&gzip, None),
+            Err(ProtocolError::UnnegotiatedCompression(Compression::Gzip))
+        ));
+        assert!(check_frame_compression(&gzip, Some(Compression::Zstd)).is_err());
+        assert!(check_frame_compression(&frame.encode(), None).is_ok());
+    }
+
+    #[test]
+    fn pty_frames_are_not_envelopes() {
+        let frame = BinaryFrame {
+            session_id: Uuid::new_v4(),
+            stream: StreamType::Stdin,
+            payload: b"input".to_vec(),
+        };
+        assert_eq!(decode_envelope_frame(&frame.encode()).unwrap(), None);
+    }
 }
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
 
-use crate::VersionRange;
+use crate::{Compression, VersionRange};
+
+/// `ClientHello` capability asking the server to send `ping` envelopes and
+/// expect `pong` replies.
+pub const HEARTBEAT_CAPABILITY: &str = "heartbeat";
 
 /// Client → Server handshake sent as the first text frame.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -12,9 +16,17 @@ pub struct ClientHello {
     /// Optional authentication token (unused in Tailscale-only MVP).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth_token: Option<String>,
-    /// Capabilities the client requests (informational).
+    /// Capabilities the client requests; `heartbeat` opts into envelope pings.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub capabilities: Vec<String>,
+    /// Compression algorithms the client can decode. Empty keeps every
+    /// frame uncompressed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compression: Vec<Compression>,
+    /// Stable id of the device the client runs on, shown to the same
+    /// identity's other connections in presence events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
 }
 
 /// Server → Client handshake response.
@@ -30,6 +42,29 @@ pub struct ServerHello {
     /// Services available on this gateway.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub services: Vec<ServiceCapability>,
+    /// Full protocol range the server speaks, so clients can tell when a
+    /// newer version was negotiated down.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supported_versions: Option<VersionRange>,
+    /// Compression selected for this connection, if any. Messages at or
+    /// above the size threshold are sent as compressed binary frames.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// How clients should back off before reconnecting after a drop.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reconnect: Option<ReconnectHint>,
+}
+
+/// Reconnect backoff the server asks clients to use, so reconnects after a
+/// restart are staggered instead of arriving at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconnectHint {
+    /// Delay before the first reconnect attempt.
+    pub min_backoff_ms: u64,
+    /// Cap for the exponentially growing delay.
+    pub max_backoff_ms: u64,
+    /// Whether each delay should be randomized.
+    pub jitter: bool,
 }
 
 /// A service capability advertised by the server.
@@ -48,6 +83,10 @@ pub struct HelloReject {
     pub code: HelloRejectCode,
     /// Human-readable reason.
     pub reason: String,
+    /// Server protocol range; set on `version_mismatch` so clients can
+    /// report which versions would have been accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supported_versions: Option<VersionRange>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -78,6 +117,8 @@ mod tests {
             client_id: "homie-web/0.1.0".into(),
             auth_token: None,
             capabilities: vec!["terminal".into()],
+            compression: vec![Compression::Gzip],
+            device_id: Some("laptop-1".into()),
         };
         let json = serde_json::to_string(&hello).unwrap();
         let decoded: ClientHello = serde_json::from_str(&json).unwrap();
@@ -94,6 +135,13 @@ mod tests {
                 service: "terminal".into(),
                 version: "1.0".into(),
             }],
+            compression: Some(Compression::Zstd),
+            supported_versions: Some(VersionRange::new(1, 2)),
+            reconnect: Some(ReconnectHint {
+                min_backoff_ms: 500,
+                max_backoff_ms: 30_000,
+                jitter: true,
+            }),
         };
         let json = serde_json::to_string(&hello).unwrap();
         let decoded: ServerHello = serde_json::from_str(&json).unwrap();
@@ -105,6 +153,7 @@ mod tests {
         let reject = HandshakeResponse::Reject(HelloReject {
             code: HelloRejectCode::VersionMismatch,
             reason: "server requires protocol >= 2".into(),
+            supported_versions: Some(VersionRange::new(2, 3)),
         });
         let json = serde_json::to_string(&reject).unwrap();
         let decoded: HandshakeResponse = serde_json::from_str(&json).unwrap();
@@ -118,9 +167,13 @@ mod tests {
             client_id: "test".into(),
             auth_token: None,
             capabilities: vec![],
+            compression: vec![],
+            device_id: None,
         };
         let json = serde_json::to_string(&hello).unwrap();
         assert!(!json.contains("auth_token"));
+        assert!(!json.contains("device_id"));
         assert!(!json.contains("capabilities"));
+        assert!(!json.contains("compression"));
     }
 }
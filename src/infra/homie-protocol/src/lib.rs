@@ -1,9 +1,11 @@
+mod compression;
 mod envelope;
 mod error;
 mod frame;
 mod handshake;
 mod version;
 
+pub use compression::*;
 pub use envelope::*;
 pub use error::*;
 pub use frame::*;
@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 /// Current protocol version.
 pub const PROTOCOL_VERSION: u16 = 1;
 
+/// Oldest protocol version the server still speaks.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
 /// Version range advertised during handshake.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VersionRange {
@@ -15,8 +18,20 @@ impl VersionRange {
         Self { min, max }
     }
 
+    /// Range the server advertises: every version from
+    /// [`MIN_PROTOCOL_VERSION`] up to [`PROTOCOL_VERSION`].
+    pub fn supported() -> Self {
+        Self::new(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION)
+    }
+
     /// Returns the highest version both ranges support, or `None`.
+    ///
+    /// The agreed version is `min(self.max, other.max)` provided it is at
+    /// least both mins; inverted ranges never overlap.
     pub fn negotiate(&self, other: &VersionRange) -> Option<u16> {
+        if self.min > self.max || other.min > other.max {
+            return None;
+        }
         let lo = self.min.max(other.min);
         let hi = self.max.min(other.max);
         if lo <= hi {
@@ -25,6 +40,11 @@ impl VersionRange {
             None
         }
     }
+
+    /// Every version in the range, lowest first.
+    pub fn versions(&self) -> Vec<u16> {
+        (self.min..=self.max).collect()
+    }
 }
 
 impl Default for VersionRange {
@@ -60,4 +80,41 @@ mod tests {
         let b = VersionRange::new(3, 4);
         assert_eq!(a.negotiate(&b), None);
     }
+
+    #[test]
+    fn negotiate_falls_back_to_older_server() {
+        let server = VersionRange::new(1, 2);
+        let newer_client = VersionRange::new(1, 4);
+        assert_eq!(server.negotiate(&newer_client), Some(2));
+        assert_eq!(newer_client.negotiate(&server), Some(2));
+    }
+
+    #[test]
+    fn negotiate_picks_client_max_when_server_is_newer() {
+        let server = VersionRange::new(1, 3);
+        let older_client = VersionRange::new(1, 1);
+        assert_eq!(server.negotiate(&older_client), Some(1));
+    }
+
+    #[test]
+    fn negotiate_touching_ranges() {
+        let a = VersionRange::new(1, 2);
+        let b = VersionRange::new(2, 4);
+        assert_eq!(a.negotiate(&b), Some(2));
+    }
+
+    #[test]
+    fn negotiate_rejects_inverted_range() {
+        let server = VersionRange::new(1, 3);
+        let bogus = VersionRange::new(3, 1);
+        assert_eq!(server.negotiate(&bogus), None);
+    }
+
+    #[test]
+    fn supported_spans_min_to_current() {
+        let supported = VersionRange::supported();
+        assert_eq!(supported.min, MIN_PROTOCOL_VERSION);
+        assert_eq!(supported.max, PROTOCOL_VERSION);
+        assert_eq!(VersionRange::new(1, 3).versions(), vec![1, 2, 3]);
+    }
 }